@@ -117,11 +117,14 @@ fn make_config(llvm_dir: &Path) -> cmake::Config {
             true
         }
     };
+    // lets perf find the JIT-compiled shaders
+    let use_perf = cfg!(target_os = "linux");
     retval
         .generator("Ninja")
         .define("LLVM_TARGETS_TO_BUILD", "host")
         .define("LLVM_CCACHE_BUILD", if found_ccache { "ON" } else { "OFF" })
         .define("LLVM_APPEND_VC_REV", "OFF") // stop llvm needing relink after git commit
+        .define("LLVM_USE_PERF", if use_perf { "ON" } else { "OFF" })
         .define(
             "LLVM_TARGET_ARCH",
             env::var("TARGET").unwrap().split("-").next().unwrap(),
@@ -221,16 +224,22 @@ fn main() {
         "cargo:rustc-link-search=native={}",
         llvm_config(&llvm_config_path, Some("--libdir"))
     );
-    let llvm_libs = get_libs(
-        &llvm_config_path,
-        &["--libs", "orcjit", "native", "analysis"],
-    );
+    let mut llvm_components = vec!["--libs", "orcjit", "native", "analysis", "ipo"];
+    if cfg!(target_os = "linux") {
+        llvm_components.push("perfjitevents");
+    }
+    let llvm_libs = get_libs(&llvm_config_path, llvm_components);
     let header = r#"
 #include "llvm-c/Core.h"
 #include "llvm-c/OrcBindings.h"
 #include "llvm-c/Target.h"
 #include "llvm-c/Analysis.h"
+#include "llvm-c/IRReader.h"
+#include "llvm-c/DebugInfo.h"
+#include "llvm-c/ExecutionEngine.h"
+#include "llvm-c/Transforms/PassManagerBuilder.h"
 #include <stdbool.h>
+#include <stddef.h>
 
 #ifdef __cplusplus
 extern "C"
@@ -242,6 +251,17 @@ void LLVM_InitializeNativeAsmParser(void);
 void LLVM_InitializeNativeAsmPrinter(void);
 void LLVM_InitializeNativeDisassembler(void);
 
+LLVMMetadataRef LLVM_GetSubprogram(LLVMValueRef function);
+void LLVM_SetSubprogram(LLVMValueRef function, LLVMMetadataRef subprogram);
+LLVMMetadataRef LLVM_GetScopeForFile(LLVMMetadataRef subprogram,
+                                     const char *file_name,
+                                     size_t file_name_length);
+
+bool LLVM_IsCPUSupported(LLVMTargetRef target, const char *triple, const char *cpu);
+bool LLVM_IsFeatureSupported(LLVMTargetRef target,
+                             const char *triple,
+                             const char *feature);
+
 #ifdef __cplusplus
 }
 #endif
@@ -249,6 +269,12 @@ void LLVM_InitializeNativeDisassembler(void);
     let header_path = out_dir.join("llvm_bindings.h");
     fs::write(&header_path, header).unwrap();
     let llvm_bindings_source = format!("#include {:?}\n", header_path) + r#"
+#include "llvm/IR/DebugInfoMetadata.h"
+#include "llvm/IR/Function.h"
+#include "llvm/MC/MCSubtargetInfo.h"
+#include "llvm/Support/TargetRegistry.h"
+#include <memory>
+
 void LLVM_InitializeNativeTarget(void)
 {
     LLVM_NATIVE_TARGETINFO();
@@ -270,6 +296,59 @@ void LLVM_InitializeNativeDisassembler(void)
 {
     LLVM_NATIVE_DISASSEMBLER();
 }
+
+// LLVM 7's C API doesn't have these
+
+LLVMMetadataRef LLVM_GetSubprogram(LLVMValueRef function)
+{
+    return llvm::wrap(llvm::unwrap<llvm::Function>(function)->getSubprogram());
+}
+
+void LLVM_SetSubprogram(LLVMValueRef function, LLVMMetadataRef subprogram)
+{
+    llvm::unwrap<llvm::Function>(function)->setSubprogram(
+        llvm::unwrap<llvm::DISubprogram>(subprogram));
+}
+
+LLVMMetadataRef LLVM_GetScopeForFile(LLVMMetadataRef subprogram,
+                                     const char *file_name,
+                                     size_t file_name_length)
+{
+    auto scope = llvm::unwrap<llvm::DISubprogram>(subprogram);
+    llvm::StringRef name(file_name, file_name_length);
+    if(scope->getFilename() == name)
+        return subprogram;
+    auto &context = scope->getContext();
+    auto file = llvm::DIFile::get(context, name, "");
+    return llvm::wrap(llvm::DILexicalBlockFile::get(context, scope, file, 0));
+}
+
+static std::unique_ptr<llvm::MCSubtargetInfo> create_subtarget_info(LLVMTargetRef target,
+                                                                    const char *triple)
+{
+    return std::unique_ptr<llvm::MCSubtargetInfo>(
+        reinterpret_cast<const llvm::Target *>(target)->createMCSubtargetInfo(
+            triple, "", ""));
+}
+
+bool LLVM_IsCPUSupported(LLVMTargetRef target, const char *triple, const char *cpu)
+{
+    auto subtarget_info = create_subtarget_info(target, triple);
+    return subtarget_info && subtarget_info->isCPUStringValid(cpu);
+}
+
+// LLVM 7 doesn't expose the feature table, but toggling an unknown feature doesn't change
+// anything
+bool LLVM_IsFeatureSupported(LLVMTargetRef target,
+                             const char *triple,
+                             const char *feature)
+{
+    auto subtarget_info = create_subtarget_info(target, triple);
+    if(!subtarget_info)
+        return false;
+    auto old_feature_bits = subtarget_info->getFeatureBits();
+    return subtarget_info->ToggleFeature(feature) != old_feature_bits;
+}
 "#;
     let llvm_bindings_path = out_dir.join("llvm_bindings.c");
     fs::write(&llvm_bindings_path, llvm_bindings_source).unwrap();
@@ -292,12 +371,19 @@ void LLVM_InitializeNativeDisassembler(void)
         .unwrap()
         .write_to_file(out_dir.join("llvm_c.rs"))
         .unwrap();
+    let cxx_flags = llvm_config(&llvm_config_path, Some("--cxxflags"));
     let build_llvm_bindings = || {
         let mut retval = cc::Build::new();
         retval
             .cpp(true)
             .file(&llvm_bindings_path)
             .include(&include_dir);
+        for flag in cxx_flags.split_whitespace() {
+            // the include directory is already added and cc picks the optimization level
+            if !flag.starts_with("-I") && !flag.starts_with("-O") {
+                retval.flag(flag);
+            }
+        }
         retval
     };
     build_llvm_bindings()
@@ -1,6 +1,9 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later
 // Copyright 2018 Jacob Lifshay
+use cooperative_matrix;
+use half;
 use llvm;
+use register_pressure;
 use shader_compiler_backend as backend;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -14,16 +17,45 @@ use std::ops::Deref;
 use std::os::raw::{c_char, c_uint};
 use std::ptr::null_mut;
 use std::ptr::NonNull;
+use std::slice;
 use std::sync::{Once, ONCE_INIT};
 
 fn to_bool(v: llvm::LLVMBool) -> bool {
     v != 0
 }
 
+fn to_cstring(v: &str) -> Result<CString, backend::Error> {
+    CString::new(v).map_err(|_| backend::Error::InvalidName(v.into()))
+}
+
+fn to_c_uint(count: usize) -> Result<c_uint, backend::Error> {
+    if count as c_uint as usize == count {
+        Ok(count as c_uint)
+    } else {
+        Err(backend::Error::TooManyElements(count))
+    }
+}
+
 #[derive(Clone)]
 pub struct LLVM7CompilerConfig {
     pub variable_vector_length_multiplier: u32,
     pub optimization_mode: backend::OptimizationMode,
+    pub debug_info: bool,
+    pub shader_dump: Option<backend::ShaderDumpConfig>,
+    /// the target triple to generate code for; `None` uses the host's.
+    /// the code is JIT compiled, so the target must be able to run on the host
+    pub target_triple: Option<String>,
+    /// the CPU to generate code for, such as `"haswell"`; `None` uses the host's
+    pub cpu: Option<String>,
+    /// comma-separated LLVM features, such as `"+sse4.2,-avx512f"`. `None` uses the host's
+    /// features if neither `target_triple` nor `cpu` is set, otherwise the CPU's default features
+    pub cpu_features: Option<String>,
+    /// rearrange optimized shaders so fewer wide vectors are live across loops, reducing spilling
+    /// at wide vector lengths. only used when optimizing
+    pub register_pressure_heuristics: bool,
+    /// the names and addresses of host functions that modules can call by declaring a function
+    /// with the same name without adding any basic blocks
+    pub host_functions: Vec<(&'static str, usize)>,
 }
 
 impl Default for LLVM7CompilerConfig {
@@ -34,10 +66,27 @@ impl Default for LLVM7CompilerConfig {
 
 impl From<backend::CompilerIndependentConfig> for LLVM7CompilerConfig {
     fn from(v: backend::CompilerIndependentConfig) -> Self {
-        let backend::CompilerIndependentConfig { optimization_mode } = v;
+        let backend::CompilerIndependentConfig {
+            optimization_mode,
+            debug_info,
+            shader_dump,
+            // used by the shader compiler when it builds the arithmetic
+            relaxed_precision_in_f16: _,
+            variable_vector_length_multiplier,
+            // used by the shader compiler when it builds loops
+            watchdog_checks: _,
+            host_functions,
+        } = v;
         Self {
-            variable_vector_length_multiplier: 1,
+            variable_vector_length_multiplier: variable_vector_length_multiplier.unwrap_or(1),
             optimization_mode,
+            debug_info,
+            shader_dump,
+            target_triple: None,
+            cpu: None,
+            cpu_features: None,
+            register_pressure_heuristics: true,
+            host_functions,
         }
     }
 }
@@ -123,6 +172,9 @@ impl<'a> backend::types::TypeBuilder<'a, LLVM7Type> for LLVM7TypeBuilder {
     fn build_i64(&self) -> LLVM7Type {
         unsafe { LLVM7Type(llvm::LLVMInt64TypeInContext(self.context)) }
     }
+    fn build_f16(&self) -> LLVM7Type {
+        unsafe { LLVM7Type(llvm::LLVMHalfTypeInContext(self.context)) }
+    }
     fn build_f32(&self) -> LLVM7Type {
         unsafe { LLVM7Type(llvm::LLVMFloatTypeInContext(self.context)) }
     }
@@ -132,43 +184,53 @@ impl<'a> backend::types::TypeBuilder<'a, LLVM7Type> for LLVM7TypeBuilder {
     fn build_pointer(&self, target: LLVM7Type) -> LLVM7Type {
         unsafe { LLVM7Type(llvm::LLVMPointerType(target.0, 0)) }
     }
-    fn build_array(&self, element: LLVM7Type, count: usize) -> LLVM7Type {
-        assert_eq!(count as u32 as usize, count);
-        unsafe { LLVM7Type(llvm::LLVMArrayType(element.0, count as u32)) }
+    fn build_array(&self, element: LLVM7Type, count: usize) -> Result<LLVM7Type, backend::Error> {
+        let count = to_c_uint(count)?;
+        unsafe { Ok(LLVM7Type(llvm::LLVMArrayType(element.0, count))) }
     }
-    fn build_vector(&self, element: LLVM7Type, length: backend::types::VectorLength) -> LLVM7Type {
+    fn build_vector(
+        &self,
+        element: LLVM7Type,
+        length: backend::types::VectorLength,
+    ) -> Result<LLVM7Type, backend::Error> {
         use self::backend::types::VectorLength::*;
         let length = match length {
             Fixed { length } => length,
             Variable { base_length } => base_length
                 .checked_mul(self.variable_vector_length_multiplier)
-                .unwrap(),
+                .ok_or(backend::Error::InvalidVectorLength)?,
         };
-        assert_ne!(length, 0);
-        unsafe { LLVM7Type(llvm::LLVMVectorType(element.0, length)) }
+        if length == 0 {
+            return Err(backend::Error::InvalidVectorLength);
+        }
+        unsafe { Ok(LLVM7Type(llvm::LLVMVectorType(element.0, length))) }
     }
-    fn build_struct(&self, members: &[LLVM7Type]) -> LLVM7Type {
-        assert_eq!(members.len() as c_uint as usize, members.len());
+    fn build_struct(&self, members: &[LLVM7Type]) -> Result<LLVM7Type, backend::Error> {
+        let member_count = to_c_uint(members.len())?;
         unsafe {
-            LLVM7Type(llvm::LLVMStructTypeInContext(
+            Ok(LLVM7Type(llvm::LLVMStructTypeInContext(
                 self.context,
                 members.as_ptr() as *mut llvm::LLVMTypeRef,
-                members.len() as c_uint,
+                member_count,
                 false as llvm::LLVMBool,
-            ))
+            )))
         }
     }
-    fn build_function(&self, arguments: &[LLVM7Type], return_type: Option<LLVM7Type>) -> LLVM7Type {
-        assert_eq!(arguments.len() as c_uint as usize, arguments.len());
+    fn build_function(
+        &self,
+        arguments: &[LLVM7Type],
+        return_type: Option<LLVM7Type>,
+    ) -> Result<LLVM7Type, backend::Error> {
+        let argument_count = to_c_uint(arguments.len())?;
         unsafe {
-            LLVM7Type(llvm::LLVMFunctionType(
+            Ok(LLVM7Type(llvm::LLVMFunctionType(
                 return_type
                     .unwrap_or_else(|| LLVM7Type(llvm::LLVMVoidTypeInContext(self.context)))
                     .0,
                 arguments.as_ptr() as *mut llvm::LLVMTypeRef,
-                arguments.len() as c_uint,
+                argument_count,
                 false as llvm::LLVMBool,
-            ))
+            )))
         }
     }
 }
@@ -241,14 +303,17 @@ impl<'a> backend::Function<'a> for LLVM7Function {
     fn as_value(&self) -> LLVM7Value {
         LLVM7Value(self.function)
     }
-    fn append_new_basic_block(&mut self, name: Option<&str>) -> LLVM7BasicBlock {
-        let name = CString::new(name.unwrap_or("")).unwrap();
+    fn append_new_basic_block(
+        &mut self,
+        name: Option<&str>,
+    ) -> Result<LLVM7BasicBlock, backend::Error> {
+        let name = to_cstring(name.unwrap_or(""))?;
         unsafe {
-            LLVM7BasicBlock(llvm::LLVMAppendBasicBlockInContext(
+            Ok(LLVM7BasicBlock(llvm::LLVMAppendBasicBlockInContext(
                 self.context,
                 self.function,
                 name.as_ptr(),
-            ))
+            )))
         }
     }
     fn parameters(&self) -> &[LLVM7Value] {
@@ -284,8 +349,8 @@ impl<'a> backend::Context<'a> for LLVM7Context {
     type VerifiedModule = LLVM7Module;
     type AttachedBuilder = LLVM7Builder;
     type DetachedBuilder = LLVM7Builder;
-    fn create_module(&self, name: &str) -> LLVM7Module {
-        let name = CString::new(name).unwrap();
+    fn create_module(&self, name: &str) -> Result<LLVM7Module, backend::Error> {
+        let name = to_cstring(name)?;
         let mut modules = self.modules.borrow_mut();
         unsafe {
             let module = OwnedModule(llvm::LLVMModuleCreateWithNameInContext(
@@ -294,11 +359,23 @@ impl<'a> backend::Context<'a> for LLVM7Context {
             ));
             let module_ref = module.0;
             modules.push(module);
-            LLVM7Module {
-                context: self.context.as_ref().unwrap().0,
+            let context = self.context.as_ref().unwrap().0;
+            let debug_info = if self.config.debug_info {
+                Some(DebugInfoBuilder::new(
+                    context,
+                    module_ref,
+                    self.config.optimization_mode != backend::OptimizationMode::NoOptimizations,
+                ))
+            } else {
+                None
+            };
+            Ok(LLVM7Module {
+                context,
                 module: module_ref,
                 name_set: HashSet::new(),
-            }
+                external_functions: HashMap::new(),
+                debug_info,
+            })
         }
     }
     fn create_builder(&self) -> LLVM7Builder {
@@ -327,21 +404,660 @@ impl Drop for LLVM7Builder {
     }
 }
 
+fn to_llvm_atomic_ordering(ordering: backend::AtomicOrdering) -> llvm::LLVMAtomicOrdering {
+    match ordering {
+        backend::AtomicOrdering::Relaxed => llvm::LLVMAtomicOrderingMonotonic,
+        backend::AtomicOrdering::Acquire => llvm::LLVMAtomicOrderingAcquire,
+        backend::AtomicOrdering::Release => llvm::LLVMAtomicOrderingRelease,
+        backend::AtomicOrdering::AcquireRelease => llvm::LLVMAtomicOrderingAcquireRelease,
+        backend::AtomicOrdering::SequentiallyConsistent => {
+            llvm::LLVMAtomicOrderingSequentiallyConsistent
+        }
+    }
+}
+
+fn is_single_thread(scope: backend::SynchronizationScope) -> llvm::LLVMBool {
+    (scope == backend::SynchronizationScope::SingleThread) as llvm::LLVMBool
+}
+
+/// the size of the scalar type `ty`, which is what the target's atomic instructions require as
+/// the alignment; 0 for other types
+unsafe fn get_scalar_alignment(ty: llvm::LLVMTypeRef) -> u32 {
+    match llvm::LLVMGetTypeKind(ty) {
+        llvm::LLVMIntegerTypeKind => (llvm::LLVMGetIntTypeWidth(ty) + 7) / 8,
+        llvm::LLVMHalfTypeKind => 2,
+        llvm::LLVMFloatTypeKind => 4,
+        llvm::LLVMDoubleTypeKind => 8,
+        llvm::LLVMPointerTypeKind => mem::size_of::<usize>() as u32,
+        _ => 0,
+    }
+}
+
+/// atomic loads and stores need an explicit alignment; use the size of the accessed type
+unsafe fn set_atomic_load_store_attributes(
+    instruction: llvm::LLVMValueRef,
+    pointer: LLVM7Value,
+    ordering: backend::AtomicOrdering,
+    scope: backend::SynchronizationScope,
+) {
+    let ty = llvm::LLVMGetElementType(llvm::LLVMTypeOf(pointer.0));
+    llvm::LLVMSetAlignment(instruction, get_scalar_alignment(ty));
+    llvm::LLVMSetOrdering(instruction, to_llvm_atomic_ordering(ordering));
+    llvm::LLVMSetAtomicSingleThread(instruction, is_single_thread(scope));
+}
+
+/// the suffix LLVM's overloaded intrinsics have for arguments or results of type `ty`, such as
+/// `v4f32` for `<4 x float>`
+unsafe fn get_intrinsic_type_suffix(ty: llvm::LLVMTypeRef) -> Option<String> {
+    Some(match llvm::LLVMGetTypeKind(ty) {
+        llvm::LLVMIntegerTypeKind => format!("i{}", llvm::LLVMGetIntTypeWidth(ty)),
+        llvm::LLVMHalfTypeKind => "f16".into(),
+        llvm::LLVMFloatTypeKind => "f32".into(),
+        llvm::LLVMDoubleTypeKind => "f64".into(),
+        llvm::LLVMVectorTypeKind => format!(
+            "v{}{}",
+            llvm::LLVMGetVectorSize(ty),
+            get_intrinsic_type_suffix(llvm::LLVMGetElementType(ty))?
+        ),
+        llvm::LLVMPointerTypeKind => format!(
+            "p{}{}",
+            llvm::LLVMGetPointerAddressSpace(ty),
+            get_intrinsic_type_suffix(llvm::LLVMGetElementType(ty))?
+        ),
+        _ => return None,
+    })
+}
+
+/// check that `cpu_features` is a list like `"+sse4.2,-avx512f"` of features known to `target`,
+/// since LLVM ignores unknown features
+unsafe fn check_cpu_features(
+    target: llvm::LLVMTargetRef,
+    target_triple: &CStr,
+    cpu_features: &str,
+) -> Result<(), backend::Error> {
+    if cpu_features.is_empty() {
+        return Ok(());
+    }
+    for feature in cpu_features.split(',') {
+        let is_valid = (feature.starts_with('+') || feature.starts_with('-'))
+            && feature.len() > 1
+            && llvm::LLVM_IsFeatureSupported(
+                target,
+                target_triple.as_ptr(),
+                to_cstring(&feature[1..])?.as_ptr(),
+            );
+        if !is_valid {
+            return Err(backend::Error::UnsupportedTarget(format!(
+                "unsupported CPU feature: {:?}",
+                feature
+            )));
+        }
+    }
+    Ok(())
+}
+
 impl<'a> backend::AttachedBuilder<'a> for LLVM7Builder {
     type Context = LLVM7Context;
     fn current_basic_block(&self) -> LLVM7BasicBlock {
         unsafe { LLVM7BasicBlock(llvm::LLVMGetInsertBlock(self.0)) }
     }
+    fn set_source_location(&self, location: Option<backend::SourceLocation>) {
+        unsafe {
+            let function = llvm::LLVMGetBasicBlockParent(llvm::LLVMGetInsertBlock(self.0));
+            let subprogram = llvm::LLVM_GetSubprogram(function);
+            let location = match location {
+                Some(location) if !subprogram.is_null() => location,
+                _ => {
+                    llvm::LLVMSetCurrentDebugLocation(self.0, null_mut());
+                    return;
+                }
+            };
+            let scope = llvm::LLVM_GetScopeForFile(
+                subprogram,
+                location.file_name.as_ptr() as *const c_char,
+                location.file_name.len(),
+            );
+            let context = llvm::LLVMGetTypeContext(llvm::LLVMTypeOf(function));
+            let debug_location = llvm::LLVMDIBuilderCreateDebugLocation(
+                context,
+                location.line,
+                location.column,
+                scope,
+                null_mut(),
+            );
+            llvm::LLVMSetCurrentDebugLocation(
+                self.0,
+                llvm::LLVMMetadataAsValue(context, debug_location),
+            );
+        }
+    }
+    fn build_call(
+        &self,
+        function: LLVM7Value,
+        arguments: &[LLVM7Value],
+    ) -> Result<LLVM7Value, backend::Error> {
+        let argument_count = to_c_uint(arguments.len())?;
+        unsafe {
+            Ok(LLVM7Value(llvm::LLVMBuildCall(
+                self.0,
+                function.0,
+                arguments.as_ptr() as *mut llvm::LLVMValueRef,
+                argument_count,
+                b"\0".as_ptr() as *const c_char,
+            )))
+        }
+    }
+    fn build_intrinsic(
+        &self,
+        name: &str,
+        return_type: Option<LLVM7Type>,
+        arguments: &[LLVM7Value],
+    ) -> Result<LLVM7Value, backend::Error> {
+        let unknown_intrinsic = || backend::Error::UnknownIntrinsic(name.into());
+        let suffix = |ty| unsafe { get_intrinsic_type_suffix(ty).ok_or_else(unknown_intrinsic) };
+        unsafe {
+            let module = llvm::LLVMGetGlobalParent(llvm::LLVMGetBasicBlockParent(
+                llvm::LLVMGetInsertBlock(self.0),
+            ));
+            let context = llvm::LLVMGetModuleContext(module);
+            let return_type = match return_type {
+                Some(return_type) => return_type.0,
+                None => llvm::LLVMVoidTypeInContext(context),
+            };
+            let mut arguments: Vec<_> = arguments.iter().map(|argument| argument.0).collect();
+            let argument_count = arguments.len();
+            // the masked intrinsics take the alignment of the elements after the pointers
+            let alignment = |vector_type| {
+                let alignment = get_scalar_alignment(llvm::LLVMGetElementType(vector_type));
+                llvm::LLVMConstInt(
+                    llvm::LLVMInt32TypeInContext(context),
+                    alignment.max(1).into(),
+                    0,
+                )
+            };
+            let intrinsic_name = match name {
+                backend::intrinsics::SQRT if argument_count == 1 => {
+                    format!("llvm.sqrt.{}", suffix(return_type)?)
+                }
+                backend::intrinsics::FMA if argument_count == 3 => {
+                    format!("llvm.fma.{}", suffix(return_type)?)
+                }
+                backend::intrinsics::RSQRT if argument_count == 1 => {
+                    // LLVM 7 doesn't have a target-independent rsqrt intrinsic, and the targets'
+                    // approximate ones aren't precise enough
+                    let sqrt = <Self as backend::AttachedBuilder<'a>>::build_intrinsic(
+                        self,
+                        backend::intrinsics::SQRT,
+                        Some(LLVM7Type(return_type)),
+                        &[LLVM7Value(arguments[0])],
+                    )?;
+                    let one = if llvm::LLVMGetTypeKind(return_type) == llvm::LLVMVectorTypeKind {
+                        let one = llvm::LLVMConstReal(llvm::LLVMGetElementType(return_type), 1.0);
+                        let mut ones = vec![one; llvm::LLVMGetVectorSize(return_type) as usize];
+                        llvm::LLVMConstVector(ones.as_mut_ptr(), ones.len() as c_uint)
+                    } else {
+                        llvm::LLVMConstReal(return_type, 1.0)
+                    };
+                    return Ok(LLVM7Value(llvm::LLVMBuildFDiv(
+                        self.0,
+                        one,
+                        sqrt.0,
+                        b"\0".as_ptr() as *const c_char,
+                    )));
+                }
+                backend::intrinsics::MASKED_LOAD | backend::intrinsics::GATHER
+                    if argument_count == 3 =>
+                {
+                    let pointer_type = llvm::LLVMTypeOf(arguments[0]);
+                    arguments.insert(1, alignment(return_type));
+                    let name = if name == backend::intrinsics::GATHER {
+                        "gather"
+                    } else {
+                        "load"
+                    };
+                    format!(
+                        "llvm.masked.{}.{}.{}",
+                        name,
+                        suffix(return_type)?,
+                        suffix(pointer_type)?
+                    )
+                }
+                backend::intrinsics::MASKED_STORE | backend::intrinsics::SCATTER
+                    if argument_count == 3 =>
+                {
+                    let value_type = llvm::LLVMTypeOf(arguments[0]);
+                    let pointer_type = llvm::LLVMTypeOf(arguments[1]);
+                    arguments.insert(2, alignment(value_type));
+                    let name = if name == backend::intrinsics::SCATTER {
+                        "scatter"
+                    } else {
+                        "store"
+                    };
+                    format!(
+                        "llvm.masked.{}.{}.{}",
+                        name,
+                        suffix(value_type)?,
+                        suffix(pointer_type)?
+                    )
+                }
+                // target-specific intrinsics, which already have any suffixes they need
+                _ if name.starts_with("llvm.") => name.into(),
+                _ => {
+                    let function = llvm::LLVMGetNamedFunction(module, to_cstring(name)?.as_ptr());
+                    if function.is_null() {
+                        return Err(unknown_intrinsic());
+                    }
+                    return <Self as backend::AttachedBuilder<'a>>::build_call(
+                        self,
+                        LLVM7Value(function),
+                        &arguments.into_iter().map(LLVM7Value).collect::<Vec<_>>(),
+                    );
+                }
+            };
+            let intrinsic_name = to_cstring(&intrinsic_name)?;
+            let mut function = llvm::LLVMGetNamedFunction(module, intrinsic_name.as_ptr());
+            if function.is_null() {
+                let mut argument_types: Vec<_> = arguments
+                    .iter()
+                    .map(|&argument| llvm::LLVMTypeOf(argument))
+                    .collect();
+                let function_type = llvm::LLVMFunctionType(
+                    return_type,
+                    argument_types.as_mut_ptr(),
+                    to_c_uint(argument_types.len())?,
+                    false as llvm::LLVMBool,
+                );
+                function = llvm::LLVMAddFunction(module, intrinsic_name.as_ptr(), function_type);
+            }
+            <Self as backend::AttachedBuilder<'a>>::build_call(
+                self,
+                LLVM7Value(function),
+                &arguments.into_iter().map(LLVM7Value).collect::<Vec<_>>(),
+            )
+        }
+    }
+    fn build_load(&self, pointer: LLVM7Value) -> LLVM7Value {
+        unsafe {
+            LLVM7Value(llvm::LLVMBuildLoad(
+                self.0,
+                pointer.0,
+                b"\0".as_ptr() as *const c_char,
+            ))
+        }
+    }
+    fn build_store(&self, value: LLVM7Value, pointer: LLVM7Value) {
+        unsafe {
+            llvm::LLVMBuildStore(self.0, value.0, pointer.0);
+        }
+    }
+    fn build_element_pointer(
+        &self,
+        pointer: LLVM7Value,
+        indices: &[u32],
+    ) -> Result<LLVM7Value, backend::Error> {
+        let index_count = to_c_uint(indices.len())?;
+        unsafe {
+            let context = llvm::LLVMGetTypeContext(llvm::LLVMTypeOf(pointer.0));
+            let index_type = llvm::LLVMInt32TypeInContext(context);
+            let mut indices: Vec<_> = indices
+                .iter()
+                .map(|&index| llvm::LLVMConstInt(index_type, index.into(), 0))
+                .collect();
+            Ok(LLVM7Value(llvm::LLVMBuildInBoundsGEP(
+                self.0,
+                pointer.0,
+                indices.as_mut_ptr(),
+                index_count,
+                b"\0".as_ptr() as *const c_char,
+            )))
+        }
+    }
+    fn build_indexed_element_pointer(
+        &self,
+        pointer: LLVM7Value,
+        index: LLVM7Value,
+    ) -> Result<LLVM7Value, backend::Error> {
+        let mut indices = [index.0];
+        unsafe {
+            Ok(LLVM7Value(llvm::LLVMBuildInBoundsGEP(
+                self.0,
+                pointer.0,
+                indices.as_mut_ptr(),
+                indices.len() as c_uint,
+                b"\0".as_ptr() as *const c_char,
+            )))
+        }
+    }
+    fn build_bitcast(&self, value: LLVM7Value, ty: LLVM7Type) -> LLVM7Value {
+        unsafe {
+            LLVM7Value(llvm::LLVMBuildBitCast(
+                self.0,
+                value.0,
+                ty.0,
+                b"\0".as_ptr() as *const c_char,
+            ))
+        }
+    }
+    fn build_float_extend(&self, value: LLVM7Value, ty: LLVM7Type) -> LLVM7Value {
+        unsafe {
+            LLVM7Value(llvm::LLVMBuildFPExt(
+                self.0,
+                value.0,
+                ty.0,
+                b"\0".as_ptr() as *const c_char,
+            ))
+        }
+    }
+    fn build_float_truncate(&self, value: LLVM7Value, ty: LLVM7Type) -> LLVM7Value {
+        unsafe {
+            LLVM7Value(llvm::LLVMBuildFPTrunc(
+                self.0,
+                value.0,
+                ty.0,
+                b"\0".as_ptr() as *const c_char,
+            ))
+        }
+    }
+    fn build_integer_extend(
+        &self,
+        value: LLVM7Value,
+        ty: LLVM7Type,
+        is_signed: bool,
+    ) -> LLVM7Value {
+        unsafe {
+            let build = if is_signed {
+                llvm::LLVMBuildSExt
+            } else {
+                llvm::LLVMBuildZExt
+            };
+            LLVM7Value(build(
+                self.0,
+                value.0,
+                ty.0,
+                b"\0".as_ptr() as *const c_char,
+            ))
+        }
+    }
+    fn build_integer_truncate(&self, value: LLVM7Value, ty: LLVM7Type) -> LLVM7Value {
+        unsafe {
+            LLVM7Value(llvm::LLVMBuildTrunc(
+                self.0,
+                value.0,
+                ty.0,
+                b"\0".as_ptr() as *const c_char,
+            ))
+        }
+    }
+    fn build_float_arithmetic(
+        &self,
+        operation: backend::FloatArithmeticOperation,
+        lhs: LLVM7Value,
+        rhs: LLVM7Value,
+    ) -> LLVM7Value {
+        use self::backend::FloatArithmeticOperation::*;
+        let build = match operation {
+            Add => llvm::LLVMBuildFAdd,
+            Subtract => llvm::LLVMBuildFSub,
+            Multiply => llvm::LLVMBuildFMul,
+            Divide => llvm::LLVMBuildFDiv,
+            Remainder => llvm::LLVMBuildFRem,
+        };
+        unsafe { LLVM7Value(build(self.0, lhs.0, rhs.0, b"\0".as_ptr() as *const c_char)) }
+    }
+    fn build_float_negate(&self, value: LLVM7Value) -> LLVM7Value {
+        unsafe {
+            LLVM7Value(llvm::LLVMBuildFNeg(
+                self.0,
+                value.0,
+                b"\0".as_ptr() as *const c_char,
+            ))
+        }
+    }
+    fn build_integer_arithmetic(
+        &self,
+        operation: backend::IntegerArithmeticOperation,
+        lhs: LLVM7Value,
+        rhs: LLVM7Value,
+    ) -> LLVM7Value {
+        use self::backend::IntegerArithmeticOperation::*;
+        let build = match operation {
+            Add => llvm::LLVMBuildAdd,
+            Subtract => llvm::LLVMBuildSub,
+            Multiply => llvm::LLVMBuildMul,
+            And => llvm::LLVMBuildAnd,
+            Or => llvm::LLVMBuildOr,
+            Xor => llvm::LLVMBuildXor,
+        };
+        unsafe { LLVM7Value(build(self.0, lhs.0, rhs.0, b"\0".as_ptr() as *const c_char)) }
+    }
+    fn build_integer_compare(
+        &self,
+        comparison: backend::IntegerComparison,
+        lhs: LLVM7Value,
+        rhs: LLVM7Value,
+    ) -> LLVM7Value {
+        use self::backend::IntegerComparison::*;
+        let predicate = match comparison {
+            Equal => llvm::LLVMIntEQ,
+            NotEqual => llvm::LLVMIntNE,
+            UnsignedLess => llvm::LLVMIntULT,
+            UnsignedLessOrEqual => llvm::LLVMIntULE,
+            UnsignedGreater => llvm::LLVMIntUGT,
+            UnsignedGreaterOrEqual => llvm::LLVMIntUGE,
+            SignedLess => llvm::LLVMIntSLT,
+            SignedLessOrEqual => llvm::LLVMIntSLE,
+            SignedGreater => llvm::LLVMIntSGT,
+            SignedGreaterOrEqual => llvm::LLVMIntSGE,
+        };
+        unsafe {
+            LLVM7Value(llvm::LLVMBuildICmp(
+                self.0,
+                predicate,
+                lhs.0,
+                rhs.0,
+                b"\0".as_ptr() as *const c_char,
+            ))
+        }
+    }
+    fn build_integer_constant(&self, ty: LLVM7Type, value: u64) -> LLVM7Value {
+        unsafe { LLVM7Value(llvm::LLVMConstInt(ty.0, value, false as llvm::LLVMBool)) }
+    }
+    fn build_atomic_load(
+        &self,
+        pointer: LLVM7Value,
+        ordering: backend::AtomicOrdering,
+        scope: backend::SynchronizationScope,
+    ) -> LLVM7Value {
+        unsafe {
+            let load = llvm::LLVMBuildLoad(self.0, pointer.0, b"\0".as_ptr() as *const c_char);
+            set_atomic_load_store_attributes(load, pointer, ordering, scope);
+            LLVM7Value(load)
+        }
+    }
+    fn build_atomic_store(
+        &self,
+        value: LLVM7Value,
+        pointer: LLVM7Value,
+        ordering: backend::AtomicOrdering,
+        scope: backend::SynchronizationScope,
+    ) {
+        unsafe {
+            let store = llvm::LLVMBuildStore(self.0, value.0, pointer.0);
+            set_atomic_load_store_attributes(store, pointer, ordering, scope);
+        }
+    }
+    fn build_atomic_rmw(
+        &self,
+        operation: backend::AtomicRMWOperation,
+        pointer: LLVM7Value,
+        value: LLVM7Value,
+        ordering: backend::AtomicOrdering,
+        scope: backend::SynchronizationScope,
+    ) -> LLVM7Value {
+        use self::backend::AtomicRMWOperation::*;
+        let operation = match operation {
+            Exchange => llvm::LLVMAtomicRMWBinOpXchg,
+            Add => llvm::LLVMAtomicRMWBinOpAdd,
+            Subtract => llvm::LLVMAtomicRMWBinOpSub,
+            And => llvm::LLVMAtomicRMWBinOpAnd,
+            Or => llvm::LLVMAtomicRMWBinOpOr,
+            Xor => llvm::LLVMAtomicRMWBinOpXor,
+            SignedMin => llvm::LLVMAtomicRMWBinOpMin,
+            SignedMax => llvm::LLVMAtomicRMWBinOpMax,
+            UnsignedMin => llvm::LLVMAtomicRMWBinOpUMin,
+            UnsignedMax => llvm::LLVMAtomicRMWBinOpUMax,
+        };
+        unsafe {
+            LLVM7Value(llvm::LLVMBuildAtomicRMW(
+                self.0,
+                operation,
+                pointer.0,
+                value.0,
+                to_llvm_atomic_ordering(ordering),
+                is_single_thread(scope),
+            ))
+        }
+    }
+    fn build_atomic_compare_exchange(
+        &self,
+        pointer: LLVM7Value,
+        comparator: LLVM7Value,
+        value: LLVM7Value,
+        success_ordering: backend::AtomicOrdering,
+        failure_ordering: backend::AtomicOrdering,
+        scope: backend::SynchronizationScope,
+    ) -> LLVM7Value {
+        unsafe {
+            let result = llvm::LLVMBuildAtomicCmpXchg(
+                self.0,
+                pointer.0,
+                comparator.0,
+                value.0,
+                to_llvm_atomic_ordering(success_ordering),
+                to_llvm_atomic_ordering(failure_ordering),
+                is_single_thread(scope),
+            );
+            // cmpxchg returns { previous value, success flag }
+            LLVM7Value(llvm::LLVMBuildExtractValue(
+                self.0,
+                result,
+                0,
+                b"\0".as_ptr() as *const c_char,
+            ))
+        }
+    }
+    fn build_fence(&self, ordering: backend::AtomicOrdering, scope: backend::SynchronizationScope) {
+        assert_ne!(ordering, backend::AtomicOrdering::Relaxed);
+        unsafe {
+            llvm::LLVMBuildFence(
+                self.0,
+                to_llvm_atomic_ordering(ordering),
+                is_single_thread(scope),
+                b"\0".as_ptr() as *const c_char,
+            );
+        }
+    }
     fn build_return(self, value: Option<LLVM7Value>) -> LLVM7Builder {
         unsafe {
             match value {
                 Some(value) => llvm::LLVMBuildRet(self.0, value.0),
                 None => llvm::LLVMBuildRetVoid(self.0),
             };
+            // the location's scope is the function we just finished
+            llvm::LLVMSetCurrentDebugLocation(self.0, null_mut());
+            llvm::LLVMClearInsertionPosition(self.0);
+        }
+        self
+    }
+    fn build_branch(self, destination: LLVM7BasicBlock) -> LLVM7Builder {
+        unsafe {
+            llvm::LLVMBuildBr(self.0, destination.0);
             llvm::LLVMClearInsertionPosition(self.0);
         }
         self
     }
+    fn build_conditional_branch(
+        self,
+        condition: LLVM7Value,
+        true_destination: LLVM7BasicBlock,
+        false_destination: LLVM7BasicBlock,
+    ) -> LLVM7Builder {
+        unsafe {
+            llvm::LLVMBuildCondBr(self.0, condition.0, true_destination.0, false_destination.0);
+            llvm::LLVMClearInsertionPosition(self.0);
+        }
+        self
+    }
+    fn build_switch(
+        self,
+        value: LLVM7Value,
+        default_destination: LLVM7BasicBlock,
+        cases: &[(u64, LLVM7BasicBlock)],
+    ) -> Result<LLVM7Builder, backend::Error> {
+        let case_count = to_c_uint(cases.len())?;
+        unsafe {
+            let switch = llvm::LLVMBuildSwitch(self.0, value.0, default_destination.0, case_count);
+            let ty = llvm::LLVMTypeOf(value.0);
+            for (case_value, destination) in cases {
+                llvm::LLVMAddCase(
+                    switch,
+                    llvm::LLVMConstInt(ty, *case_value, 0),
+                    destination.0,
+                );
+            }
+            llvm::LLVMClearInsertionPosition(self.0);
+        }
+        Ok(self)
+    }
+    fn build_jump_table(
+        self,
+        index: LLVM7Value,
+        destinations: &[LLVM7BasicBlock],
+    ) -> Result<LLVM7Builder, backend::Error> {
+        let destination_count = to_c_uint(destinations.len())?;
+        unsafe {
+            let function = llvm::LLVMGetBasicBlockParent(llvm::LLVMGetInsertBlock(self.0));
+            let context = llvm::LLVMGetTypeContext(llvm::LLVMTypeOf(function));
+            let address_type = llvm::LLVMPointerType(llvm::LLVMInt8TypeInContext(context), 0);
+            let mut addresses: Vec<_> = destinations
+                .iter()
+                .map(|destination| llvm::LLVMBlockAddress(function, destination.0))
+                .collect();
+            let table = llvm::LLVMAddGlobal(
+                llvm::LLVMGetGlobalParent(function),
+                llvm::LLVMArrayType(address_type, destination_count),
+                b"jump_table\0".as_ptr() as *const c_char,
+            );
+            llvm::LLVMSetInitializer(
+                table,
+                llvm::LLVMConstArray(address_type, addresses.as_mut_ptr(), destination_count),
+            );
+            llvm::LLVMSetGlobalConstant(table, true as llvm::LLVMBool);
+            llvm::LLVMSetLinkage(table, llvm::LLVMPrivateLinkage);
+            llvm::LLVMSetUnnamedAddr(table, true as llvm::LLVMBool);
+            let mut indices = [llvm::LLVMConstInt(llvm::LLVMTypeOf(index.0), 0, 0), index.0];
+            let address = llvm::LLVMBuildLoad(
+                self.0,
+                llvm::LLVMBuildInBoundsGEP(
+                    self.0,
+                    table,
+                    indices.as_mut_ptr(),
+                    indices.len() as c_uint,
+                    b"\0".as_ptr() as *const c_char,
+                ),
+                b"\0".as_ptr() as *const c_char,
+            );
+            let branch = llvm::LLVMBuildIndirectBr(self.0, address, destination_count);
+            let mut added_destinations = HashSet::new();
+            for destination in destinations {
+                if added_destinations.insert(destination.0) {
+                    llvm::LLVMAddDestination(branch, destination.0);
+                }
+            }
+            llvm::LLVMClearInsertionPosition(self.0);
+        }
+        Ok(self)
+    }
 }
 
 impl<'a> backend::DetachedBuilder<'a> for LLVM7Builder {
@@ -370,6 +1086,22 @@ impl OwnedModule {
         self.0 = null_mut();
         retval
     }
+    unsafe fn to_ir_string(&self) -> String {
+        LLVM7String::from_ptr(llvm::LLVMPrintModuleToString(self.0))
+            .map_or_else(String::new, |v| v.to_string_lossy().into_owned())
+    }
+    /// run the target-independent optimization passes
+    unsafe fn optimize(&self, target_machine: &LLVM7TargetMachine) {
+        let pass_manager_builder = llvm::LLVMPassManagerBuilderCreate();
+        llvm::LLVMPassManagerBuilderSetOptLevel(pass_manager_builder, 2);
+        llvm::LLVMPassManagerBuilderUseInlinerWithThreshold(pass_manager_builder, 225);
+        let pass_manager = llvm::LLVMCreatePassManager();
+        llvm::LLVMAddAnalysisPasses(target_machine.0, pass_manager);
+        llvm::LLVMPassManagerBuilderPopulateModulePassManager(pass_manager_builder, pass_manager);
+        llvm::LLVMPassManagerBuilderDispose(pass_manager_builder);
+        llvm::LLVMRunPassManager(pass_manager, self.0);
+        llvm::LLVMDisposePassManager(pass_manager);
+    }
 }
 
 struct OwnedContext(llvm::LLVMContextRef);
@@ -382,10 +1114,103 @@ impl Drop for OwnedContext {
     }
 }
 
+struct DebugInfoBuilder {
+    builder: llvm::LLVMDIBuilderRef,
+    compile_unit: Option<llvm::LLVMMetadataRef>,
+    files: HashMap<String, llvm::LLVMMetadataRef>,
+    is_optimized: bool,
+}
+
+impl Drop for DebugInfoBuilder {
+    fn drop(&mut self) {
+        unsafe {
+            llvm::LLVMDisposeDIBuilder(self.builder);
+        }
+    }
+}
+
+impl DebugInfoBuilder {
+    unsafe fn new(
+        context: llvm::LLVMContextRef,
+        module: llvm::LLVMModuleRef,
+        is_optimized: bool,
+    ) -> Self {
+        // LLVM drops debug info from modules without the debug info version flag
+        let i32_type = llvm::LLVMInt32TypeInContext(context);
+        const WARNING_BEHAVIOR: u64 = 2;
+        const KEY: &str = "Debug Info Version";
+        let mut flag = [
+            llvm::LLVMConstInt(i32_type, WARNING_BEHAVIOR, 0),
+            llvm::LLVMMDStringInContext(
+                context,
+                KEY.as_ptr() as *const c_char,
+                KEY.len() as c_uint,
+            ),
+            llvm::LLVMConstInt(i32_type, llvm::LLVMDebugMetadataVersion().into(), 0),
+        ];
+        llvm::LLVMAddNamedMetadataOperand(
+            module,
+            b"llvm.module.flags\0".as_ptr() as *const c_char,
+            llvm::LLVMMDNodeInContext(context, flag.as_mut_ptr(), flag.len() as c_uint),
+        );
+        DebugInfoBuilder {
+            builder: llvm::LLVMCreateDIBuilder(module),
+            compile_unit: None,
+            files: HashMap::new(),
+            is_optimized,
+        }
+    }
+    unsafe fn get_file(&mut self, file_name: &str) -> llvm::LLVMMetadataRef {
+        let builder = self.builder;
+        *self.files.entry(file_name.into()).or_insert_with(|| {
+            llvm::LLVMDIBuilderCreateFile(
+                builder,
+                file_name.as_ptr() as *const c_char,
+                file_name.len(),
+                b"".as_ptr() as *const c_char,
+                0,
+            )
+        })
+    }
+    /// the compile unit is created for the first file that's used, since a module only has one
+    unsafe fn get_compile_unit(&mut self, file: llvm::LLVMMetadataRef) -> llvm::LLVMMetadataRef {
+        if let Some(compile_unit) = self.compile_unit {
+            return compile_unit;
+        }
+        const PRODUCER: &str = "Kazan";
+        let compile_unit = llvm::LLVMDIBuilderCreateCompileUnit(
+            self.builder,
+            llvm::LLVMDWARFSourceLanguageC,
+            file,
+            PRODUCER.as_ptr() as *const c_char,
+            PRODUCER.len(),
+            self.is_optimized as llvm::LLVMBool,
+            b"".as_ptr() as *const c_char,
+            0,
+            0,
+            b"".as_ptr() as *const c_char,
+            0,
+            llvm::LLVMDWARFEmissionFull,
+            0,
+            false as llvm::LLVMBool,
+            false as llvm::LLVMBool,
+        );
+        self.compile_unit = Some(compile_unit);
+        compile_unit
+    }
+    fn finalize(&self) {
+        unsafe {
+            llvm::LLVMDIBuilderFinalize(self.builder);
+        }
+    }
+}
+
 pub struct LLVM7Module {
     context: llvm::LLVMContextRef,
     module: llvm::LLVMModuleRef,
     name_set: HashSet<String>,
+    external_functions: HashMap<String, llvm::LLVMOrcTargetAddress>,
+    debug_info: Option<DebugInfoBuilder>,
 }
 
 impl fmt::Debug for LLVM7Module {
@@ -409,7 +1234,7 @@ impl<'a> backend::Module<'a> for LLVM7Module {
             )
         }
     }
-    fn add_function(&mut self, name: &str, ty: LLVM7Type) -> LLVM7Function {
+    fn add_function(&mut self, name: &str, ty: LLVM7Type) -> Result<LLVM7Function, backend::Error> {
         fn is_start_char(c: char) -> bool {
             if c.is_ascii_alphabetic() {
                 true
@@ -423,24 +1248,82 @@ impl<'a> backend::Module<'a> for LLVM7Module {
         fn is_continue_char(c: char) -> bool {
             is_start_char(c) || c.is_ascii_digit()
         }
-        assert!(is_start_char(name.chars().next().unwrap()));
-        assert!(name.chars().all(is_continue_char));
-        assert!(self.name_set.insert(name.into()));
-        let name = CString::new(name).unwrap();
+        if !name.starts_with(is_start_char) || !name.chars().all(is_continue_char) {
+            return Err(backend::Error::InvalidName(name.into()));
+        }
+        if !self.name_set.insert(name.into()) {
+            return Err(backend::Error::DuplicateName(name.into()));
+        }
+        let name = to_cstring(name)?;
         unsafe {
             let function = llvm::LLVMAddFunction(self.module, name.as_ptr(), ty.0);
             let mut parameters = Vec::new();
             parameters.resize(llvm::LLVMCountParams(function) as usize, null_mut());
             llvm::LLVMGetParams(function, parameters.as_mut_ptr());
             let parameters: Vec<_> = parameters.into_iter().map(LLVM7Value).collect();
-            LLVM7Function {
+            Ok(LLVM7Function {
                 context: self.context,
-                function: llvm::LLVMAddFunction(self.module, name.as_ptr(), ty.0),
+                function,
                 parameters: parameters.into_boxed_slice(),
-            }
+            })
         }
     }
+    fn add_external_function(
+        &mut self,
+        name: &str,
+        ty: LLVM7Type,
+        address: unsafe extern "C" fn(),
+    ) -> Result<LLVM7Function, backend::Error> {
+        let function = <Self as backend::Module<'a>>::add_function(self, name, ty)?;
+        self.external_functions
+            .insert(name.into(), address as usize as llvm::LLVMOrcTargetAddress);
+        Ok(function)
+    }
+    fn set_function_source_location(
+        &mut self,
+        function: &LLVM7Function,
+        name: &str,
+        location: backend::SourceLocation,
+    ) -> Result<(), backend::Error> {
+        let debug_info = match &mut self.debug_info {
+            Some(debug_info) => debug_info,
+            None => return Ok(()),
+        };
+        unsafe {
+            let file = debug_info.get_file(location.file_name);
+            let compile_unit = debug_info.get_compile_unit(file);
+            let function_type = llvm::LLVMDIBuilderCreateSubroutineType(
+                debug_info.builder,
+                file,
+                null_mut(),
+                0,
+                llvm::LLVMDIFlagZero,
+            );
+            let linkage_name = CStr::from_ptr(llvm::LLVMGetValueName(function.function));
+            let subprogram = llvm::LLVMDIBuilderCreateFunction(
+                debug_info.builder,
+                compile_unit,
+                name.as_ptr() as *const c_char,
+                name.len(),
+                linkage_name.as_ptr(),
+                linkage_name.to_bytes().len(),
+                file,
+                location.line,
+                function_type,
+                false as llvm::LLVMBool,
+                true as llvm::LLVMBool,
+                location.line,
+                llvm::LLVMDIFlagZero,
+                debug_info.is_optimized as llvm::LLVMBool,
+            );
+            llvm::LLVM_SetSubprogram(function.function, subprogram);
+        }
+        Ok(())
+    }
     fn verify(self) -> Result<LLVM7Module, backend::VerificationFailure<'a, LLVM7Module>> {
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.finalize();
+        }
         unsafe {
             let mut message = null_mut();
             let broken = to_bool(llvm::LLVMVerifyModule(
@@ -449,15 +1332,18 @@ impl<'a> backend::Module<'a> for LLVM7Module {
                 &mut message,
             ));
             if broken {
-                let message = LLVM7String::from_ptr(message).unwrap();
-                let message = message.to_string_lossy();
-                Err(backend::VerificationFailure::new(self, message.as_ref()))
+                let message = LLVM7String::from_ptr(message)
+                    .map_or_else(String::new, |v| v.to_string_lossy().into_owned());
+                Err(backend::VerificationFailure::new(self, &message))
             } else {
                 Ok(self)
             }
         }
     }
     unsafe fn to_verified_module_unchecked(self) -> LLVM7Module {
+        if let Some(debug_info) = &self.debug_info {
+            debug_info.finalize();
+        }
         self
     }
 }
@@ -485,6 +1371,32 @@ impl LLVM7TargetMachine {
         self.0 = null_mut();
         retval
     }
+    /// generate the target assembly for `module`.
+    /// code generation modifies the module it runs on, so this uses a copy
+    unsafe fn emit_assembly(&self, module: &OwnedModule) -> Result<Vec<u8>, backend::Error> {
+        let module = OwnedModule(llvm::LLVMCloneModule(module.0));
+        let mut error = null_mut();
+        let mut buffer = null_mut();
+        let failed = to_bool(llvm::LLVMTargetMachineEmitToMemoryBuffer(
+            self.0,
+            module.0,
+            llvm::LLVMAssemblyFile,
+            &mut error,
+            &mut buffer,
+        ));
+        if failed {
+            let error = LLVM7String::from_ptr(error)
+                .map_or_else(String::new, |v| v.to_string_lossy().into_owned());
+            return Err(backend::Error::CompilationFailed(error));
+        }
+        let retval = slice::from_raw_parts(
+            llvm::LLVMGetBufferStart(buffer) as *const u8,
+            llvm::LLVMGetBufferSize(buffer),
+        )
+        .to_vec();
+        llvm::LLVMDisposeMemoryBuffer(buffer);
+        Ok(retval)
+    }
 }
 
 struct LLVM7OrcJITStack(llvm::LLVMOrcJITStackRef);
@@ -492,12 +1404,9 @@ struct LLVM7OrcJITStack(llvm::LLVMOrcJITStackRef);
 impl Drop for LLVM7OrcJITStack {
     fn drop(&mut self) {
         unsafe {
-            match llvm::LLVMOrcDisposeInstance(self.0) {
-                llvm::LLVMOrcErrSuccess => {}
-                _ => {
-                    panic!("LLVMOrcDisposeInstance failed");
-                }
-            }
+            // there's nothing useful to do if disposing fails, and panicking here would abort
+            // the process if we're already unwinding
+            let _ = llvm::LLVMOrcDisposeInstance(self.0);
         }
     }
 }
@@ -511,9 +1420,18 @@ fn initialize_native_target() {
     });
 }
 
-extern "C" fn symbol_resolver_fn<Void>(name: *const c_char, _lookup_context: *mut Void) -> u64 {
-    let name = unsafe { CStr::from_ptr(name) };
-    panic!("symbol_resolver_fn is unimplemented: name = {:?}", name)
+/// maps mangled symbol names to the addresses of functions added with `add_external_function`
+type ExternalFunctions = HashMap<CString, llvm::LLVMOrcTargetAddress>;
+
+extern "C" fn symbol_resolver_fn<Void>(name: *const c_char, lookup_context: *mut Void) -> u64 {
+    unsafe {
+        let external_functions = &*(lookup_context as *const ExternalFunctions);
+        // returning 0 tells ORC the symbol wasn't found
+        external_functions
+            .get(CStr::from_ptr(name))
+            .cloned()
+            .unwrap_or(0)
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -539,29 +1457,46 @@ impl backend::Compiler for LLVM7Compiler {
                 config: config.clone(),
             };
             let backend::CompileInputs {
-                module,
+                mut module,
                 callable_functions,
             } = user.run(&context)?;
-            let callable_functions: Vec<_> = callable_functions
-                .into_iter()
-                .map(|(key, callable_function)| {
-                    assert_eq!(
-                        llvm::LLVMGetGlobalParent(callable_function.function),
-                        module.module
-                    );
-                    let name: CString =
-                        CStr::from_ptr(llvm::LLVMGetValueName(callable_function.function)).into();
-                    assert_ne!(name.to_bytes().len(), 0);
-                    (key, name)
-                })
-                .collect();
+            let module_external_functions =
+                mem::replace(&mut module.external_functions, HashMap::new());
+            let mut named_callable_functions = Vec::with_capacity(callable_functions.len());
+            for (key, callable_function) in callable_functions {
+                let name: CString =
+                    CStr::from_ptr(llvm::LLVMGetValueName(callable_function.function)).into();
+                if llvm::LLVMGetGlobalParent(callable_function.function) != module.module
+                    || name.to_bytes().is_empty()
+                {
+                    return Err(U::create_backend_error(backend::Error::CompilationFailed(
+                        format!("function is not in the compiled module: {:?}", name),
+                    )));
+                }
+                named_callable_functions.push((key, name));
+            }
             let module = context
                 .modules
                 .get_mut()
                 .drain(..)
                 .find(|v| v.0 == module.module)
-                .unwrap();
-            let target_triple = LLVM7String::from_ptr(llvm::LLVMGetDefaultTargetTriple()).unwrap();
+                .ok_or_else(|| {
+                    U::create_backend_error(backend::Error::CompilationFailed(
+                        "module not created by this context".into(),
+                    ))
+                })?;
+            let target_triple = match &config.target_triple {
+                Some(target_triple) => {
+                    LLVM7String::new(&to_cstring(target_triple).map_err(U::create_backend_error)?)
+                }
+                None => {
+                    LLVM7String::from_ptr(llvm::LLVMGetDefaultTargetTriple()).ok_or_else(|| {
+                        U::create_backend_error(backend::Error::UnsupportedTarget(
+                            "can't get the default target triple".into(),
+                        ))
+                    })?
+                }
+            };
             let mut target = null_mut();
             let mut error = null_mut();
             let success = !to_bool(llvm::LLVMGetTargetFromTriple(
@@ -570,22 +1505,49 @@ impl backend::Compiler for LLVM7Compiler {
                 &mut error,
             ));
             if !success {
-                let error = LLVM7String::from_ptr(error).unwrap();
-                return Err(U::create_error(error.to_string_lossy().into()));
+                let error = LLVM7String::from_ptr(error)
+                    .map_or_else(String::new, |v| v.to_string_lossy().into_owned());
+                return Err(U::create_backend_error(backend::Error::UnsupportedTarget(
+                    error,
+                )));
             }
             if !to_bool(llvm::LLVMTargetHasJIT(target)) {
-                return Err(U::create_error(format!(
-                    "target {:?} doesn't support JIT",
-                    target_triple
+                return Err(U::create_backend_error(backend::Error::UnsupportedTarget(
+                    format!("target {:?} doesn't support JIT", target_triple),
                 )));
             }
-            let host_cpu_name = LLVM7String::from_ptr(llvm::LLVMGetHostCPUName()).unwrap();
-            let host_cpu_features = LLVM7String::from_ptr(llvm::LLVMGetHostCPUFeatures()).unwrap();
+            let cpu_name = match &config.cpu {
+                Some(cpu) => {
+                    let cpu = to_cstring(cpu).map_err(U::create_backend_error)?;
+                    if !llvm::LLVM_IsCPUSupported(target, target_triple.as_ptr(), cpu.as_ptr()) {
+                        return Err(U::create_backend_error(backend::Error::UnsupportedTarget(
+                            format!("unsupported CPU: {:?}", cpu),
+                        )));
+                    }
+                    LLVM7String::new(&cpu)
+                }
+                None if config.target_triple.is_some() => LLVM7String::new(Default::default()),
+                None => LLVM7String::from_ptr(llvm::LLVMGetHostCPUName())
+                    .unwrap_or_else(|| LLVM7String::new(Default::default())),
+            };
+            let cpu_features = match &config.cpu_features {
+                Some(cpu_features) => {
+                    check_cpu_features(target, &target_triple, cpu_features)
+                        .map_err(U::create_backend_error)?;
+                    LLVM7String::new(&to_cstring(cpu_features).map_err(U::create_backend_error)?)
+                }
+                // the host's features don't make sense for another CPU
+                None if config.target_triple.is_some() || config.cpu.is_some() => {
+                    LLVM7String::new(Default::default())
+                }
+                None => LLVM7String::from_ptr(llvm::LLVMGetHostCPUFeatures())
+                    .unwrap_or_else(|| LLVM7String::new(Default::default())),
+            };
             let target_machine = LLVM7TargetMachine(llvm::LLVMCreateTargetMachine(
                 target,
                 target_triple.as_ptr(),
-                host_cpu_name.as_ptr(),
-                host_cpu_features.as_ptr(),
+                cpu_name.as_ptr(),
+                cpu_features.as_ptr(),
                 match config.optimization_mode {
                     backend::OptimizationMode::NoOptimizations => llvm::LLVMCodeGenLevelNone,
                     backend::OptimizationMode::Normal => llvm::LLVMCodeGenLevelDefault,
@@ -593,42 +1555,120 @@ impl backend::Compiler for LLVM7Compiler {
                 llvm::LLVMRelocDefault,
                 llvm::LLVMCodeModelJITDefault,
             ));
-            assert!(!target_machine.0.is_null());
+            if target_machine.0.is_null() {
+                return Err(U::create_backend_error(backend::Error::UnsupportedTarget(
+                    format!("can't create target machine for {:?}", target_triple),
+                )));
+            }
+            llvm::LLVMSetTarget(module.0, target_triple.as_ptr());
+            let target_data = llvm::LLVMCreateTargetDataLayout(target_machine.0);
+            llvm::LLVMSetModuleDataLayout(module.0, target_data);
+            llvm::LLVMDisposeTargetData(target_data);
+            if let Some(shader_dump) = &config.shader_dump {
+                shader_dump.dump(
+                    backend::ShaderDumpStage::UnoptimizedIR,
+                    module.to_ir_string(),
+                );
+            }
+            if config.optimization_mode != backend::OptimizationMode::NoOptimizations {
+                module.optimize(&target_machine);
+                if config.register_pressure_heuristics {
+                    register_pressure::reduce_register_pressure(module.0);
+                }
+            }
+            if let Some(shader_dump) = &config.shader_dump {
+                shader_dump.dump(backend::ShaderDumpStage::OptimizedIR, module.to_ir_string());
+                match target_machine.emit_assembly(&module) {
+                    Ok(assembly) => shader_dump.dump(backend::ShaderDumpStage::Assembly, assembly),
+                    Err(error) => eprintln!("kazan: can't dump assembly: {}", error),
+                }
+            }
             let orc_jit_stack =
                 LLVM7OrcJITStack(llvm::LLVMOrcCreateInstance(target_machine.take()));
+            if config.debug_info {
+                // the listeners are global and never freed; they're null if not supported
+                let listeners = [
+                    llvm::LLVMCreateGDBRegistrationListener(),
+                    llvm::LLVMCreatePerfJITEventListener(),
+                ];
+                for &listener in &listeners {
+                    if !listener.is_null() {
+                        llvm::LLVMOrcRegisterJITEventListener(orc_jit_stack.0, listener);
+                    }
+                }
+            }
+            let mut external_functions: Box<ExternalFunctions> = Box::new(HashMap::new());
+            let runtime_functions = half::get_runtime_functions()
+                .into_iter()
+                .chain(cooperative_matrix::get_runtime_functions())
+                .chain(
+                    config
+                        .host_functions
+                        .iter()
+                        .map(|&(name, address)| (name, address as llvm::LLVMOrcTargetAddress)),
+                )
+                .map(|(name, address)| (name.to_string(), address));
+            for (name, address) in runtime_functions.chain(module_external_functions) {
+                let name = to_cstring(&name).map_err(U::create_backend_error)?;
+                let mut mangled_name = null_mut();
+                llvm::LLVMOrcGetMangledSymbol(orc_jit_stack.0, &mut mangled_name, name.as_ptr());
+                if mangled_name.is_null() {
+                    return Err(U::create_backend_error(backend::Error::CompilationFailed(
+                        format!("can't mangle symbol name: {:?}", name),
+                    )));
+                }
+                let key = CStr::from_ptr(mangled_name).into();
+                llvm::LLVMOrcDisposeMangledSymbol(mangled_name);
+                external_functions.insert(key, address);
+            }
             let mut module_handle = 0;
-            if llvm::LLVMOrcErrSuccess != llvm::LLVMOrcAddEagerlyCompiledIR(
-                orc_jit_stack.0,
-                &mut module_handle,
-                module.take(),
-                Some(symbol_resolver_fn),
-                null_mut(),
-            ) {
-                return Err(U::create_error("compilation failed".into()));
+            if llvm::LLVMOrcErrSuccess
+                != llvm::LLVMOrcAddEagerlyCompiledIR(
+                    orc_jit_stack.0,
+                    &mut module_handle,
+                    module.take(),
+                    Some(symbol_resolver_fn),
+                    &mut *external_functions as *mut ExternalFunctions as *mut _,
+                )
+            {
+                return Err(U::create_backend_error(backend::Error::CompilationFailed(
+                    "adding module to JIT failed".into(),
+                )));
             }
             let mut functions: HashMap<_, _> = HashMap::new();
-            for (key, name) in callable_functions {
+            for (key, name) in named_callable_functions {
                 let mut address: llvm::LLVMOrcTargetAddress = mem::zeroed();
-                if llvm::LLVMOrcErrSuccess != llvm::LLVMOrcGetSymbolAddressIn(
-                    orc_jit_stack.0,
-                    &mut address,
-                    module_handle,
-                    name.as_ptr(),
-                ) {
-                    return Err(U::create_error(format!(
-                        "function not found in compiled module: {:?}",
-                        name
+                if llvm::LLVMOrcErrSuccess
+                    != llvm::LLVMOrcGetSymbolAddressIn(
+                        orc_jit_stack.0,
+                        &mut address,
+                        module_handle,
+                        name.as_ptr(),
+                    )
+                {
+                    return Err(U::create_backend_error(backend::Error::CompilationFailed(
+                        format!("function not found in compiled module: {:?}", name),
                     )));
                 }
                 let address: Option<unsafe extern "C" fn()> = mem::transmute(address as usize);
-                if functions.insert(key, address.unwrap()).is_some() {
-                    return Err(U::create_error(format!("duplicate function: {:?}", name)));
+                let address = address.ok_or_else(|| {
+                    U::create_backend_error(backend::Error::CompilationFailed(format!(
+                        "function not found in compiled module: {:?}",
+                        name
+                    )))
+                })?;
+                if functions.insert(key, address).is_some() {
+                    return Err(U::create_backend_error(backend::Error::DuplicateName(
+                        name.to_string_lossy().into_owned(),
+                    )));
                 }
             }
             struct CompiledCode<K: Hash + Eq + Send + Sync + 'static> {
                 functions: HashMap<K, unsafe extern "C" fn()>,
                 orc_jit_stack: ManuallyDrop<LLVM7OrcJITStack>,
                 context: ManuallyDrop<OwnedContext>,
+                // must outlive `orc_jit_stack` since it's used to resolve symbols
+                _external_functions: Box<ExternalFunctions>,
             }
             unsafe impl<K: Hash + Eq + Send + Sync + 'static> Send for CompiledCode<K> {}
             unsafe impl<K: Hash + Eq + Send + Sync + 'static> Sync for CompiledCode<K> {}
@@ -649,6 +1689,7 @@ impl backend::Compiler for LLVM7Compiler {
                 functions,
                 orc_jit_stack: ManuallyDrop::new(orc_jit_stack),
                 context: context.context.take().unwrap(),
+                _external_functions: external_functions,
             }))
         }
     }
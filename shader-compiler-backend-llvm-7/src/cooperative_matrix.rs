@@ -0,0 +1,415 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! the kernels that `shader_compiler::cooperative_matrix` lowers cooperative matrix instructions
+//! to. they're host functions, like the half-precision conversions, since they're too big to
+//! benefit from inlining. matrices are stored row-major, with tightly packed rows.
+//!
+//! `mul_add` computes each row of the result in blocks of `BLOCK_SIZE` accumulators. the blocks
+//! have a fixed size so the loops over them are vectorized, and a block of 32-bit accumulators
+//! fills the widest host vectors.
+
+use half::f16_to_f32;
+use llvm;
+use std::cmp;
+use std::ptr;
+
+const BLOCK_SIZE: usize = 16;
+
+/// the type that the products of a component type are summed in
+trait Accumulator: Copy + Default {
+    /// `self + a * b`; integers wrap on overflow
+    fn mul_add(self, a: Self, b: Self) -> Self;
+}
+
+impl Accumulator for f32 {
+    fn mul_add(self, a: f32, b: f32) -> f32 {
+        self + a * b
+    }
+}
+
+impl Accumulator for i32 {
+    fn mul_add(self, a: i32, b: i32) -> i32 {
+        self.wrapping_add(a.wrapping_mul(b))
+    }
+}
+
+impl Accumulator for u32 {
+    fn mul_add(self, a: u32, b: u32) -> u32 {
+        self.wrapping_add(a.wrapping_mul(b))
+    }
+}
+
+trait Component: Copy {
+    type Accumulator: Accumulator;
+    fn widen(self) -> Self::Accumulator;
+}
+
+/// the bits of a half-precision float
+#[derive(Copy, Clone)]
+struct F16(u16);
+
+impl Component for F16 {
+    type Accumulator = f32;
+    fn widen(self) -> f32 {
+        f16_to_f32(self.0)
+    }
+}
+
+impl Component for f32 {
+    type Accumulator = f32;
+    fn widen(self) -> f32 {
+        self
+    }
+}
+
+impl Component for i8 {
+    type Accumulator = i32;
+    fn widen(self) -> i32 {
+        i32::from(self)
+    }
+}
+
+impl Component for u8 {
+    type Accumulator = u32;
+    fn widen(self) -> u32 {
+        u32::from(self)
+    }
+}
+
+/// `result = a * b + c`, where `a` is `m` by `k` and `b` is `k` by `n`
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_range_loop))]
+unsafe fn mul_add<T: Component>(
+    a: *const u8,
+    b: *const u8,
+    c: *const u8,
+    result: *mut u8,
+    m: u32,
+    n: u32,
+    k: u32,
+) {
+    let (m, n, k) = (m as usize, n as usize, k as usize);
+    let a = a as *const T;
+    let b = b as *const T;
+    let c = c as *const T::Accumulator;
+    let result = result as *mut T::Accumulator;
+    for row in 0..m {
+        let mut block_start = 0;
+        while block_start < n {
+            let block_size = cmp::min(BLOCK_SIZE, n - block_start);
+            let first_element = row * n + block_start;
+            // read all of the block from `c` before writing `result`, since they can be the same
+            let mut accumulators = [T::Accumulator::default(); BLOCK_SIZE];
+            for i in 0..block_size {
+                accumulators[i] = *c.add(first_element + i);
+            }
+            for index in 0..k {
+                let a_value = (*a.add(row * k + index)).widen();
+                let b_row = b.add(index * n + block_start);
+                for i in 0..block_size {
+                    accumulators[i] = accumulators[i].mul_add(a_value, (*b_row.add(i)).widen());
+                }
+            }
+            for i in 0..block_size {
+                *result.add(first_element + i) = accumulators[i];
+            }
+            block_start += block_size;
+        }
+    }
+}
+
+pub unsafe extern "C" fn mul_add_f16_f32(
+    a: *const u8,
+    b: *const u8,
+    c: *const u8,
+    result: *mut u8,
+    m: u32,
+    n: u32,
+    k: u32,
+) {
+    mul_add::<F16>(a, b, c, result, m, n, k)
+}
+
+pub unsafe extern "C" fn mul_add_f32_f32(
+    a: *const u8,
+    b: *const u8,
+    c: *const u8,
+    result: *mut u8,
+    m: u32,
+    n: u32,
+    k: u32,
+) {
+    mul_add::<f32>(a, b, c, result, m, n, k)
+}
+
+pub unsafe extern "C" fn mul_add_s8_s32(
+    a: *const u8,
+    b: *const u8,
+    c: *const u8,
+    result: *mut u8,
+    m: u32,
+    n: u32,
+    k: u32,
+) {
+    mul_add::<i8>(a, b, c, result, m, n, k)
+}
+
+pub unsafe extern "C" fn mul_add_u8_u32(
+    a: *const u8,
+    b: *const u8,
+    c: *const u8,
+    result: *mut u8,
+    m: u32,
+    n: u32,
+    k: u32,
+) {
+    mul_add::<u8>(a, b, c, result, m, n, k)
+}
+
+/// the offset in bytes of an element of a matrix in memory with the layout used by `load` and
+/// `store`
+fn get_memory_offset(
+    row: usize,
+    column: usize,
+    stride: usize,
+    element_size: usize,
+    column_major: bool,
+) -> usize {
+    if column_major {
+        column * stride + row * element_size
+    } else {
+        row * stride + column * element_size
+    }
+}
+
+/// copy the elements of a matrix between its storage and memory; `stride` is in units of
+/// `stride_unit` bytes
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+unsafe fn copy_elements<F: FnMut(usize, usize)>(
+    stride: u32,
+    stride_unit: u32,
+    rows: u32,
+    columns: u32,
+    element_size: u32,
+    column_major: bool,
+    mut copy: F,
+) {
+    let stride = stride as usize * stride_unit as usize;
+    let (rows, columns, element_size) = (rows as usize, columns as usize, element_size as usize);
+    if !column_major {
+        // rows are contiguous in both
+        for row in 0..rows {
+            copy(row * columns * element_size, row * stride);
+        }
+        return;
+    }
+    for row in 0..rows {
+        for column in 0..columns {
+            copy(
+                (row * columns + column) * element_size,
+                get_memory_offset(row, column, stride, element_size, true),
+            );
+        }
+    }
+}
+
+/// implements `kazan_runtime_cooperative_matrix_load`
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+pub unsafe extern "C" fn load(
+    matrix: *mut u8,
+    pointer: *const u8,
+    stride: u32,
+    stride_unit: u32,
+    rows: u32,
+    columns: u32,
+    element_size: u32,
+    column_major: u32,
+) {
+    let column_major = column_major != 0;
+    let copy_size = if column_major {
+        element_size
+    } else {
+        columns * element_size
+    } as usize;
+    copy_elements(
+        stride,
+        stride_unit,
+        rows,
+        columns,
+        element_size,
+        column_major,
+        |matrix_offset, memory_offset| {
+            ptr::copy_nonoverlapping(
+                pointer.add(memory_offset),
+                matrix.add(matrix_offset),
+                copy_size,
+            )
+        },
+    );
+}
+
+/// implements `kazan_runtime_cooperative_matrix_store`
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+pub unsafe extern "C" fn store(
+    matrix: *const u8,
+    pointer: *mut u8,
+    stride: u32,
+    stride_unit: u32,
+    rows: u32,
+    columns: u32,
+    element_size: u32,
+    column_major: u32,
+) {
+    let column_major = column_major != 0;
+    let copy_size = if column_major {
+        element_size
+    } else {
+        columns * element_size
+    } as usize;
+    copy_elements(
+        stride,
+        stride_unit,
+        rows,
+        columns,
+        element_size,
+        column_major,
+        |matrix_offset, memory_offset| {
+            ptr::copy_nonoverlapping(
+                matrix.add(matrix_offset),
+                pointer.add(memory_offset),
+                copy_size,
+            )
+        },
+    );
+}
+
+/// implements `kazan_runtime_cooperative_matrix_fill`
+pub unsafe extern "C" fn fill(matrix: *mut u8, element_count: u32, element_size: u32) {
+    let element_size = element_size as usize;
+    for index in 1..element_count as usize {
+        ptr::copy_nonoverlapping(matrix, matrix.add(index * element_size), element_size);
+    }
+}
+
+pub fn get_runtime_functions() -> Vec<(&'static str, llvm::LLVMOrcTargetAddress)> {
+    type MulAddFn = unsafe extern "C" fn(*const u8, *const u8, *const u8, *mut u8, u32, u32, u32);
+    type LoadFn = unsafe extern "C" fn(*mut u8, *const u8, u32, u32, u32, u32, u32, u32);
+    type StoreFn = unsafe extern "C" fn(*const u8, *mut u8, u32, u32, u32, u32, u32, u32);
+    type FillFn = unsafe extern "C" fn(*mut u8, u32, u32);
+    vec![
+        (
+            "kazan_runtime_cooperative_matrix_load",
+            load as LoadFn as usize as llvm::LLVMOrcTargetAddress,
+        ),
+        (
+            "kazan_runtime_cooperative_matrix_store",
+            store as StoreFn as usize as llvm::LLVMOrcTargetAddress,
+        ),
+        (
+            "kazan_runtime_cooperative_matrix_fill",
+            fill as FillFn as usize as llvm::LLVMOrcTargetAddress,
+        ),
+        (
+            "kazan_runtime_cooperative_matrix_mul_add_f16_f32",
+            mul_add_f16_f32 as MulAddFn as usize as llvm::LLVMOrcTargetAddress,
+        ),
+        (
+            "kazan_runtime_cooperative_matrix_mul_add_f32_f32",
+            mul_add_f32_f32 as MulAddFn as usize as llvm::LLVMOrcTargetAddress,
+        ),
+        (
+            "kazan_runtime_cooperative_matrix_mul_add_s8_s32",
+            mul_add_s8_s32 as MulAddFn as usize as llvm::LLVMOrcTargetAddress,
+        ),
+        (
+            "kazan_runtime_cooperative_matrix_mul_add_u8_u32",
+            mul_add_u8_u32 as MulAddFn as usize as llvm::LLVMOrcTargetAddress,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mul_add() {
+        // 2x3 times 3x20, so the result has two blocks per row
+        let (m, n, k) = (2, 20, 3);
+        let a: Vec<f32> = (0..m * k).map(|v| v as f32).collect();
+        let b: Vec<f32> = (0..k * n).map(|v| (v % 7) as f32).collect();
+        let mut c: Vec<f32> = (0..m * n).map(|v| v as f32 * 0.5).collect();
+        let mut expected = c.clone();
+        for row in 0..m {
+            for column in 0..n {
+                for index in 0..k {
+                    expected[row * n + column] += a[row * k + index] * b[index * n + column];
+                }
+            }
+        }
+        unsafe {
+            // the result replaces `c`
+            mul_add_f32_f32(
+                a.as_ptr() as *const u8,
+                b.as_ptr() as *const u8,
+                c.as_ptr() as *const u8,
+                c.as_mut_ptr() as *mut u8,
+                m as u32,
+                n as u32,
+                k as u32,
+            );
+        }
+        assert_eq!(c, expected);
+        let a: [i8; 2] = [-3, 100];
+        let b: [i8; 2] = [5, 100];
+        let mut result = [0i32];
+        unsafe {
+            mul_add_s8_s32(
+                a.as_ptr() as *const u8,
+                b.as_ptr() as *const u8,
+                [7i32].as_ptr() as *const u8,
+                result.as_mut_ptr() as *mut u8,
+                1,
+                1,
+                2,
+            );
+        }
+        assert_eq!(result, [7 - 15 + 10000]);
+    }
+
+    #[test]
+    fn test_load_store() {
+        // a 2x3 matrix in a buffer with 4 elements between columns
+        let memory: [u16; 12] = [1, 4, 0, 0, 2, 5, 0, 0, 3, 6, 0, 0];
+        let mut matrix = [0u16; 6];
+        let mut stored = [0u16; 8];
+        unsafe {
+            load(
+                matrix.as_mut_ptr() as *mut u8,
+                memory.as_ptr() as *const u8,
+                4,
+                2,
+                2,
+                3,
+                2,
+                1,
+            );
+            store(
+                matrix.as_ptr() as *const u8,
+                stored.as_mut_ptr() as *mut u8,
+                4,
+                2,
+                2,
+                3,
+                2,
+                0,
+            );
+        }
+        assert_eq!(matrix, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(stored, [1, 2, 3, 0, 4, 5, 6, 0]);
+        unsafe {
+            fill(matrix.as_mut_ptr() as *mut u8, 6, 2);
+        }
+        assert_eq!(matrix, [1; 6]);
+    }
+}
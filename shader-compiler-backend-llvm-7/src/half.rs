@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! conversions to and from 16-bit floating-point.
+//!
+//! on targets without native half-precision support, LLVM does f16 arithmetic in f32 and calls
+//! these to convert; they're normally in compiler-rt or libgcc, which JIT-compiled code can't
+//! link against.
+
+use llvm;
+
+/// round `mantissa * 2^(exponent - fraction_bits)` to the nearest f16, with ties to even.
+/// `mantissa` includes the implicit bit
+fn round_to_f16(sign: u16, exponent: i32, mantissa: u64, fraction_bits: u32) -> u16 {
+    let half_exponent = i64::from(exponent) + 15;
+    let (base, shift) = if half_exponent > 0 {
+        // the implicit bit is added to the exponent field
+        (((half_exponent - 1) as u64) << 10, fraction_bits - 10)
+    } else {
+        (0, fraction_bits - 10 + (1 - half_exponent) as u32)
+    };
+    if shift > fraction_bits + 1 {
+        // less than half of the smallest subnormal
+        return sign;
+    }
+    let truncated = mantissa >> shift;
+    let remainder = mantissa & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    let rounded = if remainder > halfway || (remainder == halfway && truncated & 1 != 0) {
+        truncated + 1
+    } else {
+        truncated
+    };
+    // overflow rounds to infinity
+    sign | (base + rounded).min(0x7C00) as u16
+}
+
+pub extern "C" fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = (bits >> 16) as u16 & 0x8000;
+    let exponent_field = (bits >> 23) & 0xFF;
+    let fraction = u64::from(bits & 0x7F_FFFF);
+    match exponent_field {
+        // keep NaNs quiet
+        0xFF if fraction != 0 => sign | 0x7E00 | (fraction >> 13) as u16,
+        0xFF => sign | 0x7C00,
+        0 => round_to_f16(sign, -126, fraction, 23),
+        _ => round_to_f16(sign, exponent_field as i32 - 127, fraction | 1 << 23, 23),
+    }
+}
+
+pub extern "C" fn f64_to_f16(value: f64) -> u16 {
+    let bits = value.to_bits();
+    let sign = (bits >> 48) as u16 & 0x8000;
+    let exponent_field = (bits >> 52) & 0x7FF;
+    let fraction = bits & 0xF_FFFF_FFFF_FFFF;
+    match exponent_field {
+        0x7FF if fraction != 0 => sign | 0x7E00 | (fraction >> 42) as u16 & 0x3FF,
+        0x7FF => sign | 0x7C00,
+        0 => round_to_f16(sign, -1022, fraction, 52),
+        _ => round_to_f16(sign, exponent_field as i32 - 1023, fraction | 1 << 52, 52),
+    }
+}
+
+pub extern "C" fn f16_to_f32(value: u16) -> f32 {
+    let sign = u32::from(value & 0x8000) << 16;
+    let exponent_field = u32::from(value >> 10) & 0x1F;
+    let fraction = u32::from(value & 0x3FF);
+    let bits = match exponent_field {
+        0 if fraction == 0 => sign,
+        0 => {
+            // subnormal; normalize so the top bit becomes the implicit bit
+            let shift = fraction.leading_zeros() - 21;
+            sign | (113 - shift) << 23 | ((fraction << shift) & 0x3FF) << 13
+        }
+        0x1F => sign | 0x7F80_0000 | fraction << 13,
+        _ => sign | (exponent_field + 127 - 15) << 23 | fraction << 13,
+    };
+    f32::from_bits(bits)
+}
+
+/// the names LLVM uses for the conversion library calls, and their addresses
+pub fn get_runtime_functions() -> Vec<(&'static str, llvm::LLVMOrcTargetAddress)> {
+    let f16_to_f32 = f16_to_f32 as extern "C" fn(u16) -> f32 as usize;
+    let f32_to_f16 = f32_to_f16 as extern "C" fn(f32) -> u16 as usize;
+    let f64_to_f16 = f64_to_f16 as extern "C" fn(f64) -> u16 as usize;
+    vec![
+        ("__gnu_h2f_ieee", f16_to_f32 as llvm::LLVMOrcTargetAddress),
+        ("__extendhfsf2", f16_to_f32 as llvm::LLVMOrcTargetAddress),
+        ("__gnu_f2h_ieee", f32_to_f16 as llvm::LLVMOrcTargetAddress),
+        ("__truncsfhf2", f32_to_f16 as llvm::LLVMOrcTargetAddress),
+        ("__truncdfhf2", f64_to_f16 as llvm::LLVMOrcTargetAddress),
+    ]
+}
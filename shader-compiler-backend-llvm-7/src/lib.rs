@@ -14,6 +14,9 @@ mod llvm {
 }
 
 mod backend;
+mod cooperative_matrix;
+mod half;
+mod register_pressure;
 mod tests;
 
 pub use backend::LLVM7CompilerConfig;
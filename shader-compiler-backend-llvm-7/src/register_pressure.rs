@@ -0,0 +1,552 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! register pressure heuristics, run on the optimized IR right before code generation.
+//!
+//! shaders are vectorized across invocations, so at 8 to 16 lanes a single value can fill one or
+//! two AVX2 registers, and LLVM's register allocator ends up spilling heavily in loops. this
+//! rearranges the IR so that fewer wide values are live across loops:
+//!
+//! * loop-carried values: the new value of a loop phi is moved below the last use of the phi in
+//!   the same block, so the phi and its new value can share a register.
+//! * cheap vector values, such as splats, defined outside a loop and used inside it are
+//!   rematerialized next to their uses. machine LICM hoists them back out when registers are
+//!   available.
+//! * wide vectors that are live across a loop without being used inside it are spilled to the
+//!   stack before the loop and reloaded where they're used.
+
+use llvm;
+use std::collections::{HashMap, HashSet};
+use std::os::raw::{c_char, c_uint};
+use std::ptr::null_mut;
+
+/// vectors at least this wide fill a whole AVX2 register
+const WIDE_VECTOR_BITS: u64 = 256;
+
+/// the maximum number of instructions that are cloned to rematerialize a value
+const MAX_REMATERIALIZED_INSTRUCTIONS: usize = 3;
+
+struct Loop {
+    header: usize,
+    body: HashSet<usize>,
+}
+
+/// the control flow graph of a function, with its natural loops
+struct FunctionCfg {
+    blocks: Vec<llvm::LLVMBasicBlockRef>,
+    indexes: HashMap<llvm::LLVMBasicBlockRef, usize>,
+    successors: Vec<Vec<usize>>,
+    loops: Vec<Loop>,
+}
+
+impl FunctionCfg {
+    unsafe fn new(function: llvm::LLVMValueRef) -> Self {
+        let mut blocks = Vec::new();
+        let mut block = llvm::LLVMGetFirstBasicBlock(function);
+        while !block.is_null() {
+            blocks.push(block);
+            block = llvm::LLVMGetNextBasicBlock(block);
+        }
+        let indexes: HashMap<_, _> = blocks
+            .iter()
+            .enumerate()
+            .map(|(index, &block)| (block, index))
+            .collect();
+        let successors: Vec<Vec<usize>> = blocks
+            .iter()
+            .map(|&block| {
+                let terminator = llvm::LLVMGetBasicBlockTerminator(block);
+                if terminator.is_null() {
+                    return Vec::new();
+                }
+                (0..llvm::LLVMGetNumSuccessors(terminator))
+                    .map(|index| indexes[&llvm::LLVMGetSuccessor(terminator, index)])
+                    .collect()
+            })
+            .collect();
+        let idom = immediate_dominators(&successors);
+        let dominates = |dominator: usize, mut block: usize| loop {
+            if block == dominator {
+                return true;
+            }
+            match idom[block] {
+                Some(next) if next != block => block = next,
+                _ => return false,
+            }
+        };
+        let mut predecessors = vec![Vec::new(); blocks.len()];
+        for (block, block_successors) in successors.iter().enumerate() {
+            if idom[block].is_none() {
+                continue;
+            }
+            for &successor in block_successors {
+                predecessors[successor].push(block);
+            }
+        }
+        let mut loops: Vec<Loop> = Vec::new();
+        for header in 0..blocks.len() {
+            let latches: Vec<usize> = predecessors[header]
+                .iter()
+                .cloned()
+                .filter(|&predecessor| dominates(header, predecessor))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+            let mut body = HashSet::new();
+            body.insert(header);
+            let mut worklist = latches;
+            while let Some(block) = worklist.pop() {
+                if body.insert(block) {
+                    worklist.extend(predecessors[block].iter().cloned());
+                }
+            }
+            loops.push(Loop { header, body });
+        }
+        FunctionCfg {
+            blocks,
+            indexes,
+            successors,
+            loops,
+        }
+    }
+    fn is_in_loop_without(&self, block: usize, excluded_block: usize) -> bool {
+        self.loops
+            .iter()
+            .any(|l| l.body.contains(&block) && !l.body.contains(&excluded_block))
+    }
+    /// the blocks reachable from `start` without going through `avoided_block`
+    fn reachable_from(&self, start: usize, avoided_block: Option<usize>) -> HashSet<usize> {
+        let mut retval = HashSet::new();
+        let mut worklist = vec![start];
+        while let Some(block) = worklist.pop() {
+            if Some(block) != avoided_block && retval.insert(block) {
+                worklist.extend(self.successors[block].iter().cloned());
+            }
+        }
+        retval
+    }
+}
+
+/// the immediate dominator of each block, with the entry block being its own dominator and
+/// unreachable blocks having none. uses the algorithm from "A Simple, Fast Dominance Algorithm"
+/// by Cooper, Harvey and Kennedy
+fn immediate_dominators(successors: &[Vec<usize>]) -> Vec<Option<usize>> {
+    let mut postorder = Vec::with_capacity(successors.len());
+    let mut visited = vec![false; successors.len()];
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some((block, index)) = stack.pop() {
+        if let Some(&successor) = successors[block].get(index) {
+            stack.push((block, index + 1));
+            if !visited[successor] {
+                visited[successor] = true;
+                stack.push((successor, 0));
+            }
+        } else {
+            postorder.push(block);
+        }
+    }
+    let mut postorder_index = vec![0; successors.len()];
+    for (index, &block) in postorder.iter().enumerate() {
+        postorder_index[block] = index;
+    }
+    let mut predecessors = vec![Vec::new(); successors.len()];
+    for &block in &postorder {
+        for &successor in &successors[block] {
+            predecessors[successor].push(block);
+        }
+    }
+    let mut idom = vec![None; successors.len()];
+    idom[0] = Some(0);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in postorder.iter().rev().skip(1) {
+            let mut new_idom = None;
+            for &predecessor in &predecessors[block] {
+                if idom[predecessor].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => predecessor,
+                    Some(mut other) => {
+                        let mut finger = predecessor;
+                        while finger != other {
+                            while postorder_index[finger] < postorder_index[other] {
+                                finger = idom[finger].unwrap();
+                            }
+                            while postorder_index[other] < postorder_index[finger] {
+                                other = idom[other].unwrap();
+                            }
+                        }
+                        finger
+                    }
+                });
+            }
+            if new_idom != idom[block] {
+                idom[block] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+unsafe fn instructions(block: llvm::LLVMBasicBlockRef) -> Vec<llvm::LLVMValueRef> {
+    let mut retval = Vec::new();
+    let mut instruction = llvm::LLVMGetFirstInstruction(block);
+    while !instruction.is_null() {
+        retval.push(instruction);
+        instruction = llvm::LLVMGetNextInstruction(instruction);
+    }
+    retval
+}
+
+unsafe fn is_phi(value: llvm::LLVMValueRef) -> bool {
+    !llvm::LLVMIsAPHINode(value).is_null()
+}
+
+unsafe fn operands(user: llvm::LLVMValueRef) -> Vec<llvm::LLVMValueRef> {
+    (0..llvm::LLVMGetNumOperands(user) as c_uint)
+        .map(|index| llvm::LLVMGetOperand(user, index))
+        .collect()
+}
+
+/// instructions without side effects that can't trap, so they can be moved or duplicated freely
+unsafe fn is_pure(instruction: llvm::LLVMValueRef) -> bool {
+    match llvm::LLVMGetInstructionOpcode(instruction) {
+        llvm::LLVMAdd
+        | llvm::LLVMFAdd
+        | llvm::LLVMSub
+        | llvm::LLVMFSub
+        | llvm::LLVMMul
+        | llvm::LLVMFMul
+        | llvm::LLVMFDiv
+        | llvm::LLVMFRem
+        | llvm::LLVMShl
+        | llvm::LLVMLShr
+        | llvm::LLVMAShr
+        | llvm::LLVMAnd
+        | llvm::LLVMOr
+        | llvm::LLVMXor
+        | llvm::LLVMTrunc
+        | llvm::LLVMZExt
+        | llvm::LLVMSExt
+        | llvm::LLVMFPToUI
+        | llvm::LLVMFPToSI
+        | llvm::LLVMUIToFP
+        | llvm::LLVMSIToFP
+        | llvm::LLVMFPTrunc
+        | llvm::LLVMFPExt
+        | llvm::LLVMPtrToInt
+        | llvm::LLVMIntToPtr
+        | llvm::LLVMBitCast
+        | llvm::LLVMAddrSpaceCast
+        | llvm::LLVMICmp
+        | llvm::LLVMFCmp
+        | llvm::LLVMSelect
+        | llvm::LLVMGetElementPtr
+        | llvm::LLVMExtractElement
+        | llvm::LLVMInsertElement
+        | llvm::LLVMShuffleVector
+        | llvm::LLVMExtractValue
+        | llvm::LLVMInsertValue => true,
+        _ => false,
+    }
+}
+
+unsafe fn is_vector(value: llvm::LLVMValueRef) -> bool {
+    llvm::LLVMGetTypeKind(llvm::LLVMTypeOf(value)) == llvm::LLVMVectorTypeKind
+}
+
+/// the uses of a value in one block, along with the instruction that a replacement for the value
+/// must be inserted before
+struct BlockUses {
+    block: llvm::LLVMBasicBlockRef,
+    insert_before: llvm::LLVMValueRef,
+    /// `(user, operand index)`
+    uses: Vec<(llvm::LLVMValueRef, c_uint)>,
+}
+
+/// the uses of `value` grouped by the block the value has to be available in, which is the
+/// incoming block for phis
+unsafe fn uses_by_block(value: llvm::LLVMValueRef) -> Vec<BlockUses> {
+    let mut users = Vec::new();
+    let mut use_ = llvm::LLVMGetFirstUse(value);
+    while !use_.is_null() {
+        let user = llvm::LLVMGetUser(use_);
+        if !users.contains(&user) {
+            users.push(user);
+        }
+        use_ = llvm::LLVMGetNextUse(use_);
+    }
+    let mut retval: Vec<BlockUses> = Vec::new();
+    for user in users {
+        for (index, operand) in operands(user).into_iter().enumerate() {
+            if operand != value {
+                continue;
+            }
+            let index = index as c_uint;
+            let block = if is_phi(user) {
+                llvm::LLVMGetIncomingBlock(user, index)
+            } else {
+                llvm::LLVMGetInstructionParent(user)
+            };
+            match retval
+                .iter_mut()
+                .find(|block_uses| block_uses.block == block)
+            {
+                Some(block_uses) => block_uses.uses.push((user, index)),
+                None => retval.push(BlockUses {
+                    block,
+                    insert_before: null_mut(),
+                    uses: vec![(user, index)],
+                }),
+            }
+        }
+    }
+    for block_uses in &mut retval {
+        block_uses.insert_before = instructions(block_uses.block)
+            .into_iter()
+            .find(|&instruction| {
+                !is_phi(instruction) && block_uses.uses.iter().any(|&(user, _)| user == instruction)
+            })
+            .unwrap_or_else(|| llvm::LLVMGetBasicBlockTerminator(block_uses.block));
+    }
+    retval
+}
+
+/// move the new value of each loop phi below the phi's last use in the same block
+unsafe fn coalesce_loop_carried_values(cfg: &FunctionCfg, builder: llvm::LLVMBuilderRef) {
+    for l in &cfg.loops {
+        for phi in instructions(cfg.blocks[l.header]) {
+            if !is_phi(phi) {
+                break;
+            }
+            for index in 0..llvm::LLVMCountIncoming(phi) {
+                let block = llvm::LLVMGetIncomingBlock(phi, index);
+                let value = llvm::LLVMGetIncomingValue(phi, index);
+                if !l.body.contains(&cfg.indexes[&block])
+                    || llvm::LLVMIsAInstruction(value).is_null()
+                    || llvm::LLVMGetInstructionParent(value) != block
+                    || is_phi(value)
+                    || !is_pure(value)
+                {
+                    continue;
+                }
+                let block_instructions = instructions(block);
+                let value_position = block_instructions
+                    .iter()
+                    .position(|&instruction| instruction == value)
+                    .unwrap();
+                let last_phi_use = match block_instructions
+                    .iter()
+                    .rposition(|&instruction| operands(instruction).contains(&phi))
+                {
+                    Some(position) if position > value_position => position,
+                    _ => continue,
+                };
+                if last_phi_use + 1 >= block_instructions.len() {
+                    // used by the terminator
+                    continue;
+                }
+                if block_instructions[value_position + 1..=last_phi_use]
+                    .iter()
+                    .any(|&instruction| operands(instruction).contains(&value))
+                {
+                    continue;
+                }
+                llvm::LLVMInstructionRemoveFromParent(value);
+                llvm::LLVMPositionBuilderBefore(builder, block_instructions[last_phi_use + 1]);
+                llvm::LLVMInsertIntoBuilder(builder, value);
+            }
+        }
+    }
+}
+
+/// the number of instructions that need to be cloned to rematerialize `value`, or `None` if
+/// rematerializing it isn't cheap. scalars and constants are used as they are.
+unsafe fn rematerialization_cost(value: llvm::LLVMValueRef) -> Option<usize> {
+    if !is_vector(value) {
+        return Some(0);
+    }
+    if llvm::LLVMIsAInstruction(value).is_null() {
+        return if llvm::LLVMIsConstant(value) != 0 {
+            Some(0)
+        } else {
+            None
+        };
+    }
+    match llvm::LLVMGetInstructionOpcode(value) {
+        llvm::LLVMInsertElement
+        | llvm::LLVMShuffleVector
+        | llvm::LLVMBitCast
+        | llvm::LLVMTrunc
+        | llvm::LLVMZExt
+        | llvm::LLVMSExt
+        | llvm::LLVMFPTrunc
+        | llvm::LLVMFPExt => {}
+        _ => return None,
+    }
+    let mut retval = 1;
+    for operand in operands(value) {
+        retval += rematerialization_cost(operand)?;
+        if retval > MAX_REMATERIALIZED_INSTRUCTIONS {
+            return None;
+        }
+    }
+    Some(retval)
+}
+
+/// clone `value` and the vector instructions it depends on at the builder's position
+unsafe fn rematerialize(
+    builder: llvm::LLVMBuilderRef,
+    value: llvm::LLVMValueRef,
+) -> llvm::LLVMValueRef {
+    if !is_vector(value) || llvm::LLVMIsAInstruction(value).is_null() {
+        return value;
+    }
+    let clone = llvm::LLVMInstructionClone(value);
+    for (index, operand) in operands(value).into_iter().enumerate() {
+        llvm::LLVMSetOperand(clone, index as c_uint, rematerialize(builder, operand));
+    }
+    llvm::LLVMInsertIntoBuilder(builder, clone);
+    clone
+}
+
+/// erase `value` and the instructions it depends on that become unused
+unsafe fn erase_if_unused(value: llvm::LLVMValueRef) {
+    if llvm::LLVMIsAInstruction(value).is_null()
+        || !llvm::LLVMGetFirstUse(value).is_null()
+        || !is_pure(value)
+    {
+        return;
+    }
+    let value_operands = operands(value);
+    llvm::LLVMInstructionEraseFromParent(value);
+    for operand in value_operands {
+        erase_if_unused(operand);
+    }
+}
+
+/// clone cheap vector values that are used in loops they're not defined in next to their uses
+unsafe fn rematerialize_in_loops(cfg: &FunctionCfg, builder: llvm::LLVMBuilderRef) {
+    for &block in &cfg.blocks {
+        let definition_block = cfg.indexes[&block];
+        for value in instructions(block) {
+            match rematerialization_cost(value) {
+                Some(cost) if cost > 0 => {}
+                _ => continue,
+            }
+            let mut rematerialized = false;
+            for block_uses in uses_by_block(value) {
+                if !cfg.is_in_loop_without(cfg.indexes[&block_uses.block], definition_block) {
+                    continue;
+                }
+                llvm::LLVMPositionBuilderBefore(builder, block_uses.insert_before);
+                let clone = rematerialize(builder, value);
+                for (user, index) in block_uses.uses {
+                    llvm::LLVMSetOperand(user, index, clone);
+                }
+                rematerialized = true;
+            }
+            if rematerialized {
+                erase_if_unused(value);
+            }
+        }
+    }
+}
+
+/// spill wide vectors that are live across a loop without being used in it
+unsafe fn split_live_ranges_across_loops(
+    function: llvm::LLVMValueRef,
+    cfg: &FunctionCfg,
+    builder: llvm::LLVMBuilderRef,
+    target_data: llvm::LLVMTargetDataRef,
+) {
+    let entry_block = llvm::LLVMGetEntryBasicBlock(function);
+    for &block in &cfg.blocks {
+        let definition_block = cfg.indexes[&block];
+        let block_instructions = instructions(block);
+        for &value in &block_instructions {
+            let ty = llvm::LLVMTypeOf(value);
+            if !is_vector(value) || llvm::LLVMSizeOfTypeInBits(target_data, ty) < WIDE_VECTOR_BITS {
+                continue;
+            }
+            // uses in the defining block can't be across a loop
+            let all_uses = uses_by_block(value);
+            let use_blocks: Vec<usize> = all_uses
+                .iter()
+                .map(|block_uses| cfg.indexes[&block_uses.block])
+                .filter(|&block| block != definition_block)
+                .collect();
+            if use_blocks.is_empty() {
+                continue;
+            }
+            let reachable_from_definition = cfg.reachable_from(definition_block, None);
+            let is_live_across_loop = cfg.loops.iter().any(|l| {
+                if l.body.contains(&definition_block)
+                    || use_blocks.iter().any(|block| l.body.contains(block))
+                    || !reachable_from_definition.contains(&l.header)
+                {
+                    return false;
+                }
+                let live_from_loop = cfg.reachable_from(l.header, Some(definition_block));
+                use_blocks
+                    .iter()
+                    .any(|block| live_from_loop.contains(block))
+            });
+            if !is_live_across_loop {
+                continue;
+            }
+            llvm::LLVMPositionBuilderBefore(builder, llvm::LLVMGetFirstInstruction(entry_block));
+            let stack_slot = llvm::LLVMBuildAlloca(builder, ty, b"\0".as_ptr() as *const c_char);
+            let alignment = llvm::LLVMABIAlignmentOfType(target_data, ty);
+            llvm::LLVMSetAlignment(stack_slot, alignment);
+            let store_position = if is_phi(value) {
+                block_instructions
+                    .iter()
+                    .cloned()
+                    .find(|&instruction| !is_phi(instruction))
+                    .unwrap()
+            } else {
+                llvm::LLVMGetNextInstruction(value)
+            };
+            llvm::LLVMPositionBuilderBefore(builder, store_position);
+            let store = llvm::LLVMBuildStore(builder, value, stack_slot);
+            llvm::LLVMSetAlignment(store, alignment);
+            for block_uses in all_uses {
+                if block_uses.block == block {
+                    continue;
+                }
+                llvm::LLVMPositionBuilderBefore(builder, block_uses.insert_before);
+                let load =
+                    llvm::LLVMBuildLoad(builder, stack_slot, b"\0".as_ptr() as *const c_char);
+                llvm::LLVMSetAlignment(load, alignment);
+                for (user, index) in block_uses.uses {
+                    llvm::LLVMSetOperand(user, index, load);
+                }
+            }
+        }
+    }
+}
+
+/// run the heuristics on all the functions defined in `module`
+pub unsafe fn reduce_register_pressure(module: llvm::LLVMModuleRef) {
+    let builder = llvm::LLVMCreateBuilderInContext(llvm::LLVMGetModuleContext(module));
+    let target_data = llvm::LLVMGetModuleDataLayout(module);
+    let mut function = llvm::LLVMGetFirstFunction(module);
+    while !function.is_null() {
+        if llvm::LLVMCountBasicBlocks(function) != 0 {
+            let cfg = FunctionCfg::new(function);
+            if !cfg.loops.is_empty() {
+                coalesce_loop_carried_values(&cfg, builder);
+                rematerialize_in_loops(&cfg, builder);
+                split_live_ranges_across_loops(function, &cfg, builder, target_data);
+            }
+        }
+        function = llvm::LLVMGetNextFunction(function);
+    }
+    llvm::LLVMDisposeBuilder(builder);
+}
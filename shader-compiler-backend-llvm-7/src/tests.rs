@@ -4,9 +4,17 @@
 // we have a tests module inside a tests module to have rls parse this tests.rs file
 #[cfg_attr(feature = "cargo-clippy", allow(clippy::module_inception))]
 mod tests {
+    use llvm;
+    use register_pressure;
     use shader_compiler_backend::types::TypeBuilder;
     use shader_compiler_backend::*;
+    use std::env;
+    use std::ffi::CStr;
+    use std::fs;
     use std::mem;
+    use std::os::raw::c_char;
+    use std::process;
+    use std::ptr::null_mut;
 
     fn make_compiler() -> impl Compiler {
         ::LLVM_7_SHADER_COMPILER
@@ -31,13 +39,15 @@ mod tests {
                 context: &'a C,
             ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
                 let type_builder = context.create_type_builder();
-                let mut module = context.create_module("test_module");
-                let mut function = module.add_function(
-                    "test_function",
-                    type_builder.build::<GeneratedFunctionType>(),
-                );
+                let mut module = context.create_module("test_module").unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
                 let builder = context.create_builder();
-                let builder = builder.attach(function.append_new_basic_block(None));
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
                 builder.build_return(None);
                 let module = module.verify().unwrap();
                 Ok(CompileInputs {
@@ -73,13 +83,15 @@ mod tests {
                 context: &'a C,
             ) -> Result<CompileInputs<'a, C, String>, String> {
                 let type_builder = context.create_type_builder();
-                let mut module = context.create_module("test_module");
+                let mut module = context.create_module("test_module").unwrap();
                 let mut functions = Vec::new();
                 let mut detached_builder = context.create_builder();
                 for name in NAMES {
-                    let mut function =
-                        module.add_function(name, type_builder.build::<GeneratedFunctionType>());
-                    let builder = detached_builder.attach(function.append_new_basic_block(None));
+                    let mut function = module
+                        .add_function(name, type_builder.build::<GeneratedFunctionType>().unwrap())
+                        .unwrap();
+                    let builder =
+                        detached_builder.attach(function.append_new_basic_block(None).unwrap());
                     detached_builder = builder.build_return(None);
                     functions.push((name.to_string(), function));
                 }
@@ -97,4 +109,1134 @@ mod tests {
             function(0);
         }
     }
+
+    #[test]
+    fn test_invalid_names() {
+        type GeneratedFunctionType = unsafe extern "C" fn(u32);
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = String;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, String>, String> {
+                let type_builder = context.create_type_builder();
+                assert_eq!(
+                    context.create_module("test\0module").err(),
+                    Some(Error::InvalidName("test\0module".into()))
+                );
+                let mut module = context.create_module("test_module").unwrap();
+                let function_type = type_builder.build::<GeneratedFunctionType>().unwrap();
+                for &name in &["", "1abc", "a\0b", "a b"] {
+                    assert_eq!(
+                        module.add_function(name, function_type.clone()).err(),
+                        Some(Error::InvalidName(name.into()))
+                    );
+                }
+                let mut function = module.add_function("main", function_type.clone()).unwrap();
+                assert_eq!(
+                    module.add_function("main", function_type).err(),
+                    Some(Error::DuplicateName("main".into()))
+                );
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![("main".to_string(), function)].into_iter().collect(),
+                })
+            }
+        }
+        assert!(make_compiler().run(Test, Default::default()).is_ok());
+    }
+
+    #[test]
+    fn test_external_function() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        type GeneratedFunctionType = unsafe extern "C" fn(u32);
+        type ExternalFunctionType = unsafe extern "C" fn(u32);
+        static CALL_ARGUMENT: AtomicUsize = AtomicUsize::new(0);
+        unsafe extern "C" fn external_function(v: u32) {
+            CALL_ARGUMENT.store(v as usize, Ordering::SeqCst);
+        }
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                let external_function = module
+                    .add_external_function(
+                        "external_function",
+                        type_builder.build::<ExternalFunctionType>().unwrap(),
+                        unsafe { mem::transmute(external_function as ExternalFunctionType) },
+                    )
+                    .unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                builder
+                    .build_call(external_function.as_value(), function.parameters())
+                    .unwrap();
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let compiled_code = make_compiler().run(Test, Default::default()).unwrap();
+        let function = compiled_code.get(&FunctionKey::Function).unwrap();
+        unsafe {
+            let function: GeneratedFunctionType = mem::transmute(function);
+            function(1234);
+        }
+        assert_eq!(CALL_ARGUMENT.load(Ordering::SeqCst), 1234);
+    }
+
+    #[test]
+    fn test_host_function() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        type GeneratedFunctionType = unsafe extern "C" fn(u32);
+        type HostFunctionType = unsafe extern "C" fn(u32);
+        static CALL_ARGUMENT: AtomicUsize = AtomicUsize::new(0);
+        unsafe extern "C" fn host_function(v: u32) {
+            CALL_ARGUMENT.store(v as usize, Ordering::SeqCst);
+        }
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                // declared without a body, so it's resolved from the config's host functions
+                let host_function = module
+                    .add_function(
+                        "host_function",
+                        type_builder.build::<HostFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                builder
+                    .build_call(host_function.as_value(), function.parameters())
+                    .unwrap();
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let config = CompilerIndependentConfig {
+            host_functions: vec![("host_function", host_function as HostFunctionType as usize)],
+            ..Default::default()
+        };
+        let compiled_code = make_compiler().run(Test, config.into()).unwrap();
+        let function = compiled_code.get(&FunctionKey::Function).unwrap();
+        unsafe {
+            let function: GeneratedFunctionType = mem::transmute(function);
+            function(5678);
+        }
+        assert_eq!(CALL_ARGUMENT.load(Ordering::SeqCst), 5678);
+    }
+
+    #[test]
+    fn test_intrinsics() {
+        use shader_compiler_backend::types::Vec4;
+        type GeneratedFunctionType = unsafe extern "C" fn(*mut [f32; 4], *mut [Vec4<u32>; 7]);
+        type RuntimeFunctionType = unsafe extern "C" fn(f32) -> f32;
+        unsafe extern "C" fn runtime_function(v: f32) -> f32 {
+            v * 2.0
+        }
+        #[repr(C, align(16))]
+        struct Vectors([Vec4<u32>; 7]);
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                module
+                    .add_external_function(
+                        "runtime_function",
+                        type_builder.build::<RuntimeFunctionType>().unwrap(),
+                        unsafe { mem::transmute(runtime_function as RuntimeFunctionType) },
+                    )
+                    .unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                let scalars = function.parameters()[0].clone();
+                let vectors = function.parameters()[1].clone();
+                let f32_type = type_builder.build::<f32>().unwrap();
+                let scalar = |index| builder.build_element_pointer(scalars.clone(), &[0, index]);
+                let vector = |index| builder.build_element_pointer(vectors.clone(), &[0, index]);
+                let x = builder.build_load(scalar(0).unwrap());
+                let results = vec![
+                    (intrinsics::SQRT, vec![x.clone()]),
+                    (intrinsics::RSQRT, vec![x.clone()]),
+                    (intrinsics::FMA, vec![x.clone(), x.clone(), x.clone()]),
+                    ("runtime_function", vec![x]),
+                ];
+                for (index, (name, arguments)) in results.into_iter().enumerate() {
+                    let result = builder
+                        .build_intrinsic(name, Some(f32_type.clone()), &arguments)
+                        .unwrap();
+                    builder.build_store(result, scalar(index as u32).unwrap());
+                }
+                match builder.build_intrinsic("unknown_intrinsic", None, &[]) {
+                    Err(Error::UnknownIntrinsic(name)) => assert_eq!(name, "unknown_intrinsic"),
+                    _ => unreachable!(),
+                }
+                let vector_type = type_builder.build::<Vec4<u32>>().unwrap();
+                let u32_pointer_type = type_builder.build::<*mut u32>().unwrap();
+                let mask = builder.build_integer_truncate(
+                    builder.build_load(vector(0).unwrap()),
+                    type_builder.build::<Vec4<bool>>().unwrap(),
+                );
+                let values = builder.build_load(vector(1).unwrap());
+                let loaded = builder
+                    .build_intrinsic(
+                        intrinsics::MASKED_LOAD,
+                        Some(vector_type.clone()),
+                        &[
+                            vector(1).unwrap(),
+                            mask.clone(),
+                            builder.build_load(vector(2).unwrap()),
+                        ],
+                    )
+                    .unwrap();
+                builder.build_store(loaded, vector(2).unwrap());
+                builder
+                    .build_intrinsic(
+                        intrinsics::MASKED_STORE,
+                        None,
+                        &[values.clone(), vector(3).unwrap(), mask.clone()],
+                    )
+                    .unwrap();
+                let indices = builder.build_load(vector(4).unwrap());
+                let gather_pointers = builder
+                    .build_indexed_element_pointer(
+                        builder.build_bitcast(vector(1).unwrap(), u32_pointer_type.clone()),
+                        indices.clone(),
+                    )
+                    .unwrap();
+                let gathered = builder
+                    .build_intrinsic(
+                        intrinsics::GATHER,
+                        Some(vector_type),
+                        &[gather_pointers, mask.clone(), values.clone()],
+                    )
+                    .unwrap();
+                builder.build_store(gathered, vector(5).unwrap());
+                let scatter_pointers = builder
+                    .build_indexed_element_pointer(
+                        builder.build_bitcast(vector(6).unwrap(), u32_pointer_type),
+                        indices,
+                    )
+                    .unwrap();
+                builder
+                    .build_intrinsic(intrinsics::SCATTER, None, &[values, scatter_pointers, mask])
+                    .unwrap();
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let compiled_code = make_compiler().run(Test, Default::default()).unwrap();
+        let function = compiled_code.get(&FunctionKey::Function).unwrap();
+        let mut scalars = [4.0, 0.0, 0.0, 0.0];
+        let vector = |elements| Vec4 { elements };
+        let mut vectors = Vectors([
+            vector([1, 0, 1, 0]),
+            vector([1, 2, 3, 4]),
+            vector([7; 4]),
+            vector([9; 4]),
+            vector([3, 2, 1, 0]),
+            vector([0; 4]),
+            vector([0; 4]),
+        ]);
+        unsafe {
+            let function: GeneratedFunctionType = mem::transmute(function);
+            function(&mut scalars, &mut vectors.0);
+        }
+        assert_eq!(scalars, [2.0, 0.5, 20.0, 8.0]);
+        let vectors: Vec<_> = vectors.0.iter().map(|vector| vector.elements).collect();
+        assert_eq!(
+            vectors,
+            [
+                [1, 0, 1, 0],
+                [1, 2, 3, 4],
+                [1, 7, 3, 7],
+                [1, 9, 3, 9],
+                [3, 2, 1, 0],
+                [4, 2, 2, 4],
+                [0, 3, 0, 1],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_store() {
+        type GeneratedFunctionType = unsafe extern "C" fn(*mut [u32; 2]);
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                let array = function.parameters()[0].clone();
+                let source = builder
+                    .build_element_pointer(array.clone(), &[0, 0])
+                    .unwrap();
+                let destination = builder.build_element_pointer(array, &[0, 1]).unwrap();
+                let value = builder.build_load(source);
+                builder.build_store(value, destination);
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let compiled_code = make_compiler().run(Test, Default::default()).unwrap();
+        let function = compiled_code.get(&FunctionKey::Function).unwrap();
+        let mut array = [1234, 0];
+        unsafe {
+            let function: GeneratedFunctionType = mem::transmute(function);
+            function(&mut array);
+        }
+        assert_eq!(array, [1234, 1234]);
+    }
+
+    #[test]
+    fn test_f16() {
+        type GeneratedFunctionType = unsafe extern "C" fn(*mut u16, *mut f32);
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                let f16_type = type_builder.build_f16();
+                let f32_type = type_builder.build_f32();
+                let half_pointer = builder.build_bitcast(
+                    function.parameters()[0].clone(),
+                    type_builder.build_pointer(f16_type.clone()),
+                );
+                let float_pointer = function.parameters()[1].clone();
+                // swap the values, converting each to the other's type
+                let half = builder.build_load(half_pointer.clone());
+                let float = builder.build_load(float_pointer.clone());
+                let extended = builder.build_float_extend(half, f32_type);
+                let truncated = builder.build_float_truncate(float, f16_type);
+                builder.build_store(extended, float_pointer);
+                builder.build_store(truncated, half_pointer);
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let compiled_code = make_compiler().run(Test, Default::default()).unwrap();
+        let function = compiled_code.get(&FunctionKey::Function).unwrap();
+        let mut half = 0xC000; // -2.0
+        let mut float = 1.0 / 3.0;
+        unsafe {
+            let function: GeneratedFunctionType = mem::transmute(function);
+            function(&mut half, &mut float);
+        }
+        assert_eq!(half, 0x3555);
+        assert_eq!(float, -2.0);
+    }
+
+    #[test]
+    fn test_half_conversions() {
+        use half::{f16_to_f32, f32_to_f16, f64_to_f16};
+        for value in 0..=0xFFFFu16 {
+            let float = f16_to_f32(value);
+            if float.is_nan() {
+                assert!(f16_to_f32(f32_to_f16(float)).is_nan());
+                continue;
+            }
+            assert_eq!(f32_to_f16(float), value, "{:#X}", value);
+            assert_eq!(f64_to_f16(float.into()), value, "{:#X}", value);
+        }
+        // subnormals
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f32_to_f16(2f32.powi(-25)), 0);
+        assert_eq!(f32_to_f16(2f32.powi(-25) * 1.5), 0x0001);
+        // ties round to even
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-11)), 0x3C00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-11)), 0x3C02);
+        // overflow
+        assert_eq!(f32_to_f16(65519.0), 0x7BFF);
+        assert_eq!(f32_to_f16(65520.0), 0x7C00);
+        assert_eq!(f64_to_f16(-1e300), 0xFC00);
+        assert_eq!(f32_to_f16(::std::f32::INFINITY), 0x7C00);
+    }
+
+    #[test]
+    fn test_atomics() {
+        type GeneratedFunctionType = unsafe extern "C" fn(*mut u32, *mut [u32; 3]);
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                let counter = function.parameters()[0].clone();
+                let results = function.parameters()[1].clone();
+                let i32_type = type_builder.build_i32();
+                let five = builder.build_integer_constant(i32_type.clone(), 5);
+                let added = builder.build_atomic_rmw(
+                    AtomicRMWOperation::Add,
+                    counter.clone(),
+                    five,
+                    AtomicOrdering::SequentiallyConsistent,
+                    SynchronizationScope::System,
+                );
+                let six = builder.build_integer_constant(i32_type.clone(), 6);
+                let ten = builder.build_integer_constant(i32_type, 10);
+                let exchanged = builder.build_atomic_compare_exchange(
+                    counter.clone(),
+                    six,
+                    ten,
+                    AtomicOrdering::AcquireRelease,
+                    AtomicOrdering::Acquire,
+                    SynchronizationScope::System,
+                );
+                let loaded = builder.build_atomic_load(
+                    counter,
+                    AtomicOrdering::Acquire,
+                    SynchronizationScope::System,
+                );
+                for (index, value) in vec![added, exchanged, loaded].into_iter().enumerate() {
+                    let pointer = builder
+                        .build_element_pointer(results.clone(), &[0, index as u32])
+                        .unwrap();
+                    builder.build_atomic_store(
+                        value,
+                        pointer,
+                        AtomicOrdering::Release,
+                        SynchronizationScope::SingleThread,
+                    );
+                }
+                builder.build_fence(
+                    AtomicOrdering::SequentiallyConsistent,
+                    SynchronizationScope::System,
+                );
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let compiled_code = make_compiler().run(Test, Default::default()).unwrap();
+        let function = compiled_code.get(&FunctionKey::Function).unwrap();
+        let mut counter = 1;
+        let mut results = [0; 3];
+        unsafe {
+            let function: GeneratedFunctionType = mem::transmute(function);
+            function(&mut counter, &mut results);
+        }
+        assert_eq!(counter, 10);
+        assert_eq!(results, [1, 6, 10]);
+    }
+
+    #[test]
+    fn test_indexed_element_pointer() {
+        type GeneratedFunctionType = unsafe extern "C" fn(*mut u32, u32);
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                let array = function.parameters()[0].clone();
+                let index = function.parameters()[1].clone();
+                let destination = builder
+                    .build_indexed_element_pointer(array.clone(), index)
+                    .unwrap();
+                let value = builder.build_load(array);
+                builder.build_store(value, destination);
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let compiled_code = make_compiler().run(Test, Default::default()).unwrap();
+        let function = compiled_code.get(&FunctionKey::Function).unwrap();
+        let mut array = [1234, 0, 0];
+        unsafe {
+            let function: GeneratedFunctionType = mem::transmute(function);
+            function(array.as_mut_ptr(), 2);
+        }
+        assert_eq!(array, [1234, 0, 1234]);
+    }
+
+    #[test]
+    fn test_branches() {
+        type GeneratedFunctionType = unsafe extern "C" fn(*mut u32, u32);
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let i32_type = type_builder.build_i32();
+                let result = function.parameters()[0].clone();
+                let selector = function.parameters()[1].clone();
+                let entry_block = function.append_new_basic_block(None).unwrap();
+                let check_block = function.append_new_basic_block(None).unwrap();
+                let table_block = function.append_new_basic_block(None).unwrap();
+                let case_blocks: Vec<_> = (0..3)
+                    .map(|_| function.append_new_basic_block(None).unwrap())
+                    .collect();
+                let cases: Vec<_> = case_blocks.iter().map(|v| v.as_basic_block()).collect();
+                // 10 and 11 go to the first two cases, and the rest go through the range check
+                // to the jump table, which sends 0 to 3 to the cases 0, 1, 0 and 2
+                let builder = context.create_builder().attach(entry_block);
+                let builder = builder
+                    .build_switch(
+                        selector.clone(),
+                        check_block.as_basic_block(),
+                        &[(10, cases[0].clone()), (11, cases[1].clone())],
+                    )
+                    .unwrap()
+                    .attach(check_block);
+                let four = builder.build_integer_constant(i32_type.clone(), 4);
+                let in_range = builder.build_integer_compare(
+                    IntegerComparison::UnsignedLess,
+                    selector.clone(),
+                    four,
+                );
+                let mut builder = builder
+                    .build_conditional_branch(
+                        in_range,
+                        table_block.as_basic_block(),
+                        cases[2].clone(),
+                    )
+                    .attach(table_block)
+                    .build_jump_table(
+                        selector.clone(),
+                        &[
+                            cases[0].clone(),
+                            cases[1].clone(),
+                            cases[0].clone(),
+                            cases[2].clone(),
+                        ],
+                    )
+                    .unwrap();
+                // the last case stores the selector plus 100
+                for (index, case_block) in case_blocks.into_iter().enumerate() {
+                    let attached_builder = builder.attach(case_block);
+                    let value = attached_builder.build_integer_constant(
+                        i32_type.clone(),
+                        if index == 2 { 100 } else { index as u64 + 1 },
+                    );
+                    let value = if index == 2 {
+                        attached_builder.build_integer_arithmetic(
+                            IntegerArithmeticOperation::Add,
+                            selector.clone(),
+                            value,
+                        )
+                    } else {
+                        value
+                    };
+                    attached_builder.build_store(value, result.clone());
+                    builder = attached_builder.build_return(None);
+                }
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let compiled_code = make_compiler().run(Test, Default::default()).unwrap();
+        let function = compiled_code.get(&FunctionKey::Function).unwrap();
+        let results: Vec<u32> = [0, 1, 2, 3, 4, 10, 11]
+            .iter()
+            .map(|&selector| {
+                let mut result = 0;
+                unsafe {
+                    let function: GeneratedFunctionType = mem::transmute(function);
+                    function(&mut result, selector);
+                }
+                result
+            })
+            .collect();
+        assert_eq!(results, [1, 2, 1, 103, 104, 1, 2]);
+    }
+
+    #[test]
+    fn test_debug_info() {
+        type GeneratedFunctionType = unsafe extern "C" fn(*mut u32);
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                module.set_source_file_name("test.comp");
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                module
+                    .set_function_source_location(
+                        &function,
+                        "main",
+                        SourceLocation {
+                            file_name: "test.comp",
+                            line: 1,
+                            column: 1,
+                        },
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                let array = function.parameters()[0].clone();
+                builder.set_source_location(Some(SourceLocation {
+                    file_name: "test.comp",
+                    line: 2,
+                    column: 5,
+                }));
+                let value = builder.build_load(array.clone());
+                // instructions can come from included files
+                builder.set_source_location(Some(SourceLocation {
+                    file_name: "include.h",
+                    line: 10,
+                    column: 0,
+                }));
+                let destination = builder.build_element_pointer(array, &[1]).unwrap();
+                builder.build_store(value, destination);
+                builder.set_source_location(None);
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let config = CompilerIndependentConfig {
+            debug_info: true,
+            ..Default::default()
+        };
+        let compiled_code = make_compiler().run(Test, config.into()).unwrap();
+        let function = compiled_code.get(&FunctionKey::Function).unwrap();
+        let mut array = [1234, 0];
+        unsafe {
+            let function: GeneratedFunctionType = mem::transmute(function);
+            function(array.as_mut_ptr());
+        }
+        assert_eq!(array, [1234, 1234]);
+    }
+
+    #[test]
+    fn test_target_overrides() {
+        type GeneratedFunctionType = unsafe extern "C" fn();
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let run = |cpu: Option<&str>, cpu_features: Option<&str>| {
+            ::LLVM_7_SHADER_COMPILER
+                .run(
+                    Test,
+                    ::LLVM7CompilerConfig {
+                        cpu: cpu.map(Into::into),
+                        cpu_features: cpu_features.map(Into::into),
+                        ..Default::default()
+                    },
+                )
+                .map(|_| ())
+        };
+        assert!(run(Some("not-a-cpu"), None)
+            .unwrap_err()
+            .contains("unsupported CPU"));
+        assert!(run(None, Some("+not-a-feature"))
+            .unwrap_err()
+            .contains("unsupported CPU feature"));
+        assert!(run(None, Some("sse2")).is_err());
+        if cfg!(target_arch = "x86_64") {
+            run(Some("x86-64"), Some("+sse4.2,-avx512f")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_shader_dump() {
+        type GeneratedFunctionType = unsafe extern "C" fn(u32);
+        #[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+        enum FunctionKey {
+            Function,
+        }
+        struct Test;
+        impl CompilerUser for Test {
+            type FunctionKey = FunctionKey;
+            type Error = String;
+            fn create_error(message: String) -> String {
+                message
+            }
+            fn run<'a, C: Context<'a>>(
+                self,
+                context: &'a C,
+            ) -> Result<CompileInputs<'a, C, FunctionKey>, String> {
+                let type_builder = context.create_type_builder();
+                let mut module = context.create_module("test_module").unwrap();
+                let mut function = module
+                    .add_function(
+                        "test_function",
+                        type_builder.build::<GeneratedFunctionType>().unwrap(),
+                    )
+                    .unwrap();
+                let builder = context.create_builder();
+                let builder = builder.attach(function.append_new_basic_block(None).unwrap());
+                builder.build_return(None);
+                let module = module.verify().unwrap();
+                Ok(CompileInputs {
+                    module,
+                    callable_functions: vec![(FunctionKey::Function, function)]
+                        .into_iter()
+                        .collect(),
+                })
+            }
+        }
+        let shader_dump = ShaderDumpConfig {
+            directory: env::temp_dir().join(format!("kazan-test-shader-dump-{}", process::id())),
+            name: "test".into(),
+        };
+        let config = CompilerIndependentConfig {
+            shader_dump: Some(shader_dump.clone()),
+            ..Default::default()
+        };
+        make_compiler().run(Test, config.into()).unwrap();
+        for &stage in &[
+            ShaderDumpStage::UnoptimizedIR,
+            ShaderDumpStage::OptimizedIR,
+            ShaderDumpStage::Assembly,
+        ] {
+            let contents = fs::read_to_string(shader_dump.path(stage)).unwrap();
+            assert!(contents.contains("test_function"), "{:?}", stage);
+        }
+        fs::remove_dir_all(&shader_dump.directory).unwrap();
+    }
+
+    /// parse the textual IR `ir`, run the register pressure heuristics on it and return the
+    /// resulting IR
+    fn reduce_register_pressure(ir: &str) -> String {
+        unsafe {
+            let context = llvm::LLVMContextCreate();
+            let memory_buffer = llvm::LLVMCreateMemoryBufferWithMemoryRangeCopy(
+                ir.as_ptr() as *const c_char,
+                ir.len(),
+                b"test_module\0".as_ptr() as *const c_char,
+            );
+            let mut module = null_mut();
+            let mut message = null_mut();
+            if llvm::LLVMParseIRInContext(context, memory_buffer, &mut module, &mut message) != 0 {
+                panic!("invalid IR: {}", CStr::from_ptr(message).to_string_lossy());
+            }
+            register_pressure::reduce_register_pressure(module);
+            if llvm::LLVMVerifyModule(module, llvm::LLVMReturnStatusAction, &mut message) != 0 {
+                panic!(
+                    "heuristics produced invalid IR: {}",
+                    CStr::from_ptr(message).to_string_lossy()
+                );
+            }
+            llvm::LLVMDisposeMessage(message);
+            let string = llvm::LLVMPrintModuleToString(module);
+            let retval = CStr::from_ptr(string).to_string_lossy().into_owned();
+            llvm::LLVMDisposeMessage(string);
+            llvm::LLVMDisposeModule(module);
+            llvm::LLVMContextDispose(context);
+            retval
+        }
+    }
+
+    /// the instructions of the block labeled `label` of the function named `function` in the
+    /// printed IR `ir`
+    fn get_block<'a>(ir: &'a str, function: &str, label: &str) -> Vec<&'a str> {
+        let function_start = ir
+            .find(&format!("@{}(", function))
+            .unwrap_or_else(|| panic!("function not found: {}\n{}", function, ir));
+        let mut lines = ir[function_start..].lines();
+        let label = format!("{}:", label);
+        lines
+            .find(|line| line.starts_with(&label))
+            .unwrap_or_else(|| panic!("block not found: {}\n{}", label, ir));
+        lines
+            .take_while(|line| !line.is_empty() && *line != "}")
+            .map(str::trim)
+            .collect()
+    }
+
+    /// the index of the first instruction in `block` containing `pattern`
+    fn position(block: &[&str], pattern: &str) -> usize {
+        block
+            .iter()
+            .position(|instruction| instruction.contains(pattern))
+            .unwrap_or_else(|| panic!("instruction not found: {}\n{:#?}", pattern, block))
+    }
+
+    #[test]
+    fn test_register_pressure_split_live_ranges() {
+        // both vectors are live across the loop, but only the 256-bit one fills a whole register
+        let ir = reduce_register_pressure(
+            r#"
+define void @test(<8 x float>* %wide_pointer, <4 x float>* %narrow_pointer, i32 %n) {
+entry:
+  %wide = load <8 x float>, <8 x float>* %wide_pointer
+  %narrow = load <4 x float>, <4 x float>* %narrow_pointer
+  br label %loop
+loop:
+  %i = phi i32 [ 0, %entry ], [ %i.next, %loop ]
+  %i.next = add i32 %i, 1
+  %c = icmp ult i32 %i.next, %n
+  br i1 %c, label %loop, label %exit
+exit:
+  store <8 x float> %wide, <8 x float>* %wide_pointer
+  store <4 x float> %narrow, <4 x float>* %narrow_pointer
+  ret void
+}
+"#,
+        );
+        let entry = get_block(&ir, "test", "entry");
+        assert!(entry[0].contains("= alloca <8 x float>"), "{}", ir);
+        assert_eq!(
+            entry.iter().filter(|v| v.contains("alloca")).count(),
+            1,
+            "{}",
+            ir
+        );
+        let spill = position(&entry, "store <8 x float> %wide,");
+        assert!(spill > position(&entry, "%wide = load"), "{}", ir);
+        let exit = get_block(&ir, "test", "exit");
+        assert!(exit[0].contains("= load <8 x float>"), "{}", ir);
+        assert!(!exit.iter().any(|v| v.contains("%wide,")), "{}", ir);
+        position(&exit, "store <4 x float> %narrow,");
+    }
+
+    #[test]
+    fn test_register_pressure_rematerialize() {
+        // the splat is cheap to compute again, but the sum isn't
+        let ir = reduce_register_pressure(
+            r#"
+define void @test(float %x, <8 x float>* %p, i32 %n) {
+entry:
+  %insert = insertelement <8 x float> undef, float %x, i32 0
+  %splat = shufflevector <8 x float> %insert, <8 x float> undef, <8 x i32> zeroinitializer
+  %loaded = load <8 x float>, <8 x float>* %p
+  %sum = fadd <8 x float> %loaded, %loaded
+  br label %loop
+loop:
+  %i = phi i32 [ 0, %entry ], [ %i.next, %loop ]
+  %value = fadd <8 x float> %splat, %sum
+  store <8 x float> %value, <8 x float>* %p
+  %i.next = add i32 %i, 1
+  %c = icmp ult i32 %i.next, %n
+  br i1 %c, label %loop, label %exit
+exit:
+  ret void
+}
+"#,
+        );
+        let entry = get_block(&ir, "test", "entry");
+        assert!(
+            !entry
+                .iter()
+                .any(|v| v.contains("insertelement") || v.contains("shufflevector")),
+            "{}",
+            ir
+        );
+        position(&entry, "%sum = fadd");
+        let loop_ = get_block(&ir, "test", "loop");
+        let insert = position(&loop_, "= insertelement");
+        let shuffle = position(&loop_, "= shufflevector");
+        let value = position(&loop_, "%value = fadd");
+        assert!(insert < shuffle && shuffle < value, "{}", ir);
+        assert!(loop_[value].ends_with("%sum"), "{}", ir);
+    }
+
+    #[test]
+    fn test_register_pressure_coalesce_loop_carried_values() {
+        // in @moved, the phi's new value is computed before the phi's last use, so both are live
+        // at once. in @kept, the new value is used before then too, so it can't be moved
+        // moving an instruction through the builder drops its name, so match on the fadd
+        let ir = reduce_register_pressure(
+            r#"
+define void @moved(<8 x float>* %p, i32 %n) {
+entry:
+  br label %loop
+loop:
+  %i = phi i32 [ 0, %entry ], [ %i.next, %loop ]
+  %acc = phi <8 x float> [ zeroinitializer, %entry ], [ %acc.next, %loop ]
+  %x = load <8 x float>, <8 x float>* %p
+  %acc.next = fadd <8 x float> %x, %x
+  %squared = fmul <8 x float> %acc, %acc
+  store <8 x float> %squared, <8 x float>* %p
+  %i.next = add i32 %i, 1
+  %c = icmp ult i32 %i.next, %n
+  br i1 %c, label %loop, label %exit
+exit:
+  ret void
+}
+
+define void @kept(<8 x float>* %p, i32 %n) {
+entry:
+  br label %loop
+loop:
+  %i = phi i32 [ 0, %entry ], [ %i.next, %loop ]
+  %acc = phi <8 x float> [ zeroinitializer, %entry ], [ %acc.next, %loop ]
+  %x = load <8 x float>, <8 x float>* %p
+  %acc.next = fadd <8 x float> %x, %x
+  %product = fmul <8 x float> %acc, %acc.next
+  store <8 x float> %product, <8 x float>* %p
+  %i.next = add i32 %i, 1
+  %c = icmp ult i32 %i.next, %n
+  br i1 %c, label %loop, label %exit
+exit:
+  ret void
+}
+"#,
+        );
+        let moved = get_block(&ir, "moved", "loop");
+        assert_eq!(
+            position(&moved, "= fadd <8 x float> %x, %x"),
+            position(&moved, "%squared = fmul") + 1,
+            "{}",
+            ir
+        );
+        let kept = get_block(&ir, "kept", "loop");
+        assert!(
+            position(&kept, "= fadd <8 x float> %x, %x") < position(&kept, "%product = fmul"),
+            "{}",
+            ir
+        );
+    }
 }
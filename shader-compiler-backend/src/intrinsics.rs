@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! names of the intrinsics that every backend implements for `AttachedBuilder::build_intrinsic`
+//!
+//! operands are scalars or vectors of the same type unless stated otherwise. `mask` operands are
+//! vectors of bools with an element for each element of the accessed vector, and the elements
+//! that are masked off aren't accessed.
+
+/// `sqrt(x)`: the square root of floats
+pub const SQRT: &str = "sqrt";
+/// `rsqrt(x)`: `1 / sqrt(x)` of floats
+pub const RSQRT: &str = "rsqrt";
+/// `fma(a, b, c)`: `a * b + c` of floats, rounded once
+pub const FMA: &str = "fma";
+/// `masked_load(pointer, mask, passthrough)`: load the vector `pointer` points to, taking the
+/// masked off elements from `passthrough`
+pub const MASKED_LOAD: &str = "masked_load";
+/// `masked_store(value, pointer, mask)`: store the vector `value` to `pointer`, leaving the
+/// masked off elements in memory unchanged. returns nothing
+pub const MASKED_STORE: &str = "masked_store";
+/// `gather(pointers, mask, passthrough)`: load each element from the matching element of the
+/// vector of pointers `pointers`, taking the masked off elements from `passthrough`
+pub const GATHER: &str = "gather";
+/// `scatter(value, pointers, mask)`: store each element of `value` to the matching element of the
+/// vector of pointers `pointers`; elements stored to the same address are stored in order.
+/// returns nothing
+pub const SCATTER: &str = "scatter";
@@ -5,15 +5,178 @@
 //! Shader Compiler Backend Traits for Kazan
 
 use std::collections::HashMap;
-use std::error::Error;
+use std::error;
 use std::fmt;
 use std::fmt::Debug;
+use std::fs;
 use std::hash::Hash;
 use std::io;
 use std::marker::PhantomData;
+use std::path::PathBuf;
 
 #[macro_use]
 pub mod types;
+pub mod intrinsics;
+
+/// error returned from the backend's builder, module, and compiler APIs
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Error {
+    /// a name that the backend can't use, such as one containing a NUL byte
+    InvalidName(String),
+    /// a function with the same name was already added to the module
+    DuplicateName(String),
+    /// an array, struct, or argument list has more elements than the backend supports
+    TooManyElements(usize),
+    /// a vector with no elements or more elements than the backend supports
+    InvalidVectorLength,
+    /// the target can't be used for compilation
+    UnsupportedTarget(String),
+    /// the backend failed to compile the module
+    CompilationFailed(String),
+    /// an intrinsic that the backend can't build with the given types and that isn't declared in
+    /// the module
+    UnknownIntrinsic(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidName(name) => write!(f, "invalid name: {:?}", name),
+            Error::DuplicateName(name) => write!(f, "duplicate name: {:?}", name),
+            Error::TooManyElements(count) => write!(f, "too many elements: {}", count),
+            Error::InvalidVectorLength => write!(f, "invalid vector length"),
+            Error::UnsupportedTarget(message) => write!(f, "unsupported target: {}", message),
+            Error::CompilationFailed(message) => write!(f, "compilation failed: {}", message),
+            Error::UnknownIntrinsic(name) => write!(f, "unknown intrinsic: {:?}", name),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(v: Error) -> Self {
+        io::Error::new(io::ErrorKind::Other, format!("{}", v))
+    }
+}
+
+/// a location in the source code that generated code came from; used for debug info
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct SourceLocation<'a> {
+    /// the source file's name
+    pub file_name: &'a str,
+    /// the line number, starting at 1. 0 means unknown
+    pub line: u32,
+    /// the column number, starting at 1. 0 means unknown
+    pub column: u32,
+}
+
+/// memory ordering of an atomic instruction; equivalent to LLVM's `AtomicOrdering`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AtomicOrdering {
+    /// only the atomic operation itself is ordered; LLVM's `monotonic`
+    Relaxed,
+    /// later memory accesses can't move before this one
+    Acquire,
+    /// earlier memory accesses can't move after this one
+    Release,
+    /// both `Acquire` and `Release`
+    AcquireRelease,
+    /// `AcquireRelease` and there is a single total order of all `SequentiallyConsistent`
+    /// operations
+    SequentiallyConsistent,
+}
+
+/// the threads an atomic instruction synchronizes with
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SynchronizationScope {
+    /// only the current thread, such as with a signal handler
+    SingleThread,
+    /// all threads
+    System,
+}
+
+/// operation done by an atomic read-modify-write instruction
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AtomicRMWOperation {
+    /// replace the value
+    Exchange,
+    /// wrapping integer addition
+    Add,
+    /// wrapping integer subtraction
+    Subtract,
+    /// bitwise and
+    And,
+    /// bitwise or
+    Or,
+    /// bitwise xor
+    Xor,
+    /// signed integer minimum
+    SignedMin,
+    /// signed integer maximum
+    SignedMax,
+    /// unsigned integer minimum
+    UnsignedMin,
+    /// unsigned integer maximum
+    UnsignedMax,
+}
+
+/// floating-point arithmetic operation with two operands
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum FloatArithmeticOperation {
+    /// addition
+    Add,
+    /// subtraction
+    Subtract,
+    /// multiplication
+    Multiply,
+    /// division
+    Divide,
+    /// remainder with the sign of the dividend, like C's `fmod`
+    Remainder,
+}
+
+/// integer arithmetic operation with two operands
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum IntegerArithmeticOperation {
+    /// wrapping addition
+    Add,
+    /// wrapping subtraction
+    Subtract,
+    /// wrapping multiplication
+    Multiply,
+    /// bitwise and
+    And,
+    /// bitwise or
+    Or,
+    /// bitwise xor
+    Xor,
+}
+
+/// integer comparison; equivalent to LLVM's `ICmpInst::Predicate`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum IntegerComparison {
+    /// `==`
+    Equal,
+    /// `!=`
+    NotEqual,
+    /// unsigned `<`
+    UnsignedLess,
+    /// unsigned `<=`
+    UnsignedLessOrEqual,
+    /// unsigned `>`
+    UnsignedGreater,
+    /// unsigned `>=`
+    UnsignedGreaterOrEqual,
+    /// signed `<`
+    SignedLess,
+    /// signed `<=`
+    SignedLessOrEqual,
+    /// signed `>`
+    SignedGreater,
+    /// signed `>=`
+    SignedGreaterOrEqual,
+}
 
 /// equivalent to LLVM's 'IRBuilder'
 pub trait AttachedBuilder<'a>: Sized {
@@ -21,11 +184,184 @@ pub trait AttachedBuilder<'a>: Sized {
     type Context: Context<'a>;
     /// get the current `BasicBlock`
     fn current_basic_block(&self) -> <Self::Context as Context<'a>>::BasicBlock;
+    /// set the source location attached to instructions built after this call.
+    /// does nothing unless debug info is enabled and the current function has a source location
+    fn set_source_location(&self, location: Option<SourceLocation>);
+    /// build a call instruction
+    fn build_call(
+        &self,
+        function: <Self::Context as Context<'a>>::Value,
+        arguments: &[<Self::Context as Context<'a>>::Value],
+    ) -> Result<<Self::Context as Context<'a>>::Value, Error>;
+    /// build a call to the intrinsic `name`, for operations that don't have their own method.
+    /// every backend implements the intrinsics in `intrinsics`; other names are backend-specific
+    /// intrinsics or runtime functions declared in the module, such as with
+    /// `Module::add_external_function`. `return_type` is `None` for intrinsics that return
+    /// nothing
+    fn build_intrinsic(
+        &self,
+        name: &str,
+        return_type: Option<<Self::Context as Context<'a>>::Type>,
+        arguments: &[<Self::Context as Context<'a>>::Value],
+    ) -> Result<<Self::Context as Context<'a>>::Value, Error>;
+    /// build a load instruction
+    fn build_load(
+        &self,
+        pointer: <Self::Context as Context<'a>>::Value,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build a store instruction
+    fn build_store(
+        &self,
+        value: <Self::Context as Context<'a>>::Value,
+        pointer: <Self::Context as Context<'a>>::Value,
+    );
+    /// build an in-bounds getelementptr instruction with constant indices
+    fn build_element_pointer(
+        &self,
+        pointer: <Self::Context as Context<'a>>::Value,
+        indices: &[u32],
+    ) -> Result<<Self::Context as Context<'a>>::Value, Error>;
+    /// build an in-bounds getelementptr instruction with a single index computed at runtime
+    fn build_indexed_element_pointer(
+        &self,
+        pointer: <Self::Context as Context<'a>>::Value,
+        index: <Self::Context as Context<'a>>::Value,
+    ) -> Result<<Self::Context as Context<'a>>::Value, Error>;
+    /// build a bitcast instruction
+    fn build_bitcast(
+        &self,
+        value: <Self::Context as Context<'a>>::Value,
+        ty: <Self::Context as Context<'a>>::Type,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build a fpext instruction, converting to the wider floating-point type `ty`
+    fn build_float_extend(
+        &self,
+        value: <Self::Context as Context<'a>>::Value,
+        ty: <Self::Context as Context<'a>>::Type,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build a fptrunc instruction, converting to the narrower floating-point type `ty`
+    fn build_float_truncate(
+        &self,
+        value: <Self::Context as Context<'a>>::Value,
+        ty: <Self::Context as Context<'a>>::Type,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build a sext or zext instruction, converting to the wider integer type `ty`
+    fn build_integer_extend(
+        &self,
+        value: <Self::Context as Context<'a>>::Value,
+        ty: <Self::Context as Context<'a>>::Type,
+        is_signed: bool,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build a trunc instruction, converting to the narrower integer type `ty`
+    fn build_integer_truncate(
+        &self,
+        value: <Self::Context as Context<'a>>::Value,
+        ty: <Self::Context as Context<'a>>::Type,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build floating-point arithmetic on scalars or vectors of the same type
+    fn build_float_arithmetic(
+        &self,
+        operation: FloatArithmeticOperation,
+        lhs: <Self::Context as Context<'a>>::Value,
+        rhs: <Self::Context as Context<'a>>::Value,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build floating-point negation
+    fn build_float_negate(
+        &self,
+        value: <Self::Context as Context<'a>>::Value,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build integer arithmetic on scalars or vectors of the same type
+    fn build_integer_arithmetic(
+        &self,
+        operation: IntegerArithmeticOperation,
+        lhs: <Self::Context as Context<'a>>::Value,
+        rhs: <Self::Context as Context<'a>>::Value,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build an integer comparison of scalars or vectors of the same type, returning a bool or a
+    /// vector of bools
+    fn build_integer_compare(
+        &self,
+        comparison: IntegerComparison,
+        lhs: <Self::Context as Context<'a>>::Value,
+        rhs: <Self::Context as Context<'a>>::Value,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build an integer constant; `value` is truncated to the width of `ty`
+    fn build_integer_constant(
+        &self,
+        ty: <Self::Context as Context<'a>>::Type,
+        value: u64,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build an atomic load instruction. `ordering` can't be `Release` or `AcquireRelease`
+    fn build_atomic_load(
+        &self,
+        pointer: <Self::Context as Context<'a>>::Value,
+        ordering: AtomicOrdering,
+        scope: SynchronizationScope,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build an atomic store instruction. `ordering` can't be `Acquire` or `AcquireRelease`
+    fn build_atomic_store(
+        &self,
+        value: <Self::Context as Context<'a>>::Value,
+        pointer: <Self::Context as Context<'a>>::Value,
+        ordering: AtomicOrdering,
+        scope: SynchronizationScope,
+    );
+    /// build an atomicrmw instruction, returning the previous value
+    fn build_atomic_rmw(
+        &self,
+        operation: AtomicRMWOperation,
+        pointer: <Self::Context as Context<'a>>::Value,
+        value: <Self::Context as Context<'a>>::Value,
+        ordering: AtomicOrdering,
+        scope: SynchronizationScope,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build a cmpxchg instruction, returning the previous value.
+    /// `failure_ordering` can't be `Release` or `AcquireRelease` and can't be stronger than
+    /// `success_ordering`
+    fn build_atomic_compare_exchange(
+        &self,
+        pointer: <Self::Context as Context<'a>>::Value,
+        comparator: <Self::Context as Context<'a>>::Value,
+        value: <Self::Context as Context<'a>>::Value,
+        success_ordering: AtomicOrdering,
+        failure_ordering: AtomicOrdering,
+        scope: SynchronizationScope,
+    ) -> <Self::Context as Context<'a>>::Value;
+    /// build a fence instruction. `ordering` can't be `Relaxed`
+    fn build_fence(&self, ordering: AtomicOrdering, scope: SynchronizationScope);
     /// build a return instruction
     fn build_return(
         self,
         value: Option<<Self::Context as Context<'a>>::Value>,
     ) -> <Self::Context as Context<'a>>::DetachedBuilder;
+    /// build an unconditional branch
+    fn build_branch(
+        self,
+        destination: <Self::Context as Context<'a>>::BasicBlock,
+    ) -> <Self::Context as Context<'a>>::DetachedBuilder;
+    /// build a conditional branch on the bool `condition`
+    fn build_conditional_branch(
+        self,
+        condition: <Self::Context as Context<'a>>::Value,
+        true_destination: <Self::Context as Context<'a>>::BasicBlock,
+        false_destination: <Self::Context as Context<'a>>::BasicBlock,
+    ) -> <Self::Context as Context<'a>>::DetachedBuilder;
+    /// build a switch instruction on the integer `value`. the case values are truncated to the
+    /// width of `value` and must be distinct
+    fn build_switch(
+        self,
+        value: <Self::Context as Context<'a>>::Value,
+        default_destination: <Self::Context as Context<'a>>::BasicBlock,
+        cases: &[(u64, <Self::Context as Context<'a>>::BasicBlock)],
+    ) -> Result<<Self::Context as Context<'a>>::DetachedBuilder, Error>;
+    /// build a computed branch to `destinations[index]` through a constant table of the
+    /// destinations' addresses. `index` is an integer that must be less than the number of
+    /// destinations, and the function's entry block can't be a destination
+    fn build_jump_table(
+        self,
+        index: <Self::Context as Context<'a>>::Value,
+        destinations: &[<Self::Context as Context<'a>>::BasicBlock],
+    ) -> Result<<Self::Context as Context<'a>>::DetachedBuilder, Error>;
 }
 
 /// equivalent to LLVM's 'IRBuilder'
@@ -75,7 +411,7 @@ pub trait Function<'a>: Debug + Sized {
     fn append_new_basic_block(
         &mut self,
         name: Option<&str>,
-    ) -> <Self::Context as Context<'a>>::BuildableBasicBlock;
+    ) -> Result<<Self::Context as Context<'a>>::BuildableBasicBlock, Error>;
     /// get this function's parameters
     fn parameters(&self) -> &[<Self::Context as Context<'a>>::Value];
 }
@@ -117,7 +453,7 @@ impl<'a, M: Module<'a>> Debug for VerificationFailure<'a, M> {
     }
 }
 
-impl<'a, M: Module<'a>> Error for VerificationFailure<'a, M> {}
+impl<'a, M: Module<'a>> error::Error for VerificationFailure<'a, M> {}
 
 impl<'a, M: Module<'a>> From<VerificationFailure<'a, M>> for io::Error {
     fn from(v: VerificationFailure<'a, M>) -> Self {
@@ -136,7 +472,23 @@ pub trait Module<'a>: Debug + Sized {
         &mut self,
         name: &str,
         ty: <Self::Context as Context<'a>>::Type,
-    ) -> <Self::Context as Context<'a>>::Function;
+    ) -> Result<<Self::Context as Context<'a>>::Function, Error>;
+    /// add a declaration for a function that is implemented outside of the generated code.
+    /// calls to the returned function go to `address`
+    fn add_external_function(
+        &mut self,
+        name: &str,
+        ty: <Self::Context as Context<'a>>::Type,
+        address: unsafe extern "C" fn(),
+    ) -> Result<<Self::Context as Context<'a>>::Function, Error>;
+    /// set the source location and name that debuggers show for `function`.
+    /// does nothing unless debug info is enabled
+    fn set_function_source_location(
+        &mut self,
+        function: &<Self::Context as Context<'a>>::Function,
+        name: &str,
+        location: SourceLocation,
+    ) -> Result<(), Error>;
     /// verify `Self`, converting into a `VerifiedModule`
     fn verify(
         self,
@@ -176,7 +528,7 @@ pub trait Context<'a>: Sized {
     /// the `TypeBuilder` type
     type TypeBuilder: types::TypeBuilder<'a, Self::Type>;
     /// create a new `Module`
-    fn create_module(&self, name: &str) -> Self::Module;
+    fn create_module(&self, name: &str) -> Result<Self::Module, Error>;
     /// create a new `DetachedBuilder`
     fn create_builder(&self) -> Self::DetachedBuilder;
     /// create a new `TypeBuilder`
@@ -207,6 +559,10 @@ pub trait CompilerUser {
     type Error;
     /// create an instance of `Error`
     fn create_error(message: String) -> Self::Error;
+    /// convert an error returned by the backend into an instance of `Error`
+    fn create_backend_error(error: Error) -> Self::Error {
+        Self::create_error(error.to_string())
+    }
     /// the function that the user of `Compiler` implements
     fn run<'a, C: Context<'a>>(
         self,
@@ -229,11 +585,78 @@ impl Default for OptimizationMode {
     }
 }
 
+/// a stage of compilation that can be written out by `ShaderDumpConfig`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ShaderDumpStage {
+    /// the SPIR-V disassembly of the shader after it was trimmed and linked
+    SpirV,
+    /// the backend's IR before optimization
+    UnoptimizedIR,
+    /// the backend's IR after optimization
+    OptimizedIR,
+    /// the generated target assembly
+    Assembly,
+}
+
+impl ShaderDumpStage {
+    /// the file extension used for this stage
+    pub fn extension(self) -> &'static str {
+        match self {
+            ShaderDumpStage::SpirV => "spvasm",
+            ShaderDumpStage::UnoptimizedIR => "ll",
+            ShaderDumpStage::OptimizedIR => "opt.ll",
+            ShaderDumpStage::Assembly => "s",
+        }
+    }
+}
+
+/// writes out the intermediate stages of compiling a shader, for debugging and bug reports
+#[derive(Clone, Debug)]
+pub struct ShaderDumpConfig {
+    /// the directory the files are written to; created if it doesn't exist
+    pub directory: PathBuf,
+    /// the file name used for all stages, usually derived from the module and entry point names
+    pub name: String,
+}
+
+impl ShaderDumpConfig {
+    /// the path of the file that `stage` is written to
+    pub fn path(&self, stage: ShaderDumpStage) -> PathBuf {
+        self.directory
+            .join(format!("{}.{}", self.name, stage.extension()))
+    }
+    /// write `contents` to the file for `stage`.
+    /// dumping is only a debugging aid, so failures are reported to stderr instead of returned
+    pub fn dump<T: AsRef<[u8]>>(&self, stage: ShaderDumpStage, contents: T) {
+        let path = self.path(stage);
+        if let Err(error) =
+            fs::create_dir_all(&self.directory).and_then(|_| fs::write(&path, contents))
+        {
+            eprintln!("kazan: can't write {}: {}", path.display(), error);
+        }
+    }
+}
+
 /// compiler independent config options
 #[derive(Clone, Debug, Default)]
 pub struct CompilerIndependentConfig {
     /// optimization mode
     pub optimization_mode: OptimizationMode,
+    /// generate debug info and register the compiled code with debuggers and profilers
+    pub debug_info: bool,
+    /// write out the intermediate stages of compilation
+    pub shader_dump: Option<ShaderDumpConfig>,
+    /// evaluate float arithmetic decorated with `RelaxedPrecision` in f16
+    pub relaxed_precision_in_f16: bool,
+    /// what to multiply the base length of variable-length vectors by; `None` uses the
+    /// compiler's default
+    pub variable_vector_length_multiplier: Option<u32>,
+    /// check the invocation's `Watchdog` at every loop back edge, so shaders that loop forever
+    /// can be stopped
+    pub watchdog_checks: bool,
+    /// the names and addresses of host functions that modules can call by declaring a function
+    /// with the same name without adding any basic blocks
+    pub host_functions: Vec<(&'static str, usize)>,
 }
 
 /// main compiler backend trait
@@ -255,18 +678,18 @@ pub trait Compiler: Copy + Send + Sync + 'static {
 mod test {
     #![allow(dead_code)]
 
-    buildable_struct!{
+    buildable_struct! {
         struct S1 {
         }
     }
 
-    buildable_struct!{
+    buildable_struct! {
         pub struct S2 {
             v: u32,
         }
     }
 
-    buildable_struct!{
+    buildable_struct! {
         struct S3 {
             p: *mut S2,
             v: ::types::VecNx4<f32>,
@@ -3,13 +3,14 @@
 
 //! types in backend IR
 
-use super::Context;
+use super::{Context, Error};
 use std::cell::UnsafeCell;
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
 use std::ptr::NonNull;
+use std::sync::atomic::AtomicU32;
 
 #[macro_export]
 macro_rules! buildable_struct_helper {
@@ -19,8 +20,8 @@ macro_rules! buildable_struct_helper {
         }
     } => {
         impl $crate::types::BuildableType for $name {
-            fn build<'a, Ty: $crate::types::Type<'a>, TB: $crate::types::TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-                type_builder.build_struct(&[$(<$member_type as $crate::types::BuildableType>::build(type_builder),)*])
+            fn build<'a, Ty: $crate::types::Type<'a>, TB: $crate::types::TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, $crate::Error> {
+                type_builder.build_struct(&[$(<$member_type as $crate::types::BuildableType>::build(type_builder)?,)*])
             }
         }
 
@@ -117,6 +118,9 @@ pub trait TypeBuilder<'a, Ty: Type<'a>> {
     fn build_i32(&self) -> Ty;
     /// build an 64-bit sign-agnostic integer type
     fn build_i64(&self) -> Ty;
+    /// build an 16-bit IEEE 754 floating-point type.
+    /// arithmetic is done in 32 bits if the target doesn't support 16-bit floating-point
+    fn build_f16(&self) -> Ty;
     /// build an 32-bit IEEE 754 floating-point type
     fn build_f32(&self) -> Ty;
     /// build an 64-bit IEEE 754 floating-point type
@@ -124,15 +128,15 @@ pub trait TypeBuilder<'a, Ty: Type<'a>> {
     /// build a pointer
     fn build_pointer(&self, target: Ty) -> Ty;
     /// build an array
-    fn build_array(&self, element: Ty, count: usize) -> Ty;
+    fn build_array(&self, element: Ty, count: usize) -> Result<Ty, Error>;
     /// build a vector
-    fn build_vector(&self, element: Ty, length: VectorLength) -> Ty;
+    fn build_vector(&self, element: Ty, length: VectorLength) -> Result<Ty, Error>;
     /// build a struct
-    fn build_struct(&self, members: &[Ty]) -> Ty;
+    fn build_struct(&self, members: &[Ty]) -> Result<Ty, Error>;
     /// build a function type
-    fn build_function(&self, arguments: &[Ty], return_type: Option<Ty>) -> Ty;
+    fn build_function(&self, arguments: &[Ty], return_type: Option<Ty>) -> Result<Ty, Error>;
     /// build a type
-    fn build<T: BuildableType>(&self) -> Ty
+    fn build<T: BuildableType>(&self) -> Result<Ty, Error>
     where
         Self: Sized,
     {
@@ -156,6 +160,9 @@ impl<'a, 'b, Ty: Type<'a>> TypeBuilder<'a, Ty> for &'b TypeBuilder<'a, Ty> {
     fn build_i64(&self) -> Ty {
         (*self).build_i64()
     }
+    fn build_f16(&self) -> Ty {
+        (*self).build_f16()
+    }
     fn build_f32(&self) -> Ty {
         (*self).build_f32()
     }
@@ -165,16 +172,16 @@ impl<'a, 'b, Ty: Type<'a>> TypeBuilder<'a, Ty> for &'b TypeBuilder<'a, Ty> {
     fn build_pointer(&self, target: Ty) -> Ty {
         (*self).build_pointer(target)
     }
-    fn build_array(&self, element: Ty, count: usize) -> Ty {
+    fn build_array(&self, element: Ty, count: usize) -> Result<Ty, Error> {
         (*self).build_array(element, count)
     }
-    fn build_vector(&self, element: Ty, length: VectorLength) -> Ty {
+    fn build_vector(&self, element: Ty, length: VectorLength) -> Result<Ty, Error> {
         (*self).build_vector(element, length)
     }
-    fn build_struct(&self, members: &[Ty]) -> Ty {
+    fn build_struct(&self, members: &[Ty]) -> Result<Ty, Error> {
         (*self).build_struct(members)
     }
-    fn build_function(&self, arguments: &[Ty], return_type: Option<Ty>) -> Ty {
+    fn build_function(&self, arguments: &[Ty], return_type: Option<Ty>) -> Result<Ty, Error> {
         (*self).build_function(arguments, return_type)
     }
 }
@@ -182,11 +189,11 @@ impl<'a, 'b, Ty: Type<'a>> TypeBuilder<'a, Ty> for &'b TypeBuilder<'a, Ty> {
 /// trait for rust types that can be built using `TypeBuilder`
 pub trait BuildableType {
     /// build the type represented by `Self`
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty;
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error>;
 }
 
 impl<T: BuildableType> BuildableType for UnsafeCell<T> {
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
         T::build(type_builder)
     }
 }
@@ -229,8 +236,10 @@ pub trait BuildableStruct: BuildableType {
 macro_rules! build_basic_scalar {
     ($type:ty, $build_fn:ident) => {
         impl BuildableType for $type {
-            fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-                type_builder.$build_fn()
+            fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(
+                type_builder: &TB,
+            ) -> Result<Ty, Error> {
+                Ok(type_builder.$build_fn())
             }
         }
 
@@ -251,10 +260,12 @@ build_basic_scalar!(u64, build_i64);
 build_basic_scalar!(i64, build_i64);
 build_basic_scalar!(f32, build_f32);
 build_basic_scalar!(f64, build_f64);
+// same layout as `u32`, for memory that compiled code and the driver access concurrently
+build_basic_scalar!(AtomicU32, build_i32);
 
 impl<'b, T: BuildableType> BuildableType for Option<&'b T> {
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-        type_builder.build_pointer(T::build(type_builder))
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+        Ok(type_builder.build_pointer(T::build(type_builder)?))
     }
 }
 
@@ -263,8 +274,8 @@ impl<'b, T: BuildableType> hidden::ScalarBuildableTypeBase for Option<&'b T> {}
 impl<'b, T: BuildableType> ScalarBuildableType for Option<&'b T> {}
 
 impl<'b, T: BuildableType> BuildableType for Option<&'b mut T> {
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-        type_builder.build_pointer(T::build(type_builder))
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+        Ok(type_builder.build_pointer(T::build(type_builder)?))
     }
 }
 
@@ -273,8 +284,8 @@ impl<'b, T: BuildableType> hidden::ScalarBuildableTypeBase for Option<&'b mut T>
 impl<'b, T: BuildableType> ScalarBuildableType for Option<&'b mut T> {}
 
 impl<'b, T: BuildableType> BuildableType for &'b T {
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-        type_builder.build_pointer(T::build(type_builder))
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+        Ok(type_builder.build_pointer(T::build(type_builder)?))
     }
 }
 
@@ -283,8 +294,8 @@ impl<'b, T: BuildableType> hidden::ScalarBuildableTypeBase for &'b T {}
 impl<'b, T: BuildableType> ScalarBuildableType for &'b T {}
 
 impl<'b, T: BuildableType> BuildableType for &'b mut T {
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-        type_builder.build_pointer(T::build(type_builder))
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+        Ok(type_builder.build_pointer(T::build(type_builder)?))
     }
 }
 
@@ -293,8 +304,8 @@ impl<'b, T: BuildableType> hidden::ScalarBuildableTypeBase for &'b mut T {}
 impl<'b, T: BuildableType> ScalarBuildableType for &'b mut T {}
 
 impl<T: BuildableType> BuildableType for *mut T {
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-        type_builder.build_pointer(T::build(type_builder))
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+        Ok(type_builder.build_pointer(T::build(type_builder)?))
     }
 }
 
@@ -303,8 +314,8 @@ impl<'b, T: BuildableType> hidden::ScalarBuildableTypeBase for *mut T {}
 impl<'b, T: BuildableType> ScalarBuildableType for *mut T {}
 
 impl<T: BuildableType> BuildableType for *const T {
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-        type_builder.build_pointer(T::build(type_builder))
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+        Ok(type_builder.build_pointer(T::build(type_builder)?))
     }
 }
 
@@ -313,8 +324,8 @@ impl<'b, T: BuildableType> hidden::ScalarBuildableTypeBase for *const T {}
 impl<'b, T: BuildableType> ScalarBuildableType for *const T {}
 
 impl<T: BuildableType> BuildableType for NonNull<T> {
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-        type_builder.build_pointer(T::build(type_builder))
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+        Ok(type_builder.build_pointer(T::build(type_builder)?))
     }
 }
 
@@ -323,8 +334,8 @@ impl<'b, T: BuildableType> hidden::ScalarBuildableTypeBase for NonNull<T> {}
 impl<'b, T: BuildableType> ScalarBuildableType for NonNull<T> {}
 
 impl<T: BuildableType> BuildableType for Option<NonNull<T>> {
-    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-        type_builder.build_pointer(T::build(type_builder))
+    fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+        Ok(type_builder.build_pointer(T::build(type_builder)?))
     }
 }
 
@@ -335,8 +346,8 @@ impl<'b, T: BuildableType> ScalarBuildableType for Option<NonNull<T>> {}
 macro_rules! build_unit_function_type {
         ($($arguments:ident,)*) => {
             impl<$($arguments: BuildableType),*> BuildableType for Option<unsafe extern "C" fn($($arguments,)*)> {
-                fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-                    type_builder.build_function(&[$($arguments::build(type_builder),)*], None)
+                fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+                    type_builder.build_function(&[$($arguments::build(type_builder)?,)*], None)
                 }
             }
 
@@ -345,8 +356,8 @@ macro_rules! build_unit_function_type {
             impl<$($arguments: BuildableType),*> ScalarBuildableType for Option<unsafe extern "C" fn($($arguments,)*)> {}
 
             impl<$($arguments: BuildableType),*> BuildableType for unsafe extern "C" fn($($arguments,)*) {
-                fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-                    type_builder.build_function(&[$($arguments::build(type_builder),)*], None)
+                fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+                    type_builder.build_function(&[$($arguments::build(type_builder)?,)*], None)
                 }
             }
 
@@ -359,8 +370,8 @@ macro_rules! build_unit_function_type {
 macro_rules! build_function_type {
         ($($arguments:ident,)*) => {
             impl<R: BuildableType, $($arguments: BuildableType),*> BuildableType for Option<unsafe extern "C" fn($($arguments,)*) -> R> {
-                fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-                    type_builder.build_function(&[$($arguments::build(type_builder),)*], Some(R::build(type_builder)))
+                fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+                    type_builder.build_function(&[$($arguments::build(type_builder)?,)*], Some(R::build(type_builder)?))
                 }
             }
 
@@ -369,8 +380,8 @@ macro_rules! build_function_type {
             impl<R: BuildableType, $($arguments: BuildableType),*> ScalarBuildableType for Option<unsafe extern "C" fn($($arguments,)*) -> R> {}
 
             impl<R: BuildableType, $($arguments: BuildableType),*> BuildableType for unsafe extern "C" fn($($arguments,)*) -> R {
-                fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-                    type_builder.build_function(&[$($arguments::build(type_builder),)*], Some(R::build(type_builder)))
+                fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Result<Ty, Error> {
+                    type_builder.build_function(&[$($arguments::build(type_builder)?,)*], Some(R::build(type_builder)?))
                 }
             }
 
@@ -400,8 +411,10 @@ build_function_types!(
 macro_rules! build_array0 {
     ($length:expr) => {
         impl<T: BuildableType> BuildableType for [T; $length + 1] {
-            fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-                type_builder.build_array(T::build(type_builder), $length + 1)
+            fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(
+                type_builder: &TB,
+            ) -> Result<Ty, Error> {
+                type_builder.build_array(T::build(type_builder)?, $length + 1)
             }
         }
     };
@@ -479,8 +492,10 @@ macro_rules! build_fixed_vector {
         }
 
         impl<Element: ScalarBuildableType> BuildableType for $name<Element> {
-            fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-                type_builder.build_vector(Element::build(type_builder), Self::LENGTH)
+            fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(
+                type_builder: &TB,
+            ) -> Result<Ty, Error> {
+                type_builder.build_vector(Element::build(type_builder)?, Self::LENGTH)
             }
         }
 
@@ -500,8 +515,10 @@ macro_rules! build_variable_vector {
         }
 
         impl<Element: ScalarBuildableType> BuildableType for $name<Element> {
-            fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(type_builder: &TB) -> Ty {
-                type_builder.build_vector(Element::build(type_builder), Self::LENGTH)
+            fn build<'a, Ty: Type<'a>, TB: TypeBuilder<'a, Ty>>(
+                type_builder: &TB,
+            ) -> Result<Ty, Error> {
+                type_builder.build_vector(Element::build(type_builder)?, Self::LENGTH)
             }
         }
 
@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! the binary interface between the driver and compiled entry points
+//!
+//! everything the driver passes to or gets back from compiled code is defined here, so the
+//! driver and the shader compiler can't disagree about it. compiled entry points have the type
+//! `EntryPointFunction`:
+//!
+//! 1. a pointer to the `DescriptorTable`, which points to the bound descriptors and the
+//!    `PushConstantBlock` (see `descriptors`)
+//! 2. a pointer to the invocation's `InvocationInputs`, which points to the submission's
+//!    `Watchdog` (see `watchdog`)
+//! 3. a pointer to the invocation's `InvocationOutputs`
+//!
+//! built-in variables are stored in fixed slots of 32-bit words in `InvocationInputs` and
+//! `InvocationOutputs`, found with `BuiltInSlot::get`. all other inputs and outputs are in
+//! varying buffers, with the word for location `l` and component `c` at `varying_word(l, c)`.
+//! fragment shader inputs are in structure-of-arrays order instead, so each word is loaded for
+//! all the invocations run together with one vector load (see `varying_buffer_index`).
+
+use descriptors::{Descriptor, DescriptorTable, MAX_DESCRIPTOR_SETS};
+use shader_compiler_backend::types::TypeBuilder;
+use shader_compiler_backend::{Context, Error};
+use spirv::{built_in, StorageClass};
+use std::mem;
+use std::ptr;
+use watchdog::Watchdog;
+
+/// the number of 32-bit words in each location of a varying buffer
+pub const WORDS_PER_LOCATION: u32 = 4;
+
+/// the index of the word for component `component` of location `location` in a varying buffer
+pub fn varying_word(location: u32, component: u32) -> u32 {
+    debug_assert!(component < WORDS_PER_LOCATION);
+    WORDS_PER_LOCATION * location + component
+}
+
+/// the index of word `word` of the invocation in lane `lane` of a fragment shader's varying
+/// buffer, which holds the varyings of the `simd_width` invocations that are run together. each
+/// word is stored for all the lanes before the next word, so compiled code reads a word for the
+/// whole group from `simd_width` contiguous words
+pub fn varying_buffer_index(word: u32, lane: u32, simd_width: u32) -> u32 {
+    debug_assert!(lane < simd_width);
+    simd_width * word + lane
+}
+
+/// the maximum size of the push constant block in bytes, reported as `maxPushConstantsSize`
+pub const MAX_PUSH_CONSTANTS_SIZE: u32 = 128;
+
+/// push constant offsets and sizes must be multiples of this
+pub const PUSH_CONSTANTS_GRANULARITY: u32 = 4;
+
+/// the memory that `DescriptorTable`'s push constant pointer points to. aligned enough for any
+/// vector that can be in a push constant block
+#[derive(Copy, Clone)]
+#[repr(C, align(32))]
+pub struct PushConstantBlock {
+    pub bytes: [u8; MAX_PUSH_CONSTANTS_SIZE as usize],
+}
+
+impl Default for PushConstantBlock {
+    fn default() -> Self {
+        PushConstantBlock {
+            bytes: [0; MAX_PUSH_CONSTANTS_SIZE as usize],
+        }
+    }
+}
+
+/// a built-in variable's words in `InvocationInputs::built_ins` or `InvocationOutputs::built_ins`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BuiltInSlot {
+    pub built_in: u32,
+    pub storage_class: StorageClass,
+    /// index of the first word
+    pub start: u32,
+    pub word_count: u32,
+}
+
+/// `(built_in, word_count)` in the order they're stored
+const BUILT_IN_INPUTS: &[(u32, u32)] = &[
+    (built_in::VERTEX_INDEX, 1),
+    (built_in::INSTANCE_INDEX, 1),
+    (built_in::BASE_VERTEX, 1),
+    (built_in::BASE_INSTANCE, 1),
+    (built_in::DRAW_INDEX, 1),
+    (built_in::FRAG_COORD, 4),
+    (built_in::FRONT_FACING, 1),
+    (built_in::SAMPLE_ID, 1),
+    (built_in::SAMPLE_MASK, 1),
+    (built_in::NUM_WORKGROUPS, 3),
+    (built_in::WORKGROUP_ID, 3),
+    (built_in::LOCAL_INVOCATION_ID, 3),
+    (built_in::GLOBAL_INVOCATION_ID, 3),
+    (built_in::LOCAL_INVOCATION_INDEX, 1),
+];
+
+const BUILT_IN_OUTPUTS: &[(u32, u32)] = &[
+    (built_in::POSITION, 4),
+    (built_in::POINT_SIZE, 1),
+    (built_in::FRAG_DEPTH, 1),
+    (built_in::FRAG_STENCIL_REF_EXT, 1),
+    (built_in::SAMPLE_MASK, 1),
+];
+
+/// the number of words in `InvocationInputs::built_ins`
+pub const BUILT_IN_INPUT_WORD_COUNT: usize = 25;
+
+/// the number of words in `InvocationOutputs::built_ins`
+pub const BUILT_IN_OUTPUT_WORD_COUNT: usize = 8;
+
+impl BuiltInSlot {
+    fn slots(storage_class: StorageClass) -> &'static [(u32, u32)] {
+        match storage_class {
+            StorageClass::Input => BUILT_IN_INPUTS,
+            StorageClass::Output => BUILT_IN_OUTPUTS,
+            _ => &[],
+        }
+    }
+    /// returns `None` for built-ins that aren't passed in a slot
+    pub fn get(built_in: u32, storage_class: StorageClass) -> Option<Self> {
+        let mut start = 0;
+        for &(slot_built_in, word_count) in Self::slots(storage_class) {
+            if slot_built_in == built_in {
+                return Some(BuiltInSlot {
+                    built_in,
+                    storage_class,
+                    start,
+                    word_count,
+                });
+            }
+            start += word_count;
+        }
+        None
+    }
+}
+
+buildable_struct! {
+    #[derive(Copy)]
+    #[derive(Clone)]
+    #[derive(Debug)]
+    pub struct InvocationInputs {
+        varyings: *const u32,
+        input_attachments: *const [u32; 4],
+        watchdog: *const Watchdog,
+        built_ins: [u32; BUILT_IN_INPUT_WORD_COUNT],
+    }
+}
+
+impl InvocationInputs {
+    /// `varyings` points to the vertex's attributes, or to the interpolated varyings of the group
+    /// of fragments the invocation is run with
+    pub fn new(varyings: *const u32) -> Self {
+        InvocationInputs {
+            varyings,
+            input_attachments: ptr::null(),
+            watchdog: ptr::null(),
+            built_ins: [0; BUILT_IN_INPUT_WORD_COUNT],
+        }
+    }
+    /// `input_attachments` points to the fragment's current value in each of the subpass's
+    /// input attachments, indexed by `InputAttachmentIndex`. see `framebuffer_fetch`
+    pub fn with_input_attachments(self, input_attachments: *const [u32; 4]) -> Self {
+        InvocationInputs {
+            input_attachments,
+            ..self
+        }
+    }
+    /// `watchdog` is checked at loop back edges when the entry point was compiled with
+    /// `watchdog_checks`
+    pub fn with_watchdog(self, watchdog: *const Watchdog) -> Self {
+        InvocationInputs { watchdog, ..self }
+    }
+    /// the words of the built-in input `built_in`
+    pub fn built_in_mut(&mut self, built_in: u32) -> Option<&mut [u32]> {
+        let slot = BuiltInSlot::get(built_in, StorageClass::Input)?;
+        Some(&mut self.built_ins[slot.start as usize..(slot.start + slot.word_count) as usize])
+    }
+}
+
+buildable_struct! {
+    #[derive(Copy)]
+    #[derive(Clone)]
+    #[derive(Debug)]
+    pub struct InvocationOutputs {
+        varyings: *mut u32,
+        built_ins: [u32; BUILT_IN_OUTPUT_WORD_COUNT],
+    }
+}
+
+impl InvocationOutputs {
+    /// `varyings` points to where this vertex's varyings are written
+    pub fn new(varyings: *mut u32) -> Self {
+        InvocationOutputs {
+            varyings,
+            built_ins: [0; BUILT_IN_OUTPUT_WORD_COUNT],
+        }
+    }
+    /// the words of the built-in output `built_in`, as written by the entry point
+    pub fn built_in(&self, built_in: u32) -> Option<&[u32]> {
+        let slot = BuiltInSlot::get(built_in, StorageClass::Output)?;
+        Some(&self.built_ins[slot.start as usize..(slot.start + slot.word_count) as usize])
+    }
+}
+
+/// the type of compiled entry points
+pub type EntryPointFunction =
+    unsafe extern "C" fn(*const DescriptorTable, *const InvocationInputs, *mut InvocationOutputs);
+
+pub fn build_entry_point_type<'a, C: Context<'a>>(context: &'a C) -> Result<C::Type, Error> {
+    context.create_type_builder().build::<EntryPointFunction>()
+}
+
+const POINTER_SIZE: usize = mem::size_of::<*const u8>();
+
+/// `size` rounded up to a multiple of the pointer size, for structs that end with words
+macro_rules! pointer_aligned {
+    ($size:expr) => {
+        ($size + POINTER_SIZE - 1) / POINTER_SIZE * POINTER_SIZE
+    };
+}
+
+// compile-time checks that the layouts haven't changed; the array lengths only match if the
+// sizes are equal
+
+#[allow(dead_code)]
+const DESCRIPTOR_SIZE_CHECK: [(); 2 * POINTER_SIZE + 8] = [(); mem::size_of::<Descriptor>()];
+
+#[allow(dead_code)]
+const DESCRIPTOR_TABLE_SIZE_CHECK: [(); 2 * POINTER_SIZE + 4 * MAX_DESCRIPTOR_SETS] =
+    [(); mem::size_of::<DescriptorTable>()];
+
+#[allow(dead_code)]
+const INVOCATION_INPUTS_SIZE_CHECK: [(); pointer_aligned!(
+    3 * POINTER_SIZE + 4 * BUILT_IN_INPUT_WORD_COUNT
+)] = [(); mem::size_of::<InvocationInputs>()];
+
+#[allow(dead_code)]
+const INVOCATION_OUTPUTS_SIZE_CHECK: [(); pointer_aligned!(
+    POINTER_SIZE + 4 * BUILT_IN_OUTPUT_WORD_COUNT
+)] = [(); mem::size_of::<InvocationOutputs>()];
+
+#[allow(dead_code)]
+const PUSH_CONSTANT_BLOCK_SIZE_CHECK: [(); MAX_PUSH_CONSTANTS_SIZE as usize] =
+    [(); mem::size_of::<PushConstantBlock>()];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shader_compiler_backend::types::BuildableStruct;
+
+    fn member_index<T: BuildableStruct>(name: &str) -> usize {
+        T::get_member_by_name(name).index
+    }
+
+    #[test]
+    fn test_invocation_layout() {
+        assert_eq!(member_index::<InvocationInputs>("varyings"), 0);
+        assert_eq!(member_index::<InvocationInputs>("input_attachments"), 1);
+        assert_eq!(member_index::<InvocationInputs>("watchdog"), 2);
+        assert_eq!(member_index::<InvocationInputs>("built_ins"), 3);
+        assert_eq!(member_index::<InvocationOutputs>("varyings"), 0);
+        assert_eq!(member_index::<InvocationOutputs>("built_ins"), 1);
+        let inputs = InvocationInputs::new(ptr::null());
+        let base = &inputs as *const _ as usize;
+        assert_eq!(
+            &inputs.built_ins as *const _ as usize - base,
+            3 * POINTER_SIZE
+        );
+    }
+
+    #[test]
+    fn test_built_in_slots() {
+        for &(storage_class, slots, word_count) in &[
+            (
+                StorageClass::Input,
+                BUILT_IN_INPUTS,
+                BUILT_IN_INPUT_WORD_COUNT,
+            ),
+            (
+                StorageClass::Output,
+                BUILT_IN_OUTPUTS,
+                BUILT_IN_OUTPUT_WORD_COUNT,
+            ),
+        ] {
+            let mut end = 0;
+            for &(built_in, _) in slots {
+                let slot = BuiltInSlot::get(built_in, storage_class).unwrap();
+                // slots are contiguous, so they don't overlap
+                assert_eq!(slot.start, end);
+                end = slot.start + slot.word_count;
+            }
+            assert_eq!(end as usize, word_count);
+        }
+        let frag_coord = BuiltInSlot::get(built_in::FRAG_COORD, StorageClass::Input).unwrap();
+        assert_eq!((frag_coord.start, frag_coord.word_count), (5, 4));
+        assert_eq!(
+            BuiltInSlot::get(built_in::POSITION, StorageClass::Input),
+            None
+        );
+        let stencil_reference =
+            BuiltInSlot::get(built_in::FRAG_STENCIL_REF_EXT, StorageClass::Output).unwrap();
+        assert_eq!(
+            (stencil_reference.start, stencil_reference.word_count),
+            (6, 1)
+        );
+        // `SampleMask` is an array, but `maxSampleMaskWords` is 1
+        let sample_mask = BuiltInSlot::get(built_in::SAMPLE_MASK, StorageClass::Input).unwrap();
+        assert_eq!((sample_mask.start, sample_mask.word_count), (11, 1));
+        let mut inputs = InvocationInputs::new(ptr::null());
+        inputs
+            .built_in_mut(built_in::INSTANCE_INDEX)
+            .unwrap()
+            .copy_from_slice(&[7]);
+        assert_eq!(inputs.built_ins[1], 7);
+    }
+
+    #[test]
+    fn test_push_constant_block() {
+        assert_eq!(mem::align_of::<PushConstantBlock>(), 32);
+        assert_eq!(MAX_PUSH_CONSTANTS_SIZE % PUSH_CONSTANTS_GRANULARITY, 0);
+    }
+
+    #[test]
+    fn test_varying_word() {
+        assert_eq!(varying_word(0, 0), 0);
+        assert_eq!(varying_word(3, 1), 13);
+        assert_eq!(varying_buffer_index(varying_word(3, 1), 0, 8), 104);
+        assert_eq!(varying_buffer_index(varying_word(3, 1), 5, 8), 109);
+        assert_eq!(varying_buffer_index(13, 0, 1), 13);
+    }
+}
@@ -0,0 +1,576 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! lowering for `OpAtomic*` instructions, barriers and the memory operands of loads and stores
+//!
+//! invocations run on host threads, so every memory scope other than `Invocation` needs
+//! system-wide synchronization. the scope and memory semantics operands must be constants.
+//!
+//! host memory is coherent, so the availability and visibility operations of the `Vulkan`
+//! memory model don't need any code of their own: `MakeAvailable` and `MakeVisible` only add the
+//! release or acquire ordering they require anyway, and accesses with `MakePointerAvailable` or
+//! `MakePointerVisible` become relaxed atomics, so they can't be cached in registers or merged
+//! with other accesses, and are ordered by the barriers and atomics around them.
+//! `NonPrivatePointer` doesn't change anything, since private accesses are never reordered
+//! across barriers in ways that non-private ones aren't.
+
+use shader_compiler_backend::{
+    AtomicOrdering, AtomicRMWOperation, AttachedBuilder, Context, SynchronizationScope,
+};
+use spirv::{op, Error, Id, Instruction};
+
+#[allow(dead_code)]
+pub mod scope {
+    pub const CROSS_DEVICE: u32 = 0;
+    pub const DEVICE: u32 = 1;
+    pub const WORKGROUP: u32 = 2;
+    pub const SUBGROUP: u32 = 3;
+    pub const INVOCATION: u32 = 4;
+    pub const QUEUE_FAMILY: u32 = 5;
+}
+
+#[allow(dead_code)]
+pub mod memory_semantics {
+    pub const ACQUIRE: u32 = 0x2;
+    pub const RELEASE: u32 = 0x4;
+    pub const ACQUIRE_RELEASE: u32 = 0x8;
+    pub const SEQUENTIALLY_CONSISTENT: u32 = 0x10;
+    pub const OUTPUT_MEMORY: u32 = 0x1000;
+    pub const MAKE_AVAILABLE: u32 = 0x2000;
+    pub const MAKE_VISIBLE: u32 = 0x4000;
+    pub const VOLATILE: u32 = 0x8000;
+}
+
+#[allow(dead_code)]
+pub mod memory_access {
+    pub const VOLATILE: u32 = 0x1;
+    pub const ALIGNED: u32 = 0x2;
+    pub const NONTEMPORAL: u32 = 0x4;
+    pub const MAKE_POINTER_AVAILABLE: u32 = 0x8;
+    pub const MAKE_POINTER_VISIBLE: u32 = 0x10;
+    pub const NON_PRIVATE_POINTER: u32 = 0x20;
+}
+
+/// the ordering for the ordering bits of `semantics`; no ordering bits means `Relaxed`.
+/// the storage class bits don't matter since all memory is ordered the same way.
+/// `MakeAvailable` needs release ordering and `MakeVisible` needs acquire ordering
+pub fn get_ordering(semantics: u32) -> AtomicOrdering {
+    let acquire = semantics
+        & (memory_semantics::ACQUIRE
+            | memory_semantics::ACQUIRE_RELEASE
+            | memory_semantics::MAKE_VISIBLE)
+        != 0;
+    let release = semantics
+        & (memory_semantics::RELEASE
+            | memory_semantics::ACQUIRE_RELEASE
+            | memory_semantics::MAKE_AVAILABLE)
+        != 0;
+    if semantics & memory_semantics::SEQUENTIALLY_CONSISTENT != 0 {
+        AtomicOrdering::SequentiallyConsistent
+    } else if acquire && release {
+        AtomicOrdering::AcquireRelease
+    } else if acquire {
+        AtomicOrdering::Acquire
+    } else if release {
+        AtomicOrdering::Release
+    } else {
+        AtomicOrdering::Relaxed
+    }
+}
+
+pub fn get_synchronization_scope(scope: u32) -> SynchronizationScope {
+    match scope {
+        scope::INVOCATION => SynchronizationScope::SingleThread,
+        _ => SynchronizationScope::System,
+    }
+}
+
+/// loads and the failure case of compare-exchange can't release
+fn without_release(ordering: AtomicOrdering) -> AtomicOrdering {
+    match ordering {
+        AtomicOrdering::Release => AtomicOrdering::Relaxed,
+        AtomicOrdering::AcquireRelease => AtomicOrdering::Acquire,
+        _ => ordering,
+    }
+}
+
+/// stores can't acquire
+fn without_acquire(ordering: AtomicOrdering) -> AtomicOrdering {
+    match ordering {
+        AtomicOrdering::Acquire => AtomicOrdering::Relaxed,
+        AtomicOrdering::AcquireRelease => AtomicOrdering::Release,
+        _ => ordering,
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum AtomicOperation {
+    Load,
+    Store,
+    /// also used for `OpAtomicCompareExchangeWeak`, since weak compare-exchanges are allowed but
+    /// not required to fail spuriously
+    CompareExchange,
+    Increment,
+    Decrement,
+    ReadModifyWrite(AtomicRMWOperation),
+}
+
+/// how `AtomicInstruction::build` gets the values of its operands
+pub trait AtomicOperands<'a, C: Context<'a>> {
+    fn get_value(&self, id: Id) -> Result<C::Value, Error>;
+    fn get_type(&self, id: Id) -> Result<C::Type, Error>;
+    /// the value of the integer constant `id`
+    fn get_constant(&self, id: Id) -> Result<u32, Error>;
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AtomicInstruction {
+    pub operation: AtomicOperation,
+    /// `(result_type, result)`; `None` for `OpAtomicStore`
+    pub result: Option<(Id, Id)>,
+    pub pointer: Id,
+    pub scope: Id,
+    pub semantics: Id,
+    /// the value stored, exchanged or combined with the value in memory
+    pub value: Option<Id>,
+    /// the value compared against by `OpAtomicCompareExchange`
+    pub comparator: Option<Id>,
+}
+
+impl AtomicInstruction {
+    /// returns `None` if `instruction` isn't an atomic instruction
+    pub fn parse(instruction: &Instruction) -> Result<Option<Self>, Error> {
+        let operation = match instruction.opcode {
+            op::ATOMIC_LOAD => AtomicOperation::Load,
+            op::ATOMIC_STORE => AtomicOperation::Store,
+            op::ATOMIC_COMPARE_EXCHANGE | op::ATOMIC_COMPARE_EXCHANGE_WEAK => {
+                AtomicOperation::CompareExchange
+            }
+            op::ATOMIC_I_INCREMENT => AtomicOperation::Increment,
+            op::ATOMIC_I_DECREMENT => AtomicOperation::Decrement,
+            opcode => AtomicOperation::ReadModifyWrite(match opcode {
+                op::ATOMIC_EXCHANGE => AtomicRMWOperation::Exchange,
+                op::ATOMIC_I_ADD => AtomicRMWOperation::Add,
+                op::ATOMIC_I_SUB => AtomicRMWOperation::Subtract,
+                op::ATOMIC_S_MIN => AtomicRMWOperation::SignedMin,
+                op::ATOMIC_U_MIN => AtomicRMWOperation::UnsignedMin,
+                op::ATOMIC_S_MAX => AtomicRMWOperation::SignedMax,
+                op::ATOMIC_U_MAX => AtomicRMWOperation::UnsignedMax,
+                op::ATOMIC_AND => AtomicRMWOperation::And,
+                op::ATOMIC_OR => AtomicRMWOperation::Or,
+                op::ATOMIC_XOR => AtomicRMWOperation::Xor,
+                _ => return Ok(None),
+            }),
+        };
+        if operation == AtomicOperation::Store {
+            return Ok(Some(AtomicInstruction {
+                operation,
+                result: None,
+                pointer: instruction.operand(0)?,
+                scope: instruction.operand(1)?,
+                semantics: instruction.operand(2)?,
+                value: Some(instruction.operand(3)?),
+                comparator: None,
+            }));
+        }
+        let (value, comparator) = match operation {
+            AtomicOperation::Load | AtomicOperation::Increment | AtomicOperation::Decrement => {
+                (None, None)
+            }
+            // operand 5 is the semantics for when the comparison fails, which can't be
+            // stronger than the semantics for when it succeeds
+            AtomicOperation::CompareExchange => {
+                (Some(instruction.operand(6)?), Some(instruction.operand(7)?))
+            }
+            _ => (Some(instruction.operand(5)?), None),
+        };
+        Ok(Some(AtomicInstruction {
+            operation,
+            result: Some((instruction.operand(0)?, instruction.operand(1)?)),
+            pointer: instruction.operand(2)?,
+            scope: instruction.operand(3)?,
+            semantics: instruction.operand(4)?,
+            value,
+            comparator,
+        }))
+    }
+    /// returns the result, or `None` for `OpAtomicStore`
+    pub fn build<'a, C: Context<'a>, O: AtomicOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        operands: &O,
+    ) -> Result<Option<C::Value>, Error> {
+        let pointer = operands.get_value(self.pointer)?;
+        let ordering = get_ordering(operands.get_constant(self.semantics)?);
+        let scope = get_synchronization_scope(operands.get_constant(self.scope)?);
+        let get_value = || operands.get_value(self.value.expect("missing value"));
+        let get_one = || -> Result<C::Value, Error> {
+            let (result_type, _) = self.result.expect("missing result");
+            Ok(builder.build_integer_constant(operands.get_type(result_type)?, 1))
+        };
+        Ok(Some(match self.operation {
+            AtomicOperation::Load => {
+                builder.build_atomic_load(pointer, without_release(ordering), scope)
+            }
+            AtomicOperation::Store => {
+                builder.build_atomic_store(get_value()?, pointer, without_acquire(ordering), scope);
+                return Ok(None);
+            }
+            AtomicOperation::CompareExchange => builder.build_atomic_compare_exchange(
+                pointer,
+                operands.get_value(self.comparator.expect("missing comparator"))?,
+                get_value()?,
+                ordering,
+                without_release(ordering),
+                scope,
+            ),
+            AtomicOperation::Increment => builder.build_atomic_rmw(
+                AtomicRMWOperation::Add,
+                pointer,
+                get_one()?,
+                ordering,
+                scope,
+            ),
+            AtomicOperation::Decrement => builder.build_atomic_rmw(
+                AtomicRMWOperation::Subtract,
+                pointer,
+                get_one()?,
+                ordering,
+                scope,
+            ),
+            AtomicOperation::ReadModifyWrite(operation) => {
+                builder.build_atomic_rmw(operation, pointer, get_value()?, ordering, scope)
+            }
+        }))
+    }
+}
+
+/// the memory operands of `OpLoad`, `OpStore`, `OpCopyMemory` and `OpCopyMemorySized`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct MemoryAccess {
+    pub volatile: bool,
+    pub alignment: Option<u32>,
+    pub nontemporal: bool,
+    /// the scope the stored value is made available to
+    pub make_pointer_available: Option<Id>,
+    /// the scope the loaded value is made visible to
+    pub make_pointer_visible: Option<Id>,
+    pub non_private_pointer: bool,
+}
+
+impl MemoryAccess {
+    /// parse the memory operands starting at operand `index`, which can be past the end for
+    /// accesses without memory operands. returns the index of the operand after them
+    pub fn parse(instruction: &Instruction, index: usize) -> Result<(Self, usize), Error> {
+        if index >= instruction.operands().len() {
+            return Ok((Self::default(), index));
+        }
+        let mask = instruction.operand(index)?;
+        let mut index = index + 1;
+        let mut next_operand = |bit: u32| -> Result<Option<u32>, Error> {
+            if mask & bit == 0 {
+                return Ok(None);
+            }
+            let operand = instruction.operand(index)?;
+            index += 1;
+            Ok(Some(operand))
+        };
+        let alignment = next_operand(memory_access::ALIGNED)?;
+        let make_pointer_available = next_operand(memory_access::MAKE_POINTER_AVAILABLE)?;
+        let make_pointer_visible = next_operand(memory_access::MAKE_POINTER_VISIBLE)?;
+        let memory_access = MemoryAccess {
+            volatile: mask & memory_access::VOLATILE != 0,
+            alignment,
+            nontemporal: mask & memory_access::NONTEMPORAL != 0,
+            make_pointer_available,
+            make_pointer_visible,
+            non_private_pointer: mask & memory_access::NON_PRIVATE_POINTER != 0,
+        };
+        Ok((memory_access, index))
+    }
+    /// the scope the access needs to be atomic at, or `None` if it can be a plain access.
+    /// `is_store` picks between the availability and visibility scopes
+    pub fn get_scope(&self, is_store: bool) -> Option<Option<Id>> {
+        let scope = if is_store {
+            self.make_pointer_available
+        } else {
+            self.make_pointer_visible
+        };
+        if scope.is_some() {
+            Some(scope)
+        } else if self.volatile {
+            Some(None)
+        } else {
+            None
+        }
+    }
+    fn get_synchronization_scope<'a, C: Context<'a>, O: AtomicOperands<'a, C>>(
+        &self,
+        is_store: bool,
+        operands: &O,
+    ) -> Result<Option<SynchronizationScope>, Error> {
+        Ok(match self.get_scope(is_store) {
+            Some(Some(scope)) => Some(get_synchronization_scope(operands.get_constant(scope)?)),
+            Some(None) => Some(SynchronizationScope::System),
+            None => None,
+        })
+    }
+    /// build a load from `pointer`, which has to point to a scalar if the access is volatile
+    /// or makes the pointer visible
+    pub fn build_load<'a, C: Context<'a>, O: AtomicOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        operands: &O,
+        pointer: C::Value,
+    ) -> Result<C::Value, Error> {
+        Ok(match self.get_synchronization_scope(false, operands)? {
+            Some(scope) => builder.build_atomic_load(pointer, AtomicOrdering::Relaxed, scope),
+            None => builder.build_load(pointer),
+        })
+    }
+    /// build a store to `pointer`, which has to point to a scalar if the access is volatile
+    /// or makes the pointer available
+    pub fn build_store<'a, C: Context<'a>, O: AtomicOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        operands: &O,
+        value: C::Value,
+        pointer: C::Value,
+    ) -> Result<(), Error> {
+        match self.get_synchronization_scope(true, operands)? {
+            Some(scope) => {
+                builder.build_atomic_store(value, pointer, AtomicOrdering::Relaxed, scope)
+            }
+            None => builder.build_store(value, pointer),
+        }
+        Ok(())
+    }
+}
+
+/// `OpControlBarrier` or `OpMemoryBarrier`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BarrierInstruction {
+    /// the scope of the invocations that wait for each other; `None` for `OpMemoryBarrier`
+    pub execution_scope: Option<Id>,
+    pub memory_scope: Id,
+    pub semantics: Id,
+}
+
+impl BarrierInstruction {
+    /// returns `None` if `instruction` isn't a barrier instruction
+    pub fn parse(instruction: &Instruction) -> Result<Option<Self>, Error> {
+        Ok(Some(match instruction.opcode {
+            op::CONTROL_BARRIER => BarrierInstruction {
+                execution_scope: Some(instruction.operand(0)?),
+                memory_scope: instruction.operand(1)?,
+                semantics: instruction.operand(2)?,
+            },
+            op::MEMORY_BARRIER => BarrierInstruction {
+                execution_scope: None,
+                memory_scope: instruction.operand(0)?,
+                semantics: instruction.operand(1)?,
+            },
+            _ => return Ok(None),
+        }))
+    }
+    /// build the fence ordering memory around the barrier. waiting for the other invocations of
+    /// an `OpControlBarrier` is up to the caller, since it depends on how invocations are
+    /// scheduled. returns the ordering of the fence, or `None` if no fence is needed
+    pub fn build_fence<'a, C: Context<'a>, O: AtomicOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        operands: &O,
+    ) -> Result<Option<AtomicOrdering>, Error> {
+        let ordering = get_ordering(operands.get_constant(self.semantics)?);
+        let scope = get_synchronization_scope(operands.get_constant(self.memory_scope)?);
+        if ordering == AtomicOrdering::Relaxed || scope == SynchronizationScope::SingleThread {
+            return Ok(None);
+        }
+        builder.build_fence(ordering, scope);
+        Ok(Some(ordering))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::{write_instruction, Instructions, MAGIC_NUMBER};
+
+    fn with_instruction<R, F: FnOnce(&Instruction) -> R>(opcode: u16, operands: &[u32], f: F) -> R {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        write_instruction(&mut code, opcode, operands);
+        let instruction = Instructions::new(&code).unwrap().next().unwrap().unwrap();
+        f(&instruction)
+    }
+
+    fn parse(opcode: u16, operands: &[u32]) -> Option<AtomicInstruction> {
+        with_instruction(opcode, operands, |instruction| {
+            AtomicInstruction::parse(instruction).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(op::LOAD, &[1, 2, 3]), None);
+        assert_eq!(
+            parse(op::ATOMIC_STORE, &[3, 4, 5, 6]),
+            Some(AtomicInstruction {
+                operation: AtomicOperation::Store,
+                result: None,
+                pointer: 3,
+                scope: 4,
+                semantics: 5,
+                value: Some(6),
+                comparator: None,
+            })
+        );
+        assert_eq!(
+            parse(op::ATOMIC_COMPARE_EXCHANGE_WEAK, &[1, 2, 3, 4, 5, 6, 7, 8]),
+            Some(AtomicInstruction {
+                operation: AtomicOperation::CompareExchange,
+                result: Some((1, 2)),
+                pointer: 3,
+                scope: 4,
+                semantics: 5,
+                value: Some(7),
+                comparator: Some(8),
+            })
+        );
+        assert_eq!(
+            parse(op::ATOMIC_U_MAX, &[1, 2, 3, 4, 5, 6]),
+            Some(AtomicInstruction {
+                operation: AtomicOperation::ReadModifyWrite(AtomicRMWOperation::UnsignedMax),
+                result: Some((1, 2)),
+                pointer: 3,
+                scope: 4,
+                semantics: 5,
+                value: Some(6),
+                comparator: None,
+            })
+        );
+        assert_eq!(
+            parse(op::ATOMIC_I_INCREMENT, &[1, 2, 3, 4, 5]).map(|v| (v.operation, v.value)),
+            Some((AtomicOperation::Increment, None))
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        // storage class bits are ignored
+        assert_eq!(get_ordering(0x40), AtomicOrdering::Relaxed);
+        assert_eq!(
+            get_ordering(memory_semantics::ACQUIRE | 0x40),
+            AtomicOrdering::Acquire
+        );
+        assert_eq!(
+            get_ordering(memory_semantics::ACQUIRE | memory_semantics::RELEASE),
+            AtomicOrdering::AcquireRelease
+        );
+        assert_eq!(
+            get_ordering(memory_semantics::SEQUENTIALLY_CONSISTENT),
+            AtomicOrdering::SequentiallyConsistent
+        );
+        assert_eq!(
+            without_release(get_ordering(memory_semantics::ACQUIRE_RELEASE)),
+            AtomicOrdering::Acquire
+        );
+        assert_eq!(
+            without_acquire(get_ordering(memory_semantics::ACQUIRE_RELEASE)),
+            AtomicOrdering::Release
+        );
+        assert_eq!(
+            get_synchronization_scope(scope::WORKGROUP),
+            SynchronizationScope::System
+        );
+        assert_eq!(
+            get_synchronization_scope(scope::INVOCATION),
+            SynchronizationScope::SingleThread
+        );
+        assert_eq!(
+            get_synchronization_scope(scope::QUEUE_FAMILY),
+            SynchronizationScope::System
+        );
+    }
+
+    #[test]
+    fn test_availability_visibility_ordering() {
+        assert_eq!(
+            get_ordering(memory_semantics::RELEASE | memory_semantics::MAKE_AVAILABLE),
+            AtomicOrdering::Release
+        );
+        assert_eq!(
+            get_ordering(memory_semantics::MAKE_AVAILABLE | memory_semantics::MAKE_VISIBLE),
+            AtomicOrdering::AcquireRelease
+        );
+        assert_eq!(
+            get_ordering(memory_semantics::OUTPUT_MEMORY | memory_semantics::VOLATILE),
+            AtomicOrdering::Relaxed
+        );
+    }
+
+    #[test]
+    fn test_memory_access() {
+        let parse = |operands: &[u32], index| {
+            with_instruction(op::STORE, operands, |instruction| {
+                MemoryAccess::parse(instruction, index).unwrap()
+            })
+        };
+        assert_eq!(parse(&[1, 2], 2), (MemoryAccess::default(), 2));
+        let (memory_access, next) = parse(
+            &[
+                1,
+                2,
+                memory_access::ALIGNED
+                    | memory_access::MAKE_POINTER_AVAILABLE
+                    | memory_access::NON_PRIVATE_POINTER,
+                16,
+                5,
+                memory_access::VOLATILE,
+            ],
+            2,
+        );
+        assert_eq!(next, 5);
+        assert_eq!(
+            memory_access,
+            MemoryAccess {
+                volatile: false,
+                alignment: Some(16),
+                nontemporal: false,
+                make_pointer_available: Some(5),
+                make_pointer_visible: None,
+                non_private_pointer: true,
+            }
+        );
+        assert_eq!(memory_access.get_scope(true), Some(Some(5)));
+        assert_eq!(memory_access.get_scope(false), None);
+        let (second, _) = parse(&[1, 2, 0, memory_access::VOLATILE], 3);
+        assert_eq!(second.get_scope(false), Some(None));
+        assert_eq!(parse(&[1, 2, 0], 2).0.get_scope(true), None);
+    }
+
+    #[test]
+    fn test_parse_barrier() {
+        let parse = |opcode, operands: &[u32]| {
+            with_instruction(opcode, operands, |instruction| {
+                BarrierInstruction::parse(instruction).unwrap()
+            })
+        };
+        assert_eq!(
+            parse(op::CONTROL_BARRIER, &[1, 2, 3]),
+            Some(BarrierInstruction {
+                execution_scope: Some(1),
+                memory_scope: 2,
+                semantics: 3,
+            })
+        );
+        assert_eq!(
+            parse(op::MEMORY_BARRIER, &[2, 3]),
+            Some(BarrierInstruction {
+                execution_scope: None,
+                memory_scope: 2,
+                semantics: 3,
+            })
+        );
+        assert_eq!(parse(op::ATOMIC_LOAD, &[1, 2, 3, 4, 5]), None);
+    }
+}
@@ -0,0 +1,219 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! the SPIR-V capabilities that decide which scalar types a module can declare, and which of
+//! them can be used in images and atomics
+//!
+//! 8 and 16-bit types map directly to the backend's types. the backend does f16 arithmetic in
+//! f32 on targets without native half-precision support. types only enabled by the storage
+//! capabilities can't be used for arithmetic, so SPIR-V converts them with `OpUConvert`,
+//! `OpSConvert` and `OpFConvert`, which become `build_integer_extend`, `build_float_extend` and
+//! friends.
+//!
+//! 64-bit atomics (`Int64Atomics`) use the backend's native 64-bit atomic instructions, both on
+//! buffers and, with `Int64ImageEXT`, on texels of `R64ui` and `R64i` storage images, which are
+//! reached through `OpImageTexelPointer` (see `image::ImageTexelPointer`).
+
+use shader_compiler_backend::types::{Type, TypeBuilder};
+use spirv::{op, Error, Instructions};
+
+#[allow(dead_code)]
+pub mod capability {
+    pub const SHADER: u32 = 1;
+    pub const FLOAT16: u32 = 9;
+    pub const FLOAT64: u32 = 10;
+    pub const INT64: u32 = 11;
+    pub const INT64_ATOMICS: u32 = 12;
+    pub const INT16: u32 = 22;
+    pub const INT8: u32 = 39;
+    pub const STORAGE_BUFFER_16_BIT_ACCESS: u32 = 4433;
+    pub const UNIFORM_AND_STORAGE_BUFFER_16_BIT_ACCESS: u32 = 4434;
+    pub const STORAGE_PUSH_CONSTANT_16: u32 = 4435;
+    pub const STORAGE_INPUT_OUTPUT_16: u32 = 4436;
+    pub const STORAGE_BUFFER_8_BIT_ACCESS: u32 = 4448;
+    pub const UNIFORM_AND_STORAGE_BUFFER_8_BIT_ACCESS: u32 = 4449;
+    pub const STORAGE_PUSH_CONSTANT_8: u32 = 4450;
+    pub const INT64_IMAGE_EXT: u32 = 5016;
+    pub const VULKAN_MEMORY_MODEL: u32 = 5345;
+    pub const VULKAN_MEMORY_MODEL_DEVICE_SCOPE: u32 = 5346;
+}
+
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq)]
+pub struct Capabilities {
+    pub float16: bool,
+    pub float64: bool,
+    pub int8: bool,
+    pub int16: bool,
+    pub int64: bool,
+    /// 8-bit integers can be used in buffers and push constants
+    pub storage_8bit: bool,
+    /// 16-bit integers and floats can be used in buffers, push constants and the interface
+    pub storage_16bit: bool,
+    pub int64_atomics: bool,
+    /// images can have 64-bit integer texels
+    pub int64_image: bool,
+    /// the module can use the `Vulkan` memory model, including `Device` scope. see
+    /// `atomics::MemoryAccess` for how its memory operands are lowered
+    pub vulkan_memory_model: bool,
+}
+
+impl Capabilities {
+    pub fn parse(code: &[u32]) -> Result<Self, Error> {
+        let mut retval = Self::default();
+        for instruction in Instructions::new(code)? {
+            let instruction = instruction?;
+            // capabilities come before all other instructions
+            if instruction.opcode != op::CAPABILITY {
+                break;
+            }
+            retval.add(instruction.operand(0)?);
+        }
+        Ok(retval)
+    }
+    /// capabilities that don't affect scalar types are ignored
+    pub fn add(&mut self, capability: u32) {
+        match capability {
+            capability::FLOAT16 => self.float16 = true,
+            capability::FLOAT64 => self.float64 = true,
+            capability::INT64 => self.int64 = true,
+            capability::INT64_ATOMICS => self.int64_atomics = true,
+            capability::INT64_IMAGE_EXT => self.int64_image = true,
+            capability::VULKAN_MEMORY_MODEL | capability::VULKAN_MEMORY_MODEL_DEVICE_SCOPE => {
+                self.vulkan_memory_model = true
+            }
+            capability::INT16 => self.int16 = true,
+            capability::INT8 => self.int8 = true,
+            capability::STORAGE_BUFFER_16_BIT_ACCESS
+            | capability::UNIFORM_AND_STORAGE_BUFFER_16_BIT_ACCESS
+            | capability::STORAGE_PUSH_CONSTANT_16
+            | capability::STORAGE_INPUT_OUTPUT_16 => self.storage_16bit = true,
+            capability::STORAGE_BUFFER_8_BIT_ACCESS
+            | capability::UNIFORM_AND_STORAGE_BUFFER_8_BIT_ACCESS
+            | capability::STORAGE_PUSH_CONSTANT_8 => self.storage_8bit = true,
+            _ => {}
+        }
+    }
+    /// check that `OpTypeInt` or `OpTypeFloat` with `width` bits is enabled.
+    /// 128-bit integers are never supported
+    pub fn check_scalar_type(&self, is_float: bool, width: u32) -> Result<(), Error> {
+        let is_enabled = match (is_float, width) {
+            (false, 8) => self.int8 || self.storage_8bit,
+            (false, 16) => self.int16 || self.storage_16bit,
+            (false, 32) | (true, 32) => true,
+            (false, 64) => self.int64,
+            (true, 16) => self.float16 || self.storage_16bit,
+            (true, 64) => self.float64,
+            _ => false,
+        };
+        if is_enabled {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedScalarType { is_float, width })
+        }
+    }
+    /// check that `OpTypeImage` can have texels of the scalar type with `width` bits
+    pub fn check_image_sampled_type(&self, is_float: bool, width: u32) -> Result<(), Error> {
+        match (is_float, width) {
+            (_, 32) => Ok(()),
+            (false, 64) if self.int64_image => self.check_scalar_type(is_float, width),
+            _ => Err(Error::UnsupportedScalarType { is_float, width }),
+        }
+    }
+    /// check that atomic instructions can operate on integers with `width` bits
+    pub fn check_atomic_width(&self, width: u32) -> Result<(), Error> {
+        match width {
+            32 => Ok(()),
+            64 if self.int64_atomics => self.check_scalar_type(false, width),
+            _ => Err(Error::UnsupportedAtomicWidth(width)),
+        }
+    }
+    /// build the backend type for `OpTypeInt` or `OpTypeFloat`
+    pub fn build_scalar_type<'a, Ty: Type<'a>>(
+        &self,
+        type_builder: &TypeBuilder<'a, Ty>,
+        is_float: bool,
+        width: u32,
+    ) -> Result<Ty, Error> {
+        self.check_scalar_type(is_float, width)?;
+        Ok(match (is_float, width) {
+            (false, 8) => type_builder.build_i8(),
+            (false, 16) => type_builder.build_i16(),
+            (false, 32) => type_builder.build_i32(),
+            (false, 64) => type_builder.build_i64(),
+            (true, 16) => type_builder.build_f16(),
+            (true, 32) => type_builder.build_f32(),
+            (true, 64) => type_builder.build_f64(),
+            _ => unreachable!(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::{write_instruction, MAGIC_NUMBER};
+
+    fn module(capabilities: &[u32]) -> Vec<u32> {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 1, 0];
+        for &capability in capabilities {
+            write_instruction(&mut code, op::CAPABILITY, &[capability]);
+        }
+        write_instruction(&mut code, op::TYPE_BOOL, &[1]);
+        // ignored, since it's after the first non-capability instruction
+        write_instruction(&mut code, op::CAPABILITY, &[capability::INT64]);
+        code
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let capabilities = Capabilities::parse(&module(&[
+            capability::SHADER,
+            capability::FLOAT16,
+            capability::STORAGE_BUFFER_8_BIT_ACCESS,
+        ]))
+        .unwrap();
+        assert_eq!(
+            capabilities,
+            Capabilities {
+                float16: true,
+                storage_8bit: true,
+                ..Default::default()
+            }
+        );
+        assert!(capabilities.check_scalar_type(true, 16).is_ok());
+        assert!(capabilities.check_scalar_type(false, 8).is_ok());
+        assert!(capabilities.check_scalar_type(false, 16).is_err());
+        assert!(capabilities.check_scalar_type(false, 64).is_err());
+        let capabilities = Capabilities::parse(&module(&[
+            capability::STORAGE_PUSH_CONSTANT_16,
+            capability::INT64,
+        ]))
+        .unwrap();
+        assert!(capabilities.check_scalar_type(true, 16).is_ok());
+        assert!(capabilities.check_scalar_type(false, 16).is_ok());
+        assert!(capabilities.check_scalar_type(false, 64).is_ok());
+        assert!(capabilities.check_scalar_type(false, 128).is_err());
+        assert!(capabilities.check_scalar_type(true, 8).is_err());
+        assert!(capabilities.check_atomic_width(32).is_ok());
+        assert!(capabilities.check_atomic_width(64).is_err());
+        assert!(capabilities.check_image_sampled_type(false, 64).is_err());
+    }
+
+    #[test]
+    fn test_int64_atomics() {
+        let capabilities = Capabilities::parse(&module(&[
+            capability::INT64,
+            capability::INT64_ATOMICS,
+            capability::INT64_IMAGE_EXT,
+        ]))
+        .unwrap();
+        assert!(capabilities.check_atomic_width(64).is_ok());
+        assert!(capabilities.check_atomic_width(16).is_err());
+        assert!(capabilities.check_image_sampled_type(false, 64).is_ok());
+        assert!(capabilities.check_image_sampled_type(true, 64).is_err());
+        assert!(capabilities.check_image_sampled_type(true, 32).is_ok());
+        // 64-bit atomics still need 64-bit integers
+        let capabilities = Capabilities::parse(&module(&[capability::INT64_ATOMICS])).unwrap();
+        assert!(capabilities.check_atomic_width(64).is_err());
+    }
+}
@@ -0,0 +1,762 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! cooperative matrices (`SPV_KHR_cooperative_matrix`)
+//!
+//! subgroups have a single invocation, so each invocation holds all of the elements of its
+//! cooperative matrices. a matrix is stored row-major in memory that the translator allocates for
+//! it (see `CooperativeMatrixOperands::allocate`), and values of cooperative matrix types are
+//! pointers to that memory. loads, stores, fills and `OpCooperativeMatrixMulAddKHR` are calls to
+//! the `kazan_runtime_cooperative_matrix_*` kernels, which compute the result a row at a time in
+//! host vectors. the LLVM backend resolves them to host functions.
+//!
+//! the kernels work for any size, but the sizes reported to the application, from
+//! `get_supported_configurations`, are the ones that fill the host's vectors. arithmetic,
+//! conversions and element access on cooperative matrices aren't supported yet.
+//!
+//! the driver doesn't compile compute shaders from SPIR-V yet, so `VK_KHR_cooperative_matrix`
+//! isn't advertised until this lowering is reached from `vkCreateComputePipelines`.
+
+use atomics::scope;
+use shader_compiler_backend::types::TypeBuilder;
+use shader_compiler_backend::{AttachedBuilder, Context, Error as BackendError, Function, Module};
+use spirv::{self, op, Error, Id, Instruction, Instructions};
+use std::collections::HashMap;
+
+#[allow(dead_code)]
+pub mod matrix_use {
+    pub const A: u32 = 0;
+    pub const B: u32 = 1;
+    pub const ACCUMULATOR: u32 = 2;
+}
+
+#[allow(dead_code)]
+pub mod matrix_layout {
+    pub const ROW_MAJOR: u32 = 0;
+    pub const COLUMN_MAJOR: u32 = 1;
+}
+
+/// the bits of the optional operand of `OpCooperativeMatrixMulAddKHR`
+#[allow(dead_code)]
+pub mod matrix_operands {
+    pub const A_SIGNED_COMPONENTS: u32 = 0x1;
+    pub const B_SIGNED_COMPONENTS: u32 = 0x2;
+    pub const C_SIGNED_COMPONENTS: u32 = 0x4;
+    pub const RESULT_SIGNED_COMPONENTS: u32 = 0x8;
+    pub const SATURATING_ACCUMULATION: u32 = 0x10;
+}
+
+/// the component types that the runtime's kernels support
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ComponentType {
+    Float16,
+    Float32,
+    SInt8,
+    SInt32,
+    UInt8,
+    UInt32,
+}
+
+impl ComponentType {
+    fn new(is_float: bool, width: u32, is_signed: bool) -> Option<Self> {
+        Some(match (is_float, width, is_signed) {
+            (true, 16, _) => ComponentType::Float16,
+            (true, 32, _) => ComponentType::Float32,
+            (false, 8, true) => ComponentType::SInt8,
+            (false, 32, true) => ComponentType::SInt32,
+            (false, 8, false) => ComponentType::UInt8,
+            (false, 32, false) => ComponentType::UInt32,
+            _ => return None,
+        })
+    }
+    /// the size in bytes
+    pub fn size(self) -> u32 {
+        match self {
+            ComponentType::SInt8 | ComponentType::UInt8 => 1,
+            ComponentType::Float16 => 2,
+            ComponentType::Float32 | ComponentType::SInt32 | ComponentType::UInt32 => 4,
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            ComponentType::Float16 => "f16",
+            ComponentType::Float32 => "f32",
+            ComponentType::SInt8 => "s8",
+            ComponentType::SInt32 => "s32",
+            ComponentType::UInt8 => "u8",
+            ComponentType::UInt32 => "u32",
+        }
+    }
+}
+
+/// the `(a_type, c_type)` of each `mul_add` kernel. `B` has the same type as `A`, and the result
+/// has the same type as `C`
+const MUL_ADD_KERNELS: &[(ComponentType, ComponentType)] = &[
+    (ComponentType::Float16, ComponentType::Float32),
+    (ComponentType::Float32, ComponentType::Float32),
+    (ComponentType::SInt8, ComponentType::SInt32),
+    (ComponentType::UInt8, ComponentType::UInt32),
+];
+
+/// a size and component types reported by `vkGetPhysicalDeviceCooperativeMatrixPropertiesKHR`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Configuration {
+    pub m: u32,
+    pub n: u32,
+    pub k: u32,
+    pub a_type: ComponentType,
+    pub c_type: ComponentType,
+}
+
+/// the size in bytes of the vectors the runtime's kernels are compiled to use
+pub fn get_host_vector_bytes() -> u32 {
+    if cfg!(target_feature = "avx512f") {
+        64
+    } else if cfg!(target_feature = "avx") {
+        32
+    } else {
+        16
+    }
+}
+
+/// each row of `A` fills one vector, and each row of the result fills two, so the kernels keep
+/// two accumulator vectors per row
+pub fn get_supported_configurations() -> Vec<Configuration> {
+    let vector_bytes = get_host_vector_bytes();
+    MUL_ADD_KERNELS
+        .iter()
+        .map(|&(a_type, c_type)| {
+            let n = 2 * vector_bytes / c_type.size();
+            Configuration {
+                m: n,
+                n,
+                k: vector_bytes / a_type.size(),
+                a_type,
+                c_type,
+            }
+        })
+        .collect()
+}
+
+/// `fn(matrix, pointer, stride, stride_unit, rows, columns, element_size, column_major)`
+///
+/// `stride` is in units of `stride_unit` bytes
+pub type LoadFn = unsafe extern "C" fn(*mut u8, *const u8, u32, u32, u32, u32, u32, u32);
+
+/// `fn(matrix, pointer, stride, stride_unit, rows, columns, element_size, column_major)`
+pub type StoreFn = unsafe extern "C" fn(*const u8, *mut u8, u32, u32, u32, u32, u32, u32);
+
+/// `fn(matrix, element_count, element_size)`; copies the first element to the others
+pub type FillFn = unsafe extern "C" fn(*mut u8, u32, u32);
+
+/// `fn(a, b, c, result, m, n, k)`; `result` can be the same as `c`
+pub type MulAddFn = unsafe extern "C" fn(*const u8, *const u8, *const u8, *mut u8, u32, u32, u32);
+
+/// an `OpTypeCooperativeMatrixKHR`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CooperativeMatrixType {
+    pub component_type: Id,
+    pub component_is_float: bool,
+    pub component_width: u32,
+    /// the signedness of the `OpTypeInt`, which `OpCooperativeMatrixMulAddKHR` overrides
+    pub component_is_signed: bool,
+    pub rows: u32,
+    pub columns: u32,
+    pub matrix_use: u32,
+}
+
+impl CooperativeMatrixType {
+    pub fn element_count(&self) -> u32 {
+        self.rows * self.columns
+    }
+    pub fn element_size(&self) -> u32 {
+        self.component_width / 8
+    }
+    fn get_component(&self, is_signed: bool) -> Option<ComponentType> {
+        ComponentType::new(self.component_is_float, self.component_width, is_signed)
+    }
+    /// the type of the memory a matrix is stored in: `[component; rows * columns]`
+    pub fn build_storage_type<'a, C: Context<'a>>(
+        &self,
+        context: &'a C,
+        component: C::Type,
+    ) -> Result<C::Type, BackendError> {
+        context
+            .create_type_builder()
+            .build_array(component, self.element_count() as usize)
+    }
+}
+
+/// the cooperative matrix types in a module, and the types of the values that have them
+#[derive(Clone, Debug, Default)]
+pub struct CooperativeMatrices {
+    types: HashMap<Id, CooperativeMatrixType>,
+    value_types: HashMap<Id, Id>,
+    /// the values of 32-bit integer constants, for the sizes and memory layouts
+    constants: HashMap<Id, u32>,
+}
+
+impl CooperativeMatrices {
+    pub fn find(code: &[u32]) -> Result<Self, Error> {
+        // `(is_float, width, is_signed)`
+        let mut scalar_types = HashMap::new();
+        let mut retval = Self::default();
+        for instruction in Instructions::new(code)? {
+            let instruction = instruction?;
+            match instruction.opcode {
+                op::TYPE_INT => {
+                    scalar_types.insert(
+                        instruction.operand(0)?,
+                        (false, instruction.operand(1)?, instruction.operand(2)? != 0),
+                    );
+                }
+                op::TYPE_FLOAT => {
+                    scalar_types.insert(
+                        instruction.operand(0)?,
+                        (true, instruction.operand(1)?, false),
+                    );
+                }
+                op::CONSTANT => {
+                    if let Some(&(false, 32, _)) = scalar_types.get(&instruction.operand(0)?) {
+                        retval
+                            .constants
+                            .insert(instruction.operand(1)?, instruction.operand(2)?);
+                    }
+                }
+                op::TYPE_COOPERATIVE_MATRIX_KHR => {
+                    let id = instruction.operand(0)?;
+                    let component_type = instruction.operand(1)?;
+                    let get_constant = |index| -> Result<u32, Error> {
+                        retval
+                            .constants
+                            .get(&instruction.operand(index)?)
+                            .cloned()
+                            .ok_or(Error::UnsupportedCooperativeMatrix(id))
+                    };
+                    let (component_is_float, component_width, component_is_signed) =
+                        match scalar_types.get(&component_type) {
+                            Some(&scalar_type) => scalar_type,
+                            None => return Err(Error::UnsupportedCooperativeMatrix(id)),
+                        };
+                    // workgroup scope matrices would have to be shared between invocations
+                    if get_constant(2)? != scope::SUBGROUP {
+                        return Err(Error::UnsupportedCooperativeMatrix(id));
+                    }
+                    let ty = CooperativeMatrixType {
+                        component_type,
+                        component_is_float,
+                        component_width,
+                        component_is_signed,
+                        rows: get_constant(3)?,
+                        columns: get_constant(4)?,
+                        matrix_use: get_constant(5)?,
+                    };
+                    if ty.get_component(component_is_signed).is_none() {
+                        return Err(Error::UnsupportedCooperativeMatrix(id));
+                    }
+                    retval.types.insert(id, ty);
+                }
+                opcode if !spirv::is_annotation(opcode) && instruction.operands().len() >= 2 => {
+                    // the result type is operand 0 of instructions that have one
+                    if retval.types.contains_key(&instruction.operand(0)?) {
+                        retval
+                            .value_types
+                            .insert(instruction.operand(1)?, instruction.operand(0)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(retval)
+    }
+    pub fn get_type(&self, ty: Id) -> Option<&CooperativeMatrixType> {
+        self.types.get(&ty)
+    }
+    fn get_required_type(&self, ty: Id) -> Result<&CooperativeMatrixType, Error> {
+        self.types
+            .get(&ty)
+            .ok_or(Error::UnsupportedCooperativeMatrix(ty))
+    }
+    fn get_value_type(&self, value: Id) -> Result<&CooperativeMatrixType, Error> {
+        match self.value_types.get(&value) {
+            Some(&ty) => self.get_required_type(ty),
+            None => Err(Error::UnsupportedCooperativeMatrix(value)),
+        }
+    }
+}
+
+/// how `CooperativeMatrixInstruction::build` gets the values of its operands
+pub trait CooperativeMatrixOperands<'a, C: Context<'a>> {
+    fn get_value(&self, id: Id) -> Result<C::Value, BackendError>;
+    fn get_type(&self, id: Id) -> Result<C::Type, BackendError>;
+    /// a pointer to new memory for a matrix of the cooperative matrix type `ty`, with the type
+    /// from `CooperativeMatrixType::build_storage_type`
+    fn allocate(&self, ty: Id) -> Result<C::Value, BackendError>;
+    /// the size in bytes of what `pointer` points to, which is the unit of load and store strides
+    fn get_pointee_size(&self, pointer: Id) -> Result<u32, BackendError>;
+}
+
+/// the runtime functions declared in a module
+pub struct DeclaredCooperativeMatrixFunctions<'a, C: Context<'a>> {
+    load: C::Function,
+    store: C::Function,
+    fill: C::Function,
+    mul_add: HashMap<(ComponentType, ComponentType), C::Function>,
+    i32_type: C::Type,
+    byte_pointer_type: C::Type,
+}
+
+impl<'a, C: Context<'a>> DeclaredCooperativeMatrixFunctions<'a, C> {
+    /// the functions are host functions that the backend resolves
+    pub fn declare(context: &'a C, module: &mut C::Module) -> Result<Self, BackendError> {
+        let type_builder = context.create_type_builder();
+        let mut mul_add = HashMap::new();
+        for &(a_type, c_type) in MUL_ADD_KERNELS {
+            mul_add.insert(
+                (a_type, c_type),
+                module.add_function(
+                    &format!(
+                        "kazan_runtime_cooperative_matrix_mul_add_{}_{}",
+                        a_type.name(),
+                        c_type.name()
+                    ),
+                    type_builder.build::<MulAddFn>()?,
+                )?,
+            );
+        }
+        Ok(DeclaredCooperativeMatrixFunctions {
+            load: module.add_function(
+                "kazan_runtime_cooperative_matrix_load",
+                type_builder.build::<LoadFn>()?,
+            )?,
+            store: module.add_function(
+                "kazan_runtime_cooperative_matrix_store",
+                type_builder.build::<StoreFn>()?,
+            )?,
+            fill: module.add_function(
+                "kazan_runtime_cooperative_matrix_fill",
+                type_builder.build::<FillFn>()?,
+            )?,
+            mul_add,
+            i32_type: type_builder.build_i32(),
+            byte_pointer_type: type_builder.build::<*mut u8>()?,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CooperativeMatrixInstruction {
+    Load {
+        result_type: Id,
+        result: Id,
+        ty: CooperativeMatrixType,
+        pointer: Id,
+        column_major: bool,
+        /// tightly packed if `None`
+        stride: Option<Id>,
+    },
+    Store {
+        pointer: Id,
+        object: Id,
+        ty: CooperativeMatrixType,
+        column_major: bool,
+        stride: Option<Id>,
+    },
+    /// an `OpCompositeConstruct` of a cooperative matrix, which sets all the elements to `value`
+    Fill {
+        result_type: Id,
+        result: Id,
+        ty: CooperativeMatrixType,
+        value: Id,
+    },
+    MulAdd {
+        result_type: Id,
+        result: Id,
+        a: Id,
+        b: Id,
+        c: Id,
+        m: u32,
+        n: u32,
+        k: u32,
+        kernel: (ComponentType, ComponentType),
+    },
+    Length {
+        result_type: Id,
+        result: Id,
+        length: u32,
+    },
+}
+
+/// the memory layout operand, which must be a constant
+fn get_column_major(
+    instruction: &Instruction,
+    index: usize,
+    matrices: &CooperativeMatrices,
+) -> Result<bool, Error> {
+    let layout = instruction.operand(index)?;
+    match matrices.constants.get(&layout) {
+        Some(&matrix_layout::ROW_MAJOR) => Ok(false),
+        Some(&matrix_layout::COLUMN_MAJOR) => Ok(true),
+        _ => Err(Error::UnsupportedCooperativeMatrix(layout)),
+    }
+}
+
+impl CooperativeMatrixInstruction {
+    /// returns `None` if `instruction` isn't a cooperative matrix instruction
+    pub fn parse(
+        instruction: &Instruction,
+        matrices: &CooperativeMatrices,
+    ) -> Result<Option<Self>, Error> {
+        let optional_operand = |index: usize| instruction.operands().get(index).cloned();
+        Ok(Some(match instruction.opcode {
+            op::COOPERATIVE_MATRIX_LOAD_KHR => {
+                let result_type = instruction.operand(0)?;
+                CooperativeMatrixInstruction::Load {
+                    result_type,
+                    result: instruction.operand(1)?,
+                    ty: *matrices.get_required_type(result_type)?,
+                    pointer: instruction.operand(2)?,
+                    column_major: get_column_major(instruction, 3, matrices)?,
+                    stride: optional_operand(4),
+                }
+            }
+            op::COOPERATIVE_MATRIX_STORE_KHR => {
+                let object = instruction.operand(1)?;
+                CooperativeMatrixInstruction::Store {
+                    pointer: instruction.operand(0)?,
+                    object,
+                    ty: *matrices.get_value_type(object)?,
+                    column_major: get_column_major(instruction, 2, matrices)?,
+                    stride: optional_operand(3),
+                }
+            }
+            op::COMPOSITE_CONSTRUCT => {
+                let result_type = instruction.operand(0)?;
+                let ty = match matrices.get_type(result_type) {
+                    Some(&ty) => ty,
+                    None => return Ok(None),
+                };
+                CooperativeMatrixInstruction::Fill {
+                    result_type,
+                    result: instruction.operand(1)?,
+                    ty,
+                    value: instruction.operand(2)?,
+                }
+            }
+            op::COOPERATIVE_MATRIX_MUL_ADD_KHR => {
+                let result_type = instruction.operand(0)?;
+                let result = instruction.operand(1)?;
+                let a = instruction.operand(2)?;
+                let b = instruction.operand(3)?;
+                let c = instruction.operand(4)?;
+                let flags = optional_operand(5).unwrap_or(0);
+                let unsupported = Error::UnsupportedCooperativeMatrix(result);
+                if flags & matrix_operands::SATURATING_ACCUMULATION != 0 {
+                    return Err(unsupported);
+                }
+                let a_type = matrices.get_value_type(a)?;
+                let b_type = matrices.get_value_type(b)?;
+                let c_type = matrices.get_value_type(c)?;
+                let result_type_info = matrices.get_required_type(result_type)?;
+                let is_signed = |bit| flags & bit != 0;
+                let kernel = (
+                    a_type.get_component(is_signed(matrix_operands::A_SIGNED_COMPONENTS)),
+                    c_type.get_component(is_signed(matrix_operands::C_SIGNED_COMPONENTS)),
+                );
+                let kernel = match kernel {
+                    (Some(a_component), Some(c_component)) => (a_component, c_component),
+                    _ => return Err(unsupported),
+                };
+                let is_supported = MUL_ADD_KERNELS.contains(&kernel)
+                    && b_type.get_component(is_signed(matrix_operands::B_SIGNED_COMPONENTS))
+                        == Some(kernel.0)
+                    && result_type_info
+                        .get_component(is_signed(matrix_operands::RESULT_SIGNED_COMPONENTS))
+                        == Some(kernel.1);
+                let (m, n, k) = (a_type.rows, b_type.columns, a_type.columns);
+                let has_matching_sizes = b_type.rows == k
+                    && (c_type.rows, c_type.columns) == (m, n)
+                    && (result_type_info.rows, result_type_info.columns) == (m, n);
+                if !is_supported || !has_matching_sizes {
+                    return Err(unsupported);
+                }
+                CooperativeMatrixInstruction::MulAdd {
+                    result_type,
+                    result,
+                    a,
+                    b,
+                    c,
+                    m,
+                    n,
+                    k,
+                    kernel,
+                }
+            }
+            op::COOPERATIVE_MATRIX_LENGTH_KHR => CooperativeMatrixInstruction::Length {
+                result_type: instruction.operand(0)?,
+                result: instruction.operand(1)?,
+                length: matrices
+                    .get_required_type(instruction.operand(2)?)?
+                    .element_count(),
+            },
+            _ => return Ok(None),
+        }))
+    }
+    /// returns the result, or `None` for `OpCooperativeMatrixStoreKHR`
+    pub fn build<'a, C: Context<'a>, O: CooperativeMatrixOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        functions: &DeclaredCooperativeMatrixFunctions<'a, C>,
+        operands: &O,
+    ) -> Result<Option<C::Value>, BackendError> {
+        let constant = |value: u32| {
+            builder.build_integer_constant(functions.i32_type.clone(), u64::from(value))
+        };
+        let bytes =
+            |pointer: C::Value| builder.build_bitcast(pointer, functions.byte_pointer_type.clone());
+        // `(stride, stride_unit)`
+        let get_stride = |ty: &CooperativeMatrixType,
+                          pointer: Id,
+                          column_major: bool,
+                          stride: Option<Id>|
+         -> Result<(C::Value, C::Value), BackendError> {
+            Ok(match stride {
+                Some(stride) => (
+                    operands.get_value(stride)?,
+                    constant(operands.get_pointee_size(pointer)?),
+                ),
+                None => (
+                    constant(if column_major { ty.rows } else { ty.columns }),
+                    constant(ty.element_size()),
+                ),
+            })
+        };
+        Ok(Some(match *self {
+            CooperativeMatrixInstruction::Load {
+                result_type,
+                ty,
+                pointer,
+                column_major,
+                stride,
+                ..
+            } => {
+                let matrix = operands.allocate(result_type)?;
+                let (stride, stride_unit) = get_stride(&ty, pointer, column_major, stride)?;
+                builder.build_call(
+                    functions.load.as_value(),
+                    &[
+                        bytes(matrix.clone()),
+                        bytes(operands.get_value(pointer)?),
+                        stride,
+                        stride_unit,
+                        constant(ty.rows),
+                        constant(ty.columns),
+                        constant(ty.element_size()),
+                        constant(column_major as u32),
+                    ],
+                )?;
+                matrix
+            }
+            CooperativeMatrixInstruction::Store {
+                pointer,
+                object,
+                ty,
+                column_major,
+                stride,
+            } => {
+                let (stride, stride_unit) = get_stride(&ty, pointer, column_major, stride)?;
+                builder.build_call(
+                    functions.store.as_value(),
+                    &[
+                        bytes(operands.get_value(object)?),
+                        bytes(operands.get_value(pointer)?),
+                        stride,
+                        stride_unit,
+                        constant(ty.rows),
+                        constant(ty.columns),
+                        constant(ty.element_size()),
+                        constant(column_major as u32),
+                    ],
+                )?;
+                return Ok(None);
+            }
+            CooperativeMatrixInstruction::Fill {
+                result_type,
+                ty,
+                value,
+                ..
+            } => {
+                let matrix = operands.allocate(result_type)?;
+                builder.build_store(
+                    operands.get_value(value)?,
+                    builder.build_element_pointer(matrix.clone(), &[0, 0])?,
+                );
+                builder.build_call(
+                    functions.fill.as_value(),
+                    &[
+                        bytes(matrix.clone()),
+                        constant(ty.element_count()),
+                        constant(ty.element_size()),
+                    ],
+                )?;
+                matrix
+            }
+            CooperativeMatrixInstruction::MulAdd {
+                result_type,
+                a,
+                b,
+                c,
+                m,
+                n,
+                k,
+                kernel,
+                ..
+            } => {
+                let matrix = operands.allocate(result_type)?;
+                builder.build_call(
+                    functions.mul_add[&kernel].as_value(),
+                    &[
+                        bytes(operands.get_value(a)?),
+                        bytes(operands.get_value(b)?),
+                        bytes(operands.get_value(c)?),
+                        bytes(matrix.clone()),
+                        constant(m),
+                        constant(n),
+                        constant(k),
+                    ],
+                )?;
+                matrix
+            }
+            CooperativeMatrixInstruction::Length {
+                result_type,
+                length,
+                ..
+            } => builder.build_integer_constant(operands.get_type(result_type)?, u64::from(length)),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::{write_instruction, MAGIC_NUMBER};
+
+    /// 1: float, 2: half, 3: int, 4: subgroup scope, 5: 16, 6: A, 7: B, 8: accumulator,
+    /// 9: row major, 10, 11 and 12: 16x16 A, B and accumulator matrices, 13: pointer,
+    /// 20 and 21: loads of A and B, 22: fill of an accumulator, 23: multiply-add, 24: store,
+    /// 25: length
+    fn test_module(accumulator_component: Id) -> Vec<u32> {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        write_instruction(&mut code, op::TYPE_FLOAT, &[1, 32]);
+        write_instruction(&mut code, op::TYPE_FLOAT, &[2, 16]);
+        write_instruction(&mut code, op::TYPE_INT, &[3, 32, 0]);
+        write_instruction(&mut code, op::CONSTANT, &[3, 4, scope::SUBGROUP]);
+        write_instruction(&mut code, op::CONSTANT, &[3, 5, 16]);
+        write_instruction(&mut code, op::CONSTANT, &[3, 6, matrix_use::A]);
+        write_instruction(&mut code, op::CONSTANT, &[3, 7, matrix_use::B]);
+        write_instruction(&mut code, op::CONSTANT, &[3, 8, matrix_use::ACCUMULATOR]);
+        write_instruction(&mut code, op::CONSTANT, &[3, 9, matrix_layout::ROW_MAJOR]);
+        for &(id, component, matrix_use) in
+            &[(10, 2, 6), (11, 2, 7), (12, accumulator_component, 8)]
+        {
+            write_instruction(
+                &mut code,
+                op::TYPE_COOPERATIVE_MATRIX_KHR,
+                &[id, component, 4, 5, 5, matrix_use],
+            );
+        }
+        write_instruction(
+            &mut code,
+            op::COOPERATIVE_MATRIX_LOAD_KHR,
+            &[10, 20, 13, 9, 5],
+        );
+        write_instruction(
+            &mut code,
+            op::COOPERATIVE_MATRIX_LOAD_KHR,
+            &[11, 21, 13, 9, 5],
+        );
+        write_instruction(&mut code, op::COMPOSITE_CONSTRUCT, &[12, 22, 30]);
+        write_instruction(
+            &mut code,
+            op::COOPERATIVE_MATRIX_MUL_ADD_KHR,
+            &[12, 23, 20, 21, 22],
+        );
+        write_instruction(&mut code, op::COOPERATIVE_MATRIX_STORE_KHR, &[13, 23, 9]);
+        write_instruction(&mut code, op::COOPERATIVE_MATRIX_LENGTH_KHR, &[3, 25, 12]);
+        code
+    }
+
+    fn parse_all(code: &[u32]) -> Result<Vec<CooperativeMatrixInstruction>, Error> {
+        let matrices = CooperativeMatrices::find(code)?;
+        let mut retval = Vec::new();
+        for instruction in Instructions::new(code)? {
+            if let Some(instruction) =
+                CooperativeMatrixInstruction::parse(&instruction?, &matrices)?
+            {
+                retval.push(instruction);
+            }
+        }
+        Ok(retval)
+    }
+
+    #[test]
+    fn test_parse() {
+        let instructions = parse_all(&test_module(1)).unwrap();
+        assert_eq!(instructions.len(), 6);
+        assert_eq!(
+            instructions[3],
+            CooperativeMatrixInstruction::MulAdd {
+                result_type: 12,
+                result: 23,
+                a: 20,
+                b: 21,
+                c: 22,
+                m: 16,
+                n: 16,
+                k: 16,
+                kernel: (ComponentType::Float16, ComponentType::Float32),
+            }
+        );
+        match instructions[4] {
+            CooperativeMatrixInstruction::Store {
+                ty,
+                column_major,
+                stride,
+                ..
+            } => {
+                assert_eq!(ty.element_size(), 4);
+                assert!(!column_major);
+                assert_eq!(stride, None);
+            }
+            ref instruction => panic!("expected a store: {:?}", instruction),
+        }
+        assert_eq!(
+            instructions[5],
+            CooperativeMatrixInstruction::Length {
+                result_type: 3,
+                result: 25,
+                length: 256,
+            }
+        );
+        // there's no kernel for half-precision accumulators
+        match parse_all(&test_module(2)) {
+            Err(Error::UnsupportedCooperativeMatrix(23)) => {}
+            result => panic!("expected an unsupported multiply-add: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_supported_configurations() {
+        for configuration in get_supported_configurations() {
+            assert!(MUL_ADD_KERNELS.contains(&(configuration.a_type, configuration.c_type)));
+            assert_eq!(
+                configuration.k * configuration.a_type.size(),
+                get_host_vector_bytes()
+            );
+            assert_eq!(
+                configuration.n * configuration.c_type.size(),
+                2 * get_host_vector_bytes()
+            );
+        }
+    }
+}
@@ -0,0 +1,334 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! source-level debug info from a module's OpSource, OpLine, and OpName instructions
+//!
+//! only used when `CompilerIndependentConfig::debug_info` is set; the locations are forwarded to
+//! the backend so debuggers and profilers can map the generated code back to the shader's source.
+//! `NonSemantic.Shader.DebugInfo.100`'s DebugSource, DebugLine, and DebugFunction are read the same
+//! way, since compilers that emit it usually don't emit OpLine.
+
+use non_semantic::NonSemanticSets;
+use shader_compiler_backend::{Context, Error, Module, SourceLocation};
+use spirv::{self, is_block_terminator, op, Id, Instruction, Instructions};
+use std::collections::HashMap;
+
+/// instruction numbers in `NonSemantic.Shader.DebugInfo.100`
+mod shader_debug_info {
+    pub const DEBUG_FUNCTION: u32 = 20;
+    pub const DEBUG_SOURCE: u32 = 35;
+    pub const DEBUG_FUNCTION_DEFINITION: u32 = 101;
+    pub const DEBUG_LINE: u32 = 103;
+    pub const DEBUG_NO_LINE: u32 = 104;
+}
+
+/// the range of instructions an OpLine applies to
+#[derive(Copy, Clone, Debug)]
+struct LineRange {
+    /// word offset of the first instruction after the OpLine
+    start: usize,
+    /// word offset just past the last instruction
+    end: usize,
+    file: Id,
+    line: u32,
+    column: u32,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DebugInfo {
+    /// from OpString
+    strings: HashMap<Id, String>,
+    /// from OpName
+    names: HashMap<Id, String>,
+    /// the file id from OpSource
+    source_file: Option<Id>,
+    /// sorted and non-overlapping
+    lines: Vec<LineRange>,
+}
+
+/// state for reading `NonSemantic.Shader.DebugInfo.100`, which passes numbers as ids of
+/// OpConstants and strings as ids of OpStrings
+#[derive(Default)]
+struct ShaderDebugInfoState {
+    non_semantic_sets: NonSemanticSets,
+    /// 32-bit OpConstants
+    constants: HashMap<Id, u32>,
+    /// maps DebugSource results to their file's OpString
+    sources: HashMap<Id, Id>,
+    /// maps DebugFunction results to their name's OpString
+    functions: HashMap<Id, Id>,
+}
+
+impl ShaderDebugInfoState {
+    /// the instruction number if `instruction` is from `NonSemantic.Shader.DebugInfo.100`
+    fn get_instruction_number(
+        &self,
+        instruction: &Instruction,
+    ) -> Result<Option<u32>, spirv::Error> {
+        if instruction.opcode != op::EXT_INST
+            || Some(instruction.operand(2)?) != self.non_semantic_sets.shader_debug_info()
+        {
+            return Ok(None);
+        }
+        Ok(Some(instruction.operand(3)?))
+    }
+    fn constant(&self, instruction: &Instruction, index: usize) -> Result<u32, spirv::Error> {
+        Ok(self
+            .constants
+            .get(&instruction.operand(index)?)
+            .cloned()
+            .unwrap_or(0))
+    }
+}
+
+impl DebugInfo {
+    pub fn parse(code: &[u32]) -> Result<Self, spirv::Error> {
+        let mut retval = DebugInfo::default();
+        let mut current_line: Option<LineRange> = None;
+        let mut state = ShaderDebugInfoState::default();
+        for instruction in Instructions::new(code)? {
+            let instruction = instruction?;
+            state.non_semantic_sets.add(&instruction)?;
+            let mut line_opcode = instruction.opcode;
+            match state.get_instruction_number(&instruction)? {
+                Some(shader_debug_info::DEBUG_SOURCE) => {
+                    let file = instruction.operand(4)?;
+                    state.sources.insert(instruction.operand(1)?, file);
+                    if retval.source_file.is_none() {
+                        retval.source_file = Some(file);
+                    }
+                }
+                Some(shader_debug_info::DEBUG_FUNCTION) => {
+                    state
+                        .functions
+                        .insert(instruction.operand(1)?, instruction.operand(4)?);
+                }
+                Some(shader_debug_info::DEBUG_FUNCTION_DEFINITION) => {
+                    let name = state
+                        .functions
+                        .get(&instruction.operand(4)?)
+                        .and_then(|name| retval.strings.get(name))
+                        .cloned();
+                    if let Some(name) = name {
+                        retval.names.entry(instruction.operand(5)?).or_insert(name);
+                    }
+                }
+                Some(shader_debug_info::DEBUG_LINE) => line_opcode = op::LINE,
+                Some(shader_debug_info::DEBUG_NO_LINE) => line_opcode = op::NO_LINE,
+                _ => {}
+            }
+            match line_opcode {
+                op::STRING => {
+                    let (string, _) = instruction.literal_string(1)?;
+                    retval.strings.insert(instruction.operand(0)?, string);
+                }
+                op::NAME => {
+                    let (name, _) = instruction.literal_string(1)?;
+                    retval.names.insert(instruction.operand(0)?, name);
+                }
+                op::CONSTANT => {
+                    if let [_, result, value] = *instruction.operands() {
+                        state.constants.insert(result, value);
+                    }
+                }
+                op::SOURCE => {
+                    if let Some(&file) = instruction.operands().get(2) {
+                        retval.source_file = Some(file);
+                    }
+                }
+                op::LINE | op::NO_LINE => {
+                    if let Some(mut line) = current_line.take() {
+                        line.end = instruction.offset;
+                        retval.lines.push(line);
+                    }
+                    if instruction.opcode == op::LINE {
+                        current_line = Some(LineRange {
+                            start: instruction.offset + instruction.words.len(),
+                            end: 0,
+                            file: instruction.operand(0)?,
+                            line: instruction.operand(1)?,
+                            column: instruction.operand(2)?,
+                        });
+                    } else if line_opcode == op::LINE {
+                        let source = instruction.operand(4)?;
+                        current_line = Some(LineRange {
+                            start: instruction.offset + instruction.words.len(),
+                            end: 0,
+                            file: state.sources.get(&source).cloned().unwrap_or(source),
+                            line: state.constant(&instruction, 5)?,
+                            column: state.constant(&instruction, 7)?,
+                        });
+                    }
+                }
+                opcode if is_block_terminator(opcode) || opcode == op::FUNCTION_END => {
+                    // an OpLine stops applying at the end of its block
+                    if let Some(mut line) = current_line.take() {
+                        line.end = instruction.offset + instruction.words.len();
+                        retval.lines.push(line);
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(mut line) = current_line {
+            line.end = code.len();
+            retval.lines.push(line);
+        }
+        Ok(retval)
+    }
+    /// the name given to `id` by OpName
+    pub fn name(&self, id: Id) -> Option<&str> {
+        self.names.get(&id).map(|v| &**v)
+    }
+    /// the name of the file given by OpSource
+    pub fn source_file_name(&self) -> Option<&str> {
+        self.strings.get(&self.source_file?).map(|v| &**v)
+    }
+    /// the source location of the instruction at `offset` words from the start of the module
+    pub fn source_location(&self, offset: usize) -> Option<SourceLocation> {
+        let index = match self.lines.binary_search_by_key(&offset, |line| line.start) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let line = &self.lines[index];
+        if offset >= line.end {
+            return None;
+        }
+        Some(SourceLocation {
+            file_name: self.strings.get(&line.file)?,
+            line: line.line,
+            column: line.column,
+        })
+    }
+    /// forward the debug info for the OpFunction at `offset` to the backend.
+    /// uses the OpSource file if the function has no OpLine; does nothing if there's no file.
+    pub fn set_function_source_location<'a, C: Context<'a>>(
+        &self,
+        module: &mut C::Module,
+        function: &C::Function,
+        function_id: Id,
+        offset: usize,
+    ) -> Result<(), Error> {
+        let location = match self.source_location(offset) {
+            Some(location) => location,
+            None => match self.source_file_name() {
+                Some(file_name) => SourceLocation {
+                    file_name,
+                    line: 0,
+                    column: 0,
+                },
+                None => return Ok(()),
+            },
+        };
+        let default_name;
+        let name = match self.name(function_id) {
+            Some(name) => name,
+            None => {
+                default_name = format!("%{}", function_id);
+                &default_name
+            }
+        };
+        module.set_function_source_location(function, name, location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::write_instruction;
+
+    const OP_LABEL: u16 = 248;
+
+    fn string(output: &mut Vec<u32>, opcode: u16, id: Id, value: &str) {
+        let mut operands = vec![id];
+        spirv::write_literal_string(&mut operands, value);
+        write_instruction(output, opcode, &operands);
+    }
+
+    #[test]
+    fn test_debug_info() {
+        let mut code = vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        string(&mut code, op::STRING, 1, "shader.frag");
+        string(&mut code, op::STRING, 2, "include.h");
+        // GLSL 450
+        write_instruction(&mut code, op::SOURCE, &[2, 450, 1]);
+        string(&mut code, op::NAME, 10, "main");
+        write_instruction(&mut code, op::LINE, &[1, 3, 1]);
+        let function_offset = code.len();
+        write_instruction(&mut code, op::FUNCTION, &[5, 10, 0, 6]);
+        write_instruction(&mut code, op::NO_LINE, &[]);
+        write_instruction(&mut code, OP_LABEL, &[11]);
+        let label_offset = code.len() - 2;
+        write_instruction(&mut code, op::LINE, &[2, 7, 5]);
+        write_instruction(&mut code, op::BRANCH, &[12]);
+        let branch_offset = code.len() - 2;
+        write_instruction(&mut code, OP_LABEL, &[12]);
+        let second_label_offset = code.len() - 2;
+        write_instruction(&mut code, op::RETURN, &[]);
+        write_instruction(&mut code, op::FUNCTION_END, &[]);
+        let debug_info = DebugInfo::parse(&code).unwrap();
+        assert_eq!(debug_info.source_file_name(), Some("shader.frag"));
+        assert_eq!(debug_info.name(10), Some("main"));
+        assert_eq!(debug_info.name(11), None);
+        assert_eq!(
+            debug_info.source_location(function_offset),
+            Some(SourceLocation {
+                file_name: "shader.frag",
+                line: 3,
+                column: 1,
+            })
+        );
+        assert_eq!(debug_info.source_location(label_offset), None);
+        assert_eq!(
+            debug_info.source_location(branch_offset),
+            Some(SourceLocation {
+                file_name: "include.h",
+                line: 7,
+                column: 5,
+            })
+        );
+        // the OpLine ends with its block
+        assert_eq!(debug_info.source_location(second_label_offset), None);
+    }
+    #[test]
+    fn test_shader_debug_info() {
+        let mut code = vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        let mut import = vec![3];
+        spirv::write_literal_string(&mut import, "NonSemantic.Shader.DebugInfo.100");
+        write_instruction(&mut code, op::EXT_INST_IMPORT, &import);
+        string(&mut code, op::STRING, 1, "shader.frag");
+        string(&mut code, op::STRING, 2, "main");
+        write_instruction(&mut code, op::CONSTANT, &[6, 20, 12]);
+        write_instruction(&mut code, op::CONSTANT, &[6, 21, 4]);
+        let debug_source = shader_debug_info::DEBUG_SOURCE;
+        write_instruction(&mut code, op::EXT_INST, &[7, 30, 3, debug_source, 1]);
+        let debug_function = shader_debug_info::DEBUG_FUNCTION;
+        write_instruction(&mut code, op::EXT_INST, &[7, 31, 3, debug_function, 2]);
+        write_instruction(&mut code, op::FUNCTION, &[5, 10, 0, 8]);
+        write_instruction(&mut code, OP_LABEL, &[11]);
+        let definition = shader_debug_info::DEBUG_FUNCTION_DEFINITION;
+        write_instruction(&mut code, op::EXT_INST, &[7, 32, 3, definition, 31, 10]);
+        let line = [7, 33, 3, shader_debug_info::DEBUG_LINE, 30, 20, 20, 21, 21];
+        write_instruction(&mut code, op::EXT_INST, &line);
+        write_instruction(&mut code, op::BRANCH, &[12]);
+        let branch_offset = code.len() - 2;
+        let no_line = [7, 34, 3, shader_debug_info::DEBUG_NO_LINE];
+        write_instruction(&mut code, op::EXT_INST, &no_line);
+        write_instruction(&mut code, op::RETURN, &[]);
+        let return_offset = code.len() - 1;
+        write_instruction(&mut code, op::FUNCTION_END, &[]);
+        let debug_info = DebugInfo::parse(&code).unwrap();
+        assert_eq!(debug_info.source_file_name(), Some("shader.frag"));
+        assert_eq!(debug_info.name(10), Some("main"));
+        assert_eq!(
+            debug_info.source_location(branch_offset),
+            Some(SourceLocation {
+                file_name: "shader.frag",
+                line: 12,
+                column: 4,
+            })
+        );
+        assert_eq!(debug_info.source_location(return_offset), None);
+    }
+}
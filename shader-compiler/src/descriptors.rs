@@ -0,0 +1,499 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! descriptor set and push constant ABI
+//!
+//! compiled entry points take a pointer to a `DescriptorTable` as their first (hidden) argument;
+//! see `abi` for the rest of the entry point interface.
+//! all bound descriptor sets are views into a single flat heap of `Descriptor`s, with descriptor
+//! set `s` starting at index `descriptor_set_offsets[s]`. a binding with a descriptor count of `n`
+//! uses `n` consecutive descriptors starting at the binding's `descriptor_index` in its set.
+
+use image::{ImageHandle, SamplerHandle};
+use interface::DescriptorBinding;
+use shader_compiler_backend::types::{BuildableStruct, TypeBuilder};
+use shader_compiler_backend::{AttachedBuilder, Context, Error};
+use spirv::StorageClass;
+use std::error;
+use std::fmt;
+use std::ptr;
+
+/// the maximum number of descriptor sets in a pipeline layout
+pub const MAX_DESCRIPTOR_SETS: usize = 32;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum DescriptorType {
+    Sampler,
+    CombinedImageSampler,
+    SampledImage,
+    StorageImage,
+    UniformTexelBuffer,
+    StorageTexelBuffer,
+    UniformBuffer,
+    StorageBuffer,
+    UniformBufferDynamic,
+    StorageBufferDynamic,
+    InputAttachment,
+}
+
+impl DescriptorType {
+    pub fn is_dynamic(self) -> bool {
+        match self {
+            DescriptorType::UniformBufferDynamic | DescriptorType::StorageBufferDynamic => true,
+            _ => false,
+        }
+    }
+}
+
+buildable_struct! {
+    #[derive(Copy)]
+    #[derive(Clone)]
+    #[derive(Debug)]
+    pub struct Descriptor {
+        pointer: *const u8,
+        sampler: SamplerHandle,
+        range: u64,
+    }
+}
+
+impl Default for Descriptor {
+    fn default() -> Self {
+        Descriptor {
+            pointer: ptr::null(),
+            sampler: ptr::null(),
+            range: 0,
+        }
+    }
+}
+
+impl Descriptor {
+    /// `pointer` points to the start of the bound range of the buffer
+    pub fn buffer(pointer: *const u8, range: u64) -> Self {
+        Descriptor {
+            pointer,
+            sampler: ptr::null(),
+            range,
+        }
+    }
+    pub fn image(image: ImageHandle) -> Self {
+        Descriptor {
+            pointer: image,
+            sampler: ptr::null(),
+            range: 0,
+        }
+    }
+    pub fn sampler(sampler: SamplerHandle) -> Self {
+        Descriptor {
+            pointer: ptr::null(),
+            sampler,
+            range: 0,
+        }
+    }
+    pub fn combined_image_sampler(image: ImageHandle, sampler: SamplerHandle) -> Self {
+        Descriptor {
+            pointer: image,
+            sampler,
+            range: 0,
+        }
+    }
+    /// apply a dynamic offset to a buffer descriptor
+    pub fn with_dynamic_offset(self, offset: u32) -> Self {
+        Descriptor {
+            pointer: self.pointer.wrapping_add(offset as usize),
+            ..self
+        }
+    }
+    pub fn pointer(&self) -> *const u8 {
+        self.pointer
+    }
+    pub fn sampler_handle(&self) -> SamplerHandle {
+        self.sampler
+    }
+    pub fn range(&self) -> u64 {
+        self.range
+    }
+}
+
+buildable_struct! {
+    #[derive(Copy)]
+    #[derive(Clone)]
+    #[derive(Debug)]
+    pub struct DescriptorTable {
+        descriptors: *const Descriptor,
+        descriptor_set_offsets: [u32; MAX_DESCRIPTOR_SETS],
+        push_constants: *const u8,
+    }
+}
+
+impl DescriptorTable {
+    /// `descriptors` points to the heap containing all the bound descriptor sets
+    pub fn new(
+        descriptors: *const Descriptor,
+        descriptor_set_offsets: [u32; MAX_DESCRIPTOR_SETS],
+        push_constants: *const u8,
+    ) -> Self {
+        DescriptorTable {
+            descriptors,
+            descriptor_set_offsets,
+            push_constants,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct DescriptorSetLayoutBinding {
+    pub descriptor_type: DescriptorType,
+    /// index of the first descriptor in the descriptor set
+    pub descriptor_index: u32,
+    pub descriptor_count: u32,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct DescriptorSetLayout {
+    /// indexed by binding number
+    pub bindings: Vec<Option<DescriptorSetLayoutBinding>>,
+    /// total number of descriptors in the descriptor set
+    pub descriptor_count: u32,
+}
+
+impl DescriptorSetLayout {
+    pub fn get_binding(&self, binding: u32) -> Option<&DescriptorSetLayoutBinding> {
+        self.bindings.get(binding as usize)?.as_ref()
+    }
+    /// descriptor sets with one layout can be used with the other: every binding number has the
+    /// same descriptor type and count in both, or is missing from both. doesn't depend on where
+    /// the layouts came from, so layouts created separately from the same bindings are compatible
+    pub fn is_compatible(&self, other: &DescriptorSetLayout) -> bool {
+        let binding_count = self.bindings.len().max(other.bindings.len());
+        (0..binding_count as u32).all(|binding| {
+            match (self.get_binding(binding), other.get_binding(binding)) {
+                (Some(a), Some(b)) => {
+                    a.descriptor_type == b.descriptor_type
+                        && a.descriptor_count == b.descriptor_count
+                }
+                (None, None) => true,
+                _ => false,
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PipelineLayout {
+    pub descriptor_sets: Vec<DescriptorSetLayout>,
+    /// size of the push constant block in bytes
+    pub push_constants_size: u32,
+}
+
+impl PipelineLayout {
+    pub fn get_binding(&self, binding: DescriptorBinding) -> Option<&DescriptorSetLayoutBinding> {
+        self.descriptor_sets
+            .get(binding.descriptor_set as usize)?
+            .get_binding(binding.binding)
+    }
+}
+
+/// why a descriptor access can't be built
+#[derive(Clone, Debug)]
+pub enum DescriptorError {
+    /// the shader uses a binding that isn't in the pipeline layout
+    BindingNotInLayout(DescriptorBinding),
+    /// a constant array element past the end of the binding
+    ElementOutOfRange {
+        binding: DescriptorBinding,
+        element: u32,
+        descriptor_count: u32,
+    },
+    /// a `Uniform` or `StorageBuffer` variable with no descriptor binding
+    VariableHasNoBinding,
+    Backend(Error),
+}
+
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DescriptorError::BindingNotInLayout(binding) => {
+                write!(f, "{:?} is not in the pipeline layout", binding)
+            }
+            DescriptorError::ElementOutOfRange {
+                binding,
+                element,
+                descriptor_count,
+            } => write!(
+                f,
+                "element {} of {:?} is out of range: the binding has {} descriptors",
+                element, binding, descriptor_count
+            ),
+            DescriptorError::VariableHasNoBinding => {
+                write!(f, "buffer variable has no descriptor binding")
+            }
+            DescriptorError::Backend(error) => error.fmt(f),
+        }
+    }
+}
+
+impl error::Error for DescriptorError {}
+
+impl From<Error> for DescriptorError {
+    fn from(v: Error) -> Self {
+        DescriptorError::Backend(v)
+    }
+}
+
+fn member_index<T: BuildableStruct>(name: &str) -> u32 {
+    T::get_member_by_name(name).index as u32
+}
+
+/// build a pointer to the first `Descriptor` of `binding`
+fn build_binding_pointer<'a, C: Context<'a>>(
+    builder: &C::AttachedBuilder,
+    descriptor_table: C::Value,
+    layout_binding: &DescriptorSetLayoutBinding,
+    binding: DescriptorBinding,
+) -> Result<C::Value, DescriptorError> {
+    assert!((binding.descriptor_set as usize) < MAX_DESCRIPTOR_SETS);
+    // the two loads are independent, so they don't form a chain of dependent loads
+    let descriptors = builder.build_load(builder.build_element_pointer(
+        descriptor_table.clone(),
+        &[0, member_index::<DescriptorTable>("descriptors")],
+    )?);
+    let descriptor_set_offset = builder.build_load(builder.build_element_pointer(
+        descriptor_table,
+        &[
+            0,
+            member_index::<DescriptorTable>("descriptor_set_offsets"),
+            binding.descriptor_set,
+        ],
+    )?);
+    let descriptor_set =
+        builder.build_indexed_element_pointer(descriptors, descriptor_set_offset)?;
+    Ok(builder.build_element_pointer(descriptor_set, &[layout_binding.descriptor_index])?)
+}
+
+/// the layout of `binding`, which must be in the pipeline layout for a shader to use it
+pub fn get_layout_binding(
+    layout: &PipelineLayout,
+    binding: DescriptorBinding,
+) -> Result<&DescriptorSetLayoutBinding, DescriptorError> {
+    layout
+        .get_binding(binding)
+        .ok_or(DescriptorError::BindingNotInLayout(binding))
+}
+
+/// build a pointer to the `Descriptor` for element `element` of `binding`
+pub fn build_descriptor_pointer<'a, C: Context<'a>>(
+    builder: &C::AttachedBuilder,
+    descriptor_table: C::Value,
+    layout: &PipelineLayout,
+    binding: DescriptorBinding,
+    element: u32,
+) -> Result<C::Value, DescriptorError> {
+    let layout_binding = get_layout_binding(layout, binding)?;
+    if element >= layout_binding.descriptor_count {
+        return Err(DescriptorError::ElementOutOfRange {
+            binding,
+            element,
+            descriptor_count: layout_binding.descriptor_count,
+        });
+    }
+    let binding_pointer =
+        build_binding_pointer::<C>(builder, descriptor_table, layout_binding, binding)?;
+    Ok(builder.build_element_pointer(binding_pointer, &[element])?)
+}
+
+/// build a pointer to the `Descriptor` for element `element` of `binding`, where `element` is
+/// computed at runtime (descriptor indexing)
+pub fn build_indexed_descriptor_pointer<'a, C: Context<'a>>(
+    builder: &C::AttachedBuilder,
+    descriptor_table: C::Value,
+    layout: &PipelineLayout,
+    binding: DescriptorBinding,
+    element: C::Value,
+) -> Result<C::Value, DescriptorError> {
+    let layout_binding = get_layout_binding(layout, binding)?;
+    let binding_pointer =
+        build_binding_pointer::<C>(builder, descriptor_table, layout_binding, binding)?;
+    Ok(builder.build_indexed_element_pointer(binding_pointer, element)?)
+}
+
+/// lowering for `OpVariable` in the `Uniform`, `StorageBuffer`, and `PushConstant` storage classes
+///
+/// returns the variable's pointer, cast to `pointer_type`
+pub fn build_variable_pointer<'a, C: Context<'a>>(
+    builder: &C::AttachedBuilder,
+    descriptor_table: C::Value,
+    layout: &PipelineLayout,
+    storage_class: StorageClass,
+    binding: Option<DescriptorBinding>,
+    pointer_type: C::Type,
+) -> Result<C::Value, DescriptorError> {
+    let pointer =
+        match storage_class {
+            StorageClass::Uniform | StorageClass::StorageBuffer => {
+                let binding = binding.ok_or(DescriptorError::VariableHasNoBinding)?;
+                let descriptor =
+                    build_descriptor_pointer::<C>(builder, descriptor_table, layout, binding, 0)?;
+                builder.build_load(builder.build_element_pointer(
+                    descriptor,
+                    &[0, member_index::<Descriptor>("pointer")],
+                )?)
+            }
+            StorageClass::PushConstant => builder.build_load(builder.build_element_pointer(
+                descriptor_table,
+                &[0, member_index::<DescriptorTable>("push_constants")],
+            )?),
+            _ => unreachable!(
+                "storage class not backed by the descriptor table: {:?}",
+                storage_class
+            ),
+        };
+    Ok(builder.build_bitcast(pointer, pointer_type))
+}
+
+/// load the `ImageHandle` for element `element` of `binding`
+pub fn build_image_handle<'a, C: Context<'a>>(
+    builder: &C::AttachedBuilder,
+    descriptor_table: C::Value,
+    layout: &PipelineLayout,
+    binding: DescriptorBinding,
+    element: u32,
+) -> Result<C::Value, DescriptorError> {
+    let descriptor =
+        build_descriptor_pointer::<C>(builder, descriptor_table, layout, binding, element)?;
+    Ok(builder.build_load(
+        builder.build_element_pointer(descriptor, &[0, member_index::<Descriptor>("pointer")])?,
+    ))
+}
+
+/// load the `SamplerHandle` for element `element` of `binding`
+pub fn build_sampler_handle<'a, C: Context<'a>>(
+    builder: &C::AttachedBuilder,
+    descriptor_table: C::Value,
+    layout: &PipelineLayout,
+    binding: DescriptorBinding,
+    element: u32,
+) -> Result<C::Value, DescriptorError> {
+    let descriptor =
+        build_descriptor_pointer::<C>(builder, descriptor_table, layout, binding, element)?;
+    Ok(builder.build_load(
+        builder.build_element_pointer(descriptor, &[0, member_index::<Descriptor>("sampler")])?,
+    ))
+}
+
+/// the type of the hidden descriptor table argument
+pub fn build_descriptor_table_pointer_type<'a, C: Context<'a>>(
+    context: &'a C,
+) -> Result<C::Type, Error> {
+    context
+        .create_type_builder()
+        .build::<*const DescriptorTable>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn test_descriptor_layout() {
+        assert_eq!(member_index::<Descriptor>("pointer"), 0);
+        assert_eq!(member_index::<Descriptor>("sampler"), 1);
+        assert_eq!(member_index::<Descriptor>("range"), 2);
+        assert_eq!(member_index::<DescriptorTable>("descriptors"), 0);
+        assert_eq!(member_index::<DescriptorTable>("descriptor_set_offsets"), 1);
+        assert_eq!(member_index::<DescriptorTable>("push_constants"), 2);
+        assert_eq!(
+            mem::size_of::<Descriptor>(),
+            2 * mem::size_of::<*const u8>() + mem::size_of::<u64>()
+        );
+    }
+
+    #[test]
+    fn test_pipeline_layout() {
+        let layout = PipelineLayout {
+            descriptor_sets: vec![
+                DescriptorSetLayout::default(),
+                DescriptorSetLayout {
+                    bindings: vec![
+                        None,
+                        Some(DescriptorSetLayoutBinding {
+                            descriptor_type: DescriptorType::UniformBuffer,
+                            descriptor_index: 0,
+                            descriptor_count: 2,
+                        }),
+                    ],
+                    descriptor_count: 2,
+                },
+            ],
+            push_constants_size: 0,
+        };
+        let binding = |descriptor_set, binding| DescriptorBinding {
+            descriptor_set,
+            binding,
+        };
+        assert!(layout.get_binding(binding(0, 0)).is_none());
+        assert!(layout.get_binding(binding(1, 0)).is_none());
+        assert_eq!(
+            layout.get_binding(binding(1, 1)).unwrap().descriptor_count,
+            2
+        );
+        assert!(layout.get_binding(binding(1, 2)).is_none());
+        assert!(layout.get_binding(binding(2, 0)).is_none());
+        assert_eq!(
+            get_layout_binding(&layout, binding(1, 1))
+                .unwrap()
+                .descriptor_count,
+            2
+        );
+        match get_layout_binding(&layout, binding(1, 2)) {
+            Err(DescriptorError::BindingNotInLayout(v)) => assert_eq!(v, binding(1, 2)),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_set_layout_compatibility() {
+        let layout = |bindings: &[Option<(DescriptorType, u32)>]| {
+            let mut descriptor_count = 0;
+            let bindings = bindings
+                .iter()
+                .map(|binding| {
+                    binding.map(|(descriptor_type, count)| {
+                        descriptor_count += count;
+                        DescriptorSetLayoutBinding {
+                            descriptor_type,
+                            descriptor_index: descriptor_count - count,
+                            descriptor_count: count,
+                        }
+                    })
+                })
+                .collect();
+            DescriptorSetLayout {
+                bindings,
+                descriptor_count,
+            }
+        };
+        let uniform_buffers = layout(&[None, Some((DescriptorType::UniformBuffer, 2))]);
+        assert!(uniform_buffers.is_compatible(&uniform_buffers.clone()));
+        // missing bindings at the end don't matter
+        let mut padded = uniform_buffers.clone();
+        padded.bindings.push(None);
+        assert!(uniform_buffers.is_compatible(&padded));
+        assert!(padded.is_compatible(&uniform_buffers));
+        let storage_buffers = layout(&[None, Some((DescriptorType::StorageBuffer, 2))]);
+        assert!(!uniform_buffers.is_compatible(&storage_buffers));
+        let one_uniform_buffer = layout(&[None, Some((DescriptorType::UniformBuffer, 1))]);
+        assert!(!uniform_buffers.is_compatible(&one_uniform_buffer));
+        let other_binding = layout(&[Some((DescriptorType::UniformBuffer, 2))]);
+        assert!(!uniform_buffers.is_compatible(&other_binding));
+        assert!(!uniform_buffers.is_compatible(&DescriptorSetLayout::default()));
+    }
+
+    #[test]
+    fn test_dynamic_offset() {
+        let data = [0u8; 16];
+        let descriptor = Descriptor::buffer(data.as_ptr(), 8).with_dynamic_offset(4);
+        assert_eq!(descriptor.pointer(), data[4..].as_ptr());
+        assert_eq!(descriptor.range(), 8);
+    }
+}
@@ -0,0 +1,233 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! scheduling the depth and stencil tests relative to fragment shading
+//!
+//! the tests logically happen after the fragment shader, since it can write `FragDepth`,
+//! `FragStencilRefEXT` or `SampleMask`, or discard. most shaders can't change the results of the
+//! tests, so testing before shading skips shading fragments that would be thrown away. the
+//! fragment shader's execution modes widen that:
+//!
+//! * `EarlyFragmentTests` requires the tests and the depth and stencil writes to happen before
+//!   shading, even if the shader discards or writes depth
+//! * `PostDepthCoverage` makes the `SampleMask` input exclude samples that failed the early tests
+//! * `DepthUnchanged` promises that `FragDepth` is the interpolated depth
+//! * `DepthGreater` and `DepthLess` promise that `FragDepth` only moves one way from the
+//!   interpolated depth. when the depth comparison passes fragments that move the other way, a
+//!   fragment that fails with its interpolated depth also fails with the shader's depth, so it can
+//!   be rejected before shading
+//!
+//! shaders that write storage buffers or images without `EarlyFragmentTests` are always tested
+//! after shading, since fragments that fail the tests still have to run for their side effects.
+
+use interface::EntryPointInterface;
+use spirv::{built_in, execution_mode};
+
+/// the depth comparison, converted from `VkCompareOp` by the driver
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum DepthCompare {
+    Never,
+    Less,
+    Equal,
+    LessOrEqual,
+    Greater,
+    NotEqual,
+    GreaterOrEqual,
+    Always,
+}
+
+/// what the fragment shader's depth output is, relative to the interpolated depth
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum DepthOutput {
+    /// the shader doesn't write `FragDepth`, or declares `DepthUnchanged`
+    Unchanged,
+    /// `DepthGreater`: never less than the interpolated depth
+    Greater,
+    /// `DepthLess`: never greater than the interpolated depth
+    Less,
+    /// the shader writes `FragDepth` without promising anything about it
+    Any,
+}
+
+/// when the rasterizer runs the depth and stencil tests, and when it writes their results
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum FragmentTestTiming {
+    /// test and write before shading; only fragments that pass are shaded
+    Early,
+    /// test before shading, and write after shading for the fragments that weren't discarded
+    EarlyTestLateWrite,
+    /// reject the fragments that fail with their interpolated depth before shading, then test
+    /// the rest with the shader's depth and write after shading. rejected fragments still get
+    /// the stencil test's and `depthFailOp`'s stencil writes
+    EarlyRejectLateTest,
+    /// test and write after shading every fragment
+    Late,
+}
+
+/// the parts of a fragment shader that decide the `FragmentTestTiming`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct FragmentShaderTests {
+    pub early_fragment_tests: bool,
+    pub post_depth_coverage: bool,
+    pub depth_output: DepthOutput,
+    /// the shader has `OpKill` or writes `SampleMask`
+    pub may_discard: bool,
+    pub writes_memory: bool,
+    /// the shader writes `FragStencilRefEXT`
+    pub exports_stencil_reference: bool,
+    /// the shader writes `SampleMask`, which restricts the samples that are written
+    pub writes_sample_mask: bool,
+}
+
+impl FragmentShaderTests {
+    pub fn new(interface: &EntryPointInterface) -> Self {
+        let writes_built_in = |built_in: u32| {
+            interface
+                .outputs
+                .iter()
+                .any(|output| output.built_in == Some(built_in))
+        };
+        let depth_output = if !writes_built_in(built_in::FRAG_DEPTH)
+            || interface.has_execution_mode(execution_mode::DEPTH_UNCHANGED)
+        {
+            DepthOutput::Unchanged
+        } else if interface.has_execution_mode(execution_mode::DEPTH_GREATER) {
+            DepthOutput::Greater
+        } else if interface.has_execution_mode(execution_mode::DEPTH_LESS) {
+            DepthOutput::Less
+        } else {
+            DepthOutput::Any
+        };
+        FragmentShaderTests {
+            early_fragment_tests: interface
+                .has_execution_mode(execution_mode::EARLY_FRAGMENT_TESTS),
+            post_depth_coverage: interface.has_execution_mode(execution_mode::POST_DEPTH_COVERAGE),
+            depth_output,
+            may_discard: interface.may_discard || writes_built_in(built_in::SAMPLE_MASK),
+            writes_memory: interface.writes_memory,
+            exports_stencil_reference: writes_built_in(built_in::FRAG_STENCIL_REF_EXT),
+            writes_sample_mask: writes_built_in(built_in::SAMPLE_MASK),
+        }
+    }
+    /// true if the `SampleMask` input only has the samples that passed the early tests.
+    /// `PostDepthCoverage` has no effect without `EarlyFragmentTests`
+    pub fn uses_post_depth_coverage(&self) -> bool {
+        self.early_fragment_tests && self.post_depth_coverage
+    }
+    /// `depth_compare` is `None` if the depth test is disabled
+    pub fn get_timing(
+        &self,
+        depth_compare: Option<DepthCompare>,
+        stencil_test_enable: bool,
+    ) -> FragmentTestTiming {
+        if self.early_fragment_tests {
+            return FragmentTestTiming::Early;
+        }
+        if self.writes_memory || (self.exports_stencil_reference && stencil_test_enable) {
+            return FragmentTestTiming::Late;
+        }
+        let depth_compare = match depth_compare {
+            Some(depth_compare) if self.depth_output != DepthOutput::Unchanged => depth_compare,
+            _ if self.may_discard => return FragmentTestTiming::EarlyTestLateWrite,
+            _ => return FragmentTestTiming::Early,
+        };
+        match (self.depth_output, depth_compare) {
+            // the result doesn't depend on the depth, but the written depth does
+            (_, DepthCompare::Never) | (_, DepthCompare::Always) => {
+                FragmentTestTiming::EarlyTestLateWrite
+            }
+            (DepthOutput::Greater, DepthCompare::Less)
+            | (DepthOutput::Greater, DepthCompare::LessOrEqual)
+            | (DepthOutput::Less, DepthCompare::Greater)
+            | (DepthOutput::Less, DepthCompare::GreaterOrEqual) => {
+                FragmentTestTiming::EarlyRejectLateTest
+            }
+            _ => FragmentTestTiming::Late,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tests(depth_output: DepthOutput) -> FragmentShaderTests {
+        FragmentShaderTests {
+            early_fragment_tests: false,
+            post_depth_coverage: false,
+            depth_output,
+            may_discard: false,
+            writes_memory: false,
+            exports_stencil_reference: false,
+            writes_sample_mask: false,
+        }
+    }
+
+    #[test]
+    fn test_timing() {
+        let unchanged = tests(DepthOutput::Unchanged);
+        let less = Some(DepthCompare::Less);
+        assert_eq!(unchanged.get_timing(less, true), FragmentTestTiming::Early);
+        let discards = FragmentShaderTests {
+            may_discard: true,
+            ..unchanged
+        };
+        assert_eq!(
+            discards.get_timing(less, true),
+            FragmentTestTiming::EarlyTestLateWrite
+        );
+        let writes_memory = FragmentShaderTests {
+            writes_memory: true,
+            ..unchanged
+        };
+        assert_eq!(
+            writes_memory.get_timing(less, true),
+            FragmentTestTiming::Late
+        );
+        let early = FragmentShaderTests {
+            early_fragment_tests: true,
+            ..writes_memory
+        };
+        assert_eq!(early.get_timing(less, true), FragmentTestTiming::Early);
+        let exports_stencil_reference = FragmentShaderTests {
+            exports_stencil_reference: true,
+            ..unchanged
+        };
+        assert_eq!(
+            exports_stencil_reference.get_timing(less, true),
+            FragmentTestTiming::Late
+        );
+        assert_eq!(
+            exports_stencil_reference.get_timing(less, false),
+            FragmentTestTiming::Early
+        );
+    }
+
+    #[test]
+    fn test_conservative_depth() {
+        let greater = tests(DepthOutput::Greater);
+        assert_eq!(
+            greater.get_timing(Some(DepthCompare::LessOrEqual), false),
+            FragmentTestTiming::EarlyRejectLateTest
+        );
+        assert_eq!(
+            greater.get_timing(Some(DepthCompare::Greater), false),
+            FragmentTestTiming::Late
+        );
+        assert_eq!(greater.get_timing(None, false), FragmentTestTiming::Early);
+        let less = tests(DepthOutput::Less);
+        assert_eq!(
+            less.get_timing(Some(DepthCompare::GreaterOrEqual), false),
+            FragmentTestTiming::EarlyRejectLateTest
+        );
+        let any = tests(DepthOutput::Any);
+        assert_eq!(
+            any.get_timing(Some(DepthCompare::Less), false),
+            FragmentTestTiming::Late
+        );
+        assert_eq!(
+            any.get_timing(Some(DepthCompare::Always), false),
+            FragmentTestTiming::EarlyTestLateWrite
+        );
+    }
+}
@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! framebuffer fetch: lowering for reads of input attachments (`subpassLoad`)
+//!
+//! the rasterizer shades each tile's fragments in rasterization order on one thread and keeps
+//! the tile's attachments in memory, so the fragment's current color is already at hand. instead
+//! of going through the input attachment's descriptor and the image intrinsics, `OpImageRead`
+//! of a `SubpassData` image loads the value that the rasterizer put in
+//! `InvocationInputs::input_attachments`. that value includes the writes of earlier fragments in
+//! the same subpass, which is what `VK_EXT_rasterization_order_attachment_access` promises, so
+//! emulation layers can implement programmable blending by reading the color attachment as an
+//! input attachment.
+//!
+//! only single-sampled input attachments are supported, and `InputAttachmentIndex` must be
+//! applied with `OpDecorate`, not decoration groups.
+//!
+//! nothing rasterizes yet, so `VK_EXT_rasterization_order_attachment_access` isn't advertised
+//! until the rasterizer fills in `input_attachments`.
+
+use abi::InvocationInputs;
+use shader_compiler_backend::types::BuildableStruct;
+use shader_compiler_backend::{AttachedBuilder, Context, Error as BackendError};
+use spirv::{decoration, op, Error, Id, Instruction, Instructions};
+use std::collections::{HashMap, HashSet};
+
+/// the `Dim` of input attachments
+const DIM_SUBPASS_DATA: u32 = 6;
+
+pub trait SubpassLoadOperands<'a, C: Context<'a>> {
+    /// the type of pointers to `ty`
+    fn get_pointer_type(&self, ty: Id) -> Result<C::Type, BackendError>;
+}
+
+/// the input attachments that are read in a module
+#[derive(Clone, Debug, Default)]
+pub struct InputAttachments {
+    /// maps the results of loading input attachment variables to their `InputAttachmentIndex`
+    loaded_images: HashMap<Id, u32>,
+}
+
+impl InputAttachments {
+    pub fn find(code: &[u32]) -> Result<Self, Error> {
+        let mut indexes = HashMap::new();
+        let mut subpass_data_types = HashSet::new();
+        let mut pointer_types = HashSet::new();
+        let mut variables = HashMap::new();
+        let mut retval = Self::default();
+        for instruction in Instructions::new(code)? {
+            let instruction = instruction?;
+            match instruction.opcode {
+                op::DECORATE => {
+                    if instruction.operand(1)? == decoration::INPUT_ATTACHMENT_INDEX {
+                        indexes.insert(instruction.operand(0)?, instruction.operand(2)?);
+                    }
+                }
+                // multisampled input attachments have `MS` set
+                op::TYPE_IMAGE => {
+                    if instruction.operand(2)? == DIM_SUBPASS_DATA && instruction.operand(5)? == 0 {
+                        subpass_data_types.insert(instruction.operand(0)?);
+                    }
+                }
+                op::TYPE_POINTER => {
+                    if subpass_data_types.contains(&instruction.operand(2)?) {
+                        pointer_types.insert(instruction.operand(0)?);
+                    }
+                }
+                op::VARIABLE => {
+                    let variable = instruction.operand(1)?;
+                    if pointer_types.contains(&instruction.operand(0)?) {
+                        if let Some(&index) = indexes.get(&variable) {
+                            variables.insert(variable, index);
+                        }
+                    }
+                }
+                op::LOAD => {
+                    if let Some(&index) = variables.get(&instruction.operand(2)?) {
+                        retval.loaded_images.insert(instruction.operand(1)?, index);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(retval)
+    }
+    /// the `InputAttachmentIndex` of `image`, if it's a loaded input attachment
+    pub fn get_index(&self, image: Id) -> Option<u32> {
+        self.loaded_images.get(&image).cloned()
+    }
+}
+
+/// an `OpImageRead` of an input attachment
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct SubpassLoad {
+    pub result_type: Id,
+    pub result: Id,
+    pub input_attachment_index: u32,
+}
+
+impl SubpassLoad {
+    /// returns `None` if `instruction` isn't an `OpImageRead` of one of `input_attachments`.
+    /// the coordinate operand is ignored since it's always 0 relative to the fragment
+    pub fn parse(
+        instruction: &Instruction,
+        input_attachments: &InputAttachments,
+    ) -> Result<Option<Self>, Error> {
+        if instruction.opcode != op::IMAGE_READ {
+            return Ok(None);
+        }
+        let input_attachment_index = match input_attachments.get_index(instruction.operand(2)?) {
+            Some(input_attachment_index) => input_attachment_index,
+            None => return Ok(None),
+        };
+        Ok(Some(SubpassLoad {
+            result_type: instruction.operand(0)?,
+            result: instruction.operand(1)?,
+            input_attachment_index,
+        }))
+    }
+    /// `invocation_inputs` is the entry point's `*const InvocationInputs` argument
+    pub fn build<'a, C: Context<'a>, O: SubpassLoadOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        invocation_inputs: C::Value,
+        operands: &O,
+    ) -> Result<C::Value, BackendError> {
+        let input_attachments = builder.build_load(builder.build_element_pointer(
+            invocation_inputs,
+            &[
+                0,
+                InvocationInputs::get_member_by_name("input_attachments").index as u32,
+            ],
+        )?);
+        let input_attachment =
+            builder.build_element_pointer(input_attachments, &[self.input_attachment_index])?;
+        Ok(builder.build_load(builder.build_bitcast(
+            input_attachment,
+            operands.get_pointer_type(self.result_type)?,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::{write_instruction, StorageClass, MAGIC_NUMBER};
+
+    /// 1: float, 2: vec4, 3: subpass data image, 4: multisampled subpass data image,
+    /// 5 and 6: pointers to those, 7: input attachment 2, 8: multisampled input attachment 3,
+    /// 10 and 11: loads of 7 and 8, 12 and 13: reads of 10 and 11
+    fn test_module() -> Vec<u32> {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        write_instruction(
+            &mut code,
+            op::DECORATE,
+            &[7, decoration::INPUT_ATTACHMENT_INDEX, 2],
+        );
+        write_instruction(
+            &mut code,
+            op::DECORATE,
+            &[8, decoration::INPUT_ATTACHMENT_INDEX, 3],
+        );
+        write_instruction(&mut code, op::TYPE_FLOAT, &[1, 32]);
+        write_instruction(&mut code, op::TYPE_VECTOR, &[2, 1, 4]);
+        write_instruction(
+            &mut code,
+            op::TYPE_IMAGE,
+            &[3, 1, DIM_SUBPASS_DATA, 0, 0, 0, 2, 0],
+        );
+        write_instruction(
+            &mut code,
+            op::TYPE_IMAGE,
+            &[4, 1, DIM_SUBPASS_DATA, 0, 0, 1, 2, 0],
+        );
+        let uniform_constant = StorageClass::UniformConstant as u32;
+        write_instruction(&mut code, op::TYPE_POINTER, &[5, uniform_constant, 3]);
+        write_instruction(&mut code, op::TYPE_POINTER, &[6, uniform_constant, 4]);
+        write_instruction(&mut code, op::VARIABLE, &[5, 7, uniform_constant]);
+        write_instruction(&mut code, op::VARIABLE, &[6, 8, uniform_constant]);
+        write_instruction(&mut code, op::LOAD, &[3, 10, 7]);
+        write_instruction(&mut code, op::LOAD, &[4, 11, 8]);
+        write_instruction(&mut code, op::IMAGE_READ, &[2, 12, 10, 20]);
+        write_instruction(&mut code, op::IMAGE_READ, &[2, 13, 11, 20]);
+        code
+    }
+
+    #[test]
+    fn test_subpass_load() {
+        let code = test_module();
+        let input_attachments = InputAttachments::find(&code).unwrap();
+        assert_eq!(input_attachments.get_index(10), Some(2));
+        assert_eq!(input_attachments.get_index(11), None);
+        let loads: Vec<SubpassLoad> = Instructions::new(&code)
+            .unwrap()
+            .filter_map(|instruction| {
+                SubpassLoad::parse(&instruction.unwrap(), &input_attachments).unwrap()
+            })
+            .collect();
+        assert_eq!(
+            loads,
+            [SubpassLoad {
+                result_type: 2,
+                result: 12,
+                input_attachment_index: 2,
+            }]
+        );
+    }
+}
@@ -0,0 +1,310 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! image access intrinsics
+//!
+//! generated code doesn't access image memory directly; image instructions such as
+//! `OpImageSampleImplicitLod` are lowered to calls to runtime functions provided by the driver.
+//! all vectors are passed through pointers to avoid depending on the vector calling convention.
+//!
+//! atomics on storage image texels don't go through the runtime: `OpImageTexelPointer` gets the
+//! texel's address from `ImageIntrinsics::texel_pointer`, and the `OpAtomic*` instructions that
+//! use it are lowered like atomics on buffer memory, including the native 64-bit atomics on
+//! `R64ui` and `R64i` images.
+//!
+//! the driver doesn't advertise `VK_EXT_shader_image_atomic_int64` or support the `R64` formats
+//! yet: storage images also need `OpImageRead` and `OpImageWrite`, and `FetchFn` returns
+//! `[f32; 4]`, which can't hold 64-bit integers.
+
+use shader_compiler_backend::types::TypeBuilder;
+use shader_compiler_backend::{AttachedBuilder, Context, Error, Function, Module};
+use spirv::{self, op, Id, Instruction};
+use std::mem;
+
+/// opaque pointer to the driver's image view
+pub type ImageHandle = *const u8;
+
+/// opaque pointer to the driver's sampler
+pub type SamplerHandle = *const u8;
+
+/// `fn(image, sampler, coordinates, derivatives, lod_bias, result)`
+///
+/// `derivatives` are the derivatives of `coordinates` with respect to x and y
+pub type SampleImplicitLodFn = unsafe extern "C" fn(
+    ImageHandle,
+    SamplerHandle,
+    *const [f32; 4],
+    *const [[f32; 4]; 2],
+    f32,
+    *mut [f32; 4],
+);
+
+/// `fn(image, sampler, coordinates, lod, result)`
+pub type SampleExplicitLodFn =
+    unsafe extern "C" fn(ImageHandle, SamplerHandle, *const [f32; 4], f32, *mut [f32; 4]);
+
+/// `fn(image, coordinates, lod, result)`
+pub type FetchFn = unsafe extern "C" fn(ImageHandle, *const [i32; 4], i32, *mut [f32; 4]);
+
+/// `fn(image, coordinates, sample) -> texel`
+///
+/// returns a pointer to the first byte of the texel in the image's memory, for atomic access.
+/// out of bounds coordinates or samples return a pointer to a scratch texel instead
+pub type TexelPointerFn = unsafe extern "C" fn(ImageHandle, *const [i32; 4], i32) -> *mut u8;
+
+const SAMPLE_IMPLICIT_LOD_NAME: &str = "kazan_image_sample_implicit_lod";
+const SAMPLE_EXPLICIT_LOD_NAME: &str = "kazan_image_sample_explicit_lod";
+const FETCH_NAME: &str = "kazan_image_fetch";
+const TEXEL_POINTER_NAME: &str = "kazan_image_texel_pointer";
+
+/// the runtime functions that implement image access
+#[derive(Copy, Clone)]
+pub struct ImageIntrinsics {
+    pub sample_implicit_lod: SampleImplicitLodFn,
+    pub sample_explicit_lod: SampleExplicitLodFn,
+    pub fetch: FetchFn,
+    pub texel_pointer: TexelPointerFn,
+}
+
+/// the image intrinsics declared in a module
+pub struct DeclaredImageIntrinsics<'a, C: Context<'a>> {
+    pub sample_implicit_lod: C::Function,
+    pub sample_explicit_lod: C::Function,
+    pub fetch: C::Function,
+    pub texel_pointer: C::Function,
+}
+
+impl ImageIntrinsics {
+    /// the intrinsics for `CompilerIndependentConfig::host_functions`, named the same as the
+    /// functions `declare` adds, so modules can also declare them without their addresses
+    pub fn get_host_functions(&self) -> Vec<(&'static str, usize)> {
+        vec![
+            (SAMPLE_IMPLICIT_LOD_NAME, self.sample_implicit_lod as usize),
+            (SAMPLE_EXPLICIT_LOD_NAME, self.sample_explicit_lod as usize),
+            (FETCH_NAME, self.fetch as usize),
+            (TEXEL_POINTER_NAME, self.texel_pointer as usize),
+        ]
+    }
+    pub fn declare<'a, C: Context<'a>>(
+        &self,
+        context: &'a C,
+        module: &mut C::Module,
+    ) -> Result<DeclaredImageIntrinsics<'a, C>, Error> {
+        let type_builder = context.create_type_builder();
+        unsafe {
+            Ok(DeclaredImageIntrinsics {
+                sample_implicit_lod: module.add_external_function(
+                    SAMPLE_IMPLICIT_LOD_NAME,
+                    type_builder.build::<SampleImplicitLodFn>()?,
+                    mem::transmute(self.sample_implicit_lod),
+                )?,
+                sample_explicit_lod: module.add_external_function(
+                    SAMPLE_EXPLICIT_LOD_NAME,
+                    type_builder.build::<SampleExplicitLodFn>()?,
+                    mem::transmute(self.sample_explicit_lod),
+                )?,
+                fetch: module.add_external_function(
+                    FETCH_NAME,
+                    type_builder.build::<FetchFn>()?,
+                    mem::transmute(self.fetch),
+                )?,
+                texel_pointer: module.add_external_function(
+                    TEXEL_POINTER_NAME,
+                    type_builder.build::<TexelPointerFn>()?,
+                    mem::transmute(self.texel_pointer),
+                )?,
+            })
+        }
+    }
+}
+
+impl<'a, C: Context<'a>> DeclaredImageIntrinsics<'a, C> {
+    /// lowering for `OpImageSampleImplicitLod`
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    pub fn build_sample_implicit_lod(
+        &self,
+        builder: &C::AttachedBuilder,
+        image: C::Value,
+        sampler: C::Value,
+        coordinates: C::Value,
+        derivatives: C::Value,
+        lod_bias: C::Value,
+        result: C::Value,
+    ) -> Result<(), Error> {
+        builder.build_call(
+            self.sample_implicit_lod.as_value(),
+            &[image, sampler, coordinates, derivatives, lod_bias, result],
+        )?;
+        Ok(())
+    }
+    /// lowering for `OpImageSampleExplicitLod`
+    pub fn build_sample_explicit_lod(
+        &self,
+        builder: &C::AttachedBuilder,
+        image: C::Value,
+        sampler: C::Value,
+        coordinates: C::Value,
+        lod: C::Value,
+        result: C::Value,
+    ) -> Result<(), Error> {
+        builder.build_call(
+            self.sample_explicit_lod.as_value(),
+            &[image, sampler, coordinates, lod, result],
+        )?;
+        Ok(())
+    }
+    /// lowering for `OpImageFetch`
+    pub fn build_fetch(
+        &self,
+        builder: &C::AttachedBuilder,
+        image: C::Value,
+        coordinates: C::Value,
+        lod: C::Value,
+        result: C::Value,
+    ) -> Result<(), Error> {
+        builder.build_call(self.fetch.as_value(), &[image, coordinates, lod, result])?;
+        Ok(())
+    }
+    /// lowering for `OpImageTexelPointer`; returns a `*mut u8`
+    pub fn build_texel_pointer(
+        &self,
+        builder: &C::AttachedBuilder,
+        image: C::Value,
+        coordinates: C::Value,
+        sample: C::Value,
+    ) -> Result<C::Value, Error> {
+        builder.build_call(self.texel_pointer.as_value(), &[image, coordinates, sample])
+    }
+}
+
+/// how `ImageTexelPointer::build` gets the values of its operands
+pub trait ImageTexelPointerOperands<'a, C: Context<'a>> {
+    /// the `ImageHandle` of the image in the image variable `image`
+    fn get_image(&self, image: Id) -> Result<C::Value, Error>;
+    /// a pointer to an `[i32; 4]` holding the integer coordinates `coordinate`, padded with zeros
+    fn get_coordinates(&self, coordinate: Id) -> Result<C::Value, Error>;
+    fn get_value(&self, id: Id) -> Result<C::Value, Error>;
+    fn get_type(&self, id: Id) -> Result<C::Type, Error>;
+}
+
+/// an `OpImageTexelPointer`, which gives the address of a storage image texel for atomics
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ImageTexelPointer {
+    pub result_type: Id,
+    pub result: Id,
+    /// a pointer to the image variable, not a loaded image
+    pub image: Id,
+    pub coordinate: Id,
+    pub sample: Id,
+}
+
+impl ImageTexelPointer {
+    /// returns `None` if `instruction` isn't an `OpImageTexelPointer`
+    pub fn parse(instruction: &Instruction) -> Result<Option<Self>, spirv::Error> {
+        if instruction.opcode != op::IMAGE_TEXEL_POINTER {
+            return Ok(None);
+        }
+        Ok(Some(ImageTexelPointer {
+            result_type: instruction.operand(0)?,
+            result: instruction.operand(1)?,
+            image: instruction.operand(2)?,
+            coordinate: instruction.operand(3)?,
+            sample: instruction.operand(4)?,
+        }))
+    }
+    /// the result has the result type, a pointer to the texel type in the `Image` storage class,
+    /// so `OpAtomic*` instructions can use it like any other pointer
+    pub fn build<'a, C: Context<'a>, O: ImageTexelPointerOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        intrinsics: &DeclaredImageIntrinsics<'a, C>,
+        operands: &O,
+    ) -> Result<C::Value, Error> {
+        let texel_pointer = intrinsics.build_texel_pointer(
+            builder,
+            operands.get_image(self.image)?,
+            operands.get_coordinates(self.coordinate)?,
+            operands.get_value(self.sample)?,
+        )?;
+        Ok(builder.build_bitcast(texel_pointer, operands.get_type(self.result_type)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::{write_instruction, Instructions, MAGIC_NUMBER};
+
+    #[test]
+    fn test_host_functions() {
+        unsafe extern "C" fn sample_implicit_lod(
+            _: ImageHandle,
+            _: SamplerHandle,
+            _: *const [f32; 4],
+            _: *const [[f32; 4]; 2],
+            _: f32,
+            _: *mut [f32; 4],
+        ) {
+        }
+        unsafe extern "C" fn sample_explicit_lod(
+            _: ImageHandle,
+            _: SamplerHandle,
+            _: *const [f32; 4],
+            _: f32,
+            _: *mut [f32; 4],
+        ) {
+        }
+        unsafe extern "C" fn fetch(_: ImageHandle, _: *const [i32; 4], _: i32, _: *mut [f32; 4]) {}
+        unsafe extern "C" fn texel_pointer(_: ImageHandle, _: *const [i32; 4], _: i32) -> *mut u8 {
+            ::std::ptr::null_mut()
+        }
+        let intrinsics = ImageIntrinsics {
+            sample_implicit_lod,
+            sample_explicit_lod,
+            fetch,
+            texel_pointer,
+        };
+        assert_eq!(
+            intrinsics.get_host_functions(),
+            [
+                (
+                    "kazan_image_sample_implicit_lod",
+                    sample_implicit_lod as SampleImplicitLodFn as usize
+                ),
+                (
+                    "kazan_image_sample_explicit_lod",
+                    sample_explicit_lod as SampleExplicitLodFn as usize
+                ),
+                ("kazan_image_fetch", fetch as FetchFn as usize),
+                (
+                    "kazan_image_texel_pointer",
+                    texel_pointer as TexelPointerFn as usize
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_texel_pointer() {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        write_instruction(&mut code, op::IMAGE_TEXEL_POINTER, &[1, 2, 3, 4, 5]);
+        write_instruction(&mut code, op::IMAGE_READ, &[6, 7, 8, 9]);
+        let parsed: Vec<Option<ImageTexelPointer>> = Instructions::new(&code)
+            .unwrap()
+            .map(|instruction| ImageTexelPointer::parse(&instruction.unwrap()).unwrap())
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                Some(ImageTexelPointer {
+                    result_type: 1,
+                    result: 2,
+                    image: 3,
+                    coordinate: 4,
+                    sample: 5,
+                }),
+                None,
+            ]
+        );
+    }
+}
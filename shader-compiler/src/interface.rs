@@ -0,0 +1,1053 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! entry point interface reflection and dead interface variable elimination
+
+use non_semantic::NonSemanticSets;
+use spirv::{
+    self, decoration, is_annotation, op, ExecutionModel, Id, Instruction, Instructions,
+    StorageClass,
+};
+use std::collections::{HashMap, HashSet};
+use varyings::{ComponentType, Interpolation, VaryingFormat};
+
+/// an `Input` or `Output` variable that's used by an entry point
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InterfaceVariable {
+    pub id: Id,
+    pub storage_class: StorageClass,
+    pub location: Option<u32>,
+    pub component: Option<u32>,
+    pub built_in: Option<u32>,
+    /// `None` for structs and other types that can't be varyings.
+    /// includes the outer per-vertex array of tessellation and geometry shader inputs
+    pub format: Option<VaryingFormat>,
+    /// always `Flat` for integer and 64-bit variables, even if they aren't decorated with `Flat`
+    pub interpolation: Interpolation,
+}
+
+/// a descriptor binding that's used by an entry point
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct DescriptorBinding {
+    pub descriptor_set: u32,
+    pub binding: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct EntryPointInterface {
+    pub name: String,
+    pub execution_model: ExecutionModel,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    /// sorted and deduplicated
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    pub uses_push_constants: bool,
+    /// the modes from the entry point's `OpExecutionMode` instructions, without their operands
+    pub execution_modes: Vec<u32>,
+    /// a function reachable from the entry point has `OpKill`
+    pub may_discard: bool,
+    /// a function reachable from the entry point can write memory that outlives the invocation,
+    /// such as storage buffers and images. determined conservatively: stores through pointers
+    /// that don't come from `Output`, `Private`, `Workgroup` or `Function` variables count
+    pub writes_memory: bool,
+}
+
+impl EntryPointInterface {
+    pub fn has_execution_mode(&self, execution_mode: u32) -> bool {
+        self.execution_modes.contains(&execution_mode)
+    }
+    pub fn input_locations<'a>(&'a self) -> impl Iterator<Item = u32> + 'a {
+        self.inputs.iter().filter_map(|v| v.location)
+    }
+    pub fn uses_descriptor_binding(&self, descriptor_set: u32, binding: u32) -> bool {
+        self.descriptor_bindings
+            .binary_search(&DescriptorBinding {
+                descriptor_set,
+                binding,
+            })
+            .is_ok()
+    }
+}
+
+/// the result of `trim_interface`
+#[derive(Clone, Debug)]
+pub struct TrimmedShader {
+    /// the SPIR-V module with only the selected entry point and what it uses
+    pub code: Vec<u32>,
+    pub interface: EntryPointInterface,
+    /// ids of the global variables that were removed
+    pub removed_variables: Vec<Id>,
+}
+
+struct EntryPoint {
+    offset: usize,
+    execution_model: ExecutionModel,
+    function: Id,
+    name: String,
+    interface: Vec<Id>,
+}
+
+struct GlobalVariable {
+    pointer_type: Id,
+    storage_class: StorageClass,
+}
+
+enum VaryingType {
+    Scalar {
+        component_type: ComponentType,
+        width: u32,
+    },
+    Vector {
+        component: Id,
+        count: u32,
+    },
+    Matrix {
+        column: Id,
+        count: u32,
+    },
+    Array {
+        element: Id,
+        length: Id,
+    },
+}
+
+/// the types needed to find the format of interface variables
+#[derive(Default)]
+struct VaryingTypes {
+    types: HashMap<Id, VaryingType>,
+    /// maps pointer types to their pointee types
+    pointer_types: HashMap<Id, Id>,
+    /// 32-bit integer constants, for array lengths
+    constants: HashMap<Id, u32>,
+}
+
+impl VaryingTypes {
+    /// record `instruction` if it's a type or constant
+    fn add(&mut self, instruction: &Instruction) -> Result<(), spirv::Error> {
+        let ty = match instruction.opcode {
+            op::TYPE_INT => VaryingType::Scalar {
+                component_type: if instruction.operand(2)? != 0 {
+                    ComponentType::SignedInt
+                } else {
+                    ComponentType::UnsignedInt
+                },
+                width: instruction.operand(1)?,
+            },
+            op::TYPE_FLOAT => VaryingType::Scalar {
+                component_type: ComponentType::Float,
+                width: instruction.operand(1)?,
+            },
+            op::TYPE_VECTOR => VaryingType::Vector {
+                component: instruction.operand(1)?,
+                count: instruction.operand(2)?,
+            },
+            op::TYPE_MATRIX => VaryingType::Matrix {
+                column: instruction.operand(1)?,
+                count: instruction.operand(2)?,
+            },
+            op::TYPE_ARRAY => VaryingType::Array {
+                element: instruction.operand(1)?,
+                length: instruction.operand(2)?,
+            },
+            op::TYPE_POINTER => {
+                self.pointer_types
+                    .insert(instruction.operand(0)?, instruction.operand(2)?);
+                return Ok(());
+            }
+            op::CONSTANT if instruction.operands().len() == 3 => {
+                self.constants
+                    .insert(instruction.operand(1)?, instruction.operand(2)?);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        self.types.insert(instruction.operand(0)?, ty);
+        Ok(())
+    }
+    fn get_format(&self, ty: Id) -> Option<VaryingFormat> {
+        match *self.types.get(&ty)? {
+            VaryingType::Scalar {
+                component_type,
+                width,
+            } => Some(VaryingFormat {
+                component_type,
+                width,
+                component_count: 1,
+                element_count: 1,
+            }),
+            VaryingType::Vector { component, count } => {
+                let format = self.get_format(component)?;
+                if format.component_count != 1 || format.element_count != 1 {
+                    return None;
+                }
+                Some(VaryingFormat {
+                    component_count: count,
+                    ..format
+                })
+            }
+            VaryingType::Matrix { column, count } => {
+                let format = self.get_format(column)?;
+                Some(VaryingFormat {
+                    element_count: format.element_count * count,
+                    ..format
+                })
+            }
+            VaryingType::Array { element, length } => {
+                let format = self.get_format(element)?;
+                Some(VaryingFormat {
+                    element_count: format.element_count * self.constants.get(&length)?,
+                    ..format
+                })
+            }
+        }
+    }
+    fn get_variable_format(&self, variable: &GlobalVariable) -> Option<VaryingFormat> {
+        self.get_format(*self.pointer_types.get(&variable.pointer_type)?)
+    }
+}
+
+/// opcodes that can appear in a function body and don't have a result id
+fn has_no_result(opcode: u16) -> bool {
+    match opcode {
+        0 // OpNop
+        | 8 // OpLine
+        | op::FUNCTION_END
+        | op::STORE
+        | 63 // OpCopyMemory
+        | 64 // OpCopyMemorySized
+        | 99 // OpImageWrite
+        | 218..=221 // OpEmitVertex .. OpEndStreamPrimitive
+        | 224 // OpControlBarrier
+        | 225 // OpMemoryBarrier
+        | 228 // OpAtomicStore
+        | 246 // OpLoopMerge
+        | 247 // OpSelectionMerge
+        | 249..=257 // OpBranch .. OpLifetimeStop
+        | 297 // OpRetainEvent
+        | 298 // OpReleaseEvent
+        | 317 // OpNoLine
+        | 318 // OpAtomicFlagClear
+        => true,
+        _ => false,
+    }
+}
+
+/// the id defined by an instruction in a function body
+pub fn function_body_result_id(instruction: &Instruction) -> Option<Id> {
+    const OP_LABEL: u16 = 248;
+    if instruction.opcode == OP_LABEL {
+        instruction.operands().get(0).cloned()
+    } else if has_no_result(instruction.opcode) {
+        None
+    } else {
+        instruction.operands().get(1).cloned()
+    }
+}
+
+#[derive(Default)]
+struct Decorations {
+    decorations: HashMap<Id, Vec<(u32, Vec<u32>)>>,
+}
+
+impl Decorations {
+    fn add(&mut self, target: Id, decoration: u32, literals: &[u32]) {
+        self.decorations
+            .entry(target)
+            .or_insert_with(Vec::new)
+            .push((decoration, literals.to_vec()));
+    }
+    fn copy_group(&mut self, group: Id, target: Id) {
+        let group_decorations = match self.decorations.get(&group) {
+            Some(v) => v.clone(),
+            None => return,
+        };
+        self.decorations
+            .entry(target)
+            .or_insert_with(Vec::new)
+            .extend(group_decorations);
+    }
+    fn has(&self, target: Id, decoration: u32) -> bool {
+        self.decorations
+            .get(&target)
+            .map_or(false, |v| v.iter().any(|v| v.0 == decoration))
+    }
+    fn get(&self, target: Id, decoration: u32) -> Option<u32> {
+        self.decorations
+            .get(&target)?
+            .iter()
+            .find(|v| v.0 == decoration)
+            .and_then(|v| v.1.get(0).cloned())
+    }
+}
+
+/// the execution model and name of every entry point in `code`
+pub fn get_entry_points(code: &[u32]) -> Result<Vec<(ExecutionModel, String)>, spirv::Error> {
+    let mut entry_points = Vec::new();
+    for instruction in Instructions::new(code)? {
+        let instruction = instruction?;
+        match instruction.opcode {
+            op::ENTRY_POINT => {
+                let execution_model = ExecutionModel::from_word(instruction.operand(0)?).ok_or(
+                    spirv::Error::InvalidOperands {
+                        offset: instruction.offset,
+                        opcode: instruction.opcode,
+                    },
+                )?;
+                entry_points.push((execution_model, instruction.literal_string(2)?.0));
+            }
+            // entry points are declared before all functions
+            op::FUNCTION => break,
+            _ => {}
+        }
+    }
+    Ok(entry_points)
+}
+
+/// remove everything from `code` that isn't used by the entry point named `entry_point_name`
+/// with the execution model `execution_model`, returning the trimmed module and its interface.
+///
+/// variable usage is determined conservatively: a global variable is kept if its id appears
+/// anywhere in a function reachable from the entry point. instructions from non-semantic sets
+/// don't count as uses; they're removed if they reference anything that's removed.
+pub fn trim_interface(
+    code: &[u32],
+    entry_point_name: &str,
+    execution_model: ExecutionModel,
+) -> Result<TrimmedShader, spirv::Error> {
+    let mut entry_points = Vec::new();
+    let mut global_variables = HashMap::new();
+    // maps function ids to the functions they call
+    let mut functions = HashMap::new();
+    let mut decorations = Decorations::default();
+    let mut current_function: Option<(Id, Vec<Id>)> = None;
+    let mut global_references = HashSet::new();
+    let mut varying_types = VaryingTypes::default();
+    let mut non_semantic_sets = NonSemanticSets::default();
+    for instruction in Instructions::new(code)? {
+        let instruction = instruction?;
+        non_semantic_sets.add(&instruction)?;
+        if let Some((_, callees)) = &mut current_function {
+            match instruction.opcode {
+                op::FUNCTION_CALL => callees.push(instruction.operand(2)?),
+                op::FUNCTION_END => {
+                    if let Some((id, callees)) = current_function.take() {
+                        functions.insert(id, callees);
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        varying_types.add(&instruction)?;
+        match instruction.opcode {
+            op::ENTRY_POINT => {
+                let execution_model = ExecutionModel::from_word(instruction.operand(0)?).ok_or(
+                    spirv::Error::InvalidOperands {
+                        offset: instruction.offset,
+                        opcode: instruction.opcode,
+                    },
+                )?;
+                let (name, interface_start) = instruction.literal_string(2)?;
+                entry_points.push(EntryPoint {
+                    offset: instruction.offset,
+                    execution_model,
+                    function: instruction.operand(1)?,
+                    name,
+                    interface: instruction.operands()[interface_start..].to_vec(),
+                });
+            }
+            op::FUNCTION => {
+                current_function = Some((instruction.operand(1)?, Vec::new()));
+            }
+            op::VARIABLE => {
+                let storage_class = StorageClass::from_word(instruction.operand(2)?).ok_or(
+                    spirv::Error::InvalidOperands {
+                        offset: instruction.offset,
+                        opcode: instruction.opcode,
+                    },
+                )?;
+                global_variables.insert(
+                    instruction.operand(1)?,
+                    GlobalVariable {
+                        pointer_type: instruction.operand(0)?,
+                        storage_class,
+                    },
+                );
+                // initializers can reference other variables
+                global_references.extend(instruction.operands().iter().skip(3).cloned());
+            }
+            op::DECORATE => {
+                let operands = instruction.operands();
+                decorations.add(instruction.operand(0)?, instruction.operand(1)?, &operands[2..]);
+            }
+            op::GROUP_DECORATE => {
+                let group = instruction.operand(0)?;
+                for &target in &instruction.operands()[1..] {
+                    decorations.copy_group(group, target);
+                }
+            }
+            opcode if is_annotation(opcode) => {}
+            op::EXECUTION_MODE | 331 /* OpExecutionModeId */ => {}
+            _ if non_semantic_sets.is_non_semantic(&instruction)? => {}
+            _ => global_references.extend(instruction.operands().iter().cloned()),
+        }
+    }
+    if current_function.is_some() {
+        return Err(spirv::Error::InvalidInstructionLength { offset: code.len() });
+    }
+    let entry_point = entry_points
+        .iter()
+        .find(|v| v.name == entry_point_name && v.execution_model == execution_model)
+        .ok_or_else(|| spirv::Error::EntryPointNotFound {
+            name: entry_point_name.into(),
+        })?;
+    let mut reachable_functions = HashSet::new();
+    let mut worklist = vec![entry_point.function];
+    while let Some(function_id) = worklist.pop() {
+        if !reachable_functions.insert(function_id) {
+            continue;
+        }
+        if let Some(callees) = functions.get(&function_id) {
+            worklist.extend(callees.iter().cloned());
+        }
+    }
+    let mut used_variables = HashSet::new();
+    let mut dead_ids = HashSet::new();
+    let mut current_function = None;
+    // pointers into memory that only the invocation can see
+    let mut local_pointers: HashSet<Id> = global_variables
+        .iter()
+        .filter(|(_, variable)| match variable.storage_class {
+            StorageClass::Output
+            | StorageClass::Private
+            | StorageClass::Workgroup
+            | StorageClass::Function => true,
+            _ => false,
+        })
+        .map(|(&id, _)| id)
+        .collect();
+    let mut may_discard = false;
+    let mut writes_memory = false;
+    for instruction in Instructions::new(code)? {
+        let instruction = instruction?;
+        if instruction.opcode == op::FUNCTION {
+            current_function = Some(instruction.operand(1)?);
+        }
+        let function_id = match current_function {
+            Some(v) => v,
+            None => continue,
+        };
+        if instruction.opcode == op::FUNCTION_END {
+            current_function = None;
+        }
+        if reachable_functions.contains(&function_id) {
+            if non_semantic_sets.is_non_semantic(&instruction)? {
+                continue;
+            }
+            used_variables.extend(
+                instruction
+                    .operands()
+                    .iter()
+                    .filter(|v| global_variables.contains_key(v))
+                    .cloned(),
+            );
+            match instruction.opcode {
+                op::KILL => may_discard = true,
+                // variables in functions are always in the `Function` storage class
+                op::VARIABLE => {
+                    local_pointers.insert(instruction.operand(1)?);
+                }
+                op::ACCESS_CHAIN | op::IN_BOUNDS_ACCESS_CHAIN | op::PTR_ACCESS_CHAIN => {
+                    if local_pointers.contains(&instruction.operand(2)?) {
+                        local_pointers.insert(instruction.operand(1)?);
+                    }
+                }
+                op::STORE | op::COPY_MEMORY | op::ATOMIC_STORE => {
+                    writes_memory |= !local_pointers.contains(&instruction.operand(0)?);
+                }
+                op::ATOMIC_EXCHANGE..=op::ATOMIC_XOR => {
+                    writes_memory |= !local_pointers.contains(&instruction.operand(2)?);
+                }
+                op::IMAGE_WRITE => writes_memory = true,
+                _ => {}
+            }
+        } else if let Some(id) = function_body_result_id(&instruction) {
+            dead_ids.insert(id);
+        }
+    }
+    used_variables.extend(
+        global_references
+            .iter()
+            .filter(|v| global_variables.contains_key(v))
+            .cloned(),
+    );
+    let mut removed_variables: Vec<Id> = global_variables
+        .keys()
+        .filter(|v| !used_variables.contains(v))
+        .cloned()
+        .collect();
+    removed_variables.sort();
+    dead_ids.extend(removed_variables.iter().cloned());
+    for function_id in functions.keys() {
+        if !reachable_functions.contains(function_id) {
+            dead_ids.insert(*function_id);
+        }
+    }
+    let mut output = code[..spirv::HEADER_WORD_COUNT].to_vec();
+    let mut operands = Vec::new();
+    let mut execution_modes = Vec::new();
+    for instruction in Instructions::new(code)? {
+        let instruction = instruction?;
+        if instruction.opcode == op::FUNCTION {
+            current_function = Some(instruction.operand(1)?);
+        }
+        if let Some(function_id) = current_function {
+            if instruction.opcode == op::FUNCTION_END {
+                current_function = None;
+            }
+            if !reachable_functions.contains(&function_id) {
+                continue;
+            }
+        }
+        if non_semantic_sets.is_non_semantic(&instruction)?
+            && instruction.operands().iter().any(|v| dead_ids.contains(v))
+        {
+            // instructions that use its result are removed too
+            dead_ids.insert(instruction.operand(1)?);
+            continue;
+        }
+        match instruction.opcode {
+            op::ENTRY_POINT => {
+                if instruction.offset != entry_point.offset {
+                    continue;
+                }
+                let (_, interface_start) = instruction.literal_string(2)?;
+                operands.clear();
+                operands.extend_from_slice(&instruction.operands()[..interface_start]);
+                operands.extend(
+                    entry_point
+                        .interface
+                        .iter()
+                        .filter(|v| !dead_ids.contains(v)),
+                );
+                spirv::write_instruction(&mut output, instruction.opcode, &operands);
+                continue;
+            }
+            op::GROUP_DECORATE => {
+                operands.clear();
+                operands.push(instruction.operand(0)?);
+                operands.extend(
+                    instruction.operands()[1..]
+                        .iter()
+                        .filter(|v| !dead_ids.contains(v)),
+                );
+                if operands.len() > 1 {
+                    spirv::write_instruction(&mut output, instruction.opcode, &operands);
+                }
+                continue;
+            }
+            op::GROUP_MEMBER_DECORATE => {
+                operands.clear();
+                operands.push(instruction.operand(0)?);
+                for pair in instruction.operands()[1..].chunks(2) {
+                    if !dead_ids.contains(&pair[0]) {
+                        operands.extend_from_slice(pair);
+                    }
+                }
+                if operands.len() > 1 {
+                    spirv::write_instruction(&mut output, instruction.opcode, &operands);
+                }
+                continue;
+            }
+            op::EXECUTION_MODE | 331 /* OpExecutionModeId */ => {
+                if instruction.operand(0)? != entry_point.function {
+                    continue;
+                }
+                execution_modes.push(instruction.operand(1)?);
+            }
+            op::VARIABLE if dead_ids.contains(&instruction.operand(1)?) => continue,
+            opcode if is_annotation(opcode) && dead_ids.contains(&instruction.operand(0)?) => {
+                continue
+            }
+            _ => {}
+        }
+        output.extend_from_slice(instruction.words);
+    }
+    let mut inputs = Vec::new();
+    let mut outputs = Vec::new();
+    for &id in &entry_point.interface {
+        if dead_ids.contains(&id) {
+            continue;
+        }
+        let global_variable = match global_variables.get(&id) {
+            Some(v) => v,
+            None => continue,
+        };
+        let storage_class = global_variable.storage_class;
+        let format = varying_types.get_variable_format(global_variable);
+        let interpolation = if decorations.has(id, decoration::FLAT)
+            || format.map_or(false, |format| format.requires_flat())
+        {
+            Interpolation::Flat
+        } else if decorations.has(id, decoration::NO_PERSPECTIVE) {
+            Interpolation::NoPerspective
+        } else {
+            Interpolation::Smooth
+        };
+        let variable = InterfaceVariable {
+            id,
+            storage_class,
+            location: decorations.get(id, decoration::LOCATION),
+            component: decorations.get(id, decoration::COMPONENT),
+            built_in: decorations.get(id, decoration::BUILT_IN),
+            format,
+            interpolation,
+        };
+        match storage_class {
+            StorageClass::Input => inputs.push(variable),
+            StorageClass::Output => outputs.push(variable),
+            _ => {}
+        }
+    }
+    let mut descriptor_bindings = Vec::new();
+    let mut uses_push_constants = false;
+    for (&id, variable) in &global_variables {
+        if dead_ids.contains(&id) {
+            continue;
+        }
+        if variable.storage_class == StorageClass::PushConstant {
+            uses_push_constants = true;
+        }
+        if !variable.storage_class.is_descriptor_backed() {
+            continue;
+        }
+        if let (Some(descriptor_set), Some(binding)) = (
+            decorations.get(id, decoration::DESCRIPTOR_SET),
+            decorations.get(id, decoration::BINDING),
+        ) {
+            descriptor_bindings.push(DescriptorBinding {
+                descriptor_set,
+                binding,
+            });
+        }
+    }
+    descriptor_bindings.sort();
+    descriptor_bindings.dedup();
+    Ok(TrimmedShader {
+        code: output,
+        interface: EntryPointInterface {
+            name: entry_point.name.clone(),
+            execution_model: entry_point.execution_model,
+            inputs,
+            outputs,
+            descriptor_bindings,
+            uses_push_constants,
+            execution_modes,
+            may_discard,
+            writes_memory,
+        },
+        removed_variables,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::{execution_mode, write_instruction};
+
+    const OP_TYPE_VOID: u16 = 19;
+    const OP_TYPE_FLOAT: u16 = 22;
+    const OP_TYPE_FUNCTION: u16 = 33;
+    const OP_LABEL: u16 = 248;
+    const OP_RETURN: u16 = 253;
+
+    fn entry_point(
+        output: &mut Vec<u32>,
+        model: ExecutionModel,
+        function: Id,
+        name: &str,
+        interface: &[Id],
+    ) {
+        let mut operands = vec![model as u32, function];
+        spirv::write_literal_string(&mut operands, name);
+        operands.extend_from_slice(interface);
+        write_instruction(output, op::ENTRY_POINT, &operands);
+    }
+
+    fn function(output: &mut Vec<u32>, id: Id, label: Id, body: &[(u16, Vec<u32>)]) {
+        write_instruction(output, op::FUNCTION, &[1, id, 0, 3]);
+        write_instruction(output, OP_LABEL, &[label]);
+        for (opcode, operands) in body {
+            write_instruction(output, *opcode, operands);
+        }
+        write_instruction(output, OP_RETURN, &[]);
+        write_instruction(output, op::FUNCTION_END, &[]);
+    }
+
+    /// ids:
+    /// 1: void, 2: float, 3: fn void(), 4: ptr input float, 5: ptr output float, 6: ptr uniform float
+    /// 10: vertex main, 11: vertex other, 12: helper called by main
+    /// 20: input loc 0 (used), 21: input loc 1 (only used by other), 22: output loc 0 (used)
+    /// 23: uniform set 0 binding 0 (used by helper), 24: uniform set 0 binding 1 (unused)
+    /// 30..: labels and temporaries
+    fn test_module() -> Vec<u32> {
+        let mut code = vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        entry_point(&mut code, ExecutionModel::Vertex, 10, "main", &[20, 21, 22]);
+        entry_point(&mut code, ExecutionModel::Vertex, 11, "other", &[21, 22]);
+        let mut name = vec![21];
+        spirv::write_literal_string(&mut name, "unused_input");
+        write_instruction(&mut code, op::NAME, &name);
+        write_instruction(&mut code, op::DECORATE, &[20, decoration::LOCATION, 0]);
+        write_instruction(&mut code, op::DECORATE, &[21, decoration::LOCATION, 1]);
+        write_instruction(&mut code, op::DECORATE, &[22, decoration::LOCATION, 0]);
+        write_instruction(
+            &mut code,
+            op::DECORATE,
+            &[23, decoration::DESCRIPTOR_SET, 0],
+        );
+        write_instruction(&mut code, op::DECORATE, &[23, decoration::BINDING, 0]);
+        write_instruction(
+            &mut code,
+            op::DECORATE,
+            &[24, decoration::DESCRIPTOR_SET, 0],
+        );
+        write_instruction(&mut code, op::DECORATE, &[24, decoration::BINDING, 1]);
+        write_instruction(
+            &mut code,
+            op::DECORATE,
+            &[41, decoration::RELAXED_PRECISION],
+        );
+        write_instruction(&mut code, OP_TYPE_VOID, &[1]);
+        write_instruction(&mut code, OP_TYPE_FLOAT, &[2, 32]);
+        write_instruction(&mut code, OP_TYPE_FUNCTION, &[3, 1]);
+        write_instruction(
+            &mut code,
+            op::TYPE_POINTER,
+            &[4, StorageClass::Input as u32, 2],
+        );
+        write_instruction(
+            &mut code,
+            op::TYPE_POINTER,
+            &[5, StorageClass::Output as u32, 2],
+        );
+        write_instruction(
+            &mut code,
+            op::TYPE_POINTER,
+            &[6, StorageClass::Uniform as u32, 2],
+        );
+        write_instruction(
+            &mut code,
+            op::VARIABLE,
+            &[4, 20, StorageClass::Input as u32],
+        );
+        write_instruction(
+            &mut code,
+            op::VARIABLE,
+            &[4, 21, StorageClass::Input as u32],
+        );
+        write_instruction(
+            &mut code,
+            op::VARIABLE,
+            &[5, 22, StorageClass::Output as u32],
+        );
+        write_instruction(
+            &mut code,
+            op::VARIABLE,
+            &[6, 23, StorageClass::Uniform as u32],
+        );
+        write_instruction(
+            &mut code,
+            op::VARIABLE,
+            &[6, 24, StorageClass::Uniform as u32],
+        );
+        function(
+            &mut code,
+            10,
+            30,
+            &[
+                (op::LOAD, vec![2, 31, 20]),
+                (op::STORE, vec![22, 31]),
+                (op::FUNCTION_CALL, vec![1, 32, 12]),
+            ],
+        );
+        function(
+            &mut code,
+            11,
+            40,
+            &[(op::LOAD, vec![2, 41, 21]), (op::STORE, vec![22, 41])],
+        );
+        function(&mut code, 12, 50, &[(op::LOAD, vec![2, 51, 23])]);
+        code
+    }
+
+    fn opcodes(code: &[u32]) -> Vec<(u16, Vec<u32>)> {
+        Instructions::new(code)
+            .unwrap()
+            .map(|v| {
+                let v = v.unwrap();
+                (v.opcode, v.operands().to_vec())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_trim_interface() {
+        let trimmed = trim_interface(&test_module(), "main", ExecutionModel::Vertex).unwrap();
+        assert_eq!(trimmed.removed_variables, [21, 24]);
+        let interface = &trimmed.interface;
+        assert_eq!(interface.input_locations().collect::<Vec<_>>(), [0]);
+        assert_eq!(interface.outputs.len(), 1);
+        assert_eq!(interface.outputs[0].id, 22);
+        assert_eq!(
+            interface.descriptor_bindings,
+            [DescriptorBinding {
+                descriptor_set: 0,
+                binding: 0,
+            }]
+        );
+        assert!(interface.uses_descriptor_binding(0, 0));
+        assert!(!interface.uses_descriptor_binding(0, 1));
+        assert!(!interface.uses_push_constants);
+        let instructions = opcodes(&trimmed.code);
+        let entry_points: Vec<_> = instructions
+            .iter()
+            .filter(|v| v.0 == op::ENTRY_POINT)
+            .collect();
+        assert_eq!(entry_points.len(), 1);
+        assert_eq!(&entry_points[0].1[entry_points[0].1.len() - 2..], [20, 22]);
+        for (opcode, operands) in &instructions {
+            match *opcode {
+                op::VARIABLE => assert!(operands[1] != 21 && operands[1] != 24),
+                op::FUNCTION => assert!(operands[1] != 11),
+                op::NAME | op::DECORATE => {
+                    assert!(operands[0] != 21 && operands[0] != 24 && operands[0] != 41)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_non_semantic_instructions() {
+        let code = test_module();
+        let first_function = Instructions::new(&code)
+            .unwrap()
+            .map(|v| v.unwrap())
+            .find(|v| v.opcode == op::FUNCTION)
+            .unwrap()
+            .offset;
+        let mut module = code[..spirv::HEADER_WORD_COUNT].to_vec();
+        let mut import = vec![60];
+        spirv::write_literal_string(&mut import, "NonSemantic.Shader.DebugInfo.100");
+        write_instruction(&mut module, op::EXT_INST_IMPORT, &import);
+        module.extend_from_slice(&code[spirv::HEADER_WORD_COUNT..first_function]);
+        // 61 references the unused uniform, 62 references 61, 63 references a used input
+        write_instruction(&mut module, op::EXT_INST, &[1, 61, 60, 18, 24]);
+        write_instruction(&mut module, op::EXT_INST, &[1, 62, 60, 1, 61]);
+        write_instruction(&mut module, op::EXT_INST, &[1, 63, 60, 18, 20]);
+        module.extend_from_slice(&code[first_function..]);
+        let trimmed = trim_interface(&module, "main", ExecutionModel::Vertex).unwrap();
+        assert_eq!(trimmed.removed_variables, [21, 24]);
+        let ext_insts: Vec<_> = opcodes(&trimmed.code)
+            .into_iter()
+            .filter(|v| v.0 == op::EXT_INST)
+            .map(|v| v.1[1])
+            .collect();
+        assert_eq!(ext_insts, [63]);
+    }
+
+    #[test]
+    fn test_trim_interface_is_idempotent() {
+        let trimmed = trim_interface(&test_module(), "main", ExecutionModel::Vertex).unwrap();
+        let trimmed_again = trim_interface(&trimmed.code, "main", ExecutionModel::Vertex).unwrap();
+        assert_eq!(trimmed.code, trimmed_again.code);
+        assert!(trimmed_again.removed_variables.is_empty());
+    }
+
+    #[test]
+    fn test_varying_formats() {
+        // 1: void, 2: float, 3: fn void(), 4: int, 5: vec4, 6: double, 7: dvec3,
+        // 8: constant 2, 9: dvec3[2]
+        // 10: ptr input vec4, 11: ptr input int, 12: ptr input dvec3[2], 13: fragment main
+        // 20: input vec4, 21: input int, 22: input dvec3[2]
+        let mut code = vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        entry_point(
+            &mut code,
+            ExecutionModel::Fragment,
+            13,
+            "main",
+            &[20, 21, 22],
+        );
+        write_instruction(&mut code, op::DECORATE, &[20, decoration::LOCATION, 0]);
+        write_instruction(&mut code, op::DECORATE, &[20, decoration::NO_PERSPECTIVE]);
+        write_instruction(&mut code, op::DECORATE, &[21, decoration::LOCATION, 1]);
+        write_instruction(&mut code, op::DECORATE, &[22, decoration::LOCATION, 2]);
+        write_instruction(&mut code, OP_TYPE_VOID, &[1]);
+        write_instruction(&mut code, OP_TYPE_FLOAT, &[2, 32]);
+        write_instruction(&mut code, OP_TYPE_FUNCTION, &[3, 1]);
+        write_instruction(&mut code, op::TYPE_INT, &[4, 32, 1]);
+        write_instruction(&mut code, op::TYPE_VECTOR, &[5, 2, 4]);
+        write_instruction(&mut code, OP_TYPE_FLOAT, &[6, 64]);
+        write_instruction(&mut code, op::TYPE_VECTOR, &[7, 6, 3]);
+        write_instruction(&mut code, op::CONSTANT, &[4, 8, 2]);
+        write_instruction(&mut code, op::TYPE_ARRAY, &[9, 7, 8]);
+        for &(pointer_type, pointee_type, variable) in &[(10, 5, 20), (11, 4, 21), (12, 9, 22)] {
+            write_instruction(
+                &mut code,
+                op::TYPE_POINTER,
+                &[pointer_type, StorageClass::Input as u32, pointee_type],
+            );
+            write_instruction(
+                &mut code,
+                op::VARIABLE,
+                &[pointer_type, variable, StorageClass::Input as u32],
+            );
+        }
+        function(
+            &mut code,
+            13,
+            30,
+            &[
+                (op::LOAD, vec![5, 31, 20]),
+                (op::LOAD, vec![4, 32, 21]),
+                (op::LOAD, vec![9, 33, 22]),
+            ],
+        );
+        let trimmed = trim_interface(&code, "main", ExecutionModel::Fragment).unwrap();
+        let inputs = &trimmed.interface.inputs;
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(
+            inputs[0].format,
+            Some(VaryingFormat {
+                component_type: ComponentType::Float,
+                width: 32,
+                component_count: 4,
+                element_count: 1,
+            })
+        );
+        assert_eq!(inputs[0].interpolation, Interpolation::NoPerspective);
+        assert_eq!(
+            inputs[1].format.map(|v| v.component_type),
+            Some(ComponentType::SignedInt)
+        );
+        // integers are always flat
+        assert_eq!(inputs[1].interpolation, Interpolation::Flat);
+        let double_format = inputs[2].format.unwrap();
+        assert_eq!(double_format.element_count, 2);
+        assert_eq!(double_format.location_count(), 4);
+        assert_eq!(inputs[2].interpolation, Interpolation::Flat);
+    }
+
+    #[test]
+    fn test_execution_modes_and_side_effects() {
+        // 1: void, 2: float, 3: fn void(), 4: ptr output float, 5: ptr storage buffer float,
+        // 6: ptr function float, 10: fragment main, 11: other fragment entry point,
+        // 20: output, 21: storage buffer, 30..: labels and temporaries
+        let mut code = vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        entry_point(&mut code, ExecutionModel::Fragment, 10, "main", &[20]);
+        entry_point(&mut code, ExecutionModel::Fragment, 11, "other", &[20]);
+        write_instruction(
+            &mut code,
+            op::EXECUTION_MODE,
+            &[10, execution_mode::EARLY_FRAGMENT_TESTS],
+        );
+        write_instruction(
+            &mut code,
+            op::EXECUTION_MODE,
+            &[11, execution_mode::DEPTH_GREATER],
+        );
+        write_instruction(&mut code, OP_TYPE_VOID, &[1]);
+        write_instruction(&mut code, OP_TYPE_FLOAT, &[2, 32]);
+        write_instruction(&mut code, OP_TYPE_FUNCTION, &[3, 1]);
+        for &(pointer_type, storage_class) in &[
+            (4, StorageClass::Output),
+            (5, StorageClass::StorageBuffer),
+            (6, StorageClass::Function),
+        ] {
+            write_instruction(
+                &mut code,
+                op::TYPE_POINTER,
+                &[pointer_type, storage_class as u32, 2],
+            );
+        }
+        write_instruction(
+            &mut code,
+            op::VARIABLE,
+            &[4, 20, StorageClass::Output as u32],
+        );
+        write_instruction(
+            &mut code,
+            op::VARIABLE,
+            &[5, 21, StorageClass::StorageBuffer as u32],
+        );
+        function(
+            &mut code,
+            10,
+            30,
+            &[
+                (op::VARIABLE, vec![6, 31, StorageClass::Function as u32]),
+                (op::LOAD, vec![2, 32, 21]),
+                (op::STORE, vec![31, 32]),
+                (op::STORE, vec![20, 32]),
+                (op::STORE, vec![21, 32]),
+            ],
+        );
+        function(
+            &mut code,
+            11,
+            40,
+            &[
+                (op::LOAD, vec![2, 41, 21]),
+                (op::STORE, vec![20, 41]),
+                (op::KILL, vec![]),
+            ],
+        );
+        let main = trim_interface(&code, "main", ExecutionModel::Fragment)
+            .unwrap()
+            .interface;
+        assert_eq!(main.execution_modes, [execution_mode::EARLY_FRAGMENT_TESTS]);
+        assert!(!main.may_discard);
+        assert!(main.writes_memory);
+        let other = trim_interface(&code, "other", ExecutionModel::Fragment)
+            .unwrap()
+            .interface;
+        assert!(other.has_execution_mode(execution_mode::DEPTH_GREATER));
+        assert!(!other.has_execution_mode(execution_mode::EARLY_FRAGMENT_TESTS));
+        assert!(other.may_discard);
+        assert!(!other.writes_memory);
+    }
+
+    #[test]
+    fn test_get_entry_points() {
+        assert_eq!(
+            get_entry_points(&test_module()).unwrap(),
+            [
+                (ExecutionModel::Vertex, "main".to_string()),
+                (ExecutionModel::Vertex, "other".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_entry_point_not_found() {
+        match trim_interface(&test_module(), "main", ExecutionModel::Fragment) {
+            Err(spirv::Error::EntryPointNotFound { .. }) => {}
+            v => panic!("unexpected result: {:?}", v.map(|v| v.interface)),
+        }
+    }
+
+    #[test]
+    fn test_literal_string() {
+        for &s in &["", "a", "main", "main_", "longer name"] {
+            let mut operands = vec![];
+            spirv::write_literal_string(&mut operands, s);
+            operands.push(1234);
+            let mut code = vec![spirv::MAGIC_NUMBER, 0, 0, 0, 0];
+            write_instruction(&mut code, op::NAME, &operands);
+            let instruction = Instructions::new(&code).unwrap().next().unwrap().unwrap();
+            let (parsed, next) = instruction.literal_string(0).unwrap();
+            assert_eq!(parsed, s);
+            assert_eq!(instruction.operand(next).unwrap(), 1234);
+        }
+    }
+}
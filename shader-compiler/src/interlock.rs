@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! fragment shader interlock (`SPV_EXT_fragment_shader_interlock`)
+//!
+//! the code between `OpBeginInvocationInterlockEXT` and `OpEndInvocationInterlockEXT` is a
+//! critical section that can't run at the same time as the critical section of another
+//! invocation for the same pixel or sample, and, for the ordered modes, runs in primitive order.
+//!
+//! the rasterizer shades a tile's fragments on one thread, in packets of 2x2 pixels. it can shade
+//! several packets together, so packets that conflict are split into separate batches by
+//! `split_into_batches`, which keeps them in rasterization order. since no two invocations that
+//! run together conflict, the whole invocation is the critical section, and the interlock
+//! instructions don't generate any code.
+//!
+//! nothing rasterizes yet, so `VK_EXT_fragment_shader_interlock` isn't advertised until the
+//! rasterizer batches packets with `split_into_batches`.
+
+use interface::EntryPointInterface;
+use spirv::{execution_mode, op, Instruction};
+
+/// the pixels in a packet, in row-major order
+pub const PIXELS_PER_PACKET: usize = 4;
+
+/// what the critical sections of two invocations have to share to conflict
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum InterlockGranularity {
+    /// also used for the shading rate modes, since each fragment is a single pixel
+    Pixel,
+    Sample,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct FragmentShaderInterlock {
+    pub granularity: InterlockGranularity,
+    /// critical sections run in primitive order. batches always run in order, so this doesn't
+    /// change the scheduling
+    pub ordered: bool,
+}
+
+impl FragmentShaderInterlock {
+    /// returns `None` if the fragment shader doesn't declare an interlock execution mode
+    pub fn new(interface: &EntryPointInterface) -> Option<Self> {
+        for &mode in &interface.execution_modes {
+            let (granularity, ordered) = match mode {
+                execution_mode::PIXEL_INTERLOCK_ORDERED_EXT
+                | execution_mode::SHADING_RATE_INTERLOCK_ORDERED_EXT => {
+                    (InterlockGranularity::Pixel, true)
+                }
+                execution_mode::PIXEL_INTERLOCK_UNORDERED_EXT
+                | execution_mode::SHADING_RATE_INTERLOCK_UNORDERED_EXT => {
+                    (InterlockGranularity::Pixel, false)
+                }
+                execution_mode::SAMPLE_INTERLOCK_ORDERED_EXT => {
+                    (InterlockGranularity::Sample, true)
+                }
+                execution_mode::SAMPLE_INTERLOCK_UNORDERED_EXT => {
+                    (InterlockGranularity::Sample, false)
+                }
+                _ => continue,
+            };
+            return Some(FragmentShaderInterlock {
+                granularity,
+                ordered,
+            });
+        }
+        None
+    }
+    /// true if `a` and `b` can't be shaded together
+    pub fn conflicts(&self, a: &FragmentPacket, b: &FragmentPacket) -> bool {
+        if (a.x, a.y) != (b.x, b.y) {
+            return false;
+        }
+        a.sample_masks
+            .iter()
+            .zip(b.sample_masks.iter())
+            .any(|(&a, &b)| match self.granularity {
+                InterlockGranularity::Pixel => a != 0 && b != 0,
+                InterlockGranularity::Sample => a & b != 0,
+            })
+    }
+}
+
+/// the fragments of one primitive in a 2x2 block of pixels
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct FragmentPacket {
+    /// the position of the top-left pixel; always even
+    pub x: u32,
+    pub y: u32,
+    /// the covered samples of each pixel; pixels with no samples covered have no fragment
+    pub sample_masks: [u32; PIXELS_PER_PACKET],
+}
+
+/// split `packets`, in rasterization order, into consecutive batches with no conflicting
+/// packets. returns the end index of each batch. without an interlock, everything is one batch
+pub fn split_into_batches(
+    interlock: Option<FragmentShaderInterlock>,
+    packets: &[FragmentPacket],
+) -> Vec<usize> {
+    let mut batch_ends = Vec::new();
+    let interlock = match interlock {
+        Some(interlock) => interlock,
+        None => {
+            if !packets.is_empty() {
+                batch_ends.push(packets.len());
+            }
+            return batch_ends;
+        }
+    };
+    let mut batch_start = 0;
+    for (index, packet) in packets.iter().enumerate() {
+        if packets[batch_start..index]
+            .iter()
+            .any(|other| interlock.conflicts(other, packet))
+        {
+            batch_ends.push(index);
+            batch_start = index;
+        }
+    }
+    if batch_start < packets.len() {
+        batch_ends.push(packets.len());
+    }
+    batch_ends
+}
+
+/// true for `OpBeginInvocationInterlockEXT` and `OpEndInvocationInterlockEXT`, which are lowered
+/// to nothing
+pub fn is_interlock_instruction(instruction: &Instruction) -> bool {
+    match instruction.opcode {
+        op::BEGIN_INVOCATION_INTERLOCK_EXT | op::END_INVOCATION_INTERLOCK_EXT => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(x: u32, sample_masks: [u32; PIXELS_PER_PACKET]) -> FragmentPacket {
+        FragmentPacket {
+            x,
+            y: 0,
+            sample_masks,
+        }
+    }
+
+    #[test]
+    fn test_split_into_batches() {
+        let packets = [
+            packet(0, [0xF, 0, 0, 0]),
+            packet(2, [0xF; 4]),
+            packet(0, [0, 0xF, 0, 0]),
+            packet(0, [0x3, 0, 0, 0]),
+            packet(0, [0xC, 0, 0, 0]),
+            packet(2, [0x1, 0, 0, 0]),
+        ];
+        assert_eq!(split_into_batches(None, &packets), [6]);
+        assert_eq!(split_into_batches(None, &[]), [] as [usize; 0]);
+        let pixel = FragmentShaderInterlock {
+            granularity: InterlockGranularity::Pixel,
+            ordered: true,
+        };
+        assert_eq!(split_into_batches(Some(pixel), &packets), [3, 4, 6]);
+        let sample = FragmentShaderInterlock {
+            granularity: InterlockGranularity::Sample,
+            ordered: false,
+        };
+        assert_eq!(split_into_batches(Some(sample), &packets), [3, 6]);
+    }
+}
@@ -0,0 +1,30 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! Shader Compiler for Kazan
+
+#[macro_use]
+extern crate shader_compiler_backend;
+extern crate spirv_parser;
+
+pub mod abi;
+pub mod atomics;
+pub mod capabilities;
+pub mod cooperative_matrix;
+pub mod debug_info;
+pub mod descriptors;
+pub mod fragment_tests;
+pub mod framebuffer_fetch;
+pub mod image;
+pub mod interface;
+pub mod interlock;
+pub mod link;
+pub mod non_semantic;
+pub mod opencl_std;
+pub mod relaxed_precision;
+pub mod simd_width;
+pub mod spirv;
+pub mod structurize;
+pub mod switch_lowering;
+pub mod varyings;
+pub mod watchdog;
@@ -0,0 +1,908 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! cross-stage interface optimization done when creating a graphics pipeline
+//!
+//! the producer is the last pre-rasterization stage and the consumer is the fragment shader;
+//! both must already be trimmed to a single entry point by `trim_interface`.
+//!
+//! * producer outputs that the consumer never reads are demoted to `Private` variables.
+//! * consumer inputs that the producer always writes the same constant to are replaced by
+//!   `Private` variables initialized to that constant.
+//! * the remaining locations are renumbered so the interface is dense.
+
+use non_semantic::NonSemanticSets;
+use spirv::{self, decoration, is_annotation, op, Id, Instruction, Instructions, StorageClass};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// the result of `link_stages`
+#[derive(Clone, Debug)]
+pub struct LinkedStages {
+    pub producer_code: Vec<u32>,
+    pub consumer_code: Vec<u32>,
+    /// producer outputs that were converted to `Private` variables; sorted
+    pub demoted_outputs: Vec<Id>,
+    /// consumer inputs that were replaced by constants; sorted
+    pub promoted_inputs: Vec<Id>,
+    /// `(old_location, new_location)` for every location that was moved; sorted
+    pub location_remap: Vec<(u32, u32)>,
+}
+
+#[derive(Clone, Debug)]
+enum Type {
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: Id, count: u32 },
+    Matrix { column: Id, count: u32 },
+    Array { element: Id, length: Id },
+    Struct { members: Vec<Id> },
+}
+
+#[derive(Clone, Debug)]
+enum Constant {
+    Scalar { words: Vec<u32> },
+    Composite { constituents: Vec<Id> },
+}
+
+struct Variable {
+    result_type: Id,
+    storage_class: StorageClass,
+}
+
+#[derive(Default)]
+struct VariableUses {
+    /// objects stored directly to the variable
+    stores: Vec<Id>,
+    uses_access_chains: bool,
+    /// used in a way that prevents changing the variable's storage class
+    escapes: bool,
+}
+
+struct DerivedPointer {
+    variable: Id,
+    result_type: Id,
+}
+
+struct InterfaceVariable {
+    id: Id,
+    location: u32,
+    component: u32,
+    /// `None` if the type isn't understood
+    location_count: Option<u32>,
+}
+
+impl InterfaceVariable {
+    fn overlaps(&self, other: &InterfaceVariable) -> bool {
+        let end = |v: &InterfaceVariable| v.location_count.map(|count| v.location + count);
+        end(self).map_or(true, |end| other.location < end)
+            && end(other).map_or(true, |end| self.location < end)
+    }
+}
+
+#[derive(Default)]
+struct Module {
+    bound: u32,
+    interface: Vec<Id>,
+    types: HashMap<Id, Type>,
+    /// maps pointer types to their pointee types
+    pointer_types: HashMap<Id, Id>,
+    /// maps pointee types to `Private` pointer types
+    private_pointer_types: HashMap<Id, Id>,
+    constants: HashMap<Id, Constant>,
+    variables: HashMap<Id, Variable>,
+    locations: HashMap<Id, u32>,
+    components: HashMap<Id, u32>,
+    has_member_locations: bool,
+    uses_decoration_groups: bool,
+    /// ids referenced by global instructions other than annotations and entry points
+    global_references: HashSet<Id>,
+    /// pointers derived from `Input` and `Output` variables through access chains
+    derived_pointers: HashMap<Id, DerivedPointer>,
+    uses: HashMap<Id, VariableUses>,
+    /// instructions from non-semantic sets don't count as uses of variables
+    non_semantic_sets: NonSemanticSets,
+}
+
+fn invalid_operands(instruction: &Instruction) -> spirv::Error {
+    spirv::Error::InvalidOperands {
+        offset: instruction.offset,
+        opcode: instruction.opcode,
+    }
+}
+
+impl Module {
+    fn parse(code: &[u32]) -> Result<Self, spirv::Error> {
+        let mut module = Module {
+            bound: spirv::Header::parse(code)?.bound,
+            ..Default::default()
+        };
+        let mut in_function = false;
+        for instruction in Instructions::new(code)? {
+            let instruction = instruction?;
+            module.non_semantic_sets.add(&instruction)?;
+            if instruction.opcode == op::FUNCTION {
+                in_function = true;
+            }
+            if in_function {
+                if instruction.opcode == op::FUNCTION_END {
+                    in_function = false;
+                }
+                if !module.non_semantic_sets.is_non_semantic(&instruction)? {
+                    module.add_function_instruction(&instruction)?;
+                }
+                continue;
+            }
+            let operands = instruction.operands();
+            match instruction.opcode {
+                op::ENTRY_POINT => {
+                    let (_, interface_start) = instruction.literal_string(2)?;
+                    module.interface = operands[interface_start..].to_vec();
+                }
+                op::TYPE_INT => {
+                    module.types.insert(
+                        instruction.operand(0)?,
+                        Type::Int {
+                            width: instruction.operand(1)?,
+                            signed: instruction.operand(2)? != 0,
+                        },
+                    );
+                }
+                op::TYPE_FLOAT => {
+                    module.types.insert(
+                        instruction.operand(0)?,
+                        Type::Float {
+                            width: instruction.operand(1)?,
+                        },
+                    );
+                }
+                op::TYPE_VECTOR => {
+                    module.types.insert(
+                        instruction.operand(0)?,
+                        Type::Vector {
+                            component: instruction.operand(1)?,
+                            count: instruction.operand(2)?,
+                        },
+                    );
+                }
+                op::TYPE_MATRIX => {
+                    module.types.insert(
+                        instruction.operand(0)?,
+                        Type::Matrix {
+                            column: instruction.operand(1)?,
+                            count: instruction.operand(2)?,
+                        },
+                    );
+                }
+                op::TYPE_ARRAY => {
+                    module.types.insert(
+                        instruction.operand(0)?,
+                        Type::Array {
+                            element: instruction.operand(1)?,
+                            length: instruction.operand(2)?,
+                        },
+                    );
+                }
+                op::TYPE_STRUCT => {
+                    module.types.insert(
+                        instruction.operand(0)?,
+                        Type::Struct {
+                            members: operands[1..].to_vec(),
+                        },
+                    );
+                }
+                op::TYPE_POINTER => {
+                    let pointer_type = instruction.operand(0)?;
+                    let pointee_type = instruction.operand(2)?;
+                    module.pointer_types.insert(pointer_type, pointee_type);
+                    if instruction.operand(1)? == StorageClass::Private as u32 {
+                        module
+                            .private_pointer_types
+                            .entry(pointee_type)
+                            .or_insert(pointer_type);
+                    }
+                }
+                op::CONSTANT => {
+                    module.constants.insert(
+                        instruction.operand(1)?,
+                        Constant::Scalar {
+                            words: operands[2..].to_vec(),
+                        },
+                    );
+                }
+                op::CONSTANT_COMPOSITE => {
+                    module.constants.insert(
+                        instruction.operand(1)?,
+                        Constant::Composite {
+                            constituents: operands[2..].to_vec(),
+                        },
+                    );
+                }
+                op::VARIABLE => {
+                    let storage_class = StorageClass::from_word(instruction.operand(2)?)
+                        .ok_or_else(|| invalid_operands(&instruction))?;
+                    module.variables.insert(
+                        instruction.operand(1)?,
+                        Variable {
+                            result_type: instruction.operand(0)?,
+                            storage_class,
+                        },
+                    );
+                    module
+                        .global_references
+                        .extend(operands.iter().skip(3).cloned());
+                }
+                op::DECORATE => match instruction.operand(1)? {
+                    decoration::LOCATION => {
+                        module
+                            .locations
+                            .insert(instruction.operand(0)?, instruction.operand(2)?);
+                    }
+                    decoration::COMPONENT => {
+                        module
+                            .components
+                            .insert(instruction.operand(0)?, instruction.operand(2)?);
+                    }
+                    _ => {}
+                },
+                op::MEMBER_DECORATE => {
+                    if instruction.operand(2)? == decoration::LOCATION {
+                        module.has_member_locations = true;
+                    }
+                }
+                op::DECORATION_GROUP | op::GROUP_DECORATE | op::GROUP_MEMBER_DECORATE => {
+                    module.uses_decoration_groups = true;
+                }
+                opcode if is_annotation(opcode) => {}
+                _ if module.non_semantic_sets.is_non_semantic(&instruction)? => {}
+                _ => module.global_references.extend(operands.iter().cloned()),
+            }
+        }
+        Ok(module)
+    }
+    fn is_interface_variable(&self, id: Id) -> bool {
+        match self.variables.get(&id) {
+            Some(Variable {
+                storage_class: StorageClass::Input,
+                ..
+            })
+            | Some(Variable {
+                storage_class: StorageClass::Output,
+                ..
+            }) => true,
+            _ => false,
+        }
+    }
+    /// get the `Input` or `Output` variable that `pointer` points into
+    fn get_root_variable(&self, pointer: Id) -> Option<Id> {
+        if self.is_interface_variable(pointer) {
+            Some(pointer)
+        } else {
+            self.derived_pointers.get(&pointer).map(|v| v.variable)
+        }
+    }
+    fn mark_escaped(&mut self, id: Id) {
+        if let Some(variable) = self.get_root_variable(id) {
+            self.uses
+                .entry(variable)
+                .or_insert_with(VariableUses::default)
+                .escapes = true;
+        }
+    }
+    /// variable usage is determined conservatively: any use of a variable other than loads,
+    /// stores, and access chains marks it as escaped
+    fn add_function_instruction(&mut self, instruction: &Instruction) -> Result<(), spirv::Error> {
+        let operands = instruction.operands();
+        match instruction.opcode {
+            op::ACCESS_CHAIN | op::IN_BOUNDS_ACCESS_CHAIN => {
+                if let Some(variable) = self.get_root_variable(instruction.operand(2)?) {
+                    self.derived_pointers.insert(
+                        instruction.operand(1)?,
+                        DerivedPointer {
+                            variable,
+                            result_type: instruction.operand(0)?,
+                        },
+                    );
+                    self.uses
+                        .entry(variable)
+                        .or_insert_with(VariableUses::default)
+                        .uses_access_chains = true;
+                    for &index in &operands[3..] {
+                        self.mark_escaped(index);
+                    }
+                    return Ok(());
+                }
+            }
+            // the remaining operands of loads are literals
+            op::LOAD => return Ok(()),
+            op::STORE => {
+                let pointer = instruction.operand(0)?;
+                let object = instruction.operand(1)?;
+                self.mark_escaped(object);
+                if self.is_interface_variable(pointer) {
+                    self.uses
+                        .entry(pointer)
+                        .or_insert_with(VariableUses::default)
+                        .stores
+                        .push(object);
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        for &id in operands {
+            self.mark_escaped(id);
+        }
+        Ok(())
+    }
+    fn get_variable_type(&self, variable: Id) -> Option<Id> {
+        self.pointer_types
+            .get(&self.variables.get(&variable)?.result_type)
+            .cloned()
+    }
+    fn get_constant_u32(&self, id: Id) -> Option<u32> {
+        match self.constants.get(&id)? {
+            Constant::Scalar { words } if words.len() == 1 => Some(words[0]),
+            _ => None,
+        }
+    }
+    fn get_scalar_width(&self, ty: Id) -> Option<u32> {
+        match self.types.get(&ty)? {
+            Type::Int { width, .. } | Type::Float { width } => Some(*width),
+            _ => None,
+        }
+    }
+    fn get_location_count(&self, ty: Id) -> Option<u32> {
+        match self.types.get(&ty)? {
+            Type::Int { .. } | Type::Float { .. } => Some(1),
+            Type::Vector { component, count } => {
+                if self.get_scalar_width(*component)? == 64 && *count > 2 {
+                    Some(2)
+                } else {
+                    Some(1)
+                }
+            }
+            Type::Matrix { column, count } => Some(self.get_location_count(*column)? * count),
+            Type::Array { element, length } => {
+                Some(self.get_location_count(*element)? * self.get_constant_u32(*length)?)
+            }
+            Type::Struct { members } => members.iter().fold(Some(0), |sum, &member| {
+                Some(sum? + self.get_location_count(member)?)
+            }),
+        }
+    }
+    fn get_interface_variables(&self, storage_class: StorageClass) -> Vec<InterfaceVariable> {
+        let mut retval: Vec<_> = self
+            .interface
+            .iter()
+            .filter(|id| {
+                self.variables
+                    .get(*id)
+                    .map_or(false, |v| v.storage_class == storage_class)
+            })
+            .filter_map(|&id| {
+                Some(InterfaceVariable {
+                    id,
+                    location: *self.locations.get(&id)?,
+                    component: self.components.get(&id).cloned().unwrap_or(0),
+                    location_count: self
+                        .get_variable_type(id)
+                        .and_then(|ty| self.get_location_count(ty)),
+                })
+            })
+            .collect();
+        retval.sort_by_key(|v| v.id);
+        retval
+    }
+    fn can_privatize(&self, variable: Id) -> bool {
+        !self.global_references.contains(&variable)
+            && self.uses.get(&variable).map_or(true, |uses| !uses.escapes)
+    }
+    /// get the constant that's always stored to `variable`
+    fn get_stored_constant(&self, variable: Id) -> Option<Id> {
+        let uses = self.uses.get(&variable)?;
+        if uses.escapes || uses.uses_access_chains {
+            return None;
+        }
+        let constant = *uses.stores.first()?;
+        if uses.stores.iter().all(|&v| v == constant) && self.constants.contains_key(&constant) {
+            Some(constant)
+        } else {
+            None
+        }
+    }
+    fn allocate_id(&mut self) -> Id {
+        let retval = self.bound;
+        self.bound += 1;
+        retval
+    }
+}
+
+/// check that `producer_type` and `consumer_type` are the same scalar or vector type
+fn types_match(producer: &Module, producer_type: Id, consumer: &Module, consumer_type: Id) -> bool {
+    match (
+        producer.types.get(&producer_type),
+        consumer.types.get(&consumer_type),
+    ) {
+        (
+            Some(Type::Int {
+                width: producer_width,
+                signed: producer_signed,
+            }),
+            Some(Type::Int {
+                width: consumer_width,
+                signed: consumer_signed,
+            }),
+        ) => producer_width == consumer_width && producer_signed == consumer_signed,
+        (
+            Some(Type::Float {
+                width: producer_width,
+            }),
+            Some(Type::Float {
+                width: consumer_width,
+            }),
+        ) => producer_width == consumer_width,
+        (
+            Some(Type::Vector {
+                component: producer_component,
+                count: producer_count,
+            }),
+            Some(Type::Vector {
+                component: consumer_component,
+                count: consumer_count,
+            }),
+        ) => {
+            producer_count == consumer_count
+                && types_match(producer, *producer_component, consumer, *consumer_component)
+        }
+        _ => false,
+    }
+}
+
+/// copy `constant` from `producer` into `consumer` as type `ty`, appending the new instructions
+/// to `output`
+fn copy_constant(
+    producer: &Module,
+    constant: Id,
+    consumer: &mut Module,
+    ty: Id,
+    output: &mut Vec<u32>,
+) -> Option<Id> {
+    match producer.constants.get(&constant)? {
+        Constant::Scalar { words } => {
+            consumer.get_scalar_width(ty)?;
+            let id = consumer.allocate_id();
+            let mut operands = vec![ty, id];
+            operands.extend_from_slice(words);
+            spirv::write_instruction(output, op::CONSTANT, &operands);
+            Some(id)
+        }
+        Constant::Composite { constituents } => {
+            let component_type = match consumer.types.get(&ty)? {
+                Type::Vector { component, count } if *count as usize == constituents.len() => {
+                    *component
+                }
+                _ => return None,
+            };
+            let mut operands = vec![ty, 0];
+            for &constituent in constituents {
+                operands.push(copy_constant(
+                    producer,
+                    constituent,
+                    consumer,
+                    component_type,
+                    output,
+                )?);
+            }
+            let id = consumer.allocate_id();
+            operands[1] = id;
+            spirv::write_instruction(output, op::CONSTANT_COMPOSITE, &operands);
+            Some(id)
+        }
+    }
+}
+
+struct Rewrite {
+    /// variables to convert to `Private` variables, mapped to their initializers
+    privatized: HashMap<Id, Option<Id>>,
+    /// variables that have their location changed
+    relocated: HashSet<Id>,
+    location_remap: HashMap<u32, u32>,
+    /// instructions to insert at the end of the global section
+    new_globals: Vec<u32>,
+}
+
+impl Rewrite {
+    fn new() -> Self {
+        Rewrite {
+            privatized: HashMap::new(),
+            relocated: HashSet::new(),
+            location_remap: HashMap::new(),
+            new_globals: Vec::new(),
+        }
+    }
+    fn run(mut self, code: &[u32], module: &mut Module) -> Result<Vec<u32>, spirv::Error> {
+        let mut privatized_variables: Vec<Id> = self.privatized.keys().cloned().collect();
+        privatized_variables.sort();
+        // maps pointer types to the `Private` pointer types that replace them
+        let mut private_pointer_types = HashMap::new();
+        let mut new_types = Vec::new();
+        {
+            let mut pointer_types: Vec<Id> = privatized_variables
+                .iter()
+                .map(|v| module.variables[v].result_type)
+                .collect();
+            pointer_types.extend(
+                module
+                    .derived_pointers
+                    .values()
+                    .filter(|v| self.privatized.contains_key(&v.variable))
+                    .map(|v| v.result_type),
+            );
+            pointer_types.sort();
+            pointer_types.dedup();
+            for pointer_type in pointer_types {
+                let pointee_type = module.pointer_types[&pointer_type];
+                let private_pointer_type =
+                    match module.private_pointer_types.get(&pointee_type).cloned() {
+                        Some(v) => v,
+                        None => {
+                            let id = module.allocate_id();
+                            spirv::write_instruction(
+                                &mut new_types,
+                                op::TYPE_POINTER,
+                                &[id, StorageClass::Private as u32, pointee_type],
+                            );
+                            module.private_pointer_types.insert(pointee_type, id);
+                            id
+                        }
+                    };
+                private_pointer_types.insert(pointer_type, private_pointer_type);
+            }
+        }
+        for &variable in &privatized_variables {
+            let mut operands = vec![
+                private_pointer_types[&module.variables[&variable].result_type],
+                variable,
+                StorageClass::Private as u32,
+            ];
+            operands.extend(self.privatized[&variable]);
+            spirv::write_instruction(&mut self.new_globals, op::VARIABLE, &operands);
+        }
+        let mut output = code[..spirv::HEADER_WORD_COUNT].to_vec();
+        let mut operands = Vec::new();
+        let mut in_function = false;
+        for instruction in Instructions::new(code)? {
+            let instruction = instruction?;
+            if instruction.opcode == op::FUNCTION && !in_function {
+                in_function = true;
+                output.extend_from_slice(&new_types);
+                output.extend_from_slice(&self.new_globals);
+            }
+            match instruction.opcode {
+                op::ENTRY_POINT => {
+                    let (_, interface_start) = instruction.literal_string(2)?;
+                    operands.clear();
+                    operands.extend_from_slice(&instruction.operands()[..interface_start]);
+                    operands.extend(
+                        instruction.operands()[interface_start..]
+                            .iter()
+                            .filter(|v| !self.privatized.contains_key(*v)),
+                    );
+                    spirv::write_instruction(&mut output, instruction.opcode, &operands);
+                    continue;
+                }
+                op::DECORATE => {
+                    let target = instruction.operand(0)?;
+                    if self.privatized.contains_key(&target) {
+                        continue;
+                    }
+                    if self.relocated.contains(&target)
+                        && instruction.operand(1)? == decoration::LOCATION
+                    {
+                        let location = instruction.operand(2)?;
+                        let location = self
+                            .location_remap
+                            .get(&location)
+                            .cloned()
+                            .unwrap_or(location);
+                        spirv::write_instruction(
+                            &mut output,
+                            instruction.opcode,
+                            &[target, decoration::LOCATION, location],
+                        );
+                        continue;
+                    }
+                }
+                op::VARIABLE if !in_function => {
+                    if self.privatized.contains_key(&instruction.operand(1)?) {
+                        continue;
+                    }
+                }
+                op::ACCESS_CHAIN | op::IN_BOUNDS_ACCESS_CHAIN => {
+                    let is_privatized = module
+                        .derived_pointers
+                        .get(&instruction.operand(1)?)
+                        .map_or(false, |v| self.privatized.contains_key(&v.variable));
+                    if is_privatized {
+                        operands.clear();
+                        operands.extend_from_slice(instruction.operands());
+                        operands[0] = private_pointer_types[&operands[0]];
+                        spirv::write_instruction(&mut output, instruction.opcode, &operands);
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            output.extend_from_slice(instruction.words);
+        }
+        output[3] = module.bound;
+        Ok(output)
+    }
+}
+
+/// optimize the interface between `producer_code` and `consumer_code`
+pub fn link_stages(
+    producer_code: &[u32],
+    consumer_code: &[u32],
+) -> Result<LinkedStages, spirv::Error> {
+    let mut producer = Module::parse(producer_code)?;
+    let mut consumer = Module::parse(consumer_code)?;
+    if producer.uses_decoration_groups || consumer.uses_decoration_groups {
+        return Ok(LinkedStages {
+            producer_code: producer_code.to_vec(),
+            consumer_code: consumer_code.to_vec(),
+            demoted_outputs: Vec::new(),
+            promoted_inputs: Vec::new(),
+            location_remap: Vec::new(),
+        });
+    }
+    let outputs = producer.get_interface_variables(StorageClass::Output);
+    let inputs = consumer.get_interface_variables(StorageClass::Input);
+    let mut producer_rewrite = Rewrite::new();
+    let mut consumer_rewrite = Rewrite::new();
+    for input in &inputs {
+        if !consumer.can_privatize(input.id) {
+            continue;
+        }
+        let mut matching_outputs = outputs.iter().filter(|output| output.overlaps(input));
+        let output = match (matching_outputs.next(), matching_outputs.next()) {
+            (Some(output), None) => output,
+            _ => continue,
+        };
+        if output.location != input.location || output.component != input.component {
+            continue;
+        }
+        let constant = match producer.get_stored_constant(output.id) {
+            Some(v) => v,
+            None => continue,
+        };
+        let (producer_type, consumer_type) = match (
+            producer.get_variable_type(output.id),
+            consumer.get_variable_type(input.id),
+        ) {
+            (Some(producer_type), Some(consumer_type)) => (producer_type, consumer_type),
+            _ => continue,
+        };
+        if !types_match(&producer, producer_type, &consumer, consumer_type) {
+            continue;
+        }
+        let mut new_constants = Vec::new();
+        if let Some(initializer) = copy_constant(
+            &producer,
+            constant,
+            &mut consumer,
+            consumer_type,
+            &mut new_constants,
+        ) {
+            consumer_rewrite
+                .new_globals
+                .extend_from_slice(&new_constants);
+            consumer_rewrite
+                .privatized
+                .insert(input.id, Some(initializer));
+        }
+    }
+    let remaining_inputs: Vec<_> = inputs
+        .iter()
+        .filter(|v| !consumer_rewrite.privatized.contains_key(&v.id))
+        .collect();
+    for output in &outputs {
+        if output.location_count.is_some()
+            && producer.can_privatize(output.id)
+            && !remaining_inputs.iter().any(|input| input.overlaps(output))
+        {
+            producer_rewrite.privatized.insert(output.id, None);
+        }
+    }
+    let remaining_outputs: Vec<_> = outputs
+        .iter()
+        .filter(|v| !producer_rewrite.privatized.contains_key(&v.id))
+        .collect();
+    let mut location_remap = Vec::new();
+    let can_pack = !producer.has_member_locations
+        && !consumer.has_member_locations
+        && remaining_outputs
+            .iter()
+            .chain(&remaining_inputs)
+            .all(|v| v.location_count.is_some());
+    if can_pack {
+        let mut locations = BTreeSet::new();
+        for variable in remaining_outputs.iter().chain(&remaining_inputs) {
+            let location_count = variable.location_count.unwrap();
+            locations.extend(variable.location..variable.location + location_count);
+        }
+        for (new_location, old_location) in locations.into_iter().enumerate() {
+            let new_location = new_location as u32;
+            if new_location != old_location {
+                location_remap.push((old_location, new_location));
+            }
+        }
+        if !location_remap.is_empty() {
+            producer_rewrite.relocated = remaining_outputs.iter().map(|v| v.id).collect();
+            consumer_rewrite.relocated = remaining_inputs.iter().map(|v| v.id).collect();
+            producer_rewrite.location_remap = location_remap.iter().cloned().collect();
+            consumer_rewrite.location_remap = location_remap.iter().cloned().collect();
+        }
+    }
+    let mut demoted_outputs: Vec<_> = producer_rewrite.privatized.keys().cloned().collect();
+    demoted_outputs.sort();
+    let mut promoted_inputs: Vec<_> = consumer_rewrite.privatized.keys().cloned().collect();
+    promoted_inputs.sort();
+    Ok(LinkedStages {
+        producer_code: producer_rewrite.run(producer_code, &mut producer)?,
+        consumer_code: consumer_rewrite.run(consumer_code, &mut consumer)?,
+        demoted_outputs,
+        promoted_inputs,
+        location_remap,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use interface::trim_interface;
+    use spirv::{write_instruction, ExecutionModel};
+
+    const OP_TYPE_VOID: u16 = 19;
+    const OP_TYPE_FUNCTION: u16 = 33;
+    const OP_LABEL: u16 = 248;
+    const OP_RETURN: u16 = 253;
+
+    fn entry_point(code: &mut Vec<u32>, model: ExecutionModel, interface: &[Id]) {
+        let mut operands = vec![model as u32, 7];
+        spirv::write_literal_string(&mut operands, "main");
+        operands.extend_from_slice(interface);
+        write_instruction(code, op::ENTRY_POINT, &operands);
+    }
+
+    /// ids:
+    /// 1: void, 2: float, 3: fn void(), 4: ptr input float, 5: ptr output float,
+    /// 6: constant 1.0, 7: main, 8: label
+    fn write_types(code: &mut Vec<u32>) {
+        write_instruction(code, OP_TYPE_VOID, &[1]);
+        write_instruction(code, op::TYPE_FLOAT, &[2, 32]);
+        write_instruction(code, OP_TYPE_FUNCTION, &[3, 1]);
+        write_instruction(code, op::TYPE_POINTER, &[4, StorageClass::Input as u32, 2]);
+        write_instruction(code, op::TYPE_POINTER, &[5, StorageClass::Output as u32, 2]);
+        write_instruction(code, op::CONSTANT, &[2, 6, 1.0f32.to_bits()]);
+    }
+
+    fn write_main(code: &mut Vec<u32>, body: &[(u16, Vec<u32>)]) {
+        write_instruction(code, op::FUNCTION, &[1, 7, 0, 3]);
+        write_instruction(code, OP_LABEL, &[8]);
+        for (opcode, operands) in body {
+            write_instruction(code, *opcode, operands);
+        }
+        write_instruction(code, OP_RETURN, &[]);
+        write_instruction(code, op::FUNCTION_END, &[]);
+    }
+
+    fn location(code: &mut Vec<u32>, id: Id, location: u32) {
+        write_instruction(code, op::DECORATE, &[id, decoration::LOCATION, location]);
+    }
+
+    fn variable(code: &mut Vec<u32>, ty: Id, id: Id, storage_class: StorageClass) {
+        write_instruction(code, op::VARIABLE, &[ty, id, storage_class as u32]);
+    }
+
+    /// writes a computed value to location 1, the constant 1.0 to location 3,
+    /// and a computed value to location 5
+    fn producer() -> Vec<u32> {
+        let mut code = vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        entry_point(&mut code, ExecutionModel::Vertex, &[10, 20, 21, 22]);
+        location(&mut code, 10, 0);
+        location(&mut code, 20, 1);
+        location(&mut code, 21, 3);
+        location(&mut code, 22, 5);
+        write_types(&mut code);
+        variable(&mut code, 4, 10, StorageClass::Input);
+        variable(&mut code, 5, 20, StorageClass::Output);
+        variable(&mut code, 5, 21, StorageClass::Output);
+        variable(&mut code, 5, 22, StorageClass::Output);
+        write_main(
+            &mut code,
+            &[
+                (op::LOAD, vec![2, 30, 10]),
+                (op::STORE, vec![20, 30]),
+                (op::STORE, vec![21, 6]),
+                (op::STORE, vec![22, 30]),
+            ],
+        );
+        code
+    }
+
+    /// reads locations 3 and 5
+    fn consumer() -> Vec<u32> {
+        let mut code = vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        entry_point(&mut code, ExecutionModel::Fragment, &[11, 12, 23]);
+        location(&mut code, 11, 3);
+        location(&mut code, 12, 5);
+        location(&mut code, 23, 0);
+        write_types(&mut code);
+        variable(&mut code, 4, 11, StorageClass::Input);
+        variable(&mut code, 4, 12, StorageClass::Input);
+        variable(&mut code, 5, 23, StorageClass::Output);
+        write_main(
+            &mut code,
+            &[
+                (op::LOAD, vec![2, 31, 11]),
+                (op::LOAD, vec![2, 32, 12]),
+                (op::STORE, vec![23, 32]),
+            ],
+        );
+        code
+    }
+
+    fn get_variable_storage_class(code: &[u32], id: Id) -> Option<u32> {
+        Instructions::new(code)
+            .unwrap()
+            .map(|v| v.unwrap())
+            .find(|v| v.opcode == op::VARIABLE && v.operands()[1] == id)
+            .map(|v| v.operands()[2])
+    }
+
+    #[test]
+    fn test_link_stages() {
+        let linked = link_stages(&producer(), &consumer()).unwrap();
+        assert_eq!(linked.demoted_outputs, [20, 21]);
+        assert_eq!(linked.promoted_inputs, [11]);
+        assert_eq!(linked.location_remap, [(5, 0)]);
+        for &id in &[20, 21] {
+            assert_eq!(
+                get_variable_storage_class(&linked.producer_code, id),
+                Some(StorageClass::Private as u32)
+            );
+        }
+        assert_eq!(
+            get_variable_storage_class(&linked.consumer_code, 11),
+            Some(StorageClass::Private as u32)
+        );
+        let producer = trim_interface(&linked.producer_code, "main", ExecutionModel::Vertex)
+            .unwrap()
+            .interface;
+        // vertex inputs must not be moved
+        assert_eq!(producer.input_locations().collect::<Vec<_>>(), [0]);
+        assert_eq!(producer.outputs.len(), 1);
+        assert_eq!(producer.outputs[0].id, 22);
+        assert_eq!(producer.outputs[0].location, Some(0));
+        let consumer = trim_interface(&linked.consumer_code, "main", ExecutionModel::Fragment)
+            .unwrap()
+            .interface;
+        assert_eq!(consumer.inputs.len(), 1);
+        assert_eq!(consumer.inputs[0].id, 12);
+        assert_eq!(consumer.inputs[0].location, Some(0));
+        // fragment outputs must not be moved
+        assert_eq!(consumer.outputs[0].location, Some(0));
+    }
+
+    #[test]
+    fn test_link_stages_idempotent() {
+        let linked = link_stages(&producer(), &consumer()).unwrap();
+        let relinked = link_stages(&linked.producer_code, &linked.consumer_code).unwrap();
+        assert!(relinked.demoted_outputs.is_empty());
+        assert!(relinked.promoted_inputs.is_empty());
+        assert!(relinked.location_remap.is_empty());
+        assert_eq!(relinked.producer_code, linked.producer_code);
+        assert_eq!(relinked.consumer_code, linked.consumer_code);
+    }
+}
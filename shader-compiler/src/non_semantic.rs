@@ -0,0 +1,60 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! non-semantic extended instruction sets (`SPV_KHR_non_semantic_info`)
+//!
+//! imports whose names start with `NonSemantic.`, such as `NonSemantic.Shader.DebugInfo.100`,
+//! only carry information for tools. their `OpExtInst`s don't affect the generated code and can
+//! be removed, but they have to be removed when anything they reference is. passes that trim or
+//! rewrite modules keep them, don't count them as uses of the ids they reference, and drop the
+//! ones that reference removed ids.
+
+use spirv::{op, Error, Id, Instruction, Instructions};
+use std::collections::HashSet;
+
+pub const NON_SEMANTIC_PREFIX: &str = "NonSemantic.";
+
+/// the set that `debug_info` reads source locations and function names from
+pub const SHADER_DEBUG_INFO_NAME: &str = "NonSemantic.Shader.DebugInfo.100";
+
+#[derive(Clone, Debug, Default)]
+pub struct NonSemanticSets {
+    /// the ids of the non-semantic imports
+    imports: HashSet<Id>,
+    shader_debug_info: Option<Id>,
+}
+
+impl NonSemanticSets {
+    pub fn find(code: &[u32]) -> Result<Self, Error> {
+        let mut retval = Self::default();
+        for instruction in Instructions::new(code)? {
+            retval.add(&instruction?)?;
+        }
+        Ok(retval)
+    }
+    /// record `instruction` if it's a non-semantic `OpExtInstImport`. imports come before the
+    /// instructions that use them, so this can be called while going through a module
+    pub fn add(&mut self, instruction: &Instruction) -> Result<(), Error> {
+        if instruction.opcode != op::EXT_INST_IMPORT {
+            return Ok(());
+        }
+        let (name, _) = instruction.literal_string(1)?;
+        if !name.starts_with(NON_SEMANTIC_PREFIX) {
+            return Ok(());
+        }
+        let id = instruction.operand(0)?;
+        self.imports.insert(id);
+        if name == SHADER_DEBUG_INFO_NAME {
+            self.shader_debug_info = Some(id);
+        }
+        Ok(())
+    }
+    /// true if `instruction` is an `OpExtInst` from a non-semantic set
+    pub fn is_non_semantic(&self, instruction: &Instruction) -> Result<bool, Error> {
+        Ok(instruction.opcode == op::EXT_INST && self.imports.contains(&instruction.operand(2)?))
+    }
+    /// the id of the `NonSemantic.Shader.DebugInfo.100` import
+    pub fn shader_debug_info(&self) -> Option<Id> {
+        self.shader_debug_info
+    }
+}
@@ -0,0 +1,679 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! lowering for the `OpenCL.std` extended instruction set, used by compute modules from OpenCL C
+//! compilers
+//!
+//! only 32-bit scalar operands are supported; vector operands have to be split into components
+//! first. instructions the backends implement directly are lowered to backend intrinsics; the
+//! rest are lowered to a call to a runtime function, declared in the module the first time it's
+//! used.
+
+use shader_compiler_backend::intrinsics;
+use shader_compiler_backend::types::TypeBuilder;
+use shader_compiler_backend::{AttachedBuilder, Context, Error, Function, Module};
+use spirv::{self, op, Id, Instructions};
+use std::collections::HashMap;
+use std::mem;
+
+pub const EXTENDED_INSTRUCTION_SET_NAME: &str = "OpenCL.std";
+
+/// the id of the `OpenCL.std` import in `code`, if there is one
+pub fn find_import(code: &[u32]) -> Result<Option<Id>, spirv::Error> {
+    for instruction in Instructions::new(code)? {
+        let instruction = instruction?;
+        if instruction.opcode == op::EXT_INST_IMPORT
+            && instruction.literal_string(1)?.0 == EXTENDED_INSTRUCTION_SET_NAME
+        {
+            return Ok(Some(instruction.operand(0)?));
+        }
+    }
+    Ok(None)
+}
+
+/// the supported `OpenCL.std` instructions; the values are the extended instruction numbers
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum OpenCLStdInstruction {
+    Acos = 0,
+    Asin = 3,
+    Atan = 6,
+    Atan2 = 7,
+    Cbrt = 11,
+    Ceil = 12,
+    Copysign = 13,
+    Cos = 14,
+    Cosh = 15,
+    Exp = 19,
+    Exp2 = 20,
+    Expm1 = 22,
+    Fabs = 23,
+    Fdim = 24,
+    Floor = 25,
+    Fma = 26,
+    Fmax = 27,
+    Fmin = 28,
+    Fmod = 29,
+    Hypot = 32,
+    Log = 37,
+    Log2 = 38,
+    Log10 = 39,
+    Log1p = 40,
+    Mad = 42,
+    Pow = 48,
+    Rint = 53,
+    Round = 55,
+    Rsqrt = 56,
+    Sin = 57,
+    Sinh = 59,
+    Sqrt = 61,
+    Tan = 62,
+    Tanh = 63,
+    Trunc = 66,
+    FClamp = 95,
+    Degrees = 96,
+    Mix = 99,
+    Radians = 100,
+    Step = 101,
+    Sign = 103,
+    SAbs = 141,
+    SClamp = 149,
+    UClamp = 150,
+    Clz = 151,
+    Ctz = 152,
+    SMax = 156,
+    UMax = 157,
+    SMin = 158,
+    UMin = 159,
+    Rotate = 161,
+    Popcount = 166,
+}
+
+impl OpenCLStdInstruction {
+    pub fn from_number(number: u32) -> Option<Self> {
+        use self::OpenCLStdInstruction::*;
+        Some(match number {
+            0 => Acos,
+            3 => Asin,
+            6 => Atan,
+            7 => Atan2,
+            11 => Cbrt,
+            12 => Ceil,
+            13 => Copysign,
+            14 => Cos,
+            15 => Cosh,
+            19 => Exp,
+            20 => Exp2,
+            22 => Expm1,
+            23 => Fabs,
+            24 => Fdim,
+            25 => Floor,
+            26 => Fma,
+            27 => Fmax,
+            28 => Fmin,
+            29 => Fmod,
+            32 => Hypot,
+            37 => Log,
+            38 => Log2,
+            39 => Log10,
+            40 => Log1p,
+            42 => Mad,
+            48 => Pow,
+            53 => Rint,
+            55 => Round,
+            56 => Rsqrt,
+            57 => Sin,
+            59 => Sinh,
+            61 => Sqrt,
+            62 => Tan,
+            63 => Tanh,
+            66 => Trunc,
+            95 => FClamp,
+            96 => Degrees,
+            99 => Mix,
+            100 => Radians,
+            101 => Step,
+            103 => Sign,
+            141 => SAbs,
+            149 => SClamp,
+            150 => UClamp,
+            151 => Clz,
+            152 => Ctz,
+            156 => SMax,
+            157 => UMax,
+            158 => SMin,
+            159 => UMin,
+            161 => Rotate,
+            166 => Popcount,
+            _ => return None,
+        })
+    }
+    /// the name used in the OpenCL specification
+    pub fn name(self) -> &'static str {
+        use self::OpenCLStdInstruction::*;
+        match self {
+            Acos => "acos",
+            Asin => "asin",
+            Atan => "atan",
+            Atan2 => "atan2",
+            Cbrt => "cbrt",
+            Ceil => "ceil",
+            Copysign => "copysign",
+            Cos => "cos",
+            Cosh => "cosh",
+            Exp => "exp",
+            Exp2 => "exp2",
+            Expm1 => "expm1",
+            Fabs => "fabs",
+            Fdim => "fdim",
+            Floor => "floor",
+            Fma => "fma",
+            Fmax => "fmax",
+            Fmin => "fmin",
+            Fmod => "fmod",
+            Hypot => "hypot",
+            Log => "log",
+            Log2 => "log2",
+            Log10 => "log10",
+            Log1p => "log1p",
+            Mad => "mad",
+            Pow => "pow",
+            Rint => "rint",
+            Round => "round",
+            Rsqrt => "rsqrt",
+            Sin => "sin",
+            Sinh => "sinh",
+            Sqrt => "sqrt",
+            Tan => "tan",
+            Tanh => "tanh",
+            Trunc => "trunc",
+            FClamp => "fclamp",
+            Degrees => "degrees",
+            Mix => "mix",
+            Radians => "radians",
+            Step => "step",
+            Sign => "sign",
+            SAbs => "s_abs",
+            SClamp => "s_clamp",
+            UClamp => "u_clamp",
+            Clz => "clz",
+            Ctz => "ctz",
+            SMax => "s_max",
+            UMax => "u_max",
+            SMin => "s_min",
+            UMin => "u_min",
+            Rotate => "rotate",
+            Popcount => "popcount",
+        }
+    }
+    /// the backend intrinsic implementing `self`, if there is one
+    fn get_backend_intrinsic(self) -> Option<&'static str> {
+        match self {
+            OpenCLStdInstruction::Fma => Some(intrinsics::FMA),
+            OpenCLStdInstruction::Rsqrt => Some(intrinsics::RSQRT),
+            OpenCLStdInstruction::Sqrt => Some(intrinsics::SQRT),
+            _ => None,
+        }
+    }
+    fn get_runtime_function(self) -> RuntimeFunction {
+        use self::OpenCLStdInstruction::*;
+        use self::RuntimeFunction::*;
+        match self {
+            Acos => F32Unary(acos),
+            Asin => F32Unary(asin),
+            Atan => F32Unary(atan),
+            Atan2 => F32Binary(atan2),
+            Cbrt => F32Unary(cbrt),
+            Ceil => F32Unary(ceil),
+            Copysign => F32Binary(copysign),
+            Cos => F32Unary(cos),
+            Cosh => F32Unary(cosh),
+            Exp => F32Unary(exp),
+            Exp2 => F32Unary(exp2),
+            Expm1 => F32Unary(expm1),
+            Fabs => F32Unary(fabs),
+            Fdim => F32Binary(fdim),
+            Floor => F32Unary(floor),
+            Fma => F32Ternary(fma),
+            Fmax => F32Binary(fmax),
+            Fmin => F32Binary(fmin),
+            Fmod => F32Binary(fmod),
+            Hypot => F32Binary(hypot),
+            Log => F32Unary(log),
+            Log2 => F32Unary(log2),
+            Log10 => F32Unary(log10),
+            Log1p => F32Unary(log1p),
+            Mad => F32Ternary(mad),
+            Pow => F32Binary(pow),
+            Rint => F32Unary(rint),
+            Round => F32Unary(round),
+            Rsqrt => F32Unary(rsqrt),
+            Sin => F32Unary(sin),
+            Sinh => F32Unary(sinh),
+            Sqrt => F32Unary(sqrt),
+            Tan => F32Unary(tan),
+            Tanh => F32Unary(tanh),
+            Trunc => F32Unary(trunc),
+            FClamp => F32Ternary(fclamp),
+            Degrees => F32Unary(degrees),
+            Mix => F32Ternary(mix),
+            Radians => F32Unary(radians),
+            Step => F32Binary(step),
+            Sign => F32Unary(sign),
+            SAbs => I32Unary(s_abs),
+            SClamp => I32Ternary(s_clamp),
+            UClamp => I32Ternary(u_clamp),
+            Clz => I32Unary(clz),
+            Ctz => I32Unary(ctz),
+            SMax => I32Binary(s_max),
+            UMax => I32Binary(u_max),
+            SMin => I32Binary(s_min),
+            UMin => I32Binary(u_min),
+            Rotate => I32Binary(rotate),
+            Popcount => I32Unary(popcount),
+        }
+    }
+}
+
+pub type F32UnaryFn = unsafe extern "C" fn(f32) -> f32;
+pub type F32BinaryFn = unsafe extern "C" fn(f32, f32) -> f32;
+pub type F32TernaryFn = unsafe extern "C" fn(f32, f32, f32) -> f32;
+/// integers are passed as `u32`; the signed instructions reinterpret them as `i32`
+pub type I32UnaryFn = unsafe extern "C" fn(u32) -> u32;
+pub type I32BinaryFn = unsafe extern "C" fn(u32, u32) -> u32;
+pub type I32TernaryFn = unsafe extern "C" fn(u32, u32, u32) -> u32;
+
+#[derive(Copy, Clone)]
+enum RuntimeFunction {
+    F32Unary(F32UnaryFn),
+    F32Binary(F32BinaryFn),
+    F32Ternary(F32TernaryFn),
+    I32Unary(I32UnaryFn),
+    I32Binary(I32BinaryFn),
+    I32Ternary(I32TernaryFn),
+}
+
+impl RuntimeFunction {
+    fn argument_count(self) -> usize {
+        match self {
+            RuntimeFunction::F32Unary(_) | RuntimeFunction::I32Unary(_) => 1,
+            RuntimeFunction::F32Binary(_) | RuntimeFunction::I32Binary(_) => 2,
+            RuntimeFunction::F32Ternary(_) | RuntimeFunction::I32Ternary(_) => 3,
+        }
+    }
+    fn declare<'a, C: Context<'a>>(
+        self,
+        context: &'a C,
+        module: &mut C::Module,
+        name: &str,
+    ) -> Result<C::Function, Error> {
+        let type_builder = context.create_type_builder();
+        unsafe {
+            match self {
+                RuntimeFunction::F32Unary(f) => module.add_external_function(
+                    name,
+                    type_builder.build::<F32UnaryFn>()?,
+                    mem::transmute(f),
+                ),
+                RuntimeFunction::F32Binary(f) => module.add_external_function(
+                    name,
+                    type_builder.build::<F32BinaryFn>()?,
+                    mem::transmute(f),
+                ),
+                RuntimeFunction::F32Ternary(f) => module.add_external_function(
+                    name,
+                    type_builder.build::<F32TernaryFn>()?,
+                    mem::transmute(f),
+                ),
+                RuntimeFunction::I32Unary(f) => module.add_external_function(
+                    name,
+                    type_builder.build::<I32UnaryFn>()?,
+                    mem::transmute(f),
+                ),
+                RuntimeFunction::I32Binary(f) => module.add_external_function(
+                    name,
+                    type_builder.build::<I32BinaryFn>()?,
+                    mem::transmute(f),
+                ),
+                RuntimeFunction::I32Ternary(f) => module.add_external_function(
+                    name,
+                    type_builder.build::<I32TernaryFn>()?,
+                    mem::transmute(f),
+                ),
+            }
+        }
+    }
+}
+
+extern "C" fn acos(x: f32) -> f32 {
+    x.acos()
+}
+
+extern "C" fn asin(x: f32) -> f32 {
+    x.asin()
+}
+
+extern "C" fn atan(x: f32) -> f32 {
+    x.atan()
+}
+
+extern "C" fn atan2(y: f32, x: f32) -> f32 {
+    y.atan2(x)
+}
+
+extern "C" fn cbrt(x: f32) -> f32 {
+    x.cbrt()
+}
+
+extern "C" fn ceil(x: f32) -> f32 {
+    x.ceil()
+}
+
+extern "C" fn copysign(x: f32, y: f32) -> f32 {
+    f32::from_bits(x.to_bits() & !0x8000_0000 | y.to_bits() & 0x8000_0000)
+}
+
+extern "C" fn cos(x: f32) -> f32 {
+    x.cos()
+}
+
+extern "C" fn cosh(x: f32) -> f32 {
+    x.cosh()
+}
+
+extern "C" fn exp(x: f32) -> f32 {
+    x.exp()
+}
+
+extern "C" fn exp2(x: f32) -> f32 {
+    x.exp2()
+}
+
+extern "C" fn expm1(x: f32) -> f32 {
+    x.exp_m1()
+}
+
+extern "C" fn fabs(x: f32) -> f32 {
+    x.abs()
+}
+
+extern "C" fn fdim(x: f32, y: f32) -> f32 {
+    if x > y {
+        x - y
+    } else if x <= y {
+        0.0
+    } else {
+        // one of them is NaN
+        x + y
+    }
+}
+
+extern "C" fn floor(x: f32) -> f32 {
+    x.floor()
+}
+
+extern "C" fn fma(a: f32, b: f32, c: f32) -> f32 {
+    a.mul_add(b, c)
+}
+
+extern "C" fn fmax(x: f32, y: f32) -> f32 {
+    x.max(y)
+}
+
+extern "C" fn fmin(x: f32, y: f32) -> f32 {
+    x.min(y)
+}
+
+extern "C" fn fmod(x: f32, y: f32) -> f32 {
+    x % y
+}
+
+extern "C" fn hypot(x: f32, y: f32) -> f32 {
+    x.hypot(y)
+}
+
+extern "C" fn log(x: f32) -> f32 {
+    x.ln()
+}
+
+extern "C" fn log2(x: f32) -> f32 {
+    x.log2()
+}
+
+extern "C" fn log10(x: f32) -> f32 {
+    x.log10()
+}
+
+extern "C" fn log1p(x: f32) -> f32 {
+    x.ln_1p()
+}
+
+extern "C" fn mad(a: f32, b: f32, c: f32) -> f32 {
+    a * b + c
+}
+
+extern "C" fn pow(x: f32, y: f32) -> f32 {
+    x.powf(y)
+}
+
+/// round to nearest, with ties to even.
+/// adding 2^23 leaves no fraction bits, so the addition does the rounding
+extern "C" fn rint(x: f32) -> f32 {
+    const TWO_POW_23: f32 = 8_388_608.0;
+    if x.abs() < TWO_POW_23 {
+        copysign(x.abs() + TWO_POW_23 - TWO_POW_23, x)
+    } else {
+        // already an integer, infinite or NaN
+        x
+    }
+}
+
+/// round to nearest, with ties away from zero
+extern "C" fn round(x: f32) -> f32 {
+    x.round()
+}
+
+extern "C" fn rsqrt(x: f32) -> f32 {
+    1.0 / x.sqrt()
+}
+
+extern "C" fn sin(x: f32) -> f32 {
+    x.sin()
+}
+
+extern "C" fn sinh(x: f32) -> f32 {
+    x.sinh()
+}
+
+extern "C" fn sqrt(x: f32) -> f32 {
+    x.sqrt()
+}
+
+extern "C" fn tan(x: f32) -> f32 {
+    x.tan()
+}
+
+extern "C" fn tanh(x: f32) -> f32 {
+    x.tanh()
+}
+
+extern "C" fn trunc(x: f32) -> f32 {
+    x.trunc()
+}
+
+extern "C" fn fclamp(x: f32, min_value: f32, max_value: f32) -> f32 {
+    x.max(min_value).min(max_value)
+}
+
+extern "C" fn degrees(radians: f32) -> f32 {
+    radians.to_degrees()
+}
+
+extern "C" fn mix(x: f32, y: f32, a: f32) -> f32 {
+    x + (y - x) * a
+}
+
+extern "C" fn radians(degrees: f32) -> f32 {
+    degrees.to_radians()
+}
+
+extern "C" fn step(edge: f32, x: f32) -> f32 {
+    if x < edge {
+        0.0
+    } else {
+        1.0
+    }
+}
+
+/// zero keeps its sign and NaN becomes zero
+extern "C" fn sign(x: f32) -> f32 {
+    if x > 0.0 {
+        1.0
+    } else if x < 0.0 {
+        -1.0
+    } else if x.is_nan() {
+        0.0
+    } else {
+        x
+    }
+}
+
+extern "C" fn s_abs(x: u32) -> u32 {
+    (x as i32).wrapping_abs() as u32
+}
+
+extern "C" fn s_clamp(x: u32, min_value: u32, max_value: u32) -> u32 {
+    (x as i32).max(min_value as i32).min(max_value as i32) as u32
+}
+
+extern "C" fn u_clamp(x: u32, min_value: u32, max_value: u32) -> u32 {
+    x.max(min_value).min(max_value)
+}
+
+extern "C" fn clz(x: u32) -> u32 {
+    x.leading_zeros()
+}
+
+extern "C" fn ctz(x: u32) -> u32 {
+    x.trailing_zeros()
+}
+
+extern "C" fn s_max(x: u32, y: u32) -> u32 {
+    (x as i32).max(y as i32) as u32
+}
+
+extern "C" fn u_max(x: u32, y: u32) -> u32 {
+    x.max(y)
+}
+
+extern "C" fn s_min(x: u32, y: u32) -> u32 {
+    (x as i32).min(y as i32) as u32
+}
+
+extern "C" fn u_min(x: u32, y: u32) -> u32 {
+    x.min(y)
+}
+
+extern "C" fn rotate(value: u32, shift: u32) -> u32 {
+    value.rotate_left(shift % 32)
+}
+
+extern "C" fn popcount(x: u32) -> u32 {
+    x.count_ones()
+}
+
+/// the runtime functions declared in a module
+pub struct DeclaredOpenCLStdFunctions<'a, C: Context<'a>> {
+    functions: HashMap<OpenCLStdInstruction, C::Function>,
+}
+
+impl<'a, C: Context<'a>> Default for DeclaredOpenCLStdFunctions<'a, C> {
+    fn default() -> Self {
+        DeclaredOpenCLStdFunctions {
+            functions: HashMap::new(),
+        }
+    }
+}
+
+impl<'a, C: Context<'a>> DeclaredOpenCLStdFunctions<'a, C> {
+    /// lowering for `OpExtInst` using the `OpenCL.std` set.
+    /// `operands` are the extended instruction's operands, which must be 32-bit scalars
+    pub fn build(
+        &mut self,
+        context: &'a C,
+        module: &mut C::Module,
+        builder: &C::AttachedBuilder,
+        instruction: OpenCLStdInstruction,
+        operands: &[C::Value],
+    ) -> Result<C::Value, Error> {
+        let runtime_function = instruction.get_runtime_function();
+        assert_eq!(
+            operands.len(),
+            runtime_function.argument_count(),
+            "wrong number of operands for OpenCL.std {}",
+            instruction.name()
+        );
+        if let Some(intrinsic) = instruction.get_backend_intrinsic() {
+            let f32_type = context.create_type_builder().build::<f32>()?;
+            return builder.build_intrinsic(intrinsic, Some(f32_type), operands);
+        }
+        if !self.functions.contains_key(&instruction) {
+            let function = runtime_function.declare(
+                context,
+                module,
+                &format!("kazan_opencl_std_{}", instruction.name()),
+            )?;
+            self.functions.insert(instruction, function);
+        }
+        builder.build_call(self.functions[&instruction].as_value(), operands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_numbers() {
+        for number in 0..256 {
+            if let Some(instruction) = OpenCLStdInstruction::from_number(number) {
+                assert_eq!(instruction as u32, number);
+            }
+        }
+        assert_eq!(
+            OpenCLStdInstruction::from_number(61),
+            Some(OpenCLStdInstruction::Sqrt)
+        );
+        assert_eq!(OpenCLStdInstruction::from_number(184), None); // printf
+    }
+
+    #[test]
+    fn test_runtime_functions() {
+        assert_eq!(rint(2.5), 2.0);
+        assert_eq!(rint(3.5), 4.0);
+        assert_eq!(rint(-2.5), -2.0);
+        assert_eq!(rint(2.4), 2.0);
+        assert_eq!(copysign(2.0, -0.0), -2.0);
+        assert_eq!(fdim(1.0, 3.0), 0.0);
+        assert!(fdim(::std::f32::NAN, 3.0).is_nan());
+        assert_eq!(sign(-0.0).to_bits(), (-0.0f32).to_bits());
+        assert_eq!(sign(::std::f32::NAN), 0.0);
+        assert_eq!(s_abs(-5i32 as u32), 5);
+        assert_eq!(s_max(-5i32 as u32, 3), 3);
+        assert_eq!(u_max(-5i32 as u32, 3), -5i32 as u32);
+        assert_eq!(s_clamp(-5i32 as u32, -2i32 as u32, 2), -2i32 as u32);
+        assert_eq!(rotate(0x8000_0001, 33), 0x0000_0003);
+    }
+
+    #[test]
+    fn test_find_import() {
+        let mut code = vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, 10, 0];
+        let mut operands = vec![1];
+        spirv::write_literal_string(&mut operands, "GLSL.std.450");
+        spirv::write_instruction(&mut code, op::EXT_INST_IMPORT, &operands);
+        assert_eq!(find_import(&code).unwrap(), None);
+        let mut operands = vec![2];
+        spirv::write_literal_string(&mut operands, EXTENDED_INSTRUCTION_SET_NAME);
+        spirv::write_instruction(&mut code, op::EXT_INST_IMPORT, &operands);
+        assert_eq!(find_import(&code).unwrap(), Some(2));
+    }
+}
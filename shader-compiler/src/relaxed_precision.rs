@@ -0,0 +1,243 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! evaluating float arithmetic decorated with `RelaxedPrecision` in f16
+//!
+//! only used when `CompilerIndependentConfig::relaxed_precision_in_f16` is set. the decoration
+//! allows 32-bit float results to be computed with as little as f16 precision, and a vector
+//! register holds twice as many f16 lanes as f32 lanes. the backend uses native half-precision
+//! arithmetic where the target has it; otherwise it does the arithmetic in f32 and converts to
+//! and from f16 around it, which gives f16's rounding but only saves memory bandwidth.
+//!
+//! operands are truncated to f16 where they enter a relaxed instruction, and relaxed results are
+//! extended back to f32 where anything else uses them, such as stores, calls and arithmetic that
+//! isn't relaxed.
+
+use shader_compiler_backend::{AttachedBuilder, Context, FloatArithmeticOperation};
+use spirv::{decoration, op, Error, Id, Instruction, Instructions};
+use std::collections::{HashMap, HashSet};
+
+/// how `FloatInstruction::build` and `RelaxedPrecision` get values and types
+pub trait FloatOperands<'a, C: Context<'a>> {
+    /// the value of `id`, which is in f16 if `RelaxedPrecision::is_relaxed` returns true for it
+    fn get_value(&self, id: Id) -> Result<C::Value, Error>;
+    fn get_type(&self, id: Id) -> Result<C::Type, Error>;
+    /// the type `ty` with its f32 elements replaced by f16
+    fn get_half_type(&self, ty: Id) -> Result<C::Type, Error>;
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum FloatOperation {
+    Negate,
+    Arithmetic(FloatArithmeticOperation),
+}
+
+/// a float arithmetic instruction that can be evaluated in f16
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FloatInstruction {
+    pub operation: FloatOperation,
+    pub result_type: Id,
+    pub result: Id,
+    /// one operand for `Negate`, otherwise two
+    pub operands: Vec<Id>,
+}
+
+impl FloatInstruction {
+    /// returns `None` if `instruction` isn't a supported float arithmetic instruction
+    pub fn parse(instruction: &Instruction) -> Result<Option<Self>, Error> {
+        let operation = match instruction.opcode {
+            op::F_NEGATE => FloatOperation::Negate,
+            op::F_ADD => FloatOperation::Arithmetic(FloatArithmeticOperation::Add),
+            op::F_SUB => FloatOperation::Arithmetic(FloatArithmeticOperation::Subtract),
+            op::F_MUL => FloatOperation::Arithmetic(FloatArithmeticOperation::Multiply),
+            op::F_DIV => FloatOperation::Arithmetic(FloatArithmeticOperation::Divide),
+            op::F_REM => FloatOperation::Arithmetic(FloatArithmeticOperation::Remainder),
+            _ => return Ok(None),
+        };
+        let operands = match operation {
+            FloatOperation::Negate => vec![instruction.operand(2)?],
+            FloatOperation::Arithmetic(_) => {
+                vec![instruction.operand(2)?, instruction.operand(3)?]
+            }
+        };
+        Ok(Some(FloatInstruction {
+            operation,
+            result_type: instruction.operand(0)?,
+            result: instruction.operand(1)?,
+            operands,
+        }))
+    }
+    /// the result is in f16 if `relaxed_precision.is_relaxed(self.result)`
+    pub fn build<'a, C: Context<'a>, O: FloatOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        operands: &O,
+        relaxed_precision: &RelaxedPrecision,
+    ) -> Result<C::Value, Error> {
+        let is_relaxed = relaxed_precision.is_relaxed(self.result);
+        let get_operand = |id: Id| -> Result<C::Value, Error> {
+            if !is_relaxed {
+                relaxed_precision.get_full_precision_value(builder, operands, id)
+            } else if relaxed_precision.is_relaxed(id) {
+                operands.get_value(id)
+            } else {
+                Ok(builder.build_float_truncate(
+                    operands.get_value(id)?,
+                    operands.get_half_type(self.result_type)?,
+                ))
+            }
+        };
+        Ok(match self.operation {
+            FloatOperation::Negate => builder.build_float_negate(get_operand(self.operands[0])?),
+            FloatOperation::Arithmetic(operation) => builder.build_float_arithmetic(
+                operation,
+                get_operand(self.operands[0])?,
+                get_operand(self.operands[1])?,
+            ),
+        })
+    }
+}
+
+/// the float arithmetic results that are evaluated in f16
+#[derive(Clone, Debug, Default)]
+pub struct RelaxedPrecision {
+    /// maps each result to its f32 result type
+    relaxed_results: HashMap<Id, Id>,
+}
+
+impl RelaxedPrecision {
+    /// find the results in `code` to evaluate in f16: those decorated with `RelaxedPrecision`
+    /// whose type is f32 or a vector of f32. finds nothing unless `enabled`
+    pub fn find(code: &[u32], enabled: bool) -> Result<Self, Error> {
+        let mut retval = Self::default();
+        if !enabled {
+            return Ok(retval);
+        }
+        let mut decorated = HashSet::new();
+        let mut f32_types = HashSet::new();
+        // decorations come before types, which come before functions
+        for instruction in Instructions::new(code)? {
+            let instruction = instruction?;
+            match instruction.opcode {
+                op::DECORATE => {
+                    if instruction.operand(1)? == decoration::RELAXED_PRECISION {
+                        decorated.insert(instruction.operand(0)?);
+                    }
+                }
+                op::TYPE_FLOAT => {
+                    if instruction.operand(1)? == 32 {
+                        f32_types.insert(instruction.operand(0)?);
+                    }
+                }
+                op::TYPE_VECTOR => {
+                    if f32_types.contains(&instruction.operand(1)?) {
+                        f32_types.insert(instruction.operand(0)?);
+                    }
+                }
+                _ => {
+                    if let Some(float_instruction) = FloatInstruction::parse(&instruction)? {
+                        if decorated.contains(&float_instruction.result)
+                            && f32_types.contains(&float_instruction.result_type)
+                        {
+                            retval
+                                .relaxed_results
+                                .insert(float_instruction.result, float_instruction.result_type);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(retval)
+    }
+    pub fn is_relaxed(&self, id: Id) -> bool {
+        self.relaxed_results.contains_key(&id)
+    }
+    /// get `id`, extending it back to f32 if it's evaluated in f16.
+    /// used for all operands other than those of relaxed instructions
+    pub fn get_full_precision_value<'a, C: Context<'a>, O: FloatOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        operands: &O,
+        id: Id,
+    ) -> Result<C::Value, Error> {
+        let value = operands.get_value(id)?;
+        match self.relaxed_results.get(&id) {
+            Some(&ty) => Ok(builder.build_float_extend(value, operands.get_type(ty)?)),
+            None => Ok(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::{write_instruction, MAGIC_NUMBER};
+
+    fn parse(opcode: u16, operands: &[u32]) -> Option<FloatInstruction> {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        write_instruction(&mut code, opcode, operands);
+        let instruction = Instructions::new(&code).unwrap().next().unwrap().unwrap();
+        FloatInstruction::parse(&instruction).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(op::LOAD, &[1, 2, 3]), None);
+        assert_eq!(
+            parse(op::F_NEGATE, &[1, 2, 3]),
+            Some(FloatInstruction {
+                operation: FloatOperation::Negate,
+                result_type: 1,
+                result: 2,
+                operands: vec![3],
+            })
+        );
+        assert_eq!(
+            parse(op::F_REM, &[1, 2, 3, 4]),
+            Some(FloatInstruction {
+                operation: FloatOperation::Arithmetic(FloatArithmeticOperation::Remainder),
+                result_type: 1,
+                result: 2,
+                operands: vec![3, 4],
+            })
+        );
+    }
+
+    /// 1: f32, 2: vec4 of f32, 3: f64, 4: int
+    /// 10: relaxed f32 add, 11: f32 multiply, 12: relaxed vec4 subtract, 13: relaxed f64 add,
+    /// 14: relaxed integer add
+    fn test_module() -> Vec<u32> {
+        const OP_TYPE_INT: u16 = 21;
+        const OP_I_ADD: u16 = 128;
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        for &id in &[10, 12, 13, 14] {
+            write_instruction(
+                &mut code,
+                op::DECORATE,
+                &[id, decoration::RELAXED_PRECISION],
+            );
+        }
+        write_instruction(&mut code, op::TYPE_FLOAT, &[1, 32]);
+        write_instruction(&mut code, op::TYPE_VECTOR, &[2, 1, 4]);
+        write_instruction(&mut code, op::TYPE_FLOAT, &[3, 64]);
+        write_instruction(&mut code, OP_TYPE_INT, &[4, 32, 1]);
+        write_instruction(&mut code, op::F_ADD, &[1, 10, 20, 21]);
+        write_instruction(&mut code, op::F_MUL, &[1, 11, 10, 20]);
+        write_instruction(&mut code, op::F_SUB, &[2, 12, 22, 23]);
+        write_instruction(&mut code, op::F_ADD, &[3, 13, 24, 25]);
+        write_instruction(&mut code, OP_I_ADD, &[4, 14, 26, 27]);
+        code
+    }
+
+    #[test]
+    fn test_find() {
+        let code = test_module();
+        let relaxed_precision = RelaxedPrecision::find(&code, true).unwrap();
+        let relaxed: Vec<Id> = (10..15)
+            .filter(|&id| relaxed_precision.is_relaxed(id))
+            .collect();
+        assert_eq!(relaxed, [10, 12]);
+        let relaxed_precision = RelaxedPrecision::find(&code, false).unwrap();
+        assert!(!relaxed_precision.is_relaxed(10));
+    }
+}
@@ -0,0 +1,639 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! choosing the SIMD width of each shader
+//!
+//! shaders are vectorized across invocations: a compiled shader runs a group of invocations
+//! together, one per vector lane, and the number of invocations in a group is its SIMD width,
+//! which is the multiplier for the base length of its variable-length vectors. wider groups
+//! spread the per-group overhead over more invocations, but they aren't always faster:
+//!
+//! * every value takes a lane per invocation, so a shader with many values live at once spills
+//!   to the stack at wide widths. `ShaderProfile::register_pressure` estimates how many 32-bit
+//!   words each invocation has live at once.
+//! * f64 values take twice the lanes of f32 values.
+//! * when the invocations of a group take different sides of a branch, both sides run with the
+//!   lanes of the other invocations masked off, and a loop runs until the last invocation of the
+//!   group leaves it, so the wasted work grows with the width. a branch is divergent when its
+//!   condition can differ between invocations, which is found with a conservative uniformity
+//!   analysis: values are uniform if they're computed only from constants and from memory that's
+//!   the same for every invocation, which is uniform buffers, push constants, and `Function` and
+//!   `Private` variables that are only assigned uniform values.
+//!
+//! `ShaderProfile::choose_simd_width` picks the widest width that none of these rule out, and
+//! records which one limited it.
+
+use interface::function_body_result_id;
+use spirv::{built_in, decoration, op, Error, Id, Instruction, Instructions, StorageClass};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+/// the 32-bit lanes of live values a group can have before wider vectors spill heavily: twice
+/// the 128 lanes of the 16 AVX2 registers, since the estimate also counts values that are
+/// rematerialized or used straight from memory
+const REGISTER_BUDGET_LANES: u32 = 256;
+
+/// the width is halved for each this many divergent branches
+const DIVERGENT_BRANCHES_PER_HALVING: u32 = 4;
+
+/// the number of divergent branches a divergent loop counts as, since the whole group runs
+/// every iteration that any of its invocations takes
+const DIVERGENT_LOOP_WEIGHT: u32 = 4;
+
+const OP_LABEL: u16 = 248;
+
+/// subgroup operations from Vulkan 1.1 and `SPV_KHR_shader_ballot`
+fn is_subgroup_operation(opcode: u16) -> bool {
+    match opcode {
+        333..=366 // OpGroupNonUniformElect .. OpGroupNonUniformQuadSwap
+        | 4421 // OpSubgroupBallotKHR
+        | 4422 // OpSubgroupFirstInvocationKHR
+        | 4428..=4430 // OpSubgroupAllKHR .. OpSubgroupAllEqualKHR
+        | 4432 // OpSubgroupReadInvocationKHR
+        => true,
+        _ => false,
+    }
+}
+
+fn is_subgroup_built_in(built_in: u32) -> bool {
+    match built_in {
+        built_in::SUBGROUP_SIZE
+        | built_in::NUM_SUBGROUPS
+        | built_in::SUBGROUP_ID
+        | built_in::SUBGROUP_LOCAL_INVOCATION_ID
+        | built_in::SUBGROUP_EQ_MASK..=built_in::SUBGROUP_LT_MASK => true,
+        _ => false,
+    }
+}
+
+/// the largest power of two that's at most `value`, or 0
+fn floor_power_of_two(value: u32) -> u32 {
+    if value == 0 {
+        0
+    } else {
+        1 << (31 - value.leading_zeros())
+    }
+}
+
+/// what limited the SIMD width of a shader
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SimdWidthLimit {
+    /// the shader has the widest width
+    None,
+    /// the application required a subgroup size (`VK_EXT_subgroup_size_control`)
+    RequiredSubgroupSize,
+    /// the shader relies on the default subgroup size: it uses subgroup operations or built-ins
+    /// without `VK_PIPELINE_SHADER_STAGE_CREATE_ALLOW_VARYING_SUBGROUP_SIZE_BIT_EXT`, or it
+    /// requires full subgroups
+    SubgroupSize,
+    RegisterPressure,
+    Float64,
+    Divergence,
+}
+
+impl fmt::Display for SimdWidthLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            SimdWidthLimit::None => "nothing",
+            SimdWidthLimit::RequiredSubgroupSize => "the required subgroup size",
+            SimdWidthLimit::SubgroupSize => "the default subgroup size",
+            SimdWidthLimit::RegisterPressure => "register pressure",
+            SimdWidthLimit::Float64 => "f64 arithmetic",
+            SimdWidthLimit::Divergence => "divergent control flow",
+        })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SimdWidth {
+    pub width: u32,
+    /// the tightest limit; the first one if several limit the width equally
+    pub limit: SimdWidthLimit,
+}
+
+/// which values and memory can differ between the invocations of a group
+#[derive(Default)]
+struct Uniformity {
+    varying: HashSet<Id>,
+    /// the variable each pointer points into. loads through pointers without one, such as
+    /// function parameters and image texel pointers, are varying
+    roots: HashMap<Id, Id>,
+    /// variables whose contents can differ between invocations
+    varying_memory: HashSet<Id>,
+}
+
+impl Uniformity {
+    fn is_varying(&self, id: Id) -> bool {
+        self.varying.contains(&id)
+    }
+    fn is_memory_varying(&self, pointer: Id) -> bool {
+        self.is_varying(pointer)
+            || self
+                .roots
+                .get(&pointer)
+                .map_or(true, |root| self.varying_memory.contains(root))
+    }
+    /// these return true if anything changed
+    fn set_varying(&mut self, id: Id) -> bool {
+        self.varying.insert(id)
+    }
+    fn set_memory_varying(&mut self, pointer: Id) -> bool {
+        match self.roots.get(&pointer) {
+            Some(&root) => self.varying_memory.insert(root),
+            None => false,
+        }
+    }
+    /// update the analysis with an instruction in a function body. returns true if anything
+    /// changed
+    fn visit(&mut self, instruction: &Instruction) -> Result<bool, Error> {
+        let operands = instruction.operands();
+        Ok(match instruction.opcode {
+            op::VARIABLE => {
+                let variable = instruction.operand(1)?;
+                let mut changed = self.roots.insert(variable, variable).is_none();
+                if operands.get(3).map_or(false, |&v| self.is_varying(v)) {
+                    changed |= self.varying_memory.insert(variable);
+                }
+                changed
+            }
+            op::ACCESS_CHAIN | op::IN_BOUNDS_ACCESS_CHAIN | op::PTR_ACCESS_CHAIN => {
+                let pointer = instruction.operand(1)?;
+                let mut changed = false;
+                if let Some(&root) = self.roots.get(&instruction.operand(2)?) {
+                    changed |= self.roots.insert(pointer, root).is_none();
+                }
+                if operands[2..].iter().any(|&v| self.is_varying(v)) {
+                    changed |= self.set_varying(pointer);
+                }
+                changed
+            }
+            op::LOAD => {
+                self.is_memory_varying(instruction.operand(2)?)
+                    && self.set_varying(instruction.operand(1)?)
+            }
+            op::STORE => {
+                let pointer = instruction.operand(0)?;
+                (self.is_varying(pointer) || self.is_varying(instruction.operand(1)?))
+                    && self.set_memory_varying(pointer)
+            }
+            op::COPY_MEMORY | op::COPY_MEMORY_SIZED => {
+                let target = instruction.operand(0)?;
+                (self.is_varying(target) || self.is_memory_varying(instruction.operand(1)?))
+                    && self.set_memory_varying(target)
+            }
+            op::ATOMIC_STORE => self.set_memory_varying(instruction.operand(0)?),
+            // each invocation gets a different result
+            op::ATOMIC_LOAD..=op::ATOMIC_XOR => {
+                let changed = self.set_varying(instruction.operand(1)?);
+                self.set_memory_varying(instruction.operand(2)?) || changed
+            }
+            // called functions aren't analyzed, so their results and anything they're passed a
+            // pointer to are varying
+            op::FUNCTION_CALL => {
+                let mut changed = self.set_varying(instruction.operand(1)?);
+                for &argument in operands.iter().skip(3) {
+                    changed |= self.set_memory_varying(argument);
+                }
+                changed
+            }
+            op::FUNCTION_PARAMETER => self.set_varying(instruction.operand(1)?),
+            // literal operands that happen to equal a varying id make the result varying too,
+            // which is only more conservative
+            _ => match function_body_result_id(instruction) {
+                Some(result) if operands.iter().skip(2).any(|&v| self.is_varying(v)) => {
+                    self.set_varying(result)
+                }
+                _ => false,
+            },
+        })
+    }
+}
+
+/// what the SIMD width of a shader is chosen from
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ShaderProfile {
+    /// an estimate of the most 32-bit words an invocation has live at once, in any function
+    pub register_pressure: u32,
+    /// the shader declares an f64 type
+    pub uses_float64: bool,
+    /// conditional branches and switches
+    pub branch_count: u32,
+    /// divergent conditional branches and switches that don't leave a loop
+    pub divergent_branch_count: u32,
+    /// loops that the invocations of a group can leave at different iterations
+    pub divergent_loop_count: u32,
+    /// the shader uses subgroup operations or built-ins, so what it computes can depend on its
+    /// SIMD width
+    pub uses_subgroup_size: bool,
+}
+
+impl ShaderProfile {
+    pub fn new(code: &[u32]) -> Result<Self, Error> {
+        let mut retval = ShaderProfile::default();
+        let mut type_words = HashMap::new();
+        let mut pointee_types = HashMap::new();
+        let mut constants = HashMap::new();
+        let mut uniformity = Uniformity::default();
+        let mut functions = Vec::new();
+        let mut current_function = None;
+        for instruction in Instructions::new(code)? {
+            let instruction = instruction?;
+            match instruction.opcode {
+                op::FUNCTION => {
+                    current_function = Some(Vec::new());
+                    continue;
+                }
+                op::FUNCTION_END => {
+                    functions.extend(current_function.take());
+                    continue;
+                }
+                _ => {}
+            }
+            if let Some(body) = &mut current_function {
+                retval.uses_subgroup_size |= is_subgroup_operation(instruction.opcode);
+                body.push(instruction);
+                continue;
+            }
+            let operands = instruction.operands();
+            match instruction.opcode {
+                op::DECORATE if instruction.operand(1)? == decoration::BUILT_IN => {
+                    retval.uses_subgroup_size |= is_subgroup_built_in(instruction.operand(2)?);
+                }
+                op::TYPE_BOOL => {
+                    type_words.insert(instruction.operand(0)?, 1);
+                }
+                op::TYPE_INT | op::TYPE_FLOAT => {
+                    let width = instruction.operand(1)?;
+                    if instruction.opcode == op::TYPE_FLOAT && width == 64 {
+                        retval.uses_float64 = true;
+                    }
+                    type_words.insert(instruction.operand(0)?, (width + 31) / 32);
+                }
+                op::TYPE_VECTOR | op::TYPE_MATRIX => {
+                    let words = type_words.get(&instruction.operand(1)?).cloned();
+                    let count = instruction.operand(2)?;
+                    type_words.insert(
+                        instruction.operand(0)?,
+                        words.unwrap_or(0).saturating_mul(count),
+                    );
+                }
+                op::TYPE_ARRAY => {
+                    let words = type_words.get(&instruction.operand(1)?).cloned();
+                    // arrays sized by specialization constants count as one element
+                    let length = constants.get(&instruction.operand(2)?).cloned();
+                    type_words.insert(
+                        instruction.operand(0)?,
+                        words.unwrap_or(0).saturating_mul(length.unwrap_or(1)),
+                    );
+                }
+                op::TYPE_STRUCT => {
+                    let words = operands[1..]
+                        .iter()
+                        .map(|member| type_words.get(member).cloned().unwrap_or(0))
+                        .fold(0u32, u32::saturating_add);
+                    type_words.insert(instruction.operand(0)?, words);
+                }
+                op::TYPE_POINTER => {
+                    pointee_types.insert(instruction.operand(0)?, instruction.operand(2)?);
+                }
+                op::CONSTANT => {
+                    constants.insert(instruction.operand(1)?, instruction.operand(2)?);
+                }
+                op::VARIABLE => {
+                    let variable = instruction.operand(1)?;
+                    uniformity.roots.insert(variable, variable);
+                    match StorageClass::from_word(instruction.operand(2)?) {
+                        Some(StorageClass::UniformConstant)
+                        | Some(StorageClass::Uniform)
+                        | Some(StorageClass::PushConstant)
+                        | Some(StorageClass::Private) => {}
+                        _ => {
+                            uniformity.varying_memory.insert(variable);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        // values can be used before they're defined through phis, so the analysis is repeated
+        // until nothing changes
+        loop {
+            let mut changed = false;
+            for instruction in functions.iter().flatten() {
+                changed |= uniformity.visit(instruction)?;
+            }
+            if !changed {
+                break;
+            }
+        }
+        for body in &functions {
+            retval.add_function(body, &uniformity, &type_words, &pointee_types)?;
+        }
+        Ok(retval)
+    }
+    fn add_function(
+        &mut self,
+        body: &[Instruction],
+        uniformity: &Uniformity,
+        type_words: &HashMap<Id, u32>,
+        pointee_types: &HashMap<Id, Id>,
+    ) -> Result<(), Error> {
+        let mut label_positions = HashMap::new();
+        for (position, instruction) in body.iter().enumerate() {
+            if instruction.opcode == OP_LABEL {
+                label_positions.insert(instruction.operand(0)?, position);
+            }
+        }
+        // `(position, condition, target positions)`; the condition is `None` for `OpBranch`
+        let mut branches = Vec::new();
+        for (position, instruction) in body.iter().enumerate() {
+            let operands = instruction.operands();
+            let (condition, targets) = match instruction.opcode {
+                op::BRANCH => (None, operands),
+                // without the branch weights
+                op::BRANCH_CONDITIONAL => (
+                    Some(instruction.operand(0)?),
+                    &operands[1..operands.len().min(3)],
+                ),
+                // the case literals can't be told apart from labels without the selector's
+                // width, but they're very unlikely to equal a label
+                op::SWITCH => (Some(instruction.operand(0)?), &operands[1..]),
+                _ => continue,
+            };
+            let targets: Vec<usize> = targets
+                .iter()
+                .filter_map(|target| label_positions.get(target).cloned())
+                .collect();
+            branches.push((position, condition, targets));
+        }
+        // blocks are in dominance order, so a branch to an earlier block is a loop's back edge.
+        // each loop is `(header position, last back edge position)`
+        let mut loops: Vec<(usize, usize)> = Vec::new();
+        for &(position, _, ref targets) in &branches {
+            for &target in targets.iter().filter(|&&target| target <= position) {
+                match loops.iter_mut().find(|l| l.0 == target) {
+                    Some(l) => l.1 = l.1.max(position),
+                    None => loops.push((target, position)),
+                }
+            }
+        }
+        let mut divergent_loops = HashSet::new();
+        for &(position, condition, ref targets) in &branches {
+            let condition = match condition {
+                Some(condition) => condition,
+                None => continue,
+            };
+            self.branch_count += 1;
+            if !uniformity.is_varying(condition) {
+                continue;
+            }
+            let mut leaves_loop = false;
+            for (index, &(header, back_edge)) in loops.iter().enumerate() {
+                if header <= position
+                    && position <= back_edge
+                    && targets
+                        .iter()
+                        .any(|&target| target < header || target > back_edge)
+                {
+                    divergent_loops.insert(index);
+                    leaves_loop = true;
+                }
+            }
+            if !leaves_loop {
+                self.divergent_branch_count += 1;
+            }
+        }
+        self.divergent_loop_count += divergent_loops.len() as u32;
+        // `(definition position, words)`
+        let mut definitions = HashMap::new();
+        for (position, instruction) in body.iter().enumerate() {
+            let result = match function_body_result_id(instruction) {
+                Some(result) if instruction.opcode != OP_LABEL => result,
+                _ => continue,
+            };
+            let result_type = instruction.operand(0)?;
+            let words = if instruction.opcode == op::VARIABLE {
+                // variables in functions are promoted to registers
+                pointee_types
+                    .get(&result_type)
+                    .and_then(|pointee_type| type_words.get(pointee_type))
+            } else {
+                type_words.get(&result_type)
+            };
+            match words {
+                Some(&words) if words != 0 => {
+                    definitions.insert(result, (position, words));
+                }
+                _ => {}
+            }
+        }
+        // `(first use position, last use position)`, which is before the definition for values
+        // used by phis at back edges
+        let mut uses: HashMap<Id, (usize, usize)> = HashMap::new();
+        for (position, instruction) in body.iter().enumerate() {
+            let skipped = if function_body_result_id(instruction).is_some() {
+                2
+            } else {
+                0
+            };
+            for operand in instruction.operands().iter().skip(skipped) {
+                if definitions.contains_key(operand) {
+                    let range = uses.entry(*operand).or_insert((position, position));
+                    range.0 = range.0.min(position);
+                    range.1 = range.1.max(position);
+                }
+            }
+        }
+        let mut live_changes = vec![0i64; body.len() + 1];
+        for (id, &(first_use, last_use)) in &uses {
+            let (definition, words) = definitions[id];
+            let start = definition.min(first_use);
+            let mut end = definition.max(last_use);
+            for &(header, back_edge) in &loops {
+                // values that are live into a loop stay live for all of its iterations
+                if start < header && end >= header {
+                    end = end.max(back_edge);
+                }
+            }
+            live_changes[start] += i64::from(words);
+            live_changes[end + 1] -= i64::from(words);
+        }
+        let mut live = 0;
+        for live_change in live_changes {
+            live += live_change;
+            self.register_pressure = self.register_pressure.max(live as u32);
+        }
+        Ok(())
+    }
+    /// the widest width up to `max_width`, which is a power of two, that nothing rules out
+    pub fn choose_simd_width(&self, max_width: u32) -> SimdWidth {
+        assert!(max_width.is_power_of_two());
+        let mut retval = SimdWidth {
+            width: max_width,
+            limit: SimdWidthLimit::None,
+        };
+        {
+            let mut limit_to = |width: u32, limit| {
+                let width = width.max(1);
+                if width < retval.width {
+                    retval = SimdWidth { width, limit };
+                }
+            };
+            if let Some(lanes) = REGISTER_BUDGET_LANES.checked_div(self.register_pressure) {
+                limit_to(floor_power_of_two(lanes), SimdWidthLimit::RegisterPressure);
+            }
+            if self.uses_float64 {
+                // f64 vectors at half the width fill as many registers as f32 vectors at the full
+                // width
+                limit_to(max_width / 2, SimdWidthLimit::Float64);
+            }
+            let divergence = DIVERGENT_LOOP_WEIGHT
+                .saturating_mul(self.divergent_loop_count)
+                .saturating_add(self.divergent_branch_count);
+            limit_to(
+                max_width
+                    .checked_shr(divergence / DIVERGENT_BRANCHES_PER_HALVING)
+                    .unwrap_or(0),
+                SimdWidthLimit::Divergence,
+            );
+        }
+        retval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::{write_instruction, MAGIC_NUMBER};
+
+    const OP_TYPE_VOID: u16 = 19;
+    const OP_TYPE_FUNCTION: u16 = 33;
+    const OP_I_ADD: u16 = 128;
+    const OP_S_LESS_THAN: u16 = 177;
+    const OP_F_ORD_LESS_THAN: u16 = 184;
+    const OP_PHI: u16 = 245;
+    const OP_LOOP_MERGE: u16 = 246;
+    const OP_SELECTION_MERGE: u16 = 247;
+
+    /// ids:
+    /// 1: void, 2: fn void(), 3: float, 4: ptr input float, 5: bool, 6: int, 7: ptr function int
+    /// 8: int 0, 9: int 10, 10: float 0, 11: int 1, 20: input variable, 30: main
+    /// 40..: labels, 50..: temporaries
+    fn test_module() -> Vec<u32> {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 60, 0];
+        let mut write = |opcode, operands: &[u32]| write_instruction(&mut code, opcode, operands);
+        write(OP_TYPE_VOID, &[1]);
+        write(OP_TYPE_FUNCTION, &[2, 1]);
+        write(op::TYPE_FLOAT, &[3, 32]);
+        write(op::TYPE_POINTER, &[4, StorageClass::Input as u32, 3]);
+        write(op::TYPE_BOOL, &[5]);
+        write(op::TYPE_INT, &[6, 32, 1]);
+        write(op::TYPE_POINTER, &[7, StorageClass::Function as u32, 6]);
+        write(op::CONSTANT, &[6, 8, 0]);
+        write(op::CONSTANT, &[6, 9, 10]);
+        write(op::CONSTANT, &[3, 10, 0]);
+        write(op::CONSTANT, &[6, 11, 1]);
+        write(op::VARIABLE, &[4, 20, StorageClass::Input as u32]);
+        write(op::FUNCTION, &[1, 30, 0, 2]);
+        write(OP_LABEL, &[40]);
+        write(op::VARIABLE, &[7, 50, StorageClass::Function as u32]);
+        write(op::STORE, &[50, 8]);
+        write(op::BRANCH, &[41]);
+        // a loop with a uniform counter
+        write(OP_LABEL, &[41]);
+        write(op::LOAD, &[6, 51, 50]);
+        write(OP_S_LESS_THAN, &[5, 52, 51, 9]);
+        write(OP_LOOP_MERGE, &[43, 42, 0]);
+        write(op::BRANCH_CONDITIONAL, &[52, 42, 43]);
+        write(OP_LABEL, &[42]);
+        write(OP_I_ADD, &[6, 53, 51, 11]);
+        write(op::STORE, &[50, 53]);
+        write(op::BRANCH, &[41]);
+        // a selection on an input
+        write(OP_LABEL, &[43]);
+        write(op::LOAD, &[3, 54, 20]);
+        write(OP_F_ORD_LESS_THAN, &[5, 55, 54, 10]);
+        write(OP_SELECTION_MERGE, &[45, 0]);
+        write(op::BRANCH_CONDITIONAL, &[55, 44, 45]);
+        write(OP_LABEL, &[44]);
+        write(op::BRANCH, &[45]);
+        write(OP_LABEL, &[45]);
+        write(op::BRANCH, &[46]);
+        // a loop that runs until a value computed from the input is big enough
+        write(OP_LABEL, &[46]);
+        write(OP_PHI, &[3, 56, 54, 45, 57, 46]);
+        write(op::F_ADD, &[3, 57, 56, 54]);
+        write(OP_F_ORD_LESS_THAN, &[5, 58, 57, 10]);
+        write(OP_LOOP_MERGE, &[47, 46, 0]);
+        write(op::BRANCH_CONDITIONAL, &[58, 46, 47]);
+        write(OP_LABEL, &[47]);
+        write(op::RETURN, &[]);
+        write(op::FUNCTION_END, &[]);
+        code
+    }
+
+    #[test]
+    fn test_shader_profile() {
+        assert_eq!(
+            ShaderProfile::new(&test_module()).unwrap(),
+            ShaderProfile {
+                register_pressure: 3,
+                uses_float64: false,
+                branch_count: 3,
+                divergent_branch_count: 1,
+                divergent_loop_count: 1,
+                uses_subgroup_size: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_choose_simd_width() {
+        let profile = ShaderProfile::default();
+        assert_eq!(
+            profile.choose_simd_width(16),
+            SimdWidth {
+                width: 16,
+                limit: SimdWidthLimit::None,
+            }
+        );
+        let profile = ShaderProfile {
+            register_pressure: 40,
+            uses_float64: true,
+            ..ShaderProfile::default()
+        };
+        assert_eq!(
+            profile.choose_simd_width(16),
+            SimdWidth {
+                width: 4,
+                limit: SimdWidthLimit::RegisterPressure,
+            }
+        );
+        let profile = ShaderProfile {
+            register_pressure: 10,
+            uses_float64: true,
+            divergent_branch_count: 2,
+            divergent_loop_count: 1,
+            ..ShaderProfile::default()
+        };
+        assert_eq!(
+            profile.choose_simd_width(16),
+            SimdWidth {
+                width: 8,
+                limit: SimdWidthLimit::Float64,
+            }
+        );
+        let profile = ShaderProfile {
+            divergent_loop_count: 1000,
+            ..ShaderProfile::default()
+        };
+        assert_eq!(
+            profile.choose_simd_width(16),
+            SimdWidth {
+                width: 1,
+                limit: SimdWidthLimit::Divergence,
+            }
+        );
+        assert_eq!(profile.choose_simd_width(1).limit, SimdWidthLimit::None);
+    }
+}
@@ -0,0 +1,394 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! minimal word-level SPIR-V reader
+//!
+//! this only knows about the handful of instructions needed to reflect a module's interface;
+//! everything else is passed through as opaque words.
+
+use std::error;
+use std::fmt;
+use std::fmt::Write;
+
+pub use spirv_parser::{built_in, decoration, execution_mode, op};
+pub use spirv_parser::{HEADER_WORD_COUNT, MAGIC_NUMBER};
+
+pub type Id = u32;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[repr(u32)]
+pub enum ExecutionModel {
+    Vertex = 0,
+    TessellationControl = 1,
+    TessellationEvaluation = 2,
+    Geometry = 3,
+    Fragment = 4,
+    GLCompute = 5,
+    Kernel = 6,
+}
+
+impl ExecutionModel {
+    pub fn from_word(v: u32) -> Option<Self> {
+        Some(match v {
+            0 => ExecutionModel::Vertex,
+            1 => ExecutionModel::TessellationControl,
+            2 => ExecutionModel::TessellationEvaluation,
+            3 => ExecutionModel::Geometry,
+            4 => ExecutionModel::Fragment,
+            5 => ExecutionModel::GLCompute,
+            6 => ExecutionModel::Kernel,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[repr(u32)]
+pub enum StorageClass {
+    UniformConstant = 0,
+    Input = 1,
+    Uniform = 2,
+    Output = 3,
+    Workgroup = 4,
+    CrossWorkgroup = 5,
+    Private = 6,
+    Function = 7,
+    Generic = 8,
+    PushConstant = 9,
+    AtomicCounter = 10,
+    Image = 11,
+    StorageBuffer = 12,
+}
+
+impl StorageClass {
+    pub fn from_word(v: u32) -> Option<Self> {
+        Some(match v {
+            0 => StorageClass::UniformConstant,
+            1 => StorageClass::Input,
+            2 => StorageClass::Uniform,
+            3 => StorageClass::Output,
+            4 => StorageClass::Workgroup,
+            5 => StorageClass::CrossWorkgroup,
+            6 => StorageClass::Private,
+            7 => StorageClass::Function,
+            8 => StorageClass::Generic,
+            9 => StorageClass::PushConstant,
+            10 => StorageClass::AtomicCounter,
+            11 => StorageClass::Image,
+            12 => StorageClass::StorageBuffer,
+            _ => return None,
+        })
+    }
+    /// true if variables in this storage class are accessed through descriptors
+    pub fn is_descriptor_backed(self) -> bool {
+        match self {
+            StorageClass::UniformConstant | StorageClass::Uniform | StorageClass::StorageBuffer => {
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Error {
+    TooShort,
+    InvalidMagicNumber(u32),
+    InvalidInstructionLength { offset: usize },
+    InvalidLiteralString { offset: usize },
+    InvalidOperands { offset: usize, opcode: u16 },
+    EntryPointNotFound { name: String },
+    UnsupportedScalarType { is_float: bool, width: u32 },
+    UnsupportedAtomicWidth(u32),
+    /// a cooperative matrix type or operation with no runtime kernel; has the type or result id
+    UnsupportedCooperativeMatrix(Id),
+    FunctionHasNoBlocks,
+    DuplicateLabel(Id),
+    UnknownLabel(Id),
+    ControlFlowTooComplex,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::TooShort => write!(f, "SPIR-V module is too short"),
+            Error::InvalidMagicNumber(v) => write!(f, "invalid SPIR-V magic number: {:#010X}", v),
+            Error::InvalidInstructionLength { offset } => {
+                write!(f, "invalid SPIR-V instruction length at word {}", offset)
+            }
+            Error::InvalidLiteralString { offset } => {
+                write!(f, "invalid SPIR-V literal string at word {}", offset)
+            }
+            Error::InvalidOperands { offset, opcode } => write!(
+                f,
+                "invalid operands for SPIR-V opcode {} at word {}",
+                opcode, offset
+            ),
+            Error::EntryPointNotFound { name } => write!(f, "entry point not found: {:?}", name),
+            Error::UnsupportedScalarType { is_float, width } => write!(
+                f,
+                "unsupported SPIR-V scalar type: {}{}",
+                if *is_float { "f" } else { "i" },
+                width
+            ),
+            Error::UnsupportedAtomicWidth(width) => {
+                write!(f, "unsupported SPIR-V atomic width: {}", width)
+            }
+            Error::UnsupportedCooperativeMatrix(id) => {
+                write!(f, "unsupported SPIR-V cooperative matrix: {}", id)
+            }
+            Error::FunctionHasNoBlocks => write!(f, "SPIR-V function has no blocks"),
+            Error::DuplicateLabel(id) => write!(f, "duplicate SPIR-V label: {}", id),
+            Error::UnknownLabel(id) => write!(f, "branch to unknown SPIR-V label: {}", id),
+            Error::ControlFlowTooComplex => {
+                write!(f, "SPIR-V control flow is too complex to structurize")
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Header {
+    pub version: u32,
+    pub generator: u32,
+    pub bound: u32,
+    pub schema: u32,
+}
+
+impl Header {
+    pub fn parse(code: &[u32]) -> Result<Self, Error> {
+        if code.len() < HEADER_WORD_COUNT {
+            return Err(Error::TooShort);
+        }
+        if code[0] != MAGIC_NUMBER {
+            return Err(Error::InvalidMagicNumber(code[0]));
+        }
+        Ok(Header {
+            version: code[1],
+            generator: code[2],
+            bound: code[3],
+            schema: code[4],
+        })
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Instruction<'a> {
+    /// offset in words from the start of the module
+    pub offset: usize,
+    pub opcode: u16,
+    /// all the words of the instruction, including the opcode word
+    pub words: &'a [u32],
+}
+
+impl<'a> Instruction<'a> {
+    pub fn operands(&self) -> &'a [u32] {
+        &self.words[1..]
+    }
+    pub fn operand(&self, index: usize) -> Result<u32, Error> {
+        self.operands()
+            .get(index)
+            .cloned()
+            .ok_or(Error::InvalidOperands {
+                offset: self.offset,
+                opcode: self.opcode,
+            })
+    }
+    /// parse a literal string starting at operand `index`,
+    /// returning the string and the index of the operand following it
+    pub fn literal_string(&self, index: usize) -> Result<(String, usize), Error> {
+        let operands = self.operands();
+        if index > operands.len() {
+            return Err(Error::InvalidOperands {
+                offset: self.offset,
+                opcode: self.opcode,
+            });
+        }
+        let mut bytes = Vec::new();
+        for (word_index, &word) in operands[index..].iter().enumerate() {
+            for byte_index in 0..4 {
+                let byte = (word >> (8 * byte_index)) as u8;
+                if byte == 0 {
+                    return String::from_utf8(bytes)
+                        .map(|v| (v, index + word_index + 1))
+                        .map_err(|_| Error::InvalidLiteralString {
+                            offset: self.offset,
+                        });
+                }
+                bytes.push(byte);
+            }
+        }
+        Err(Error::InvalidLiteralString {
+            offset: self.offset,
+        })
+    }
+}
+
+/// iterator over the instructions following the header of a SPIR-V module
+#[derive(Clone)]
+pub struct Instructions<'a> {
+    code: &'a [u32],
+    offset: usize,
+}
+
+impl<'a> Instructions<'a> {
+    pub fn new(code: &'a [u32]) -> Result<Self, Error> {
+        Header::parse(code)?;
+        Ok(Instructions {
+            code,
+            offset: HEADER_WORD_COUNT,
+        })
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>, Error>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let offset = self.offset;
+        let first_word = *self.code.get(offset)?;
+        let length = (first_word >> 16) as usize;
+        if length == 0 || offset + length > self.code.len() {
+            self.offset = self.code.len();
+            return Some(Err(Error::InvalidInstructionLength { offset }));
+        }
+        self.offset += length;
+        Some(Ok(Instruction {
+            offset,
+            opcode: first_word as u16,
+            words: &self.code[offset..offset + length],
+        }))
+    }
+}
+
+/// debug and annotation instructions that only apply to their target
+pub fn is_annotation(opcode: u16) -> bool {
+    match opcode {
+        op::NAME
+        | op::MEMBER_NAME
+        | op::DECORATE
+        | op::MEMBER_DECORATE
+        | op::DECORATION_GROUP
+        | op::GROUP_DECORATE
+        | op::GROUP_MEMBER_DECORATE
+        | op::DECORATE_ID
+        | op::DECORATE_STRING => true,
+        _ => false,
+    }
+}
+
+/// instructions that end a block
+pub fn is_block_terminator(opcode: u16) -> bool {
+    match opcode {
+        op::BRANCH
+        | op::BRANCH_CONDITIONAL
+        | op::SWITCH
+        | op::KILL
+        | op::RETURN
+        | op::RETURN_VALUE
+        | op::UNREACHABLE => true,
+        _ => false,
+    }
+}
+
+/// encode `opcode` and `operands` as an instruction, appending it to `output`
+pub fn write_instruction(output: &mut Vec<u32>, opcode: u16, operands: &[u32]) {
+    let length = operands.len() + 1;
+    assert!(length <= 0xFFFF, "SPIR-V instruction too long");
+    output.push((length as u32) << 16 | u32::from(opcode));
+    output.extend_from_slice(operands);
+}
+
+/// encode `s` as a literal string, appending it to `output`
+pub fn write_literal_string(output: &mut Vec<u32>, s: &str) {
+    let bytes = s.as_bytes();
+    for chunk in bytes.chunks(4) {
+        let mut word = 0;
+        for (i, &byte) in chunk.iter().enumerate() {
+            word |= u32::from(byte) << (8 * i);
+        }
+        output.push(word);
+    }
+    if bytes.len() % 4 == 0 {
+        output.push(0);
+    }
+}
+
+/// the index of the operand that starts a literal string, for the opcodes that have one
+fn literal_string_operand(opcode: u16) -> Option<usize> {
+    match opcode {
+        op::NAME | op::STRING => Some(1),
+        op::MEMBER_NAME | op::ENTRY_POINT => Some(2),
+        _ => None,
+    }
+}
+
+/// word-level disassembly of `code`, for debugging.
+/// unknown opcodes are written as `Op<number>` and operands other than literal strings are
+/// written as plain numbers
+pub fn disassemble(code: &[u32]) -> Result<String, Error> {
+    let header = Header::parse(code)?;
+    let mut retval = String::new();
+    writeln!(
+        retval,
+        "; SPIR-V\n; Version: {}.{}\n; Generator: {:#010X}\n; Bound: {}\n; Schema: {}",
+        header.version >> 16 & 0xFF,
+        header.version >> 8 & 0xFF,
+        header.generator,
+        header.bound,
+        header.schema
+    )
+    .unwrap();
+    for instruction in Instructions::new(code)? {
+        let instruction = instruction?;
+        match op::name(instruction.opcode) {
+            Some(name) => retval += name,
+            None => write!(retval, "Op{}", instruction.opcode).unwrap(),
+        }
+        let operands = instruction.operands();
+        let mut index = 0;
+        while index < operands.len() {
+            if literal_string_operand(instruction.opcode) == Some(index) {
+                let (string, next_index) = instruction.literal_string(index)?;
+                write!(retval, " {:?}", string).unwrap();
+                index = next_index;
+            } else {
+                write!(retval, " {}", operands[index]).unwrap();
+                index += 1;
+            }
+        }
+        retval.push('\n');
+    }
+    Ok(retval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0300, 0x0008_0007, 20, 0];
+        let mut operands = vec![4, 5];
+        write_literal_string(&mut operands, "main");
+        operands.push(7);
+        write_instruction(&mut code, op::ENTRY_POINT, &operands);
+        write_instruction(&mut code, op::LABEL, &[11]);
+        // not a SPIR-V opcode
+        write_instruction(&mut code, 0xFFFF, &[12]);
+        write_instruction(&mut code, op::RETURN, &[]);
+        assert_eq!(
+            disassemble(&code).unwrap(),
+            "; SPIR-V\n\
+             ; Version: 1.3\n\
+             ; Generator: 0x00080007\n\
+             ; Bound: 20\n\
+             ; Schema: 0\n\
+             OpEntryPoint 4 5 \"main\" 7\n\
+             OpLabel 11\n\
+             Op65535 12\n\
+             OpReturn\n"
+        );
+    }
+}
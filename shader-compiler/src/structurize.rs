@@ -0,0 +1,1216 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! control-flow structurization
+//!
+//! the translator only handles structured control flow: every loop has a single header, a single
+//! continue target and a single merge block, and every conditional branch or switch is the
+//! header of a single-entry selection construct with its own merge block. some toolchains and
+//! the relaxed rules from `maintenance` extensions produce functions that don't follow those
+//! rules, so instead of rejecting them the CFG is normalized here:
+//!
+//! * irreducible regions are made reducible by duplicating blocks until each cycle has a single
+//!   entry.
+//! * loops get an empty synthetic continue target, and their exits are funneled into a single
+//!   merge block. when the exits go to more than one block, the merge block is a dispatch block
+//!   that branches on a selector variable that is assigned along each exit edge.
+//! * selections that share a merge block, or that are entered from outside, get synthetic merge
+//!   blocks or have their blocks duplicated.
+//!
+//! the structurizer only looks at the edges between blocks; existing merge instructions are
+//! ignored and recomputed. blocks can be duplicated, so the translator must give the
+//! instructions of each copy new result ids, and must rewrite the `OpPhi` instructions of blocks
+//! whose predecessors changed using the `kind` of the predecessors.
+
+use spirv::{Error, Id};
+use std::collections::{HashMap, HashSet};
+
+/// structurization gives up when the number of blocks grows past this multiple of the original
+/// number of blocks
+const MAX_GROWTH_FACTOR: usize = 16;
+
+/// a basic block of the function to be structurized
+#[derive(Clone, Debug)]
+pub struct InputBlock {
+    pub label: Id,
+    /// the targets of the block's terminator, in operand order
+    pub successors: Vec<Id>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SyntheticBlock {
+    /// an empty block that unconditionally branches to its only successor
+    Branch,
+    /// an empty block that branches to the successor selected by the selector variable, which is
+    /// set by the `SelectorAssignment`s along the edges leading to it.
+    /// copies of a dispatch block share its selector variable.
+    Dispatch { selector: usize },
+    /// an empty block that ends in `OpUnreachable`, used as the merge block of constructs that
+    /// are never exited normally
+    Unreachable,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum BlockKind {
+    /// a copy of the input block with the given label
+    Original(Id),
+    Synthetic(SyntheticBlock),
+}
+
+/// set the selector variable `selector` to `case`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct SelectorAssignment {
+    pub selector: usize,
+    pub case: usize,
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Edge {
+    pub target: usize,
+    /// assignments to make when taking this edge
+    pub assignments: Vec<SelectorAssignment>,
+}
+
+impl Edge {
+    fn new(target: usize) -> Self {
+        Edge {
+            target,
+            assignments: Vec::new(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Merge {
+    Selection {
+        merge: usize,
+    },
+    Loop {
+        merge: usize,
+        continue_target: usize,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct StructuredBlock {
+    pub kind: BlockKind,
+    /// for `Original` blocks, the edges corresponding to `InputBlock::successors`;
+    /// for `Dispatch` blocks, the edge for each case
+    pub successors: Vec<Edge>,
+    /// the merge instruction for header blocks
+    pub merge: Option<Merge>,
+}
+
+#[derive(Clone, Debug)]
+pub struct StructuredCfg {
+    pub entry: usize,
+    pub blocks: Vec<StructuredBlock>,
+    /// the number of selector variables used by `Dispatch` blocks
+    pub selector_count: usize,
+}
+
+impl StructuredCfg {
+    /// the edges from each loop's continue target back to its header, as
+    /// `(block, successor index)`. every cycle goes through one of them, so checking the
+    /// `Watchdog` along them is enough to stop any infinite loop
+    pub fn loop_back_edges(&self) -> Vec<(usize, usize)> {
+        let mut retval = Vec::new();
+        for (header, block) in self.blocks.iter().enumerate() {
+            if let Some(Merge::Loop {
+                continue_target, ..
+            }) = block.merge
+            {
+                for (index, edge) in self.blocks[continue_target].successors.iter().enumerate() {
+                    if edge.target == header {
+                        retval.push((continue_target, index));
+                    }
+                }
+            }
+        }
+        retval
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Block {
+    kind: BlockKind,
+    successors: Vec<Edge>,
+}
+
+struct Graph {
+    entry: usize,
+    blocks: Vec<Block>,
+    selector_count: usize,
+    max_block_count: usize,
+}
+
+impl Graph {
+    fn new(input_blocks: &[InputBlock]) -> Result<Self, Error> {
+        if input_blocks.is_empty() {
+            return Err(Error::FunctionHasNoBlocks);
+        }
+        let mut indexes = HashMap::new();
+        for (index, block) in input_blocks.iter().enumerate() {
+            if indexes.insert(block.label, index).is_some() {
+                return Err(Error::DuplicateLabel(block.label));
+            }
+        }
+        let mut blocks = Vec::with_capacity(input_blocks.len());
+        for block in input_blocks {
+            let mut successors = Vec::with_capacity(block.successors.len());
+            for &label in &block.successors {
+                let target = *indexes.get(&label).ok_or(Error::UnknownLabel(label))?;
+                successors.push(Edge::new(target));
+            }
+            blocks.push(Block {
+                kind: BlockKind::Original(block.label),
+                successors,
+            });
+        }
+        Ok(Graph {
+            entry: 0,
+            blocks,
+            selector_count: 0,
+            max_block_count: input_blocks.len() * MAX_GROWTH_FACTOR,
+        })
+    }
+    fn add_block(&mut self, kind: BlockKind, successors: Vec<Edge>) -> Result<usize, Error> {
+        if self.blocks.len() >= self.max_block_count {
+            return Err(Error::ControlFlowTooComplex);
+        }
+        self.blocks.push(Block { kind, successors });
+        Ok(self.blocks.len() - 1)
+    }
+    fn add_synthetic_branch(&mut self, target: usize) -> Result<usize, Error> {
+        self.add_block(
+            BlockKind::Synthetic(SyntheticBlock::Branch),
+            vec![Edge::new(target)],
+        )
+    }
+    fn successor_targets(&self, block: usize) -> Vec<usize> {
+        self.blocks[block]
+            .successors
+            .iter()
+            .map(|edge| edge.target)
+            .collect()
+    }
+    fn distinct_successor_targets(&self, block: usize) -> Vec<usize> {
+        let mut retval = Vec::new();
+        for edge in &self.blocks[block].successors {
+            if !retval.contains(&edge.target) {
+                retval.push(edge.target);
+            }
+        }
+        retval
+    }
+    /// all the edges `(block, successor index)` going to `target`
+    fn edges_to(&self, target: usize) -> Vec<(usize, usize)> {
+        let mut retval = Vec::new();
+        for (block_index, block) in self.blocks.iter().enumerate() {
+            for (edge_index, edge) in block.successors.iter().enumerate() {
+                if edge.target == target {
+                    retval.push((block_index, edge_index));
+                }
+            }
+        }
+        retval
+    }
+    fn redirect(&mut self, edges: &[(usize, usize)], target: usize) {
+        for &(block, edge_index) in edges {
+            self.blocks[block].successors[edge_index].target = target;
+        }
+    }
+    /// duplicate `blocks`, returning the map from each block to its copy.
+    /// edges between the copied blocks are redirected to the copies.
+    fn copy_blocks(&mut self, blocks: &[usize]) -> Result<HashMap<usize, usize>, Error> {
+        let mut map = HashMap::new();
+        for &block in blocks {
+            let kind = self.blocks[block].kind;
+            map.insert(block, self.add_block(kind, Vec::new())?);
+        }
+        for &block in blocks {
+            let successors = self.blocks[block]
+                .successors
+                .iter()
+                .map(|edge| remap_edge(edge, &map))
+                .collect();
+            self.blocks[map[&block]].successors = successors;
+        }
+        Ok(map)
+    }
+    /// redirect `edges` to the copies of their targets from `map`
+    fn redirect_to_copies(&mut self, edges: &[(usize, usize)], map: &HashMap<usize, usize>) {
+        for &(block, edge_index) in edges {
+            let edge = &mut self.blocks[block].successors[edge_index];
+            edge.target = map[&edge.target];
+        }
+    }
+    fn remove_unreachable_blocks(&mut self) {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut worklist = vec![self.entry];
+        reachable[self.entry] = true;
+        while let Some(block) = worklist.pop() {
+            for edge in &self.blocks[block].successors {
+                if !reachable[edge.target] {
+                    reachable[edge.target] = true;
+                    worklist.push(edge.target);
+                }
+            }
+        }
+        if reachable.iter().all(|&v| v) {
+            return;
+        }
+        let mut new_indexes = Vec::with_capacity(self.blocks.len());
+        let mut new_index = 0;
+        for &v in &reachable {
+            new_indexes.push(if v { Some(new_index) } else { None });
+            if v {
+                new_index += 1;
+            }
+        }
+        let blocks = self.blocks.drain(..).collect::<Vec<_>>();
+        for (block, v) in blocks.into_iter().zip(reachable) {
+            if !v {
+                continue;
+            }
+            let successors = block
+                .successors
+                .into_iter()
+                .map(|edge| Edge {
+                    target: new_indexes[edge.target].unwrap(),
+                    assignments: edge.assignments,
+                })
+                .collect();
+            self.blocks.push(Block {
+                kind: block.kind,
+                successors,
+            });
+        }
+        self.entry = new_indexes[self.entry].unwrap();
+    }
+}
+
+fn remap_edge(edge: &Edge, map: &HashMap<usize, usize>) -> Edge {
+    Edge {
+        target: *map.get(&edge.target).unwrap_or(&edge.target),
+        assignments: edge.assignments.clone(),
+    }
+}
+
+/// the dominator tree of the nodes reachable from `entry`
+struct Dominators {
+    entry: usize,
+    /// the reachable nodes in reverse postorder
+    rpo: Vec<usize>,
+    rpo_index: Vec<Option<usize>>,
+    /// the predecessors of each node that are reachable
+    predecessors: Vec<Vec<usize>>,
+    idom: Vec<Option<usize>>,
+}
+
+impl Dominators {
+    /// uses the algorithm from "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy
+    fn new<F: Fn(usize) -> Vec<usize>>(node_count: usize, entry: usize, successors: F) -> Self {
+        let mut postorder = Vec::with_capacity(node_count);
+        let mut visited = vec![false; node_count];
+        visited[entry] = true;
+        let mut stack = vec![(entry, successors(entry), 0)];
+        while let Some((node, node_successors, index)) = stack.pop() {
+            if let Some(&successor) = node_successors.get(index) {
+                stack.push((node, node_successors, index + 1));
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, successors(successor), 0));
+                }
+            } else {
+                postorder.push(node);
+            }
+        }
+        let rpo: Vec<usize> = postorder.into_iter().rev().collect();
+        let mut rpo_index = vec![None; node_count];
+        let mut predecessors = vec![Vec::new(); node_count];
+        for (index, &node) in rpo.iter().enumerate() {
+            rpo_index[node] = Some(index);
+            for successor in successors(node) {
+                if !predecessors[successor].contains(&node) {
+                    predecessors[successor].push(node);
+                }
+            }
+        }
+        let mut idom = vec![None; node_count];
+        idom[entry] = Some(entry);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in &rpo[1..] {
+                let mut new_idom = None;
+                for &predecessor in &predecessors[node] {
+                    if idom[predecessor].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => predecessor,
+                        Some(mut other) => {
+                            let mut finger = predecessor;
+                            while finger != other {
+                                while rpo_index[finger] > rpo_index[other] {
+                                    finger = idom[finger].unwrap();
+                                }
+                                while rpo_index[other] > rpo_index[finger] {
+                                    other = idom[other].unwrap();
+                                }
+                            }
+                            finger
+                        }
+                    });
+                }
+                if new_idom != idom[node] {
+                    idom[node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        Dominators {
+            entry,
+            rpo,
+            rpo_index,
+            predecessors,
+            idom,
+        }
+    }
+    fn dominates(&self, dominator: usize, mut node: usize) -> bool {
+        if self.idom[node].is_none() {
+            return false;
+        }
+        loop {
+            if node == dominator {
+                return true;
+            }
+            if node == self.entry {
+                return false;
+            }
+            node = self.idom[node].unwrap();
+        }
+    }
+    /// the immediate dominator, or `None` for the entry and unreachable nodes
+    fn immediate_dominator(&self, node: usize) -> Option<usize> {
+        if node == self.entry {
+            None
+        } else {
+            self.idom[node]
+        }
+    }
+}
+
+fn graph_dominators(graph: &Graph) -> Dominators {
+    Dominators::new(graph.blocks.len(), graph.entry, |block| {
+        graph.successor_targets(block)
+    })
+}
+
+/// the strongly connected component containing `node`, restricted to the nodes in `scope`
+fn strongly_connected_component(
+    dominators: &Dominators,
+    graph: &Graph,
+    node: usize,
+    scope: &HashSet<usize>,
+) -> HashSet<usize> {
+    let search = |get_next: &Fn(usize) -> Vec<usize>| {
+        let mut found = HashSet::new();
+        found.insert(node);
+        let mut worklist = vec![node];
+        while let Some(current) = worklist.pop() {
+            for next in get_next(current) {
+                if scope.contains(&next) && found.insert(next) {
+                    worklist.push(next);
+                }
+            }
+        }
+        found
+    };
+    let forward = search(&|block| graph.successor_targets(block));
+    let backward = search(&|block| dominators.predecessors[block].clone());
+    forward.intersection(&backward).cloned().collect()
+}
+
+/// duplicate blocks until all cycles have a single entry, returning `true` if the graph changed
+fn split_irreducible_regions(graph: &mut Graph, dominators: &Dominators) -> Result<bool, Error> {
+    for &source in &dominators.rpo {
+        for target in graph.successor_targets(source) {
+            if dominators.rpo_index[target] > dominators.rpo_index[source]
+                || dominators.dominates(target, source)
+            {
+                continue;
+            }
+            // retreating edge that isn't a back edge: find the smallest cycle around it with
+            // more than one entry
+            let mut scope: HashSet<usize> = dominators.rpo.iter().cloned().collect();
+            loop {
+                let component = strongly_connected_component(dominators, graph, target, &scope);
+                let mut entries: Vec<usize> = component
+                    .iter()
+                    .cloned()
+                    .filter(|&block| {
+                        block == graph.entry
+                            || dominators.predecessors[block]
+                                .iter()
+                                .any(|predecessor| !component.contains(predecessor))
+                    })
+                    .collect();
+                entries.sort_by_key(|&block| dominators.rpo_index[block]);
+                if entries.len() < 2 {
+                    scope = component;
+                    scope.remove(&entries[0]);
+                    continue;
+                }
+                // give the second entry its own copy of the cycle, which only reenters the
+                // original at the first entry
+                let header = entries[0];
+                let entry = entries[1];
+                let mut copied_blocks: Vec<usize> = component
+                    .iter()
+                    .cloned()
+                    .filter(|&block| block != header)
+                    .collect();
+                copied_blocks.sort();
+                let entering_edges: Vec<_> = graph
+                    .edges_to(entry)
+                    .into_iter()
+                    .filter(|(block, _)| !component.contains(block))
+                    .collect();
+                let map = graph.copy_blocks(&copied_blocks)?;
+                graph.redirect_to_copies(&entering_edges, &map);
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+struct Loop {
+    header: usize,
+    body: HashSet<usize>,
+    parent: Option<usize>,
+    /// the blocks in `body` that branch to `header`
+    latches: Vec<usize>,
+    /// the edges `(block, successor index)` leaving `body`
+    exits: Vec<(usize, usize)>,
+    /// the distinct targets of `exits`, in reverse postorder
+    exit_targets: Vec<usize>,
+}
+
+struct Loops {
+    /// ordered by the reverse postorder of the headers, so outer loops come first
+    loops: Vec<Loop>,
+    /// the innermost loop containing each block
+    innermost: Vec<Option<usize>>,
+    header_loops: HashMap<usize, usize>,
+}
+
+impl Loops {
+    /// requires the graph to be reducible
+    fn new(graph: &Graph, dominators: &Dominators) -> Self {
+        let mut loops = Vec::new();
+        let mut header_loops = HashMap::new();
+        for &header in &dominators.rpo {
+            let latches: Vec<usize> = dominators.predecessors[header]
+                .iter()
+                .cloned()
+                .filter(|&predecessor| dominators.dominates(header, predecessor))
+                .collect();
+            if latches.is_empty() {
+                continue;
+            }
+            let mut body = HashSet::new();
+            body.insert(header);
+            let mut worklist = latches.clone();
+            while let Some(block) = worklist.pop() {
+                if body.insert(block) {
+                    worklist.extend(dominators.predecessors[block].iter().cloned());
+                }
+            }
+            let mut exits = Vec::new();
+            let mut exit_targets = Vec::new();
+            for &block in &dominators.rpo {
+                if !body.contains(&block) {
+                    continue;
+                }
+                for (edge_index, edge) in graph.blocks[block].successors.iter().enumerate() {
+                    if !body.contains(&edge.target) {
+                        exits.push((block, edge_index));
+                        if !exit_targets.contains(&edge.target) {
+                            exit_targets.push(edge.target);
+                        }
+                    }
+                }
+            }
+            exit_targets.sort_by_key(|&block| dominators.rpo_index[block]);
+            header_loops.insert(header, loops.len());
+            loops.push(Loop {
+                header,
+                body,
+                parent: None,
+                latches,
+                exits,
+                exit_targets,
+            });
+        }
+        let mut innermost = vec![None; graph.blocks.len()];
+        // outer loops come first, so the last loop found containing a block is the innermost
+        for (loop_index, l) in loops.iter().enumerate() {
+            for &block in &l.body {
+                innermost[block] = Some(loop_index);
+            }
+        }
+        // the parent is the innermost of the enclosing loops, all of which come earlier
+        for loop_index in 0..loops.len() {
+            let header = loops[loop_index].header;
+            loops[loop_index].parent = (0..loop_index)
+                .rev()
+                .find(|&parent| loops[parent].body.contains(&header));
+        }
+        Loops {
+            loops,
+            innermost,
+            header_loops,
+        }
+    }
+    fn loop_for_header(&self, block: usize) -> Option<usize> {
+        self.header_loops.get(&block).cloned()
+    }
+    /// the node representing `block` in the region made of the blocks directly in `region`,
+    /// where inner loops are collapsed into their headers
+    fn collapse(&self, block: usize, region: Option<usize>) -> Option<usize> {
+        let mut node = block;
+        let mut current = self.innermost[block];
+        loop {
+            if current == region {
+                return Some(node);
+            }
+            let l = &self.loops[current?];
+            node = l.header;
+            current = l.parent;
+        }
+    }
+}
+
+/// give each loop a header with a single successor in the loop, a synthetic continue target and
+/// a single merge block, returning `true` if the graph changed
+fn normalize_loops(graph: &mut Graph, loops: &Loops) -> Result<bool, Error> {
+    let continue_targets: HashSet<usize> = loops
+        .loops
+        .iter()
+        .flat_map(|l| l.latches.iter().cloned())
+        .collect();
+    for l in &loops.loops {
+        let header = l.header;
+        let successors_in_body = graph
+            .distinct_successor_targets(header)
+            .into_iter()
+            .filter(|block| l.body.contains(block))
+            .count();
+        // `OpLoopMerge` must be followed by `OpBranch` or `OpBranchConditional`, and the loop
+        // body must start at a single block
+        if successors_in_body > 1
+            || graph.blocks[header].successors.len() > 2
+            || match graph.blocks[header].kind {
+                BlockKind::Synthetic(SyntheticBlock::Dispatch { .. }) => true,
+                _ => false,
+            }
+        {
+            let edges = graph.edges_to(header);
+            let new_header = graph.add_synthetic_branch(header)?;
+            graph.redirect(&edges, new_header);
+            return Ok(true);
+        }
+        let has_continue_target = match *l.latches {
+            [latch] => {
+                graph.blocks[latch].kind == BlockKind::Synthetic(SyntheticBlock::Branch)
+                    && graph.successor_targets(latch) == [header]
+            }
+            _ => false,
+        };
+        if !has_continue_target {
+            let edges: Vec<_> = graph
+                .edges_to(header)
+                .into_iter()
+                .filter(|(block, _)| l.body.contains(block))
+                .collect();
+            let continue_target = graph.add_synthetic_branch(header)?;
+            graph.redirect(&edges, continue_target);
+            return Ok(true);
+        }
+        let has_merge = match *l.exit_targets {
+            [] => true,
+            [merge] => {
+                loops.loop_for_header(merge).is_none()
+                    && !continue_targets.contains(&merge)
+                    && graph
+                        .edges_to(merge)
+                        .iter()
+                        .all(|(block, _)| l.body.contains(block))
+            }
+            _ => false,
+        };
+        if has_merge {
+            continue;
+        }
+        if let [target] = *l.exit_targets {
+            let merge = graph.add_synthetic_branch(target)?;
+            graph.redirect(&l.exits, merge);
+            return Ok(true);
+        }
+        let selector = graph.selector_count;
+        graph.selector_count += 1;
+        let merge = graph.add_block(
+            BlockKind::Synthetic(SyntheticBlock::Dispatch { selector }),
+            l.exit_targets
+                .iter()
+                .map(|&target| Edge::new(target))
+                .collect(),
+        )?;
+        for &(block, edge_index) in &l.exits {
+            let edge = &mut graph.blocks[block].successors[edge_index];
+            let case = l
+                .exit_targets
+                .iter()
+                .position(|&target| target == edge.target)
+                .unwrap();
+            edge.target = merge;
+            edge.assignments.push(SelectorAssignment { selector, case });
+        }
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// the acyclic graph of the blocks directly in a loop, or directly in the function for the
+/// outermost region, with inner loops collapsed into their headers.
+/// branches to the loop's header or continue target and exits from the loop go to a virtual
+/// exit node, represented as `None`.
+struct Region {
+    /// the block for each node
+    blocks: Vec<usize>,
+    nodes: HashMap<usize, usize>,
+    successors: Vec<Vec<Option<usize>>>,
+    predecessors: Vec<Vec<usize>>,
+    /// the immediate post-dominator of each node
+    ipdom: Vec<Option<usize>>,
+}
+
+impl Region {
+    fn new(graph: &Graph, loops: &Loops, region: Option<usize>) -> Self {
+        let entry = match region {
+            Some(region) => loops.loops[region].header,
+            None => graph.entry,
+        };
+        let mut retval = Region {
+            blocks: vec![entry],
+            nodes: HashMap::new(),
+            successors: Vec::new(),
+            predecessors: Vec::new(),
+            ipdom: Vec::new(),
+        };
+        retval.nodes.insert(entry, 0);
+        let mut node = 0;
+        while node < retval.blocks.len() {
+            let block = retval.blocks[node];
+            let targets = match loops.loop_for_header(block) {
+                Some(l) if Some(l) != region => loops.loops[l].exit_targets.clone(),
+                _ => graph.successor_targets(block),
+            };
+            let mut successors = Vec::with_capacity(targets.len());
+            for target in targets {
+                let target = match region {
+                    Some(region) => {
+                        let l = &loops.loops[region];
+                        if target == l.header || l.latches.contains(&target) {
+                            None
+                        } else {
+                            loops.collapse(target, Some(region))
+                        }
+                    }
+                    None => loops.collapse(target, None),
+                };
+                successors.push(target.map(|target| {
+                    if let Some(&node) = retval.nodes.get(&target) {
+                        return node;
+                    }
+                    retval.nodes.insert(target, retval.blocks.len());
+                    retval.blocks.push(target);
+                    retval.blocks.len() - 1
+                }));
+            }
+            retval.successors.push(successors);
+            node += 1;
+        }
+        let node_count = retval.blocks.len();
+        retval.predecessors = vec![Vec::new(); node_count];
+        let mut exit_predecessors = Vec::new();
+        for (node, successors) in retval.successors.iter().enumerate() {
+            if successors.is_empty() {
+                exit_predecessors.push(node);
+            }
+            for &successor in successors {
+                match successor {
+                    Some(successor) => {
+                        if !retval.predecessors[successor].contains(&node) {
+                            retval.predecessors[successor].push(node);
+                        }
+                    }
+                    None => {
+                        if !exit_predecessors.contains(&node) {
+                            exit_predecessors.push(node);
+                        }
+                    }
+                }
+            }
+        }
+        // post-dominators are the dominators of the reversed graph, with the exit as the entry
+        let exit = node_count;
+        let post_dominators = Dominators::new(node_count + 1, exit, |node| {
+            if node == exit {
+                exit_predecessors.clone()
+            } else {
+                retval.predecessors[node].clone()
+            }
+        });
+        retval.ipdom = (0..node_count)
+            .map(|node| {
+                post_dominators.immediate_dominator(node).and_then(|ipdom| {
+                    if ipdom == exit {
+                        None
+                    } else {
+                        Some(ipdom)
+                    }
+                })
+            })
+            .collect();
+        retval
+    }
+    /// the nodes reachable from `start` without going through `stop`
+    fn reachable_from(&self, start: usize, stop: Option<usize>) -> HashSet<usize> {
+        let mut retval = HashSet::new();
+        retval.insert(start);
+        let mut worklist = vec![start];
+        while let Some(node) = worklist.pop() {
+            for &successor in &self.successors[node] {
+                if let Some(successor) = successor {
+                    if Some(successor) != stop && retval.insert(successor) {
+                        worklist.push(successor);
+                    }
+                }
+            }
+        }
+        retval
+    }
+}
+
+/// find the merge block of each selection header, which is `None` for selections that are never
+/// exited normally. returns `true` if the graph had to be changed to make the selections
+/// single-entry with unique merge blocks.
+fn place_selections(
+    graph: &mut Graph,
+    dominators: &Dominators,
+    loops: &Loops,
+    selections: &mut Vec<(usize, Option<usize>)>,
+) -> Result<bool, Error> {
+    let mut merges: HashSet<usize> = loops
+        .loops
+        .iter()
+        .flat_map(|l| l.exit_targets.iter().cloned())
+        .collect();
+    let mut regions = HashMap::new();
+    for &header in &dominators.rpo {
+        if loops.loop_for_header(header).is_some()
+            || graph.distinct_successor_targets(header).len() < 2
+        {
+            continue;
+        }
+        let region_index = loops.innermost[header];
+        let region = regions
+            .entry(region_index)
+            .or_insert_with(|| Region::new(graph, loops, region_index));
+        let header_node = region.nodes[&header];
+        let merge_node = region.ipdom[header_node];
+        let construct = region.reachable_from(header_node, merge_node);
+        let in_construct = |block: usize| {
+            loops
+                .collapse(block, region_index)
+                .and_then(|node| region.nodes.get(&node))
+                .map_or(false, |node| construct.contains(node))
+        };
+        let mut construct_nodes: Vec<usize> = construct.iter().cloned().collect();
+        construct_nodes.sort_by_key(|&node| dominators.rpo_index[region.blocks[node]]);
+        for node in construct_nodes {
+            if node == header_node
+                || region.predecessors[node]
+                    .iter()
+                    .all(|predecessor| construct.contains(predecessor))
+            {
+                continue;
+            }
+            // entered from outside the construct: give the other predecessors their own copy
+            let block = region.blocks[node];
+            let mut copied_blocks: Vec<usize> = match loops.loop_for_header(block) {
+                Some(l) => loops.loops[l].body.iter().cloned().collect(),
+                None => vec![block],
+            };
+            copied_blocks.sort();
+            let entering_edges: Vec<_> = graph
+                .edges_to(block)
+                .into_iter()
+                .filter(|&(predecessor, _)| {
+                    !copied_blocks.contains(&predecessor) && !in_construct(predecessor)
+                })
+                .collect();
+            let map = graph.copy_blocks(&copied_blocks)?;
+            graph.redirect_to_copies(&entering_edges, &map);
+            return Ok(true);
+        }
+        let merge_node = match merge_node {
+            Some(merge_node) => merge_node,
+            None => {
+                selections.push((header, None));
+                continue;
+            }
+        };
+        let merge = region.blocks[merge_node];
+        if merges.contains(&merge)
+            || region.predecessors[merge_node]
+                .iter()
+                .any(|predecessor| !construct.contains(predecessor))
+        {
+            let edges: Vec<_> = graph
+                .edges_to(merge)
+                .into_iter()
+                .filter(|&(predecessor, _)| in_construct(predecessor))
+                .collect();
+            let new_merge = graph.add_synthetic_branch(merge)?;
+            graph.redirect(&edges, new_merge);
+            return Ok(true);
+        }
+        merges.insert(merge);
+        selections.push((header, Some(merge)));
+    }
+    Ok(false)
+}
+
+fn merge_or_unreachable(blocks: &mut Vec<StructuredBlock>, merge: Option<usize>) -> usize {
+    merge.unwrap_or_else(|| {
+        blocks.push(StructuredBlock {
+            kind: BlockKind::Synthetic(SyntheticBlock::Unreachable),
+            successors: Vec::new(),
+            merge: None,
+        });
+        blocks.len() - 1
+    })
+}
+
+/// normalize the control flow of a function into structured control flow.
+/// the first block in `blocks` is the entry block.
+pub fn structurize(blocks: &[InputBlock]) -> Result<StructuredCfg, Error> {
+    let mut graph = Graph::new(blocks)?;
+    loop {
+        graph.remove_unreachable_blocks();
+        let entry = graph.entry;
+        if !graph.edges_to(entry).is_empty() {
+            graph.entry = graph.add_synthetic_branch(entry)?;
+            continue;
+        }
+        let dominators = graph_dominators(&graph);
+        if split_irreducible_regions(&mut graph, &dominators)? {
+            continue;
+        }
+        let loops = Loops::new(&graph, &dominators);
+        if normalize_loops(&mut graph, &loops)? {
+            continue;
+        }
+        let mut selections = Vec::new();
+        if place_selections(&mut graph, &dominators, &loops, &mut selections)? {
+            continue;
+        }
+        let mut structured_blocks: Vec<StructuredBlock> = graph
+            .blocks
+            .into_iter()
+            .map(|block| StructuredBlock {
+                kind: block.kind,
+                successors: block.successors,
+                merge: None,
+            })
+            .collect();
+        for l in &loops.loops {
+            let merge = Merge::Loop {
+                merge: merge_or_unreachable(
+                    &mut structured_blocks,
+                    l.exit_targets.first().cloned(),
+                ),
+                continue_target: l.latches[0],
+            };
+            structured_blocks[l.header].merge = Some(merge);
+        }
+        for (header, merge) in selections {
+            let merge = Merge::Selection {
+                merge: merge_or_unreachable(&mut structured_blocks, merge),
+            };
+            structured_blocks[header].merge = Some(merge);
+        }
+        return Ok(StructuredCfg {
+            entry: graph.entry,
+            blocks: structured_blocks,
+            selector_count: graph.selector_count,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(blocks: &[(Id, &[Id])]) -> Vec<InputBlock> {
+        blocks
+            .iter()
+            .map(|&(label, successors)| InputBlock {
+                label,
+                successors: successors.to_vec(),
+            })
+            .collect()
+    }
+
+    /// follow `edge` through synthetic blocks to the label of the original block it leads to
+    fn follow(cfg: &StructuredCfg, edge: &Edge) -> Id {
+        let mut selectors = HashMap::new();
+        let mut edge = edge;
+        loop {
+            for assignment in &edge.assignments {
+                selectors.insert(assignment.selector, assignment.case);
+            }
+            let block = &cfg.blocks[edge.target];
+            edge = match block.kind {
+                BlockKind::Original(label) => return label,
+                BlockKind::Synthetic(SyntheticBlock::Branch) => &block.successors[0],
+                BlockKind::Synthetic(SyntheticBlock::Dispatch { selector }) => {
+                    &block.successors[selectors[&selector]]
+                }
+                BlockKind::Synthetic(SyntheticBlock::Unreachable) => panic!("reached unreachable"),
+            };
+        }
+    }
+
+    /// check that `cfg` is structured and has the same paths between original blocks as `blocks`
+    fn check(blocks: &[InputBlock], cfg: &StructuredCfg) {
+        let successors: HashMap<Id, &[Id]> = blocks
+            .iter()
+            .map(|block| (block.label, &*block.successors))
+            .collect();
+        assert_eq!(follow(cfg, &Edge::new(cfg.entry)), blocks[0].label, "entry");
+        let dominators = Dominators::new(cfg.blocks.len(), cfg.entry, |block| {
+            cfg.blocks[block]
+                .successors
+                .iter()
+                .map(|edge| edge.target)
+                .collect()
+        });
+        let mut reached_labels = HashSet::new();
+        let mut merge_blocks = HashSet::new();
+        for &block_index in &dominators.rpo {
+            let block = &cfg.blocks[block_index];
+            if let BlockKind::Original(label) = block.kind {
+                reached_labels.insert(label);
+                let targets: Vec<Id> = block
+                    .successors
+                    .iter()
+                    .map(|edge| follow(cfg, edge))
+                    .collect();
+                assert_eq!(&*targets, successors[&label], "successors of {}", label);
+            }
+            let mut distinct_targets: Vec<usize> =
+                block.successors.iter().map(|edge| edge.target).collect();
+            distinct_targets.sort();
+            distinct_targets.dedup();
+            for &target in &distinct_targets {
+                if dominators.rpo_index[target] <= dominators.rpo_index[block_index] {
+                    assert!(
+                        dominators.dominates(target, block_index),
+                        "irreducible edge {} -> {}",
+                        block_index,
+                        target
+                    );
+                    match cfg.blocks[target].merge {
+                        Some(Merge::Loop {
+                            continue_target, ..
+                        }) => assert_eq!(continue_target, block_index),
+                        _ => panic!("back edge {} -> {} to non-loop", block_index, target),
+                    }
+                }
+            }
+            match block.merge {
+                None => assert!(distinct_targets.len() < 2, "{} needs a merge", block_index),
+                Some(Merge::Selection { merge }) => {
+                    assert!(merge_blocks.insert(merge), "merge {} reused", merge);
+                    assert!(
+                        dominators.rpo_index[merge].is_none()
+                            || dominators.dominates(block_index, merge)
+                    );
+                }
+                Some(Merge::Loop {
+                    merge,
+                    continue_target,
+                }) => {
+                    assert!(merge_blocks.insert(merge), "merge {} reused", merge);
+                    assert!(block.successors.len() <= 2);
+                    assert!(
+                        dominators.rpo_index[merge].is_none()
+                            || dominators.dominates(block_index, merge)
+                    );
+                    assert!(dominators.dominates(block_index, continue_target));
+                }
+            }
+        }
+        let mut expected_labels = HashSet::new();
+        let mut worklist = vec![blocks[0].label];
+        while let Some(label) = worklist.pop() {
+            if expected_labels.insert(label) {
+                worklist.extend(successors[&label].iter().cloned());
+            }
+        }
+        assert_eq!(reached_labels, expected_labels);
+    }
+
+    fn structurize_and_check(blocks: &[(Id, &[Id])]) -> StructuredCfg {
+        let blocks = input(blocks);
+        let cfg = structurize(&blocks).unwrap();
+        check(&blocks, &cfg);
+        cfg
+    }
+
+    fn count_synthetic(cfg: &StructuredCfg, kind: SyntheticBlock) -> usize {
+        cfg.blocks
+            .iter()
+            .filter(|block| block.kind == BlockKind::Synthetic(kind))
+            .count()
+    }
+
+    fn count_copies(cfg: &StructuredCfg, label: Id) -> usize {
+        cfg.blocks
+            .iter()
+            .filter(|block| block.kind == BlockKind::Original(label))
+            .count()
+    }
+
+    #[test]
+    fn test_if_else() {
+        let cfg = structurize_and_check(&[(1, &[2, 3]), (2, &[4]), (3, &[4]), (4, &[])]);
+        assert_eq!(cfg.blocks.len(), 4);
+        assert_eq!(cfg.blocks[0].merge, Some(Merge::Selection { merge: 3 }));
+    }
+
+    #[test]
+    fn test_while_loop() {
+        let cfg = structurize_and_check(&[(1, &[2]), (2, &[3, 4]), (3, &[2]), (4, &[])]);
+        assert_eq!(count_synthetic(&cfg, SyntheticBlock::Branch), 1);
+        match cfg.blocks[1].merge {
+            Some(Merge::Loop { merge: 3, .. }) => {}
+            merge => panic!("unexpected merge: {:?}", merge),
+        }
+        let back_edges = cfg.loop_back_edges();
+        assert_eq!(back_edges.len(), 1);
+        let (block, successor) = back_edges[0];
+        assert_eq!(cfg.blocks[block].successors[successor].target, 1);
+    }
+
+    #[test]
+    fn test_loop_at_entry() {
+        structurize_and_check(&[(1, &[1, 2]), (2, &[])]);
+    }
+
+    #[test]
+    fn test_irreducible() {
+        let cfg = structurize_and_check(&[(1, &[2, 3]), (2, &[3]), (3, &[2, 4]), (4, &[])]);
+        assert!(count_copies(&cfg, 3) > 1);
+    }
+
+    #[test]
+    fn test_loop_with_multiple_exits() {
+        let cfg = structurize_and_check(&[
+            (1, &[2]),
+            (2, &[3, 5]),
+            (3, &[4, 6]),
+            (4, &[2]),
+            (5, &[7]),
+            (6, &[7]),
+            (7, &[]),
+        ]);
+        assert_eq!(
+            count_synthetic(&cfg, SyntheticBlock::Dispatch { selector: 0 }),
+            1
+        );
+        assert_eq!(cfg.selector_count, 1);
+    }
+
+    #[test]
+    fn test_break_out_of_nested_loops() {
+        let cfg = structurize_and_check(&[
+            (1, &[2]),
+            (2, &[3, 7]),
+            (3, &[4, 6]),
+            (4, &[3, 8]),
+            (6, &[2]),
+            (7, &[8]),
+            (8, &[]),
+        ]);
+        assert_eq!(cfg.loop_back_edges().len(), 2);
+    }
+
+    #[test]
+    fn test_early_merge() {
+        let cfg =
+            structurize_and_check(&[(1, &[2, 3]), (2, &[4, 5]), (3, &[5]), (4, &[5]), (5, &[])]);
+        assert_eq!(count_synthetic(&cfg, SyntheticBlock::Branch), 1);
+    }
+
+    #[test]
+    fn test_branch_into_selection() {
+        let cfg = structurize_and_check(&[
+            (1, &[2, 3]),
+            (2, &[4, 5]),
+            (3, &[5]),
+            (4, &[6]),
+            (5, &[6]),
+            (6, &[]),
+        ]);
+        assert_eq!(count_copies(&cfg, 5), 2);
+    }
+
+    #[test]
+    fn test_returns_from_loop() {
+        structurize_and_check(&[(1, &[2]), (2, &[3, 4]), (3, &[]), (4, &[5, 2]), (5, &[])]);
+    }
+
+    #[test]
+    fn test_switch() {
+        structurize_and_check(&[
+            (1, &[2, 3, 4, 2]),
+            (2, &[3]),
+            (3, &[5]),
+            (4, &[5]),
+            (5, &[1]),
+        ]);
+    }
+
+    #[test]
+    fn test_errors() {
+        match structurize(&[]) {
+            Err(Error::FunctionHasNoBlocks) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match structurize(&input(&[(1, &[2])])) {
+            Err(Error::UnknownLabel(2)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match structurize(&input(&[(1, &[]), (1, &[])])) {
+            Err(Error::DuplicateLabel(1)) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
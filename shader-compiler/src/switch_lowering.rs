@@ -0,0 +1,403 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! lowering for `OpSwitch`
+//!
+//! when the selector is the same for every invocation of a group, the switch is a single branch,
+//! lowered in one of three ways depending on how its case literals are spread out:
+//!
+//! * a few cases become an if-chain that compares the selector against each literal in turn,
+//!   which is cheaper than anything that needs a table or a search.
+//! * dense cases, whose literals fill most of the range from the smallest to the largest, become
+//!   a jump table: a range check followed by a computed branch through a table with an entry for
+//!   every value in the range.
+//! * sparse cases use the backend's switch instruction, which searches the literals.
+//!
+//! a divergent selector can send the invocations of a group to different cases, so no single
+//! branch is right for all of them. the targets then run one after another, each with the
+//! invocations whose selector matches one of its literals active, plus the invocations that fell
+//! through from the target before it; the default target has the invocations that match no other
+//! literal, and targets with no active invocations are skipped. `SwitchInstruction::targets`
+//! gives the order and `SwitchInstruction::build_case_masks` computes the masks.
+//!
+//! cases that branch to the default target are left out of all of this, since taking them is
+//! the same as taking the default.
+
+use shader_compiler_backend::{
+    AttachedBuilder, BuildableBasicBlock, Context, DetachedBuilder, Error as BackendError,
+    Function, IntegerArithmeticOperation, IntegerComparison,
+};
+use spirv::{op, Error, Id, Instruction};
+use std::collections::HashSet;
+
+/// switches with at most this many cases are lowered to an if-chain
+const MAX_IF_CHAIN_CASES: usize = 3;
+
+/// a jump table can have at most this many entries per case, so at least a third of the entries
+/// go to a case rather than to the default
+const MAX_JUMP_TABLE_ENTRIES_PER_CASE: u64 = 3;
+
+/// how `SwitchInstruction::build` and `SwitchInstruction::build_case_masks` get values and blocks
+pub trait SwitchOperands<'a, C: Context<'a>> {
+    /// the value of `id`. a divergent selector is a vector with a lane per invocation
+    fn get_value(&self, id: Id) -> Result<C::Value, BackendError>;
+    /// `literal` as a constant of the type of the selector `selector`, in every lane if it's a
+    /// vector
+    fn get_literal(&self, selector: Id, literal: u64) -> Result<C::Value, BackendError>;
+    /// the block for the label `id`
+    fn get_block(&self, id: Id) -> Result<C::BasicBlock, BackendError>;
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SwitchLowering {
+    /// compare the selector against each literal in turn
+    IfChain,
+    /// check that the selector minus `first_literal` is less than `entry_count`, then branch
+    /// through a table with an entry for each literal from `first_literal` on
+    JumpTable {
+        first_literal: u64,
+        entry_count: u64,
+    },
+    /// the backend's switch instruction
+    Switch,
+    /// the selector can differ between the invocations of a group
+    Divergent,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SwitchInstruction {
+    pub selector: Id,
+    /// the width in bits of the selector's integer type
+    pub width: u32,
+    pub default: Id,
+    /// `(literal, target)` in operand order. literals are truncated to `width` bits
+    pub cases: Vec<(u64, Id)>,
+}
+
+impl SwitchInstruction {
+    /// returns `None` if `instruction` isn't `OpSwitch`. `selector_width` is the width in bits of
+    /// the selector's integer type, which decides whether literals take one or two words
+    pub fn parse(instruction: &Instruction, selector_width: u32) -> Result<Option<Self>, Error> {
+        if instruction.opcode != op::SWITCH {
+            return Ok(None);
+        }
+        let invalid_operands = || Error::InvalidOperands {
+            offset: instruction.offset,
+            opcode: instruction.opcode,
+        };
+        if selector_width == 0 || selector_width > 64 {
+            return Err(invalid_operands());
+        }
+        let literal_words = if selector_width > 32 { 2 } else { 1 };
+        let case_operands = instruction.operands().get(2..).unwrap_or(&[]);
+        if case_operands.len() % (literal_words + 1) != 0 {
+            return Err(invalid_operands());
+        }
+        let cases = case_operands
+            .chunks(literal_words + 1)
+            .map(|case| {
+                let literal = case[..literal_words]
+                    .iter()
+                    .rev()
+                    .fold(0, |literal, &word| literal << 32 | u64::from(word));
+                (literal, case[literal_words])
+            })
+            .collect();
+        let mut retval = SwitchInstruction {
+            selector: instruction.operand(0)?,
+            width: selector_width,
+            default: instruction.operand(1)?,
+            cases,
+        };
+        // literals for types narrower than 32 bits are sign-extended if the type is signed
+        let literal_mask = retval.literal_mask();
+        for case in &mut retval.cases {
+            case.0 &= literal_mask;
+        }
+        Ok(Some(retval))
+    }
+    fn literal_mask(&self) -> u64 {
+        !0 >> (64 - self.width)
+    }
+    /// the cases that don't branch to the default target
+    fn non_default_cases<'b>(&'b self) -> impl Iterator<Item = (u64, Id)> + 'b {
+        self.cases
+            .iter()
+            .cloned()
+            .filter(move |&(_, target)| target != self.default)
+    }
+    /// the targets in the order a divergent switch runs them, which is the operand order with
+    /// each target at its last appearance, so a target comes right before the one it falls
+    /// through to. the default target is included even when it's the merge block
+    pub fn targets(&self) -> Vec<Id> {
+        let operand_targets: Vec<Id> = Some(self.default)
+            .into_iter()
+            .chain(self.cases.iter().map(|&(_, target)| target))
+            .collect();
+        let mut retval = Vec::new();
+        let mut seen = HashSet::new();
+        for &target in operand_targets.iter().rev() {
+            if seen.insert(target) {
+                retval.push(target);
+            }
+        }
+        retval.reverse();
+        retval
+    }
+    /// `(first literal, entry count)` of the smallest jump table covering the literals of the
+    /// non-default cases, if it's dense enough. the literals are ordered as unsigned and as
+    /// signed numbers, since the table is indexed with wrapping subtraction either way
+    fn dense_range(&self) -> Option<(u64, u64)> {
+        let literals: Vec<u64> = self
+            .non_default_cases()
+            .map(|(literal, _)| literal)
+            .collect();
+        if literals.is_empty() {
+            return None;
+        }
+        let sign_bit = 1 << (self.width - 1);
+        let (first_literal, entry_count) = [0, sign_bit]
+            .iter()
+            .map(|&flip| {
+                let first = literals.iter().map(|&v| v ^ flip).min().unwrap();
+                let last = literals.iter().map(|&v| v ^ flip).max().unwrap();
+                (first ^ flip, (last - first).saturating_add(1))
+            })
+            .min_by_key(|&(_, entry_count)| entry_count)
+            .unwrap();
+        // a table covering every value would need an entry count that doesn't fit the selector
+        if entry_count <= literals.len() as u64 * MAX_JUMP_TABLE_ENTRIES_PER_CASE
+            && entry_count <= self.literal_mask()
+        {
+            Some((first_literal, entry_count))
+        } else {
+            None
+        }
+    }
+    /// `selector_is_divergent` is whether the selector can differ between the invocations of a
+    /// group
+    pub fn choose_lowering(&self, selector_is_divergent: bool) -> SwitchLowering {
+        let case_count = self.non_default_cases().count();
+        if case_count == 0 {
+            // always branches to the default target
+            return SwitchLowering::IfChain;
+        }
+        if selector_is_divergent {
+            return SwitchLowering::Divergent;
+        }
+        if case_count <= MAX_IF_CHAIN_CASES {
+            return SwitchLowering::IfChain;
+        }
+        match self.dense_range() {
+            Some((first_literal, entry_count)) => SwitchLowering::JumpTable {
+                first_literal,
+                entry_count,
+            },
+            None => SwitchLowering::Switch,
+        }
+    }
+    /// build the branch for a uniform selector, ending the current block. `lowering` is from
+    /// `choose_lowering` and can't be `Divergent`. the edges are added to `edges` as
+    /// `(target, block branching to it)`, since if-chains and jump tables add blocks between the
+    /// switch and its targets, which the `OpPhi` instructions of the targets have to name
+    pub fn build<'a, C: Context<'a>, O: SwitchOperands<'a, C>>(
+        &self,
+        lowering: SwitchLowering,
+        builder: C::AttachedBuilder,
+        function: &mut C::Function,
+        operands: &O,
+        edges: &mut Vec<(Id, C::BasicBlock)>,
+    ) -> Result<C::DetachedBuilder, BackendError> {
+        let selector = operands.get_value(self.selector)?;
+        let default = operands.get_block(self.default)?;
+        Ok(match lowering {
+            SwitchLowering::IfChain => {
+                let mut builder = builder;
+                for (literal, target) in self.non_default_cases() {
+                    let next_block = function.append_new_basic_block(None)?;
+                    let is_equal = builder.build_integer_compare(
+                        IntegerComparison::Equal,
+                        selector.clone(),
+                        operands.get_literal(self.selector, literal)?,
+                    );
+                    edges.push((target, builder.current_basic_block()));
+                    builder = builder
+                        .build_conditional_branch(
+                            is_equal,
+                            operands.get_block(target)?,
+                            next_block.as_basic_block(),
+                        )
+                        .attach(next_block);
+                }
+                edges.push((self.default, builder.current_basic_block()));
+                builder.build_branch(default)
+            }
+            SwitchLowering::JumpTable {
+                first_literal,
+                entry_count,
+            } => {
+                let mut destinations = vec![self.default; entry_count as usize];
+                for (literal, target) in self.non_default_cases() {
+                    let index = literal.wrapping_sub(first_literal) & self.literal_mask();
+                    destinations[index as usize] = target;
+                }
+                let index = builder.build_integer_arithmetic(
+                    IntegerArithmeticOperation::Subtract,
+                    selector,
+                    operands.get_literal(self.selector, first_literal)?,
+                );
+                let is_in_range = builder.build_integer_compare(
+                    IntegerComparison::UnsignedLess,
+                    index.clone(),
+                    operands.get_literal(self.selector, entry_count)?,
+                );
+                let table_block = function.append_new_basic_block(None)?;
+                edges.push((self.default, builder.current_basic_block()));
+                let builder = builder
+                    .build_conditional_branch(is_in_range, table_block.as_basic_block(), default)
+                    .attach(table_block);
+                let mut destination_blocks = Vec::with_capacity(destinations.len());
+                let mut seen = HashSet::new();
+                for &destination in &destinations {
+                    if seen.insert(destination) {
+                        edges.push((destination, builder.current_basic_block()));
+                    }
+                    destination_blocks.push(operands.get_block(destination)?);
+                }
+                builder.build_jump_table(index, &destination_blocks)?
+            }
+            SwitchLowering::Switch => {
+                let mut cases = Vec::new();
+                for (literal, target) in self.non_default_cases() {
+                    edges.push((target, builder.current_basic_block()));
+                    cases.push((literal, operands.get_block(target)?));
+                }
+                edges.push((self.default, builder.current_basic_block()));
+                builder.build_switch(selector, default, &cases)?
+            }
+            SwitchLowering::Divergent => {
+                panic!("divergent switches are lowered with `build_case_masks`")
+            }
+        })
+    }
+    /// the mask of the invocations that go to each target of a divergent switch, in the order of
+    /// `targets`. the masks are bools, or vectors of bools for vector selectors. the switch must
+    /// have a case that doesn't branch to the default target, which `choose_lowering` checks
+    /// before choosing `Divergent`
+    pub fn build_case_masks<'a, C: Context<'a>, O: SwitchOperands<'a, C>>(
+        &self,
+        builder: &C::AttachedBuilder,
+        operands: &O,
+    ) -> Result<Vec<(Id, C::Value)>, BackendError> {
+        let selector = operands.get_value(self.selector)?;
+        let mut retval = Vec::new();
+        for target in self.targets() {
+            let (comparison, combine) = if target == self.default {
+                (IntegerComparison::NotEqual, IntegerArithmeticOperation::And)
+            } else {
+                (IntegerComparison::Equal, IntegerArithmeticOperation::Or)
+            };
+            let mut mask = None;
+            for (literal, case_target) in self.non_default_cases() {
+                if target != self.default && case_target != target {
+                    continue;
+                }
+                let compare = builder.build_integer_compare(
+                    comparison,
+                    selector.clone(),
+                    operands.get_literal(self.selector, literal)?,
+                );
+                mask = Some(match mask {
+                    Some(mask) => builder.build_integer_arithmetic(combine, mask, compare),
+                    None => compare,
+                });
+            }
+            retval.push((target, mask.expect("switch has no non-default cases")));
+        }
+        Ok(retval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::{write_instruction, Instructions, MAGIC_NUMBER};
+
+    fn parse(selector_width: u32, operands: &[u32]) -> SwitchInstruction {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        write_instruction(&mut code, op::SWITCH, operands);
+        let instruction = Instructions::new(&code).unwrap().next().unwrap().unwrap();
+        SwitchInstruction::parse(&instruction, selector_width)
+            .unwrap()
+            .unwrap()
+    }
+
+    fn switch(default: Id, cases: &[(u64, Id)]) -> SwitchInstruction {
+        SwitchInstruction {
+            selector: 1,
+            width: 32,
+            default,
+            cases: cases.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(32, &[1, 2, 5, 3, 0xFFFF_FFFF, 4]),
+            switch(2, &[(5, 3), (0xFFFF_FFFF, 4)])
+        );
+        // literals of 64-bit selectors are two words, low-order word first
+        assert_eq!(parse(64, &[1, 2, 5, 6, 3]).cases, [(0x6_0000_0005, 3)]);
+        // -1 as an i16 is sign-extended to 32 bits
+        assert_eq!(parse(16, &[1, 2, 0xFFFF_FFFF, 3]).cases, [(0xFFFF, 3)]);
+        assert_eq!(parse(32, &[1, 2]).cases, []);
+    }
+
+    #[test]
+    fn test_choose_lowering() {
+        let cases: Vec<(u64, Id)> = (0..8).map(|v| (v, 10 + v as Id)).collect();
+        assert_eq!(
+            switch(2, &cases).choose_lowering(false),
+            SwitchLowering::JumpTable {
+                first_literal: 0,
+                entry_count: 8,
+            }
+        );
+        assert_eq!(
+            switch(2, &cases).choose_lowering(true),
+            SwitchLowering::Divergent
+        );
+        assert_eq!(
+            switch(2, &cases[..3]).choose_lowering(false),
+            SwitchLowering::IfChain
+        );
+        // cases that go to the default don't count
+        let to_default: Vec<(u64, Id)> = (0..8).map(|v| (v, 2)).collect();
+        assert_eq!(
+            switch(2, &to_default).choose_lowering(true),
+            SwitchLowering::IfChain
+        );
+        // -2 to 1 is dense when the literals are ordered as signed numbers
+        let signed = [(0xFFFF_FFFE, 3), (0xFFFF_FFFF, 4), (0, 5), (1, 6)];
+        assert_eq!(
+            switch(2, &signed).choose_lowering(false),
+            SwitchLowering::JumpTable {
+                first_literal: 0xFFFF_FFFE,
+                entry_count: 4,
+            }
+        );
+        let sparse = [(0, 3), (100, 4), (200, 5), (300, 6)];
+        assert_eq!(
+            switch(2, &sparse).choose_lowering(false),
+            SwitchLowering::Switch
+        );
+    }
+
+    #[test]
+    fn test_targets() {
+        assert_eq!(switch(2, &[(0, 3), (1, 4), (2, 3)]).targets(), [2, 4, 3]);
+        // the default also has a literal, so it comes after the case that falls through to it
+        assert_eq!(switch(2, &[(0, 3), (1, 2)]).targets(), [3, 2]);
+    }
+}
@@ -0,0 +1,423 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! layout of the varying buffer that carries fragment shader inputs from the rasterizer
+//!
+//! each vertex's varyings are an array of 32-bit words, 4 per location, so the word for
+//! location `l` and component `c` is at `abi::varying_word(l, c)`. 64-bit components take two words, so
+//! 64-bit vectors with more than 2 components span two locations, matching the SPIR-V rules for
+//! location assignment. only 32-bit floating-point words are interpolated; everything else is
+//! copied from the provoking vertex.
+//!
+//! the fragment shader runs `simd_width` fragments together, so their interpolated varyings are
+//! stored together in structure-of-arrays order (see `abi::varying_buffer_index`): interpolation
+//! writes each word for the whole group with contiguous vector stores, and the fragment shader
+//! loads each input word for the group with a single vector load, instead of gathering it from
+//! every fragment's varyings.
+
+use abi::{varying_buffer_index, varying_word, WORDS_PER_LOCATION};
+use interface::InterfaceVariable;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ComponentType {
+    Float,
+    SignedInt,
+    UnsignedInt,
+}
+
+/// the type of an interface variable, which must be a scalar, vector, matrix or array of those
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct VaryingFormat {
+    pub component_type: ComponentType,
+    pub width: u32,
+    /// the number of components in each vector
+    pub component_count: u32,
+    /// the number of vectors, counting the columns of matrices and the elements of arrays
+    pub element_count: u32,
+}
+
+impl VaryingFormat {
+    pub fn is_integer(&self) -> bool {
+        self.component_type != ComponentType::Float
+    }
+    /// integers and 64-bit floats can't be interpolated
+    pub fn requires_flat(&self) -> bool {
+        self.is_integer() || self.width == 64
+    }
+    pub fn words_per_component(&self) -> u32 {
+        if self.width == 64 {
+            2
+        } else {
+            1
+        }
+    }
+    pub fn locations_per_element(&self) -> u32 {
+        if self.component_count * self.words_per_component() > WORDS_PER_LOCATION {
+            2
+        } else {
+            1
+        }
+    }
+    pub fn location_count(&self) -> u32 {
+        self.element_count * self.locations_per_element()
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Interpolation {
+    Smooth,
+    NoPerspective,
+    Flat,
+}
+
+/// a run of words in the varying buffer that all use the same interpolation
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct VaryingWords {
+    pub start: u32,
+    pub count: u32,
+    pub interpolation: Interpolation,
+}
+
+/// the weights of a primitive's vertices for each fragment in a group, in structure-of-arrays
+/// order: `smooth[v][lane]` is the perspective-correct weight of vertex `v` for the fragment in
+/// lane `lane`. points and lines give the unused vertices a weight of 0
+#[derive(Copy, Clone, Debug)]
+pub struct Barycentrics<'a> {
+    pub smooth: [&'a [f32]; 3],
+    pub no_perspective: [&'a [f32]; 3],
+}
+
+/// the layout of one vertex's varyings, and of the interpolated varyings of a group of fragments
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VaryingLayout {
+    /// sorted by `start` and not overlapping
+    pub words: Vec<VaryingWords>,
+    pub words_per_vertex: u32,
+    /// the number of fragments the fragment shader runs together
+    pub simd_width: u32,
+}
+
+impl Default for VaryingLayout {
+    fn default() -> Self {
+        VaryingLayout {
+            words: Vec::new(),
+            words_per_vertex: 0,
+            simd_width: 1,
+        }
+    }
+}
+
+impl VaryingLayout {
+    /// `inputs` are the fragment shader's inputs; built-ins and inputs without a location or a
+    /// known format are skipped. `simd_width` is the fragment shader's vector length
+    pub fn new(inputs: &[InterfaceVariable], simd_width: u32) -> Self {
+        assert_ne!(simd_width, 0);
+        let mut words = Vec::new();
+        for input in inputs {
+            let (location, format) = match (input.location, input.format) {
+                (Some(location), Some(format)) if input.built_in.is_none() => (location, format),
+                _ => continue,
+            };
+            let words_per_element = format.component_count * format.words_per_component();
+            for element in 0..format.element_count {
+                words.push(VaryingWords {
+                    start: varying_word(
+                        location + element * format.locations_per_element(),
+                        input.component.unwrap_or(0),
+                    ),
+                    count: words_per_element,
+                    interpolation: input.interpolation,
+                });
+            }
+        }
+        words.sort_by_key(|v| v.start);
+        let mut retval = VaryingLayout {
+            words: Vec::with_capacity(words.len()),
+            words_per_vertex: 0,
+            simd_width,
+        };
+        for v in words {
+            match retval.words.last_mut() {
+                Some(last)
+                    if last.start + last.count == v.start
+                        && last.interpolation == v.interpolation =>
+                {
+                    last.count += v.count;
+                    continue;
+                }
+                _ => {}
+            }
+            retval.words.push(v);
+        }
+        retval.words_per_vertex = retval.words.last().map_or(0, |v| v.start + v.count);
+        retval
+    }
+    pub fn flat_words<'a>(&'a self) -> impl Iterator<Item = VaryingWords> + 'a {
+        self.words
+            .iter()
+            .filter(|v| v.interpolation == Interpolation::Flat)
+            .cloned()
+    }
+    pub fn interpolated_words<'a>(&'a self) -> impl Iterator<Item = VaryingWords> + 'a {
+        self.words
+            .iter()
+            .filter(|v| v.interpolation != Interpolation::Flat)
+            .cloned()
+    }
+    /// the size of the varying buffer of a group of fragments
+    pub fn words_per_group(&self) -> u32 {
+        self.words_per_vertex * self.simd_width
+    }
+    /// the index in a group's varying buffer of the first of the `simd_width` contiguous words
+    /// that the fragment shader loads for location `location` and component `component`
+    pub fn group_word_start(&self, location: u32, component: u32) -> u32 {
+        varying_buffer_index(varying_word(location, component), 0, self.simd_width)
+    }
+    /// writes the varyings of a group of fragments of one primitive to `group`, which has
+    /// `words_per_group` words. `vertices` are the varyings of the primitive's vertices, with
+    /// `words_per_vertex` words each, and flat varyings are copied from
+    /// `vertices[provoking_vertex]`. words that aren't fragment shader inputs aren't written
+    pub fn interpolate_group(
+        &self,
+        vertices: [&[u32]; 3],
+        provoking_vertex: usize,
+        barycentrics: &Barycentrics,
+        group: &mut [u32],
+    ) {
+        let simd_width = self.simd_width as usize;
+        assert_eq!(group.len(), self.words_per_group() as usize);
+        for run in &self.words {
+            let weights = match run.interpolation {
+                Interpolation::Smooth => barycentrics.smooth,
+                Interpolation::NoPerspective => barycentrics.no_perspective,
+                Interpolation::Flat => {
+                    for word in run.start..run.start + run.count {
+                        let start = varying_buffer_index(word, 0, self.simd_width) as usize;
+                        let value = vertices[provoking_vertex][word as usize];
+                        for lane in &mut group[start..start + simd_width] {
+                            *lane = value;
+                        }
+                    }
+                    continue;
+                }
+            };
+            let weights = [
+                &weights[0][..simd_width],
+                &weights[1][..simd_width],
+                &weights[2][..simd_width],
+            ];
+            for word in run.start..run.start + run.count {
+                let start = varying_buffer_index(word, 0, self.simd_width) as usize;
+                let values = [
+                    f32::from_bits(vertices[0][word as usize]),
+                    f32::from_bits(vertices[1][word as usize]),
+                    f32::from_bits(vertices[2][word as usize]),
+                ];
+                // the same operation on every lane, so it's vectorized
+                for (lane, output) in group[start..start + simd_width].iter_mut().enumerate() {
+                    *output = (values[0] * weights[0][lane]
+                        + values[1] * weights[1][lane]
+                        + values[2] * weights[2][lane])
+                        .to_bits();
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum PrimitiveTopology {
+    PointList,
+    LineList,
+    LineStrip,
+    TriangleList,
+    TriangleStrip,
+    TriangleFan,
+    LineListWithAdjacency,
+    LineStripWithAdjacency,
+    TriangleListWithAdjacency,
+    TriangleStripWithAdjacency,
+    PatchList,
+}
+
+impl PrimitiveTopology {
+    /// the index of the vertex that flat varyings are taken from, relative to the first vertex
+    /// of the draw. uses the first vertex of each primitive, like D3D and Vulkan's default;
+    /// triangle fans use the first vertex that isn't the shared center vertex.
+    /// returns `None` for `PatchList`, since tessellation decides the primitives
+    pub fn get_provoking_vertex(self, primitive_index: u32) -> Option<u32> {
+        Some(match self {
+            PrimitiveTopology::PointList
+            | PrimitiveTopology::LineStrip
+            | PrimitiveTopology::TriangleStrip => primitive_index,
+            PrimitiveTopology::LineList => 2 * primitive_index,
+            PrimitiveTopology::TriangleList => 3 * primitive_index,
+            PrimitiveTopology::TriangleFan | PrimitiveTopology::LineStripWithAdjacency => {
+                primitive_index + 1
+            }
+            PrimitiveTopology::LineListWithAdjacency => 4 * primitive_index + 1,
+            PrimitiveTopology::TriangleListWithAdjacency => 6 * primitive_index,
+            PrimitiveTopology::TriangleStripWithAdjacency => 2 * primitive_index,
+            PrimitiveTopology::PatchList => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv::StorageClass;
+
+    fn input(
+        location: u32,
+        component: u32,
+        format: VaryingFormat,
+        interpolation: Interpolation,
+    ) -> InterfaceVariable {
+        InterfaceVariable {
+            id: location + 100,
+            storage_class: StorageClass::Input,
+            location: Some(location),
+            component: Some(component),
+            built_in: None,
+            format: Some(format),
+            interpolation,
+        }
+    }
+
+    fn format(component_type: ComponentType, width: u32, component_count: u32) -> VaryingFormat {
+        VaryingFormat {
+            component_type,
+            width,
+            component_count,
+            element_count: 1,
+        }
+    }
+
+    #[test]
+    fn test_varying_layout() {
+        let dvec3 = format(ComponentType::Float, 64, 3);
+        assert_eq!(dvec3.location_count(), 2);
+        assert!(dvec3.requires_flat());
+        let layout = VaryingLayout::new(
+            &[
+                input(
+                    0,
+                    0,
+                    format(ComponentType::Float, 32, 4),
+                    Interpolation::Smooth,
+                ),
+                input(1, 0, dvec3, Interpolation::Flat),
+                input(
+                    3,
+                    0,
+                    format(ComponentType::SignedInt, 32, 1),
+                    Interpolation::Flat,
+                ),
+                input(
+                    3,
+                    1,
+                    format(ComponentType::Float, 32, 2),
+                    Interpolation::NoPerspective,
+                ),
+            ],
+            4,
+        );
+        assert_eq!(
+            layout.words,
+            [
+                VaryingWords {
+                    start: 0,
+                    count: 4,
+                    interpolation: Interpolation::Smooth,
+                },
+                VaryingWords {
+                    start: 4,
+                    count: 6,
+                    interpolation: Interpolation::Flat,
+                },
+                VaryingWords {
+                    start: 12,
+                    count: 1,
+                    interpolation: Interpolation::Flat,
+                },
+                VaryingWords {
+                    start: 13,
+                    count: 2,
+                    interpolation: Interpolation::NoPerspective,
+                },
+            ]
+        );
+        assert_eq!(layout.words_per_vertex, 15);
+        assert_eq!(layout.flat_words().count(), 2);
+        assert_eq!(layout.interpolated_words().count(), 2);
+        assert_eq!(layout.words_per_group(), 60);
+        assert_eq!(layout.group_word_start(3, 1), 52);
+    }
+
+    #[test]
+    fn test_interpolate_group() {
+        let layout = VaryingLayout::new(
+            &[
+                input(
+                    0,
+                    0,
+                    format(ComponentType::Float, 32, 1),
+                    Interpolation::Smooth,
+                ),
+                input(
+                    0,
+                    1,
+                    format(ComponentType::UnsignedInt, 32, 1),
+                    Interpolation::Flat,
+                ),
+                input(
+                    0,
+                    2,
+                    format(ComponentType::Float, 32, 1),
+                    Interpolation::NoPerspective,
+                ),
+            ],
+            2,
+        );
+        let vertex = |v: f32, flat: u32| [v.to_bits(), flat, (v * 10.0).to_bits()];
+        let vertices = [vertex(1.0, 5), vertex(2.0, 6), vertex(4.0, 7)];
+        let barycentrics = Barycentrics {
+            smooth: [&[1.0, 0.5], &[0.0, 0.25], &[0.0, 0.25]],
+            no_perspective: [&[0.0, 0.0], &[1.0, 0.0], &[0.0, 1.0]],
+        };
+        let mut group = vec![0; layout.words_per_group() as usize];
+        layout.interpolate_group(
+            [&vertices[0], &vertices[1], &vertices[2]],
+            1,
+            &barycentrics,
+            &mut group,
+        );
+        let floats: Vec<f32> = group.iter().map(|&v| f32::from_bits(v)).collect();
+        // each word is stored for both lanes before the next word
+        assert_eq!(floats[0..2], [1.0, 2.0]);
+        assert_eq!(group[2..4], [6, 6]);
+        assert_eq!(floats[4..6], [20.0, 40.0]);
+    }
+
+    #[test]
+    fn test_provoking_vertex() {
+        assert_eq!(
+            PrimitiveTopology::TriangleList.get_provoking_vertex(2),
+            Some(6)
+        );
+        assert_eq!(
+            PrimitiveTopology::TriangleStrip.get_provoking_vertex(2),
+            Some(2)
+        );
+        assert_eq!(
+            PrimitiveTopology::TriangleFan.get_provoking_vertex(2),
+            Some(3)
+        );
+        assert_eq!(
+            PrimitiveTopology::LineListWithAdjacency.get_provoking_vertex(1),
+            Some(5)
+        );
+        assert_eq!(PrimitiveTopology::PatchList.get_provoking_vertex(0), None);
+    }
+}
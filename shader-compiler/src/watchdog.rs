@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! the watchdog that stops shaders that never finish
+//!
+//! shaders run on the host's threads, so a shader stuck in an infinite loop would hang the
+//! application forever. `CompilerIndependentConfig::watchdog_checks` asks for every loop back
+//! edge (see `StructuredCfg::loop_back_edges`) to be instrumented with the equivalent of
+//! `Watchdog::check_back_edge`, using a counter that's local to the invocation and starts at 0.
+//! when the check fails, the invocation returns from its entry point right away and the driver
+//! turns the submission into a device loss.
+//!
+//! the instrumentation belongs in the SPIR-V to backend IR translation, which doesn't exist yet,
+//! so no compiled code checks the watchdog so far. until it does, the watchdog only stops a
+//! submission between its draws and dispatches (see `Watchdog::get_trip`), and the limit on the
+//! number of back edges each invocation takes isn't enforced.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// the value of `Watchdog::tripped` when the watchdog hasn't stopped anything
+pub const NOT_TRIPPED: u32 = 0;
+
+/// the value of `Watchdog::tripped` when an invocation took too many back edges
+pub const TRIPPED_BY_LOOP_ITERATION_LIMIT: u32 = 1;
+
+/// the value of `Watchdog::tripped` when the driver cancelled the submission
+pub const TRIPPED_BY_CANCEL: u32 = 2;
+
+/// why the watchdog stopped a submission
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum WatchdogTrip {
+    /// an invocation took more than `loop_iteration_limit` back edges
+    LoopIterationLimit { loop_iteration_limit: u64 },
+    /// the driver cancelled the submission, because it ran for too long
+    Cancel,
+}
+
+impl fmt::Display for WatchdogTrip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WatchdogTrip::LoopIterationLimit {
+                loop_iteration_limit,
+            } => write!(
+                f,
+                "a shader invocation took more than {} loop iterations; it probably has an \
+                 infinite loop",
+                loop_iteration_limit
+            ),
+            WatchdogTrip::Cancel => write!(f, "the submission timed out"),
+        }
+    }
+}
+
+buildable_struct! {
+    #[derive(Debug)]
+    pub struct Watchdog {
+        // nonzero when the driver cancelled the submission
+        cancelled: AtomicU32,
+        // one of the `TRIPPED_BY_*` values, set by compiled code when it stops an invocation
+        tripped: AtomicU32,
+        // `u64::MAX` when there's no limit
+        loop_iteration_limit: u64,
+    }
+}
+
+impl Watchdog {
+    /// `loop_iteration_limit` is the most back edges each invocation can take
+    pub fn new(loop_iteration_limit: Option<u64>) -> Self {
+        Watchdog {
+            cancelled: AtomicU32::new(0),
+            tripped: AtomicU32::new(NOT_TRIPPED),
+            loop_iteration_limit: loop_iteration_limit.unwrap_or(u64::MAX),
+        }
+    }
+    /// stop the submission at the next back edge. can be called from any thread
+    pub fn cancel(&self) {
+        self.cancelled.store(1, Ordering::Relaxed);
+    }
+    /// what compiled code does at each loop back edge. `iterations` is the number of back edges
+    /// the invocation has taken. returns false if the invocation has to stop
+    pub fn check_back_edge(&self, iterations: &mut u64) -> bool {
+        *iterations += 1;
+        let tripped = if *iterations > self.loop_iteration_limit {
+            TRIPPED_BY_LOOP_ITERATION_LIMIT
+        } else if self.cancelled.load(Ordering::Relaxed) != 0 {
+            TRIPPED_BY_CANCEL
+        } else {
+            return true;
+        };
+        self.tripped.store(tripped, Ordering::Relaxed);
+        false
+    }
+    /// checked by the driver after each draw or dispatch. cancelling trips the watchdog even if
+    /// no back edges are taken afterwards, so the driver stops between commands too
+    pub fn get_trip(&self) -> Option<WatchdogTrip> {
+        match self.tripped.load(Ordering::Relaxed) {
+            NOT_TRIPPED if self.cancelled.load(Ordering::Relaxed) != 0 => {
+                Some(WatchdogTrip::Cancel)
+            }
+            NOT_TRIPPED => None,
+            TRIPPED_BY_LOOP_ITERATION_LIMIT => Some(WatchdogTrip::LoopIterationLimit {
+                loop_iteration_limit: self.loop_iteration_limit,
+            }),
+            TRIPPED_BY_CANCEL => Some(WatchdogTrip::Cancel),
+            tripped => unreachable!("invalid watchdog trip: {}", tripped),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_loop_iteration_limit() {
+        let watchdog = Watchdog::new(Some(3));
+        let mut iterations = 0;
+        for _ in 0..3 {
+            assert!(watchdog.check_back_edge(&mut iterations));
+        }
+        assert_eq!(watchdog.get_trip(), None);
+        assert!(!watchdog.check_back_edge(&mut iterations));
+        assert_eq!(
+            watchdog.get_trip(),
+            Some(WatchdogTrip::LoopIterationLimit {
+                loop_iteration_limit: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_cancel() {
+        let watchdog = Watchdog::new(None);
+        let mut iterations = 0;
+        for _ in 0..1000 {
+            assert!(watchdog.check_back_edge(&mut iterations));
+        }
+        watchdog.cancel();
+        assert_eq!(watchdog.get_trip(), Some(WatchdogTrip::Cancel));
+        assert!(!watchdog.check_back_edge(&mut iterations));
+        assert_eq!(watchdog.get_trip(), Some(WatchdogTrip::Cancel));
+    }
+}
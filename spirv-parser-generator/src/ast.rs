@@ -53,8 +53,8 @@ impl<'de> Deserialize<'de> for QuotedInteger {
             ));
         }
         let retval = match digits.len() {
-            4 => QuotedInteger::U16Hex(u16::from_str_radix(digits, radix).unwrap()),
-            8 => QuotedInteger::U32Hex(u32::from_str_radix(digits, radix).unwrap()),
+            1...4 => QuotedInteger::U16Hex(u16::from_str_radix(digits, radix).unwrap()),
+            5...8 => QuotedInteger::U32Hex(u32::from_str_radix(digits, radix).unwrap()),
             _ => {
                 return Err(de::Error::custom(
                     "invalid quoted integer -- wrong number of hex digits",
@@ -124,9 +124,9 @@ pub enum Quantifier {
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct InstructionOperand {
-    kind: String,
-    name: Option<String>,
-    quantifier: Option<Quantifier>,
+    pub kind: String,
+    pub name: Option<String>,
+    pub quantifier: Option<Quantifier>,
 }
 
 impl InstructionOperand {
@@ -145,16 +145,26 @@ impl InstructionOperand {
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Instruction {
-    opname: String,
-    opcode: u16,
+    pub opname: String,
+    pub opcode: u16,
     #[serde(default)]
-    operands: Vec<InstructionOperand>,
+    pub operands: Vec<InstructionOperand>,
     #[serde(default)]
-    capabilities: Vec<String>,
+    pub capabilities: Vec<String>,
     #[serde(default)]
-    extensions: Vec<String>,
+    pub extensions: Vec<String>,
     #[serde(default)]
-    version: SPIRVVersion,
+    pub version: SPIRVVersion,
+    #[serde(default, rename = "lastVersion")]
+    pub last_version: Option<SPIRVVersion>,
+    /// the section of the specification the instruction is listed in
+    pub class: Option<String>,
+    /// other names for the instruction, such as the name from the extension it came from
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// part of a provisional extension, so it can still change
+    #[serde(default)]
+    pub provisional: bool,
 }
 
 impl Instruction {
@@ -169,12 +179,12 @@ impl Instruction {
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct ExtensionInstruction {
-    opname: String,
-    opcode: u16,
+    pub opname: String,
+    pub opcode: u16,
     #[serde(default)]
-    operands: Vec<InstructionOperand>,
+    pub operands: Vec<InstructionOperand>,
     #[serde(default)]
-    capabilities: Vec<String>,
+    pub capabilities: Vec<String>,
 }
 
 impl ExtensionInstruction {
@@ -189,8 +199,9 @@ impl ExtensionInstruction {
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct EnumerantParameter {
-    kind: String,
-    name: Option<String>,
+    pub kind: String,
+    pub name: Option<String>,
+    pub quantifier: Option<Quantifier>,
 }
 
 impl EnumerantParameter {
@@ -209,16 +220,22 @@ impl EnumerantParameter {
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Enumerant<Value> {
-    enumerant: String,
-    value: Value,
+    pub enumerant: String,
+    pub value: Value,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
     #[serde(default)]
-    capabilities: Vec<String>,
+    pub parameters: Vec<EnumerantParameter>,
     #[serde(default)]
-    parameters: Vec<EnumerantParameter>,
+    pub extensions: Vec<String>,
     #[serde(default)]
-    extensions: Vec<String>,
+    pub version: SPIRVVersion,
+    #[serde(default, rename = "lastVersion")]
+    pub last_version: Option<SPIRVVersion>,
     #[serde(default)]
-    version: SPIRVVersion,
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub provisional: bool,
 }
 
 impl<Value> Enumerant<Value> {
@@ -277,16 +294,25 @@ impl OperandKind {
     }
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct InstructionPrintingClass {
+    pub tag: String,
+    pub heading: Option<String>,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct CoreGrammar {
-    copyright: Vec<String>,
-    magic_number: QuotedInteger,
-    major_version: u16,
-    minor_version: u16,
-    revision: u32,
-    instructions: Vec<Instruction>,
-    operand_kinds: Vec<OperandKind>,
+    pub copyright: Vec<String>,
+    pub magic_number: QuotedInteger,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub revision: u32,
+    #[serde(default)]
+    pub instruction_printing_class: Vec<InstructionPrintingClass>,
+    pub instructions: Vec<Instruction>,
+    pub operand_kinds: Vec<OperandKind>,
 }
 
 impl CoreGrammar {
@@ -304,10 +330,10 @@ impl CoreGrammar {
 #[derive(Deserialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct ExtensionInstructionSet {
-    copyright: Vec<String>,
-    version: u32,
-    revision: u32,
-    instructions: Vec<ExtensionInstruction>,
+    pub copyright: Vec<String>,
+    pub version: u32,
+    pub revision: u32,
+    pub instructions: Vec<ExtensionInstruction>,
 }
 
 impl ExtensionInstructionSet {
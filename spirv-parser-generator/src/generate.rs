@@ -0,0 +1,921 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! generation of the Rust code for the parser
+//!
+//! the generated code is included in `spirv-parser`, which provides the types for literals and
+//! the `ParseOperand`, `WriteOperand` and `ParseState` types the generated code uses.
+
+use ast;
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use std::collections::{HashMap, HashSet};
+use std::iter;
+use util::NameFormat::*;
+use util::WordIterator;
+use Error;
+use ExtensionInstructionSet;
+
+/// names of locals in the generated code, which fields can't use
+const GENERATED_LOCAL_NAMES: &[&str] = &[
+    "words",
+    "parse_state",
+    "output",
+    "start",
+    "length",
+    "opcode",
+    "mask",
+    "retval",
+];
+
+/// operand kinds that need the type of an earlier operand to know their size
+const CONTEXT_DEPENDENT_KINDS: &[&str] =
+    &["LiteralContextDependentNumber", "PairLiteralIntegerIdRef"];
+
+fn new_id<T: AsRef<str>>(name: T) -> Ident {
+    Ident::new(name.as_ref(), Span::call_site())
+}
+
+fn new_field_name(
+    name: &str,
+    used_names: &mut HashSet<String>,
+    error: fn() -> Error,
+) -> Result<Ident, Error> {
+    let mut name = SnakeCase
+        .name_from_words(WordIterator::new(name))
+        .ok_or_else(error)?;
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name = format!("value_{}", name);
+    }
+    if GENERATED_LOCAL_NAMES.contains(&&*name) {
+        name += "_";
+    }
+    if used_names.contains(&name) {
+        let mut index = 2;
+        while used_names.contains(&format!("{}_{}", name, index)) {
+            index += 1;
+        }
+        name = format!("{}_{}", name, index);
+    }
+    used_names.insert(name.clone());
+    Ok(new_id(name))
+}
+
+fn new_type_name(name: &str, prefix: &str) -> Result<Ident, Error> {
+    let name = CamelCase
+        .name_from_words(WordIterator::new(name))
+        .ok_or(Error::DeducingNameForEnumerantFailed)?;
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        Ok(new_id(format!("{}{}", prefix, name)))
+    } else {
+        Ok(new_id(name))
+    }
+}
+
+/// the name of a constant in one of the modules of enumerant values or opcodes
+fn new_constant_name<'a, I: Iterator<Item = &'a str>>(
+    words: I,
+    prefix: &str,
+    error: fn() -> Error,
+) -> Result<String, Error> {
+    let name = ScreamingSnakeCase
+        .name_from_words(words)
+        .ok_or_else(error)?;
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        let prefix = ScreamingSnakeCase
+            .name_from_words(WordIterator::new(prefix))
+            .ok_or_else(error)?;
+        Ok(format!("{}_{}", prefix, name))
+    } else {
+        Ok(name)
+    }
+}
+
+fn quantified_type(kind: &Ident, quantifier: Option<ast::Quantifier>) -> TokenStream {
+    match quantifier {
+        None => quote! {#kind},
+        Some(ast::Quantifier::Optional) => quote! {Option<#kind>},
+        Some(ast::Quantifier::Variadic) => quote! {Vec<#kind>},
+    }
+}
+
+/// the rules for which operand kinds are `Copy`
+struct OperandKinds<'a> {
+    kinds: HashMap<&'a str, &'a ast::OperandKind>,
+}
+
+impl<'a> OperandKinds<'a> {
+    fn new(operand_kinds: &'a [ast::OperandKind]) -> Self {
+        OperandKinds {
+            kinds: operand_kinds
+                .iter()
+                .map(|operand_kind| (get_kind_name(operand_kind), operand_kind))
+                .collect(),
+        }
+    }
+    fn is_copy(&self, kind: &str) -> bool {
+        match self.kinds.get(kind) {
+            Some(ast::OperandKind::Id { .. }) => true,
+            Some(ast::OperandKind::Literal { kind, .. }) => match &**kind {
+                "LiteralString" | "LiteralSpecConstantOpInteger" => false,
+                _ => true,
+            },
+            Some(ast::OperandKind::ValueEnum { enumerants, .. }) => enumerants
+                .iter()
+                .flat_map(|enumerant| &enumerant.parameters)
+                .all(|parameter| self.is_quantified_copy(&parameter.kind, parameter.quantifier)),
+            Some(ast::OperandKind::BitEnum { enumerants, .. }) => enumerants
+                .iter()
+                .flat_map(|enumerant| &enumerant.parameters)
+                .all(|parameter| self.is_quantified_copy(&parameter.kind, parameter.quantifier)),
+            Some(ast::OperandKind::Composite { bases, .. }) => {
+                bases.iter().all(|base| self.is_copy(base))
+            }
+            None => false,
+        }
+    }
+    fn is_quantified_copy(&self, kind: &str, quantifier: Option<ast::Quantifier>) -> bool {
+        match quantifier {
+            None | Some(ast::Quantifier::Optional) => self.is_copy(kind),
+            Some(ast::Quantifier::Variadic) => false,
+        }
+    }
+    fn derives(&self, is_copy: bool) -> TokenStream {
+        if is_copy {
+            quote! {#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]}
+        } else {
+            quote! {#[derive(Clone, Debug, Eq, PartialEq, Hash)]}
+        }
+    }
+}
+
+fn get_kind_name(operand_kind: &ast::OperandKind) -> &str {
+    match operand_kind {
+        ast::OperandKind::BitEnum { kind, .. }
+        | ast::OperandKind::ValueEnum { kind, .. }
+        | ast::OperandKind::Id { kind, .. }
+        | ast::OperandKind::Literal { kind, .. }
+        | ast::OperandKind::Composite { kind, .. } => kind,
+    }
+}
+
+/// keeps the first of the enumerants that have the same value, returning the enumerants and
+/// their values
+fn dedup_enumerants<Value: Copy>(
+    enumerants: &[ast::Enumerant<Value>],
+    get_value: fn(Value) -> u32,
+) -> Vec<(u32, &ast::Enumerant<Value>)> {
+    let mut values = HashSet::new();
+    enumerants
+        .iter()
+        .map(|enumerant| (get_value(enumerant.value), enumerant))
+        .filter(|&(value, _)| values.insert(value))
+        .collect()
+}
+
+fn quoted_integer_value(value: ast::QuotedInteger) -> u32 {
+    match value {
+        ast::QuotedInteger::U16Hex(v) => v.into(),
+        ast::QuotedInteger::U32Hex(v) => v,
+    }
+}
+
+/// an operand of an instruction or a parameter of an enumerant
+struct Field {
+    name: Ident,
+    ty: TokenStream,
+    parse: TokenStream,
+}
+
+/// the fields for `operands`, along with whether they're all `Copy`.
+/// `OpExtInst`'s instruction and its operands are combined into one `ExtensionInstruction` field
+fn get_instruction_fields(
+    operands: &[ast::InstructionOperand],
+    operand_kinds: &OperandKinds,
+) -> Result<(Vec<Field>, bool), Error> {
+    let mut used_names = HashSet::new();
+    let mut fields: Vec<Field> = Vec::new();
+    let mut is_copy = true;
+    let needs_context = operands
+        .iter()
+        .any(|operand| CONTEXT_DEPENDENT_KINDS.contains(&&*operand.kind));
+    let mut has_context = false;
+    for operand in operands {
+        let name = new_field_name(
+            operand.name.as_ref().unwrap_or(&operand.kind),
+            &mut used_names,
+            || Error::DeducingNameForInstructionOperandFailed,
+        )?;
+        if operand.kind == "LiteralExtInstInteger" {
+            let set = fields
+                .last()
+                .expect("OpExtInst's instruction must follow the set")
+                .name
+                .clone();
+            fields.push(Field {
+                parse: quote! {
+                    let (#name, words) = ExtensionInstruction::parse(words, parse_state, #set)?;
+                },
+                ty: quote! {ExtensionInstruction},
+                name,
+            });
+            is_copy = false;
+            break;
+        }
+        let kind = new_id(&operand.kind);
+        let ty = quantified_type(&kind, operand.quantifier);
+        is_copy = is_copy && operand_kinds.is_quantified_copy(&operand.kind, operand.quantifier);
+        let mut parse = quote! {
+            let (#name, words) = <#ty as ParseOperand>::parse(words, parse_state)?;
+        };
+        if needs_context && !has_context && operand.quantifier.is_none() {
+            // the size of the literals depends on the result type or on the type of the first
+            // id, like `OpSwitch`'s selector
+            match &*operand.kind {
+                "IdResultType" => {
+                    parse.extend(quote! {parse_state.set_context_type(#name.0);});
+                    has_context = true;
+                }
+                "IdRef" => {
+                    parse.extend(quote! {parse_state.set_context_id(#name.0);});
+                    has_context = true;
+                }
+                _ => {}
+            }
+        }
+        fields.push(Field { name, ty, parse });
+    }
+    Ok((fields, is_copy))
+}
+
+/// the variant declaration, its pattern and the statements to parse its fields
+fn generate_variant(
+    type_name: &Ident,
+    variant_name: &Ident,
+    fields: &[Field],
+) -> (TokenStream, TokenStream, TokenStream) {
+    let parse_fields: Vec<_> = fields.iter().map(|field| &field.parse).collect();
+    if fields.is_empty() {
+        (
+            quote! {#variant_name},
+            quote! {#type_name::#variant_name},
+            quote! {},
+        )
+    } else {
+        let names: &Vec<_> = &fields.iter().map(|field| &field.name).collect();
+        let names2 = names;
+        let types = fields.iter().map(|field| &field.ty);
+        (
+            quote! {#variant_name { #(#names: #types,)* }},
+            quote! {#type_name::#variant_name { #(#names2,)* }},
+            quote! {#(#parse_fields)*},
+        )
+    }
+}
+
+fn generate_id_kind(kind: &str, doc: &Option<String>) -> TokenStream {
+    let name = new_id(kind);
+    let doc = doc.as_ref().map(|doc| quote! {#[doc = #doc]});
+    quote! {
+        #doc
+        #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+        pub struct #name(pub u32);
+
+        impl ParseOperand for #name {
+            fn parse<'a>(words: &'a [u32], parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+                let (id, words) = <u32 as ParseOperand>::parse(words, parse_state)?;
+                Ok((#name(id), words))
+            }
+        }
+
+        impl WriteOperand for #name {
+            fn write(&self, output: &mut Vec<u32>) {
+                output.push(self.0);
+            }
+        }
+    }
+}
+
+fn generate_value_enum(
+    kind: &str,
+    enumerants: &[ast::Enumerant<u32>],
+    operand_kinds: &OperandKinds,
+) -> Result<TokenStream, Error> {
+    let name = new_id(kind);
+    let mut variants = Vec::new();
+    let mut parse_cases = Vec::new();
+    let mut write_cases = Vec::new();
+    for (value, enumerant) in dedup_enumerants(enumerants, |value| value) {
+        let mut used_names = HashSet::new();
+        let mut fields = Vec::new();
+        for parameter in &enumerant.parameters {
+            let field_name = new_field_name(
+                parameter.name.as_ref().unwrap_or(&parameter.kind),
+                &mut used_names,
+                || Error::DeducingNameForEnumerantParameterFailed,
+            )?;
+            let ty = quantified_type(&new_id(&parameter.kind), parameter.quantifier);
+            fields.push(Field {
+                parse: quote! {
+                    let (#field_name, words) = <#ty as ParseOperand>::parse(words, parse_state)?;
+                },
+                ty,
+                name: field_name,
+            });
+        }
+        let variant_name = new_type_name(&enumerant.enumerant, kind)?;
+        let (variant, pattern, parse_fields) = generate_variant(&name, &variant_name, &fields);
+        let value = Literal::u32_unsuffixed(value);
+        let field_names = fields.iter().map(|field| &field.name);
+        variants.push(variant);
+        parse_cases.push(quote! {
+            #value => {
+                #parse_fields
+                Ok((#pattern, words))
+            }
+        });
+        write_cases.push(quote! {
+            #pattern => {
+                output.push(#value);
+                #(WriteOperand::write(#field_names, output);)*
+            }
+        });
+    }
+    // a module of the values, for code that works on words instead of parsing them
+    let module_name = new_id(
+        SnakeCase
+            .name_from_words(WordIterator::new(kind))
+            .ok_or(Error::DeducingNameForEnumerantFailed)?,
+    );
+    let mut constants = Vec::new();
+    let mut constant_names = HashSet::new();
+    for enumerant in enumerants {
+        for enumerant_name in iter::once(&enumerant.enumerant).chain(&enumerant.aliases) {
+            let constant_name = new_constant_name(WordIterator::new(enumerant_name), kind, || {
+                Error::DeducingNameForEnumerantFailed
+            })?;
+            if !constant_names.insert(constant_name.clone()) {
+                continue;
+            }
+            let constant_name = new_id(constant_name);
+            let value = Literal::u32_unsuffixed(enumerant.value);
+            constants.push(quote! {
+                #[doc = #enumerant_name]
+                pub const #constant_name: u32 = #value;
+            });
+        }
+    }
+    let derives = operand_kinds.derives(operand_kinds.is_copy(kind));
+    Ok(quote! {
+        #derives
+        pub enum #name {
+            #(#variants,)*
+        }
+
+        pub mod #module_name {
+            #(#constants)*
+        }
+
+        impl ParseOperand for #name {
+            fn parse<'a>(words: &'a [u32], parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+                let (value, words) = <u32 as ParseOperand>::parse(words, parse_state)?;
+                match value {
+                    #(#parse_cases)*
+                    value => Err(Error::InvalidEnumValue { kind: #kind, value }),
+                }
+            }
+        }
+
+        impl WriteOperand for #name {
+            fn write(&self, output: &mut Vec<u32>) {
+                match self {
+                    #(#write_cases)*
+                }
+            }
+        }
+    })
+}
+
+fn generate_bit_enum(
+    kind: &str,
+    enumerants: &[ast::Enumerant<ast::QuotedInteger>],
+    operand_kinds: &OperandKinds,
+) -> Result<TokenStream, Error> {
+    let name = new_id(kind);
+    let mut enumerants: Vec<_> = dedup_enumerants(enumerants, quoted_integer_value)
+        .into_iter()
+        .filter(|&(value, _)| value != 0)
+        .collect();
+    // parameters are in the order of the bits
+    enumerants.sort_by_key(|&(value, _)| value);
+    let mut used_names = HashSet::new();
+    let mut parameter_structs = Vec::new();
+    let mut fields = Vec::new();
+    let mut field_names = Vec::new();
+    let mut parse_bits = Vec::new();
+    let mut write_masks = Vec::new();
+    let mut write_parameters = Vec::new();
+    let mut known_bits = 0u32;
+    for (value, enumerant) in enumerants {
+        known_bits |= value;
+        let field_name = new_field_name(&enumerant.enumerant, &mut used_names, || {
+            Error::DeducingNameForEnumerantFailed
+        })?;
+        let bit = Literal::u32_unsuffixed(value);
+        field_names.push(field_name.clone());
+        if enumerant.parameters.is_empty() {
+            fields.push(quote! {pub #field_name: bool});
+            parse_bits.push(quote! {let #field_name = mask & #bit != 0;});
+            write_masks.push(quote! {mask |= if self.#field_name { #bit } else { 0 };});
+            continue;
+        }
+        let parameter_struct_name = new_id(format!(
+            "{}{}",
+            kind,
+            new_type_name(&enumerant.enumerant, "")?
+        ));
+        let parameter_types: &Vec<_> = &enumerant
+            .parameters
+            .iter()
+            .map(|parameter| quantified_type(&new_id(&parameter.kind), parameter.quantifier))
+            .collect();
+        let parameter_names: &Vec<_> = &(0..parameter_types.len())
+            .map(|index| new_id(format!("parameter{}", index)))
+            .collect();
+        let parameter_names2 = parameter_names;
+        let derives = operand_kinds.derives(enumerant.parameters.iter().all(|parameter| {
+            operand_kinds.is_quantified_copy(&parameter.kind, parameter.quantifier)
+        }));
+        parameter_structs.push(quote! {
+            #derives
+            pub struct #parameter_struct_name(#(pub #parameter_types,)*);
+        });
+        fields.push(quote! {pub #field_name: Option<#parameter_struct_name>});
+        parse_bits.push(quote! {
+            let #field_name = if mask & #bit != 0 {
+                #(
+                    let (#parameter_names, new_words) =
+                        <#parameter_types as ParseOperand>::parse(words, parse_state)?;
+                    words = new_words;
+                )*
+                Some(#parameter_struct_name(#(#parameter_names2,)*))
+            } else {
+                None
+            };
+        });
+        write_masks.push(quote! {mask |= if self.#field_name.is_some() { #bit } else { 0 };});
+        write_parameters.push(quote! {
+            if let Some(#parameter_struct_name(#(#parameter_names,)*)) = &self.#field_name {
+                #(WriteOperand::write(#parameter_names2, output);)*
+            };
+        });
+    }
+    let known_bits = Literal::u32_unsuffixed(known_bits);
+    let mut_words = if write_parameters.is_empty() {
+        quote! {words}
+    } else {
+        quote! {mut words}
+    };
+    let derives = if operand_kinds.is_copy(kind) {
+        quote! {#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]}
+    } else {
+        quote! {#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]}
+    };
+    Ok(quote! {
+        #(#parameter_structs)*
+
+        #derives
+        pub struct #name {
+            #(#fields,)*
+        }
+
+        impl ParseOperand for #name {
+            fn parse<'a>(words: &'a [u32], parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+                let (mask, #mut_words) = <u32 as ParseOperand>::parse(words, parse_state)?;
+                if mask & !#known_bits != 0 {
+                    return Err(Error::InvalidEnumValue { kind: #kind, value: mask });
+                }
+                #(#parse_bits)*
+                Ok((Self { #(#field_names,)* }, words))
+            }
+        }
+
+        impl WriteOperand for #name {
+            fn write(&self, output: &mut Vec<u32>) {
+                let mut mask = 0;
+                #(#write_masks)*
+                output.push(mask);
+                #(#write_parameters)*
+            }
+        }
+    })
+}
+
+fn generate_composite(kind: &str, bases: &[String], operand_kinds: &OperandKinds) -> TokenStream {
+    let name = new_id(kind);
+    let base_types: Vec<_> = bases
+        .iter()
+        .map(|base| {
+            // `OpSwitch`'s literals are the same width as its selector
+            if kind == "PairLiteralIntegerIdRef" && base == "LiteralInteger" {
+                new_id("LiteralContextDependentNumber")
+            } else {
+                new_id(base)
+            }
+        })
+        .collect();
+    let base_types = &base_types;
+    let names: Vec<_> = (0..bases.len())
+        .map(|index| new_id(format!("base{}", index)))
+        .collect();
+    let names = &names;
+    let names2 = names;
+    let derives = operand_kinds.derives(bases.iter().all(|base| operand_kinds.is_copy(base)));
+    quote! {
+        #derives
+        pub struct #name(#(pub #base_types,)*);
+
+        impl ParseOperand for #name {
+            fn parse<'a>(words: &'a [u32], parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+                #(let (#names, words) = <#base_types as ParseOperand>::parse(words, parse_state)?;)*
+                Ok((#name(#(#names2,)*), words))
+            }
+        }
+
+        impl WriteOperand for #name {
+            fn write(&self, output: &mut Vec<u32>) {
+                let #name(#(#names,)*) = self;
+                #(WriteOperand::write(#names2, output);)*
+            }
+        }
+    }
+}
+
+fn generate_extension_instruction_set(
+    extension_instruction_set: ExtensionInstructionSet,
+    grammar: &ast::ExtensionInstructionSet,
+    operand_kinds: &OperandKinds,
+) -> Result<TokenStream, Error> {
+    let name = new_id(format!(
+        "{}Instruction",
+        extension_instruction_set.get_rust_name()
+    ));
+    let import_name = extension_instruction_set.get_import_name();
+    let doc = format!(
+        "an instruction from the {} extended instruction set",
+        import_name
+    );
+    let mut variants = Vec::new();
+    let mut parse_cases = Vec::new();
+    let mut write_cases = Vec::new();
+    let mut is_copy = true;
+    let mut opcodes = HashSet::new();
+    for instruction in &grammar.instructions {
+        if !opcodes.insert(instruction.opcode) {
+            continue;
+        }
+        let (fields, fields_are_copy) =
+            get_instruction_fields(&instruction.operands, operand_kinds)?;
+        is_copy = is_copy && fields_are_copy;
+        let variant_name = new_type_name(&instruction.opname, "")?;
+        let (variant, pattern, parse_fields) = generate_variant(&name, &variant_name, &fields);
+        let opcode = Literal::u32_unsuffixed(instruction.opcode.into());
+        let field_names = fields.iter().map(|field| &field.name);
+        variants.push(variant);
+        parse_cases.push(quote! {
+            #opcode => {
+                #parse_fields
+                Ok((#pattern, words))
+            }
+        });
+        write_cases.push(quote! {
+            #pattern => {
+                output.push(#opcode);
+                #(WriteOperand::write(#field_names, output);)*
+            }
+        });
+    }
+    let derives = operand_kinds.derives(is_copy);
+    Ok(quote! {
+        #[doc = #doc]
+        #derives
+        pub enum #name {
+            #(#variants,)*
+        }
+
+        impl #name {
+            fn parse<'a>(
+                instruction: u32,
+                words: &'a [u32],
+                parse_state: &mut ParseState,
+            ) -> Result<(Self, &'a [u32])> {
+                match instruction {
+                    #(#parse_cases)*
+                    instruction => Err(Error::UnknownExtensionInstruction {
+                        set: #import_name,
+                        instruction,
+                    }),
+                }
+            }
+        }
+
+        impl WriteOperand for #name {
+            fn write(&self, output: &mut Vec<u32>) {
+                match self {
+                    #(#write_cases)*
+                }
+            }
+        }
+    })
+}
+
+fn generate_extension_instruction(
+    extension_instruction_sets: &[ExtensionInstructionSet],
+) -> TokenStream {
+    let names: Vec<_> = extension_instruction_sets
+        .iter()
+        .map(|v| new_id(v.get_rust_name()))
+        .collect();
+    let names = &names;
+    let instruction_types: Vec<_> = extension_instruction_sets
+        .iter()
+        .map(|v| new_id(format!("{}Instruction", v.get_rust_name())))
+        .collect();
+    let instruction_types = &instruction_types;
+    let import_names = extension_instruction_sets
+        .iter()
+        .map(|v| v.get_import_name());
+    let (names2, names3, names4, names5) = (names, names, names, names);
+    quote! {
+        /// the extended instruction sets this parser knows about
+        #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+        pub enum ExtensionInstructionSet {
+            #(#names,)*
+        }
+
+        impl ExtensionInstructionSet {
+            /// the set for the name used in `OpExtInstImport`
+            pub fn from_import_name(name: &str) -> Option<Self> {
+                match name {
+                    #(#import_names => Some(ExtensionInstructionSet::#names2),)*
+                    _ => None,
+                }
+            }
+        }
+
+        /// the instruction used by `OpExtInst`.
+        /// instructions from sets this parser doesn't know about are kept as words
+        #[derive(Clone, Debug, Eq, PartialEq, Hash)]
+        pub enum ExtensionInstruction {
+            #(#names3(#instruction_types),)*
+            Unknown {
+                instruction: LiteralExtInstInteger,
+                operands: Vec<u32>,
+            },
+        }
+
+        impl ExtensionInstruction {
+            fn parse<'a>(
+                words: &'a [u32],
+                parse_state: &mut ParseState,
+                set: IdRef,
+            ) -> Result<(Self, &'a [u32])> {
+                let (instruction, words) =
+                    <LiteralExtInstInteger as ParseOperand>::parse(words, parse_state)?;
+                match parse_state.get_extension_instruction_set(set) {
+                    Some(set) => match set {
+                        #(ExtensionInstructionSet::#names4 => {
+                            let (instruction, words) =
+                                #instruction_types::parse(instruction, words, parse_state)?;
+                            Ok((ExtensionInstruction::#names5(instruction), words))
+                        })*
+                    },
+                    None => Ok((
+                        ExtensionInstruction::Unknown {
+                            instruction,
+                            operands: words.to_vec(),
+                        },
+                        &[],
+                    )),
+                }
+            }
+        }
+
+        impl WriteOperand for ExtensionInstruction {
+            fn write(&self, output: &mut Vec<u32>) {
+                match self {
+                    #(ExtensionInstruction::#names(instruction) => instruction.write(output),)*
+                    ExtensionInstruction::Unknown {
+                        instruction,
+                        operands,
+                    } => {
+                        output.push(*instruction);
+                        output.extend_from_slice(operands);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// the `op` module, with a constant for each opcode and alias, for code that works on the words
+/// of instructions instead of parsing them
+fn generate_opcodes(instructions: &[ast::Instruction]) -> Result<TokenStream, Error> {
+    let mut constants = Vec::new();
+    let mut name_cases = Vec::new();
+    let mut names = HashSet::new();
+    let mut opcodes = HashSet::new();
+    for instruction in instructions {
+        let opcode = Literal::u16_unsuffixed(instruction.opcode);
+        // later instructions with the same opcode are aliases
+        if opcodes.insert(instruction.opcode) {
+            let opname = &instruction.opname;
+            name_cases.push(quote! {#opcode => #opname,});
+        }
+        for opname in iter::once(&instruction.opname).chain(&instruction.aliases) {
+            // skip the `Op` prefix
+            let name = new_constant_name(WordIterator::new(opname).skip(1), "", || {
+                Error::DeducingNameForOpcodeFailed
+            })?;
+            if !names.insert(name.clone()) {
+                continue;
+            }
+            let name = new_id(name);
+            constants.push(quote! {
+                #[doc = #opname]
+                pub const #name: u16 = #opcode;
+            });
+        }
+    }
+    Ok(quote! {
+        pub mod op {
+            #(#constants)*
+
+            /// the name of `opcode`, such as `"OpNop"`
+            pub fn name(opcode: u16) -> Option<&'static str> {
+                Some(match opcode {
+                    #(#name_cases)*
+                    _ => return None,
+                })
+            }
+        }
+    })
+}
+
+fn generate_instruction(
+    instructions: &[ast::Instruction],
+    operand_kinds: &OperandKinds,
+) -> Result<TokenStream, Error> {
+    let name = new_id("Instruction");
+    let mut variants = Vec::new();
+    let mut parse_cases = Vec::new();
+    let mut write_cases = Vec::new();
+    let mut opcode_cases = Vec::new();
+    let mut id_result_cases = Vec::new();
+    let mut id_result_type_cases = Vec::new();
+    let mut opcodes = HashSet::new();
+    for instruction in instructions {
+        // later instructions with the same opcode are aliases
+        if !opcodes.insert(instruction.opcode) {
+            continue;
+        }
+        let (fields, _) = get_instruction_fields(&instruction.operands, operand_kinds)?;
+        let variant_name = new_id(&instruction.opname);
+        let (variant, pattern, parse_fields) = generate_variant(&name, &variant_name, &fields);
+        let opcode = Literal::u16_unsuffixed(instruction.opcode);
+        let field_names = fields.iter().map(|field| &field.name);
+        let opname = &instruction.opname;
+        variants.push(quote! {
+            #[doc = #opname]
+            #variant
+        });
+        parse_cases.push(quote! {
+            #opcode => {
+                #parse_fields
+                if !words.is_empty() {
+                    return Err(Error::InstructionTooLong { opcode });
+                }
+                Ok(#pattern)
+            }
+        });
+        let pattern_without_fields = if fields.is_empty() {
+            quote! {#name::#variant_name}
+        } else {
+            quote! {#name::#variant_name { .. }}
+        };
+        write_cases.push(quote! {
+            #pattern => {
+                #(WriteOperand::write(#field_names, output);)*
+                #opcode
+            }
+        });
+        opcode_cases.push(quote! {#pattern_without_fields => #opcode,});
+        for (operand, field) in instruction.operands.iter().zip(&fields) {
+            let field_name = &field.name;
+            match &*operand.kind {
+                "IdResult" => id_result_cases.push(quote! {
+                    #name::#variant_name { #field_name, .. } => Some(*#field_name),
+                }),
+                "IdResultType" => id_result_type_cases.push(quote! {
+                    #name::#variant_name { #field_name, .. } => Some(*#field_name),
+                }),
+                _ => {}
+            }
+        }
+    }
+    Ok(quote! {
+        #[derive(Clone, Debug, Eq, PartialEq, Hash)]
+        pub enum #name {
+            #(#variants,)*
+        }
+
+        impl #name {
+            /// parse the instruction in `words`, which includes the word with the opcode
+            fn parse(words: &[u32], parse_state: &mut ParseState) -> Result<Self> {
+                let opcode = words[0] as u16;
+                let words = &words[1..];
+                match opcode {
+                    #(#parse_cases)*
+                    opcode => Err(Error::UnknownOpcode(opcode)),
+                }
+            }
+            /// append the encoded instruction to `output`
+            pub fn write(&self, output: &mut Vec<u32>) {
+                let start = output.len();
+                output.push(0);
+                let opcode: u16 = match self {
+                    #(#write_cases)*
+                };
+                let length = output.len() - start;
+                assert!(length <= 0xFFFF, "instruction too long");
+                output[start] = (length as u32) << 16 | u32::from(opcode);
+            }
+            pub fn opcode(&self) -> u16 {
+                match self {
+                    #(#opcode_cases)*
+                }
+            }
+            pub fn id_result(&self) -> Option<IdResult> {
+                match self {
+                    #(#id_result_cases)*
+                    _ => None,
+                }
+            }
+            pub fn id_result_type(&self) -> Option<IdResultType> {
+                match self {
+                    #(#id_result_type_cases)*
+                    _ => None,
+                }
+            }
+        }
+    })
+}
+
+pub fn generate(
+    core_grammar: &ast::CoreGrammar,
+    extension_instruction_sets: &HashMap<ExtensionInstructionSet, ast::ExtensionInstructionSet>,
+) -> Result<TokenStream, Error> {
+    let operand_kinds = OperandKinds::new(&core_grammar.operand_kinds);
+    let magic_number = Literal::u32_unsuffixed(quoted_integer_value(core_grammar.magic_number));
+    let major_version = Literal::u32_unsuffixed(core_grammar.major_version.into());
+    let minor_version = Literal::u32_unsuffixed(core_grammar.minor_version.into());
+    let revision = Literal::u32_unsuffixed(core_grammar.revision);
+    let mut retval = quote! {
+        pub const MAGIC_NUMBER: u32 = #magic_number;
+        /// the newest version of SPIR-V the parser supports
+        pub const MAJOR_VERSION: u32 = #major_version;
+        pub const MINOR_VERSION: u32 = #minor_version;
+        pub const REVISION: u32 = #revision;
+    };
+    for operand_kind in &core_grammar.operand_kinds {
+        retval.extend(match operand_kind {
+            ast::OperandKind::Id { kind, doc } => generate_id_kind(kind, doc),
+            // literals are implemented by hand since they don't follow a pattern
+            ast::OperandKind::Literal { .. } => continue,
+            ast::OperandKind::ValueEnum { kind, enumerants } => {
+                generate_value_enum(kind, enumerants, &operand_kinds)?
+            }
+            ast::OperandKind::BitEnum { kind, enumerants } => {
+                generate_bit_enum(kind, enumerants, &operand_kinds)?
+            }
+            ast::OperandKind::Composite { kind, bases } => {
+                generate_composite(kind, bases, &operand_kinds)
+            }
+        });
+    }
+    let mut sets: Vec<_> = extension_instruction_sets.keys().cloned().collect();
+    sets.sort_by_key(|set| set.get_rust_name());
+    for &set in &sets {
+        retval.extend(generate_extension_instruction_set(
+            set,
+            &extension_instruction_sets[&set],
+            &operand_kinds,
+        )?);
+    }
+    retval.extend(generate_extension_instruction(&sets));
+    retval.extend(generate_opcodes(&core_grammar.instructions)?);
+    retval.extend(generate_instruction(
+        &core_grammar.instructions,
+        &operand_kinds,
+    )?);
+    Ok(retval)
+}
@@ -3,6 +3,9 @@
 
 #[macro_use]
 extern crate serde_derive;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
 extern crate serde;
 extern crate serde_json;
 
@@ -10,11 +13,12 @@ use std::collections::HashMap;
 use std::error;
 use std::fmt;
 use std::fs::File;
-use std::io;
+use std::io::{self, Write};
 use std::path::Path;
 use std::path::PathBuf;
 
 mod ast;
+mod generate;
 mod util;
 
 pub const SPIRV_CORE_GRAMMAR_JSON_FILE_NAME: &str = "spirv.core.grammar.json";
@@ -32,6 +36,20 @@ impl ExtensionInstructionSet {
             ExtensionInstructionSet::OpenCLStd => "extinst.opencl.std.100.grammar.json",
         }
     }
+    /// the name used by `OpExtInstImport`
+    pub fn get_import_name(self) -> &'static str {
+        match self {
+            ExtensionInstructionSet::GLSLStd450 => "GLSL.std.450",
+            ExtensionInstructionSet::OpenCLStd => "OpenCL.std",
+        }
+    }
+    /// the name used for the set in the generated code
+    pub fn get_rust_name(self) -> &'static str {
+        match self {
+            ExtensionInstructionSet::GLSLStd450 => "GLSLStd450",
+            ExtensionInstructionSet::OpenCLStd => "OpenCLStd",
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -40,6 +58,8 @@ pub enum Error {
     JSONError(serde_json::Error),
     DeducingNameForInstructionOperandFailed,
     DeducingNameForEnumerantParameterFailed,
+    DeducingNameForEnumerantFailed,
+    DeducingNameForOpcodeFailed,
 }
 
 impl From<io::Error> for Error {
@@ -69,6 +89,10 @@ impl fmt::Display for Error {
             Error::DeducingNameForEnumerantParameterFailed => {
                 write!(f, "deducing name for EnumerantParameter failed")
             }
+            Error::DeducingNameForEnumerantFailed => {
+                write!(f, "deducing name for Enumerant failed")
+            }
+            Error::DeducingNameForOpcodeFailed => write!(f, "deducing name for opcode failed"),
         }
     }
 }
@@ -81,14 +105,28 @@ impl From<Error> for io::Error {
             Error::IOError(v) => v,
             Error::JSONError(v) => v.into(),
             error @ Error::DeducingNameForInstructionOperandFailed
-            | error @ Error::DeducingNameForEnumerantParameterFailed => {
+            | error @ Error::DeducingNameForEnumerantParameterFailed
+            | error @ Error::DeducingNameForEnumerantFailed
+            | error @ Error::DeducingNameForOpcodeFailed => {
                 io::Error::new(io::ErrorKind::Other, format!("{}", error))
             }
         }
     }
 }
 
-pub struct Output {}
+/// the generated parser
+pub struct Output {
+    text: String,
+}
+
+impl Output {
+    pub fn write<W: Write>(&self, mut output: W) -> io::Result<()> {
+        write!(output, "{}", self.text)
+    }
+    pub fn write_to_file<T: AsRef<Path>>(&self, path: T) -> io::Result<()> {
+        self.write(File::create(path)?)
+    }
+}
 
 pub struct Input {
     spirv_core_grammar_json_path: PathBuf,
@@ -132,13 +170,17 @@ impl Input {
             let mut parsed_extension_instruction_set: ast::ExtensionInstructionSet =
                 serde_json::from_reader(File::open(path)?)?;
             parsed_extension_instruction_set.guess_names()?;
-            assert!(
-                parsed_extension_instruction_sets
-                    .insert(extension_instruction_set, parsed_extension_instruction_set)
-                    .is_none()
-            );
+            assert!(parsed_extension_instruction_sets
+                .insert(extension_instruction_set, parsed_extension_instruction_set)
+                .is_none());
         }
-        unimplemented!()
+        let code = generate::generate(&core_grammar, &parsed_extension_instruction_sets)?;
+        Ok(Output {
+            text: format!(
+                "// automatically generated by spirv-parser-generator from {}\n{}\n",
+                SPIRV_CORE_GRAMMAR_JSON_FILE_NAME, code
+            ),
+        })
     }
 }
 
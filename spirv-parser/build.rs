@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+extern crate spirv_parser_generator;
+use spirv_parser_generator::*;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const SPIRV_GRAMMAR_PATH: &str = "../external/SPIRV-Headers/include/spirv/unified1";
+
+fn main() -> io::Result<()> {
+    let grammar_path = Path::new(SPIRV_GRAMMAR_PATH);
+    let extension_instruction_sets = [
+        ExtensionInstructionSet::GLSLStd450,
+        ExtensionInstructionSet::OpenCLStd,
+    ];
+    let core_grammar_path = grammar_path.join(SPIRV_CORE_GRAMMAR_JSON_FILE_NAME);
+    println!("cargo:rerun-if-changed={}", core_grammar_path.display());
+    let mut input = Input::new(core_grammar_path);
+    for &extension_instruction_set in &extension_instruction_sets {
+        let path = grammar_path.join(extension_instruction_set.get_grammar_json_file_name());
+        println!("cargo:rerun-if-changed={}", path.display());
+        input = input.add_extension_instruction_set(extension_instruction_set, path);
+    }
+    input
+        .generate()?
+        .write_to_file(PathBuf::from(env::var("OUT_DIR").unwrap()).join("generated_parser.rs"))
+}
@@ -1,2 +1,607 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later
 // Copyright 2018 Jacob Lifshay
+
+//! SPIR-V parser generated from the Khronos machine-readable grammar
+//!
+//! every instruction is parsed into a variant of `Instruction` and can be written back out
+//! unchanged. instructions using the `GLSL.std.450` and `OpenCL.std` extended instruction sets
+//! are parsed into `GLSLStd450Instruction` and `OpenCLStdInstruction`.
+//!
+//! for code that works on words instead, `op` has a constant for each opcode, and each enum in
+//! the grammar has a module of constants for its values, such as `decoration` and `built_in`.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::result;
+
+include!(concat!(env!("OUT_DIR"), "/generated_parser.rs"));
+
+pub const HEADER_WORD_COUNT: usize = 5;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    MissingHeader,
+    InvalidMagicNumber(u32),
+    UnsupportedVersion {
+        major: u32,
+        minor: u32,
+    },
+    InvalidVersion(u32),
+    ZeroInstructionLength,
+    SourcePrematurelyEnded,
+    UnknownOpcode(u16),
+    UnknownExtensionInstruction {
+        set: &'static str,
+        instruction: u32,
+    },
+    InvalidEnumValue {
+        kind: &'static str,
+        value: u32,
+    },
+    InstructionPrematurelyEnded,
+    InstructionTooLong {
+        opcode: u16,
+    },
+    InvalidStringTermination,
+    InvalidUTF8String,
+    /// the type that decides the width of a literal isn't a scalar type declared earlier
+    UnknownLiteralWidth,
+    UnsupportedLiteralWidth(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::MissingHeader => write!(f, "SPIR-V header is missing"),
+            Error::InvalidMagicNumber(v) => write!(f, "invalid SPIR-V magic number: {:#010X}", v),
+            Error::UnsupportedVersion { major, minor } => write!(
+                f,
+                "unsupported SPIR-V version: {}.{} (newest supported is {}.{})",
+                major, minor, MAJOR_VERSION, MINOR_VERSION
+            ),
+            Error::InvalidVersion(v) => write!(f, "invalid SPIR-V version: {:#010X}", v),
+            Error::ZeroInstructionLength => write!(f, "SPIR-V instruction has a length of zero"),
+            Error::SourcePrematurelyEnded => write!(f, "SPIR-V source ended in an instruction"),
+            Error::UnknownOpcode(v) => write!(f, "unknown SPIR-V opcode: {}", v),
+            Error::UnknownExtensionInstruction { set, instruction } => write!(
+                f,
+                "unknown instruction in the {} extended instruction set: {}",
+                set, instruction
+            ),
+            Error::InvalidEnumValue { kind, value } => {
+                write!(f, "invalid value for {}: {:#X}", kind, value)
+            }
+            Error::InstructionPrematurelyEnded => write!(f, "SPIR-V instruction is too short"),
+            Error::InstructionTooLong { opcode } => {
+                write!(f, "SPIR-V instruction is too long: opcode {}", opcode)
+            }
+            Error::InvalidStringTermination => write!(f, "SPIR-V string is not terminated"),
+            Error::InvalidUTF8String => write!(f, "SPIR-V string is not valid UTF-8"),
+            Error::UnknownLiteralWidth => write!(f, "can't determine SPIR-V literal's width"),
+            Error::UnsupportedLiteralWidth(v) => {
+                write!(f, "unsupported SPIR-V literal width: {}", v)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+pub type Result<T> = result::Result<T, Error>;
+
+pub type LiteralInteger = u32;
+pub type LiteralExtInstInteger = u32;
+/// the bits of a 32-bit float, so instructions holding one can still be compared and hashed
+pub type LiteralFloat = u32;
+pub type LiteralString = String;
+
+/// a literal that's the width of its type: `OpConstant`'s value or `OpSwitch`'s case values
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum LiteralContextDependentNumber {
+    /// types with 32 or fewer bits
+    U32(u32),
+    /// the low word is first
+    U64(u64),
+}
+
+/// `OpSpecConstantOp`'s opcode and the operands of the operation
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct LiteralSpecConstantOpInteger {
+    pub opcode: u32,
+    pub operands: Vec<u32>,
+}
+
+/// what the parser knows from the instructions before the one being parsed
+#[derive(Default)]
+struct ParseState {
+    /// the result type of each id
+    id_types: HashMap<u32, u32>,
+    /// the width of each `OpTypeInt` and `OpTypeFloat`
+    scalar_widths: HashMap<u32, u32>,
+    extension_instruction_sets: HashMap<u32, ExtensionInstructionSet>,
+    /// the width of the context-dependent literals in the current instruction
+    context_width: Option<u32>,
+}
+
+impl ParseState {
+    fn set_context_type(&mut self, type_id: u32) {
+        self.context_width = self.scalar_widths.get(&type_id).cloned();
+    }
+    fn set_context_id(&mut self, id: u32) {
+        self.context_width = self
+            .id_types
+            .get(&id)
+            .and_then(|type_id| self.scalar_widths.get(type_id))
+            .cloned();
+    }
+    fn get_extension_instruction_set(&self, set: IdRef) -> Option<ExtensionInstructionSet> {
+        self.extension_instruction_sets.get(&set.0).cloned()
+    }
+    /// record what later instructions need to know about `instruction`
+    fn define(&mut self, instruction: &Instruction) {
+        self.context_width = None;
+        if let (Some(id_result_type), Some(id_result)) =
+            (instruction.id_result_type(), instruction.id_result())
+        {
+            self.id_types.insert(id_result.0, id_result_type.0);
+        }
+        match instruction {
+            Instruction::OpTypeInt {
+                id_result, width, ..
+            }
+            | Instruction::OpTypeFloat {
+                id_result, width, ..
+            } => {
+                self.scalar_widths.insert(id_result.0, *width);
+            }
+            Instruction::OpExtInstImport { id_result, name } => {
+                if let Some(set) = ExtensionInstructionSet::from_import_name(name) {
+                    self.extension_instruction_sets.insert(id_result.0, set);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+trait ParseOperand: Sized {
+    /// parse `Self` from the start of `words`, returning the remaining words
+    fn parse<'a>(words: &'a [u32], parse_state: &mut ParseState) -> Result<(Self, &'a [u32])>;
+}
+
+trait WriteOperand {
+    fn write(&self, output: &mut Vec<u32>);
+}
+
+impl ParseOperand for u32 {
+    fn parse<'a>(words: &'a [u32], _parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+        let (&word, words) = words
+            .split_first()
+            .ok_or(Error::InstructionPrematurelyEnded)?;
+        Ok((word, words))
+    }
+}
+
+impl WriteOperand for u32 {
+    fn write(&self, output: &mut Vec<u32>) {
+        output.push(*self);
+    }
+}
+
+impl ParseOperand for String {
+    fn parse<'a>(words: &'a [u32], _parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+        let mut bytes = Vec::new();
+        for (index, &word) in words.iter().enumerate() {
+            for byte_index in 0..4 {
+                let byte = (word >> (8 * byte_index)) as u8;
+                if byte == 0 {
+                    let string = String::from_utf8(bytes).map_err(|_| Error::InvalidUTF8String)?;
+                    return Ok((string, &words[index + 1..]));
+                }
+                bytes.push(byte);
+            }
+        }
+        Err(Error::InvalidStringTermination)
+    }
+}
+
+impl WriteOperand for String {
+    fn write(&self, output: &mut Vec<u32>) {
+        let bytes = self.as_bytes();
+        for chunk in bytes.chunks(4) {
+            let mut word = 0;
+            for (index, &byte) in chunk.iter().enumerate() {
+                word |= u32::from(byte) << (8 * index);
+            }
+            output.push(word);
+        }
+        if bytes.len() % 4 == 0 {
+            output.push(0);
+        }
+    }
+}
+
+impl<T: ParseOperand> ParseOperand for Option<T> {
+    fn parse<'a>(words: &'a [u32], parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+        if words.is_empty() {
+            Ok((None, words))
+        } else {
+            let (value, words) = T::parse(words, parse_state)?;
+            Ok((Some(value), words))
+        }
+    }
+}
+
+impl<T: WriteOperand> WriteOperand for Option<T> {
+    fn write(&self, output: &mut Vec<u32>) {
+        if let Some(value) = self {
+            value.write(output);
+        }
+    }
+}
+
+impl<T: ParseOperand> ParseOperand for Vec<T> {
+    fn parse<'a>(mut words: &'a [u32], parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+        let mut retval = Vec::new();
+        while !words.is_empty() {
+            let (value, new_words) = T::parse(words, parse_state)?;
+            retval.push(value);
+            words = new_words;
+        }
+        Ok((retval, words))
+    }
+}
+
+impl<T: WriteOperand> WriteOperand for Vec<T> {
+    fn write(&self, output: &mut Vec<u32>) {
+        for value in self {
+            value.write(output);
+        }
+    }
+}
+
+impl ParseOperand for LiteralContextDependentNumber {
+    fn parse<'a>(words: &'a [u32], parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+        match parse_state.context_width {
+            None => Err(Error::UnknownLiteralWidth),
+            Some(width) if width <= 32 => {
+                let (value, words) = u32::parse(words, parse_state)?;
+                Ok((LiteralContextDependentNumber::U32(value), words))
+            }
+            Some(64) => {
+                let (low, words) = u32::parse(words, parse_state)?;
+                let (high, words) = u32::parse(words, parse_state)?;
+                Ok((
+                    LiteralContextDependentNumber::U64(u64::from(high) << 32 | u64::from(low)),
+                    words,
+                ))
+            }
+            Some(width) => Err(Error::UnsupportedLiteralWidth(width)),
+        }
+    }
+}
+
+impl WriteOperand for LiteralContextDependentNumber {
+    fn write(&self, output: &mut Vec<u32>) {
+        match *self {
+            LiteralContextDependentNumber::U32(value) => output.push(value),
+            LiteralContextDependentNumber::U64(value) => {
+                output.push(value as u32);
+                output.push((value >> 32) as u32);
+            }
+        }
+    }
+}
+
+impl ParseOperand for LiteralSpecConstantOpInteger {
+    /// takes all the remaining words, since the operands depend on the opcode
+    fn parse<'a>(words: &'a [u32], parse_state: &mut ParseState) -> Result<(Self, &'a [u32])> {
+        let (opcode, words) = u32::parse(words, parse_state)?;
+        Ok((
+            LiteralSpecConstantOpInteger {
+                opcode,
+                operands: words.to_vec(),
+            },
+            &[],
+        ))
+    }
+}
+
+impl WriteOperand for LiteralSpecConstantOpInteger {
+    fn write(&self, output: &mut Vec<u32>) {
+        output.push(self.opcode);
+        output.extend_from_slice(&self.operands);
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Header {
+    /// `(major, minor)`
+    pub version: (u32, u32),
+    pub generator: u32,
+    pub bound: u32,
+    pub instruction_schema: u32,
+}
+
+impl Header {
+    pub fn parse(words: &[u32]) -> Result<Self> {
+        if words.len() < HEADER_WORD_COUNT {
+            return Err(Error::MissingHeader);
+        }
+        if words[0] != MAGIC_NUMBER {
+            return Err(Error::InvalidMagicNumber(words[0]));
+        }
+        if words[1] & 0xFF00_00FF != 0 {
+            return Err(Error::InvalidVersion(words[1]));
+        }
+        let version = ((words[1] >> 16) & 0xFF, (words[1] >> 8) & 0xFF);
+        if version > (MAJOR_VERSION, MINOR_VERSION) {
+            return Err(Error::UnsupportedVersion {
+                major: version.0,
+                minor: version.1,
+            });
+        }
+        Ok(Header {
+            version,
+            generator: words[2],
+            bound: words[3],
+            instruction_schema: words[4],
+        })
+    }
+    pub fn write(&self, output: &mut Vec<u32>) {
+        output.extend_from_slice(&[
+            MAGIC_NUMBER,
+            self.version.0 << 16 | self.version.1 << 8,
+            self.generator,
+            self.bound,
+            self.instruction_schema,
+        ]);
+    }
+}
+
+/// iterator over the instructions of a SPIR-V module.
+/// stops after the first error
+pub struct Parser<'a> {
+    words: &'a [u32],
+    header: Header,
+    parse_state: ParseState,
+}
+
+impl<'a> Parser<'a> {
+    pub fn start(words: &'a [u32]) -> Result<Self> {
+        Ok(Parser {
+            header: Header::parse(words)?,
+            words: &words[HEADER_WORD_COUNT..],
+            parse_state: ParseState::default(),
+        })
+    }
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Result<Instruction>;
+    fn next(&mut self) -> Option<Result<Instruction>> {
+        let length = (*self.words.first()? >> 16) as usize;
+        let result = if length == 0 {
+            Err(Error::ZeroInstructionLength)
+        } else if length > self.words.len() {
+            Err(Error::SourcePrematurelyEnded)
+        } else {
+            let (instruction_words, words) = self.words.split_at(length);
+            self.words = words;
+            Instruction::parse(instruction_words, &mut self.parse_state)
+        };
+        match &result {
+            Ok(instruction) => self.parse_state.define(instruction),
+            Err(_) => self.words = &[],
+        }
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    fn instruction(output: &mut Vec<u32>, opcode: u16, operands: &[u32]) {
+        output.push((operands.len() as u32 + 1) << 16 | u32::from(opcode));
+        output.extend_from_slice(operands);
+    }
+
+    fn string(s: &str) -> Vec<u32> {
+        let mut retval = Vec::new();
+        s.to_string().write(&mut retval);
+        retval
+    }
+
+    fn round_trip(words: &[u32]) -> Result<Vec<Instruction>> {
+        let mut parser = Parser::start(words)?;
+        let mut output = Vec::new();
+        parser.header().write(&mut output);
+        let mut instructions = Vec::new();
+        for instruction in &mut parser {
+            let instruction = instruction?;
+            instruction.write(&mut output);
+            instructions.push(instruction);
+        }
+        assert_eq!(output, words);
+        Ok(instructions)
+    }
+
+    /// a compute shader using 64-bit constants, `OpSwitch` and `GLSL.std.450`
+    fn test_module() -> Vec<u32> {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0300, 0, 14, 0];
+        instruction(&mut code, 17, &[1]); // OpCapability Shader
+        instruction(&mut code, 17, &[11]); // OpCapability Int64
+        instruction(&mut code, 17, &[64]); // OpCapability GroupNonUniformBallot
+        instruction(&mut code, 17, &[4442]); // OpCapability VariablePointers
+        instruction(&mut code, 10, &string("SPV_KHR_variable_pointers")); // OpExtension
+        let mut operands = vec![1];
+        operands.extend(string("GLSL.std.450"));
+        instruction(&mut code, 11, &operands); // OpExtInstImport
+        instruction(&mut code, 14, &[0, 1]); // OpMemoryModel Logical GLSL450
+        let mut operands = vec![5, 2];
+        operands.extend(string("main"));
+        instruction(&mut code, 15, &operands); // OpEntryPoint GLCompute
+        instruction(&mut code, 16, &[2, 17, 64, 1, 1]); // OpExecutionMode LocalSize
+        instruction(&mut code, 71, &[3, 11, 29]); // OpDecorate BuiltIn LocalInvocationIndex
+        instruction(&mut code, 19, &[4]); // OpTypeVoid
+        instruction(&mut code, 33, &[5, 4]); // OpTypeFunction
+        instruction(&mut code, 21, &[6, 64, 0]); // OpTypeInt
+        instruction(&mut code, 22, &[7, 32]); // OpTypeFloat
+        instruction(&mut code, 43, &[6, 8, 2, 1]); // OpConstant
+        instruction(&mut code, 43, &[7, 9, 1.5f32.to_bits()]); // OpConstant
+        instruction(&mut code, 54, &[4, 2, 0, 5]); // OpFunction
+        instruction(&mut code, 248, &[10]); // OpLabel
+        instruction(&mut code, 12, &[7, 11, 1, 1, 9]); // OpExtInst Round
+        instruction(&mut code, 251, &[8, 12, 2, 1, 13]); // OpSwitch
+        instruction(&mut code, 248, &[12]); // OpLabel
+        instruction(&mut code, 253, &[]); // OpReturn
+        instruction(&mut code, 248, &[13]); // OpLabel
+        instruction(&mut code, 253, &[]); // OpReturn
+        instruction(&mut code, 56, &[]); // OpFunctionEnd
+        code
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let instructions = round_trip(&test_module()).unwrap();
+        assert_eq!(
+            instructions[2],
+            Instruction::OpCapability {
+                capability: Capability::GroupNonUniformBallot
+            }
+        );
+        assert_eq!(
+            instructions[9],
+            Instruction::OpDecorate {
+                target: IdRef(3),
+                decoration: Decoration::BuiltIn {
+                    built_in: BuiltIn::LocalInvocationIndex
+                },
+            }
+        );
+        assert_eq!(
+            instructions[14],
+            Instruction::OpConstant {
+                id_result_type: IdResultType(6),
+                id_result: IdResult(8),
+                value: LiteralContextDependentNumber::U64(0x1_0000_0002),
+            }
+        );
+        assert_eq!(
+            instructions[18],
+            Instruction::OpExtInst {
+                id_result_type: IdResultType(7),
+                id_result: IdResult(11),
+                set: IdRef(1),
+                instruction: ExtensionInstruction::GLSLStd450(GLSLStd450Instruction::Round {
+                    x: IdRef(9)
+                }),
+            }
+        );
+        assert_eq!(
+            instructions[19],
+            Instruction::OpSwitch {
+                selector: IdRef(8),
+                default: IdRef(12),
+                target: vec![PairLiteralIntegerIdRef(
+                    LiteralContextDependentNumber::U64(0x1_0000_0002),
+                    IdRef(13)
+                )],
+            }
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut code = test_module();
+        code[0] = 0;
+        assert_eq!(
+            Parser::start(&code).err(),
+            Some(Error::InvalidMagicNumber(0))
+        );
+        let mut code = test_module();
+        code[1] = 0x0063_0000;
+        assert_eq!(
+            Parser::start(&code).err(),
+            Some(Error::UnsupportedVersion {
+                major: 0x63,
+                minor: 0
+            })
+        );
+        let mut code = test_module();
+        instruction(&mut code, 0xFFFF, &[]);
+        assert_eq!(round_trip(&code).err(), Some(Error::UnknownOpcode(0xFFFF)));
+        let mut code = test_module();
+        instruction(&mut code, 71, &[3, 0xFFFF]); // OpDecorate
+        assert_eq!(
+            round_trip(&code).err(),
+            Some(Error::InvalidEnumValue {
+                kind: "Decoration",
+                value: 0xFFFF
+            })
+        );
+        let mut code = test_module();
+        code.push(3 << 16 | 17); // truncated OpCapability
+        assert_eq!(round_trip(&code).err(), Some(Error::SourcePrematurelyEnded));
+    }
+
+    /// round-trips every `.spv` file in `test-corpus`, and in the directory named by
+    /// `SPIRV_PARSER_TEST_CORPUS`, such as the binaries from the SPIR-V test suites
+    #[test]
+    fn test_corpus() {
+        fn visit(path: &Path, count: &mut usize) {
+            if path.is_dir() {
+                for entry in fs::read_dir(path).unwrap() {
+                    visit(&entry.unwrap().path(), count);
+                }
+                return;
+            }
+            if path.extension().map_or(true, |v| v != "spv") {
+                return;
+            }
+            let bytes = fs::read(path).unwrap();
+            if bytes.len() % 4 != 0 {
+                return;
+            }
+            let words: Vec<u32> = bytes
+                .chunks(4)
+                .map(|v| {
+                    u32::from(v[0])
+                        | u32::from(v[1]) << 8
+                        | u32::from(v[2]) << 16
+                        | u32::from(v[3]) << 24
+                })
+                .collect();
+            if words.first() != Some(&MAGIC_NUMBER) {
+                return;
+            }
+            if let Err(error) = round_trip(&words) {
+                panic!("{}: {}", path.display(), error);
+            }
+            *count += 1;
+        }
+        let mut count = 0;
+        visit(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("test-corpus"),
+            &mut count,
+        );
+        assert_ne!(count, 0, "no SPIR-V binaries found in test-corpus");
+        if let Some(corpus) = env::var_os("SPIRV_PARSER_TEST_CORPUS") {
+            let in_tree_count = count;
+            visit(Path::new(&corpus), &mut count);
+            assert_ne!(
+                count, in_tree_count,
+                "no SPIR-V binaries found in {:?}",
+                corpus
+            );
+        }
+    }
+}
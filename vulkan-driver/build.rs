@@ -2,6 +2,7 @@
 // Copyright 2018 Jacob Lifshay
 extern crate bindgen;
 extern crate regex;
+extern crate vulkan_registry_generator;
 extern crate xmltree;
 use std::env;
 use std::fs;
@@ -13,6 +14,9 @@ use xmltree::Element;
 
 const VULKAN_HEADERS_INCLUDE_PATH: &'static str = "../external/Vulkan-Headers/include";
 
+/// the core versions the driver implements every command of
+const IMPLEMENTED_VULKAN_VERSIONS: &[&str] = &["VK_VERSION_1_0", "VK_VERSION_1_1"];
+
 fn detect_vulkan_calling_convention() -> io::Result<String> {
     let code = bindgen::builder()
         .header_contents(
@@ -50,9 +54,23 @@ VKAPI_ATTR void VKAPI_CALL detect_fn();
 fn main() -> io::Result<()> {
     let vulkan_wrapper_header_path = "vulkan-wrapper.h";
     let vulkan_vk_xml_path = "../external/Vulkan-Headers/registry/vk.xml";
+    let vulkan_supplement_path = "vk-supplement.xml";
     println!("cargo:rerun-if-changed={}", vulkan_wrapper_header_path);
     println!("cargo:rerun-if-changed={}", VULKAN_HEADERS_INCLUDE_PATH);
     println!("cargo:rerun-if-changed={}", vulkan_vk_xml_path);
+    println!("cargo:rerun-if-changed={}", vulkan_supplement_path);
+    let mut registry_input = vulkan_registry_generator::Input::new(vulkan_vk_xml_path)
+        .add_supplement(vulkan_supplement_path);
+    for &version in IMPLEMENTED_VULKAN_VERSIONS {
+        registry_input = registry_input.add_core_version(version);
+    }
+    let registry_output = registry_input.generate()?;
+    registry_output.write_core_commands_to_file(
+        PathBuf::from(env::var("OUT_DIR").unwrap()).join("vulkan-core-commands.rs"),
+    )?;
+    registry_output.write_bindings_to_file(
+        PathBuf::from(env::var("OUT_DIR").unwrap()).join("vulkan-supplement.rs"),
+    )?;
     let parsed_xml = Element::parse(fs::File::open(&PathBuf::from(vulkan_vk_xml_path))?)
         .map_err(|v| io::Error::new(io::ErrorKind::Other, format!("{}", v)))?;
     let types = parsed_xml.get_child("types").unwrap();
@@ -85,6 +103,10 @@ fn main() -> io::Result<()> {
         .layout_tests(false)
         .whitelist_var("VK_.*")
         .whitelist_var("ICD_LOADER_MAGIC");
+    // the XCB types are only declared when the XCB surface extension is compiled in
+    if env::var_os("CARGO_FEATURE_WSI_XCB").is_some() {
+        builder = builder.clang_arg("-DKAZAN_WSI_XCB");
+    }
     for t in types
         .children
         .iter()
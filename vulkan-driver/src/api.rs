@@ -15,7 +15,7 @@ pub use handle::{
     VkSamplerYcbcrConversion, VkSemaphore, VkShaderModule, VkSurfaceKHR, VkSwapchainKHR,
     VkValidationCacheEXT,
 };
-#[cfg(unix)]
+#[cfg(all(unix, feature = "wsi-xcb"))]
 use xcb::ffi::{xcb_connection_t, xcb_visualid_t, xcb_window_t};
 include!(concat!(env!("OUT_DIR"), "/vulkan-types.rs"));
 
@@ -51,3 +51,7 @@ pub struct VkAllocationCallbacks {
     pub pfnInternalAllocation: PFN_vkInternalAllocationNotification,
     pub pfnInternalFree: PFN_vkInternalFreeNotification,
 }
+
+// the features and extensions in vk-supplement.xml that are newer than the Vulkan headers, and
+// kazan's own extensions
+include!(concat!(env!("OUT_DIR"), "/vulkan-supplement.rs"));
@@ -9,16 +9,34 @@
 
 use api;
 use buffer::{Buffer, BufferMemory};
+use command_buffer::{self, CommandPool};
+use compile_thread_pool::{CompilePriority, CompileThreadPool};
 use constants::*;
+use debug_utils::{DebugUtilsMessenger, DebugUtilsMessengers};
+use descriptor_set::{DescriptorPool, DescriptorSetLayout};
 use device_memory::{
-    DeviceMemory, DeviceMemoryAllocation, DeviceMemoryHeap, DeviceMemoryHeaps, DeviceMemoryLayout,
-    DeviceMemoryType, DeviceMemoryTypes,
+    get_page_size, DeviceMemory, DeviceMemoryAllocation, DeviceMemoryHeap, DeviceMemoryHeaps,
+    DeviceMemoryLayout, DeviceMemoryType, DeviceMemoryTypes,
 };
+use driver_configuration::DriverConfiguration;
 use enum_map::EnumMap;
-use handle::{Handle, MutHandle, OwnedHandle, SharedHandle};
-use image::{Image, ImageMemory, ImageMultisampleCount, ImageProperties, SupportedTilings};
+use handle::{Fence, Handle, MutHandle, OwnedHandle, Semaphore, SharedHandle};
+use image::{
+    Image, ImageMemory, ImageMultisampleCount, ImageProperties, ImageRobustness, ImageView,
+    SupportedTilings, Tiling,
+};
+use mipmap;
+use pipeline::{self, ComputePipeline, GraphicsPipeline, Pipeline, PipelineError, PipelineLayout};
+use pipeline_report::dump_pipeline_report;
+use query::QueryPool;
+use render_pass::{Framebuffer, RenderPass};
 use sampler;
 use sampler::Sampler;
+use shader_compiler::abi::MAX_PUSH_CONSTANTS_SIZE;
+use shader_compiler::descriptors::MAX_DESCRIPTOR_SETS;
+use shader_compiler::simd_width::SimdWidthLimit;
+use shader_compiler::spirv;
+use shader_compiler::watchdog::Watchdog;
 use shader_module::ShaderModule;
 use std::ffi::CStr;
 use std::iter;
@@ -28,34 +46,39 @@ use std::ops::*;
 use std::os::raw::{c_char, c_void};
 use std::ptr::null;
 use std::ptr::null_mut;
-#[cfg(unix)]
 use std::ptr::NonNull;
 use std::slice;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use submit_graph::SubmitGraph;
+#[cfg(feature = "wsi")]
 use swapchain::SurfacePlatform;
 use sys_info;
+use trace::Tracer;
 use uuid;
-#[cfg(unix)]
+use validation::{self, Validation};
+use viewport::{DepthBias, Viewport};
+use watchdog::QueueWatchdog;
+#[cfg(all(unix, feature = "wsi-xcb"))]
 use xcb;
 
 /// structure types the driver should know about
 fn is_supported_structure_type(v: api::VkStructureType) -> bool {
-    #[cfg(unix)]
+    if Extensions::default()
+        .iter()
+        .any(|(extension, _)| extension.get_structure_types().contains(&v))
     {
-        #[cfg_attr(feature = "cargo-clippy", allow(clippy::single_match))]
-        match v {
-            api::VK_STRUCTURE_TYPE_XCB_SURFACE_CREATE_INFO_KHR => return true,
-            _ => {}
-        }
+        return true;
     }
     match v {
-        api::VK_STRUCTURE_TYPE_ACQUIRE_NEXT_IMAGE_INFO_KHR
-        | api::VK_STRUCTURE_TYPE_APPLICATION_INFO
+        api::VK_STRUCTURE_TYPE_APPLICATION_INFO
         | api::VK_STRUCTURE_TYPE_BIND_BUFFER_MEMORY_DEVICE_GROUP_INFO
         | api::VK_STRUCTURE_TYPE_BIND_BUFFER_MEMORY_INFO
         | api::VK_STRUCTURE_TYPE_BIND_IMAGE_MEMORY_DEVICE_GROUP_INFO
         | api::VK_STRUCTURE_TYPE_BIND_IMAGE_MEMORY_INFO
-        | api::VK_STRUCTURE_TYPE_BIND_IMAGE_MEMORY_SWAPCHAIN_INFO_KHR
         | api::VK_STRUCTURE_TYPE_BIND_IMAGE_PLANE_MEMORY_INFO
         | api::VK_STRUCTURE_TYPE_BIND_SPARSE_INFO
         | api::VK_STRUCTURE_TYPE_BUFFER_CREATE_INFO
@@ -77,11 +100,8 @@ fn is_supported_structure_type(v: api::VkStructureType) -> bool {
         | api::VK_STRUCTURE_TYPE_DEVICE_GROUP_BIND_SPARSE_INFO
         | api::VK_STRUCTURE_TYPE_DEVICE_GROUP_COMMAND_BUFFER_BEGIN_INFO
         | api::VK_STRUCTURE_TYPE_DEVICE_GROUP_DEVICE_CREATE_INFO
-        | api::VK_STRUCTURE_TYPE_DEVICE_GROUP_PRESENT_CAPABILITIES_KHR
-        | api::VK_STRUCTURE_TYPE_DEVICE_GROUP_PRESENT_INFO_KHR
         | api::VK_STRUCTURE_TYPE_DEVICE_GROUP_RENDER_PASS_BEGIN_INFO
         | api::VK_STRUCTURE_TYPE_DEVICE_GROUP_SUBMIT_INFO
-        | api::VK_STRUCTURE_TYPE_DEVICE_GROUP_SWAPCHAIN_CREATE_INFO_KHR
         | api::VK_STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO
         | api::VK_STRUCTURE_TYPE_DEVICE_QUEUE_INFO_2
         | api::VK_STRUCTURE_TYPE_EVENT_CREATE_INFO
@@ -104,7 +124,6 @@ fn is_supported_structure_type(v: api::VkStructureType) -> bool {
         | api::VK_STRUCTURE_TYPE_IMAGE_MEMORY_REQUIREMENTS_INFO_2
         | api::VK_STRUCTURE_TYPE_IMAGE_PLANE_MEMORY_REQUIREMENTS_INFO
         | api::VK_STRUCTURE_TYPE_IMAGE_SPARSE_MEMORY_REQUIREMENTS_INFO_2
-        | api::VK_STRUCTURE_TYPE_IMAGE_SWAPCHAIN_CREATE_INFO_KHR
         | api::VK_STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO
         | api::VK_STRUCTURE_TYPE_IMAGE_VIEW_USAGE_CREATE_INFO
         | api::VK_STRUCTURE_TYPE_INSTANCE_CREATE_INFO
@@ -151,7 +170,6 @@ fn is_supported_structure_type(v: api::VkStructureType) -> bool {
         | api::VK_STRUCTURE_TYPE_PIPELINE_TESSELLATION_STATE_CREATE_INFO
         | api::VK_STRUCTURE_TYPE_PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO
         | api::VK_STRUCTURE_TYPE_PIPELINE_VIEWPORT_STATE_CREATE_INFO
-        | api::VK_STRUCTURE_TYPE_PRESENT_INFO_KHR
         | api::VK_STRUCTURE_TYPE_PROTECTED_SUBMIT_INFO
         | api::VK_STRUCTURE_TYPE_QUERY_POOL_CREATE_INFO
         | api::VK_STRUCTURE_TYPE_QUEUE_FAMILY_PROPERTIES_2
@@ -168,8 +186,8 @@ fn is_supported_structure_type(v: api::VkStructureType) -> bool {
         | api::VK_STRUCTURE_TYPE_SPARSE_IMAGE_FORMAT_PROPERTIES_2
         | api::VK_STRUCTURE_TYPE_SPARSE_IMAGE_MEMORY_REQUIREMENTS_2
         | api::VK_STRUCTURE_TYPE_SUBMIT_INFO
+        | api::VK_STRUCTURE_TYPE_SUBPASS_DESCRIPTION_DEPTH_STENCIL_RESOLVE_KHR
         | api::VK_STRUCTURE_TYPE_SURFACE_CAPABILITIES_2_KHR
-        | api::VK_STRUCTURE_TYPE_SWAPCHAIN_CREATE_INFO_KHR
         | api::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET => true,
         _ => false,
     }
@@ -183,7 +201,7 @@ fn verify_structure_type_is_supported(v: api::VkStructureType) {
     );
 }
 
-unsafe fn parse_next_chain_const(
+pub unsafe fn parse_next_chain_const(
     root: *const api::VkBaseInStructure,
     expected_root_struct_type: api::VkStructureType,
     expected_child_structs: &[(api::VkStructureType, *mut *const api::VkBaseInStructure)],
@@ -212,8 +230,11 @@ unsafe fn parse_next_chain_const(
                 break;
             }
         }
+        let is_extension_feature_struct = is_extension_feature_structure_type(search_for_type)
+            && (expected_root_struct_type == api::VK_STRUCTURE_TYPE_DEVICE_CREATE_INFO
+                || expected_root_struct_type == api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_FEATURES_2);
         assert!(
-            found || !is_supported_structure_type(search_for_type),
+            found || is_extension_feature_struct || !is_supported_structure_type(search_for_type),
             "unexpected struct type in pNext chain: {:?}",
             search_for_type
         );
@@ -276,39 +297,6 @@ fn copy_str_to_char_array(dest: &mut [c_char], src: &str) {
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Enum)]
-#[repr(u32)]
-#[allow(non_camel_case_types)]
-pub enum Extension {
-    VK_KHR_surface,
-    VK_KHR_bind_memory2,
-    VK_KHR_device_group_creation,
-    VK_KHR_device_group,
-    VK_KHR_descriptor_update_template,
-    VK_KHR_maintenance1,
-    VK_KHR_get_memory_requirements2,
-    VK_KHR_get_physical_device_properties2,
-    VK_KHR_sampler_ycbcr_conversion,
-    VK_KHR_maintenance2,
-    VK_KHR_maintenance3,
-    VK_KHR_external_memory_capabilities,
-    VK_KHR_external_fence_capabilities,
-    VK_KHR_external_semaphore_capabilities,
-    VK_KHR_16bit_storage,
-    VK_KHR_storage_buffer_storage_class,
-    VK_KHR_dedicated_allocation,
-    VK_KHR_external_fence,
-    VK_KHR_external_memory,
-    VK_KHR_external_semaphore,
-    VK_KHR_multiview,
-    VK_KHR_relaxed_block_layout,
-    VK_KHR_shader_draw_parameters,
-    VK_KHR_variable_pointers,
-    VK_KHR_swapchain,
-    #[cfg(unix)]
-    VK_KHR_xcb_surface,
-}
-
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum ExtensionScope {
     Device,
@@ -324,55 +312,831 @@ macro_rules! extensions {
     };
 }
 
-impl Extension {
-    pub fn get_required_extensions(self) -> Extensions {
-        match self {
-            Extension::VK_KHR_surface
-            | Extension::VK_KHR_bind_memory2
-            | Extension::VK_KHR_device_group_creation
-            | Extension::VK_KHR_descriptor_update_template
-            | Extension::VK_KHR_maintenance1
-            | Extension::VK_KHR_get_memory_requirements2
-            | Extension::VK_KHR_get_physical_device_properties2
-            | Extension::VK_KHR_maintenance2
-            | Extension::VK_KHR_storage_buffer_storage_class
-            | Extension::VK_KHR_relaxed_block_layout
-            | Extension::VK_KHR_shader_draw_parameters => extensions![],
-            Extension::VK_KHR_device_group => extensions![Extension::VK_KHR_device_group_creation],
-            Extension::VK_KHR_sampler_ycbcr_conversion => extensions![
-                Extension::VK_KHR_maintenance1,
-                Extension::VK_KHR_bind_memory2,
-                Extension::VK_KHR_get_memory_requirements2,
-                Extension::VK_KHR_get_physical_device_properties2
-            ],
-            Extension::VK_KHR_maintenance3
-            | Extension::VK_KHR_external_memory_capabilities
-            | Extension::VK_KHR_external_fence_capabilities
-            | Extension::VK_KHR_external_semaphore_capabilities
-            | Extension::VK_KHR_multiview => {
-                extensions![Extension::VK_KHR_get_physical_device_properties2]
+macro_rules! impl_import_export_feature_set {
+    ($type:ident, $member:ident) => {
+        impl ImportExportFeatureSet<api::$type> for Features {
+            fn import_feature_set(&mut self, features: &api::$type) {
+                self.$member = api::$type {
+                    sType: self.$member.sType,
+                    pNext: self.$member.pNext,
+                    ..*features
+                };
+            }
+            fn export_feature_set(&self, features: &mut api::$type) {
+                *features = api::$type {
+                    sType: features.sType,
+                    pNext: features.pNext,
+                    ..self.$member
+                };
+            }
+        }
+    };
+}
+
+/// a command added by an extension
+#[derive(Copy, Clone, Debug)]
+pub struct ExtensionCommand {
+    /// the scope of the handle the command is dispatched on
+    pub scope: ExtensionScope,
+    pub function: api::PFN_vkVoidFunction,
+}
+
+/// declares the `Extension` enum and everything the driver needs to know about each extension:
+///
+/// * `requires`: the extensions that have to be enabled along with it
+/// * `promoted_to`: the core version the extension was promoted to, as `(major, minor)`
+/// * `promoted_commands`: core commands also exposed with a `KHR` suffix while it's enabled
+/// * `commands`: commands only exposed while it's enabled
+/// * `structure_types`: structure types it adds that aren't in core Vulkan 1.1
+/// * `feature_structs`: feature structs it adds, each stored in a member of `Features`, which
+///   are imported from `VkDeviceCreateInfo` and exported to `VkPhysicalDeviceFeatures2`
+macro_rules! declare_extensions {
+    {
+        $(
+            $(#[$attributes:meta])*
+            $name:ident {
+                scope: $scope:ident,
+                spec_version: $spec_version:ident,
+                requires: [$($required:ident),*],
+                promoted_to: $promoted_to:expr,
+                promoted_commands: [$($promoted_command:ident),*],
+                commands: [$(($command:ident, $command_pfn:ident, $command_scope:ident)),*],
+                structure_types: [$($structure_type:ident),*],
+                feature_structs: [
+                    $(($feature_struct:ident, $feature_struct_type:ident, $feature_member:ident)),*
+                ],
+            },
+        )*
+    } => {
+        #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Enum)]
+        #[repr(u32)]
+        #[allow(non_camel_case_types)]
+        pub enum Extension {
+            $($(#[$attributes])* $name,)*
+        }
+
+        impl Extension {
+            pub fn get_required_extensions(self) -> Extensions {
+                match self {
+                    $($(#[$attributes])* Extension::$name => {
+                        extensions![$(Extension::$required),*]
+                    })*
+                }
             }
-            Extension::VK_KHR_16bit_storage | Extension::VK_KHR_variable_pointers => extensions![
-                Extension::VK_KHR_get_physical_device_properties2,
-                Extension::VK_KHR_storage_buffer_storage_class
-            ],
-            Extension::VK_KHR_dedicated_allocation => {
-                extensions![Extension::VK_KHR_get_memory_requirements2]
+            pub fn get_name(self) -> &'static str {
+                match self {
+                    $($(#[$attributes])* Extension::$name => stringify!($name),)*
+                }
+            }
+            pub fn get_spec_version(self) -> u32 {
+                match self {
+                    $($(#[$attributes])* Extension::$name => api::$spec_version,)*
+                }
+            }
+            pub fn get_scope(self) -> ExtensionScope {
+                match self {
+                    $($(#[$attributes])* Extension::$name => ExtensionScope::$scope,)*
+                }
+            }
+            #[allow(dead_code)]
+            pub fn get_promoted_version(self) -> Option<u32> {
+                let promoted_to: Option<(u32, u32)> = match self {
+                    $($(#[$attributes])* Extension::$name => $promoted_to,)*
+                };
+                promoted_to.map(|(major, minor)| make_api_version(major, minor, 0))
+            }
+            pub fn get_promoted_commands(self) -> &'static [&'static str] {
+                match self {
+                    $($(#[$attributes])* Extension::$name => {
+                        &[$(stringify!($promoted_command)),*]
+                    })*
+                }
+            }
+            pub fn get_command(self, name: &str) -> Option<ExtensionCommand> {
+                match self {
+                    $($(#[$attributes])* Extension::$name => {
+                        $(if name == stringify!($command) {
+                            let function: api::$command_pfn = Some($command);
+                            return Some(ExtensionCommand {
+                                scope: ExtensionScope::$command_scope,
+                                function: unsafe {
+                                    mem::transmute::<api::$command_pfn, api::PFN_vkVoidFunction>(
+                                        function,
+                                    )
+                                },
+                            });
+                        })*
+                    })*
+                }
+                None
+            }
+            pub fn get_structure_types(self) -> &'static [api::VkStructureType] {
+                match self {
+                    $($(#[$attributes])* Extension::$name => {
+                        &[$(api::$structure_type,)* $(api::$feature_struct_type,)*]
+                    })*
+                }
             }
-            Extension::VK_KHR_external_fence => {
-                extensions![Extension::VK_KHR_external_fence_capabilities]
+        }
+
+        $($(impl_import_export_feature_set!($feature_struct, $feature_member);)*)*
+
+        fn is_extension_feature_structure_type(v: api::VkStructureType) -> bool {
+            match v {
+                $($(api::$feature_struct_type => true,)*)*
+                _ => false,
             }
-            Extension::VK_KHR_external_memory => {
-                extensions![Extension::VK_KHR_external_memory_capabilities]
+        }
+
+        /// imports the extension feature structs in the `pNext` chain of `root`
+        unsafe fn import_extension_feature_structs(
+            features: &mut Features,
+            root: *const api::VkBaseInStructure,
+        ) {
+            let mut child = (*root).pNext as *const api::VkBaseInStructure;
+            while !child.is_null() {
+                match (*child).sType {
+                    $($(api::$feature_struct_type => {
+                        features.import_feature_set(&*(child as *const api::$feature_struct))
+                    })*)*
+                    _ => {}
+                }
+                child = (*child).pNext as *const _;
             }
-            Extension::VK_KHR_external_semaphore => {
-                extensions![Extension::VK_KHR_external_semaphore_capabilities]
+        }
+
+        /// exports to the extension feature structs in the `pNext` chain of `root`
+        unsafe fn export_extension_feature_structs(
+            features: &Features,
+            root: *mut api::VkBaseOutStructure,
+        ) {
+            let mut child = (*root).pNext;
+            while !child.is_null() {
+                match (*child).sType {
+                    $($(api::$feature_struct_type => {
+                        features.export_feature_set(&mut *(child as *mut api::$feature_struct))
+                    })*)*
+                    _ => {}
+                }
+                child = (*child).pNext;
             }
-            Extension::VK_KHR_swapchain => extensions![Extension::VK_KHR_surface],
-            #[cfg(unix)]
-            Extension::VK_KHR_xcb_surface => extensions![Extension::VK_KHR_surface],
         }
-    }
+    };
+}
+
+declare_extensions! {
+    #[cfg(feature = "wsi")]
+    VK_KHR_surface {
+        scope: Instance,
+        spec_version: VK_KHR_SURFACE_SPEC_VERSION,
+        requires: [],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [
+            (vkDestroySurfaceKHR, PFN_vkDestroySurfaceKHR, Device),
+            (
+                vkGetPhysicalDeviceSurfaceSupportKHR,
+                PFN_vkGetPhysicalDeviceSurfaceSupportKHR,
+                Device
+            ),
+            (
+                vkGetPhysicalDeviceSurfaceCapabilitiesKHR,
+                PFN_vkGetPhysicalDeviceSurfaceCapabilitiesKHR,
+                Device
+            ),
+            (
+                vkGetPhysicalDeviceSurfaceFormatsKHR,
+                PFN_vkGetPhysicalDeviceSurfaceFormatsKHR,
+                Device
+            ),
+            (
+                vkGetPhysicalDeviceSurfacePresentModesKHR,
+                PFN_vkGetPhysicalDeviceSurfacePresentModesKHR,
+                Device
+            )
+        ],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_bind_memory2 {
+        scope: Device,
+        spec_version: VK_KHR_BIND_MEMORY_2_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [vkBindBufferMemory2, vkBindImageMemory2],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_device_group_creation {
+        scope: Instance,
+        spec_version: VK_KHR_DEVICE_GROUP_CREATION_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [vkEnumeratePhysicalDeviceGroups],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_device_group {
+        scope: Device,
+        spec_version: VK_KHR_DEVICE_GROUP_SPEC_VERSION,
+        requires: [VK_KHR_device_group_creation],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [
+            vkCmdDispatchBase,
+            vkCmdSetDeviceMask,
+            vkGetDeviceGroupPeerMemoryFeatures
+        ],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_descriptor_update_template {
+        scope: Device,
+        spec_version: VK_KHR_DESCRIPTOR_UPDATE_TEMPLATE_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [
+            vkCreateDescriptorUpdateTemplate,
+            vkDestroyDescriptorUpdateTemplate,
+            vkUpdateDescriptorSetWithTemplate
+        ],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_maintenance1 {
+        scope: Device,
+        spec_version: VK_KHR_MAINTENANCE1_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [vkTrimCommandPool],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_get_memory_requirements2 {
+        scope: Device,
+        spec_version: VK_KHR_GET_MEMORY_REQUIREMENTS_2_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [
+            vkGetBufferMemoryRequirements2,
+            vkGetImageMemoryRequirements2,
+            vkGetImageSparseMemoryRequirements2
+        ],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_get_physical_device_properties2 {
+        scope: Instance,
+        spec_version: VK_KHR_GET_PHYSICAL_DEVICE_PROPERTIES_2_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [
+            vkGetPhysicalDeviceFeatures2,
+            vkGetPhysicalDeviceFormatProperties2,
+            vkGetPhysicalDeviceImageFormatProperties2,
+            vkGetPhysicalDeviceMemoryProperties2,
+            vkGetPhysicalDeviceProperties2,
+            vkGetPhysicalDeviceQueueFamilyProperties2,
+            vkGetPhysicalDeviceSparseImageFormatProperties2
+        ],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_sampler_ycbcr_conversion {
+        scope: Device,
+        spec_version: VK_KHR_SAMPLER_YCBCR_CONVERSION_SPEC_VERSION,
+        requires: [
+            VK_KHR_maintenance1,
+            VK_KHR_bind_memory2,
+            VK_KHR_get_memory_requirements2,
+            VK_KHR_get_physical_device_properties2
+        ],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [vkCreateSamplerYcbcrConversion, vkDestroySamplerYcbcrConversion],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_maintenance2 {
+        scope: Device,
+        spec_version: VK_KHR_MAINTENANCE2_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_maintenance3 {
+        scope: Device,
+        spec_version: VK_KHR_MAINTENANCE3_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [vkGetDescriptorSetLayoutSupport],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_external_memory_capabilities {
+        scope: Instance,
+        spec_version: VK_KHR_EXTERNAL_MEMORY_CAPABILITIES_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [vkGetPhysicalDeviceExternalBufferProperties],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_external_fence_capabilities {
+        scope: Instance,
+        spec_version: VK_KHR_EXTERNAL_FENCE_CAPABILITIES_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [vkGetPhysicalDeviceExternalFenceProperties],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_external_semaphore_capabilities {
+        scope: Instance,
+        spec_version: VK_KHR_EXTERNAL_SEMAPHORE_CAPABILITIES_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [vkGetPhysicalDeviceExternalSemaphoreProperties],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_16bit_storage {
+        scope: Device,
+        spec_version: VK_KHR_16BIT_STORAGE_SPEC_VERSION,
+        requires: [
+            VK_KHR_get_physical_device_properties2,
+            VK_KHR_storage_buffer_storage_class
+        ],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_storage_buffer_storage_class {
+        scope: Device,
+        spec_version: VK_KHR_STORAGE_BUFFER_STORAGE_CLASS_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_dedicated_allocation {
+        scope: Device,
+        spec_version: VK_KHR_DEDICATED_ALLOCATION_SPEC_VERSION,
+        requires: [VK_KHR_get_memory_requirements2],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_external_fence {
+        scope: Device,
+        spec_version: VK_KHR_EXTERNAL_FENCE_SPEC_VERSION,
+        requires: [VK_KHR_external_fence_capabilities],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_external_memory {
+        scope: Device,
+        spec_version: VK_KHR_EXTERNAL_MEMORY_SPEC_VERSION,
+        requires: [VK_KHR_external_memory_capabilities],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_external_semaphore {
+        scope: Device,
+        spec_version: VK_KHR_EXTERNAL_SEMAPHORE_SPEC_VERSION,
+        requires: [VK_KHR_external_semaphore_capabilities],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_multiview {
+        scope: Device,
+        spec_version: VK_KHR_MULTIVIEW_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_relaxed_block_layout {
+        scope: Device,
+        spec_version: VK_KHR_RELAXED_BLOCK_LAYOUT_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_shader_draw_parameters {
+        scope: Device,
+        spec_version: VK_KHR_SHADER_DRAW_PARAMETERS_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_shader_non_semantic_info {
+        scope: Device,
+        spec_version: VK_KHR_SHADER_NON_SEMANTIC_INFO_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 3)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KHR_variable_pointers {
+        scope: Device,
+        spec_version: VK_KHR_VARIABLE_POINTERS_SPEC_VERSION,
+        requires: [
+            VK_KHR_get_physical_device_properties2,
+            VK_KHR_storage_buffer_storage_class
+        ],
+        promoted_to: Some((1, 1)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    #[cfg(feature = "wsi")]
+    VK_KHR_swapchain {
+        scope: Device,
+        spec_version: VK_KHR_SWAPCHAIN_SPEC_VERSION,
+        requires: [VK_KHR_surface],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [
+            (vkCreateSwapchainKHR, PFN_vkCreateSwapchainKHR, Device),
+            (vkDestroySwapchainKHR, PFN_vkDestroySwapchainKHR, Device),
+            (vkGetSwapchainImagesKHR, PFN_vkGetSwapchainImagesKHR, Device),
+            (vkAcquireNextImageKHR, PFN_vkAcquireNextImageKHR, Device),
+            (vkQueuePresentKHR, PFN_vkQueuePresentKHR, Device),
+            (
+                vkGetDeviceGroupPresentCapabilitiesKHR,
+                PFN_vkGetDeviceGroupPresentCapabilitiesKHR,
+                Device
+            ),
+            (
+                vkGetDeviceGroupSurfacePresentModesKHR,
+                PFN_vkGetDeviceGroupSurfacePresentModesKHR,
+                Device
+            ),
+            (
+                vkGetPhysicalDevicePresentRectanglesKHR,
+                PFN_vkGetPhysicalDevicePresentRectanglesKHR,
+                Device
+            ),
+            (vkAcquireNextImage2KHR, PFN_vkAcquireNextImage2KHR, Device)
+        ],
+        structure_types: [
+            VK_STRUCTURE_TYPE_ACQUIRE_NEXT_IMAGE_INFO_KHR,
+            VK_STRUCTURE_TYPE_BIND_IMAGE_MEMORY_SWAPCHAIN_INFO_KHR,
+            VK_STRUCTURE_TYPE_DEVICE_GROUP_PRESENT_CAPABILITIES_KHR,
+            VK_STRUCTURE_TYPE_DEVICE_GROUP_PRESENT_INFO_KHR,
+            VK_STRUCTURE_TYPE_DEVICE_GROUP_SWAPCHAIN_CREATE_INFO_KHR,
+            VK_STRUCTURE_TYPE_IMAGE_SWAPCHAIN_CREATE_INFO_KHR,
+            VK_STRUCTURE_TYPE_PRESENT_INFO_KHR,
+            VK_STRUCTURE_TYPE_SWAPCHAIN_CREATE_INFO_KHR
+        ],
+        feature_structs: [],
+    },
+    VK_KHR_vulkan_memory_model {
+        scope: Device,
+        spec_version: VK_KHR_VULKAN_MEMORY_MODEL_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: Some((1, 2)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [(
+            VkPhysicalDeviceVulkanMemoryModelFeaturesKHR,
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_VULKAN_MEMORY_MODEL_FEATURES_KHR,
+            vulkan_memory_model_features
+        )],
+    },
+    VK_KHR_create_renderpass2 {
+        scope: Device,
+        spec_version: VK_KHR_CREATE_RENDERPASS_2_SPEC_VERSION,
+        requires: [VK_KHR_multiview, VK_KHR_maintenance2],
+        promoted_to: Some((1, 2)),
+        promoted_commands: [],
+        commands: [
+            (vkCreateRenderPass2KHR, PFN_vkCreateRenderPass2KHR, Device),
+            (vkCmdBeginRenderPass2KHR, PFN_vkCmdBeginRenderPass2KHR, Device),
+            (vkCmdNextSubpass2KHR, PFN_vkCmdNextSubpass2KHR, Device),
+            (vkCmdEndRenderPass2KHR, PFN_vkCmdEndRenderPass2KHR, Device)
+        ],
+        structure_types: [
+            VK_STRUCTURE_TYPE_ATTACHMENT_DESCRIPTION_2_KHR,
+            VK_STRUCTURE_TYPE_ATTACHMENT_REFERENCE_2_KHR,
+            VK_STRUCTURE_TYPE_SUBPASS_DESCRIPTION_2_KHR,
+            VK_STRUCTURE_TYPE_SUBPASS_DEPENDENCY_2_KHR,
+            VK_STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO_2_KHR,
+            VK_STRUCTURE_TYPE_SUBPASS_BEGIN_INFO_KHR,
+            VK_STRUCTURE_TYPE_SUBPASS_END_INFO_KHR
+        ],
+        feature_structs: [],
+    },
+    VK_KHR_map_memory2 {
+        scope: Device,
+        spec_version: VK_KHR_MAP_MEMORY_2_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 4)),
+        promoted_commands: [],
+        commands: [
+            (vkMapMemory2KHR, PFN_vkMapMemory2KHR, Device),
+            (vkUnmapMemory2KHR, PFN_vkUnmapMemory2KHR, Device)
+        ],
+        structure_types: [
+            VK_STRUCTURE_TYPE_MEMORY_MAP_INFO_KHR,
+            VK_STRUCTURE_TYPE_MEMORY_UNMAP_INFO_KHR
+        ],
+        feature_structs: [],
+    },
+    VK_KHR_pipeline_executable_properties {
+        scope: Device,
+        spec_version: VK_KHR_PIPELINE_EXECUTABLE_PROPERTIES_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [
+            (
+                vkGetPipelineExecutablePropertiesKHR,
+                PFN_vkGetPipelineExecutablePropertiesKHR,
+                Device
+            ),
+            (
+                vkGetPipelineExecutableStatisticsKHR,
+                PFN_vkGetPipelineExecutableStatisticsKHR,
+                Device
+            ),
+            (
+                vkGetPipelineExecutableInternalRepresentationsKHR,
+                PFN_vkGetPipelineExecutableInternalRepresentationsKHR,
+                Device
+            )
+        ],
+        structure_types: [
+            VK_STRUCTURE_TYPE_PIPELINE_INFO_KHR,
+            VK_STRUCTURE_TYPE_PIPELINE_EXECUTABLE_PROPERTIES_KHR,
+            VK_STRUCTURE_TYPE_PIPELINE_EXECUTABLE_INFO_KHR,
+            VK_STRUCTURE_TYPE_PIPELINE_EXECUTABLE_STATISTIC_KHR,
+            VK_STRUCTURE_TYPE_PIPELINE_EXECUTABLE_INTERNAL_REPRESENTATION_KHR
+        ],
+        feature_structs: [(
+            VkPhysicalDevicePipelineExecutablePropertiesFeaturesKHR,
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PIPELINE_EXECUTABLE_PROPERTIES_FEATURES_KHR,
+            pipeline_executable_properties_features
+        )],
+    },
+    #[cfg(all(unix, feature = "wsi-xcb"))]
+    VK_KHR_xcb_surface {
+        scope: Instance,
+        spec_version: VK_KHR_XCB_SURFACE_SPEC_VERSION,
+        requires: [VK_KHR_surface],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [
+            (vkCreateXcbSurfaceKHR, PFN_vkCreateXcbSurfaceKHR, Device),
+            (
+                vkGetPhysicalDeviceXcbPresentationSupportKHR,
+                PFN_vkGetPhysicalDeviceXcbPresentationSupportKHR,
+                Device
+            )
+        ],
+        structure_types: [VK_STRUCTURE_TYPE_XCB_SURFACE_CREATE_INFO_KHR],
+        feature_structs: [],
+    },
+    VK_AMD_buffer_marker {
+        scope: Device,
+        spec_version: VK_AMD_BUFFER_MARKER_SPEC_VERSION,
+        requires: [],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [(vkCmdWriteBufferMarkerAMD, PFN_vkCmdWriteBufferMarkerAMD, Device)],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_EXT_debug_utils {
+        scope: Instance,
+        spec_version: VK_EXT_DEBUG_UTILS_SPEC_VERSION,
+        requires: [],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [
+            (vkCmdBeginDebugUtilsLabelEXT, PFN_vkCmdBeginDebugUtilsLabelEXT, Device),
+            (vkCmdEndDebugUtilsLabelEXT, PFN_vkCmdEndDebugUtilsLabelEXT, Device),
+            (vkCmdInsertDebugUtilsLabelEXT, PFN_vkCmdInsertDebugUtilsLabelEXT, Device),
+            (vkCreateDebugUtilsMessengerEXT, PFN_vkCreateDebugUtilsMessengerEXT, Instance),
+            (vkDestroyDebugUtilsMessengerEXT, PFN_vkDestroyDebugUtilsMessengerEXT, Instance),
+            (vkQueueBeginDebugUtilsLabelEXT, PFN_vkQueueBeginDebugUtilsLabelEXT, Device),
+            (vkQueueEndDebugUtilsLabelEXT, PFN_vkQueueEndDebugUtilsLabelEXT, Device),
+            (vkQueueInsertDebugUtilsLabelEXT, PFN_vkQueueInsertDebugUtilsLabelEXT, Device),
+            (vkSetDebugUtilsObjectNameEXT, PFN_vkSetDebugUtilsObjectNameEXT, Device),
+            (vkSetDebugUtilsObjectTagEXT, PFN_vkSetDebugUtilsObjectTagEXT, Device),
+            (vkSubmitDebugUtilsMessageEXT, PFN_vkSubmitDebugUtilsMessageEXT, Instance)
+        ],
+        structure_types: [
+            VK_STRUCTURE_TYPE_DEBUG_UTILS_LABEL_EXT,
+            VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CALLBACK_DATA_EXT,
+            VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
+            VK_STRUCTURE_TYPE_DEBUG_UTILS_OBJECT_NAME_INFO_EXT,
+            VK_STRUCTURE_TYPE_DEBUG_UTILS_OBJECT_TAG_INFO_EXT
+        ],
+        feature_structs: [],
+    },
+    VK_EXT_tooling_info {
+        scope: Device,
+        spec_version: VK_EXT_TOOLING_INFO_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 3)),
+        promoted_commands: [],
+        commands: [
+            (
+                vkGetPhysicalDeviceToolPropertiesEXT,
+                PFN_vkGetPhysicalDeviceToolPropertiesEXT,
+                Instance
+            )
+        ],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_EXT_image_2d_view_of_3d {
+        scope: Device,
+        spec_version: VK_EXT_IMAGE_2D_VIEW_OF_3D_SPEC_VERSION,
+        requires: [VK_KHR_maintenance1, VK_KHR_get_physical_device_properties2],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [(
+            VkPhysicalDeviceImage2DViewOf3DFeaturesEXT,
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_IMAGE_2D_VIEW_OF_3D_FEATURES_EXT,
+            image_2d_view_of_3d_features
+        )],
+    },
+    VK_EXT_image_robustness {
+        scope: Device,
+        spec_version: VK_EXT_IMAGE_ROBUSTNESS_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [(
+            VkPhysicalDeviceImageRobustnessFeaturesEXT,
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_IMAGE_ROBUSTNESS_FEATURES_EXT,
+            image_robustness_features
+        )],
+    },
+    #[cfg(target_os = "linux")]
+    VK_EXT_map_memory_placed {
+        scope: Device,
+        spec_version: VK_EXT_MAP_MEMORY_PLACED_SPEC_VERSION,
+        requires: [VK_KHR_map_memory2],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [],
+        structure_types: [
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MAP_MEMORY_PLACED_PROPERTIES_EXT,
+            VK_STRUCTURE_TYPE_MEMORY_MAP_PLACED_INFO_EXT
+        ],
+        feature_structs: [(
+            VkPhysicalDeviceMapMemoryPlacedFeaturesEXT,
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MAP_MEMORY_PLACED_FEATURES_EXT,
+            map_memory_placed_features
+        )],
+    },
+    VK_EXT_pipeline_creation_cache_control {
+        scope: Device,
+        spec_version: VK_EXT_PIPELINE_CREATION_CACHE_CONTROL_SPEC_VERSION,
+        requires: [],
+        promoted_to: Some((1, 3)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [(
+            VkPhysicalDevicePipelineCreationCacheControlFeaturesEXT,
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PIPELINE_CREATION_CACHE_CONTROL_FEATURES_EXT,
+            pipeline_creation_cache_control_features
+        )],
+    },
+    VK_EXT_robustness2 {
+        scope: Device,
+        spec_version: VK_EXT_ROBUSTNESS_2_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [],
+        structure_types: [VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_ROBUSTNESS_2_PROPERTIES_EXT],
+        feature_structs: [(
+            VkPhysicalDeviceRobustness2FeaturesEXT,
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_ROBUSTNESS_2_FEATURES_EXT,
+            robustness_2_features
+        )],
+    },
+    VK_EXT_subgroup_size_control {
+        scope: Device,
+        spec_version: VK_EXT_SUBGROUP_SIZE_CONTROL_SPEC_VERSION,
+        requires: [VK_KHR_get_physical_device_properties2],
+        promoted_to: Some((1, 3)),
+        promoted_commands: [],
+        commands: [],
+        structure_types: [
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_SIZE_CONTROL_PROPERTIES_EXT,
+            VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_REQUIRED_SUBGROUP_SIZE_CREATE_INFO_EXT
+        ],
+        feature_structs: [(
+            VkPhysicalDeviceSubgroupSizeControlFeaturesEXT,
+            VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_SIZE_CONTROL_FEATURES_EXT,
+            subgroup_size_control_features
+        )],
+    },
+    #[cfg(feature = "wsi")]
+    VK_EXT_swapchain_colorspace {
+        scope: Instance,
+        spec_version: VK_EXT_SWAPCHAIN_COLOR_SPACE_SPEC_VERSION,
+        requires: [VK_KHR_surface],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [],
+        structure_types: [],
+        feature_structs: [],
+    },
+    #[cfg(feature = "wsi")]
+    VK_GOOGLE_display_timing {
+        scope: Device,
+        spec_version: VK_GOOGLE_DISPLAY_TIMING_SPEC_VERSION,
+        requires: [VK_KHR_swapchain],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [
+            (vkGetPastPresentationTimingGOOGLE, PFN_vkGetPastPresentationTimingGOOGLE, Device),
+            (vkGetRefreshCycleDurationGOOGLE, PFN_vkGetRefreshCycleDurationGOOGLE, Device)
+        ],
+        structure_types: [VK_STRUCTURE_TYPE_PRESENT_TIMES_INFO_GOOGLE],
+        feature_structs: [],
+    },
+    VK_KAZAN_driver_configuration {
+        scope: Instance,
+        spec_version: VK_KAZAN_DRIVER_CONFIGURATION_SPEC_VERSION,
+        requires: [],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [
+            (
+                vkGetPhysicalDeviceDriverConfigurationKAZAN,
+                PFN_vkGetPhysicalDeviceDriverConfigurationKAZAN,
+                Instance
+            )
+        ],
+        structure_types: [],
+        feature_structs: [],
+    },
+    VK_KAZAN_generate_mipmaps {
+        scope: Device,
+        spec_version: VK_KAZAN_GENERATE_MIPMAPS_SPEC_VERSION,
+        requires: [],
+        promoted_to: None,
+        promoted_commands: [],
+        commands: [(vkCmdGenerateMipmapsKAZAN, PFN_vkCmdGenerateMipmapsKAZAN, Device)],
+        structure_types: [],
+        feature_structs: [],
+    },
+}
+
+impl Extension {
     pub fn get_recursively_required_extensions(self) -> Extensions {
         let mut retval = self.get_required_extensions();
         let mut worklist: EnumMap<Extension, Extension> = enum_map!{_ => self};
@@ -391,95 +1155,6 @@ impl Extension {
         }
         retval
     }
-    pub fn get_name(self) -> &'static str {
-        macro_rules! name {
-            ($($(#[$attributes:meta])* $name:ident,)*) => {
-                match self {
-                    $($(#[$attributes])* Extension::$name => stringify!($name),)*
-                }
-            }
-        }
-        name!(
-            VK_KHR_surface,
-            VK_KHR_bind_memory2,
-            VK_KHR_device_group,
-            VK_KHR_device_group_creation,
-            VK_KHR_descriptor_update_template,
-            VK_KHR_maintenance1,
-            VK_KHR_get_memory_requirements2,
-            VK_KHR_get_physical_device_properties2,
-            VK_KHR_sampler_ycbcr_conversion,
-            VK_KHR_maintenance2,
-            VK_KHR_maintenance3,
-            VK_KHR_external_memory_capabilities,
-            VK_KHR_external_fence_capabilities,
-            VK_KHR_external_semaphore_capabilities,
-            VK_KHR_16bit_storage,
-            VK_KHR_storage_buffer_storage_class,
-            VK_KHR_dedicated_allocation,
-            VK_KHR_external_fence,
-            VK_KHR_external_memory,
-            VK_KHR_external_semaphore,
-            VK_KHR_multiview,
-            VK_KHR_relaxed_block_layout,
-            VK_KHR_shader_draw_parameters,
-            VK_KHR_variable_pointers,
-            VK_KHR_swapchain,
-            #[cfg(unix)]
-            VK_KHR_xcb_surface,
-        )
-    }
-    pub fn get_spec_version(self) -> u32 {
-        match self {
-            Extension::VK_KHR_surface => api::VK_KHR_SURFACE_SPEC_VERSION,
-            Extension::VK_KHR_bind_memory2 => api::VK_KHR_BIND_MEMORY_2_SPEC_VERSION,
-            Extension::VK_KHR_device_group => api::VK_KHR_DEVICE_GROUP_SPEC_VERSION,
-            Extension::VK_KHR_device_group_creation => {
-                api::VK_KHR_DEVICE_GROUP_CREATION_SPEC_VERSION
-            }
-            Extension::VK_KHR_descriptor_update_template => {
-                api::VK_KHR_DESCRIPTOR_UPDATE_TEMPLATE_SPEC_VERSION
-            }
-            Extension::VK_KHR_maintenance1 => api::VK_KHR_MAINTENANCE1_SPEC_VERSION,
-            Extension::VK_KHR_get_memory_requirements2 => {
-                api::VK_KHR_GET_MEMORY_REQUIREMENTS_2_SPEC_VERSION
-            }
-            Extension::VK_KHR_get_physical_device_properties2 => {
-                api::VK_KHR_GET_PHYSICAL_DEVICE_PROPERTIES_2_SPEC_VERSION
-            }
-            Extension::VK_KHR_sampler_ycbcr_conversion => {
-                api::VK_KHR_SAMPLER_YCBCR_CONVERSION_SPEC_VERSION
-            }
-            Extension::VK_KHR_maintenance2 => api::VK_KHR_MAINTENANCE2_SPEC_VERSION,
-            Extension::VK_KHR_maintenance3 => api::VK_KHR_MAINTENANCE3_SPEC_VERSION,
-            Extension::VK_KHR_external_memory_capabilities => {
-                api::VK_KHR_EXTERNAL_MEMORY_CAPABILITIES_SPEC_VERSION
-            }
-            Extension::VK_KHR_external_fence_capabilities => {
-                api::VK_KHR_EXTERNAL_FENCE_CAPABILITIES_SPEC_VERSION
-            }
-            Extension::VK_KHR_external_semaphore_capabilities => {
-                api::VK_KHR_EXTERNAL_SEMAPHORE_CAPABILITIES_SPEC_VERSION
-            }
-            Extension::VK_KHR_16bit_storage => api::VK_KHR_16BIT_STORAGE_SPEC_VERSION,
-            Extension::VK_KHR_storage_buffer_storage_class => {
-                api::VK_KHR_STORAGE_BUFFER_STORAGE_CLASS_SPEC_VERSION
-            }
-            Extension::VK_KHR_dedicated_allocation => api::VK_KHR_DEDICATED_ALLOCATION_SPEC_VERSION,
-            Extension::VK_KHR_external_fence => api::VK_KHR_EXTERNAL_FENCE_SPEC_VERSION,
-            Extension::VK_KHR_external_memory => api::VK_KHR_EXTERNAL_MEMORY_SPEC_VERSION,
-            Extension::VK_KHR_external_semaphore => api::VK_KHR_EXTERNAL_SEMAPHORE_SPEC_VERSION,
-            Extension::VK_KHR_multiview => api::VK_KHR_MULTIVIEW_SPEC_VERSION,
-            Extension::VK_KHR_relaxed_block_layout => api::VK_KHR_RELAXED_BLOCK_LAYOUT_SPEC_VERSION,
-            Extension::VK_KHR_shader_draw_parameters => {
-                api::VK_KHR_SHADER_DRAW_PARAMETERS_SPEC_VERSION
-            }
-            Extension::VK_KHR_variable_pointers => api::VK_KHR_VARIABLE_POINTERS_SPEC_VERSION,
-            Extension::VK_KHR_swapchain => api::VK_KHR_SWAPCHAIN_SPEC_VERSION,
-            #[cfg(unix)]
-            Extension::VK_KHR_xcb_surface => api::VK_KHR_XCB_SURFACE_SPEC_VERSION,
-        }
-    }
     pub fn get_properties(self) -> api::VkExtensionProperties {
         let mut retval = api::VkExtensionProperties {
             extensionName: [0; api::VK_MAX_EXTENSION_NAME_SIZE as usize],
@@ -488,37 +1163,6 @@ impl Extension {
         copy_str_to_char_array(&mut retval.extensionName, self.get_name());
         retval
     }
-    pub fn get_scope(self) -> ExtensionScope {
-        match self {
-            Extension::VK_KHR_surface
-            | Extension::VK_KHR_device_group_creation
-            | Extension::VK_KHR_get_physical_device_properties2
-            | Extension::VK_KHR_external_memory_capabilities
-            | Extension::VK_KHR_external_fence_capabilities
-            | Extension::VK_KHR_external_semaphore_capabilities => ExtensionScope::Instance,
-            Extension::VK_KHR_bind_memory2
-            | Extension::VK_KHR_device_group
-            | Extension::VK_KHR_descriptor_update_template
-            | Extension::VK_KHR_maintenance1
-            | Extension::VK_KHR_get_memory_requirements2
-            | Extension::VK_KHR_sampler_ycbcr_conversion
-            | Extension::VK_KHR_maintenance2
-            | Extension::VK_KHR_maintenance3
-            | Extension::VK_KHR_16bit_storage
-            | Extension::VK_KHR_storage_buffer_storage_class
-            | Extension::VK_KHR_dedicated_allocation
-            | Extension::VK_KHR_external_fence
-            | Extension::VK_KHR_external_memory
-            | Extension::VK_KHR_external_semaphore
-            | Extension::VK_KHR_multiview
-            | Extension::VK_KHR_relaxed_block_layout
-            | Extension::VK_KHR_shader_draw_parameters
-            | Extension::VK_KHR_variable_pointers
-            | Extension::VK_KHR_swapchain => ExtensionScope::Device,
-            #[cfg(unix)]
-            Extension::VK_KHR_xcb_surface => ExtensionScope::Instance,
-        }
-    }
 }
 
 impl FromStr for Extension {
@@ -673,121 +1317,22 @@ fn get_proc_address(
         instance: scope == GetProcAddressScope::Instance,
         device: scope != GetProcAddressScope::Global,
     };
-    macro_rules! proc_alias_khr {
-        ($base_name:ident, $required_extension:expr) => {
-            if name == concat!(stringify!($base_name), "KHR") {
-                if scope.instance && $required_extension {
-                    name = stringify!($base_name);
-                } else {
+    if name.ends_with("KHR") {
+        let base_name = &name[..name.len() - "KHR".len()];
+        for (extension, &enabled) in extensions.iter() {
+            if extension
+                .get_promoted_commands()
+                .iter()
+                .any(|&command| command == base_name)
+            {
+                if !enabled {
                     return None;
                 }
+                name = base_name;
+                break;
             }
-        };
+        }
     }
-    proc_alias_khr!(
-        vkBindBufferMemory2,
-        extensions[Extension::VK_KHR_bind_memory2]
-    );
-    proc_alias_khr!(
-        vkBindImageMemory2,
-        extensions[Extension::VK_KHR_bind_memory2]
-    );
-    proc_alias_khr!(
-        vkCmdDispatchBase,
-        extensions[Extension::VK_KHR_device_group]
-    );
-    proc_alias_khr!(
-        vkCmdSetDeviceMask,
-        extensions[Extension::VK_KHR_device_group]
-    );
-    proc_alias_khr!(
-        vkCreateDescriptorUpdateTemplate,
-        extensions[Extension::VK_KHR_descriptor_update_template]
-    );
-    proc_alias_khr!(
-        vkCreateSamplerYcbcrConversion,
-        extensions[Extension::VK_KHR_sampler_ycbcr_conversion]
-    );
-    proc_alias_khr!(
-        vkDestroyDescriptorUpdateTemplate,
-        extensions[Extension::VK_KHR_descriptor_update_template]
-    );
-    proc_alias_khr!(
-        vkDestroySamplerYcbcrConversion,
-        extensions[Extension::VK_KHR_sampler_ycbcr_conversion]
-    );
-    proc_alias_khr!(
-        vkEnumeratePhysicalDeviceGroups,
-        extensions[Extension::VK_KHR_device_group_creation]
-    );
-    proc_alias_khr!(
-        vkGetBufferMemoryRequirements2,
-        extensions[Extension::VK_KHR_get_memory_requirements2]
-    );
-    proc_alias_khr!(
-        vkGetDescriptorSetLayoutSupport,
-        extensions[Extension::VK_KHR_maintenance3]
-    );
-    proc_alias_khr!(
-        vkGetDeviceGroupPeerMemoryFeatures,
-        extensions[Extension::VK_KHR_device_group]
-    );
-    proc_alias_khr!(
-        vkGetImageMemoryRequirements2,
-        extensions[Extension::VK_KHR_get_memory_requirements2]
-    );
-    proc_alias_khr!(
-        vkGetImageSparseMemoryRequirements2,
-        extensions[Extension::VK_KHR_get_memory_requirements2]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceExternalBufferProperties,
-        extensions[Extension::VK_KHR_external_memory_capabilities]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceExternalFenceProperties,
-        extensions[Extension::VK_KHR_external_fence_capabilities]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceExternalSemaphoreProperties,
-        extensions[Extension::VK_KHR_external_semaphore_capabilities]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceFeatures2,
-        extensions[Extension::VK_KHR_get_physical_device_properties2]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceFormatProperties2,
-        extensions[Extension::VK_KHR_get_physical_device_properties2]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceImageFormatProperties2,
-        extensions[Extension::VK_KHR_get_physical_device_properties2]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceMemoryProperties2,
-        extensions[Extension::VK_KHR_get_physical_device_properties2]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceProperties2,
-        extensions[Extension::VK_KHR_get_physical_device_properties2]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceQueueFamilyProperties2,
-        extensions[Extension::VK_KHR_get_physical_device_properties2]
-    );
-    proc_alias_khr!(
-        vkGetPhysicalDeviceSparseImageFormatProperties2,
-        extensions[Extension::VK_KHR_get_physical_device_properties2]
-    );
-    proc_alias_khr!(
-        vkTrimCommandPool,
-        extensions[Extension::VK_KHR_maintenance1]
-    );
-    proc_alias_khr!(
-        vkUpdateDescriptorSetWithTemplate,
-        extensions[Extension::VK_KHR_descriptor_update_template]
-    );
     macro_rules! proc_address {
         ($name:ident, $pfn_name:ident, $required_scope:ident, $required_extension:expr) => {
             if stringify!($name) == name {
@@ -802,197 +1347,11 @@ fn get_proc_address(
     }
     #[cfg_attr(rustfmt, rustfmt_skip)]
     {
-        proc_address!(vkCreateInstance, PFN_vkCreateInstance, global, true);
-        proc_address!(vkEnumerateInstanceExtensionProperties, PFN_vkEnumerateInstanceExtensionProperties, global, true);
-        proc_address!(vkEnumerateInstanceLayerProperties, PFN_vkEnumerateInstanceLayerProperties, global, true);
-        proc_address!(vkEnumerateInstanceVersion, PFN_vkEnumerateInstanceVersion, global, true);
-
-        proc_address!(vkAllocateCommandBuffers, PFN_vkAllocateCommandBuffers, device, true);
-        proc_address!(vkAllocateDescriptorSets, PFN_vkAllocateDescriptorSets, device, true);
-        proc_address!(vkAllocateMemory, PFN_vkAllocateMemory, device, true);
-        proc_address!(vkBeginCommandBuffer, PFN_vkBeginCommandBuffer, device, true);
-        proc_address!(vkBindBufferMemory, PFN_vkBindBufferMemory, device, true);
-        proc_address!(vkBindBufferMemory2, PFN_vkBindBufferMemory2, device, true);
-        proc_address!(vkBindImageMemory, PFN_vkBindImageMemory, device, true);
-        proc_address!(vkBindImageMemory2, PFN_vkBindImageMemory2, device, true);
-        proc_address!(vkCmdBeginQuery, PFN_vkCmdBeginQuery, device, true);
-        proc_address!(vkCmdBeginRenderPass, PFN_vkCmdBeginRenderPass, device, true);
-        proc_address!(vkCmdBindDescriptorSets, PFN_vkCmdBindDescriptorSets, device, true);
-        proc_address!(vkCmdBindIndexBuffer, PFN_vkCmdBindIndexBuffer, device, true);
-        proc_address!(vkCmdBindPipeline, PFN_vkCmdBindPipeline, device, true);
-        proc_address!(vkCmdBindVertexBuffers, PFN_vkCmdBindVertexBuffers, device, true);
-        proc_address!(vkCmdBlitImage, PFN_vkCmdBlitImage, device, true);
-        proc_address!(vkCmdClearAttachments, PFN_vkCmdClearAttachments, device, true);
-        proc_address!(vkCmdClearColorImage, PFN_vkCmdClearColorImage, device, true);
-        proc_address!(vkCmdClearDepthStencilImage, PFN_vkCmdClearDepthStencilImage, device, true);
-        proc_address!(vkCmdCopyBuffer, PFN_vkCmdCopyBuffer, device, true);
-        proc_address!(vkCmdCopyBufferToImage, PFN_vkCmdCopyBufferToImage, device, true);
-        proc_address!(vkCmdCopyImage, PFN_vkCmdCopyImage, device, true);
-        proc_address!(vkCmdCopyImageToBuffer, PFN_vkCmdCopyImageToBuffer, device, true);
-        proc_address!(vkCmdCopyQueryPoolResults, PFN_vkCmdCopyQueryPoolResults, device, true);
-        proc_address!(vkCmdDispatch, PFN_vkCmdDispatch, device, true);
-        proc_address!(vkCmdDispatchBase, PFN_vkCmdDispatchBase, device, true);
-        proc_address!(vkCmdDispatchIndirect, PFN_vkCmdDispatchIndirect, device, true);
-        proc_address!(vkCmdDraw, PFN_vkCmdDraw, device, true);
-        proc_address!(vkCmdDrawIndexed, PFN_vkCmdDrawIndexed, device, true);
-        proc_address!(vkCmdDrawIndexedIndirect, PFN_vkCmdDrawIndexedIndirect, device, true);
-        proc_address!(vkCmdDrawIndirect, PFN_vkCmdDrawIndirect, device, true);
-        proc_address!(vkCmdEndQuery, PFN_vkCmdEndQuery, device, true);
-        proc_address!(vkCmdEndRenderPass, PFN_vkCmdEndRenderPass, device, true);
-        proc_address!(vkCmdExecuteCommands, PFN_vkCmdExecuteCommands, device, true);
-        proc_address!(vkCmdFillBuffer, PFN_vkCmdFillBuffer, device, true);
-        proc_address!(vkCmdNextSubpass, PFN_vkCmdNextSubpass, device, true);
-        proc_address!(vkCmdPipelineBarrier, PFN_vkCmdPipelineBarrier, device, true);
-        proc_address!(vkCmdPushConstants, PFN_vkCmdPushConstants, device, true);
-        proc_address!(vkCmdResetEvent, PFN_vkCmdResetEvent, device, true);
-        proc_address!(vkCmdResetQueryPool, PFN_vkCmdResetQueryPool, device, true);
-        proc_address!(vkCmdResolveImage, PFN_vkCmdResolveImage, device, true);
-        proc_address!(vkCmdSetBlendConstants, PFN_vkCmdSetBlendConstants, device, true);
-        proc_address!(vkCmdSetDepthBias, PFN_vkCmdSetDepthBias, device, true);
-        proc_address!(vkCmdSetDepthBounds, PFN_vkCmdSetDepthBounds, device, true);
-        proc_address!(vkCmdSetDeviceMask, PFN_vkCmdSetDeviceMask, device, true);
-        proc_address!(vkCmdSetEvent, PFN_vkCmdSetEvent, device, true);
-        proc_address!(vkCmdSetLineWidth, PFN_vkCmdSetLineWidth, device, true);
-        proc_address!(vkCmdSetScissor, PFN_vkCmdSetScissor, device, true);
-        proc_address!(vkCmdSetStencilCompareMask, PFN_vkCmdSetStencilCompareMask, device, true);
-        proc_address!(vkCmdSetStencilReference, PFN_vkCmdSetStencilReference, device, true);
-        proc_address!(vkCmdSetStencilWriteMask, PFN_vkCmdSetStencilWriteMask, device, true);
-        proc_address!(vkCmdSetViewport, PFN_vkCmdSetViewport, device, true);
-        proc_address!(vkCmdUpdateBuffer, PFN_vkCmdUpdateBuffer, device, true);
-        proc_address!(vkCmdWaitEvents, PFN_vkCmdWaitEvents, device, true);
-        proc_address!(vkCmdWriteTimestamp, PFN_vkCmdWriteTimestamp, device, true);
-        proc_address!(vkCreateBuffer, PFN_vkCreateBuffer, device, true);
-        proc_address!(vkCreateBufferView, PFN_vkCreateBufferView, device, true);
-        proc_address!(vkCreateCommandPool, PFN_vkCreateCommandPool, device, true);
-        proc_address!(vkCreateComputePipelines, PFN_vkCreateComputePipelines, device, true);
-        proc_address!(vkCreateDescriptorPool, PFN_vkCreateDescriptorPool, device, true);
-        proc_address!(vkCreateDescriptorSetLayout, PFN_vkCreateDescriptorSetLayout, device, true);
-        proc_address!(vkCreateDescriptorUpdateTemplate, PFN_vkCreateDescriptorUpdateTemplate, device, true);
-        proc_address!(vkCreateDevice, PFN_vkCreateDevice, instance, true);
-        proc_address!(vkCreateEvent, PFN_vkCreateEvent, device, true);
-        proc_address!(vkCreateFence, PFN_vkCreateFence, device, true);
-        proc_address!(vkCreateFramebuffer, PFN_vkCreateFramebuffer, device, true);
-        proc_address!(vkCreateGraphicsPipelines, PFN_vkCreateGraphicsPipelines, device, true);
-        proc_address!(vkCreateImage, PFN_vkCreateImage, device, true);
-        proc_address!(vkCreateImageView, PFN_vkCreateImageView, device, true);
-        proc_address!(vkCreatePipelineCache, PFN_vkCreatePipelineCache, device, true);
-        proc_address!(vkCreatePipelineLayout, PFN_vkCreatePipelineLayout, device, true);
-        proc_address!(vkCreateQueryPool, PFN_vkCreateQueryPool, device, true);
-        proc_address!(vkCreateRenderPass, PFN_vkCreateRenderPass, device, true);
-        proc_address!(vkCreateSampler, PFN_vkCreateSampler, device, true);
-        proc_address!(vkCreateSamplerYcbcrConversion, PFN_vkCreateSamplerYcbcrConversion, device, true);
-        proc_address!(vkCreateSemaphore, PFN_vkCreateSemaphore, device, true);
-        proc_address!(vkCreateShaderModule, PFN_vkCreateShaderModule, device, true);
-        proc_address!(vkDestroyBuffer, PFN_vkDestroyBuffer, device, true);
-        proc_address!(vkDestroyBufferView, PFN_vkDestroyBufferView, device, true);
-        proc_address!(vkDestroyCommandPool, PFN_vkDestroyCommandPool, device, true);
-        proc_address!(vkDestroyDescriptorPool, PFN_vkDestroyDescriptorPool, device, true);
-        proc_address!(vkDestroyDescriptorSetLayout, PFN_vkDestroyDescriptorSetLayout, device, true);
-        proc_address!(vkDestroyDescriptorUpdateTemplate, PFN_vkDestroyDescriptorUpdateTemplate, device, true);
-        proc_address!(vkDestroyDevice, PFN_vkDestroyDevice, device, true);
-        proc_address!(vkDestroyEvent, PFN_vkDestroyEvent, device, true);
-        proc_address!(vkDestroyFence, PFN_vkDestroyFence, device, true);
-        proc_address!(vkDestroyFramebuffer, PFN_vkDestroyFramebuffer, device, true);
-        proc_address!(vkDestroyImage, PFN_vkDestroyImage, device, true);
-        proc_address!(vkDestroyImageView, PFN_vkDestroyImageView, device, true);
-        proc_address!(vkDestroyInstance, PFN_vkDestroyInstance, instance, true);
-        proc_address!(vkDestroyPipeline, PFN_vkDestroyPipeline, device, true);
-        proc_address!(vkDestroyPipelineCache, PFN_vkDestroyPipelineCache, device, true);
-        proc_address!(vkDestroyPipelineLayout, PFN_vkDestroyPipelineLayout, device, true);
-        proc_address!(vkDestroyQueryPool, PFN_vkDestroyQueryPool, device, true);
-        proc_address!(vkDestroyRenderPass, PFN_vkDestroyRenderPass, device, true);
-        proc_address!(vkDestroySampler, PFN_vkDestroySampler, device, true);
-        proc_address!(vkDestroySamplerYcbcrConversion, PFN_vkDestroySamplerYcbcrConversion, device, true);
-        proc_address!(vkDestroySemaphore, PFN_vkDestroySemaphore, device, true);
-        proc_address!(vkDestroyShaderModule, PFN_vkDestroyShaderModule, device, true);
-        proc_address!(vkDeviceWaitIdle, PFN_vkDeviceWaitIdle, device, true);
-        proc_address!(vkEndCommandBuffer, PFN_vkEndCommandBuffer, device, true);
-        proc_address!(vkEnumerateDeviceExtensionProperties, PFN_vkEnumerateDeviceExtensionProperties, instance, true);
-        proc_address!(vkEnumerateDeviceLayerProperties, PFN_vkEnumerateDeviceLayerProperties, instance, true);
-        proc_address!(vkEnumeratePhysicalDeviceGroups, PFN_vkEnumeratePhysicalDeviceGroups, instance, true);
-        proc_address!(vkEnumeratePhysicalDevices, PFN_vkEnumeratePhysicalDevices, instance, true);
-        proc_address!(vkFlushMappedMemoryRanges, PFN_vkFlushMappedMemoryRanges, device, true);
-        proc_address!(vkFreeCommandBuffers, PFN_vkFreeCommandBuffers, device, true);
-        proc_address!(vkFreeDescriptorSets, PFN_vkFreeDescriptorSets, device, true);
-        proc_address!(vkFreeMemory, PFN_vkFreeMemory, device, true);
-        proc_address!(vkGetBufferMemoryRequirements, PFN_vkGetBufferMemoryRequirements, device, true);
-        proc_address!(vkGetBufferMemoryRequirements2, PFN_vkGetBufferMemoryRequirements2, device, true);
-        proc_address!(vkGetDescriptorSetLayoutSupport, PFN_vkGetDescriptorSetLayoutSupport, device, true);
-        proc_address!(vkGetDeviceGroupPeerMemoryFeatures, PFN_vkGetDeviceGroupPeerMemoryFeatures, device, true);
-        proc_address!(vkGetDeviceMemoryCommitment, PFN_vkGetDeviceMemoryCommitment, device, true);
-        proc_address!(vkGetDeviceProcAddr, PFN_vkGetDeviceProcAddr, device, true);
-        proc_address!(vkGetDeviceQueue, PFN_vkGetDeviceQueue, device, true);
-        proc_address!(vkGetDeviceQueue2, PFN_vkGetDeviceQueue2, device, true);
-        proc_address!(vkGetEventStatus, PFN_vkGetEventStatus, device, true);
-        proc_address!(vkGetFenceStatus, PFN_vkGetFenceStatus, device, true);
-        proc_address!(vkGetImageMemoryRequirements, PFN_vkGetImageMemoryRequirements, device, true);
-        proc_address!(vkGetImageMemoryRequirements2, PFN_vkGetImageMemoryRequirements2, device, true);
-        proc_address!(vkGetImageSparseMemoryRequirements, PFN_vkGetImageSparseMemoryRequirements, device, true);
-        proc_address!(vkGetImageSparseMemoryRequirements2, PFN_vkGetImageSparseMemoryRequirements2, device, true);
-        proc_address!(vkGetImageSubresourceLayout, PFN_vkGetImageSubresourceLayout, device, true);
-        proc_address!(vkGetInstanceProcAddr, PFN_vkGetInstanceProcAddr, device, true);
-        proc_address!(vkGetPhysicalDeviceExternalBufferProperties, PFN_vkGetPhysicalDeviceExternalBufferProperties, instance, true);
-        proc_address!(vkGetPhysicalDeviceExternalFenceProperties, PFN_vkGetPhysicalDeviceExternalFenceProperties, instance, true);
-        proc_address!(vkGetPhysicalDeviceExternalSemaphoreProperties, PFN_vkGetPhysicalDeviceExternalSemaphoreProperties, instance, true);
-        proc_address!(vkGetPhysicalDeviceFeatures, PFN_vkGetPhysicalDeviceFeatures, instance, true);
-        proc_address!(vkGetPhysicalDeviceFeatures2, PFN_vkGetPhysicalDeviceFeatures2, instance, true);
-        proc_address!(vkGetPhysicalDeviceFormatProperties, PFN_vkGetPhysicalDeviceFormatProperties, instance, true);
-        proc_address!(vkGetPhysicalDeviceFormatProperties2, PFN_vkGetPhysicalDeviceFormatProperties2, instance, true);
-        proc_address!(vkGetPhysicalDeviceImageFormatProperties, PFN_vkGetPhysicalDeviceImageFormatProperties, instance, true);
-        proc_address!(vkGetPhysicalDeviceImageFormatProperties2, PFN_vkGetPhysicalDeviceImageFormatProperties2, instance, true);
-        proc_address!(vkGetPhysicalDeviceMemoryProperties, PFN_vkGetPhysicalDeviceMemoryProperties, instance, true);
-        proc_address!(vkGetPhysicalDeviceMemoryProperties2, PFN_vkGetPhysicalDeviceMemoryProperties2, instance, true);
-        proc_address!(vkGetPhysicalDeviceProperties, PFN_vkGetPhysicalDeviceProperties, instance, true);
-        proc_address!(vkGetPhysicalDeviceProperties2, PFN_vkGetPhysicalDeviceProperties2, instance, true);
-        proc_address!(vkGetPhysicalDeviceQueueFamilyProperties, PFN_vkGetPhysicalDeviceQueueFamilyProperties, instance, true);
-        proc_address!(vkGetPhysicalDeviceQueueFamilyProperties2, PFN_vkGetPhysicalDeviceQueueFamilyProperties2, instance, true);
-        proc_address!(vkGetPhysicalDeviceSparseImageFormatProperties, PFN_vkGetPhysicalDeviceSparseImageFormatProperties, instance, true);
-        proc_address!(vkGetPhysicalDeviceSparseImageFormatProperties2, PFN_vkGetPhysicalDeviceSparseImageFormatProperties2, instance, true);
-        proc_address!(vkGetPipelineCacheData, PFN_vkGetPipelineCacheData, device, true);
-        proc_address!(vkGetQueryPoolResults, PFN_vkGetQueryPoolResults, device, true);
-        proc_address!(vkGetRenderAreaGranularity, PFN_vkGetRenderAreaGranularity, device, true);
-        proc_address!(vkInvalidateMappedMemoryRanges, PFN_vkInvalidateMappedMemoryRanges, device, true);
-        proc_address!(vkMapMemory, PFN_vkMapMemory, device, true);
-        proc_address!(vkMergePipelineCaches, PFN_vkMergePipelineCaches, device, true);
-        proc_address!(vkQueueBindSparse, PFN_vkQueueBindSparse, device, true);
-        proc_address!(vkQueueSubmit, PFN_vkQueueSubmit, device, true);
-        proc_address!(vkQueueWaitIdle, PFN_vkQueueWaitIdle, device, true);
-        proc_address!(vkResetCommandBuffer, PFN_vkResetCommandBuffer, device, true);
-        proc_address!(vkResetCommandPool, PFN_vkResetCommandPool, device, true);
-        proc_address!(vkResetDescriptorPool, PFN_vkResetDescriptorPool, device, true);
-        proc_address!(vkResetEvent, PFN_vkResetEvent, device, true);
-        proc_address!(vkResetFences, PFN_vkResetFences, device, true);
-        proc_address!(vkSetEvent, PFN_vkSetEvent, device, true);
-        proc_address!(vkTrimCommandPool, PFN_vkTrimCommandPool, device, true);
-        proc_address!(vkUnmapMemory, PFN_vkUnmapMemory, device, true);
-        proc_address!(vkUpdateDescriptorSets, PFN_vkUpdateDescriptorSets, device, true);
-        proc_address!(vkUpdateDescriptorSetWithTemplate, PFN_vkUpdateDescriptorSetWithTemplate, device, true);
-        proc_address!(vkWaitForFences, PFN_vkWaitForFences, device, true);
-
-        proc_address!(vkDestroySurfaceKHR, PFN_vkDestroySurfaceKHR, device, extensions[Extension::VK_KHR_surface]);
-        proc_address!(vkGetPhysicalDeviceSurfaceSupportKHR, PFN_vkGetPhysicalDeviceSurfaceSupportKHR, device, extensions[Extension::VK_KHR_surface]);
-        proc_address!(vkGetPhysicalDeviceSurfaceCapabilitiesKHR, PFN_vkGetPhysicalDeviceSurfaceCapabilitiesKHR, device, extensions[Extension::VK_KHR_surface]);
-        proc_address!(vkGetPhysicalDeviceSurfaceFormatsKHR, PFN_vkGetPhysicalDeviceSurfaceFormatsKHR, device, extensions[Extension::VK_KHR_surface]);
-        proc_address!(vkGetPhysicalDeviceSurfacePresentModesKHR, PFN_vkGetPhysicalDeviceSurfacePresentModesKHR, device, extensions[Extension::VK_KHR_surface]);
-
-        proc_address!(vkCreateSwapchainKHR, PFN_vkCreateSwapchainKHR, device, extensions[Extension::VK_KHR_swapchain]);
-        proc_address!(vkDestroySwapchainKHR, PFN_vkDestroySwapchainKHR, device, extensions[Extension::VK_KHR_swapchain]);
-        proc_address!(vkGetSwapchainImagesKHR, PFN_vkGetSwapchainImagesKHR, device, extensions[Extension::VK_KHR_swapchain]);
-        proc_address!(vkAcquireNextImageKHR, PFN_vkAcquireNextImageKHR, device, extensions[Extension::VK_KHR_swapchain]);
-        proc_address!(vkQueuePresentKHR, PFN_vkQueuePresentKHR, device, extensions[Extension::VK_KHR_swapchain]);
-        proc_address!(vkGetDeviceGroupPresentCapabilitiesKHR, PFN_vkGetDeviceGroupPresentCapabilitiesKHR, device, extensions[Extension::VK_KHR_swapchain]);
-        proc_address!(vkGetDeviceGroupSurfacePresentModesKHR, PFN_vkGetDeviceGroupSurfacePresentModesKHR, device, extensions[Extension::VK_KHR_swapchain]);
-        proc_address!(vkGetPhysicalDevicePresentRectanglesKHR, PFN_vkGetPhysicalDevicePresentRectanglesKHR, device, extensions[Extension::VK_KHR_swapchain]);
-        proc_address!(vkAcquireNextImage2KHR, PFN_vkAcquireNextImage2KHR, device, extensions[Extension::VK_KHR_swapchain]);
-
-        #[cfg(unix)]
-        proc_address!(vkCreateXcbSurfaceKHR, PFN_vkCreateXcbSurfaceKHR, device, extensions[Extension::VK_KHR_xcb_surface]);
-        #[cfg(unix)]
-        proc_address!(vkGetPhysicalDeviceXcbPresentationSupportKHR, PFN_vkGetPhysicalDeviceXcbPresentationSupportKHR, device, extensions[Extension::VK_KHR_xcb_surface]);
+        // every command of the core versions the driver implements, generated from vk.xml
+        include!(concat!(env!("OUT_DIR"), "/vulkan-core-commands.rs"));
+
         /*
         proc_address!(vkCmdBeginConditionalRenderingEXT, PFN_vkCmdBeginConditionalRenderingEXT, device, unknown);
-        proc_address!(vkCmdBeginDebugUtilsLabelEXT, PFN_vkCmdBeginDebugUtilsLabelEXT, device, unknown);
-        proc_address!(vkCmdBeginRenderPass2KHR, PFN_vkCmdBeginRenderPass2KHR, device, unknown);
         proc_address!(vkCmdBindShadingRateImageNV, PFN_vkCmdBindShadingRateImageNV, device, unknown);
         proc_address!(vkCmdDebugMarkerBeginEXT, PFN_vkCmdDebugMarkerBeginEXT, device, unknown);
         proc_address!(vkCmdDebugMarkerEndEXT, PFN_vkCmdDebugMarkerEndEXT, device, unknown);
@@ -1005,10 +1364,6 @@ fn get_proc_address(
         proc_address!(vkCmdDrawMeshTasksIndirectNV, PFN_vkCmdDrawMeshTasksIndirectNV, device, unknown);
         proc_address!(vkCmdDrawMeshTasksNV, PFN_vkCmdDrawMeshTasksNV, device, unknown);
         proc_address!(vkCmdEndConditionalRenderingEXT, PFN_vkCmdEndConditionalRenderingEXT, device, unknown);
-        proc_address!(vkCmdEndDebugUtilsLabelEXT, PFN_vkCmdEndDebugUtilsLabelEXT, device, unknown);
-        proc_address!(vkCmdEndRenderPass2KHR, PFN_vkCmdEndRenderPass2KHR, device, unknown);
-        proc_address!(vkCmdInsertDebugUtilsLabelEXT, PFN_vkCmdInsertDebugUtilsLabelEXT, device, unknown);
-        proc_address!(vkCmdNextSubpass2KHR, PFN_vkCmdNextSubpass2KHR, device, unknown);
         proc_address!(vkCmdPushDescriptorSetKHR, PFN_vkCmdPushDescriptorSetKHR, device, unknown);
         proc_address!(vkCmdPushDescriptorSetWithTemplateKHR, PFN_vkCmdPushDescriptorSetWithTemplateKHR, device, unknown);
         proc_address!(vkCmdSetCheckpointNV, PFN_vkCmdSetCheckpointNV, device, unknown);
@@ -1018,12 +1373,9 @@ fn get_proc_address(
         proc_address!(vkCmdSetSampleLocationsEXT, PFN_vkCmdSetSampleLocationsEXT, device, unknown);
         proc_address!(vkCmdSetViewportShadingRatePaletteNV, PFN_vkCmdSetViewportShadingRatePaletteNV, device, unknown);
         proc_address!(vkCmdSetViewportWScalingNV, PFN_vkCmdSetViewportWScalingNV, device, unknown);
-        proc_address!(vkCmdWriteBufferMarkerAMD, PFN_vkCmdWriteBufferMarkerAMD, device, unknown);
         proc_address!(vkCreateDebugReportCallbackEXT, PFN_vkCreateDebugReportCallbackEXT, device, unknown);
-        proc_address!(vkCreateDebugUtilsMessengerEXT, PFN_vkCreateDebugUtilsMessengerEXT, device, unknown);
         proc_address!(vkCreateDisplayModeKHR, PFN_vkCreateDisplayModeKHR, device, unknown);
         proc_address!(vkCreateDisplayPlaneSurfaceKHR, PFN_vkCreateDisplayPlaneSurfaceKHR, device, unknown);
-        proc_address!(vkCreateRenderPass2KHR, PFN_vkCreateRenderPass2KHR, device, unknown);
         proc_address!(vkCreateSharedSwapchainsKHR, PFN_vkCreateSharedSwapchainsKHR, device, unknown);
         proc_address!(vkCreateValidationCacheEXT, PFN_vkCreateValidationCacheEXT, device, unknown);
         proc_address!(vkDebugMarkerSetObjectNameEXT, PFN_vkDebugMarkerSetObjectNameEXT, device, unknown);
@@ -1032,7 +1384,6 @@ fn get_proc_address(
         proc_address!(vkDebugReportMessageEXT, PFN_vkDebugReportMessageEXT, device, unknown);
         proc_address!(vkDebugUtilsMessengerCallbackEXT, PFN_vkDebugUtilsMessengerCallbackEXT, device, unknown);
         proc_address!(vkDestroyDebugReportCallbackEXT, PFN_vkDestroyDebugReportCallbackEXT, device, unknown);
-        proc_address!(vkDestroyDebugUtilsMessengerEXT, PFN_vkDestroyDebugUtilsMessengerEXT, device, unknown);
         proc_address!(vkDestroyValidationCacheEXT, PFN_vkDestroyValidationCacheEXT, device, unknown);
         proc_address!(vkDisplayPowerControlEXT, PFN_vkDisplayPowerControlEXT, device, unknown);
         proc_address!(vkGetDisplayModeProperties2KHR, PFN_vkGetDisplayModeProperties2KHR, device, unknown);
@@ -1044,7 +1395,6 @@ fn get_proc_address(
         proc_address!(vkGetMemoryFdKHR, PFN_vkGetMemoryFdKHR, device, unknown);
         proc_address!(vkGetMemoryFdPropertiesKHR, PFN_vkGetMemoryFdPropertiesKHR, device, unknown);
         proc_address!(vkGetMemoryHostPointerPropertiesEXT, PFN_vkGetMemoryHostPointerPropertiesEXT, device, unknown);
-        proc_address!(vkGetPastPresentationTimingGOOGLE, PFN_vkGetPastPresentationTimingGOOGLE, device, unknown);
         proc_address!(vkGetPhysicalDeviceDisplayPlaneProperties2KHR, PFN_vkGetPhysicalDeviceDisplayPlaneProperties2KHR, device, unknown);
         proc_address!(vkGetPhysicalDeviceDisplayPlanePropertiesKHR, PFN_vkGetPhysicalDeviceDisplayPlanePropertiesKHR, device, unknown);
         proc_address!(vkGetPhysicalDeviceDisplayProperties2KHR, PFN_vkGetPhysicalDeviceDisplayProperties2KHR, device, unknown);
@@ -1055,7 +1405,6 @@ fn get_proc_address(
         proc_address!(vkGetPhysicalDeviceSurfaceCapabilities2KHR, PFN_vkGetPhysicalDeviceSurfaceCapabilities2KHR, device, unknown);
         proc_address!(vkGetPhysicalDeviceSurfaceFormats2KHR, PFN_vkGetPhysicalDeviceSurfaceFormats2KHR, device, unknown);
         proc_address!(vkGetQueueCheckpointDataNV, PFN_vkGetQueueCheckpointDataNV, device, unknown);
-        proc_address!(vkGetRefreshCycleDurationGOOGLE, PFN_vkGetRefreshCycleDurationGOOGLE, device, unknown);
         proc_address!(vkGetSemaphoreFdKHR, PFN_vkGetSemaphoreFdKHR, device, unknown);
         proc_address!(vkGetShaderInfoAMD, PFN_vkGetShaderInfoAMD, device, unknown);
         proc_address!(vkGetSwapchainCounterEXT, PFN_vkGetSwapchainCounterEXT, device, unknown);
@@ -1064,18 +1413,25 @@ fn get_proc_address(
         proc_address!(vkImportFenceFdKHR, PFN_vkImportFenceFdKHR, device, unknown);
         proc_address!(vkImportSemaphoreFdKHR, PFN_vkImportSemaphoreFdKHR, device, unknown);
         proc_address!(vkMergeValidationCachesEXT, PFN_vkMergeValidationCachesEXT, device, unknown);
-        proc_address!(vkQueueBeginDebugUtilsLabelEXT, PFN_vkQueueBeginDebugUtilsLabelEXT, device, unknown);
-        proc_address!(vkQueueEndDebugUtilsLabelEXT, PFN_vkQueueEndDebugUtilsLabelEXT, device, unknown);
-        proc_address!(vkQueueInsertDebugUtilsLabelEXT, PFN_vkQueueInsertDebugUtilsLabelEXT, device, unknown);
         proc_address!(vkRegisterDeviceEventEXT, PFN_vkRegisterDeviceEventEXT, device, unknown);
         proc_address!(vkRegisterDisplayEventEXT, PFN_vkRegisterDisplayEventEXT, device, unknown);
         proc_address!(vkReleaseDisplayEXT, PFN_vkReleaseDisplayEXT, device, unknown);
-        proc_address!(vkSetDebugUtilsObjectNameEXT, PFN_vkSetDebugUtilsObjectNameEXT, device, unknown);
-        proc_address!(vkSetDebugUtilsObjectTagEXT, PFN_vkSetDebugUtilsObjectTagEXT, device, unknown);
         proc_address!(vkSetHdrMetadataEXT, PFN_vkSetHdrMetadataEXT, device, unknown);
-        proc_address!(vkSubmitDebugUtilsMessageEXT, PFN_vkSubmitDebugUtilsMessageEXT, device, unknown);
         */
     }
+    for (extension, &enabled) in extensions.iter() {
+        if let Some(command) = extension.get_command(name) {
+            let is_in_scope = match command.scope {
+                ExtensionScope::Instance => scope.instance,
+                ExtensionScope::Device => scope.device,
+            };
+            return if enabled && is_in_scope {
+                command.function
+            } else {
+                None
+            };
+        }
+    }
     //eprintln!("unknown function: {:?}", name);
     None
 }
@@ -1089,6 +1445,16 @@ pub struct Features {
     shader_draw_parameter_features: api::VkPhysicalDeviceShaderDrawParameterFeatures,
     protected_memory_features: api::VkPhysicalDeviceProtectedMemoryFeatures,
     multiview_features: api::VkPhysicalDeviceMultiviewFeatures,
+    subgroup_size_control_features: api::VkPhysicalDeviceSubgroupSizeControlFeaturesEXT,
+    vulkan_memory_model_features: api::VkPhysicalDeviceVulkanMemoryModelFeaturesKHR,
+    map_memory_placed_features: api::VkPhysicalDeviceMapMemoryPlacedFeaturesEXT,
+    image_2d_view_of_3d_features: api::VkPhysicalDeviceImage2DViewOf3DFeaturesEXT,
+    image_robustness_features: api::VkPhysicalDeviceImageRobustnessFeaturesEXT,
+    robustness_2_features: api::VkPhysicalDeviceRobustness2FeaturesEXT,
+    pipeline_executable_properties_features:
+        api::VkPhysicalDevicePipelineExecutablePropertiesFeaturesKHR,
+    pipeline_creation_cache_control_features:
+        api::VkPhysicalDevicePipelineCreationCacheControlFeaturesEXT,
 }
 
 impl Features {
@@ -1188,6 +1554,67 @@ impl Features {
                 multiviewGeometryShader: api::VK_FALSE,
                 multiviewTessellationShader: api::VK_FALSE,
             },
+            // the subgroup size is chosen for each shader stage when it's compiled
+            subgroup_size_control_features: api::VkPhysicalDeviceSubgroupSizeControlFeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_SIZE_CONTROL_FEATURES_EXT,
+                pNext: null_mut(),
+                subgroupSizeControl: api::VK_TRUE,
+                computeFullSubgroups: api::VK_TRUE,
+            },
+            // host memory is coherent, so availability and visibility operations are free
+            vulkan_memory_model_features: api::VkPhysicalDeviceVulkanMemoryModelFeaturesKHR {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_VULKAN_MEMORY_MODEL_FEATURES_KHR,
+                pNext: null_mut(),
+                vulkanMemoryModel: api::VK_TRUE,
+                vulkanMemoryModelDeviceScope: api::VK_TRUE,
+                vulkanMemoryModelAvailabilityVisibilityChains: api::VK_TRUE,
+            },
+            // placing memory needs linux's mremap
+            map_memory_placed_features: api::VkPhysicalDeviceMapMemoryPlacedFeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MAP_MEMORY_PLACED_FEATURES_EXT,
+                pNext: null_mut(),
+                memoryMapPlaced: cfg!(target_os = "linux") as api::VkBool32,
+                memoryMapRangePlaced: cfg!(target_os = "linux") as api::VkBool32,
+                memoryUnmapReserve: cfg!(target_os = "linux") as api::VkBool32,
+            },
+            // views of 3D images are addressed through ViewLayers::DepthSlices
+            image_2d_view_of_3d_features: api::VkPhysicalDeviceImage2DViewOf3DFeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_IMAGE_2D_VIEW_OF_3D_FEATURES_EXT,
+                pNext: null_mut(),
+                image2DViewOf3D: api::VK_TRUE,
+                sampler2DViewOf3D: api::VK_TRUE,
+            },
+            // image reads are always bounds checked, see ImageRobustness
+            image_robustness_features: api::VkPhysicalDeviceImageRobustnessFeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_IMAGE_ROBUSTNESS_FEATURES_EXT,
+                pNext: null_mut(),
+                robustImageAccess: api::VK_TRUE,
+            },
+            // out of bounds buffer accesses aren't tightly bounds checked
+            robustness_2_features: api::VkPhysicalDeviceRobustness2FeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_ROBUSTNESS_2_FEATURES_EXT,
+                pNext: null_mut(),
+                robustBufferAccess2: api::VK_FALSE,
+                robustImageAccess2: api::VK_TRUE,
+                nullDescriptor: api::VK_TRUE,
+            },
+            // each shader stage is an executable, with its SIMD width as its statistics
+            pipeline_executable_properties_features:
+                api::VkPhysicalDevicePipelineExecutablePropertiesFeaturesKHR {
+                    sType:
+                        api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PIPELINE_EXECUTABLE_PROPERTIES_FEATURES_KHR,
+                    pNext: null_mut(),
+                    pipelineExecutableInfo: api::VK_TRUE,
+                },
+            // there's no pipeline cache, so only the shader stages created in the background when
+            // shader modules are created can be used without compiling
+            pipeline_creation_cache_control_features:
+                api::VkPhysicalDevicePipelineCreationCacheControlFeaturesEXT {
+                    sType:
+                        api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PIPELINE_CREATION_CACHE_CONTROL_FEATURES_EXT,
+                    pNext: null_mut(),
+                    pipelineCreationCacheControl: api::VK_TRUE,
+                },
         }
     }
     fn splat(value: bool) -> Self {
@@ -1287,6 +1714,58 @@ impl Features {
                 multiviewGeometryShader: value32,
                 multiviewTessellationShader: value32,
             },
+            subgroup_size_control_features: api::VkPhysicalDeviceSubgroupSizeControlFeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_SIZE_CONTROL_FEATURES_EXT,
+                pNext: null_mut(),
+                subgroupSizeControl: value32,
+                computeFullSubgroups: value32,
+            },
+            vulkan_memory_model_features: api::VkPhysicalDeviceVulkanMemoryModelFeaturesKHR {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_VULKAN_MEMORY_MODEL_FEATURES_KHR,
+                pNext: null_mut(),
+                vulkanMemoryModel: value32,
+                vulkanMemoryModelDeviceScope: value32,
+                vulkanMemoryModelAvailabilityVisibilityChains: value32,
+            },
+            map_memory_placed_features: api::VkPhysicalDeviceMapMemoryPlacedFeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MAP_MEMORY_PLACED_FEATURES_EXT,
+                pNext: null_mut(),
+                memoryMapPlaced: value32,
+                memoryMapRangePlaced: value32,
+                memoryUnmapReserve: value32,
+            },
+            image_2d_view_of_3d_features: api::VkPhysicalDeviceImage2DViewOf3DFeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_IMAGE_2D_VIEW_OF_3D_FEATURES_EXT,
+                pNext: null_mut(),
+                image2DViewOf3D: value32,
+                sampler2DViewOf3D: value32,
+            },
+            image_robustness_features: api::VkPhysicalDeviceImageRobustnessFeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_IMAGE_ROBUSTNESS_FEATURES_EXT,
+                pNext: null_mut(),
+                robustImageAccess: value32,
+            },
+            robustness_2_features: api::VkPhysicalDeviceRobustness2FeaturesEXT {
+                sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_ROBUSTNESS_2_FEATURES_EXT,
+                pNext: null_mut(),
+                robustBufferAccess2: value32,
+                robustImageAccess2: value32,
+                nullDescriptor: value32,
+            },
+            pipeline_executable_properties_features:
+                api::VkPhysicalDevicePipelineExecutablePropertiesFeaturesKHR {
+                    sType:
+                        api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PIPELINE_EXECUTABLE_PROPERTIES_FEATURES_KHR,
+                    pNext: null_mut(),
+                    pipelineExecutableInfo: value32,
+                },
+            pipeline_creation_cache_control_features:
+                api::VkPhysicalDevicePipelineCreationCacheControlFeaturesEXT {
+                    sType:
+                        api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PIPELINE_CREATION_CACHE_CONTROL_FEATURES_EXT,
+                    pNext: null_mut(),
+                    pipelineCreationCacheControl: value32,
+                },
         }
     }
     fn visit2_mut<F: FnMut(&mut bool, &mut bool)>(&mut self, rhs: &mut Self, f: F) {
@@ -1384,6 +1863,22 @@ impl Features {
         visit!(multiview_features.multiview);
         visit!(multiview_features.multiviewGeometryShader);
         visit!(multiview_features.multiviewTessellationShader);
+        visit!(subgroup_size_control_features.subgroupSizeControl);
+        visit!(subgroup_size_control_features.computeFullSubgroups);
+        visit!(vulkan_memory_model_features.vulkanMemoryModel);
+        visit!(vulkan_memory_model_features.vulkanMemoryModelDeviceScope);
+        visit!(vulkan_memory_model_features.vulkanMemoryModelAvailabilityVisibilityChains);
+        visit!(map_memory_placed_features.memoryMapPlaced);
+        visit!(map_memory_placed_features.memoryMapRangePlaced);
+        visit!(map_memory_placed_features.memoryUnmapReserve);
+        visit!(image_2d_view_of_3d_features.image2DViewOf3D);
+        visit!(image_2d_view_of_3d_features.sampler2DViewOf3D);
+        visit!(image_robustness_features.robustImageAccess);
+        visit!(robustness_2_features.robustBufferAccess2);
+        visit!(robustness_2_features.robustImageAccess2);
+        visit!(robustness_2_features.nullDescriptor);
+        visit!(pipeline_executable_properties_features.pipelineExecutableInfo);
+        visit!(pipeline_creation_cache_control_features.pipelineCreationCacheControl);
     }
     fn visit2<F: FnMut(bool, bool)>(mut self, mut rhs: Self, mut f: F) {
         self.visit2_mut(&mut rhs, |v1, v2| f(*v1, *v2));
@@ -1421,27 +1916,6 @@ impl ImportExportFeatureSet<api::VkPhysicalDeviceFeatures2> for Features {
     }
 }
 
-macro_rules! impl_import_export_feature_set {
-    ($type:ident, $member:ident) => {
-        impl ImportExportFeatureSet<api::$type> for Features {
-            fn import_feature_set(&mut self, features: &api::$type) {
-                self.$member = api::$type {
-                    sType: self.$member.sType,
-                    pNext: self.$member.pNext,
-                    ..*features
-                };
-            }
-            fn export_feature_set(&self, features: &mut api::$type) {
-                *features = api::$type {
-                    sType: features.sType,
-                    pNext: features.pNext,
-                    ..self.$member
-                };
-            }
-        }
-    };
-}
-
 impl_import_export_feature_set!(
     VkPhysicalDevice16BitStorageFeatures,
     physical_device_16bit_storage_features
@@ -1529,18 +2003,41 @@ impl Not for Features {
     }
 }
 
-pub struct Queue {}
+pub struct Queue {
+    /// set by `KAZAN_NULL_DRIVER`
+    null_driver: bool,
+    /// where device loss is reported
+    debug_utils_messengers: Arc<DebugUtilsMessengers>,
+    /// set when running a command buffer panics; every later submission fails
+    lost: AtomicBool,
+    /// `None` when `KAZAN_WATCHDOG_TIMEOUT` is 0
+    watchdog: Option<QueueWatchdog>,
+    /// set by `KAZAN_LOOP_ITERATION_LIMIT`
+    loop_iteration_limit: Option<u64>,
+    /// `Some` when `KAZAN_TRACE` is set; shared by the device's queues
+    tracer: Option<Arc<Tracer>>,
+    /// when the device was created; timestamps are relative to it
+    timestamp_epoch: Instant,
+    /// runs the command buffers of submissions; `None` when `KAZAN_SUBMIT_THREADS` is 1. shared
+    /// by the device's queues
+    submit_thread_pool: Option<Arc<CompileThreadPool>>,
+}
 
 pub struct Device {
-    #[allow(dead_code)]
     physical_device: SharedHandle<api::VkPhysicalDevice>,
     extensions: Extensions,
-    #[allow(dead_code)]
     features: Features,
     queues: Vec<Vec<OwnedHandle<api::VkQueue>>>,
+    compile_thread_pool: CompileThreadPool,
+    /// `Some` when validation is enabled
+    validation: Option<Arc<Validation>>,
 }
 
 impl Device {
+    /// whether submissions skip draws and dispatches instead of running them
+    pub fn is_null_driver(&self) -> bool {
+        self.physical_device.configuration.null_driver
+    }
     unsafe fn new(
         physical_device: SharedHandle<api::VkPhysicalDevice>,
         create_info: *const api::VkDeviceCreateInfo,
@@ -1638,6 +2135,10 @@ impl Device {
         if !physical_device_variable_pointer_features.is_null() {
             selected_features.import_feature_set(&*physical_device_variable_pointer_features);
         }
+        import_extension_feature_structs(
+            &mut selected_features,
+            create_info as *const api::VkDeviceCreateInfo as *const api::VkBaseInStructure,
+        );
         if (selected_features & !physical_device.features) != Features::splat(false) {
             return Err(api::VK_ERROR_FEATURE_NOT_PRESENT);
         }
@@ -1678,11 +2179,39 @@ impl Device {
             total_queue_count += queue_count as usize;
         }
         assert!(total_queue_count <= TOTAL_QUEUE_COUNT);
+        let tracer = match physical_device.configuration.trace_path {
+            Some(ref trace_path) => Tracer::from_path(trace_path).map(Arc::new),
+            None => None,
+        };
+        let timestamp_epoch = Instant::now();
+        let submit_thread_pool = match physical_device.configuration.submit_thread_count {
+            1 => None,
+            thread_count => Some(Arc::new(CompileThreadPool::with_thread_name(
+                "kazan-submit",
+                thread_count,
+            ))),
+        };
         let mut queues = Vec::new();
         for queue_count in queue_counts {
             let mut queue_family_queues = Vec::new();
             for _queue_index in 0..queue_count {
-                queue_family_queues.push(OwnedHandle::<api::VkQueue>::new(Queue {}));
+                queue_family_queues.push(OwnedHandle::<api::VkQueue>::new(Queue {
+                    null_driver: physical_device.configuration.null_driver,
+                    debug_utils_messengers: physical_device.debug_utils_messengers.clone(),
+                    lost: AtomicBool::new(false),
+                    watchdog: physical_device
+                        .configuration
+                        .watchdog
+                        .timeout
+                        .map(QueueWatchdog::new),
+                    loop_iteration_limit: physical_device
+                        .configuration
+                        .watchdog
+                        .loop_iteration_limit,
+                    tracer: tracer.clone(),
+                    timestamp_epoch,
+                    submit_thread_pool: submit_thread_pool.clone(),
+                }));
             }
             queues.push(queue_family_queues);
         }
@@ -1691,6 +2220,10 @@ impl Device {
             extensions: enabled_extensions,
             features: selected_features,
             queues,
+            compile_thread_pool: CompileThreadPool::new(
+                physical_device.configuration.compile_thread_count,
+            ),
+            validation: Validation::from_environment(&physical_device.debug_utils_messengers),
         }))
     }
 }
@@ -1707,6 +2240,8 @@ pub struct PhysicalDevice {
     maintenance_3_properties: api::VkPhysicalDeviceMaintenance3Properties,
     protected_memory_properties: api::VkPhysicalDeviceProtectedMemoryProperties,
     subgroup_properties: api::VkPhysicalDeviceSubgroupProperties,
+    debug_utils_messengers: Arc<DebugUtilsMessengers>,
+    configuration: DriverConfiguration,
 }
 
 impl PhysicalDevice {
@@ -1733,12 +2268,12 @@ impl PhysicalDevice {
             maxTexelBufferElements: !0,
             maxUniformBufferRange: !0,
             maxStorageBufferRange: !0,
-            maxPushConstantsSize: !0,
+            maxPushConstantsSize: MAX_PUSH_CONSTANTS_SIZE,
             maxMemoryAllocationCount: !0,
             maxSamplerAllocationCount: !0,
             bufferImageGranularity: 1,
             sparseAddressSpaceSize: 0,
-            maxBoundDescriptorSets: !0,
+            maxBoundDescriptorSets: MAX_DESCRIPTOR_SETS as u32,
             maxPerStageDescriptorSamplers: !0,
             maxPerStageDescriptorUniformBuffers: !0,
             maxPerStageDescriptorStorageBuffers: !0,
@@ -1819,8 +2354,9 @@ impl PhysicalDevice {
                 | api::VK_SAMPLE_COUNT_4_BIT, // FIXME: update to correct value
             storageImageSampleCounts: api::VK_SAMPLE_COUNT_1_BIT, // FIXME: update to correct value
             maxSampleMaskWords: 1,
-            timestampComputeAndGraphics: api::VK_FALSE,
-            timestampPeriod: 0.0,
+            timestampComputeAndGraphics: api::VK_TRUE,
+            // timestamps are in nanoseconds
+            timestampPeriod: 1.0,
             maxClipDistances: 0,
             maxCullDistances: 0,
             maxCombinedClipAndCullDistances: 0,
@@ -2062,8 +2598,14 @@ impl PhysicalDevice {
             },
             api::VK_FORMAT_R8G8B8A8_UNORM => api::VkFormatProperties {
                 // FIXME: finish
-                linearTilingFeatures: 0,
-                optimalTilingFeatures: 0,
+                linearTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
+                optimalTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
                 bufferFeatures: 0,
             },
             api::VK_FORMAT_R8G8B8A8_SNORM => api::VkFormatProperties {
@@ -2098,14 +2640,26 @@ impl PhysicalDevice {
             },
             api::VK_FORMAT_R8G8B8A8_SRGB => api::VkFormatProperties {
                 // FIXME: finish
-                linearTilingFeatures: 0,
-                optimalTilingFeatures: 0,
+                linearTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
+                optimalTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
                 bufferFeatures: 0,
             },
             api::VK_FORMAT_B8G8R8A8_UNORM => api::VkFormatProperties {
                 // FIXME: finish
-                linearTilingFeatures: 0,
-                optimalTilingFeatures: 0,
+                linearTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
+                optimalTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
                 bufferFeatures: 0,
             },
             api::VK_FORMAT_B8G8R8A8_SNORM => api::VkFormatProperties {
@@ -2140,8 +2694,14 @@ impl PhysicalDevice {
             },
             api::VK_FORMAT_B8G8R8A8_SRGB => api::VkFormatProperties {
                 // FIXME: finish
-                linearTilingFeatures: 0,
-                optimalTilingFeatures: 0,
+                linearTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
+                optimalTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
                 bufferFeatures: 0,
             },
             api::VK_FORMAT_A8B8G8R8_UNORM_PACK32 => api::VkFormatProperties {
@@ -2597,7 +3157,9 @@ impl PhysicalDevice {
             api::VK_FORMAT_D32_SFLOAT => api::VkFormatProperties {
                 // FIXME: finish
                 linearTilingFeatures: 0,
-                optimalTilingFeatures: 0,
+                optimalTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
                 bufferFeatures: 0,
             },
             api::VK_FORMAT_S8_UINT => api::VkFormatProperties {
@@ -2615,7 +3177,9 @@ impl PhysicalDevice {
             api::VK_FORMAT_D24_UNORM_S8_UINT => api::VkFormatProperties {
                 // FIXME: finish
                 linearTilingFeatures: 0,
-                optimalTilingFeatures: 0,
+                optimalTilingFeatures: api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_SRC_BIT
+                    | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT,
                 bufferFeatures: 0,
             },
             api::VK_FORMAT_D32_SFLOAT_S8_UINT => api::VkFormatProperties {
@@ -3159,6 +3723,9 @@ impl PhysicalDevice {
 
 pub struct Instance {
     physical_device: OwnedHandle<api::VkPhysicalDevice>,
+    /// the messenger from `VkInstanceCreateInfo::pNext`
+    #[allow(dead_code)]
+    create_info_messenger: Option<OwnedHandle<api::VkDebugUtilsMessengerEXT>>,
 }
 
 impl Instance {
@@ -3168,6 +3735,7 @@ impl Instance {
         parse_next_chain_const!{
             create_info,
             root = api::VK_STRUCTURE_TYPE_INSTANCE_CREATE_INFO,
+            debug_utils_messenger_create_info: api::VkDebugUtilsMessengerCreateInfoEXT = api::VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
         }
         let create_info = &*create_info;
         if create_info.enabledLayerCount != 0 {
@@ -3210,8 +3778,20 @@ impl Instance {
                 eprintln!("mem_info error: {}", error);
                 return Err(api::VK_ERROR_INITIALIZATION_FAILED);
             }
-            Ok(info) => system_memory_size = info.total * 1024,
+            // allocations are limited to `isize::MAX` bytes, which is less than the memory of
+            // most systems when running on a 32-bit target
+            Ok(info) => system_memory_size = (info.total * 1024).min(isize::MAX as u64),
         }
+        let debug_utils_messengers = Arc::new(DebugUtilsMessengers::default());
+        let create_info_messenger = if debug_utils_messenger_create_info.is_null() {
+            None
+        } else {
+            let messenger = OwnedHandle::<api::VkDebugUtilsMessengerEXT>::new(
+                DebugUtilsMessenger::new(&*debug_utils_messenger_create_info),
+            );
+            debug_utils_messengers.add(messenger.get_handle());
+            Some(messenger)
+        };
         let mut device_name = [0; api::VK_MAX_PHYSICAL_DEVICE_NAME_SIZE as usize];
         copy_str_to_char_array(&mut device_name, KAZAN_DEVICE_NAME);
         #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_update))]
@@ -3283,7 +3863,10 @@ impl Instance {
                     supportedOperations: api::VK_SUBGROUP_FEATURE_BASIC_BIT,
                     quadOperationsInAllStages: api::VK_FALSE,
                 },
+                debug_utils_messengers,
+                configuration: DriverConfiguration::from_environment(),
             }),
+            create_info_messenger,
         });
         Ok(retval.take())
     }
@@ -3484,7 +4067,7 @@ unsafe fn get_physical_device_queue_family_properties(
             | api::VK_QUEUE_COMPUTE_BIT
             | api::VK_QUEUE_TRANSFER_BIT,
         queueCount: queue_count,
-        timestampValidBits: 0,
+        timestampValidBits: 64,
         minImageTransferGranularity: api::VkExtent3D {
             width: 1,
             height: 1,
@@ -3639,35 +4222,146 @@ pub unsafe extern "system" fn vkGetDeviceQueue(
     );
 }
 
-#[allow(non_snake_case)]
-pub unsafe extern "system" fn vkQueueSubmit(
-    _queue: api::VkQueue,
-    _submitCount: u32,
-    _pSubmits: *const api::VkSubmitInfo,
-    _fence: api::VkFence,
-) -> api::VkResult {
-    unimplemented!()
-}
-
-#[allow(non_snake_case)]
-pub unsafe extern "system" fn vkQueueWaitIdle(_queue: api::VkQueue) -> api::VkResult {
-    unimplemented!()
-}
+/// lets submitted command buffers be run on the submit threads;
+/// the application can't record or free them until the submission finishes
+struct SubmittedCommandBuffer(api::VkCommandBuffer);
 
-#[allow(non_snake_case)]
-pub unsafe extern "system" fn vkDeviceWaitIdle(_device: api::VkDevice) -> api::VkResult {
-    unimplemented!()
-}
+unsafe impl Sync for SubmittedCommandBuffer {}
 
 #[allow(non_snake_case)]
-pub unsafe extern "system" fn vkAllocateMemory(
-    _device: api::VkDevice,
-    allocate_info: *const api::VkMemoryAllocateInfo,
-    _allocator: *const api::VkAllocationCallbacks,
-    memory: *mut api::VkDeviceMemory,
+pub unsafe extern "system" fn vkQueueSubmit(
+    queue: api::VkQueue,
+    submit_count: u32,
+    submits: *const api::VkSubmitInfo,
+    fence: api::VkFence,
 ) -> api::VkResult {
-    parse_next_chain_const!{
-        allocate_info,
+    let queue = SharedHandle::from(queue).unwrap();
+    if queue.lost.load(Ordering::Acquire) {
+        return api::VK_ERROR_DEVICE_LOST;
+    }
+    let watchdog = Arc::new(Watchdog::new(queue.loop_iteration_limit));
+    let _watch = queue
+        .watchdog
+        .as_ref()
+        .map(|queue_watchdog| queue_watchdog.watch(watchdog.clone()));
+    let submits: &[api::VkSubmitInfo] = if submit_count == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(submits, submit_count as usize)
+    };
+    let mut graph = SubmitGraph::default();
+    let mut is_valid = true;
+    for submit in submits {
+        // there's only one physical device in the group and protected memory isn't supported,
+        // so these can be ignored
+        parse_next_chain_const!{
+            submit as *const api::VkSubmitInfo,
+            root = api::VK_STRUCTURE_TYPE_SUBMIT_INFO,
+            _device_group_submit_info: api::VkDeviceGroupSubmitInfo = api::VK_STRUCTURE_TYPE_DEVICE_GROUP_SUBMIT_INFO,
+            _protected_submit_info: api::VkProtectedSubmitInfo = api::VK_STRUCTURE_TYPE_PROTECTED_SUBMIT_INFO,
+        }
+        // semaphores signaled by earlier submissions are already signaled, since commands run
+        // before vkQueueSubmit returns, so only the ones signaled by this submission order
+        // anything
+        if submit.waitSemaphoreCount != 0 {
+            graph.wait_semaphores(
+                slice::from_raw_parts(submit.pWaitSemaphores, submit.waitSemaphoreCount as usize)
+                    .iter()
+                    .cloned(),
+            );
+        }
+        if submit.commandBufferCount != 0 {
+            for &command_buffer in
+                slice::from_raw_parts(submit.pCommandBuffers, submit.commandBufferCount as usize)
+            {
+                let buffer = SharedHandle::from(command_buffer).unwrap();
+                is_valid &= buffer.validate_submit(command_buffer);
+                graph.add(
+                    SubmittedCommandBuffer(command_buffer),
+                    buffer.commands.has_ordered_commands(),
+                );
+            }
+        }
+        if submit.signalSemaphoreCount != 0 {
+            graph.signal_semaphores(
+                slice::from_raw_parts(
+                    submit.pSignalSemaphores,
+                    submit.signalSemaphoreCount as usize,
+                )
+                .iter()
+                .cloned(),
+            );
+        }
+    }
+    // validation errors are reported for every command buffer before anything runs, and the
+    // submission is dropped, since running part of it could depend on the part that's invalid
+    if !is_valid {
+        return api::VK_ERROR_VALIDATION_FAILED_EXT;
+    }
+    let queue = &*queue;
+    let watchdog = &*watchdog;
+    let result = graph.run(
+        queue.submit_thread_pool.as_deref(),
+        |&SubmittedCommandBuffer(command_buffer)| {
+            let buffer = SharedHandle::from(command_buffer).unwrap();
+            let mut last_buffer_marker = None;
+            // a command that fails loses the device rather than aborting, and so does a
+            // submission that's stopped by the watchdog rather than hanging
+            let error = match buffer.execute(
+                command_buffer,
+                queue.null_driver,
+                watchdog,
+                &mut last_buffer_marker,
+                queue.tracer.as_deref(),
+                queue.timestamp_epoch,
+            ) {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+            queue.lost.store(true, Ordering::Release);
+            command_buffer::report_device_lost(
+                &queue.debug_utils_messengers,
+                command_buffer,
+                &error.to_string(),
+                last_buffer_marker,
+            );
+            Err(())
+        },
+    );
+    if result.is_err() {
+        return api::VK_ERROR_DEVICE_LOST;
+    }
+    if let Some(fence) = SharedHandle::from(fence) {
+        fence.signaled.store(true, Ordering::Release);
+    }
+    api::VK_SUCCESS
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkQueueWaitIdle(queue: api::VkQueue) -> api::VkResult {
+    // commands run before vkQueueSubmit returns
+    if SharedHandle::from(queue).unwrap().lost.load(Ordering::Acquire) {
+        api::VK_ERROR_DEVICE_LOST
+    } else {
+        api::VK_SUCCESS
+    }
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkDeviceWaitIdle(_device: api::VkDevice) -> api::VkResult {
+    // commands run before vkQueueSubmit returns
+    api::VK_SUCCESS
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkAllocateMemory(
+    device: api::VkDevice,
+    allocate_info: *const api::VkMemoryAllocateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    memory: *mut api::VkDeviceMemory,
+) -> api::VkResult {
+    parse_next_chain_const!{
+        allocate_info,
         root = api::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
         export_memory_allocate_info: api::VkExportMemoryAllocateInfo = api::VK_STRUCTURE_TYPE_EXPORT_MEMORY_ALLOCATE_INFO,
         memory_allocate_flags_info: api::VkMemoryAllocateFlagsInfo = api::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_FLAGS_INFO,
@@ -3688,10 +4382,15 @@ pub unsafe extern "system" fn vkAllocateMemory(
             if allocate_info.allocationSize > isize::max_value() as u64 {
                 return api::VK_ERROR_OUT_OF_DEVICE_MEMORY;
             }
-            match DeviceMemory::allocate_from_default_heap(DeviceMemoryLayout::calculate(
-                allocate_info.allocationSize as usize,
-                MIN_MEMORY_MAP_ALIGNMENT,
-            )) {
+            let device = SharedHandle::from(device).unwrap();
+            match DeviceMemory::allocate(
+                DeviceMemoryLayout::calculate(
+                    allocate_info.allocationSize as usize,
+                    MIN_MEMORY_MAP_ALIGNMENT,
+                ),
+                &device.physical_device.configuration.memory,
+                device.features.map_memory_placed_features.memoryMapPlaced != api::VK_FALSE,
+            ) {
                 Ok(new_memory) => {
                     *memory = OwnedHandle::<api::VkDeviceMemory>::new(new_memory).take();
                     api::VK_SUCCESS
@@ -3715,21 +4414,96 @@ pub unsafe extern "system" fn vkFreeMemory(
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkMapMemory(
-    _device: api::VkDevice,
+    device: api::VkDevice,
     memory: api::VkDeviceMemory,
     offset: api::VkDeviceSize,
-    _size: api::VkDeviceSize,
-    _flags: api::VkMemoryMapFlags,
+    size: api::VkDeviceSize,
+    flags: api::VkMemoryMapFlags,
+    data: *mut *mut c_void,
+) -> api::VkResult {
+    vkMapMemory2KHR(
+        device,
+        &api::VkMemoryMapInfoKHR {
+            sType: api::VK_STRUCTURE_TYPE_MEMORY_MAP_INFO_KHR,
+            pNext: null(),
+            flags,
+            memory,
+            offset,
+            size,
+        },
+        data,
+    )
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkUnmapMemory(device: api::VkDevice, memory: api::VkDeviceMemory) {
+    vkUnmapMemory2KHR(
+        device,
+        &api::VkMemoryUnmapInfoKHR {
+            sType: api::VK_STRUCTURE_TYPE_MEMORY_UNMAP_INFO_KHR,
+            pNext: null(),
+            flags: 0,
+            memory,
+        },
+    );
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkMapMemory2KHR(
+    _device: api::VkDevice,
+    memory_map_info: *const api::VkMemoryMapInfoKHR,
     data: *mut *mut c_void,
 ) -> api::VkResult {
-    let memory = SharedHandle::from(memory).unwrap();
-    // remember to keep vkUnmapMemory up to date
-    *data = memory.get().as_ptr().offset(offset as isize) as *mut c_void;
+    parse_next_chain_const!{
+        memory_map_info,
+        root = api::VK_STRUCTURE_TYPE_MEMORY_MAP_INFO_KHR,
+        memory_map_placed_info: api::VkMemoryMapPlacedInfoEXT = api::VK_STRUCTURE_TYPE_MEMORY_MAP_PLACED_INFO_EXT,
+    }
+    let memory_map_info = &*memory_map_info;
+    let memory = SharedHandle::from(memory_map_info.memory).unwrap();
+    let offset = memory_map_info.offset as usize;
+    if (memory_map_info.flags & api::VK_MEMORY_MAP_PLACED_BIT_EXT) != 0 {
+        assert!(!memory_map_placed_info.is_null());
+        let address = NonNull::new((*memory_map_placed_info).pPlacedAddress as *mut u8).unwrap();
+        let size = if memory_map_info.size == api::VK_WHOLE_SIZE as api::VkDeviceSize {
+            memory.size() - offset
+        } else {
+            memory_map_info.size as usize
+        };
+        if let Err(error) = memory.map_placed(offset, size, address) {
+            eprintln!("kazan: {}", error);
+            return api::VK_ERROR_MEMORY_MAP_FAILED;
+        }
+        *data = address.as_ptr() as *mut c_void;
+        return api::VK_SUCCESS;
+    }
+    // memory is host coherent and commands only use the allocation's own address, so the
+    // mapping can be used while the memory is in use by submissions.
+    // remember to keep vkUnmapMemory2KHR up to date
+    *data = memory.get().as_ptr().add(offset) as *mut c_void;
     api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
-pub unsafe extern "system" fn vkUnmapMemory(_device: api::VkDevice, _memory: api::VkDeviceMemory) {}
+pub unsafe extern "system" fn vkUnmapMemory2KHR(
+    _device: api::VkDevice,
+    memory_unmap_info: *const api::VkMemoryUnmapInfoKHR,
+) -> api::VkResult {
+    parse_next_chain_const!{
+        memory_unmap_info,
+        root = api::VK_STRUCTURE_TYPE_MEMORY_UNMAP_INFO_KHR,
+    }
+    let memory_unmap_info = &*memory_unmap_info;
+    let memory = SharedHandle::from(memory_unmap_info.memory).unwrap();
+    let reserve = (memory_unmap_info.flags & api::VK_MEMORY_UNMAP_RESERVE_BIT_EXT) != 0;
+    match memory.unmap_placed(reserve) {
+        Ok(()) => api::VK_SUCCESS,
+        Err(error) => {
+            eprintln!("kazan: {}", error);
+            api::VK_ERROR_MEMORY_MAP_FAILED
+        }
+    }
+}
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkFlushMappedMemoryRanges(
@@ -3737,7 +4511,8 @@ pub unsafe extern "system" fn vkFlushMappedMemoryRanges(
     _memoryRangeCount: u32,
     _pMemoryRanges: *const api::VkMappedMemoryRange,
 ) -> api::VkResult {
-    unimplemented!()
+    // all memory is host coherent
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
@@ -3746,7 +4521,8 @@ pub unsafe extern "system" fn vkInvalidateMappedMemoryRanges(
     _memoryRangeCount: u32,
     _pMemoryRanges: *const api::VkMappedMemoryRange,
 ) -> api::VkResult {
-    unimplemented!()
+    // all memory is host coherent
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
@@ -3881,67 +4657,125 @@ pub unsafe extern "system" fn vkQueueBindSparse(
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateFence(
     _device: api::VkDevice,
-    _pCreateInfo: *const api::VkFenceCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pFence: *mut api::VkFence,
+    create_info: *const api::VkFenceCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    fence: *mut api::VkFence,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        create_info,
+        root = api::VK_STRUCTURE_TYPE_FENCE_CREATE_INFO,
+        export_fence_create_info: api::VkExportFenceCreateInfo = api::VK_STRUCTURE_TYPE_EXPORT_FENCE_CREATE_INFO,
+    }
+    let create_info = &*create_info;
+    if !export_fence_create_info.is_null() {
+        unimplemented!()
+    }
+    *fence = OwnedHandle::<api::VkFence>::new(Fence {
+        signaled: AtomicBool::new(create_info.flags & api::VK_FENCE_CREATE_SIGNALED_BIT != 0),
+    })
+    .take();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyFence(
     _device: api::VkDevice,
-    _fence: api::VkFence,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    fence: api::VkFence,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    OwnedHandle::from(fence);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkResetFences(
     _device: api::VkDevice,
-    _fenceCount: u32,
-    _pFences: *const api::VkFence,
+    fence_count: u32,
+    fences: *const api::VkFence,
 ) -> api::VkResult {
-    unimplemented!()
+    assert_ne!(fence_count, 0);
+    for &fence in slice::from_raw_parts(fences, fence_count as usize) {
+        let fence = SharedHandle::from(fence).unwrap();
+        fence.signaled.store(false, Ordering::Relaxed);
+    }
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetFenceStatus(
     _device: api::VkDevice,
-    _fence: api::VkFence,
+    fence: api::VkFence,
 ) -> api::VkResult {
-    unimplemented!()
+    let fence = SharedHandle::from(fence).unwrap();
+    if fence.signaled.load(Ordering::Acquire) {
+        api::VK_SUCCESS
+    } else {
+        api::VK_NOT_READY
+    }
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkWaitForFences(
     _device: api::VkDevice,
-    _fenceCount: u32,
-    _pFences: *const api::VkFence,
-    _waitAll: api::VkBool32,
-    _timeout: u64,
+    fence_count: u32,
+    fences: *const api::VkFence,
+    wait_all: api::VkBool32,
+    timeout: u64,
 ) -> api::VkResult {
-    unimplemented!()
+    assert_ne!(fence_count, 0);
+    let fences: Vec<_> = slice::from_raw_parts(fences, fence_count as usize)
+        .iter()
+        .map(|&fence| SharedHandle::from(fence).unwrap())
+        .collect();
+    let is_done = || {
+        let mut signaled = fences
+            .iter()
+            .map(|fence| fence.signaled.load(Ordering::Acquire));
+        if wait_all != api::VK_FALSE {
+            signaled.all(|v| v)
+        } else {
+            signaled.any(|v| v)
+        }
+    };
+    // fences are only signaled by vkQueueSubmit, so an unsignaled fence can only be signaled by
+    // a submission on another thread
+    let start_time = Instant::now();
+    let timeout = Duration::from_nanos(timeout);
+    while !is_done() {
+        if start_time.elapsed() >= timeout {
+            return api::VK_TIMEOUT;
+        }
+        thread::yield_now();
+    }
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateSemaphore(
     _device: api::VkDevice,
-    _pCreateInfo: *const api::VkSemaphoreCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pSemaphore: *mut api::VkSemaphore,
+    create_info: *const api::VkSemaphoreCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    semaphore: *mut api::VkSemaphore,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        create_info,
+        root = api::VK_STRUCTURE_TYPE_SEMAPHORE_CREATE_INFO,
+        export_semaphore_create_info: api::VkExportSemaphoreCreateInfo = api::VK_STRUCTURE_TYPE_EXPORT_SEMAPHORE_CREATE_INFO,
+    }
+    if !export_semaphore_create_info.is_null() {
+        unimplemented!()
+    }
+    // commands run before vkQueueSubmit returns, so semaphores don't need any state
+    *semaphore = OwnedHandle::<api::VkSemaphore>::new(Semaphore {}).take();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroySemaphore(
     _device: api::VkDevice,
-    _semaphore: api::VkSemaphore,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    semaphore: api::VkSemaphore,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    OwnedHandle::from(semaphore);
 }
 
 #[allow(non_snake_case)]
@@ -3990,34 +4824,54 @@ pub unsafe extern "system" fn vkResetEvent(
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateQueryPool(
     _device: api::VkDevice,
-    _pCreateInfo: *const api::VkQueryPoolCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pQueryPool: *mut api::VkQueryPool,
+    create_info: *const api::VkQueryPoolCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    query_pool: *mut api::VkQueryPool,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        create_info,
+        root = api::VK_STRUCTURE_TYPE_QUERY_POOL_CREATE_INFO,
+    }
+    *query_pool = OwnedHandle::<api::VkQueryPool>::new(QueryPool::new(&*create_info)).take();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyQueryPool(
     _device: api::VkDevice,
-    _queryPool: api::VkQueryPool,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    query_pool: api::VkQueryPool,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    OwnedHandle::from(query_pool);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetQueryPoolResults(
     _device: api::VkDevice,
-    _queryPool: api::VkQueryPool,
-    _firstQuery: u32,
-    _queryCount: u32,
-    _dataSize: usize,
-    _pData: *mut c_void,
-    _stride: api::VkDeviceSize,
-    _flags: api::VkQueryResultFlags,
+    query_pool: api::VkQueryPool,
+    first_query: u32,
+    query_count: u32,
+    data_size: usize,
+    data: *mut c_void,
+    stride: api::VkDeviceSize,
+    flags: api::VkQueryResultFlags,
 ) -> api::VkResult {
-    unimplemented!()
+    let query_pool = SharedHandle::from(query_pool).unwrap();
+    let result_size = if flags & api::VK_QUERY_RESULT_64_BIT != 0 {
+        8
+    } else {
+        4
+    };
+    if query_count != 0 {
+        assert!((query_count as usize - 1) * stride as usize + result_size <= data_size);
+    }
+    query_pool.write_results(
+        first_query,
+        query_count,
+        data as *mut u8,
+        stride as usize,
+        flags,
+    )
 }
 
 #[allow(non_snake_case)]
@@ -4103,6 +4957,8 @@ pub unsafe extern "system" fn vkCreateImage(
                 api::VK_IMAGE_TILING_LINEAR => SupportedTilings::LinearOnly,
                 _ => unreachable!("invalid image tiling"),
             },
+            image_type: create_info.imageType,
+            flags: create_info.flags,
             format: create_info.format,
             extents: create_info.extent,
             array_layers: create_info.arrayLayers,
@@ -4132,35 +4988,66 @@ pub unsafe extern "system" fn vkDestroyImage(
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetImageSubresourceLayout(
     _device: api::VkDevice,
-    _image: api::VkImage,
-    _pSubresource: *const api::VkImageSubresource,
-    _pLayout: *mut api::VkSubresourceLayout,
-) {
-    unimplemented!()
+    image: api::VkImage,
+    subresource: *const api::VkImageSubresource,
+    layout: *mut api::VkSubresourceLayout,
+) {
+    let image = SharedHandle::from(image).unwrap();
+    let subresource = &*subresource;
+    let computed_properties = image.properties.computed_properties();
+    assert_eq!(computed_properties.tiling, Tiling::Linear);
+    assert!(subresource.mipLevel < image.properties.mip_levels);
+    assert!(subresource.arrayLayer < image.properties.array_layers);
+    let mip_level_layout = &computed_properties.mip_level_layouts[subresource.mipLevel as usize];
+    *layout = api::VkSubresourceLayout {
+        offset: (computed_properties.array_pitch * subresource.arrayLayer as usize
+            + mip_level_layout.offset) as u64,
+        size: mip_level_layout.size as u64,
+        rowPitch: mip_level_layout.row_pitch as u64,
+        arrayPitch: computed_properties.array_pitch as u64,
+        depthPitch: mip_level_layout.depth_pitch as u64,
+    };
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateImageView(
-    _device: api::VkDevice,
-    _pCreateInfo: *const api::VkImageViewCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pView: *mut api::VkImageView,
+    device: api::VkDevice,
+    create_info: *const api::VkImageViewCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    view: *mut api::VkImageView,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        create_info,
+        root = api::VK_STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
+    }
+    let create_info = &*create_info;
+    let device = SharedHandle::from(device).unwrap();
+    let image = SharedHandle::from(create_info.image).unwrap();
+    let mut image_view = ImageView::new(image, create_info);
+    let features = &device.features;
+    image_view.robustness = if features.robustness_2_features.robustImageAccess2 != api::VK_FALSE {
+        ImageRobustness::RobustImageAccess2
+    } else if features.image_robustness_features.robustImageAccess != api::VK_FALSE {
+        ImageRobustness::RobustImageAccess
+    } else {
+        ImageRobustness::Disabled
+    };
+    *view = OwnedHandle::<api::VkImageView>::new(image_view).take();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyImageView(
     _device: api::VkDevice,
-    _imageView: api::VkImageView,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    image_view: api::VkImageView,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    OwnedHandle::from(image_view);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateShaderModule(
-    _device: api::VkDevice,
+    device: api::VkDevice,
     create_info: *const api::VkShaderModuleCreateInfo,
     _allocator: *const api::VkAllocationCallbacks,
     shader_module: *mut api::VkShaderModule,
@@ -4175,9 +5062,17 @@ pub unsafe extern "system" fn vkCreateShaderModule(
     assert_eq!(create_info.codeSize % U32_BYTE_COUNT, 0);
     assert_ne!(create_info.codeSize, 0);
     let code = slice::from_raw_parts(create_info.pCode, create_info.codeSize / U32_BYTE_COUNT);
-    *shader_module = OwnedHandle::<api::VkShaderModule>::new(ShaderModule {
-        code: code.to_owned(),
-    })
+    let device = SharedHandle::from(device).unwrap();
+    let watchdog_checks = device
+        .physical_device
+        .configuration
+        .watchdog
+        .needs_shader_checks();
+    *shader_module = OwnedHandle::<api::VkShaderModule>::new(ShaderModule::new(
+        code.to_owned(),
+        &device.compile_thread_pool,
+        watchdog_checks,
+    ))
     .take();
     api::VK_SUCCESS
 }
@@ -4230,56 +5125,197 @@ pub unsafe extern "system" fn vkMergePipelineCaches(
     unimplemented!()
 }
 
+/// lets pipeline create infos be read from the compile threads;
+/// the application can't modify them until the create function returns
+struct CreateInfoRef<'a, T: 'a>(&'a T);
+
+unsafe impl<'a, T: 'a> Send for CreateInfoRef<'a, T> {}
+
+/// store the pipelines created by `vkCreate*Pipelines`; `flags` are the flags they were created
+/// with. the pipelines that couldn't be created are `VK_NULL_HANDLE`, and the result is the error
+/// of the first of them. the pipelines after one that couldn't be created with
+/// `VK_PIPELINE_CREATE_EARLY_RETURN_ON_FAILURE_BIT_EXT` are destroyed, since they would not have
+/// been created if the pipelines were created one at a time
+unsafe fn store_created_pipelines<I: IntoIterator<Item = api::VkPipelineCreateFlags>>(
+    device: &Device,
+    created_pipelines: Vec<thread::Result<Result<Pipeline, PipelineError>>>,
+    flags: I,
+    pipelines: &mut [api::VkPipeline],
+) -> api::VkResult {
+    let mut result = api::VK_SUCCESS;
+    let mut returned_early = false;
+    for ((created_pipeline, flags), pipeline) in created_pipelines
+        .into_iter()
+        .zip(flags)
+        .zip(pipelines.iter_mut())
+    {
+        let created_pipeline = created_pipeline.unwrap_or_else(|payload| {
+            Err(PipelineError::Panicked(
+                command_buffer::get_panic_message(&*payload).into(),
+            ))
+        });
+        match created_pipeline {
+            Ok(_) if returned_early => *pipeline = Handle::null(),
+            Ok(created_pipeline) => {
+                *pipeline = OwnedHandle::<api::VkPipeline>::new(created_pipeline).take();
+                if let Some(ref validation) = device.validation {
+                    validation.add_object(validation::pipeline_object(*pipeline));
+                }
+            }
+            Err(error) => {
+                match error {
+                    // the application asked to fail instead of compiling
+                    PipelineError::CompileRequired => {}
+                    _ => eprintln!("kazan: can't create pipeline: {}", error),
+                }
+                *pipeline = Handle::null();
+                if result == api::VK_SUCCESS {
+                    result = error.vk_result();
+                }
+                returned_early |=
+                    flags & api::VK_PIPELINE_CREATE_EARLY_RETURN_ON_FAILURE_BIT_EXT != 0;
+            }
+        }
+    }
+    result
+}
+
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateGraphicsPipelines(
-    _device: api::VkDevice,
-    _pipelineCache: api::VkPipelineCache,
-    _createInfoCount: u32,
-    _pCreateInfos: *const api::VkGraphicsPipelineCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pPipelines: *mut api::VkPipeline,
+    device: api::VkDevice,
+    _pipeline_cache: api::VkPipelineCache,
+    create_info_count: u32,
+    create_infos: *const api::VkGraphicsPipelineCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    pipelines: *mut api::VkPipeline,
 ) -> api::VkResult {
-    unimplemented!()
+    assert_ne!(create_info_count, 0);
+    let create_infos = slice::from_raw_parts(create_infos, create_info_count as usize);
+    let pipelines = slice::from_raw_parts_mut(pipelines, create_info_count as usize);
+    for create_info in create_infos {
+        parse_next_chain_const!{
+            create_info as *const api::VkGraphicsPipelineCreateInfo,
+            root = api::VK_STRUCTURE_TYPE_GRAPHICS_PIPELINE_CREATE_INFO,
+        }
+    }
+    let device = SharedHandle::from(device).unwrap();
+    let dither_alpha_to_coverage = device
+        .physical_device
+        .configuration
+        .dither_alpha_to_coverage;
+    let watchdog_checks = device
+        .physical_device
+        .configuration
+        .watchdog
+        .needs_shader_checks();
+    let created_pipelines = device.compile_thread_pool.run_all(
+        CompilePriority::Blocking,
+        create_infos.iter().map(|create_info| {
+            let create_info = CreateInfoRef(create_info);
+            move || -> Result<_, PipelineError> {
+                let pipeline = Pipeline::Graphics(GraphicsPipeline::new(
+                    create_info.0,
+                    dither_alpha_to_coverage,
+                    watchdog_checks,
+                )?);
+                dump_pipeline_report(&pipeline);
+                Ok(pipeline)
+            }
+        }),
+    );
+    store_created_pipelines(
+        &device,
+        created_pipelines,
+        create_infos.iter().map(|v| v.flags),
+        pipelines,
+    )
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateComputePipelines(
-    _device: api::VkDevice,
-    _pipelineCache: api::VkPipelineCache,
-    _createInfoCount: u32,
-    _pCreateInfos: *const api::VkComputePipelineCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pPipelines: *mut api::VkPipeline,
+    device: api::VkDevice,
+    _pipeline_cache: api::VkPipelineCache,
+    create_info_count: u32,
+    create_infos: *const api::VkComputePipelineCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    pipelines: *mut api::VkPipeline,
 ) -> api::VkResult {
-    unimplemented!()
+    assert_ne!(create_info_count, 0);
+    let create_infos = slice::from_raw_parts(create_infos, create_info_count as usize);
+    let pipelines = slice::from_raw_parts_mut(pipelines, create_info_count as usize);
+    for create_info in create_infos {
+        parse_next_chain_const!{
+            create_info as *const api::VkComputePipelineCreateInfo,
+            root = api::VK_STRUCTURE_TYPE_COMPUTE_PIPELINE_CREATE_INFO,
+        }
+    }
+    let device = SharedHandle::from(device).unwrap();
+    let watchdog_checks = device
+        .physical_device
+        .configuration
+        .watchdog
+        .needs_shader_checks();
+    let created_pipelines = device.compile_thread_pool.run_all(
+        CompilePriority::Blocking,
+        create_infos.iter().map(|create_info| {
+            let create_info = CreateInfoRef(create_info);
+            move || -> Result<_, PipelineError> {
+                let pipeline =
+                    Pipeline::Compute(ComputePipeline::new(create_info.0, watchdog_checks)?);
+                dump_pipeline_report(&pipeline);
+                Ok(pipeline)
+            }
+        }),
+    );
+    store_created_pipelines(
+        &device,
+        created_pipelines,
+        create_infos.iter().map(|v| v.flags),
+        pipelines,
+    )
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyPipeline(
-    _device: api::VkDevice,
-    _pipeline: api::VkPipeline,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    device: api::VkDevice,
+    pipeline: api::VkPipeline,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    if let Some(ref validation) = SharedHandle::from(device).unwrap().validation {
+        validation.remove_object(validation::pipeline_object(pipeline));
+    }
+    OwnedHandle::from(pipeline);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreatePipelineLayout(
-    _device: api::VkDevice,
-    _pCreateInfo: *const api::VkPipelineLayoutCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pPipelineLayout: *mut api::VkPipelineLayout,
+    device: api::VkDevice,
+    create_info: *const api::VkPipelineLayoutCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    pipeline_layout: *mut api::VkPipelineLayout,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        create_info,
+        root = api::VK_STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
+    }
+    *pipeline_layout =
+        OwnedHandle::<api::VkPipelineLayout>::new(PipelineLayout::new(&*create_info)).take();
+    if let Some(ref validation) = SharedHandle::from(device).unwrap().validation {
+        validation.add_object(validation::pipeline_layout_object(*pipeline_layout));
+    }
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyPipelineLayout(
-    _device: api::VkDevice,
-    _pipelineLayout: api::VkPipelineLayout,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    device: api::VkDevice,
+    pipeline_layout: api::VkPipelineLayout,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    if let Some(ref validation) = SharedHandle::from(device).unwrap().validation {
+        validation.remove_object(validation::pipeline_layout_object(pipeline_layout));
+    }
+    OwnedHandle::from(pipeline_layout);
 }
 
 #[allow(non_snake_case)]
@@ -4294,6 +5330,16 @@ pub unsafe extern "system" fn vkCreateSampler(
         root = api::VK_STRUCTURE_TYPE_SAMPLER_CREATE_INFO,
     }
     let create_info = &*create_info;
+    // image sampling runs in the runtime functions called by shaders, where it can't fail, so
+    // samplers it can't do are rejected here. samplerAnisotropy isn't advertised
+    if create_info.compareEnable != api::VK_FALSE {
+        eprintln!("kazan: can't create sampler: depth comparison isn't implemented");
+        return api::VK_ERROR_FEATURE_NOT_PRESENT;
+    }
+    if create_info.anisotropyEnable != api::VK_FALSE {
+        eprintln!("kazan: can't create sampler: anisotropic filtering isn't supported");
+        return api::VK_ERROR_FEATURE_NOT_PRESENT;
+    }
     *sampler = OwnedHandle::<api::VkSampler>::new(Sampler {
         mag_filter: create_info.magFilter,
         min_filter: create_info.minFilter,
@@ -4338,214 +5384,365 @@ pub unsafe extern "system" fn vkDestroySampler(
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateDescriptorSetLayout(
     _device: api::VkDevice,
-    _pCreateInfo: *const api::VkDescriptorSetLayoutCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pSetLayout: *mut api::VkDescriptorSetLayout,
+    create_info: *const api::VkDescriptorSetLayoutCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    set_layout: *mut api::VkDescriptorSetLayout,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        create_info,
+        root = api::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+    }
+    match DescriptorSetLayout::new(&*create_info) {
+        Ok(layout) => {
+            *set_layout = OwnedHandle::<api::VkDescriptorSetLayout>::new(layout).take();
+            api::VK_SUCCESS
+        }
+        Err(error) => error,
+    }
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyDescriptorSetLayout(
     _device: api::VkDevice,
-    _descriptorSetLayout: api::VkDescriptorSetLayout,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    descriptor_set_layout: api::VkDescriptorSetLayout,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    OwnedHandle::from(descriptor_set_layout);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateDescriptorPool(
     _device: api::VkDevice,
-    _pCreateInfo: *const api::VkDescriptorPoolCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pDescriptorPool: *mut api::VkDescriptorPool,
+    create_info: *const api::VkDescriptorPoolCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    descriptor_pool: *mut api::VkDescriptorPool,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        create_info,
+        root = api::VK_STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+    }
+    *descriptor_pool =
+        OwnedHandle::<api::VkDescriptorPool>::new(DescriptorPool::new(&*create_info)).take();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyDescriptorPool(
-    _device: api::VkDevice,
-    _descriptorPool: api::VkDescriptorPool,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    device: api::VkDevice,
+    descriptor_pool: api::VkDescriptorPool,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    if let Some(descriptor_pool) = OwnedHandle::from(descriptor_pool) {
+        if let Some(ref validation) = SharedHandle::from(device).unwrap().validation {
+            for descriptor_set in descriptor_pool.descriptor_sets() {
+                validation.remove_object(validation::descriptor_set_object(descriptor_set));
+            }
+        }
+    }
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkResetDescriptorPool(
-    _device: api::VkDevice,
-    _descriptorPool: api::VkDescriptorPool,
+    device: api::VkDevice,
+    descriptor_pool: api::VkDescriptorPool,
     _flags: api::VkDescriptorPoolResetFlags,
 ) -> api::VkResult {
-    unimplemented!()
+    let mut descriptor_pool = MutHandle::from(descriptor_pool).unwrap();
+    if let Some(ref validation) = SharedHandle::from(device).unwrap().validation {
+        for descriptor_set in descriptor_pool.descriptor_sets() {
+            validation.remove_object(validation::descriptor_set_object(descriptor_set));
+        }
+    }
+    descriptor_pool.reset();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkAllocateDescriptorSets(
-    _device: api::VkDevice,
-    _pAllocateInfo: *const api::VkDescriptorSetAllocateInfo,
-    _pDescriptorSets: *mut api::VkDescriptorSet,
+    device: api::VkDevice,
+    allocate_info: *const api::VkDescriptorSetAllocateInfo,
+    descriptor_sets: *mut api::VkDescriptorSet,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        allocate_info,
+        root = api::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+    }
+    let allocate_info = &*allocate_info;
+    let mut descriptor_pool = MutHandle::from(allocate_info.descriptorPool).unwrap();
+    let set_layouts = slice::from_raw_parts(
+        allocate_info.pSetLayouts,
+        allocate_info.descriptorSetCount as usize,
+    );
+    let descriptor_sets =
+        slice::from_raw_parts_mut(descriptor_sets, allocate_info.descriptorSetCount as usize);
+    for index in 0..descriptor_sets.len() {
+        match descriptor_pool.allocate(&SharedHandle::from(set_layouts[index]).unwrap()) {
+            Ok(descriptor_set) => descriptor_sets[index] = descriptor_set,
+            Err(result) => {
+                for descriptor_set in &mut descriptor_sets[..index] {
+                    descriptor_pool.free(*descriptor_set);
+                }
+                for descriptor_set in descriptor_sets.iter_mut() {
+                    *descriptor_set = Handle::null();
+                }
+                return result;
+            }
+        }
+    }
+    if let Some(ref validation) = SharedHandle::from(device).unwrap().validation {
+        for &descriptor_set in descriptor_sets.iter() {
+            validation.add_object(validation::descriptor_set_object(descriptor_set));
+        }
+    }
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkFreeDescriptorSets(
-    _device: api::VkDevice,
-    _descriptorPool: api::VkDescriptorPool,
-    _descriptorSetCount: u32,
-    _pDescriptorSets: *const api::VkDescriptorSet,
+    device: api::VkDevice,
+    descriptor_pool: api::VkDescriptorPool,
+    descriptor_set_count: u32,
+    descriptor_sets: *const api::VkDescriptorSet,
 ) -> api::VkResult {
-    unimplemented!()
+    let device = SharedHandle::from(device).unwrap();
+    let mut descriptor_pool = MutHandle::from(descriptor_pool).unwrap();
+    for &descriptor_set in slice::from_raw_parts(descriptor_sets, descriptor_set_count as usize) {
+        if let Some(ref validation) = device.validation {
+            validation.remove_object(validation::descriptor_set_object(descriptor_set));
+        }
+        descriptor_pool.free(descriptor_set);
+    }
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkUpdateDescriptorSets(
     _device: api::VkDevice,
-    _descriptorWriteCount: u32,
-    _pDescriptorWrites: *const api::VkWriteDescriptorSet,
-    _descriptorCopyCount: u32,
-    _pDescriptorCopies: *const api::VkCopyDescriptorSet,
-) {
-    unimplemented!()
+    descriptor_write_count: u32,
+    descriptor_writes: *const api::VkWriteDescriptorSet,
+    descriptor_copy_count: u32,
+    descriptor_copies: *const api::VkCopyDescriptorSet,
+) {
+    if descriptor_write_count != 0 {
+        for descriptor_write in
+            slice::from_raw_parts(descriptor_writes, descriptor_write_count as usize)
+        {
+            MutHandle::from(descriptor_write.dstSet)
+                .unwrap()
+                .write(descriptor_write);
+        }
+    }
+    if descriptor_copy_count != 0 {
+        for descriptor_copy in
+            slice::from_raw_parts(descriptor_copies, descriptor_copy_count as usize)
+        {
+            let mut destination = MutHandle::from(descriptor_copy.dstSet).unwrap();
+            if descriptor_copy.srcSet == descriptor_copy.dstSet {
+                destination.copy(None, descriptor_copy);
+            } else {
+                let source = SharedHandle::from(descriptor_copy.srcSet).unwrap();
+                destination.copy(Some(&*source), descriptor_copy);
+            }
+        }
+    }
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateFramebuffer(
     _device: api::VkDevice,
-    _pCreateInfo: *const api::VkFramebufferCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pFramebuffer: *mut api::VkFramebuffer,
+    create_info: *const api::VkFramebufferCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    framebuffer: *mut api::VkFramebuffer,
 ) -> api::VkResult {
-    unimplemented!()
+    *framebuffer = OwnedHandle::<api::VkFramebuffer>::new(Framebuffer::new(&*create_info)).take();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyFramebuffer(
     _device: api::VkDevice,
-    _framebuffer: api::VkFramebuffer,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    framebuffer: api::VkFramebuffer,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    OwnedHandle::from(framebuffer);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateRenderPass(
     _device: api::VkDevice,
-    _pCreateInfo: *const api::VkRenderPassCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pRenderPass: *mut api::VkRenderPass,
+    create_info: *const api::VkRenderPassCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    render_pass: *mut api::VkRenderPass,
 ) -> api::VkResult {
-    unimplemented!()
+    *render_pass = OwnedHandle::<api::VkRenderPass>::new(RenderPass::new(&*create_info)).take();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyRenderPass(
     _device: api::VkDevice,
-    _renderPass: api::VkRenderPass,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    render_pass: api::VkRenderPass,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    OwnedHandle::from(render_pass);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetRenderAreaGranularity(
     _device: api::VkDevice,
-    _renderPass: api::VkRenderPass,
-    _pGranularity: *mut api::VkExtent2D,
+    _render_pass: api::VkRenderPass,
+    granularity: *mut api::VkExtent2D,
 ) {
-    unimplemented!()
+    // render areas are clipped per pixel, so any render area is as fast as any other
+    *granularity = api::VkExtent2D {
+        width: 1,
+        height: 1,
+    };
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateCommandPool(
-    _device: api::VkDevice,
-    _pCreateInfo: *const api::VkCommandPoolCreateInfo,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pCommandPool: *mut api::VkCommandPool,
+    device: api::VkDevice,
+    create_info: *const api::VkCommandPoolCreateInfo,
+    _allocator: *const api::VkAllocationCallbacks,
+    command_pool: *mut api::VkCommandPool,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        create_info,
+        root = api::VK_STRUCTURE_TYPE_COMMAND_POOL_CREATE_INFO,
+    }
+    assert!((*create_info).queueFamilyIndex < QUEUE_FAMILY_COUNT);
+    let device = SharedHandle::from(device).unwrap();
+    *command_pool =
+        OwnedHandle::<api::VkCommandPool>::new(CommandPool::new(device.validation.clone())).take();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroyCommandPool(
     _device: api::VkDevice,
-    _commandPool: api::VkCommandPool,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    command_pool: api::VkCommandPool,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    OwnedHandle::from(command_pool);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkResetCommandPool(
     _device: api::VkDevice,
-    _commandPool: api::VkCommandPool,
+    command_pool: api::VkCommandPool,
     _flags: api::VkCommandPoolResetFlags,
 ) -> api::VkResult {
-    unimplemented!()
+    MutHandle::from(command_pool).unwrap().reset();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkAllocateCommandBuffers(
     _device: api::VkDevice,
-    _pAllocateInfo: *const api::VkCommandBufferAllocateInfo,
-    _pCommandBuffers: *mut api::VkCommandBuffer,
+    allocate_info: *const api::VkCommandBufferAllocateInfo,
+    command_buffers: *mut api::VkCommandBuffer,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        allocate_info,
+        root = api::VK_STRUCTURE_TYPE_COMMAND_BUFFER_ALLOCATE_INFO,
+    }
+    let allocate_info = &*allocate_info;
+    let mut command_pool = MutHandle::from(allocate_info.commandPool).unwrap();
+    for command_buffer in slice::from_raw_parts_mut(
+        command_buffers,
+        allocate_info.commandBufferCount as usize,
+    ) {
+        *command_buffer = command_pool.allocate();
+    }
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkFreeCommandBuffers(
     _device: api::VkDevice,
-    _commandPool: api::VkCommandPool,
-    _commandBufferCount: u32,
-    _pCommandBuffers: *const api::VkCommandBuffer,
+    command_pool: api::VkCommandPool,
+    command_buffer_count: u32,
+    command_buffers: *const api::VkCommandBuffer,
 ) {
-    unimplemented!()
+    let mut command_pool = MutHandle::from(command_pool).unwrap();
+    for &command_buffer in slice::from_raw_parts(command_buffers, command_buffer_count as usize) {
+        command_pool.free(command_buffer);
+    }
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkBeginCommandBuffer(
-    _commandBuffer: api::VkCommandBuffer,
-    _pBeginInfo: *const api::VkCommandBufferBeginInfo,
+    command_buffer: api::VkCommandBuffer,
+    begin_info: *const api::VkCommandBufferBeginInfo,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        begin_info,
+        root = api::VK_STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
+    }
+    MutHandle::from(command_buffer)
+        .unwrap()
+        .begin(command_buffer);
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkEndCommandBuffer(
-    _commandBuffer: api::VkCommandBuffer,
+    command_buffer: api::VkCommandBuffer,
 ) -> api::VkResult {
-    unimplemented!()
+    MutHandle::from(command_buffer).unwrap().end(command_buffer);
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkResetCommandBuffer(
-    _commandBuffer: api::VkCommandBuffer,
+    command_buffer: api::VkCommandBuffer,
     _flags: api::VkCommandBufferResetFlags,
 ) -> api::VkResult {
-    unimplemented!()
+    MutHandle::from(command_buffer).unwrap().reset();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdBindPipeline(
-    _commandBuffer: api::VkCommandBuffer,
-    _pipelineBindPoint: api::VkPipelineBindPoint,
-    _pipeline: api::VkPipeline,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    pipeline_bind_point: api::VkPipelineBindPoint,
+    pipeline: api::VkPipeline,
+) {
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    let pipeline_object = validation::pipeline_object(pipeline);
+    let pipeline = SharedHandle::from(pipeline).unwrap();
+    if let Some(validation) =
+        buffer.validate_command(command_buffer, "vkCmdBindPipeline", &[pipeline_object])
+    {
+        validation.check_pipeline_bind_point(
+            command_buffer,
+            pipeline_bind_point,
+            pipeline.get_handle(),
+            pipeline.get_bind_point(),
+        );
+    }
+    buffer
+        .commands
+        .bind_pipeline(pipeline_bind_point, pipeline);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdSetViewport(
-    _commandBuffer: api::VkCommandBuffer,
-    _firstViewport: u32,
-    _viewportCount: u32,
-    _pViewports: *const api::VkViewport,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    first_viewport: u32,
+    viewport_count: u32,
+    viewports: *const api::VkViewport,
+) {
+    assert_ne!(viewport_count, 0);
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    buffer.validate_command(command_buffer, "vkCmdSetViewport", &[]);
+    buffer.commands.set_viewport(
+        first_viewport,
+        slice::from_raw_parts(viewports, viewport_count as usize)
+            .iter()
+            .map(Viewport::new),
+    );
 }
 
 #[allow(non_snake_case)]
@@ -4568,12 +5765,18 @@ pub unsafe extern "system" fn vkCmdSetLineWidth(
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdSetDepthBias(
-    _commandBuffer: api::VkCommandBuffer,
-    _depthBiasConstantFactor: f32,
-    _depthBiasClamp: f32,
-    _depthBiasSlopeFactor: f32,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    depth_bias_constant_factor: f32,
+    depth_bias_clamp: f32,
+    depth_bias_slope_factor: f32,
+) {
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    buffer.validate_command(command_buffer, "vkCmdSetDepthBias", &[]);
+    buffer.commands.set_depth_bias(DepthBias::new(
+        depth_bias_constant_factor,
+        depth_bias_clamp,
+        depth_bias_slope_factor,
+    ));
 }
 
 #[allow(non_snake_case)]
@@ -4622,16 +5825,60 @@ pub unsafe extern "system" fn vkCmdSetStencilReference(
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdBindDescriptorSets(
-    _commandBuffer: api::VkCommandBuffer,
-    _pipelineBindPoint: api::VkPipelineBindPoint,
-    _layout: api::VkPipelineLayout,
-    _firstSet: u32,
-    _descriptorSetCount: u32,
-    _pDescriptorSets: *const api::VkDescriptorSet,
-    _dynamicOffsetCount: u32,
-    _pDynamicOffsets: *const u32,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    pipeline_bind_point: api::VkPipelineBindPoint,
+    layout: api::VkPipelineLayout,
+    first_set: u32,
+    descriptor_set_count: u32,
+    descriptor_sets: *const api::VkDescriptorSet,
+    dynamic_offset_count: u32,
+    dynamic_offsets: *const u32,
+) {
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    let buffer = &mut *buffer;
+    let descriptor_sets = slice::from_raw_parts(descriptor_sets, descriptor_set_count as usize);
+    let objects = buffer.scratch.alloc_slice_from_iter(
+        descriptor_sets.len() + 1,
+        iter::once(validation::pipeline_layout_object(layout)).chain(
+            descriptor_sets
+                .iter()
+                .map(|&descriptor_set| validation::descriptor_set_object(descriptor_set)),
+        ),
+    );
+    if let Some(validation) =
+        buffer.validate_command(command_buffer, "vkCmdBindDescriptorSets", objects)
+    {
+        let descriptor_sets = buffer.scratch.alloc_slice_from_iter(
+            descriptor_sets.len(),
+            descriptor_sets
+                .iter()
+                .map(|&descriptor_set| SharedHandle::from(descriptor_set).unwrap()),
+        );
+        validation.check_bind_descriptor_sets(
+            command_buffer,
+            &SharedHandle::from(layout).unwrap(),
+            first_set,
+            descriptor_sets,
+            dynamic_offset_count as usize,
+        );
+    }
+    let mut dynamic_offsets: &[u32] = if dynamic_offset_count == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(dynamic_offsets, dynamic_offset_count as usize)
+    };
+    let descriptor_sets = descriptor_sets.iter().map(|&descriptor_set| {
+        let descriptor_set = SharedHandle::from(descriptor_set).unwrap();
+        // dynamic offsets are consumed in descriptor set order
+        let (set_dynamic_offsets, rest) =
+            dynamic_offsets.split_at(descriptor_set.dynamic_descriptor_indices().count());
+        dynamic_offsets = rest;
+        (descriptor_set, set_dynamic_offsets)
+    });
+    buffer
+        .commands
+        .bind_descriptor_sets(pipeline_bind_point, first_set, descriptor_sets);
+    assert!(dynamic_offsets.is_empty());
 }
 
 #[allow(non_snake_case)]
@@ -4657,25 +5904,44 @@ pub unsafe extern "system" fn vkCmdBindVertexBuffers(
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdDraw(
-    _commandBuffer: api::VkCommandBuffer,
-    _vertexCount: u32,
-    _instanceCount: u32,
-    _firstVertex: u32,
-    _firstInstance: u32,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    vertex_count: u32,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+) {
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some((validation, state)) = buffer.validate_command_mut(command_buffer, "vkCmdDraw", &[])
+    {
+        validation.check_inside_render_pass(command_buffer, state, "vkCmdDraw");
+    }
+    buffer
+        .commands
+        .draw(vertex_count, instance_count, first_vertex, first_instance);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdDrawIndexed(
-    _commandBuffer: api::VkCommandBuffer,
-    _indexCount: u32,
-    _instanceCount: u32,
-    _firstIndex: u32,
-    _vertexOffset: i32,
-    _firstInstance: u32,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+    first_instance: u32,
+) {
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some((validation, state)) =
+        buffer.validate_command_mut(command_buffer, "vkCmdDrawIndexed", &[])
+    {
+        validation.check_inside_render_pass(command_buffer, state, "vkCmdDrawIndexed");
+    }
+    buffer.commands.draw_indexed(
+        index_count,
+        instance_count,
+        first_index,
+        vertex_offset,
+        first_instance,
+    );
 }
 
 #[allow(non_snake_case)]
@@ -4702,12 +5968,20 @@ pub unsafe extern "system" fn vkCmdDrawIndexedIndirect(
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdDispatch(
-    _commandBuffer: api::VkCommandBuffer,
-    _groupCountX: u32,
-    _groupCountY: u32,
-    _groupCountZ: u32,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    group_count_x: u32,
+    group_count_y: u32,
+    group_count_z: u32,
+) {
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some((validation, state)) =
+        buffer.validate_command_mut(command_buffer, "vkCmdDispatch", &[])
+    {
+        validation.check_outside_render_pass(command_buffer, state, "vkCmdDispatch");
+    }
+    buffer
+        .commands
+        .dispatch([group_count_x, group_count_y, group_count_z]);
 }
 
 #[allow(non_snake_case)]
@@ -4888,105 +6162,225 @@ pub unsafe extern "system" fn vkCmdWaitEvents(
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdPipelineBarrier(
-    _commandBuffer: api::VkCommandBuffer,
-    _srcStageMask: api::VkPipelineStageFlags,
-    _dstStageMask: api::VkPipelineStageFlags,
-    _dependencyFlags: api::VkDependencyFlags,
-    _memoryBarrierCount: u32,
-    _pMemoryBarriers: *const api::VkMemoryBarrier,
-    _bufferMemoryBarrierCount: u32,
-    _pBufferMemoryBarriers: *const api::VkBufferMemoryBarrier,
-    _imageMemoryBarrierCount: u32,
-    _pImageMemoryBarriers: *const api::VkImageMemoryBarrier,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    _src_stage_mask: api::VkPipelineStageFlags,
+    _dst_stage_mask: api::VkPipelineStageFlags,
+    _dependency_flags: api::VkDependencyFlags,
+    _memory_barrier_count: u32,
+    _memory_barriers: *const api::VkMemoryBarrier,
+    _buffer_memory_barrier_count: u32,
+    _buffer_memory_barriers: *const api::VkBufferMemoryBarrier,
+    image_memory_barrier_count: u32,
+    image_memory_barriers: *const api::VkImageMemoryBarrier,
+) {
+    // each command finishes before the next one starts, and images are stored the same way in
+    // every layout, so barriers only have to order the command buffers of a submission. layout
+    // transitions are only validated
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some((validation, state)) =
+        buffer.validate_command_mut(command_buffer, "vkCmdPipelineBarrier", &[])
+    {
+        let image_memory_barriers: &[_] = if image_memory_barrier_count == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(image_memory_barriers, image_memory_barrier_count as usize)
+        };
+        for barrier in image_memory_barriers {
+            validation.transition_image_layout(
+                command_buffer,
+                state,
+                "vkCmdPipelineBarrier",
+                SharedHandle::from(barrier.image).unwrap(),
+                &barrier.subresourceRange,
+                barrier.oldLayout,
+                barrier.newLayout,
+            );
+        }
+    }
+    buffer.commands.pipeline_barrier();
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdBeginQuery(
-    _commandBuffer: api::VkCommandBuffer,
-    _queryPool: api::VkQueryPool,
-    _query: u32,
+    command_buffer: api::VkCommandBuffer,
+    query_pool: api::VkQueryPool,
+    query: u32,
     _flags: api::VkQueryControlFlags,
 ) {
-    unimplemented!()
+    // occlusion queries are always exact, so `VK_QUERY_CONTROL_PRECISE_BIT` can be ignored
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    buffer.validate_command(command_buffer, "vkCmdBeginQuery", &[]);
+    let query_pool = SharedHandle::from(query_pool).unwrap();
+    buffer.commands.begin_query(query_pool, query);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdEndQuery(
-    _commandBuffer: api::VkCommandBuffer,
-    _queryPool: api::VkQueryPool,
-    _query: u32,
+    command_buffer: api::VkCommandBuffer,
+    query_pool: api::VkQueryPool,
+    query: u32,
 ) {
-    unimplemented!()
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    buffer.validate_command(command_buffer, "vkCmdEndQuery", &[]);
+    let query_pool = SharedHandle::from(query_pool).unwrap();
+    buffer.commands.end_query(query_pool, query);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdResetQueryPool(
-    _commandBuffer: api::VkCommandBuffer,
-    _queryPool: api::VkQueryPool,
-    _firstQuery: u32,
-    _queryCount: u32,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    query_pool: api::VkQueryPool,
+    first_query: u32,
+    query_count: u32,
+) {
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some((validation, state)) =
+        buffer.validate_command_mut(command_buffer, "vkCmdResetQueryPool", &[])
+    {
+        validation.check_outside_render_pass(command_buffer, state, "vkCmdResetQueryPool");
+    }
+    let query_pool = SharedHandle::from(query_pool).unwrap();
+    buffer
+        .commands
+        .reset_query_pool(query_pool, first_query, query_count);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdWriteTimestamp(
-    _commandBuffer: api::VkCommandBuffer,
-    _pipelineStage: api::VkPipelineStageFlagBits,
-    _queryPool: api::VkQueryPool,
-    _query: u32,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    pipeline_stage: api::VkPipelineStageFlagBits,
+    query_pool: api::VkQueryPool,
+    query: u32,
+) {
+    // every stage of the earlier commands is done by the time the timestamp is written
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some(validation) = buffer.validate_command(command_buffer, "vkCmdWriteTimestamp", &[])
+    {
+        validation.check_timestamp_stage(command_buffer, pipeline_stage);
+    }
+    let query_pool = SharedHandle::from(query_pool).unwrap();
+    buffer.commands.write_timestamp(query_pool, query);
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdCopyQueryPoolResults(
-    _commandBuffer: api::VkCommandBuffer,
-    _queryPool: api::VkQueryPool,
-    _firstQuery: u32,
-    _queryCount: u32,
-    _dstBuffer: api::VkBuffer,
-    _dstOffset: api::VkDeviceSize,
-    _stride: api::VkDeviceSize,
-    _flags: api::VkQueryResultFlags,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    query_pool: api::VkQueryPool,
+    first_query: u32,
+    query_count: u32,
+    dst_buffer: api::VkBuffer,
+    dst_offset: api::VkDeviceSize,
+    stride: api::VkDeviceSize,
+    flags: api::VkQueryResultFlags,
+) {
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some((validation, state)) =
+        buffer.validate_command_mut(command_buffer, "vkCmdCopyQueryPoolResults", &[])
+    {
+        validation.check_outside_render_pass(command_buffer, state, "vkCmdCopyQueryPoolResults");
+    }
+    let query_pool = SharedHandle::from(query_pool).unwrap();
+    let dst_buffer = SharedHandle::from(dst_buffer).unwrap();
+    let result_size = if flags & api::VK_QUERY_RESULT_64_BIT != 0 {
+        8
+    } else {
+        4
+    };
+    if query_count != 0 {
+        assert!(
+            dst_offset + u64::from(query_count - 1) * stride + result_size
+                <= dst_buffer.size as u64
+        );
+    }
+    buffer.commands.copy_query_pool_results(
+        query_pool,
+        first_query,
+        query_count,
+        dst_buffer,
+        dst_offset,
+        stride,
+        flags,
+    );
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdPushConstants(
-    _commandBuffer: api::VkCommandBuffer,
-    _layout: api::VkPipelineLayout,
-    _stageFlags: api::VkShaderStageFlags,
-    _offset: u32,
-    _size: u32,
-    _pValues: *const c_void,
-) {
-    unimplemented!()
+    command_buffer: api::VkCommandBuffer,
+    layout: api::VkPipelineLayout,
+    stage_flags: api::VkShaderStageFlags,
+    offset: u32,
+    size: u32,
+    values: *const c_void,
+) {
+    assert_ne!(size, 0);
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some(validation) = buffer.validate_command(
+        command_buffer,
+        "vkCmdPushConstants",
+        &[validation::pipeline_layout_object(layout)],
+    ) {
+        validation.check_push_constants(
+            command_buffer,
+            &SharedHandle::from(layout).unwrap(),
+            stage_flags,
+            offset,
+            size,
+        );
+    }
+    buffer.commands.push_constants(
+        offset,
+        slice::from_raw_parts(values as *const u8, size as usize),
+    );
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdBeginRenderPass(
-    _commandBuffer: api::VkCommandBuffer,
-    _pRenderPassBegin: *const api::VkRenderPassBeginInfo,
+    command_buffer: api::VkCommandBuffer,
+    render_pass_begin: *const api::VkRenderPassBeginInfo,
     _contents: api::VkSubpassContents,
 ) {
-    unimplemented!()
+    // nothing draws into render passes yet, so they aren't recorded; only validation keeps
+    // track of them
+    parse_next_chain_const!{
+        render_pass_begin,
+        root = api::VK_STRUCTURE_TYPE_RENDER_PASS_BEGIN_INFO,
+        _device_group_render_pass_begin_info: api::VkDeviceGroupRenderPassBeginInfo = api::VK_STRUCTURE_TYPE_DEVICE_GROUP_RENDER_PASS_BEGIN_INFO,
+    }
+    let render_pass_begin = &*render_pass_begin;
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some((validation, state)) =
+        buffer.validate_command_mut(command_buffer, "vkCmdBeginRenderPass", &[])
+    {
+        validation.begin_render_pass(
+            command_buffer,
+            state,
+            SharedHandle::from(render_pass_begin.renderPass).unwrap(),
+            SharedHandle::from(render_pass_begin.framebuffer).unwrap(),
+        );
+    }
 }
 
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCmdNextSubpass(
-    _commandBuffer: api::VkCommandBuffer,
+    command_buffer: api::VkCommandBuffer,
     _contents: api::VkSubpassContents,
 ) {
-    unimplemented!()
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some((validation, state)) =
+        buffer.validate_command_mut(command_buffer, "vkCmdNextSubpass", &[])
+    {
+        validation.next_subpass(command_buffer, state);
+    }
 }
 
 #[allow(non_snake_case)]
-pub unsafe extern "system" fn vkCmdEndRenderPass(_commandBuffer: api::VkCommandBuffer) {
-    unimplemented!()
+pub unsafe extern "system" fn vkCmdEndRenderPass(command_buffer: api::VkCommandBuffer) {
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    if let Some((validation, state)) =
+        buffer.validate_command_mut(command_buffer, "vkCmdEndRenderPass", &[])
+    {
+        validation.end_render_pass(command_buffer, state);
+    }
 }
 
 #[allow(non_snake_case)]
@@ -5273,6 +6667,10 @@ pub unsafe extern "system" fn vkGetPhysicalDeviceFeatures2(
             .features
             .export_feature_set(&mut *physical_device_multiview_features);
     }
+    export_extension_feature_structs(
+        &physical_device.features,
+        features as *mut api::VkBaseOutStructure,
+    );
 }
 
 #[allow(non_snake_case)]
@@ -5289,6 +6687,9 @@ pub unsafe extern "system" fn vkGetPhysicalDeviceProperties2(
         maintenance_3_properties: api::VkPhysicalDeviceMaintenance3Properties = api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MAINTENANCE_3_PROPERTIES,
         protected_memory_properties: api::VkPhysicalDeviceProtectedMemoryProperties = api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_PROTECTED_MEMORY_PROPERTIES,
         subgroup_properties: api::VkPhysicalDeviceSubgroupProperties = api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_PROPERTIES,
+        subgroup_size_control_properties: api::VkPhysicalDeviceSubgroupSizeControlPropertiesEXT = api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_SUBGROUP_SIZE_CONTROL_PROPERTIES_EXT,
+        map_memory_placed_properties: api::VkPhysicalDeviceMapMemoryPlacedPropertiesEXT = api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MAP_MEMORY_PLACED_PROPERTIES_EXT,
+        robustness_2_properties: api::VkPhysicalDeviceRobustness2PropertiesEXT = api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_ROBUSTNESS_2_PROPERTIES_EXT,
     }
     let properties = &mut *properties;
     let physical_device = SharedHandle::from(physical_device).unwrap();
@@ -5341,6 +6742,39 @@ pub unsafe extern "system" fn vkGetPhysicalDeviceProperties2(
             ..physical_device.subgroup_properties
         };
     }
+    if !subgroup_size_control_properties.is_null() {
+        let subgroup_size_control_properties = &mut *subgroup_size_control_properties;
+        *subgroup_size_control_properties = api::VkPhysicalDeviceSubgroupSizeControlPropertiesEXT {
+            sType: subgroup_size_control_properties.sType,
+            pNext: subgroup_size_control_properties.pNext,
+            minSubgroupSize: pipeline::MIN_SUBGROUP_SIZE,
+            maxSubgroupSize: pipeline::MAX_SUBGROUP_SIZE,
+            maxComputeWorkgroupSubgroups: physical_device
+                .properties
+                .limits
+                .maxComputeWorkGroupInvocations,
+            requiredSubgroupSizeStages: api::VK_SHADER_STAGE_COMPUTE_BIT,
+        };
+    }
+    if !map_memory_placed_properties.is_null() {
+        let map_memory_placed_properties = &mut *map_memory_placed_properties;
+        *map_memory_placed_properties = api::VkPhysicalDeviceMapMemoryPlacedPropertiesEXT {
+            sType: map_memory_placed_properties.sType,
+            pNext: map_memory_placed_properties.pNext,
+            // placed mappings are made with mremap
+            minPlacedMemoryMapAlignment: get_page_size() as api::VkDeviceSize,
+        };
+    }
+    if !robustness_2_properties.is_null() {
+        let robustness_2_properties = &mut *robustness_2_properties;
+        *robustness_2_properties = api::VkPhysicalDeviceRobustness2PropertiesEXT {
+            sType: robustness_2_properties.sType,
+            pNext: robustness_2_properties.pNext,
+            // buffer accesses are bounds checked against the exact descriptor range
+            robustStorageBufferAccessSizeAlignment: 1,
+            robustUniformBufferAccessSizeAlignment: 1,
+        };
+    }
 }
 
 #[allow(non_snake_case)]
@@ -5539,6 +6973,7 @@ pub unsafe extern "system" fn vkGetDescriptorSetLayoutSupport(
     unimplemented!()
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroySurfaceKHR(
     _instance: api::VkInstance,
@@ -5553,6 +6988,7 @@ pub unsafe extern "system" fn vkDestroySurfaceKHR(
     }
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetPhysicalDeviceSurfaceSupportKHR(
     _physicalDevice: api::VkPhysicalDevice,
@@ -5563,6 +6999,7 @@ pub unsafe extern "system" fn vkGetPhysicalDeviceSurfaceSupportKHR(
     unimplemented!()
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetPhysicalDeviceSurfaceCapabilitiesKHR(
     physical_device: api::VkPhysicalDevice,
@@ -5591,9 +7028,10 @@ pub unsafe extern "system" fn vkGetPhysicalDeviceSurfaceCapabilitiesKHR(
     }
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetPhysicalDeviceSurfaceFormatsKHR(
-    _physical_device: api::VkPhysicalDevice,
+    physical_device: api::VkPhysicalDevice,
     surface: api::VkSurfaceKHR,
     surface_format_count: *mut u32,
     surface_formats: *mut api::VkSurfaceFormatKHR,
@@ -5606,14 +7044,21 @@ pub unsafe extern "system" fn vkGetPhysicalDeviceSurfaceFormatsKHR(
         Ok(returned_surface_formats) => returned_surface_formats,
         Err(result) => return result,
     };
+    let extended_color_spaces = SharedHandle::from(physical_device)
+        .unwrap()
+        .enabled_extensions[Extension::VK_EXT_swapchain_colorspace];
     enumerate_helper(
         surface_format_count,
         surface_formats,
-        returned_surface_formats.iter(),
+        returned_surface_formats.iter().filter(|surface_format| {
+            extended_color_spaces
+                || surface_format.colorSpace == api::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR
+        }),
         |a, b| *a = *b,
     )
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetPhysicalDeviceSurfacePresentModesKHR(
     _physical_device: api::VkPhysicalDevice,
@@ -5637,6 +7082,7 @@ pub unsafe extern "system" fn vkGetPhysicalDeviceSurfacePresentModesKHR(
     )
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateSwapchainKHR(
     _device: api::VkDevice,
@@ -5670,6 +7116,7 @@ pub unsafe extern "system" fn vkCreateSwapchainKHR(
     }
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkDestroySwapchainKHR(
     _device: api::VkDevice,
@@ -5679,6 +7126,7 @@ pub unsafe extern "system" fn vkDestroySwapchainKHR(
     OwnedHandle::from(swapchain);
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetSwapchainImagesKHR(
     _device: api::VkDevice,
@@ -5689,6 +7137,7 @@ pub unsafe extern "system" fn vkGetSwapchainImagesKHR(
     unimplemented!()
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkAcquireNextImageKHR(
     _device: api::VkDevice,
@@ -5701,14 +7150,48 @@ pub unsafe extern "system" fn vkAcquireNextImageKHR(
     unimplemented!()
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkQueuePresentKHR(
     _queue: api::VkQueue,
-    _pPresentInfo: *const api::VkPresentInfoKHR,
+    present_info: *const api::VkPresentInfoKHR,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        present_info,
+        root = api::VK_STRUCTURE_TYPE_PRESENT_INFO_KHR,
+        present_times_info: api::VkPresentTimesInfoGOOGLE = api::VK_STRUCTURE_TYPE_PRESENT_TIMES_INFO_GOOGLE,
+    }
+    let present_info = &*present_info;
+    // commands run before vkQueueSubmit returns, so the wait semaphores are always signaled
+    let swapchain_count = present_info.swapchainCount as usize;
+    let swapchains = slice::from_raw_parts(present_info.pSwapchains, swapchain_count);
+    let image_indices = slice::from_raw_parts(present_info.pImageIndices, swapchain_count);
+    let present_times = if present_times_info.is_null() || (*present_times_info).pTimes.is_null() {
+        None
+    } else {
+        let present_times_info = &*present_times_info;
+        assert_eq!(present_times_info.swapchainCount as usize, swapchain_count);
+        Some(slice::from_raw_parts(present_times_info.pTimes, swapchain_count))
+    };
+    let mut retval = api::VK_SUCCESS;
+    for (index, (&swapchain, &image_index)) in swapchains.iter().zip(image_indices).enumerate() {
+        let swapchain = SharedHandle::from(swapchain).unwrap();
+        let present_time = present_times.map(|present_times| present_times[index]);
+        let result = match swapchain.present(image_index, present_time) {
+            Ok(()) => api::VK_SUCCESS,
+            Err(error) => error,
+        };
+        if !present_info.pResults.is_null() {
+            *present_info.pResults.add(index) = result;
+        }
+        if retval == api::VK_SUCCESS {
+            retval = result;
+        }
+    }
+    retval
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetDeviceGroupPresentCapabilitiesKHR(
     _device: api::VkDevice,
@@ -5717,6 +7200,7 @@ pub unsafe extern "system" fn vkGetDeviceGroupPresentCapabilitiesKHR(
     unimplemented!()
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetDeviceGroupSurfacePresentModesKHR(
     _device: api::VkDevice,
@@ -5726,6 +7210,7 @@ pub unsafe extern "system" fn vkGetDeviceGroupSurfacePresentModesKHR(
     unimplemented!()
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetPhysicalDevicePresentRectanglesKHR(
     _physicalDevice: api::VkPhysicalDevice,
@@ -5736,6 +7221,7 @@ pub unsafe extern "system" fn vkGetPhysicalDevicePresentRectanglesKHR(
     unimplemented!()
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkAcquireNextImage2KHR(
     _device: api::VkDevice,
@@ -5899,43 +7385,60 @@ pub unsafe extern "system" fn vkCmdPushDescriptorSetWithTemplateKHR(
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkCreateRenderPass2KHR(
     _device: api::VkDevice,
-    _pCreateInfo: *const api::VkRenderPassCreateInfo2KHR,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pRenderPass: *mut api::VkRenderPass,
+    create_info: *const api::VkRenderPassCreateInfo2KHR,
+    _allocator: *const api::VkAllocationCallbacks,
+    render_pass: *mut api::VkRenderPass,
 ) -> api::VkResult {
-    unimplemented!()
+    *render_pass = OwnedHandle::<api::VkRenderPass>::new(RenderPass::new2(&*create_info)).take();
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkCmdBeginRenderPass2KHR(
-    _commandBuffer: api::VkCommandBuffer,
-    _pRenderPassBegin: *const api::VkRenderPassBeginInfo,
-    _pSubpassBeginInfo: *const api::VkSubpassBeginInfoKHR,
+    command_buffer: api::VkCommandBuffer,
+    render_pass_begin: *const api::VkRenderPassBeginInfo,
+    subpass_begin_info: *const api::VkSubpassBeginInfoKHR,
 ) {
-    unimplemented!()
+    parse_next_chain_const!{
+        subpass_begin_info,
+        root = api::VK_STRUCTURE_TYPE_SUBPASS_BEGIN_INFO_KHR,
+    }
+    vkCmdBeginRenderPass(
+        command_buffer,
+        render_pass_begin,
+        (*subpass_begin_info).contents,
+    )
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkCmdNextSubpass2KHR(
-    _commandBuffer: api::VkCommandBuffer,
-    _pSubpassBeginInfo: *const api::VkSubpassBeginInfoKHR,
-    _pSubpassEndInfo: *const api::VkSubpassEndInfoKHR,
+    command_buffer: api::VkCommandBuffer,
+    subpass_begin_info: *const api::VkSubpassBeginInfoKHR,
+    subpass_end_info: *const api::VkSubpassEndInfoKHR,
 ) {
-    unimplemented!()
+    parse_next_chain_const!{
+        subpass_begin_info,
+        root = api::VK_STRUCTURE_TYPE_SUBPASS_BEGIN_INFO_KHR,
+    }
+    parse_next_chain_const!{
+        subpass_end_info,
+        root = api::VK_STRUCTURE_TYPE_SUBPASS_END_INFO_KHR,
+    }
+    vkCmdNextSubpass(command_buffer, (*subpass_begin_info).contents)
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkCmdEndRenderPass2KHR(
-    _commandBuffer: api::VkCommandBuffer,
-    _pSubpassEndInfo: *const api::VkSubpassEndInfoKHR,
+    command_buffer: api::VkCommandBuffer,
+    subpass_end_info: *const api::VkSubpassEndInfoKHR,
 ) {
-    unimplemented!()
+    parse_next_chain_const!{
+        subpass_end_info,
+        root = api::VK_STRUCTURE_TYPE_SUBPASS_END_INFO_KHR,
+    }
+    vkCmdEndRenderPass(command_buffer)
 }
 
 #[allow(non_snake_case)]
@@ -5966,6 +7469,7 @@ pub unsafe extern "system" fn vkGetFenceFdKHR(
     unimplemented!()
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetPhysicalDeviceSurfaceCapabilities2KHR(
     _physical_device: api::VkPhysicalDevice,
@@ -6300,25 +7804,44 @@ pub unsafe extern "system" fn vkGetSwapchainCounterEXT(
     unimplemented!()
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkGetRefreshCycleDurationGOOGLE(
     _device: api::VkDevice,
-    _swapchain: api::VkSwapchainKHR,
-    _pDisplayTimingProperties: *mut api::VkRefreshCycleDurationGOOGLE,
+    swapchain: api::VkSwapchainKHR,
+    display_timing_properties: *mut api::VkRefreshCycleDurationGOOGLE,
 ) -> api::VkResult {
-    unimplemented!()
+    let swapchain = SharedHandle::from(swapchain).unwrap();
+    (*display_timing_properties).refreshDuration = swapchain.get_refresh_duration();
+    api::VK_SUCCESS
 }
 
+#[cfg(feature = "wsi")]
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkGetPastPresentationTimingGOOGLE(
     _device: api::VkDevice,
-    _swapchain: api::VkSwapchainKHR,
-    _pPresentationTimingCount: *mut u32,
-    _pPresentationTimings: *mut api::VkPastPresentationTimingGOOGLE,
+    swapchain: api::VkSwapchainKHR,
+    presentation_timing_count: *mut u32,
+    presentation_timings: *mut api::VkPastPresentationTimingGOOGLE,
 ) -> api::VkResult {
-    unimplemented!()
+    let swapchain = SharedHandle::from(swapchain).unwrap();
+    let history = swapchain.get_present_timing_history();
+    if presentation_timings.is_null() {
+        *presentation_timing_count = history.len() as u32;
+        return api::VK_SUCCESS;
+    }
+    // timings are only returned once, so only take the ones that fit
+    enumerate_helper(
+        presentation_timing_count,
+        presentation_timings,
+        history.take(*presentation_timing_count as usize),
+        |api_value, value| *api_value = value.into(),
+    );
+    if history.is_empty() {
+        api::VK_SUCCESS
+    } else {
+        api::VK_INCOMPLETE
+    }
 }
 
 #[allow(non_snake_case)]
@@ -6344,101 +7867,377 @@ pub unsafe extern "system" fn vkSetHdrMetadataEXT(
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkSetDebugUtilsObjectNameEXT(
     _device: api::VkDevice,
-    _pNameInfo: *const api::VkDebugUtilsObjectNameInfoEXT,
+    name_info: *const api::VkDebugUtilsObjectNameInfoEXT,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        name_info,
+        root = api::VK_STRUCTURE_TYPE_DEBUG_UTILS_OBJECT_NAME_INFO_EXT,
+    }
+    // object names are ignored
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkSetDebugUtilsObjectTagEXT(
     _device: api::VkDevice,
-    _pTagInfo: *const api::VkDebugUtilsObjectTagInfoEXT,
+    tag_info: *const api::VkDebugUtilsObjectTagInfoEXT,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        tag_info,
+        root = api::VK_STRUCTURE_TYPE_DEBUG_UTILS_OBJECT_TAG_INFO_EXT,
+    }
+    // object tags are ignored
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkQueueBeginDebugUtilsLabelEXT(
     _queue: api::VkQueue,
-    _pLabelInfo: *const api::VkDebugUtilsLabelEXT,
+    _label_info: *const api::VkDebugUtilsLabelEXT,
 ) {
-    unimplemented!()
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
-pub unsafe extern "system" fn vkQueueEndDebugUtilsLabelEXT(_queue: api::VkQueue) {
-    unimplemented!()
-}
+pub unsafe extern "system" fn vkQueueEndDebugUtilsLabelEXT(_queue: api::VkQueue) {}
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkQueueInsertDebugUtilsLabelEXT(
     _queue: api::VkQueue,
-    _pLabelInfo: *const api::VkDebugUtilsLabelEXT,
+    _label_info: *const api::VkDebugUtilsLabelEXT,
 ) {
-    unimplemented!()
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkCmdBeginDebugUtilsLabelEXT(
-    _commandBuffer: api::VkCommandBuffer,
-    _pLabelInfo: *const api::VkDebugUtilsLabelEXT,
+    _command_buffer: api::VkCommandBuffer,
+    _label_info: *const api::VkDebugUtilsLabelEXT,
 ) {
-    unimplemented!()
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
-pub unsafe extern "system" fn vkCmdEndDebugUtilsLabelEXT(_commandBuffer: api::VkCommandBuffer) {
-    unimplemented!()
-}
+pub unsafe extern "system" fn vkCmdEndDebugUtilsLabelEXT(_command_buffer: api::VkCommandBuffer) {}
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkCmdInsertDebugUtilsLabelEXT(
-    _commandBuffer: api::VkCommandBuffer,
-    _pLabelInfo: *const api::VkDebugUtilsLabelEXT,
+    _command_buffer: api::VkCommandBuffer,
+    _label_info: *const api::VkDebugUtilsLabelEXT,
 ) {
-    unimplemented!()
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkCreateDebugUtilsMessengerEXT(
-    _instance: api::VkInstance,
-    _pCreateInfo: *const api::VkDebugUtilsMessengerCreateInfoEXT,
-    _pAllocator: *const api::VkAllocationCallbacks,
-    _pMessenger: *mut api::VkDebugUtilsMessengerEXT,
+    instance: api::VkInstance,
+    create_info: *const api::VkDebugUtilsMessengerCreateInfoEXT,
+    _allocator: *const api::VkAllocationCallbacks,
+    messenger: *mut api::VkDebugUtilsMessengerEXT,
 ) -> api::VkResult {
-    unimplemented!()
+    parse_next_chain_const!{
+        create_info,
+        root = api::VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
+    }
+    let instance = SharedHandle::from(instance).unwrap();
+    *messenger =
+        OwnedHandle::<api::VkDebugUtilsMessengerEXT>::new(DebugUtilsMessenger::new(&*create_info))
+            .take();
+    instance
+        .physical_device
+        .debug_utils_messengers
+        .add(*messenger);
+    api::VK_SUCCESS
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkDestroyDebugUtilsMessengerEXT(
-    _instance: api::VkInstance,
-    _messenger: api::VkDebugUtilsMessengerEXT,
-    _pAllocator: *const api::VkAllocationCallbacks,
+    instance: api::VkInstance,
+    messenger: api::VkDebugUtilsMessengerEXT,
+    _allocator: *const api::VkAllocationCallbacks,
 ) {
-    unimplemented!()
+    if messenger.is_null() {
+        return;
+    }
+    SharedHandle::from(instance)
+        .unwrap()
+        .physical_device
+        .debug_utils_messengers
+        .remove(messenger);
+    OwnedHandle::from(messenger);
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkSubmitDebugUtilsMessageEXT(
-    _instance: api::VkInstance,
-    _messageSeverity: api::VkDebugUtilsMessageSeverityFlagBitsEXT,
-    _messageTypes: api::VkDebugUtilsMessageTypeFlagsEXT,
-    _pCallbackData: *const api::VkDebugUtilsMessengerCallbackDataEXT,
+    instance: api::VkInstance,
+    message_severity: api::VkDebugUtilsMessageSeverityFlagBitsEXT,
+    message_types: api::VkDebugUtilsMessageTypeFlagsEXT,
+    callback_data: *const api::VkDebugUtilsMessengerCallbackDataEXT,
 ) {
-    unimplemented!()
+    parse_next_chain_const!{
+        callback_data,
+        root = api::VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CALLBACK_DATA_EXT,
+    }
+    SharedHandle::from(instance)
+        .unwrap()
+        .physical_device
+        .debug_utils_messengers
+        .submit(message_severity, message_types, &*callback_data);
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkGetPhysicalDeviceToolPropertiesEXT(
+    physical_device: api::VkPhysicalDevice,
+    tool_count: *mut u32,
+    tool_properties: *mut api::VkPhysicalDeviceToolPropertiesEXT,
+) -> api::VkResult {
+    let physical_device = SharedHandle::from(physical_device).unwrap();
+    enumerate_helper(
+        tool_count,
+        tool_properties,
+        iter::once(physical_device.configuration.get_tool_properties()),
+        |api_value, value| {
+            assert_eq!(
+                api_value.sType,
+                api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_TOOL_PROPERTIES_EXT
+            );
+            *api_value = api::VkPhysicalDeviceToolPropertiesEXT {
+                pNext: api_value.pNext,
+                ..value
+            };
+        },
+    )
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkGetPhysicalDeviceDriverConfigurationKAZAN(
+    physical_device: api::VkPhysicalDevice,
+    configuration: *mut api::VkDriverConfigurationKAZAN,
+) {
+    *configuration = SharedHandle::from(physical_device)
+        .unwrap()
+        .configuration
+        .get_api_configuration();
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkCmdGenerateMipmapsKAZAN(
+    command_buffer: api::VkCommandBuffer,
+    image: api::VkImage,
+    image_layout: api::VkImageLayout,
+    subresource_range: *const api::VkImageSubresourceRange,
+    filter: api::VkFilter,
+) {
+    // images don't have a different representation per layout, so the layout is only validated
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    let image = SharedHandle::from(image).unwrap();
+    let subresource_range = *subresource_range;
+    let supported = mipmap::check_generate_mipmaps(image, &subresource_range, filter);
+    if let Some((validation, state)) =
+        buffer.validate_command_mut(command_buffer, "vkCmdGenerateMipmapsKAZAN", &[])
+    {
+        validation.check_outside_render_pass(command_buffer, state, "vkCmdGenerateMipmapsKAZAN");
+        validation.transition_image_layout(
+            command_buffer,
+            state,
+            "vkCmdGenerateMipmapsKAZAN",
+            image,
+            &subresource_range,
+            image_layout,
+            image_layout,
+        );
+        if let Err(ref message) = supported {
+            validation.error(
+                "kazan-generate-mipmaps-unsupported",
+                &format!("vkCmdGenerateMipmapsKAZAN: {}", message),
+                &[
+                    validation::command_buffer_object(command_buffer),
+                    validation::image_object(image.get_handle()),
+                ],
+            );
+        }
+    }
+    // unsupported calls aren't recorded, so they can't fail when the command buffer runs
+    if supported.is_ok() {
+        buffer
+            .commands
+            .generate_mipmaps(image, subresource_range, filter);
+    }
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkGetPipelineExecutablePropertiesKHR(
+    _device: api::VkDevice,
+    pPipelineInfo: *const api::VkPipelineInfoKHR,
+    pExecutableCount: *mut u32,
+    pProperties: *mut api::VkPipelineExecutablePropertiesKHR,
+) -> api::VkResult {
+    assert_eq!(
+        (*pPipelineInfo).sType,
+        api::VK_STRUCTURE_TYPE_PIPELINE_INFO_KHR
+    );
+    let pipeline = SharedHandle::from((*pPipelineInfo).pipeline).unwrap();
+    // each shader stage is compiled separately, so it's an executable
+    enumerate_helper(
+        pExecutableCount,
+        pProperties,
+        pipeline.stages(),
+        |properties, stage| {
+            assert_eq!(
+                properties.sType,
+                api::VK_STRUCTURE_TYPE_PIPELINE_EXECUTABLE_PROPERTIES_KHR
+            );
+            properties.stages = stage.stage;
+            copy_str_to_char_array(
+                &mut properties.name,
+                &format!("{:?}", stage.interface.execution_model),
+            );
+            let mut description = format!(
+                "the {:?} shader, run {} invocations at a time",
+                stage.interface.execution_model,
+                stage.simd_width()
+            );
+            if stage.simd_width_choice.limit != SimdWidthLimit::None {
+                description += &format!(", limited by {}", stage.simd_width_choice.limit);
+            }
+            copy_str_to_char_array(&mut properties.description, &description);
+            properties.subgroupSize = stage.simd_width();
+        },
+    )
+}
+
+fn get_pipeline_executable(
+    executable_info: &api::VkPipelineExecutableInfoKHR,
+) -> (SharedHandle<api::VkPipeline>, usize) {
+    assert_eq!(
+        executable_info.sType,
+        api::VK_STRUCTURE_TYPE_PIPELINE_EXECUTABLE_INFO_KHR
+    );
+    let pipeline = SharedHandle::from(executable_info.pipeline).unwrap();
+    let index = executable_info.executableIndex as usize;
+    assert!(index < pipeline.stages().len());
+    (pipeline, index)
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkGetPipelineExecutableStatisticsKHR(
+    _device: api::VkDevice,
+    pExecutableInfo: *const api::VkPipelineExecutableInfoKHR,
+    pStatisticCount: *mut u32,
+    pStatistics: *mut api::VkPipelineExecutableStatisticKHR,
+) -> api::VkResult {
+    let (pipeline, index) = get_pipeline_executable(&*pExecutableInfo);
+    let stage = &pipeline.stages()[index];
+    let profile = &stage.profile;
+    let uint64 = |value: u32| {
+        (
+            api::VK_PIPELINE_EXECUTABLE_STATISTIC_FORMAT_UINT64_KHR,
+            api::VkPipelineExecutableStatisticValueKHR {
+                u64: u64::from(value),
+            },
+        )
+    };
+    let bool32 = |value: bool| {
+        (
+            api::VK_PIPELINE_EXECUTABLE_STATISTIC_FORMAT_BOOL32_KHR,
+            api::VkPipelineExecutableStatisticValueKHR {
+                b32: value as api::VkBool32,
+            },
+        )
+    };
+    let statistics = [
+        (
+            "SIMD width",
+            "the number of invocations run together",
+            uint64(stage.simd_width()),
+        ),
+        (
+            "Register pressure",
+            "the estimated most 32-bit words an invocation has live at once",
+            uint64(profile.register_pressure),
+        ),
+        (
+            "Uses f64",
+            "whether the shader has f64 values, which take twice the vector lanes",
+            bool32(profile.uses_float64),
+        ),
+        (
+            "Branches",
+            "conditional branches and switches",
+            uint64(profile.branch_count),
+        ),
+        (
+            "Divergent branches",
+            "branches whose condition can differ between the invocations run together",
+            uint64(profile.divergent_branch_count),
+        ),
+        (
+            "Divergent loops",
+            "loops the invocations run together can leave at different iterations",
+            uint64(profile.divergent_loop_count),
+        ),
+    ];
+    enumerate_helper(
+        pStatisticCount,
+        pStatistics,
+        statistics.iter(),
+        |statistic, &(name, description, (format, value))| {
+            assert_eq!(
+                statistic.sType,
+                api::VK_STRUCTURE_TYPE_PIPELINE_EXECUTABLE_STATISTIC_KHR
+            );
+            copy_str_to_char_array(&mut statistic.name, name);
+            copy_str_to_char_array(&mut statistic.description, description);
+            statistic.format = format;
+            statistic.value = value;
+        },
+    )
+}
+
+#[allow(non_snake_case)]
+pub unsafe extern "system" fn vkGetPipelineExecutableInternalRepresentationsKHR(
+    _device: api::VkDevice,
+    pExecutableInfo: *const api::VkPipelineExecutableInfoKHR,
+    pInternalRepresentationCount: *mut u32,
+    pInternalRepresentations: *mut api::VkPipelineExecutableInternalRepresentationKHR,
+) -> api::VkResult {
+    let (pipeline, index) = get_pipeline_executable(&*pExecutableInfo);
+    let stage = &pipeline.stages()[index];
+    // the LLVM IR and assembly are only kept when shaders are dumped
+    let mut disassembly = spirv::disassemble(&stage.code)
+        .unwrap_or_else(|error| panic!("invalid shader module: {}", error))
+        .into_bytes();
+    disassembly.push(0);
+    let mut is_truncated = false;
+    let result = enumerate_helper(
+        pInternalRepresentationCount,
+        pInternalRepresentations,
+        iter::once(&disassembly),
+        |representation, text| {
+            assert_eq!(
+                representation.sType,
+                api::VK_STRUCTURE_TYPE_PIPELINE_EXECUTABLE_INTERNAL_REPRESENTATION_KHR
+            );
+            copy_str_to_char_array(&mut representation.name, "SPIR-V");
+            copy_str_to_char_array(
+                &mut representation.description,
+                "the SPIR-V after specialization and linking with the other stages",
+            );
+            representation.isText = api::VK_TRUE;
+            if !representation.pData.is_null() {
+                let size = representation.dataSize.min(text.len());
+                is_truncated = size < text.len();
+                slice::from_raw_parts_mut(representation.pData as *mut u8, size)
+                    .copy_from_slice(&text[..size]);
+                representation.dataSize = size;
+            } else {
+                representation.dataSize = text.len();
+            }
+        },
+    );
+    if is_truncated {
+        api::VK_INCOMPLETE
+    } else {
+        result
+    }
 }
 
 #[allow(non_snake_case)]
@@ -6547,15 +8346,22 @@ pub unsafe extern "system" fn vkGetMemoryHostPointerPropertiesEXT(
 }
 
 #[allow(non_snake_case)]
-#[cfg(kazan_include_unused_vulkan_api)]
 pub unsafe extern "system" fn vkCmdWriteBufferMarkerAMD(
-    _commandBuffer: api::VkCommandBuffer,
-    _pipelineStage: api::VkPipelineStageFlagBits,
-    _dstBuffer: api::VkBuffer,
-    _dstOffset: api::VkDeviceSize,
-    _marker: u32,
+    command_buffer: api::VkCommandBuffer,
+    _pipeline_stage: api::VkPipelineStageFlagBits,
+    dst_buffer: api::VkBuffer,
+    dst_offset: api::VkDeviceSize,
+    marker: u32,
 ) {
-    unimplemented!()
+    // each command finishes before the next one starts, so the pipeline stage doesn't matter
+    let mut buffer = MutHandle::<api::VkCommandBuffer>::from(command_buffer).unwrap();
+    buffer.validate_command(command_buffer, "vkCmdWriteBufferMarkerAMD", &[]);
+    let dst_buffer = SharedHandle::from(dst_buffer).unwrap();
+    assert_eq!(dst_offset % 4, 0);
+    assert!(dst_offset + 4 <= dst_buffer.size as u64);
+    buffer
+        .commands
+        .write_buffer_marker(dst_buffer, dst_offset, marker);
 }
 
 #[allow(non_snake_case)]
@@ -6624,7 +8430,7 @@ pub unsafe extern "system" fn vkGetQueueCheckpointDataNV(
     unimplemented!()
 }
 
-#[cfg(unix)]
+#[cfg(all(unix, feature = "wsi-xcb"))]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkCreateXcbSurfaceKHR(
     _instance: api::VkInstance,
@@ -6650,7 +8456,7 @@ pub unsafe extern "system" fn vkCreateXcbSurfaceKHR(
     api::VK_SUCCESS
 }
 
-#[cfg(unix)]
+#[cfg(all(unix, feature = "wsi-xcb"))]
 #[allow(non_snake_case)]
 pub unsafe extern "system" fn vkGetPhysicalDeviceXcbPresentationSupportKHR(
     _physicalDevice: api::VkPhysicalDevice,
@@ -6660,3 +8466,959 @@ pub unsafe extern "system" fn vkGetPhysicalDeviceXcbPresentationSupportKHR(
 ) -> api::VkBool32 {
     unimplemented!()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compile_thread_pool::CompileThreadPool;
+    use driver_configuration::get_build_features;
+    use format;
+    use pipeline::tests::compute_module;
+    use shader_compiler_backend::Compiler;
+    use shader_compiler_backend_llvm_7::LLVM_7_SHADER_COMPILER;
+    use std::ffi::CString;
+    use std::sync::Mutex;
+    use submit_graph;
+
+    /// an instance with the instance extensions `extensions` enabled, destroyed when dropped
+    struct TestInstance {
+        instance: api::VkInstance,
+    }
+
+    impl TestInstance {
+        fn new(extensions: &[&str]) -> Self {
+            let extensions: Vec<_> = extensions
+                .iter()
+                .map(|&extension| CString::new(extension).unwrap())
+                .collect();
+            let extension_pointers: Vec<_> = extensions.iter().map(|v| v.as_ptr()).collect();
+            let mut instance = Handle::null();
+            unsafe {
+                assert_eq!(
+                    vkCreateInstance(
+                        &api::VkInstanceCreateInfo {
+                            sType: api::VK_STRUCTURE_TYPE_INSTANCE_CREATE_INFO,
+                            pNext: null(),
+                            flags: 0,
+                            pApplicationInfo: null(),
+                            enabledLayerCount: 0,
+                            ppEnabledLayerNames: null(),
+                            enabledExtensionCount: extension_pointers.len() as u32,
+                            ppEnabledExtensionNames: extension_pointers.as_ptr(),
+                        },
+                        null(),
+                        &mut instance,
+                    ),
+                    api::VK_SUCCESS
+                );
+            }
+            TestInstance { instance }
+        }
+        fn get_physical_device(&self) -> api::VkPhysicalDevice {
+            let mut physical_device = Handle::null();
+            let mut physical_device_count = 1;
+            unsafe {
+                assert_eq!(
+                    vkEnumeratePhysicalDevices(
+                        self.instance,
+                        &mut physical_device_count,
+                        &mut physical_device,
+                    ),
+                    api::VK_SUCCESS
+                );
+            }
+            physical_device
+        }
+    }
+
+    impl Drop for TestInstance {
+        fn drop(&mut self) {
+            unsafe { vkDestroyInstance(self.instance, null()) };
+        }
+    }
+
+    /// a device with one queue and the device extensions `extensions` enabled, destroyed when
+    /// dropped
+    struct TestDevice {
+        device: api::VkDevice,
+        queue: api::VkQueue,
+    }
+
+    impl TestDevice {
+        fn new(instance: &TestInstance, extensions: &[&str]) -> Self {
+            Self::with_next(instance, extensions, null()).unwrap()
+        }
+        /// like `new`, with `next` as the `pNext` chain of the create info
+        fn with_next(
+            instance: &TestInstance,
+            extensions: &[&str],
+            next: *const c_void,
+        ) -> Result<Self, api::VkResult> {
+            let extensions: Vec<_> = extensions
+                .iter()
+                .map(|&extension| CString::new(extension).unwrap())
+                .collect();
+            let extension_pointers: Vec<_> = extensions.iter().map(|v| v.as_ptr()).collect();
+            let queue_priority = 1.0;
+            let mut device = Handle::null();
+            let mut queue = Handle::null();
+            unsafe {
+                match vkCreateDevice(
+                    instance.get_physical_device(),
+                    &api::VkDeviceCreateInfo {
+                        sType: api::VK_STRUCTURE_TYPE_DEVICE_CREATE_INFO,
+                        pNext: next,
+                        flags: 0,
+                        queueCreateInfoCount: 1,
+                        pQueueCreateInfos: &api::VkDeviceQueueCreateInfo {
+                            sType: api::VK_STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
+                            pNext: null(),
+                            flags: 0,
+                            queueFamilyIndex: 0,
+                            queueCount: 1,
+                            pQueuePriorities: &queue_priority,
+                        },
+                        enabledLayerCount: 0,
+                        ppEnabledLayerNames: null(),
+                        enabledExtensionCount: extension_pointers.len() as u32,
+                        ppEnabledExtensionNames: extension_pointers.as_ptr(),
+                        pEnabledFeatures: null(),
+                    },
+                    null(),
+                    &mut device,
+                ) {
+                    api::VK_SUCCESS => {}
+                    result => return Err(result),
+                }
+                vkGetDeviceQueue(device, 0, 0, &mut queue);
+            }
+            Ok(TestDevice { device, queue })
+        }
+        /// a host-visible buffer of `size` bytes with its own memory
+        fn create_buffer(
+            &self,
+            size: u64,
+        ) -> (OwnedHandle<api::VkBuffer>, OwnedHandle<api::VkDeviceMemory>) {
+            let mut buffer = Handle::null();
+            let mut memory = Handle::null();
+            unsafe {
+                assert_eq!(
+                    vkCreateBuffer(
+                        self.device,
+                        &api::VkBufferCreateInfo {
+                            sType: api::VK_STRUCTURE_TYPE_BUFFER_CREATE_INFO,
+                            pNext: null(),
+                            flags: 0,
+                            size,
+                            usage: api::VK_BUFFER_USAGE_TRANSFER_DST_BIT,
+                            sharingMode: api::VK_SHARING_MODE_EXCLUSIVE,
+                            queueFamilyIndexCount: 0,
+                            pQueueFamilyIndices: null(),
+                        },
+                        null(),
+                        &mut buffer,
+                    ),
+                    api::VK_SUCCESS
+                );
+                assert_eq!(
+                    vkAllocateMemory(
+                        self.device,
+                        &api::VkMemoryAllocateInfo {
+                            sType: api::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+                            pNext: null(),
+                            allocationSize: size,
+                            memoryTypeIndex: DeviceMemoryType::Main as u32,
+                        },
+                        null(),
+                        &mut memory,
+                    ),
+                    api::VK_SUCCESS
+                );
+                assert_eq!(
+                    vkBindBufferMemory(self.device, buffer, memory, 0),
+                    api::VK_SUCCESS
+                );
+                (
+                    OwnedHandle::from(buffer).unwrap(),
+                    OwnedHandle::from(memory).unwrap(),
+                )
+            }
+        }
+        /// a command pool with one command buffer that's ready for recording
+        fn begin_command_buffer(&self) -> (OwnedHandle<api::VkCommandPool>, api::VkCommandBuffer) {
+            let mut command_pool = Handle::null();
+            let mut command_buffer = Handle::null();
+            unsafe {
+                assert_eq!(
+                    vkCreateCommandPool(
+                        self.device,
+                        &api::VkCommandPoolCreateInfo {
+                            sType: api::VK_STRUCTURE_TYPE_COMMAND_POOL_CREATE_INFO,
+                            pNext: null(),
+                            flags: 0,
+                            queueFamilyIndex: 0,
+                        },
+                        null(),
+                        &mut command_pool,
+                    ),
+                    api::VK_SUCCESS
+                );
+                assert_eq!(
+                    vkAllocateCommandBuffers(
+                        self.device,
+                        &api::VkCommandBufferAllocateInfo {
+                            sType: api::VK_STRUCTURE_TYPE_COMMAND_BUFFER_ALLOCATE_INFO,
+                            pNext: null(),
+                            commandPool: command_pool,
+                            level: api::VK_COMMAND_BUFFER_LEVEL_PRIMARY,
+                            commandBufferCount: 1,
+                        },
+                        &mut command_buffer,
+                    ),
+                    api::VK_SUCCESS
+                );
+                assert_eq!(
+                    vkBeginCommandBuffer(
+                        command_buffer,
+                        &api::VkCommandBufferBeginInfo {
+                            sType: api::VK_STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
+                            pNext: null(),
+                            flags: 0,
+                            pInheritanceInfo: null(),
+                        },
+                    ),
+                    api::VK_SUCCESS
+                );
+                (OwnedHandle::from(command_pool).unwrap(), command_buffer)
+            }
+        }
+        /// end recording `command_buffer` and submit it by itself
+        fn submit(
+            &self,
+            command_buffer: api::VkCommandBuffer,
+            fence: api::VkFence,
+        ) -> api::VkResult {
+            unsafe {
+                assert_eq!(vkEndCommandBuffer(command_buffer), api::VK_SUCCESS);
+                vkQueueSubmit(
+                    self.queue,
+                    1,
+                    &api::VkSubmitInfo {
+                        sType: api::VK_STRUCTURE_TYPE_SUBMIT_INFO,
+                        pNext: null(),
+                        waitSemaphoreCount: 0,
+                        pWaitSemaphores: null(),
+                        pWaitDstStageMask: null(),
+                        commandBufferCount: 1,
+                        pCommandBuffers: &command_buffer,
+                        signalSemaphoreCount: 0,
+                        pSignalSemaphores: null(),
+                    },
+                    fence,
+                )
+            }
+        }
+    }
+
+    impl Drop for TestDevice {
+        fn drop(&mut self) {
+            unsafe { vkDestroyDevice(self.device, null()) };
+        }
+    }
+
+    fn get_str(chars: &[c_char]) -> &str {
+        unsafe { CStr::from_ptr(chars.as_ptr()) }.to_str().unwrap()
+    }
+
+    #[test]
+    fn test_tool_properties() {
+        let instance = TestInstance::new(&[]);
+        let physical_device = instance.get_physical_device();
+        let mut tool_count = 0;
+        assert_eq!(
+            unsafe {
+                vkGetPhysicalDeviceToolPropertiesEXT(physical_device, &mut tool_count, null_mut())
+            },
+            api::VK_SUCCESS
+        );
+        assert_eq!(tool_count, 1);
+        let mut tool_properties: [api::VkPhysicalDeviceToolPropertiesEXT; 2] =
+            unsafe { mem::zeroed() };
+        let mut next = 0u8;
+        for properties in tool_properties.iter_mut() {
+            properties.sType = api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_TOOL_PROPERTIES_EXT;
+            properties.pNext = &mut next as *mut u8 as *mut c_void;
+        }
+        // too small an array returns as many tools as fit
+        tool_count = 0;
+        assert_eq!(
+            unsafe {
+                vkGetPhysicalDeviceToolPropertiesEXT(
+                    physical_device,
+                    &mut tool_count,
+                    tool_properties.as_mut_ptr(),
+                )
+            },
+            api::VK_INCOMPLETE
+        );
+        assert_eq!(tool_count, 0);
+        assert_eq!(get_str(&tool_properties[0].name), "");
+        tool_count = 2;
+        assert_eq!(
+            unsafe {
+                vkGetPhysicalDeviceToolPropertiesEXT(
+                    physical_device,
+                    &mut tool_count,
+                    tool_properties.as_mut_ptr(),
+                )
+            },
+            api::VK_SUCCESS
+        );
+        assert_eq!(tool_count, 1);
+        let properties = &tool_properties[0];
+        assert_eq!(properties.pNext, &mut next as *mut u8 as *mut c_void);
+        assert_eq!(get_str(&properties.name), "Kazan");
+        assert_eq!(get_str(&properties.version), env!("CARGO_PKG_VERSION"));
+        assert_ne!(
+            properties.purposes & api::VK_TOOL_PURPOSE_DEBUG_REPORTING_BIT_EXT,
+            0
+        );
+        assert_eq!(get_str(&tool_properties[1].name), "");
+    }
+
+    #[test]
+    fn test_driver_configuration() {
+        let instance = TestInstance::new(&["VK_KAZAN_driver_configuration"]);
+        let mut configuration: api::VkDriverConfigurationKAZAN = unsafe { mem::zeroed() };
+        unsafe {
+            vkGetPhysicalDeviceDriverConfigurationKAZAN(
+                instance.get_physical_device(),
+                &mut configuration,
+            );
+        }
+        assert_eq!(
+            get_str(&configuration.compilerBackend),
+            LLVM_7_SHADER_COMPILER.name()
+        );
+        assert_eq!(
+            configuration.minVariableVectorLengthMultiplier,
+            pipeline::MIN_SUBGROUP_SIZE
+        );
+        assert_eq!(
+            configuration.maxVariableVectorLengthMultiplier,
+            pipeline::MAX_SUBGROUP_SIZE
+        );
+        assert_eq!(
+            configuration.compileThreadCount as usize,
+            CompileThreadPool::default_thread_count()
+        );
+        assert_eq!(
+            configuration.submitThreadCount as usize,
+            submit_graph::get_submit_thread_count()
+        );
+        assert_eq!(get_str(&configuration.cacheDirectory), "");
+        assert_eq!(
+            configuration.validationEnabled,
+            Validation::is_enabled_in_environment() as api::VkBool32
+        );
+    }
+
+    /// adds `"<message id>: <message>"` to the `Mutex<Vec<String>>` in `user_data`
+    unsafe extern "system" fn collect_message(
+        _message_severity: api::VkDebugUtilsMessageSeverityFlagBitsEXT,
+        _message_types: api::VkDebugUtilsMessageTypeFlagsEXT,
+        callback_data: *const api::VkDebugUtilsMessengerCallbackDataEXT,
+        user_data: *mut c_void,
+    ) -> api::VkBool32 {
+        let callback_data = &*callback_data;
+        let messages = &*(user_data as *const Mutex<Vec<String>>);
+        messages.lock().unwrap().push(format!(
+            "{}: {}",
+            CStr::from_ptr(callback_data.pMessageIdName).to_string_lossy(),
+            CStr::from_ptr(callback_data.pMessage).to_string_lossy()
+        ));
+        api::VK_FALSE
+    }
+
+    /// a messenger that collects the error messages sent to `instance` in `messages`; it has
+    /// to be destroyed before `messages` is dropped
+    fn create_messenger(
+        instance: &TestInstance,
+        messages: &Mutex<Vec<String>>,
+    ) -> api::VkDebugUtilsMessengerEXT {
+        let mut messenger = Handle::null();
+        assert_eq!(
+            unsafe {
+                vkCreateDebugUtilsMessengerEXT(
+                    instance.instance,
+                    &api::VkDebugUtilsMessengerCreateInfoEXT {
+                        sType: api::VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
+                        pNext: null(),
+                        flags: 0,
+                        messageSeverity: api::VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT,
+                        messageType: api::VK_DEBUG_UTILS_MESSAGE_TYPE_GENERAL_BIT_EXT
+                            | api::VK_DEBUG_UTILS_MESSAGE_TYPE_VALIDATION_BIT_EXT,
+                        pfnUserCallback: Some(collect_message),
+                        pUserData: messages as *const Mutex<Vec<String>> as *mut c_void,
+                    },
+                    null(),
+                    &mut messenger,
+                )
+            },
+            api::VK_SUCCESS
+        );
+        messenger
+    }
+
+    #[test]
+    fn test_buffer_marker_device_lost() {
+        let instance = TestInstance::new(&["VK_EXT_debug_utils"]);
+        let messages = Mutex::new(Vec::<String>::new());
+        let messenger = create_messenger(&instance, &messages);
+        let device = TestDevice::new(&instance, &["VK_AMD_buffer_marker"]);
+        let (buffer, memory) = device.create_buffer(16);
+        let mut markers = null_mut();
+        unsafe {
+            assert_eq!(
+                vkMapMemory(device.device, memory.get_handle(), 0, 16, 0, &mut markers),
+                api::VK_SUCCESS
+            );
+        }
+        let markers = markers as *mut [u32; 4];
+        unsafe { *markers = [!0; 4] };
+        // writing a marker to a buffer without memory fails while running the command buffer,
+        // which loses the device
+        let unbound_buffer = OwnedHandle::<api::VkBuffer>::new(Buffer {
+            size: 4,
+            memory: None,
+        });
+        let (_command_pool, command_buffer) = device.begin_command_buffer();
+        unsafe {
+            for &(buffer, offset, marker) in &[
+                (buffer.get_handle(), 0, 1),
+                (buffer.get_handle(), 4, 2),
+                (unbound_buffer.get_handle(), 0, 3),
+                (buffer.get_handle(), 8, 4),
+            ] {
+                vkCmdWriteBufferMarkerAMD(
+                    command_buffer,
+                    api::VK_PIPELINE_STAGE_TRANSFER_BIT,
+                    buffer,
+                    offset,
+                    marker,
+                );
+            }
+        }
+        let fence = OwnedHandle::<api::VkFence>::new(Fence {
+            signaled: AtomicBool::new(false),
+        });
+        assert_eq!(
+            device.submit(command_buffer, unsafe { fence.get_handle() }),
+            api::VK_ERROR_DEVICE_LOST
+        );
+        assert_eq!(unsafe { *markers }, [1, 2, !0, !0]);
+        assert!(!fence.signaled.load(Ordering::Acquire));
+        assert_eq!(
+            unsafe { vkQueueWaitIdle(device.queue) },
+            api::VK_ERROR_DEVICE_LOST
+        );
+        unsafe { vkDestroyDebugUtilsMessengerEXT(instance.instance, messenger, null()) };
+        let messages = messages.into_inner().unwrap();
+        let device_lost_messages: Vec<_> = messages
+            .iter()
+            .filter(|message| message.starts_with("kazan-device-lost: "))
+            .collect();
+        assert_eq!(device_lost_messages.len(), 1, "{:?}", messages);
+        assert!(
+            device_lost_messages[0]
+                .ends_with("; the last buffer marker written was 2, to offset 4 of the buffer"),
+            "{:?}",
+            messages
+        );
+    }
+
+    /// a device with `VK_AMD_buffer_marker` enabled that validates commands whether or not
+    /// `KAZAN_VALIDATION` is set, and that runs submissions with the null driver if
+    /// `null_driver` is set. the configuration is changed directly rather than by setting
+    /// `KAZAN_NULL_DRIVER`, since the tests share the environment
+    fn create_null_driver_device(instance: &TestInstance, null_driver: bool) -> TestDevice {
+        let physical_device = instance.get_physical_device();
+        unsafe {
+            MutHandle::from(physical_device)
+                .unwrap()
+                .configuration
+                .null_driver = null_driver;
+        }
+        let device = TestDevice::new(instance, &["VK_AMD_buffer_marker"]);
+        unsafe {
+            let messengers = &SharedHandle::from(physical_device)
+                .unwrap()
+                .debug_utils_messengers;
+            MutHandle::from(device.device).unwrap().validation =
+                Some(Arc::new(Validation::new(messengers)));
+        }
+        device
+    }
+
+    /// a compute pipeline made from `compute_module`, with an empty layout
+    fn create_compute_pipeline(
+        device: &TestDevice,
+    ) -> (
+        OwnedHandle<api::VkShaderModule>,
+        OwnedHandle<api::VkPipelineLayout>,
+        OwnedHandle<api::VkPipeline>,
+    ) {
+        let code = compute_module();
+        let mut shader_module = Handle::null();
+        let mut pipeline_layout = Handle::null();
+        let mut pipeline = Handle::null();
+        unsafe {
+            assert_eq!(
+                vkCreateShaderModule(
+                    device.device,
+                    &api::VkShaderModuleCreateInfo {
+                        sType: api::VK_STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO,
+                        pNext: null(),
+                        flags: 0,
+                        codeSize: code.len() * mem::size_of::<u32>(),
+                        pCode: code.as_ptr(),
+                    },
+                    null(),
+                    &mut shader_module,
+                ),
+                api::VK_SUCCESS
+            );
+            assert_eq!(
+                vkCreatePipelineLayout(
+                    device.device,
+                    &api::VkPipelineLayoutCreateInfo {
+                        sType: api::VK_STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
+                        pNext: null(),
+                        flags: 0,
+                        setLayoutCount: 0,
+                        pSetLayouts: null(),
+                        pushConstantRangeCount: 0,
+                        pPushConstantRanges: null(),
+                    },
+                    null(),
+                    &mut pipeline_layout,
+                ),
+                api::VK_SUCCESS
+            );
+            assert_eq!(
+                vkCreateComputePipelines(
+                    device.device,
+                    Handle::null(),
+                    1,
+                    &api::VkComputePipelineCreateInfo {
+                        sType: api::VK_STRUCTURE_TYPE_COMPUTE_PIPELINE_CREATE_INFO,
+                        pNext: null(),
+                        flags: 0,
+                        stage: api::VkPipelineShaderStageCreateInfo {
+                            sType: api::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+                            pNext: null(),
+                            flags: 0,
+                            stage: api::VK_SHADER_STAGE_COMPUTE_BIT,
+                            module: shader_module,
+                            pName: b"main\0".as_ptr() as *const c_char,
+                            pSpecializationInfo: null(),
+                        },
+                        layout: pipeline_layout,
+                        basePipelineHandle: Handle::null(),
+                        basePipelineIndex: -1,
+                    },
+                    null(),
+                    &mut pipeline,
+                ),
+                api::VK_SUCCESS
+            );
+            (
+                OwnedHandle::from(shader_module).unwrap(),
+                OwnedHandle::from(pipeline_layout).unwrap(),
+                OwnedHandle::from(pipeline).unwrap(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_null_driver() {
+        let instance = TestInstance::new(&["VK_EXT_debug_utils"]);
+        let messages = Mutex::new(Vec::<String>::new());
+        let messenger = create_messenger(&instance, &messages);
+        let devices = [
+            (create_null_driver_device(&instance, true), true),
+            (create_null_driver_device(&instance, false), false),
+        ];
+        for &(ref device, null_driver) in &devices {
+            let (buffer, memory) = device.create_buffer(12);
+            let mut markers = null_mut();
+            unsafe {
+                assert_eq!(
+                    vkMapMemory(device.device, memory.get_handle(), 0, 12, 0, &mut markers),
+                    api::VK_SUCCESS
+                );
+            }
+            let markers = markers as *mut [u32; 3];
+            unsafe { *markers = [!0; 3] };
+            let (_shader_module, _pipeline_layout, pipeline) = create_compute_pipeline(device);
+            let (_command_pool, command_buffer) = device.begin_command_buffer();
+            unsafe {
+                let write_marker = |offset, marker| {
+                    vkCmdWriteBufferMarkerAMD(
+                        command_buffer,
+                        api::VK_PIPELINE_STAGE_TRANSFER_BIT,
+                        buffer.get_handle(),
+                        offset,
+                        marker,
+                    )
+                };
+                // nothing is bound yet, so this dispatch is skipped after reporting an error
+                vkCmdDispatch(command_buffer, 1, 1, 1);
+                write_marker(0, 1);
+                vkCmdBindPipeline(
+                    command_buffer,
+                    api::VK_PIPELINE_BIND_POINT_COMPUTE,
+                    pipeline.get_handle(),
+                );
+                // a dispatch of no workgroups does nothing, even without the null driver
+                vkCmdDispatch(command_buffer, 0, 1, 1);
+                write_marker(4, 2);
+                vkCmdDispatch(command_buffer, 1, 1, 1);
+                write_marker(8, 3);
+            }
+            let fence = OwnedHandle::<api::VkFence>::new(Fence {
+                signaled: AtomicBool::new(false),
+            });
+            let result = device.submit(command_buffer, unsafe { fence.get_handle() });
+            let queue_result = unsafe { vkQueueWaitIdle(device.queue) };
+            if null_driver {
+                // the null driver skips the dispatches once it has checked that the pipeline
+                // is bound, and runs everything else
+                assert_eq!(result, api::VK_SUCCESS);
+                assert_eq!(unsafe { *markers }, [1, 2, 3]);
+                assert!(fence.signaled.load(Ordering::Acquire));
+                assert_eq!(queue_result, api::VK_SUCCESS);
+            } else {
+                // the first non-empty dispatch with a pipeline bound would run, which isn't
+                // implemented
+                assert_eq!(result, api::VK_ERROR_DEVICE_LOST);
+                assert_eq!(unsafe { *markers }, [1, 2, !0]);
+                assert!(!fence.signaled.load(Ordering::Acquire));
+                assert_eq!(queue_result, api::VK_ERROR_DEVICE_LOST);
+            }
+        }
+        unsafe { vkDestroyDebugUtilsMessengerEXT(instance.instance, messenger, null()) };
+        let messages = messages.into_inner().unwrap();
+        let no_pipeline_bound_message = format!(
+            "kazan-no-pipeline-bound: vkCmdDispatch: no pipeline is bound to pipeline bind \
+             point {}",
+            api::VK_PIPELINE_BIND_POINT_COMPUTE
+        );
+        assert_eq!(messages.len(), 3, "{:?}", messages);
+        assert_eq!(messages[0], no_pipeline_bound_message);
+        assert_eq!(messages[1], no_pipeline_bound_message);
+        assert!(
+            messages[2].starts_with("kazan-device-lost: ")
+                && messages[2].contains("vkCmdDispatch isn't implemented")
+                && messages[2]
+                    .ends_with("; the last buffer marker written was 2, to offset 4 of the buffer"),
+            "{:?}",
+            messages
+        );
+    }
+
+    /// the names of the extensions with scope `scope` that the driver reports
+    fn get_enumerated_extension_names(scope: ExtensionScope) -> Vec<String> {
+        let enumerate = |property_count: &mut u32, properties| unsafe {
+            match scope {
+                ExtensionScope::Instance => {
+                    vkEnumerateInstanceExtensionProperties(null(), property_count, properties)
+                }
+                ExtensionScope::Device => vkEnumerateDeviceExtensionProperties(
+                    Handle::null(),
+                    null(),
+                    property_count,
+                    properties,
+                ),
+            }
+        };
+        let mut property_count = 0;
+        assert_eq!(enumerate(&mut property_count, null_mut()), api::VK_SUCCESS);
+        let mut properties: Vec<api::VkExtensionProperties> =
+            vec![unsafe { mem::zeroed() }; property_count as usize];
+        assert_eq!(
+            enumerate(&mut property_count, properties.as_mut_ptr()),
+            api::VK_SUCCESS
+        );
+        assert_eq!(property_count as usize, properties.len());
+        properties
+            .iter()
+            .map(|properties| get_str(&properties.extensionName).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_wsi_extensions() {
+        // building with `--no-default-features` leaves out the window system integration
+        let wsi = cfg!(feature = "wsi");
+        assert_eq!(get_build_features().contains(&"wsi"), wsi);
+        let instance_extensions = get_enumerated_extension_names(ExtensionScope::Instance);
+        let device_extensions = get_enumerated_extension_names(ExtensionScope::Device);
+        for name in &["VK_KHR_surface", "VK_EXT_swapchain_colorspace"] {
+            assert_eq!(
+                instance_extensions.contains(&name.to_string()),
+                wsi,
+                "{}",
+                name
+            );
+        }
+        assert_eq!(
+            instance_extensions.contains(&"VK_KHR_xcb_surface".to_string()),
+            cfg!(all(unix, feature = "wsi-xcb"))
+        );
+        for name in &["VK_KHR_swapchain", "VK_GOOGLE_display_timing"] {
+            assert_eq!(
+                device_extensions.contains(&name.to_string()),
+                wsi,
+                "{}",
+                name
+            );
+        }
+        // the rest of the extensions don't depend on it
+        assert!(instance_extensions.contains(&"VK_EXT_debug_utils".to_string()));
+        assert!(device_extensions.contains(&"VK_AMD_buffer_marker".to_string()));
+    }
+
+    fn get_instance_proc_address(instance: &TestInstance, name: &str) -> Option<usize> {
+        let name = CString::new(name).unwrap();
+        unsafe { vkGetInstanceProcAddr(instance.instance, name.as_ptr()) }.map(|f| f as usize)
+    }
+
+    fn get_device_proc_address(device: &TestDevice, name: &str) -> Option<usize> {
+        let name = CString::new(name).unwrap();
+        unsafe { vkGetDeviceProcAddr(device.device, name.as_ptr()) }.map(|f| f as usize)
+    }
+
+    /// the names of the extensions in `extensions` with scope `scope`
+    fn get_extension_names(extensions: Extensions, scope: ExtensionScope) -> Vec<&'static str> {
+        extensions
+            .iter()
+            .filter(|&(extension, &enabled)| enabled && extension.get_scope() == scope)
+            .map(|(extension, _)| extension.get_name())
+            .collect()
+    }
+
+    #[test]
+    fn test_extension_commands() {
+        let instance = TestInstance::new(&[]);
+        // a device extension's instance command is available from the instance, since
+        // VK_EXT_tooling_info doesn't need any instance extensions
+        let command = "vkGetPhysicalDeviceToolPropertiesEXT";
+        assert!(get_instance_proc_address(&instance, command).is_some());
+        // device commands are only available from devices with the extension enabled, and
+        // instance commands never are
+        let device = TestDevice::new(&instance, &[]);
+        assert_eq!(
+            get_device_proc_address(&device, "vkCmdWriteBufferMarkerAMD"),
+            None
+        );
+        let marker_device = TestDevice::new(&instance, &["VK_AMD_buffer_marker"]);
+        assert!(get_device_proc_address(&marker_device, "vkCmdWriteBufferMarkerAMD").is_some());
+        let tooling_info_device = TestDevice::new(&instance, &["VK_EXT_tooling_info"]);
+        assert_eq!(get_device_proc_address(&tooling_info_device, command), None);
+        // extensions promoted to versions after the one the driver implements keep only their
+        // extension commands
+        let command = "vkCreateRenderPass2KHR";
+        let extensions = Extension::VK_KHR_create_renderpass2.get_recursively_required_extensions();
+        let renderpass2_instance =
+            TestInstance::new(&get_extension_names(extensions, ExtensionScope::Instance));
+        let renderpass2_device = TestDevice::new(
+            &renderpass2_instance,
+            &get_extension_names(extensions, ExtensionScope::Device),
+        );
+        assert!(get_device_proc_address(&renderpass2_device, command).is_some());
+        let device = TestDevice::new(&renderpass2_instance, &[]);
+        assert_eq!(get_device_proc_address(&device, command), None);
+        assert_eq!(
+            get_device_proc_address(&device, "vkCreateRenderPass2"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_promoted_commands() {
+        let implemented_version = make_api_version(1, 1, 0);
+        for (extension, _) in Extensions::default().iter() {
+            if extension.get_promoted_commands().is_empty() {
+                continue;
+            }
+            // the promoted names are only looked up in the core versions the driver implements
+            assert!(
+                extension.get_promoted_version().unwrap() <= implemented_version,
+                "{}",
+                extension.get_name()
+            );
+            let extensions = extension.get_recursively_required_extensions();
+            let plain_instance = TestInstance::new(&[]);
+            let instance =
+                TestInstance::new(&get_extension_names(extensions, ExtensionScope::Instance));
+            let plain_device = TestDevice::new(&instance, &[]);
+            let device = TestDevice::new(
+                &instance,
+                &get_extension_names(extensions, ExtensionScope::Device),
+            );
+            // the core name is always available, and the extension name is the same function
+            // once the extension is enabled
+            for &command in extension.get_promoted_commands() {
+                let extension_command = format!("{}KHR", command);
+                let (plain, enabled) = match extension.get_scope() {
+                    ExtensionScope::Instance => (
+                        [
+                            get_instance_proc_address(&plain_instance, command),
+                            get_instance_proc_address(&plain_instance, &extension_command),
+                        ],
+                        [
+                            get_instance_proc_address(&instance, command),
+                            get_instance_proc_address(&instance, &extension_command),
+                        ],
+                    ),
+                    ExtensionScope::Device => (
+                        [
+                            get_device_proc_address(&plain_device, command),
+                            get_device_proc_address(&plain_device, &extension_command),
+                        ],
+                        [
+                            get_device_proc_address(&device, command),
+                            get_device_proc_address(&device, &extension_command),
+                        ],
+                    ),
+                };
+                assert!(plain[0].is_some(), "{}", command);
+                assert_eq!(plain[1], None, "{}", extension_command);
+                assert_eq!(enabled, [plain[0]; 2], "{}", extension_command);
+            }
+        }
+    }
+
+    #[test]
+    fn test_feature_structs() {
+        let instance = TestInstance::new(&["VK_KHR_get_physical_device_properties2"]);
+        // extension feature structs are filled in wherever they are in the chain, including
+        // after structs that aren't from extensions
+        let mut image_robustness_features = api::VkPhysicalDeviceImageRobustnessFeaturesEXT {
+            sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_IMAGE_ROBUSTNESS_FEATURES_EXT,
+            pNext: null_mut(),
+            robustImageAccess: api::VK_FALSE,
+        };
+        let mut multiview_features: api::VkPhysicalDeviceMultiviewFeatures =
+            unsafe { mem::zeroed() };
+        multiview_features.sType = api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_MULTIVIEW_FEATURES;
+        multiview_features.multiview = api::VK_TRUE;
+        multiview_features.pNext = &mut image_robustness_features
+            as *mut api::VkPhysicalDeviceImageRobustnessFeaturesEXT
+            as *mut c_void;
+        let mut robustness_2_features = api::VkPhysicalDeviceRobustness2FeaturesEXT {
+            sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_ROBUSTNESS_2_FEATURES_EXT,
+            pNext: &mut multiview_features as *mut api::VkPhysicalDeviceMultiviewFeatures
+                as *mut c_void,
+            robustBufferAccess2: api::VK_TRUE,
+            robustImageAccess2: api::VK_FALSE,
+            nullDescriptor: api::VK_FALSE,
+        };
+        let mut features2: api::VkPhysicalDeviceFeatures2 = unsafe { mem::zeroed() };
+        features2.sType = api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_FEATURES_2;
+        features2.pNext = &mut robustness_2_features
+            as *mut api::VkPhysicalDeviceRobustness2FeaturesEXT
+            as *mut c_void;
+        unsafe { vkGetPhysicalDeviceFeatures2(instance.get_physical_device(), &mut features2) };
+        assert_eq!(robustness_2_features.robustBufferAccess2, api::VK_FALSE);
+        assert_eq!(robustness_2_features.robustImageAccess2, api::VK_TRUE);
+        assert_eq!(robustness_2_features.nullDescriptor, api::VK_TRUE);
+        assert_eq!(multiview_features.multiview, api::VK_FALSE);
+        assert_eq!(image_robustness_features.robustImageAccess, api::VK_TRUE);
+        assert_eq!(
+            multiview_features.pNext,
+            &mut image_robustness_features as *mut api::VkPhysicalDeviceImageRobustnessFeaturesEXT
+                as *mut c_void
+        );
+        // the features reported as supported can be enabled together, and an unsupported one
+        // in any struct of the chain fails device creation
+        let extensions = ["VK_EXT_robustness2", "VK_EXT_image_robustness"];
+        let next = &robustness_2_features as *const api::VkPhysicalDeviceRobustness2FeaturesEXT
+            as *const c_void;
+        let device = TestDevice::with_next(&instance, &extensions, next).unwrap();
+        let shared_device = unsafe { SharedHandle::from(device.device) }.unwrap();
+        let device_features = &shared_device.features;
+        assert_eq!(
+            device_features.robustness_2_features.robustImageAccess2,
+            api::VK_TRUE
+        );
+        assert_eq!(
+            device_features.image_robustness_features.robustImageAccess,
+            api::VK_TRUE
+        );
+        robustness_2_features.robustBufferAccess2 = api::VK_TRUE;
+        let next = &robustness_2_features as *const api::VkPhysicalDeviceRobustness2FeaturesEXT
+            as *const c_void;
+        assert_eq!(
+            TestDevice::with_next(&instance, &extensions, next).err(),
+            Some(api::VK_ERROR_FEATURE_NOT_PRESENT)
+        );
+    }
+
+    #[test]
+    fn test_format_features() {
+        // the core formats, then the formats VK_KHR_sampler_ycbcr_conversion adds
+        let formats = (api::VK_FORMAT_UNDEFINED..=api::VK_FORMAT_ASTC_12x12_SRGB_BLOCK)
+            .chain(api::VK_FORMAT_G8B8G8R8_422_UNORM..=api::VK_FORMAT_G16_B16_R16_3PLANE_444_UNORM);
+        for format in formats {
+            let properties = PhysicalDevice::get_format_properties(format);
+            let has_features = properties.linearTilingFeatures != 0
+                || properties.optimalTilingFeatures != 0
+                || properties.bufferFeatures != 0;
+            let format_info = format::get_format_info(format);
+            assert_eq!(has_features, format_info.is_some(), "format = {}", format);
+            // every format with features can be decoded and encoded
+            if let Some(format_info) = format_info {
+                let mut texel = vec![0; format_info.texel_size_in_bytes];
+                let value = format::decode_texel(format, &texel);
+                format::encode_texel(format, &mut texel, value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unimplemented_subsystems() {
+        // tessellation, geometry shaders, ray tracing and compressed formats aren't implemented,
+        // so every build leaves them out. they get cargo features like `wsi` once they are
+        let instance = TestInstance::new(&[]);
+        let mut features: api::VkPhysicalDeviceFeatures = unsafe { mem::zeroed() };
+        unsafe { vkGetPhysicalDeviceFeatures(instance.get_physical_device(), &mut features) };
+        for &(name, value) in &[
+            ("tessellationShader", features.tessellationShader),
+            ("geometryShader", features.geometryShader),
+            ("textureCompressionETC2", features.textureCompressionETC2),
+            (
+                "textureCompressionASTC_LDR",
+                features.textureCompressionASTC_LDR,
+            ),
+            ("textureCompressionBC", features.textureCompressionBC),
+        ] {
+            assert_eq!(value, api::VK_FALSE, "{}", name);
+        }
+        let device_extensions = get_enumerated_extension_names(ExtensionScope::Device);
+        for name in &[
+            "VK_KHR_acceleration_structure",
+            "VK_KHR_ray_tracing_pipeline",
+            "VK_KHR_ray_query",
+        ] {
+            assert!(!device_extensions.contains(&name.to_string()), "{}", name);
+        }
+        for format in api::VK_FORMAT_BC1_RGB_UNORM_BLOCK..=api::VK_FORMAT_ASTC_12x12_SRGB_BLOCK {
+            let properties = PhysicalDevice::get_format_properties(format);
+            assert_eq!(properties.optimalTilingFeatures, 0, "format = {}", format);
+            assert_eq!(properties.linearTilingFeatures, 0, "format = {}", format);
+            assert_eq!(properties.bufferFeatures, 0, "format = {}", format);
+        }
+    }
+}
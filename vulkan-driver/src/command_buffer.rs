@@ -0,0 +1,697 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+use api;
+use buffer::Buffer;
+use command_stream::{Command, CommandStream};
+use debug_utils::{DebugUtilsMessengers, MessageObject};
+use device_memory::DeviceMemoryAllocation;
+use handle::{OwnedHandle, SharedHandle};
+use mipmap;
+use query;
+use shader_compiler::abi::PushConstantBlock;
+use shader_compiler::descriptors::{self, Descriptor, DescriptorTable, MAX_DESCRIPTOR_SETS};
+use scratch_arena::ScratchArena;
+use shader_compiler::watchdog::{Watchdog, WatchdogTrip};
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::env;
+use std::error;
+use std::fmt;
+use std::ptr;
+use std::sync::Arc;
+use std::time::Instant;
+use trace::{PassTrace, TraceStage, Tracer};
+use validation::{command_buffer_object, RecordingState, Validation};
+use viewport::{DepthBias, Viewport};
+
+/// set to run submitted command buffers without drawing or dispatching, so the CPU overhead of
+/// the application and of kazan's command processing can be measured by themselves. commands
+/// are still validated and the state they set is still tracked
+pub const NULL_DRIVER_ENV_VAR: &str = "KAZAN_NULL_DRIVER";
+
+pub fn is_null_driver_enabled_in_environment() -> bool {
+    match env::var(NULL_DRIVER_ENV_VAR) {
+        Ok(ref v) => v != "" && v != "0",
+        Err(_) => false,
+    }
+}
+
+/// a marker written by `vkCmdWriteBufferMarkerAMD`
+#[derive(Copy, Clone, Debug)]
+pub struct BufferMarker {
+    pub buffer: api::VkBuffer,
+    pub offset: u64,
+    pub marker: u32,
+}
+
+/// why running a command buffer stopped before its end, which loses the device. release builds
+/// abort on panic, so failures in user content are returned rather than unwound
+#[derive(Clone, Debug)]
+pub enum ExecuteError {
+    /// the watchdog stopped the submission
+    WatchdogTrip(WatchdogTrip),
+    /// a command wrote to a buffer without memory bound to it
+    BufferWithoutMemory { command_name: &'static str },
+    /// kazan can't run the command yet; draws and dispatches can only be skipped with the null
+    /// driver
+    Unimplemented { command_name: &'static str },
+}
+
+impl fmt::Display for ExecuteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecuteError::WatchdogTrip(trip) => trip.fmt(f),
+            ExecuteError::BufferWithoutMemory { command_name } => {
+                write!(f, "{}: no memory is bound to the buffer", command_name)
+            }
+            ExecuteError::Unimplemented { command_name } => write!(
+                f,
+                "{} isn't implemented; set {}=1 to skip draws and dispatches",
+                command_name, NULL_DRIVER_ENV_VAR
+            ),
+        }
+    }
+}
+
+impl error::Error for ExecuteError {}
+
+impl From<WatchdogTrip> for ExecuteError {
+    fn from(v: WatchdogTrip) -> Self {
+        ExecuteError::WatchdogTrip(v)
+    }
+}
+
+unsafe fn get_buffer_pointer(
+    buffer: &Buffer,
+    offset: u64,
+    command_name: &'static str,
+) -> Result<*mut u8, ExecuteError> {
+    let memory = buffer
+        .memory
+        .as_ref()
+        .ok_or(ExecuteError::BufferWithoutMemory { command_name })?;
+    Ok(memory
+        .device_memory
+        .get()
+        .as_ptr()
+        .add(memory.offset + offset as usize))
+}
+
+unsafe fn write_buffer_marker(
+    buffer: &Buffer,
+    offset: u64,
+    marker: u32,
+) -> Result<(), ExecuteError> {
+    let pointer = get_buffer_pointer(buffer, offset, "vkCmdWriteBufferMarkerAMD")?;
+    ptr::write(pointer as *mut u32, marker);
+    Ok(())
+}
+
+/// the message of a caught panic
+pub fn get_panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "no panic message"
+    }
+}
+
+/// report that running `command_buffer` failed, along with `cause` and the last marker written
+/// before then, so hangs and crashes in user content can be
+/// narrowed down with `vkCmdWriteBufferMarkerAMD`
+pub fn report_device_lost(
+    messengers: &DebugUtilsMessengers,
+    command_buffer: api::VkCommandBuffer,
+    cause: &str,
+    last_buffer_marker: Option<BufferMarker>,
+) {
+    let mut objects = vec![command_buffer_object(command_buffer)];
+    let message = match last_buffer_marker {
+        Some(BufferMarker {
+            buffer,
+            offset,
+            marker,
+        }) => {
+            objects.push(MessageObject::new(api::VK_OBJECT_TYPE_BUFFER, buffer));
+            format!(
+                "device lost while running a command buffer ({}); the last buffer marker written \
+                 was {}, to offset {} of the buffer",
+                cause, marker, offset
+            )
+        }
+        None => format!(
+            "device lost while running a command buffer ({}); no buffer markers were written by \
+             the command buffer",
+            cause
+        ),
+    };
+    if messengers.is_empty() {
+        eprintln!("kazan error: [kazan-device-lost] {}", message);
+        return;
+    }
+    messengers.send(
+        api::VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT,
+        api::VK_DEBUG_UTILS_MESSAGE_TYPE_GENERAL_BIT_EXT,
+        "kazan-device-lost",
+        &message,
+        &objects,
+    );
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum CommandBufferState {
+    Initial,
+    Recording,
+    Executable,
+}
+
+pub struct CommandBuffer {
+    pub commands: CommandStream,
+    /// for temporary allocations while recording; freed when the command buffer is reset
+    pub scratch: ScratchArena,
+    state: CommandBufferState,
+    /// `Some` when validation is enabled
+    validation: Option<Arc<Validation>>,
+    /// only used when validation is enabled
+    recording_state: RecordingState,
+}
+
+impl CommandBuffer {
+    pub fn new(validation: Option<Arc<Validation>>) -> Self {
+        CommandBuffer {
+            commands: CommandStream::default(),
+            scratch: ScratchArena::default(),
+            state: CommandBufferState::Initial,
+            validation,
+            recording_state: RecordingState::default(),
+        }
+    }
+    pub fn reset(&mut self) {
+        self.commands.clear();
+        self.scratch.reset();
+        self.state = CommandBufferState::Initial;
+        self.recording_state = RecordingState::default();
+    }
+    pub fn begin(&mut self, command_buffer: api::VkCommandBuffer) {
+        if let Some(ref validation) = self.validation {
+            if self.state == CommandBufferState::Recording {
+                validation.error(
+                    "kazan-command-buffer-already-recording",
+                    "vkBeginCommandBuffer called on a command buffer that is already recording",
+                    &[command_buffer_object(command_buffer)],
+                );
+            }
+        }
+        self.reset();
+        self.state = CommandBufferState::Recording;
+    }
+    pub fn end(&mut self, command_buffer: api::VkCommandBuffer) {
+        if let Some(ref validation) = self.validation {
+            validation.check_recording(command_buffer, self.state, "vkEndCommandBuffer");
+            validation.check_render_pass_ended(command_buffer, &self.recording_state);
+        }
+        self.commands.finish();
+        self.state = CommandBufferState::Executable;
+    }
+    /// report if the command buffer isn't recording or if any of `objects` have been destroyed.
+    /// returns the validation to run the command's other checks with, or `None` if validation is
+    /// disabled or the command can't look at `objects`.
+    pub fn validate_command(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        command_name: &str,
+        objects: &[MessageObject],
+    ) -> Option<&Validation> {
+        let validation: &Validation = self.validation.as_ref()?;
+        if check_command(
+            validation,
+            self.state,
+            command_buffer,
+            command_name,
+            objects,
+        ) {
+            Some(validation)
+        } else {
+            None
+        }
+    }
+    /// `validate_command` for commands that check or change what validation keeps track of
+    /// while recording, like the current render pass instance and image layouts
+    pub fn validate_command_mut(
+        &mut self,
+        command_buffer: api::VkCommandBuffer,
+        command_name: &str,
+        objects: &[MessageObject],
+    ) -> Option<(&Validation, &mut RecordingState)> {
+        let validation: &Validation = self.validation.as_ref()?;
+        if check_command(
+            validation,
+            self.state,
+            command_buffer,
+            command_name,
+            objects,
+        ) {
+            Some((validation, &mut self.recording_state))
+        } else {
+            None
+        }
+    }
+    /// check that the command buffer can be submitted; returns false if it can't.
+    /// always returns true if validation is disabled.
+    pub unsafe fn validate_submit(&self, command_buffer: api::VkCommandBuffer) -> bool {
+        let validation = match self.validation {
+            Some(ref validation) => validation,
+            None => return true,
+        };
+        if self.state != CommandBufferState::Executable {
+            validation.error(
+                "kazan-command-buffer-not-executable",
+                &format!(
+                    "vkQueueSubmit: command buffer is not executable (state is {:?})",
+                    self.state
+                ),
+                &[command_buffer_object(command_buffer)],
+            );
+            return false;
+        }
+        let mut is_valid = true;
+        for object in self.commands.referenced_objects() {
+            is_valid &= validation.check_live(command_buffer, object, "vkQueueSubmit");
+        }
+        is_valid
+    }
+    /// run the commands on the current thread. with `null_driver`, draws and dispatches
+    /// only check that what they use is bound. `last_buffer_marker` is updated as markers are
+    /// written, so it's still up to date if a command fails. stops before the next command
+    /// once `watchdog` is tripped, which includes right after the draw or dispatch that tripped it.
+    /// the command buffer and its passes are timed with `tracer`, and timestamps are relative to
+    /// `timestamp_epoch`
+    pub unsafe fn execute(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        null_driver: bool,
+        watchdog: &Watchdog,
+        last_buffer_marker: &mut Option<BufferMarker>,
+        tracer: Option<&Tracer>,
+        timestamp_epoch: Instant,
+    ) -> Result<(), ExecuteError> {
+        let command_buffer_trace = PassTrace::begin(tracer, "command buffer");
+        let mut bound_state = BoundState::default();
+        for command in self.commands.iter() {
+            if let Some(trip) = watchdog.get_trip() {
+                return Err(trip.into());
+            }
+            let (pipeline_bind_point, command_name, is_empty) = match command {
+                Command::WriteBufferMarker {
+                    buffer,
+                    offset,
+                    marker,
+                } => {
+                    write_buffer_marker(&buffer, offset, marker)?;
+                    *last_buffer_marker = Some(BufferMarker {
+                        buffer: buffer.get_handle(),
+                        offset,
+                        marker,
+                    });
+                    continue;
+                }
+                Command::GenerateMipmaps {
+                    image,
+                    subresource_range,
+                    filter,
+                } => {
+                    // generating mipmaps is kazan's own dispatch, so the null driver skips it
+                    if !null_driver {
+                        let mut pass = PassTrace::begin(tracer, "vkCmdGenerateMipmapsKAZAN");
+                        pass.time(TraceStage::Dispatch, || {
+                            mipmap::generate_mipmaps(image, subresource_range, filter)
+                        });
+                        pass.end();
+                    }
+                    continue;
+                }
+                Command::ResetQueryPool {
+                    query_pool,
+                    first_query,
+                    query_count,
+                } => {
+                    query_pool.reset(first_query, query_count);
+                    continue;
+                }
+                Command::BeginQuery { query_pool, query } => {
+                    query_pool.begin_query(query);
+                    continue;
+                }
+                Command::EndQuery { query_pool, query } => {
+                    query_pool.end_query(query);
+                    continue;
+                }
+                Command::WriteTimestamp { query_pool, query } => {
+                    query_pool.write_timestamp(
+                        query,
+                        query::get_timestamp(timestamp_epoch, Instant::now()),
+                    );
+                    continue;
+                }
+                Command::CopyQueryPoolResults {
+                    query_pool,
+                    first_query,
+                    query_count,
+                    buffer,
+                    offset,
+                    stride,
+                    flags,
+                } => {
+                    // unavailable queries are left out, like vkGetQueryPoolResults does
+                    query_pool.write_results(
+                        first_query,
+                        query_count,
+                        get_buffer_pointer(&buffer, offset, "vkCmdCopyQueryPoolResults")?,
+                        stride as usize,
+                        flags,
+                    );
+                    continue;
+                }
+                Command::Draw {
+                    vertex_count,
+                    instance_count,
+                    ..
+                } => (
+                    api::VK_PIPELINE_BIND_POINT_GRAPHICS,
+                    "vkCmdDraw",
+                    vertex_count == 0 || instance_count == 0,
+                ),
+                Command::DrawIndexed {
+                    index_count,
+                    instance_count,
+                    ..
+                } => (
+                    api::VK_PIPELINE_BIND_POINT_GRAPHICS,
+                    "vkCmdDrawIndexed",
+                    index_count == 0 || instance_count == 0,
+                ),
+                Command::Dispatch { group_count } => (
+                    api::VK_PIPELINE_BIND_POINT_COMPUTE,
+                    "vkCmdDispatch",
+                    group_count.contains(&0),
+                ),
+                _ => {
+                    bound_state.execute(&command);
+                    continue;
+                }
+            };
+            if let Some(ref validation) = self.validation {
+                if !bound_state.validate(
+                    validation,
+                    command_buffer,
+                    pipeline_bind_point,
+                    command_name,
+                ) {
+                    continue;
+                }
+            }
+            // draws and dispatches with nothing to run are still checked, but do nothing
+            if is_empty {
+                continue;
+            }
+            if !null_driver {
+                return Err(ExecuteError::Unimplemented { command_name });
+            }
+        }
+        command_buffer_trace.end();
+        match watchdog.get_trip() {
+            Some(trip) => Err(trip.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn check_command(
+    validation: &Validation,
+    state: CommandBufferState,
+    command_buffer: api::VkCommandBuffer,
+    command_name: &str,
+    objects: &[MessageObject],
+) -> bool {
+    validation.check_recording(command_buffer, state, command_name);
+    let mut is_live = true;
+    for &object in objects {
+        is_live &= validation.check_live(command_buffer, object, command_name);
+    }
+    is_live
+}
+
+pub struct CommandPool {
+    command_buffers: Vec<OwnedHandle<api::VkCommandBuffer>>,
+    validation: Option<Arc<Validation>>,
+}
+
+impl CommandPool {
+    pub fn new(validation: Option<Arc<Validation>>) -> Self {
+        CommandPool {
+            command_buffers: Vec::new(),
+            validation,
+        }
+    }
+    pub unsafe fn allocate(&mut self) -> api::VkCommandBuffer {
+        let command_buffer =
+            OwnedHandle::<api::VkCommandBuffer>::new(CommandBuffer::new(self.validation.clone()));
+        let retval = command_buffer.get_handle();
+        self.command_buffers.push(command_buffer);
+        retval
+    }
+    pub unsafe fn free(&mut self, command_buffer: api::VkCommandBuffer) {
+        if let Some(index) = self
+            .command_buffers
+            .iter()
+            .position(|v| v.get_handle() == command_buffer)
+        {
+            self.command_buffers.swap_remove(index);
+        }
+    }
+    pub fn reset(&mut self) {
+        for command_buffer in &mut self.command_buffers {
+            command_buffer.reset();
+        }
+    }
+}
+
+struct BoundDescriptorSet<'a> {
+    descriptor_set: SharedHandle<api::VkDescriptorSet>,
+    /// borrowed from the command stream, which already has a copy
+    dynamic_offsets: &'a [u32],
+}
+
+/// the memory that a `DescriptorTable` points to, allocated from a `ScratchArena`
+pub struct DescriptorTableStorage<'a> {
+    /// the descriptors of all the bound descriptor sets, one after another
+    descriptors: &'a [Descriptor],
+    descriptor_set_offsets: [u32; MAX_DESCRIPTOR_SETS],
+    push_constants: &'a PushConstantBlock,
+}
+
+impl<'a> DescriptorTableStorage<'a> {
+    /// the returned table is valid as long as `self` isn't dropped
+    #[allow(dead_code)]
+    pub fn table(&self) -> DescriptorTable {
+        DescriptorTable::new(
+            self.descriptors.as_ptr(),
+            self.descriptor_set_offsets,
+            self.push_constants.bytes.as_ptr(),
+        )
+    }
+}
+
+/// the state set by commands, used when running draws and dispatches. borrows from the
+/// command stream it's running
+#[allow(dead_code)]
+#[derive(Default)]
+pub struct BoundState<'a> {
+    pub graphics_pipeline: Option<SharedHandle<api::VkPipeline>>,
+    pub compute_pipeline: Option<SharedHandle<api::VkPipeline>>,
+    graphics_descriptor_sets: Vec<Option<BoundDescriptorSet<'a>>>,
+    compute_descriptor_sets: Vec<Option<BoundDescriptorSet<'a>>>,
+    push_constants: PushConstantBlock,
+    /// used when the bound graphics pipeline's viewports are dynamic state
+    viewports: Vec<Viewport>,
+    /// used when the bound graphics pipeline's depth bias is dynamic state
+    depth_bias: DepthBias,
+}
+
+#[allow(dead_code)]
+impl<'a> BoundState<'a> {
+    fn descriptor_sets_mut(
+        &mut self,
+        pipeline_bind_point: api::VkPipelineBindPoint,
+    ) -> &mut Vec<Option<BoundDescriptorSet<'a>>> {
+        match pipeline_bind_point {
+            api::VK_PIPELINE_BIND_POINT_GRAPHICS => &mut self.graphics_descriptor_sets,
+            api::VK_PIPELINE_BIND_POINT_COMPUTE => &mut self.compute_descriptor_sets,
+            _ => unreachable!("invalid pipeline bind point: {}", pipeline_bind_point),
+        }
+    }
+    pub fn execute(&mut self, command: &Command<'a>) {
+        match command {
+            Command::BindPipeline {
+                pipeline_bind_point,
+                pipeline,
+            } => match *pipeline_bind_point {
+                api::VK_PIPELINE_BIND_POINT_GRAPHICS => self.graphics_pipeline = Some(*pipeline),
+                api::VK_PIPELINE_BIND_POINT_COMPUTE => self.compute_pipeline = Some(*pipeline),
+                _ => unreachable!("invalid pipeline bind point: {}", pipeline_bind_point),
+            },
+            Command::BindDescriptorSets {
+                pipeline_bind_point,
+                first_set,
+                descriptor_sets,
+            } => {
+                let bound_descriptor_sets = self.descriptor_sets_mut(*pipeline_bind_point);
+                for (index, (descriptor_set, dynamic_offsets)) in
+                    descriptor_sets.clone().enumerate()
+                {
+                    let index = *first_set as usize + index;
+                    while bound_descriptor_sets.len() <= index {
+                        bound_descriptor_sets.push(None);
+                    }
+                    bound_descriptor_sets[index] = Some(BoundDescriptorSet {
+                        descriptor_set,
+                        dynamic_offsets,
+                    });
+                }
+            }
+            Command::PushConstants { offset, values } => {
+                let end = *offset as usize + values.len();
+                self.push_constants.bytes[*offset as usize..end].copy_from_slice(values);
+            }
+            Command::SetViewport {
+                first_viewport,
+                viewports,
+            } => {
+                let end = *first_viewport as usize + viewports.len();
+                if self.viewports.len() < end {
+                    self.viewports.resize(end, viewports[0]);
+                }
+                self.viewports[*first_viewport as usize..end].copy_from_slice(viewports);
+            }
+            Command::SetDepthBias(depth_bias) => self.depth_bias = *depth_bias,
+            // these don't change the bound state
+            Command::Draw { .. }
+            | Command::DrawIndexed { .. }
+            | Command::Dispatch { .. }
+            | Command::WriteBufferMarker { .. }
+            | Command::GenerateMipmaps { .. }
+            | Command::ResetQueryPool { .. }
+            | Command::WriteTimestamp { .. }
+            | Command::CopyQueryPoolResults { .. }
+            | Command::BeginQuery { .. }
+            | Command::EndQuery { .. } => {}
+        }
+    }
+    fn bound(
+        &self,
+        pipeline_bind_point: api::VkPipelineBindPoint,
+    ) -> (
+        Option<SharedHandle<api::VkPipeline>>,
+        &[Option<BoundDescriptorSet<'a>>],
+    ) {
+        match pipeline_bind_point {
+            api::VK_PIPELINE_BIND_POINT_GRAPHICS => {
+                (self.graphics_pipeline, &self.graphics_descriptor_sets)
+            }
+            api::VK_PIPELINE_BIND_POINT_COMPUTE => {
+                (self.compute_pipeline, &self.compute_descriptor_sets)
+            }
+            _ => unreachable!("invalid pipeline bind point: {}", pipeline_bind_point),
+        }
+    }
+    /// check that everything used by a draw or dispatch with the pipeline bound to
+    /// `pipeline_bind_point` has been bound; returns false if anything is missing
+    pub fn validate(
+        &self,
+        validation: &Validation,
+        command_buffer: api::VkCommandBuffer,
+        pipeline_bind_point: api::VkPipelineBindPoint,
+        command_name: &str,
+    ) -> bool {
+        let (pipeline, bound_descriptor_sets) = self.bound(pipeline_bind_point);
+        let pipeline = match pipeline {
+            Some(pipeline) => pipeline,
+            None => {
+                validation.error(
+                    "kazan-no-pipeline-bound",
+                    &format!(
+                        "{}: no pipeline is bound to pipeline bind point {}",
+                        command_name, pipeline_bind_point
+                    ),
+                    &[command_buffer_object(command_buffer)],
+                );
+                return false;
+            }
+        };
+        let used_sets: BTreeSet<_> = pipeline
+            .descriptor_bindings()
+            .iter()
+            .map(|binding| binding.descriptor_set as usize)
+            .collect();
+        validation.check_bound_descriptor_sets(
+            command_buffer,
+            command_name,
+            pipeline.layout(),
+            used_sets,
+            |index| match bound_descriptor_sets.get(index) {
+                Some(Some(bound_descriptor_set)) => {
+                    Some(&bound_descriptor_set.descriptor_set.layout)
+                }
+                _ => None,
+            },
+        )
+    }
+    /// build the `DescriptorTable` for a draw or dispatch with the pipeline bound to
+    /// `pipeline_bind_point`, snapshotting the descriptors and push constants into `scratch`
+    pub fn build_descriptor_table<'b>(
+        &self,
+        pipeline_bind_point: api::VkPipelineBindPoint,
+        scratch: &'b ScratchArena,
+    ) -> DescriptorTableStorage<'b> {
+        let (pipeline, bound_descriptor_sets) = self.bound(pipeline_bind_point);
+        let pipeline = pipeline.expect("no pipeline bound");
+        let layout: &descriptors::PipelineLayout = pipeline.layout();
+        let set_count = layout.descriptor_sets.len().min(bound_descriptor_sets.len());
+        let bound_sets = || {
+            bound_descriptor_sets[..set_count]
+                .iter()
+                .enumerate()
+                .filter_map(|(index, v)| v.as_ref().map(|v| (index, v)))
+        };
+        let mut descriptor_set_offsets = [0; MAX_DESCRIPTOR_SETS];
+        let mut descriptor_count = 0;
+        for (index, bound_descriptor_set) in bound_sets() {
+            descriptor_set_offsets[index] = descriptor_count as u32;
+            descriptor_count += bound_descriptor_set.descriptor_set.descriptors().len();
+        }
+        let descriptors = scratch.alloc_slice_from_iter(
+            descriptor_count,
+            bound_sets()
+                .flat_map(|(_, v)| v.descriptor_set.descriptors())
+                .cloned(),
+        );
+        for (index, bound_descriptor_set) in bound_sets() {
+            let offset = descriptor_set_offsets[index] as usize;
+            for (index, &dynamic_offset) in bound_descriptor_set
+                .descriptor_set
+                .dynamic_descriptor_indices()
+                .zip(bound_descriptor_set.dynamic_offsets)
+            {
+                let descriptor = &mut descriptors[offset + index];
+                *descriptor = descriptor.with_dynamic_offset(dynamic_offset);
+            }
+        }
+        DescriptorTableStorage {
+            descriptors,
+            descriptor_set_offsets,
+            push_constants: scratch.alloc(self.push_constants),
+        }
+    }
+}
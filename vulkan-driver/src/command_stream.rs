@@ -0,0 +1,746 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! the compact encoding of recorded commands
+//!
+//! commands are encoded into a single arena of words that's reused when the command buffer is
+//! reset, rather than stored as a `Vec` of enums that each own their heap allocations. each
+//! record is a header word, with the opcode in the low 8 bits and the payload's length in words
+//! in the rest, followed by the payload. handles are stored in side tables and referenced by
+//! index, and draw and dispatch records are just their packed arguments.
+//!
+//! the state-setting commands between two draws or dispatches are a run of state deltas. when a
+//! run ends, it's sorted by opcode, keeping the recorded order of deltas with the same opcode, and
+//! deltas that a later delta in the run completely overrides are dropped. deltas with different
+//! opcodes set disjoint state, so that doesn't change the state any draw sees, and running the
+//! deltas grouped by kind keeps the decoder's branches predictable.
+
+use api;
+use debug_utils::MessageObject;
+use handle::SharedHandle;
+use std::mem;
+use std::ptr;
+use std::slice;
+use validation::{descriptor_set_object, pipeline_object};
+use viewport::{DepthBias, DepthBiasRepresentation, Viewport};
+
+/// state deltas are sorted by opcode, so this is the order they run in
+mod opcode {
+    pub const BIND_PIPELINE: u8 = 0;
+    pub const BIND_DESCRIPTOR_SETS: u8 = 1;
+    pub const PUSH_CONSTANTS: u8 = 2;
+    pub const SET_VIEWPORT: u8 = 3;
+    pub const SET_DEPTH_BIAS: u8 = 4;
+    /// opcodes from here on end runs of state deltas
+    pub const FIRST_ACTION: u8 = 0x80;
+    pub const DRAW: u8 = 0x80;
+    pub const DRAW_INDEXED: u8 = 0x81;
+    pub const DISPATCH: u8 = 0x82;
+    pub const WRITE_BUFFER_MARKER: u8 = 0x83;
+    pub const GENERATE_MIPMAPS: u8 = 0x84;
+    pub const RESET_QUERY_POOL: u8 = 0x85;
+    pub const WRITE_TIMESTAMP: u8 = 0x86;
+    pub const COPY_QUERY_POOL_RESULTS: u8 = 0x87;
+    pub const BEGIN_QUERY: u8 = 0x88;
+    pub const END_QUERY: u8 = 0x89;
+}
+
+/// the number of payload words that identify what state a delta sets: a later delta with the
+/// same opcode and the same identifying words completely overrides an earlier one
+fn get_state_key_length(opcode: u8) -> usize {
+    match opcode {
+        // bind point
+        opcode::BIND_PIPELINE => 1,
+        // bind point, first set and set count
+        opcode::BIND_DESCRIPTOR_SETS => 3,
+        // offset and size
+        opcode::PUSH_CONSTANTS => 2,
+        // first viewport and viewport count
+        opcode::SET_VIEWPORT => 2,
+        opcode::SET_DEPTH_BIAS => 0,
+        _ => unreachable!("not a state delta: {:#x}", opcode),
+    }
+}
+
+fn encode_depth_bias_representation(representation: DepthBiasRepresentation) -> u32 {
+    match representation {
+        DepthBiasRepresentation::LeastRepresentableValueFormat => 0,
+        DepthBiasRepresentation::LeastRepresentableValueForceUnorm => 1,
+        DepthBiasRepresentation::Float => 2,
+    }
+}
+
+fn decode_depth_bias_representation(representation: u32) -> DepthBiasRepresentation {
+    match representation {
+        0 => DepthBiasRepresentation::LeastRepresentableValueFormat,
+        1 => DepthBiasRepresentation::LeastRepresentableValueForceUnorm,
+        2 => DepthBiasRepresentation::Float,
+        _ => unreachable!(),
+    }
+}
+
+/// the number of words needed to hold `count` `T`s
+fn get_word_count<T: Copy>(count: usize) -> usize {
+    assert!(mem::align_of::<T>() <= mem::align_of::<u32>());
+    (count * mem::size_of::<T>()).div_ceil(mem::size_of::<u32>())
+}
+
+/// `T` has to be plain data, with no padding or pointers
+unsafe fn read_plain_data<T: Copy>(words: &[u32], count: usize) -> &[T] {
+    assert!(get_word_count::<T>(count) <= words.len());
+    slice::from_raw_parts(words.as_ptr() as *const T, count)
+}
+
+/// a decoded command, borrowing its variable-length arguments from the stream
+pub enum Command<'a> {
+    BindPipeline {
+        pipeline_bind_point: api::VkPipelineBindPoint,
+        pipeline: SharedHandle<api::VkPipeline>,
+    },
+    BindDescriptorSets {
+        pipeline_bind_point: api::VkPipelineBindPoint,
+        first_set: u32,
+        descriptor_sets: DescriptorSets<'a>,
+    },
+    PushConstants {
+        offset: u32,
+        values: &'a [u8],
+    },
+    SetViewport {
+        first_viewport: u32,
+        viewports: &'a [Viewport],
+    },
+    SetDepthBias(DepthBias),
+    /// draws and dispatches with a count of zero are skipped; otherwise only the null driver
+    /// runs them so far, which ignores the rest of the arguments
+    Draw {
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    },
+    DrawIndexed {
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    },
+    Dispatch {
+        group_count: [u32; 3],
+    },
+    WriteBufferMarker {
+        buffer: SharedHandle<api::VkBuffer>,
+        offset: u64,
+        marker: u32,
+    },
+    GenerateMipmaps {
+        image: SharedHandle<api::VkImage>,
+        subresource_range: api::VkImageSubresourceRange,
+        filter: api::VkFilter,
+    },
+    ResetQueryPool {
+        query_pool: SharedHandle<api::VkQueryPool>,
+        first_query: u32,
+        query_count: u32,
+    },
+    WriteTimestamp {
+        query_pool: SharedHandle<api::VkQueryPool>,
+        query: u32,
+    },
+    CopyQueryPoolResults {
+        query_pool: SharedHandle<api::VkQueryPool>,
+        first_query: u32,
+        query_count: u32,
+        buffer: SharedHandle<api::VkBuffer>,
+        offset: u64,
+        stride: u64,
+        flags: api::VkQueryResultFlags,
+    },
+    BeginQuery {
+        query_pool: SharedHandle<api::VkQueryPool>,
+        query: u32,
+    },
+    EndQuery {
+        query_pool: SharedHandle<api::VkQueryPool>,
+        query: u32,
+    },
+}
+
+/// the descriptor sets of a `BindDescriptorSets` command, with each set's dynamic offsets
+#[derive(Clone)]
+pub struct DescriptorSets<'a> {
+    stream: &'a CommandStream,
+    words: &'a [u32],
+    remaining: u32,
+}
+
+impl<'a> Iterator for DescriptorSets<'a> {
+    type Item = (SharedHandle<api::VkDescriptorSet>, &'a [u32]);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let descriptor_set = self.stream.descriptor_sets[self.words[0] as usize];
+        let dynamic_offset_count = self.words[1] as usize;
+        let dynamic_offsets = &self.words[2..2 + dynamic_offset_count];
+        self.words = &self.words[2 + dynamic_offset_count..];
+        Some((descriptor_set, dynamic_offsets))
+    }
+}
+
+#[derive(Default)]
+pub struct CommandStream {
+    words: Vec<u32>,
+    pipelines: Vec<SharedHandle<api::VkPipeline>>,
+    descriptor_sets: Vec<SharedHandle<api::VkDescriptorSet>>,
+    buffers: Vec<SharedHandle<api::VkBuffer>>,
+    images: Vec<SharedHandle<api::VkImage>>,
+    query_pools: Vec<SharedHandle<api::VkQueryPool>>,
+    /// the index in `words` of the first record of the current run of state deltas
+    state_run_start: usize,
+    /// reused by `finish_state_run`; the opcode, start and end of each record in the run
+    state_run_records: Vec<(u8, usize, usize)>,
+    /// reused by `finish_state_run`
+    state_run_words: Vec<u32>,
+    /// whether any command has to run in submission order relative to other command buffers
+    has_ordered_commands: bool,
+}
+
+impl CommandStream {
+    /// remove all the commands, keeping the memory for the next recording
+    pub fn clear(&mut self) {
+        self.words.clear();
+        self.pipelines.clear();
+        self.descriptor_sets.clear();
+        self.buffers.clear();
+        self.images.clear();
+        self.query_pools.clear();
+        self.state_run_start = 0;
+        self.has_ordered_commands = false;
+    }
+    /// whether the command buffer has commands that have to run after every command buffer
+    /// submitted before it and before every one submitted after it, such as pipeline barriers
+    /// and writes to queries and buffer markers, which are ordered by submission
+    pub fn has_ordered_commands(&self) -> bool {
+        self.has_ordered_commands
+    }
+    /// commands are run in order, so a pipeline barrier isn't encoded, but it orders this command
+    /// buffer relative to the other command buffers of its submission
+    pub fn pipeline_barrier(&mut self) {
+        self.has_ordered_commands = true;
+    }
+    /// the number of words used by the encoded commands
+    #[allow(dead_code)]
+    pub fn len_in_words(&self) -> usize {
+        self.words.len()
+    }
+    fn begin_record(&mut self, opcode: u8) -> usize {
+        if opcode >= opcode::FIRST_ACTION {
+            self.finish_state_run();
+        }
+        let header_index = self.words.len();
+        self.words.push(u32::from(opcode));
+        header_index
+    }
+    fn end_record(&mut self, header_index: usize) {
+        let payload_length = self.words.len() - header_index - 1;
+        assert!(payload_length < 1 << 24, "command too big");
+        self.words[header_index] |= (payload_length as u32) << 8;
+        if self.words[header_index] as u8 >= opcode::FIRST_ACTION {
+            self.state_run_start = self.words.len();
+        }
+    }
+    fn push_record(&mut self, opcode: u8, payload: &[u32]) {
+        let header_index = self.begin_record(opcode);
+        self.words.extend_from_slice(payload);
+        self.end_record(header_index);
+    }
+    /// `T` has to be plain data, with no padding or pointers
+    unsafe fn push_plain_data<T: Copy>(&mut self, values: &[T]) {
+        let start = self.words.len();
+        self.words
+            .resize(start + get_word_count::<T>(values.len()), 0);
+        ptr::copy_nonoverlapping(
+            values.as_ptr() as *const u8,
+            self.words[start..].as_mut_ptr() as *mut u8,
+            mem::size_of_val(values),
+        );
+    }
+    /// sort the current run of state deltas and drop the overridden deltas
+    fn finish_state_run(&mut self) {
+        let mut records = mem::take(&mut self.state_run_records);
+        records.clear();
+        let mut position = self.state_run_start;
+        while position < self.words.len() {
+            let header = self.words[position];
+            let end = position + 1 + (header >> 8) as usize;
+            records.push((header as u8, position, end));
+            position = end;
+        }
+        if records.len() > 1 {
+            // stable, so deltas with the same opcode stay in the recorded order
+            records.sort_by_key(|&(opcode, _, _)| opcode);
+            let mut words = mem::take(&mut self.state_run_words);
+            words.clear();
+            for (index, &(opcode, start, end)) in records.iter().enumerate() {
+                let key_length = get_state_key_length(opcode);
+                let key = &self.words[start + 1..start + 1 + key_length];
+                let is_overridden = records[index + 1..]
+                    .iter()
+                    .take_while(|&&(later_opcode, _, _)| later_opcode == opcode)
+                    .any(|&(_, later_start, _)| {
+                        *key == self.words[later_start + 1..later_start + 1 + key_length]
+                    });
+                if !is_overridden {
+                    words.extend_from_slice(&self.words[start..end]);
+                }
+            }
+            self.words.truncate(self.state_run_start);
+            self.words.extend_from_slice(&words);
+            self.state_run_words = words;
+        }
+        self.state_run_records = records;
+    }
+    /// finish recording; has to be called before the commands are read
+    pub fn finish(&mut self) {
+        self.finish_state_run();
+        self.state_run_start = self.words.len();
+    }
+    pub fn bind_pipeline(
+        &mut self,
+        pipeline_bind_point: api::VkPipelineBindPoint,
+        pipeline: SharedHandle<api::VkPipeline>,
+    ) {
+        let index = self.pipelines.len() as u32;
+        self.pipelines.push(pipeline);
+        self.push_record(opcode::BIND_PIPELINE, &[pipeline_bind_point, index]);
+    }
+    /// each descriptor set comes with its dynamic offsets
+    pub fn bind_descriptor_sets<'a, I>(
+        &mut self,
+        pipeline_bind_point: api::VkPipelineBindPoint,
+        first_set: u32,
+        descriptor_sets: I,
+    ) where
+        I: IntoIterator<Item = (SharedHandle<api::VkDescriptorSet>, &'a [u32])>,
+    {
+        let header_index = self.begin_record(opcode::BIND_DESCRIPTOR_SETS);
+        self.words
+            .extend_from_slice(&[pipeline_bind_point, first_set, 0]);
+        let mut set_count = 0;
+        for (descriptor_set, dynamic_offsets) in descriptor_sets {
+            let index = self.descriptor_sets.len() as u32;
+            self.descriptor_sets.push(descriptor_set);
+            self.words.push(index);
+            self.words.push(dynamic_offsets.len() as u32);
+            self.words.extend_from_slice(dynamic_offsets);
+            set_count += 1;
+        }
+        self.words[header_index + 3] = set_count;
+        self.end_record(header_index);
+    }
+    pub fn push_constants(&mut self, offset: u32, values: &[u8]) {
+        let header_index = self.begin_record(opcode::PUSH_CONSTANTS);
+        self.words.extend_from_slice(&[offset, values.len() as u32]);
+        unsafe { self.push_plain_data(values) };
+        self.end_record(header_index);
+    }
+    pub fn set_viewport<I: IntoIterator<Item = Viewport>>(
+        &mut self,
+        first_viewport: u32,
+        viewports: I,
+    ) {
+        let header_index = self.begin_record(opcode::SET_VIEWPORT);
+        self.words.extend_from_slice(&[first_viewport, 0]);
+        let mut viewport_count = 0;
+        for viewport in viewports {
+            unsafe { self.push_plain_data(slice::from_ref(&viewport)) };
+            viewport_count += 1;
+        }
+        self.words[header_index + 2] = viewport_count;
+        self.end_record(header_index);
+    }
+    pub fn set_depth_bias(&mut self, depth_bias: DepthBias) {
+        self.push_record(
+            opcode::SET_DEPTH_BIAS,
+            &[
+                depth_bias.constant_factor.to_bits(),
+                depth_bias.clamp.to_bits(),
+                depth_bias.slope_factor.to_bits(),
+                encode_depth_bias_representation(depth_bias.representation),
+            ],
+        );
+    }
+    pub fn draw(
+        &mut self,
+        vertex_count: u32,
+        instance_count: u32,
+        first_vertex: u32,
+        first_instance: u32,
+    ) {
+        self.push_record(
+            opcode::DRAW,
+            &[vertex_count, instance_count, first_vertex, first_instance],
+        );
+    }
+    pub fn draw_indexed(
+        &mut self,
+        index_count: u32,
+        instance_count: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+    ) {
+        self.push_record(
+            opcode::DRAW_INDEXED,
+            &[
+                index_count,
+                instance_count,
+                first_index,
+                vertex_offset as u32,
+                first_instance,
+            ],
+        );
+    }
+    pub fn dispatch(&mut self, group_count: [u32; 3]) {
+        self.push_record(opcode::DISPATCH, &group_count);
+    }
+    pub fn write_buffer_marker(
+        &mut self,
+        buffer: SharedHandle<api::VkBuffer>,
+        offset: u64,
+        marker: u32,
+    ) {
+        let index = self.buffers.len() as u32;
+        self.buffers.push(buffer);
+        self.has_ordered_commands = true;
+        self.push_record(
+            opcode::WRITE_BUFFER_MARKER,
+            &[index, offset as u32, (offset >> 32) as u32, marker],
+        );
+    }
+    pub fn generate_mipmaps(
+        &mut self,
+        image: SharedHandle<api::VkImage>,
+        subresource_range: api::VkImageSubresourceRange,
+        filter: api::VkFilter,
+    ) {
+        let index = self.images.len() as u32;
+        self.images.push(image);
+        self.push_record(
+            opcode::GENERATE_MIPMAPS,
+            &[
+                index,
+                filter,
+                subresource_range.aspectMask,
+                subresource_range.baseMipLevel,
+                subresource_range.levelCount,
+                subresource_range.baseArrayLayer,
+                subresource_range.layerCount,
+            ],
+        );
+    }
+    fn add_query_pool(&mut self, query_pool: SharedHandle<api::VkQueryPool>) -> u32 {
+        let index = self.query_pools.len() as u32;
+        self.query_pools.push(query_pool);
+        index
+    }
+    pub fn reset_query_pool(
+        &mut self,
+        query_pool: SharedHandle<api::VkQueryPool>,
+        first_query: u32,
+        query_count: u32,
+    ) {
+        let index = self.add_query_pool(query_pool);
+        self.has_ordered_commands = true;
+        self.push_record(opcode::RESET_QUERY_POOL, &[index, first_query, query_count]);
+    }
+    pub fn write_timestamp(&mut self, query_pool: SharedHandle<api::VkQueryPool>, query: u32) {
+        let index = self.add_query_pool(query_pool);
+        self.has_ordered_commands = true;
+        self.push_record(opcode::WRITE_TIMESTAMP, &[index, query]);
+    }
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    pub fn copy_query_pool_results(
+        &mut self,
+        query_pool: SharedHandle<api::VkQueryPool>,
+        first_query: u32,
+        query_count: u32,
+        buffer: SharedHandle<api::VkBuffer>,
+        offset: u64,
+        stride: u64,
+        flags: api::VkQueryResultFlags,
+    ) {
+        let query_pool_index = self.add_query_pool(query_pool);
+        let buffer_index = self.buffers.len() as u32;
+        self.buffers.push(buffer);
+        self.has_ordered_commands = true;
+        self.push_record(
+            opcode::COPY_QUERY_POOL_RESULTS,
+            &[
+                query_pool_index,
+                first_query,
+                query_count,
+                buffer_index,
+                offset as u32,
+                (offset >> 32) as u32,
+                stride as u32,
+                (stride >> 32) as u32,
+                flags,
+            ],
+        );
+    }
+    pub fn begin_query(&mut self, query_pool: SharedHandle<api::VkQueryPool>, query: u32) {
+        let index = self.add_query_pool(query_pool);
+        self.has_ordered_commands = true;
+        self.push_record(opcode::BEGIN_QUERY, &[index, query]);
+    }
+    pub fn end_query(&mut self, query_pool: SharedHandle<api::VkQueryPool>, query: u32) {
+        let index = self.add_query_pool(query_pool);
+        self.has_ordered_commands = true;
+        self.push_record(opcode::END_QUERY, &[index, query]);
+    }
+    /// the objects that must not be destroyed while the command buffer can be submitted. buffers,
+    /// images and query pools aren't tracked by validation yet, so they're left out
+    pub unsafe fn referenced_objects<'a>(&'a self) -> impl Iterator<Item = MessageObject> + 'a {
+        self.pipelines
+            .iter()
+            .map(|pipeline| pipeline_object(pipeline.get_handle()))
+            .chain(
+                self.descriptor_sets
+                    .iter()
+                    .map(|descriptor_set| descriptor_set_object(descriptor_set.get_handle())),
+            )
+    }
+    pub fn iter(&self) -> Commands<'_> {
+        assert_eq!(
+            self.state_run_start,
+            self.words.len(),
+            "command stream isn't finished"
+        );
+        Commands {
+            stream: self,
+            position: 0,
+        }
+    }
+    fn decode<'a>(&'a self, opcode: u8, payload: &'a [u32]) -> Command<'a> {
+        match opcode {
+            opcode::BIND_PIPELINE => Command::BindPipeline {
+                pipeline_bind_point: payload[0],
+                pipeline: self.pipelines[payload[1] as usize],
+            },
+            opcode::BIND_DESCRIPTOR_SETS => Command::BindDescriptorSets {
+                pipeline_bind_point: payload[0],
+                first_set: payload[1],
+                descriptor_sets: DescriptorSets {
+                    stream: self,
+                    words: &payload[3..],
+                    remaining: payload[2],
+                },
+            },
+            opcode::PUSH_CONSTANTS => Command::PushConstants {
+                offset: payload[0],
+                values: unsafe { read_plain_data(&payload[2..], payload[1] as usize) },
+            },
+            opcode::SET_VIEWPORT => Command::SetViewport {
+                first_viewport: payload[0],
+                viewports: unsafe { read_plain_data(&payload[2..], payload[1] as usize) },
+            },
+            opcode::SET_DEPTH_BIAS => Command::SetDepthBias(DepthBias {
+                constant_factor: f32::from_bits(payload[0]),
+                clamp: f32::from_bits(payload[1]),
+                slope_factor: f32::from_bits(payload[2]),
+                representation: decode_depth_bias_representation(payload[3]),
+            }),
+            opcode::DRAW => Command::Draw {
+                vertex_count: payload[0],
+                instance_count: payload[1],
+                first_vertex: payload[2],
+                first_instance: payload[3],
+            },
+            opcode::DRAW_INDEXED => Command::DrawIndexed {
+                index_count: payload[0],
+                instance_count: payload[1],
+                first_index: payload[2],
+                vertex_offset: payload[3] as i32,
+                first_instance: payload[4],
+            },
+            opcode::DISPATCH => Command::Dispatch {
+                group_count: [payload[0], payload[1], payload[2]],
+            },
+            opcode::WRITE_BUFFER_MARKER => Command::WriteBufferMarker {
+                buffer: self.buffers[payload[0] as usize],
+                offset: u64::from(payload[1]) | (u64::from(payload[2]) << 32),
+                marker: payload[3],
+            },
+            opcode::GENERATE_MIPMAPS => Command::GenerateMipmaps {
+                image: self.images[payload[0] as usize],
+                filter: payload[1],
+                subresource_range: api::VkImageSubresourceRange {
+                    aspectMask: payload[2],
+                    baseMipLevel: payload[3],
+                    levelCount: payload[4],
+                    baseArrayLayer: payload[5],
+                    layerCount: payload[6],
+                },
+            },
+            opcode::RESET_QUERY_POOL => Command::ResetQueryPool {
+                query_pool: self.query_pools[payload[0] as usize],
+                first_query: payload[1],
+                query_count: payload[2],
+            },
+            opcode::WRITE_TIMESTAMP => Command::WriteTimestamp {
+                query_pool: self.query_pools[payload[0] as usize],
+                query: payload[1],
+            },
+            opcode::COPY_QUERY_POOL_RESULTS => Command::CopyQueryPoolResults {
+                query_pool: self.query_pools[payload[0] as usize],
+                first_query: payload[1],
+                query_count: payload[2],
+                buffer: self.buffers[payload[3] as usize],
+                offset: u64::from(payload[4]) | (u64::from(payload[5]) << 32),
+                stride: u64::from(payload[6]) | (u64::from(payload[7]) << 32),
+                flags: payload[8],
+            },
+            opcode::BEGIN_QUERY => Command::BeginQuery {
+                query_pool: self.query_pools[payload[0] as usize],
+                query: payload[1],
+            },
+            opcode::END_QUERY => Command::EndQuery {
+                query_pool: self.query_pools[payload[0] as usize],
+                query: payload[1],
+            },
+            _ => unreachable!("invalid opcode: {:#x}", opcode),
+        }
+    }
+}
+
+pub struct Commands<'a> {
+    stream: &'a CommandStream,
+    position: usize,
+}
+
+impl<'a> Iterator for Commands<'a> {
+    type Item = Command<'a>;
+    fn next(&mut self) -> Option<Command<'a>> {
+        let header = *self.stream.words.get(self.position)?;
+        let start = self.position + 1;
+        let end = start + (header >> 8) as usize;
+        self.position = end;
+        Some(
+            self.stream
+                .decode(header as u8, &self.stream.words[start..end]),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn depth_bias(constant_factor: f32) -> DepthBias {
+        DepthBias {
+            constant_factor,
+            ..DepthBias::default()
+        }
+    }
+
+    fn viewport(x: f32) -> Viewport {
+        Viewport {
+            x,
+            y: 0.0,
+            width: 64.0,
+            height: 32.0,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+
+    /// a description of each command, to compare against
+    fn describe(stream: &CommandStream) -> Vec<String> {
+        stream
+            .iter()
+            .map(|command| match command {
+                Command::PushConstants { offset, values } => {
+                    format!("PushConstants {} {:?}", offset, values)
+                }
+                Command::SetViewport {
+                    first_viewport,
+                    viewports,
+                } => format!(
+                    "SetViewport {} {:?}",
+                    first_viewport,
+                    viewports.iter().map(|v| v.x).collect::<Vec<_>>()
+                ),
+                Command::SetDepthBias(depth_bias) => {
+                    format!("SetDepthBias {}", depth_bias.constant_factor)
+                }
+                Command::Draw { vertex_count, .. } => format!("Draw {}", vertex_count),
+                Command::DrawIndexed { vertex_offset, .. } => {
+                    format!("DrawIndexed {}", vertex_offset)
+                }
+                Command::Dispatch { group_count } => format!("Dispatch {:?}", group_count),
+                Command::BindPipeline { .. }
+                | Command::BindDescriptorSets { .. }
+                | Command::WriteBufferMarker { .. }
+                | Command::GenerateMipmaps { .. }
+                | Command::ResetQueryPool { .. }
+                | Command::WriteTimestamp { .. }
+                | Command::CopyQueryPoolResults { .. }
+                | Command::BeginQuery { .. }
+                | Command::EndQuery { .. } => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_state_runs() {
+        let mut stream = CommandStream::default();
+        stream.set_depth_bias(depth_bias(1.0));
+        stream.push_constants(4, &[1, 2, 3]);
+        stream.set_viewport(0, vec![viewport(1.0)]);
+        stream.push_constants(0, &[4, 5, 6, 7, 8]);
+        stream.set_depth_bias(depth_bias(2.0));
+        stream.push_constants(4, &[9, 10, 11]);
+        stream.draw(3, 1, 0, 0);
+        stream.set_viewport(0, vec![viewport(2.0)]);
+        stream.draw_indexed(6, 1, 0, -2, 0);
+        stream.dispatch([1, 2, 3]);
+        stream.set_depth_bias(depth_bias(3.0));
+        stream.finish();
+        assert_eq!(
+            describe(&stream),
+            [
+                "PushConstants 0 [4, 5, 6, 7, 8]",
+                "PushConstants 4 [9, 10, 11]",
+                "SetViewport 0 [1.0]",
+                "SetDepthBias 2",
+                "Draw 3",
+                "SetViewport 0 [2.0]",
+                "DrawIndexed -2",
+                "Dispatch [1, 2, 3]",
+                "SetDepthBias 3",
+            ]
+        );
+        stream.clear();
+        assert_eq!(stream.len_in_words(), 0);
+        stream.finish();
+        assert_eq!(describe(&stream), [] as [String; 0]);
+    }
+
+    #[test]
+    fn test_ordered_commands() {
+        let mut stream = CommandStream::default();
+        stream.draw(3, 1, 0, 0);
+        stream.dispatch([1, 1, 1]);
+        assert!(!stream.has_ordered_commands());
+        stream.pipeline_barrier();
+        assert!(stream.has_ordered_commands());
+        stream.finish();
+        assert_eq!(describe(&stream), ["Draw 3", "Dispatch [1, 1, 1]"]);
+        stream.clear();
+        assert!(!stream.has_ordered_commands());
+    }
+}
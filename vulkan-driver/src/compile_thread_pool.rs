@@ -0,0 +1,369 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! device-level thread pool for shader compilation
+//!
+//! jobs that an application thread is blocked on run before background jobs (such as creating
+//! the shader stages of a new shader module before any pipeline uses them), and waiting for a job
+//! that hasn't started runs it on the waiting thread. the pool only uses some of the cores, so
+//! bursts of pipeline creation don't starve the threads doing the frame's rasterization, which
+//! run the command buffers of submissions in a separate pool (see `submit_graph`).
+
+use std::collections::VecDeque;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use sys_info;
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum CompilePriority {
+    /// nothing is waiting on the result yet
+    Background,
+    /// an application thread is blocked until the job finishes
+    Blocking,
+}
+
+trait Job: Send {
+    fn run(self: Box<Self>);
+}
+
+impl<F: FnOnce() + Send> Job for F {
+    fn run(self: Box<Self>) {
+        (*self)()
+    }
+}
+
+struct QueuedJob {
+    id: u64,
+    job: Box<dyn Job>,
+}
+
+#[derive(Default)]
+struct Queue {
+    blocking_jobs: VecDeque<QueuedJob>,
+    background_jobs: VecDeque<QueuedJob>,
+    next_id: u64,
+    shutting_down: bool,
+}
+
+impl Queue {
+    fn pop(&mut self) -> Option<QueuedJob> {
+        self.blocking_jobs
+            .pop_front()
+            .or_else(|| self.background_jobs.pop_front())
+    }
+    /// remove a job that hasn't started yet
+    fn take(&mut self, id: u64) -> Option<QueuedJob> {
+        for jobs in &mut [&mut self.blocking_jobs, &mut self.background_jobs] {
+            if let Some(index) = jobs.iter().position(|job| job.id == id) {
+                return jobs.remove(index);
+            }
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    condition: Condvar,
+}
+
+fn worker_main(shared: &Shared) {
+    loop {
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+            loop {
+                if queue.shutting_down {
+                    return;
+                }
+                if let Some(job) = queue.pop() {
+                    break job;
+                }
+                queue = shared.condition.wait(queue).unwrap();
+            }
+        };
+        job.job.run();
+    }
+}
+
+/// a job submitted to a `CompileThreadPool`
+pub struct CompileJob<T> {
+    id: u64,
+    shared: Arc<Shared>,
+    receiver: mpsc::Receiver<thread::Result<T>>,
+}
+
+impl<T> CompileJob<T> {
+    /// run the job on the current thread if it hasn't started yet
+    fn run_if_queued(&self) {
+        let job = self.shared.queue.lock().unwrap().take(self.id);
+        if let Some(job) = job {
+            job.job.run();
+        }
+    }
+    fn wait_result(&self) -> thread::Result<T> {
+        self.run_if_queued();
+        self.receiver
+            .recv()
+            .expect("compile thread pool shut down before running job")
+    }
+    /// wait for the job to finish, returning `Err` with the panic payload if it panicked. a job
+    /// that hasn't started yet is run on the current thread, so jobs running on the pool can wait
+    /// for other jobs without every thread of the pool waiting for a job that can't start
+    pub fn wait(self) -> thread::Result<T> {
+        self.wait_result()
+    }
+    /// get the result without waiting, returning `Err(self)` if the job hasn't finished.
+    ///
+    /// used for pipeline creation that has to fail instead of waiting for compilation
+    /// (`pipelineCreationCacheControl`)
+    pub fn try_get(self) -> Result<thread::Result<T>, Self> {
+        match self.receiver.try_recv() {
+            Ok(result) => Ok(result),
+            Err(mpsc::TryRecvError::Empty) => Err(self),
+            Err(mpsc::TryRecvError::Disconnected) => {
+                panic!("compile thread pool shut down before running job")
+            }
+        }
+    }
+}
+
+/// waits for jobs that borrow from the caller of `CompileThreadPool::run_all` when it returns or
+/// unwinds, so they can't outlive what they borrow
+struct JoinGuard<T> {
+    jobs: Vec<CompileJob<T>>,
+}
+
+impl<T> Drop for JoinGuard<T> {
+    fn drop(&mut self) {
+        for job in &self.jobs {
+            job.run_if_queued();
+            // fails for the jobs that were already waited for
+            let _ = job.receiver.recv();
+        }
+    }
+}
+
+pub struct CompileThreadPool {
+    shared: Arc<Shared>,
+    threads: Vec<thread::JoinHandle<()>>,
+}
+
+impl CompileThreadPool {
+    /// uses half the cores, leaving the rest for rasterization
+    pub fn default_thread_count() -> usize {
+        sys_info::cpu_num()
+            .map(|cpu_count| cpu_count as usize / 2)
+            .unwrap_or(1)
+            .max(1)
+    }
+    pub fn new(thread_count: usize) -> Self {
+        Self::with_thread_name("kazan-compile", thread_count)
+    }
+    /// the threads are named `thread_name` followed by their index
+    pub fn with_thread_name(thread_name: &str, thread_count: usize) -> Self {
+        assert_ne!(thread_count, 0);
+        let shared = Arc::new(Shared::default());
+        let threads = (0..thread_count)
+            .map(|index| {
+                let shared = shared.clone();
+                thread::Builder::new()
+                    .name(format!("{}-{}", thread_name, index))
+                    .spawn(move || worker_main(&shared))
+                    .expect("failed to spawn thread pool thread")
+            })
+            .collect();
+        CompileThreadPool { shared, threads }
+    }
+    /// the caller must ensure that everything borrowed by `f` outlives the job, even if the caller
+    /// unwinds
+    unsafe fn submit_unchecked<'a, T, F>(&self, priority: CompilePriority, f: F) -> CompileJob<T>
+    where
+        T: Send + 'a,
+        F: FnOnce() -> T + Send + 'a,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let job: Box<dyn Job + 'a> = Box::new(move || {
+            // the receiver may have been dropped
+            let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(f)));
+        });
+        let job = mem::transmute::<Box<dyn Job + 'a>, Box<dyn Job + 'static>>(job);
+        let mut queue = self.shared.queue.lock().unwrap();
+        let id = queue.next_id;
+        queue.next_id += 1;
+        let queued_job = QueuedJob { id, job };
+        match priority {
+            CompilePriority::Blocking => queue.blocking_jobs.push_back(queued_job),
+            CompilePriority::Background => queue.background_jobs.push_back(queued_job),
+        }
+        self.shared.condition.notify_one();
+        CompileJob {
+            id,
+            shared: self.shared.clone(),
+            receiver,
+        }
+    }
+    pub fn submit<T, F>(&self, priority: CompilePriority, f: F) -> CompileJob<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        unsafe { self.submit_unchecked(priority, f) }
+    }
+    /// run all of `jobs` on the pool, waiting for them to finish. the result of a job that
+    /// panicked is `Err` with the panic payload
+    pub fn run_all<'a, T, F, I>(&self, priority: CompilePriority, jobs: I) -> Vec<thread::Result<T>>
+    where
+        T: Send + 'a,
+        F: FnOnce() -> T + Send + 'a,
+        I: IntoIterator<Item = F>,
+    {
+        let mut guard = JoinGuard { jobs: Vec::new() };
+        for f in jobs {
+            // safe because `guard` waits for every job to finish, even if `jobs` panics
+            guard
+                .jobs
+                .push(unsafe { self.submit_unchecked(priority, f) });
+        }
+        guard.jobs.iter().map(CompileJob::wait_result).collect()
+    }
+}
+
+impl Drop for CompileThreadPool {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutting_down = true;
+        self.shared.condition.notify_all();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Barrier;
+    use std::time::Duration;
+
+    #[test]
+    fn test_run_all() {
+        let pool = CompileThreadPool::new(2);
+        let values = [1, 2, 3, 4, 5];
+        let results: Vec<_> = pool
+            .run_all(
+                CompilePriority::Blocking,
+                values.iter().map(|v| move || v * 2),
+            )
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(results, [2, 4, 6, 8, 10]);
+    }
+
+    #[test]
+    fn test_run_all_panic() {
+        let pool = CompileThreadPool::new(2);
+        let results = pool.run_all(
+            CompilePriority::Blocking,
+            (0..3).map(|index| {
+                move || {
+                    if index == 1 {
+                        panic!("job panicked");
+                    }
+                    index
+                }
+            }),
+        );
+        assert_eq!(results.len(), 3);
+        assert_eq!(*results[0].as_ref().unwrap(), 0);
+        assert!(results[1].is_err());
+        assert_eq!(*results[2].as_ref().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_run_all_waits_when_jobs_panics() {
+        let pool = CompileThreadPool::new(2);
+        let finished = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.run_all(
+                CompilePriority::Blocking,
+                (0..4).map(|index| {
+                    if index == 3 {
+                        panic!("jobs panicked");
+                    }
+                    let finished = &finished;
+                    move || {
+                        thread::sleep(Duration::from_millis(10));
+                        finished.fetch_add(1, Ordering::SeqCst);
+                    }
+                }),
+            )
+        }));
+        assert!(result.is_err());
+        // the jobs queued before the panic borrow `finished`, so they have to finish first
+        assert_eq!(finished.load(Ordering::SeqCst), 3);
+    }
+
+    /// submit a job that keeps the pool's only thread busy until `barrier` is waited on
+    fn block_pool(pool: &CompileThreadPool, barrier: &Arc<Barrier>) -> CompileJob<()> {
+        let barrier = barrier.clone();
+        pool.submit(CompilePriority::Blocking, move || {
+            barrier.wait();
+        })
+    }
+
+    #[test]
+    fn test_priority() {
+        let mut queue = Queue::default();
+        let job = |id| QueuedJob {
+            id,
+            job: Box::new(|| {}),
+        };
+        queue.background_jobs.push_back(job(0));
+        queue.blocking_jobs.push_back(job(1));
+        queue.background_jobs.push_back(job(2));
+        queue.background_jobs.push_back(job(3));
+        assert_eq!(queue.take(2).map(|job| job.id), Some(2));
+        assert!(queue.take(2).is_none());
+        let order: Vec<_> = (0..3).map(|_| queue.pop().unwrap().id).collect();
+        assert_eq!(order, [1, 0, 3]);
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn test_wait_runs_job() {
+        let pool = CompileThreadPool::new(1);
+        let barrier = Arc::new(Barrier::new(2));
+        let blocker = block_pool(&pool, &barrier);
+        let job = pool.submit(CompilePriority::Background, || thread::current().id());
+        // the pool's only thread is busy, so the job has to run here
+        assert_eq!(job.wait().unwrap(), thread::current().id());
+        barrier.wait();
+        blocker.wait().unwrap();
+    }
+
+    #[test]
+    fn test_try_get() {
+        let pool = CompileThreadPool::new(1);
+        let barrier = Arc::new(Barrier::new(2));
+        let blocker = block_pool(&pool, &barrier);
+        let mut job = match pool.submit(CompilePriority::Background, || 5).try_get() {
+            Ok(_) => unreachable!(),
+            Err(job) => job,
+        };
+        barrier.wait();
+        blocker.wait().unwrap();
+        let result = loop {
+            job = match job.try_get() {
+                Ok(result) => break result,
+                Err(job) => job,
+            };
+            thread::yield_now();
+        };
+        assert_eq!(result.unwrap(), 5);
+    }
+}
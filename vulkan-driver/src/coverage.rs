@@ -0,0 +1,222 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! which samples of a fragment are written
+//!
+//! a fragment's coverage starts as the samples the rasterizer found inside the primitive, and is
+//! then restricted by the pipeline's sample mask, by the fragment shader's `SampleMask` output, and
+//! by alpha to coverage. the fragment shader's `SampleMask` input is the coverage after the
+//! sample mask, and after the early depth and stencil tests with `PostDepthCoverage`.
+//!
+//! alpha to coverage covers the fraction of the samples given by the alpha of color attachment 0.
+//! with few samples that leaves visible bands where alpha changes slowly, such as the edges of
+//! foliage, so when `KAZAN_DITHER_ALPHA_TO_COVERAGE` is set, the threshold for covering each
+//! additional sample is varied over 4x4 pixel blocks with an ordered dither, and the covered
+//! samples are rotated by the same pattern, trading the bands for fine noise.
+
+use api;
+use std::env;
+
+/// set to dither alpha to coverage
+pub const DITHER_ALPHA_TO_COVERAGE_ENV_VAR: &str = "KAZAN_DITHER_ALPHA_TO_COVERAGE";
+
+pub fn is_alpha_to_coverage_dithering_enabled_in_environment() -> bool {
+    match env::var(DITHER_ALPHA_TO_COVERAGE_ENV_VAR) {
+        Ok(ref v) => !v.is_empty() && v != "0",
+        Err(_) => false,
+    }
+}
+
+/// the 4x4 ordered dither matrix, indexed by `[y % 4][x % 4]`
+const DITHER_MATRIX: [[u32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// the multisample state of a graphics pipeline
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Multisample {
+    pub sample_count: u32,
+    /// only the first word, since `maxSampleMaskWords` is 1
+    pub sample_mask: u32,
+    pub alpha_to_coverage_enable: bool,
+    pub alpha_to_one_enable: bool,
+    /// set by `KAZAN_DITHER_ALPHA_TO_COVERAGE`
+    pub dither_alpha_to_coverage: bool,
+}
+
+impl Default for Multisample {
+    fn default() -> Self {
+        Multisample {
+            sample_count: 1,
+            sample_mask: !0,
+            alpha_to_coverage_enable: false,
+            alpha_to_one_enable: false,
+            dither_alpha_to_coverage: false,
+        }
+    }
+}
+
+impl Multisample {
+    /// `dither_alpha_to_coverage` is usually from
+    /// `is_alpha_to_coverage_dithering_enabled_in_environment`
+    pub unsafe fn new(
+        multisample_state: &api::VkPipelineMultisampleStateCreateInfo,
+        dither_alpha_to_coverage: bool,
+    ) -> Self {
+        assert_eq!(
+            multisample_state.sType,
+            api::VK_STRUCTURE_TYPE_PIPELINE_MULTISAMPLE_STATE_CREATE_INFO
+        );
+        assert!(multisample_state.pNext.is_null());
+        assert_eq!(multisample_state.flags, 0);
+        let sample_count = multisample_state.rasterizationSamples as u32;
+        assert!(sample_count.is_power_of_two() && sample_count <= 32);
+        Multisample {
+            sample_count,
+            sample_mask: if multisample_state.pSampleMask.is_null() {
+                !0
+            } else {
+                *multisample_state.pSampleMask
+            },
+            alpha_to_coverage_enable: multisample_state.alphaToCoverageEnable != api::VK_FALSE,
+            alpha_to_one_enable: multisample_state.alphaToOneEnable != api::VK_FALSE,
+            dither_alpha_to_coverage,
+        }
+    }
+    /// a mask with a bit for each sample
+    pub fn all_samples(&self) -> u32 {
+        !0 >> (32 - self.sample_count)
+    }
+    /// the fragment shader's `SampleMask` input. `rasterized` is the samples inside the
+    /// primitive, or with `PostDepthCoverage`, the samples that also passed the early tests
+    #[allow(dead_code)]
+    pub fn get_sample_mask_input(&self, rasterized: u32) -> u32 {
+        rasterized & self.sample_mask & self.all_samples()
+    }
+    /// the samples alpha to coverage leaves covered for a fragment at `(x, y)` in framebuffer
+    /// coordinates with alpha `alpha`, before it's replaced with 1 by alpha to one
+    pub fn get_alpha_coverage(&self, alpha: f32, x: u32, y: u32) -> u32 {
+        if !self.alpha_to_coverage_enable {
+            return !0;
+        }
+        let alpha = if alpha.is_nan() {
+            0.0
+        } else {
+            alpha.clamp(0.0, 1.0)
+        };
+        let dither_index = DITHER_MATRIX[y as usize % 4][x as usize % 4];
+        let threshold = if self.dither_alpha_to_coverage {
+            (dither_index as f32 + 0.5) / 16.0
+        } else {
+            0.5
+        };
+        let covered_count =
+            ((alpha * self.sample_count as f32 + threshold) as u32).min(self.sample_count);
+        if covered_count == 0 {
+            return 0;
+        }
+        let covered = !0 >> (32 - covered_count);
+        if !self.dither_alpha_to_coverage || covered_count == self.sample_count {
+            return covered;
+        }
+        let rotation = dither_index % self.sample_count;
+        if rotation == 0 {
+            return covered;
+        }
+        (covered << rotation | covered >> (self.sample_count - rotation)) & self.all_samples()
+    }
+    /// the samples of a fragment that are written. `sample_mask_input` is from
+    /// `get_sample_mask_input`, and `shader_sample_mask` is the fragment shader's `SampleMask`
+    /// output, or `None` if the shader doesn't write it
+    #[allow(dead_code)]
+    pub fn get_coverage(
+        &self,
+        sample_mask_input: u32,
+        shader_sample_mask: Option<u32>,
+        alpha: f32,
+        x: u32,
+        y: u32,
+    ) -> u32 {
+        sample_mask_input & shader_sample_mask.unwrap_or(!0) & self.get_alpha_coverage(alpha, x, y)
+    }
+    /// the alpha written to the color attachments
+    #[allow(dead_code)]
+    pub fn get_output_alpha(&self, alpha: f32) -> f32 {
+        if self.alpha_to_one_enable {
+            1.0
+        } else {
+            alpha
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn multisample(sample_count: u32, dither_alpha_to_coverage: bool) -> Multisample {
+        Multisample {
+            sample_count,
+            alpha_to_coverage_enable: true,
+            dither_alpha_to_coverage,
+            ..Multisample::default()
+        }
+    }
+
+    #[test]
+    fn test_alpha_to_coverage() {
+        let multisample = multisample(4, false);
+        assert_eq!(multisample.get_alpha_coverage(0.0, 0, 0), 0);
+        assert_eq!(multisample.get_alpha_coverage(0.5, 0, 0), 0b0011);
+        assert_eq!(multisample.get_alpha_coverage(1.0, 3, 2), 0b1111);
+        assert_eq!(multisample.get_alpha_coverage(2.0, 3, 2), 0b1111);
+        assert_eq!(multisample.get_alpha_coverage(-1.0, 3, 2), 0);
+        let disabled = Multisample {
+            sample_count: 4,
+            ..Multisample::default()
+        };
+        assert_eq!(disabled.get_alpha_coverage(0.0, 0, 0), !0);
+    }
+
+    #[test]
+    fn test_dithered_alpha_to_coverage() {
+        for &sample_count in &[1, 4, 8] {
+            let multisample = multisample(sample_count, true);
+            for &alpha in &[0.0, 0.25, 0.3125, 0.5, 0.875, 1.0] {
+                let mut covered_count = 0;
+                for y in 0..4 {
+                    for x in 0..4 {
+                        let coverage = multisample.get_alpha_coverage(alpha, x, y);
+                        assert_eq!(coverage & !multisample.all_samples(), 0);
+                        covered_count += coverage.count_ones();
+                    }
+                }
+                // each 4x4 block covers exactly the fraction of samples given by alpha
+                assert_eq!(
+                    covered_count as f32,
+                    alpha * (16 * sample_count) as f32,
+                    "sample_count = {}, alpha = {}",
+                    sample_count,
+                    alpha
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_coverage() {
+        let multisample = Multisample {
+            sample_count: 4,
+            sample_mask: 0b0111,
+            ..Multisample::default()
+        };
+        let sample_mask_input = multisample.get_sample_mask_input(!0);
+        assert_eq!(sample_mask_input, 0b0111);
+        assert_eq!(
+            multisample.get_coverage(sample_mask_input, Some(0b1110), 0.0, 0, 0),
+            0b0110
+        );
+        assert_eq!(
+            multisample.get_coverage(sample_mask_input, None, 0.0, 0, 0),
+            0b0111
+        );
+    }
+}
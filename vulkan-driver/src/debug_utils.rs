@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+use api;
+use handle::{Handle, SharedHandle};
+use std::ffi::CString;
+use std::os::raw::c_void;
+use std::ptr::null;
+use std::sync::Mutex;
+
+pub struct DebugUtilsMessenger {
+    message_severity: api::VkDebugUtilsMessageSeverityFlagsEXT,
+    message_type: api::VkDebugUtilsMessageTypeFlagsEXT,
+    user_callback: unsafe extern "system" fn(
+        api::VkDebugUtilsMessageSeverityFlagBitsEXT,
+        api::VkDebugUtilsMessageTypeFlagsEXT,
+        *const api::VkDebugUtilsMessengerCallbackDataEXT,
+        *mut c_void,
+    ) -> api::VkBool32,
+    user_data: *mut c_void,
+}
+
+impl DebugUtilsMessenger {
+    pub unsafe fn new(create_info: &api::VkDebugUtilsMessengerCreateInfoEXT) -> Self {
+        assert_eq!(create_info.flags, 0);
+        DebugUtilsMessenger {
+            message_severity: create_info.messageSeverity,
+            message_type: create_info.messageType,
+            user_callback: create_info
+                .pfnUserCallback
+                .expect("pfnUserCallback must not be null"),
+            user_data: create_info.pUserData,
+        }
+    }
+}
+
+/// an object that a message is about
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct MessageObject {
+    pub object_type: api::VkObjectType,
+    pub object_handle: u64,
+}
+
+impl MessageObject {
+    pub fn new<T: Handle>(object_type: api::VkObjectType, handle: T) -> Self {
+        MessageObject {
+            object_type,
+            object_handle: handle.get().map_or(0, |v| v.as_ptr() as usize as u64),
+        }
+    }
+}
+
+/// the messengers created for an instance
+#[derive(Default)]
+pub struct DebugUtilsMessengers {
+    messengers: Mutex<Vec<api::VkDebugUtilsMessengerEXT>>,
+}
+
+// the messengers are used from whichever thread reports a message; the application has to
+// keep them alive until vkDestroyDebugUtilsMessengerEXT removes them from the list
+unsafe impl Send for DebugUtilsMessengers {}
+unsafe impl Sync for DebugUtilsMessengers {}
+
+impl DebugUtilsMessengers {
+    pub fn add(&self, messenger: api::VkDebugUtilsMessengerEXT) {
+        self.messengers.lock().unwrap().push(messenger);
+    }
+    pub fn remove(&self, messenger: api::VkDebugUtilsMessengerEXT) {
+        self.messengers.lock().unwrap().retain(|&v| v != messenger);
+    }
+    pub fn is_empty(&self) -> bool {
+        self.messengers.lock().unwrap().is_empty()
+    }
+    /// calls every messenger that is interested in the message
+    pub unsafe fn submit(
+        &self,
+        message_severity: api::VkDebugUtilsMessageSeverityFlagBitsEXT,
+        message_type: api::VkDebugUtilsMessageTypeFlagsEXT,
+        callback_data: &api::VkDebugUtilsMessengerCallbackDataEXT,
+    ) {
+        let messengers = self.messengers.lock().unwrap().clone();
+        for messenger in messengers {
+            let messenger = SharedHandle::from(messenger).unwrap();
+            if messenger.message_severity & message_severity != 0
+                && messenger.message_type & message_type != 0
+            {
+                (messenger.user_callback)(
+                    message_severity,
+                    message_type,
+                    callback_data,
+                    messenger.user_data,
+                );
+            }
+        }
+    }
+    /// send a message generated by the driver
+    pub fn send(
+        &self,
+        message_severity: api::VkDebugUtilsMessageSeverityFlagBitsEXT,
+        message_type: api::VkDebugUtilsMessageTypeFlagsEXT,
+        message_id_name: &str,
+        message: &str,
+        objects: &[MessageObject],
+    ) {
+        let message_id_name = CString::new(message_id_name).unwrap();
+        let message = CString::new(message.replace('\0', "\\0")).unwrap();
+        let objects: Vec<_> = objects
+            .iter()
+            .map(|object| api::VkDebugUtilsObjectNameInfoEXT {
+                sType: api::VK_STRUCTURE_TYPE_DEBUG_UTILS_OBJECT_NAME_INFO_EXT,
+                pNext: null(),
+                objectType: object.object_type,
+                objectHandle: object.object_handle,
+                pObjectName: null(),
+            })
+            .collect();
+        let callback_data = api::VkDebugUtilsMessengerCallbackDataEXT {
+            sType: api::VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CALLBACK_DATA_EXT,
+            pNext: null(),
+            flags: 0,
+            pMessageIdName: message_id_name.as_ptr(),
+            messageIdNumber: 0,
+            pMessage: message.as_ptr(),
+            queueLabelCount: 0,
+            pQueueLabels: null(),
+            cmdBufLabelCount: 0,
+            pCmdBufLabels: null(),
+            objectCount: objects.len() as u32,
+            pObjects: objects.as_ptr(),
+        };
+        unsafe { self.submit(message_severity, message_type, &callback_data) }
+    }
+}
@@ -0,0 +1,489 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+use api;
+use device_memory::DeviceMemoryAllocation;
+use handle::{Handle, OwnedHandle, SharedHandle};
+use shader_compiler::descriptors::{self, Descriptor, DescriptorType};
+use shader_compiler::image::{ImageHandle, SamplerHandle};
+use std::ops::Range;
+use std::ptr::{self, NonNull};
+use std::slice;
+
+pub fn get_descriptor_type(descriptor_type: api::VkDescriptorType) -> DescriptorType {
+    match descriptor_type {
+        api::VK_DESCRIPTOR_TYPE_SAMPLER => DescriptorType::Sampler,
+        api::VK_DESCRIPTOR_TYPE_COMBINED_IMAGE_SAMPLER => DescriptorType::CombinedImageSampler,
+        api::VK_DESCRIPTOR_TYPE_SAMPLED_IMAGE => DescriptorType::SampledImage,
+        api::VK_DESCRIPTOR_TYPE_STORAGE_IMAGE => DescriptorType::StorageImage,
+        api::VK_DESCRIPTOR_TYPE_UNIFORM_TEXEL_BUFFER => DescriptorType::UniformTexelBuffer,
+        api::VK_DESCRIPTOR_TYPE_STORAGE_TEXEL_BUFFER => DescriptorType::StorageTexelBuffer,
+        api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER => DescriptorType::UniformBuffer,
+        api::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER => DescriptorType::StorageBuffer,
+        api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC => DescriptorType::UniformBufferDynamic,
+        api::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER_DYNAMIC => DescriptorType::StorageBufferDynamic,
+        api::VK_DESCRIPTOR_TYPE_INPUT_ATTACHMENT => DescriptorType::InputAttachment,
+        _ => unreachable!("invalid descriptor type: {}", descriptor_type),
+    }
+}
+
+unsafe fn get_sampler_handle(sampler: api::VkSampler) -> SamplerHandle {
+    SharedHandle::from(sampler).unwrap().into_nonnull().as_ptr() as SamplerHandle
+}
+
+/// a null `image_view` is a null descriptor (`VK_EXT_robustness2`), which reads as zero
+unsafe fn get_image_handle(image_view: api::VkImageView) -> ImageHandle {
+    match SharedHandle::from(image_view) {
+        Some(image_view) => image_view.into_nonnull().as_ptr() as ImageHandle,
+        None => ptr::null(),
+    }
+}
+
+/// a null buffer is a null descriptor (`VK_EXT_robustness2`); its range is 0, so every access is
+/// out of bounds
+unsafe fn get_buffer_descriptor(buffer_info: &api::VkDescriptorBufferInfo) -> Descriptor {
+    let buffer = match SharedHandle::from(buffer_info.buffer) {
+        Some(buffer) => buffer,
+        None => return Descriptor::buffer(ptr::null(), 0),
+    };
+    let memory = buffer.memory.as_ref().expect("buffer not bound to memory");
+    let offset = buffer_info.offset as usize;
+    assert!(offset <= buffer.size);
+    let range = if buffer_info.range == api::VK_WHOLE_SIZE as api::VkDeviceSize {
+        (buffer.size - offset) as u64
+    } else {
+        buffer_info.range
+    };
+    Descriptor::buffer(
+        memory
+            .device_memory
+            .get()
+            .as_ptr()
+            .add(memory.offset + offset),
+        range,
+    )
+}
+
+pub struct DescriptorSetLayout {
+    pub layout: descriptors::DescriptorSetLayout,
+    /// the descriptors that new descriptor sets start with; contains the immutable samplers
+    pub initial_descriptors: Vec<Descriptor>,
+    /// indexed by binding number
+    pub immutable_samplers: Vec<bool>,
+}
+
+impl DescriptorSetLayout {
+    /// fails with `VK_ERROR_FEATURE_NOT_PRESENT` for texel buffer bindings, since buffer views
+    /// aren't supported and no format has texel buffer features
+    pub unsafe fn new(
+        create_info: &api::VkDescriptorSetLayoutCreateInfo,
+    ) -> Result<Self, api::VkResult> {
+        assert_eq!(create_info.flags, 0);
+        let bindings: &[_] = if create_info.bindingCount == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(create_info.pBindings, create_info.bindingCount as usize)
+        };
+        if bindings.iter().any(|binding| {
+            binding.descriptorCount != 0
+                && match get_descriptor_type(binding.descriptorType) {
+                    DescriptorType::UniformTexelBuffer | DescriptorType::StorageTexelBuffer => true,
+                    _ => false,
+                }
+        }) {
+            return Err(api::VK_ERROR_FEATURE_NOT_PRESENT);
+        }
+        let binding_count = bindings
+            .iter()
+            .map(|binding| binding.binding as usize + 1)
+            .max()
+            .unwrap_or(0);
+        let mut layout = descriptors::DescriptorSetLayout {
+            bindings: vec![None; binding_count],
+            descriptor_count: 0,
+        };
+        let mut immutable_samplers = vec![false; binding_count];
+        let mut initial_descriptors = Vec::new();
+        let mut sorted_bindings: Vec<_> = bindings.iter().collect();
+        sorted_bindings.sort_by_key(|binding| binding.binding);
+        for binding in sorted_bindings {
+            let descriptor_type = get_descriptor_type(binding.descriptorType);
+            assert!(layout.bindings[binding.binding as usize].is_none());
+            layout.bindings[binding.binding as usize] =
+                Some(descriptors::DescriptorSetLayoutBinding {
+                    descriptor_type,
+                    descriptor_index: layout.descriptor_count,
+                    descriptor_count: binding.descriptorCount,
+                });
+            layout.descriptor_count += binding.descriptorCount;
+            match descriptor_type {
+                DescriptorType::Sampler | DescriptorType::CombinedImageSampler
+                    if !binding.pImmutableSamplers.is_null() =>
+                {
+                    immutable_samplers[binding.binding as usize] = true;
+                    initial_descriptors.extend(
+                        slice::from_raw_parts(
+                            binding.pImmutableSamplers,
+                            binding.descriptorCount as usize,
+                        )
+                        .iter()
+                        .map(|&sampler| Descriptor::sampler(get_sampler_handle(sampler))),
+                    );
+                }
+                _ => initial_descriptors
+                    .extend((0..binding.descriptorCount).map(|_| Descriptor::default())),
+            }
+        }
+        Ok(DescriptorSetLayout {
+            layout,
+            initial_descriptors,
+            immutable_samplers,
+        })
+    }
+}
+
+/// a view into the descriptor heap of the `DescriptorPool` it was allocated from
+pub struct DescriptorSet {
+    /// copied from the `DescriptorSetLayout` since it can be destroyed before the descriptor set
+    pub layout: descriptors::DescriptorSetLayout,
+    /// index of the first descriptor in the pool's heap
+    heap_offset: u32,
+    /// points to `layout.descriptor_count` descriptors in the pool's heap
+    descriptors: NonNull<Descriptor>,
+    immutable_samplers: Vec<bool>,
+}
+
+impl DescriptorSet {
+    pub fn descriptors(&self) -> &[Descriptor] {
+        unsafe {
+            slice::from_raw_parts(
+                self.descriptors.as_ptr(),
+                self.layout.descriptor_count as usize,
+            )
+        }
+    }
+    fn descriptors_mut(&mut self) -> &mut [Descriptor] {
+        unsafe {
+            slice::from_raw_parts_mut(
+                self.descriptors.as_ptr(),
+                self.layout.descriptor_count as usize,
+            )
+        }
+    }
+    fn heap_range(&self) -> Range<u32> {
+        self.heap_offset..self.heap_offset + self.layout.descriptor_count
+    }
+    /// get the descriptor indices for `descriptor_count` consecutive descriptors, continuing into
+    /// the following bindings when the end of a binding is reached. binding numbers that aren't
+    /// in the layout are skipped, since layouts can have gaps between binding numbers
+    fn get_descriptor_indices(
+        &self,
+        mut binding: u32,
+        mut array_element: u32,
+        descriptor_count: u32,
+    ) -> Vec<(u32, usize)> {
+        let mut retval = Vec::with_capacity(descriptor_count as usize);
+        while retval.len() < descriptor_count as usize {
+            assert!(
+                (binding as usize) < self.layout.bindings.len(),
+                "descriptor update goes past the last binding"
+            );
+            let layout_binding = match self.layout.get_binding(binding) {
+                Some(layout_binding) => layout_binding,
+                None => {
+                    binding += 1;
+                    continue;
+                }
+            };
+            if array_element >= layout_binding.descriptor_count {
+                array_element -= layout_binding.descriptor_count;
+                binding += 1;
+                continue;
+            }
+            retval.push((
+                binding,
+                (layout_binding.descriptor_index + array_element) as usize,
+            ));
+            array_element += 1;
+        }
+        retval
+    }
+    /// the dynamic descriptor indices, in the order that the dynamic offsets are applied
+    pub fn dynamic_descriptor_indices<'a>(&'a self) -> impl Iterator<Item = usize> + 'a {
+        self.layout
+            .bindings
+            .iter()
+            .filter_map(|binding| binding.as_ref())
+            .filter(|binding| binding.descriptor_type.is_dynamic())
+            .flat_map(|binding| {
+                let start = binding.descriptor_index as usize;
+                start..start + binding.descriptor_count as usize
+            })
+    }
+    pub unsafe fn write(&mut self, write: &api::VkWriteDescriptorSet) {
+        assert_eq!(write.sType, api::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET);
+        assert!(write.pNext.is_null());
+        let descriptor_type = get_descriptor_type(write.descriptorType);
+        let indices = self.get_descriptor_indices(
+            write.dstBinding,
+            write.dstArrayElement,
+            write.descriptorCount,
+        );
+        for (i, (binding, index)) in indices.into_iter().enumerate() {
+            let immutable_samplers = self.immutable_samplers[binding as usize];
+            let descriptor = &mut self.descriptors_mut()[index];
+            *descriptor = match descriptor_type {
+                DescriptorType::Sampler => {
+                    if immutable_samplers {
+                        continue;
+                    }
+                    Descriptor::sampler(get_sampler_handle((*write.pImageInfo.add(i)).sampler))
+                }
+                DescriptorType::CombinedImageSampler => {
+                    let image_info = &*write.pImageInfo.add(i);
+                    let sampler = if immutable_samplers {
+                        descriptor.sampler_handle()
+                    } else {
+                        get_sampler_handle(image_info.sampler)
+                    };
+                    Descriptor::combined_image_sampler(
+                        get_image_handle(image_info.imageView),
+                        sampler,
+                    )
+                }
+                DescriptorType::SampledImage
+                | DescriptorType::StorageImage
+                | DescriptorType::InputAttachment => {
+                    Descriptor::image(get_image_handle((*write.pImageInfo.add(i)).imageView))
+                }
+                DescriptorType::UniformTexelBuffer | DescriptorType::StorageTexelBuffer => {
+                    unreachable!("texel buffer layouts are rejected when they're created")
+                }
+                DescriptorType::UniformBuffer
+                | DescriptorType::StorageBuffer
+                | DescriptorType::UniformBufferDynamic
+                | DescriptorType::StorageBufferDynamic => {
+                    get_buffer_descriptor(&*write.pBufferInfo.add(i))
+                }
+            };
+        }
+    }
+    pub unsafe fn copy(&mut self, source: Option<&DescriptorSet>, copy: &api::VkCopyDescriptorSet) {
+        assert_eq!(copy.sType, api::VK_STRUCTURE_TYPE_COPY_DESCRIPTOR_SET);
+        assert!(copy.pNext.is_null());
+        let source_descriptors: Vec<Descriptor> = {
+            // `source` is `None` when copying within the same descriptor set
+            let source = source.unwrap_or(&*self);
+            source
+                .get_descriptor_indices(copy.srcBinding, copy.srcArrayElement, copy.descriptorCount)
+                .into_iter()
+                .map(|(_, index)| source.descriptors()[index])
+                .collect()
+        };
+        let destination_indices = self.get_descriptor_indices(
+            copy.dstBinding,
+            copy.dstArrayElement,
+            copy.descriptorCount,
+        );
+        let descriptors = self.descriptors_mut();
+        for ((_, index), descriptor) in destination_indices.into_iter().zip(source_descriptors) {
+            descriptors[index] = descriptor;
+        }
+    }
+}
+
+pub struct DescriptorPool {
+    /// the descriptors of all the descriptor sets allocated from this pool.
+    /// never reallocated, since descriptor sets point into it
+    heap: Box<[Descriptor]>,
+    /// unused ranges of `heap`; sorted and never adjacent
+    free_ranges: Vec<Range<u32>>,
+    max_sets: u32,
+    descriptor_sets: Vec<OwnedHandle<api::VkDescriptorSet>>,
+}
+
+impl DescriptorPool {
+    pub unsafe fn new(create_info: &api::VkDescriptorPoolCreateInfo) -> Self {
+        let pool_sizes: &[_] = if create_info.poolSizeCount == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(create_info.pPoolSizes, create_info.poolSizeCount as usize)
+        };
+        let descriptor_count = pool_sizes
+            .iter()
+            .map(|pool_size| u64::from(pool_size.descriptorCount))
+            .sum::<u64>();
+        assert!(
+            descriptor_count <= u64::from(!0u32),
+            "too many descriptors in pool"
+        );
+        let mut retval = DescriptorPool {
+            heap: vec![Descriptor::default(); descriptor_count as usize].into_boxed_slice(),
+            free_ranges: Vec::new(),
+            max_sets: create_info.maxSets,
+            descriptor_sets: Vec::new(),
+        };
+        retval.reset();
+        retval
+    }
+    fn allocate_range(&mut self, descriptor_count: u32) -> Result<u32, api::VkResult> {
+        if descriptor_count == 0 {
+            return Ok(0);
+        }
+        let index = match self
+            .free_ranges
+            .iter()
+            .position(|range| range.end - range.start >= descriptor_count)
+        {
+            Some(index) => index,
+            None => {
+                let free_count = self
+                    .free_ranges
+                    .iter()
+                    .map(|range| u64::from(range.end - range.start))
+                    .sum::<u64>();
+                return Err(if free_count >= u64::from(descriptor_count) {
+                    api::VK_ERROR_FRAGMENTED_POOL
+                } else {
+                    api::VK_ERROR_OUT_OF_POOL_MEMORY
+                });
+            }
+        };
+        let start = self.free_ranges[index].start;
+        self.free_ranges[index].start += descriptor_count;
+        if self.free_ranges[index].start == self.free_ranges[index].end {
+            self.free_ranges.remove(index);
+        }
+        Ok(start)
+    }
+    fn free_range(&mut self, range: Range<u32>) {
+        if range.start == range.end {
+            return;
+        }
+        let index = self
+            .free_ranges
+            .iter()
+            .position(|v| v.start > range.start)
+            .unwrap_or_else(|| self.free_ranges.len());
+        self.free_ranges.insert(index, range);
+        if index + 1 < self.free_ranges.len()
+            && self.free_ranges[index].end == self.free_ranges[index + 1].start
+        {
+            self.free_ranges[index].end = self.free_ranges.remove(index + 1).end;
+        }
+        if index > 0 && self.free_ranges[index - 1].end == self.free_ranges[index].start {
+            self.free_ranges[index - 1].end = self.free_ranges.remove(index).end;
+        }
+    }
+    pub unsafe fn allocate(
+        &mut self,
+        layout: &DescriptorSetLayout,
+    ) -> Result<api::VkDescriptorSet, api::VkResult> {
+        if self.descriptor_sets.len() >= self.max_sets as usize {
+            return Err(api::VK_ERROR_OUT_OF_POOL_MEMORY);
+        }
+        let heap_offset = self.allocate_range(layout.layout.descriptor_count)?;
+        let descriptors =
+            &mut self.heap[heap_offset as usize..][..layout.initial_descriptors.len()];
+        descriptors.copy_from_slice(&layout.initial_descriptors);
+        let descriptor_set = OwnedHandle::<api::VkDescriptorSet>::new(DescriptorSet {
+            layout: layout.layout.clone(),
+            heap_offset,
+            descriptors: NonNull::new(descriptors.as_mut_ptr()).unwrap(),
+            immutable_samplers: layout.immutable_samplers.clone(),
+        });
+        let retval = descriptor_set.get_handle();
+        self.descriptor_sets.push(descriptor_set);
+        Ok(retval)
+    }
+    pub unsafe fn free(&mut self, descriptor_set: api::VkDescriptorSet) {
+        if descriptor_set.is_null() {
+            return;
+        }
+        let index = self
+            .descriptor_sets
+            .iter()
+            .position(|v| v.get_handle() == descriptor_set)
+            .expect("descriptor set not allocated from this pool");
+        let descriptor_set = self.descriptor_sets.swap_remove(index);
+        self.free_range(descriptor_set.heap_range());
+    }
+    pub unsafe fn descriptor_sets<'a>(&'a self) -> impl Iterator<Item = api::VkDescriptorSet> + 'a {
+        self.descriptor_sets
+            .iter()
+            .map(|descriptor_set| descriptor_set.get_handle())
+    }
+    pub fn reset(&mut self) {
+        self.descriptor_sets.clear();
+        self.free_ranges.clear();
+        let heap_size = self.heap.len() as u32;
+        self.free_range(0..heap_size);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn create_set_layout(
+        bindings: &[(u32, api::VkDescriptorType, u32)],
+    ) -> Result<DescriptorSetLayout, api::VkResult> {
+        let bindings: Vec<_> = bindings
+            .iter()
+            .map(|&(binding, descriptor_type, descriptor_count)| {
+                api::VkDescriptorSetLayoutBinding {
+                    binding,
+                    descriptorType: descriptor_type,
+                    descriptorCount: descriptor_count,
+                    stageFlags: api::VK_SHADER_STAGE_ALL,
+                    pImmutableSamplers: ptr::null(),
+                }
+            })
+            .collect();
+        DescriptorSetLayout::new(&api::VkDescriptorSetLayoutCreateInfo {
+            sType: api::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            bindingCount: bindings.len() as u32,
+            pBindings: bindings.as_ptr(),
+        })
+    }
+
+    #[test]
+    fn test_sparse_bindings() {
+        unsafe {
+            let layout = create_set_layout(&[
+                (0, api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER, 2),
+                (3, api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER, 0),
+                (5, api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER, 3),
+            ])
+            .unwrap();
+            let descriptor_set = DescriptorSet {
+                layout: layout.layout.clone(),
+                heap_offset: 0,
+                descriptors: NonNull::dangling(),
+                immutable_samplers: layout.immutable_samplers.clone(),
+            };
+            // continues past the gaps and the empty binding into binding 5
+            assert_eq!(
+                descriptor_set.get_descriptor_indices(0, 1, 3),
+                [(0, 1), (5, 2), (5, 3)]
+            );
+            assert_eq!(descriptor_set.get_descriptor_indices(5, 2, 1), [(5, 4)]);
+        }
+    }
+
+    #[test]
+    fn test_texel_buffers_rejected() {
+        unsafe {
+            for &descriptor_type in &[
+                api::VK_DESCRIPTOR_TYPE_UNIFORM_TEXEL_BUFFER,
+                api::VK_DESCRIPTOR_TYPE_STORAGE_TEXEL_BUFFER,
+            ] {
+                assert_eq!(
+                    create_set_layout(&[(0, descriptor_type, 1)]).err(),
+                    Some(api::VK_ERROR_FEATURE_NOT_PRESENT)
+                );
+            }
+        }
+    }
+}
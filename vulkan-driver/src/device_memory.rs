@@ -1,11 +1,100 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later
 // Copyright 2018 Jacob Lifshay
+
+//! device memory allocation and mapping
+//!
+//! device memory is host memory, so mapping memory returns a pointer into the allocation itself,
+//! and the memory is host coherent, so flushing and invalidating mapped ranges does nothing.
+//! memory can stay mapped while it's used by submissions: commands run on the thread that calls
+//! `vkQueueSubmit`, before it returns, so they see every host write made before the submit, and
+//! fences are signaled with release ordering and waited on with acquire ordering, so the host
+//! sees everything the commands wrote once a wait returns.
+//!
+//! placed mappings (`VK_EXT_map_memory_placed`) map an allocation's pages a second time, at an
+//! address the application chose. commands only use pointers computed from the allocation's own
+//! address, so they aren't affected by placed mappings coming and going. only shared mappings can
+//! be mapped twice, so memory that can be placed gets its own `MAP_SHARED` mapping, which is then
+//! mapped again with linux's `mremap`.
+
 use api;
+use constants::MIN_MEMORY_MAP_ALIGNMENT;
 use enum_map::EnumMap;
+#[cfg(unix)]
+use errno;
+#[cfg(unix)]
+use libc;
 use std::alloc;
+use std::env;
 use std::fmt::{self, Debug, Display};
 use std::ops::{Deref, DerefMut};
+#[cfg(unix)]
+use std::ptr::null_mut;
 use std::ptr::NonNull;
+#[cfg(unix)]
+use std::sync::Mutex;
+
+/// the minimum alignment of device memory allocations, in bytes. must be a power of 2 that's at
+/// least `MIN_MEMORY_MAP_ALIGNMENT`
+pub const ALLOCATION_ALIGNMENT_ENV_VAR: &str = "KAZAN_ALLOCATION_ALIGNMENT";
+
+/// when set, device memory is allocated with `mmap` and large allocations are marked as
+/// candidates for transparent huge pages
+pub const HUGE_PAGES_ENV_VAR: &str = "KAZAN_HUGE_PAGES";
+
+/// when set, device memory is allocated with `mmap` and placed right before an inaccessible
+/// page, so writes past the end of an allocation fault where they happen instead of silently
+/// corrupting other memory. this is meant for debugging the rasterizer and JITed shaders
+pub const GUARD_PAGES_ENV_VAR: &str = "KAZAN_GUARD_PAGES";
+
+fn is_env_var_enabled(name: &str) -> bool {
+    match env::var(name) {
+        Ok(ref v) => !v.is_empty() && v != "0",
+        Err(_) => false,
+    }
+}
+
+/// how device memory is allocated
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DeviceMemoryConfig {
+    /// set by `KAZAN_ALLOCATION_ALIGNMENT`
+    pub alignment: usize,
+    /// set by `KAZAN_HUGE_PAGES`
+    pub huge_pages: bool,
+    /// set by `KAZAN_GUARD_PAGES`
+    pub guard_pages: bool,
+}
+
+impl Default for DeviceMemoryConfig {
+    fn default() -> Self {
+        DeviceMemoryConfig {
+            alignment: MIN_MEMORY_MAP_ALIGNMENT,
+            huge_pages: false,
+            guard_pages: false,
+        }
+    }
+}
+
+impl DeviceMemoryConfig {
+    pub fn from_environment() -> Self {
+        let mut retval = Self::default();
+        if let Ok(alignment) = env::var(ALLOCATION_ALIGNMENT_ENV_VAR) {
+            match alignment.parse::<usize>() {
+                Ok(alignment)
+                    if alignment.is_power_of_two() && alignment >= MIN_MEMORY_MAP_ALIGNMENT =>
+                {
+                    retval.alignment = alignment
+                }
+                _ => eprintln!(
+                    "{} must be a power of 2 that's at least {}, ignoring: {:?}",
+                    ALLOCATION_ALIGNMENT_ENV_VAR, MIN_MEMORY_MAP_ALIGNMENT, alignment
+                ),
+            }
+        }
+        retval.huge_pages = is_env_var_enabled(HUGE_PAGES_ENV_VAR);
+        retval.guard_pages = is_env_var_enabled(GUARD_PAGES_ENV_VAR);
+        retval
+    }
+}
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Enum)]
 #[repr(u32)]
@@ -180,12 +269,63 @@ impl DeviceMemoryLayout {
     }
 }
 
+/// the granularity of `mmap`, and so the alignment of placed mappings
+#[cfg(unix)]
+pub fn get_page_size() -> usize {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    assert!(page_size.is_power_of_two());
+    page_size
+}
+
+#[cfg(not(unix))]
+pub fn get_page_size() -> usize {
+    4096
+}
+
+#[derive(Debug)]
+pub enum PlacedMappingFailure {
+    /// the memory wasn't allocated so it can be mapped a second time
+    NotPlaceable,
+    #[cfg(unix)]
+    Mmap(errno::Errno),
+}
+
+impl Display for PlacedMappingFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlacedMappingFailure::NotPlaceable => {
+                f.write_str("device memory wasn't allocated to be placed")
+            }
+            #[cfg(unix)]
+            PlacedMappingFailure::Mmap(error) => {
+                write!(f, "placing device memory failed: {}", error)
+            }
+        }
+    }
+}
+
 pub trait DeviceMemoryAllocation: 'static + Send + Sync + Debug {
     unsafe fn get(&self) -> NonNull<u8>;
     fn layout(&self) -> DeviceMemoryLayout;
     fn size(&self) -> usize {
         self.layout().size
     }
+    /// map `size` bytes starting at `offset` again at `address`, replacing whatever was mapped
+    /// there. `offset`, `size` and `address` have to be multiples of the page size, and there
+    /// can only be one placed mapping at a time
+    unsafe fn map_placed(
+        &self,
+        _offset: usize,
+        _size: usize,
+        _address: NonNull<u8>,
+    ) -> Result<(), PlacedMappingFailure> {
+        Err(PlacedMappingFailure::NotPlaceable)
+    }
+    /// remove the placed mapping, if there is one. when `reserve` is set, the addresses are left
+    /// reserved, so nothing else is mapped there until the application unmaps them
+    unsafe fn unmap_placed(&self, _reserve: bool) -> Result<(), PlacedMappingFailure> {
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -240,6 +380,217 @@ impl Drop for DefaultDeviceMemoryAllocation {
     }
 }
 
+/// the size of the huge pages used for transparent huge pages on x86_64 and most other targets
+#[cfg(unix)]
+const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct MappedDeviceMemoryAllocationFailure(errno::Errno);
+
+#[cfg(unix)]
+impl Display for MappedDeviceMemoryAllocationFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "device memory allocation failed with mmap: {}", self.0)
+    }
+}
+
+/// device memory from its own `mmap`, for huge pages, guard pages and placed mappings
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct MappedDeviceMemoryAllocation {
+    mapping: NonNull<u8>,
+    mapping_size: usize,
+    memory: NonNull<u8>,
+    layout: DeviceMemoryLayout,
+    /// mapped with `MAP_SHARED`, so the memory can be placed
+    shared: bool,
+    /// the address and size of the placed mapping
+    placed_mapping: Mutex<Option<(NonNull<u8>, usize)>>,
+}
+
+#[cfg(unix)]
+impl MappedDeviceMemoryAllocation {
+    /// when `guard_pages` is set, the memory ends right before an inaccessible page, and there's
+    /// an inaccessible page at the start of the mapping.
+    /// when `huge_pages` is set, allocations of at least a huge page are aligned to one and the
+    /// mapping is marked as a candidate for transparent huge pages; that's only done on linux.
+    /// when `shared` is set, the memory can be placed, which needs a layout that's made of whole
+    /// pages
+    pub fn new(
+        layout: DeviceMemoryLayout,
+        huge_pages: bool,
+        guard_pages: bool,
+        shared: bool,
+    ) -> Result<Self, MappedDeviceMemoryAllocationFailure> {
+        unsafe {
+            let page_size = get_page_size();
+            if shared {
+                assert_eq!(layout.size % page_size, 0);
+                assert!(layout.alignment >= page_size);
+            }
+            let mut alignment = layout.alignment.max(page_size);
+            if huge_pages && layout.size >= HUGE_PAGE_SIZE {
+                alignment = alignment.max(HUGE_PAGE_SIZE);
+            }
+            let guard_size = if guard_pages { page_size } else { 0 };
+            // leaves enough room to align the memory and still have whole guard pages around it
+            let mapping_size =
+                (guard_size * 2 + layout.size + alignment + page_size - 1) & !(page_size - 1);
+            let mapping = libc::mmap(
+                null_mut(),
+                mapping_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                if shared {
+                    libc::MAP_SHARED | libc::MAP_ANONYMOUS
+                } else {
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS
+                },
+                -1,
+                0,
+            );
+            if mapping == libc::MAP_FAILED {
+                return Err(MappedDeviceMemoryAllocationFailure(errno::errno()));
+            }
+            let mapping_address = mapping as usize;
+            let memory_address = if guard_pages {
+                // the size is a multiple of the required alignment, so aligning the end aligns
+                // the start too
+                let guard_address =
+                    (mapping_address + guard_size + layout.size + alignment - 1) & !(alignment - 1);
+                for &address in &[mapping_address, guard_address] {
+                    if libc::mprotect(address as *mut libc::c_void, page_size, libc::PROT_NONE) != 0
+                    {
+                        let error = errno::errno();
+                        libc::munmap(mapping, mapping_size);
+                        return Err(MappedDeviceMemoryAllocationFailure(error));
+                    }
+                }
+                guard_address - layout.size
+            } else {
+                (mapping_address + alignment - 1) & !(alignment - 1)
+            };
+            #[cfg(target_os = "linux")]
+            {
+                if huge_pages {
+                    // transparent huge pages may be disabled, in which case this is only a hint
+                    libc::madvise(mapping, mapping_size, libc::MADV_HUGEPAGE);
+                }
+            }
+            Ok(Self {
+                mapping: NonNull::new_unchecked(mapping as *mut u8),
+                mapping_size,
+                memory: NonNull::new_unchecked(memory_address as *mut u8),
+                layout,
+                shared,
+                placed_mapping: Mutex::new(None),
+            })
+        }
+    }
+}
+
+#[cfg(unix)]
+unsafe impl Send for MappedDeviceMemoryAllocation {}
+
+#[cfg(unix)]
+unsafe impl Sync for MappedDeviceMemoryAllocation {}
+
+#[cfg(unix)]
+impl DeviceMemoryAllocation for MappedDeviceMemoryAllocation {
+    unsafe fn get(&self) -> NonNull<u8> {
+        self.memory
+    }
+    fn layout(&self) -> DeviceMemoryLayout {
+        self.layout
+    }
+    #[cfg(target_os = "linux")]
+    unsafe fn map_placed(
+        &self,
+        offset: usize,
+        size: usize,
+        address: NonNull<u8>,
+    ) -> Result<(), PlacedMappingFailure> {
+        if !self.shared {
+            return Err(PlacedMappingFailure::NotPlaceable);
+        }
+        let page_size = get_page_size();
+        assert_eq!((offset | size | address.as_ptr() as usize) % page_size, 0);
+        assert!(size != 0 && offset + size <= self.layout.size);
+        let mut placed_mapping = self.placed_mapping.lock().unwrap();
+        assert!(placed_mapping.is_none(), "device memory is already mapped");
+        // with an old size of 0, mremap maps the same pages again instead of moving them
+        let mapping = libc::mremap(
+            self.memory.as_ptr().add(offset) as *mut libc::c_void,
+            0,
+            size,
+            libc::MREMAP_MAYMOVE | libc::MREMAP_FIXED,
+            address.as_ptr() as *mut libc::c_void,
+        );
+        if mapping == libc::MAP_FAILED {
+            return Err(PlacedMappingFailure::Mmap(errno::errno()));
+        }
+        *placed_mapping = Some((address, size));
+        Ok(())
+    }
+    unsafe fn unmap_placed(&self, reserve: bool) -> Result<(), PlacedMappingFailure> {
+        let (address, size) = match self.placed_mapping.lock().unwrap().take() {
+            Some(placed_mapping) => placed_mapping,
+            None => return Ok(()),
+        };
+        let address = address.as_ptr() as *mut libc::c_void;
+        let failed = if reserve {
+            // replacing the placed mapping, instead of unmapping it first, keeps other threads
+            // from mapping something else at the reserved addresses in between
+            libc::mmap(
+                address,
+                size,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED | libc::MAP_NORESERVE,
+                -1,
+                0,
+            ) == libc::MAP_FAILED
+        } else {
+            libc::munmap(address, size) != 0
+        };
+        if failed {
+            Err(PlacedMappingFailure::Mmap(errno::errno()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for MappedDeviceMemoryAllocation {
+    fn drop(&mut self) {
+        unsafe {
+            // freeing memory unmaps it
+            let _ = self.unmap_placed(false);
+            libc::munmap(
+                self.mapping.as_ptr() as *mut libc::c_void,
+                self.mapping_size,
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DeviceMemoryAllocationFailure {
+    Default(DefaultDeviceMemoryAllocationFailure),
+    #[cfg(unix)]
+    Mapped(MappedDeviceMemoryAllocationFailure),
+}
+
+impl Display for DeviceMemoryAllocationFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceMemoryAllocationFailure::Default(failure) => Display::fmt(failure, f),
+            #[cfg(unix)]
+            DeviceMemoryAllocationFailure::Mapped(failure) => Display::fmt(failure, f),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum DeviceMemory {
     Default(DefaultDeviceMemoryAllocation),
@@ -255,6 +606,38 @@ impl DeviceMemory {
             layout,
         )?))
     }
+    /// allocates from the default heap, unless `config` asks for huge pages or guard pages, or
+    /// the memory has to be `placeable` (`VK_EXT_map_memory_placed`). huge pages and guard pages
+    /// need `mmap`, so they're ignored on targets other than unix, and placing memory needs
+    /// `mremap`, so it's ignored on targets other than linux
+    pub fn allocate(
+        layout: DeviceMemoryLayout,
+        config: &DeviceMemoryConfig,
+        placeable: bool,
+    ) -> Result<Self, DeviceMemoryAllocationFailure> {
+        let mut layout =
+            DeviceMemoryLayout::calculate(layout.size, layout.alignment.max(config.alignment));
+        let placeable = placeable && cfg!(target_os = "linux");
+        if placeable {
+            layout =
+                DeviceMemoryLayout::calculate(layout.size, layout.alignment.max(get_page_size()));
+        }
+        #[cfg(unix)]
+        {
+            if config.huge_pages || config.guard_pages || placeable {
+                return Ok(DeviceMemory::Special(Box::new(
+                    MappedDeviceMemoryAllocation::new(
+                        layout,
+                        config.huge_pages,
+                        config.guard_pages,
+                        placeable,
+                    )
+                    .map_err(DeviceMemoryAllocationFailure::Mapped)?,
+                )));
+            }
+        }
+        Self::allocate_from_default_heap(layout).map_err(DeviceMemoryAllocationFailure::Default)
+    }
 }
 
 impl DeviceMemoryAllocation for DeviceMemory {
@@ -270,4 +653,168 @@ impl DeviceMemoryAllocation for DeviceMemory {
             DeviceMemory::Special(memory) => memory.as_ref().layout(),
         }
     }
+    unsafe fn map_placed(
+        &self,
+        offset: usize,
+        size: usize,
+        address: NonNull<u8>,
+    ) -> Result<(), PlacedMappingFailure> {
+        match self {
+            DeviceMemory::Default(memory) => memory.map_placed(offset, size, address),
+            DeviceMemory::Special(memory) => memory.as_ref().map_placed(offset, size, address),
+        }
+    }
+    unsafe fn unmap_placed(&self, reserve: bool) -> Result<(), PlacedMappingFailure> {
+        match self {
+            DeviceMemory::Default(memory) => memory.unmap_placed(reserve),
+            DeviceMemory::Special(memory) => memory.as_ref().unmap_placed(reserve),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::ptr;
+
+    fn allocate(size: usize, config: &DeviceMemoryConfig, placeable: bool) -> DeviceMemory {
+        DeviceMemory::allocate(
+            DeviceMemoryLayout::calculate(size, MIN_MEMORY_MAP_ALIGNMENT),
+            config,
+            placeable,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_alignment() {
+        for &alignment in &[MIN_MEMORY_MAP_ALIGNMENT, 4096, 1 << 16] {
+            for &(huge_pages, guard_pages) in &[(false, false), (true, false), (false, true)] {
+                let config = DeviceMemoryConfig {
+                    alignment,
+                    huge_pages,
+                    guard_pages,
+                };
+                let memory = allocate(100, &config, false);
+                let layout = memory.layout();
+                assert_eq!(layout.alignment, alignment, "{:?}", config);
+                // the size is rounded up to the alignment too
+                assert_eq!(layout.size, alignment, "{:?}", config);
+                assert_eq!(
+                    unsafe { memory.get() }.as_ptr() as usize % alignment,
+                    0,
+                    "{:?}",
+                    config
+                );
+            }
+        }
+    }
+
+    /// runs `f` in a child process, returning the signal that killed it, if any
+    #[cfg(unix)]
+    fn run_in_child_process<F: FnOnce()>(f: F) -> Option<i32> {
+        unsafe {
+            match libc::fork() {
+                -1 => panic!("fork failed: {}", errno::errno()),
+                0 => {
+                    f();
+                    libc::_exit(0);
+                }
+                child => {
+                    let mut status = 0;
+                    assert_eq!(libc::waitpid(child, &mut status, 0), child);
+                    if libc::WIFSIGNALED(status) {
+                        Some(libc::WTERMSIG(status))
+                    } else {
+                        assert_eq!(libc::WEXITSTATUS(status), 0);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_guard_pages() {
+        let config = DeviceMemoryConfig {
+            guard_pages: true,
+            ..DeviceMemoryConfig::default()
+        };
+        let memory = allocate(100, &config, false);
+        let size = memory.size();
+        let address = unsafe { memory.get() }.as_ptr();
+        // the whole allocation is usable, and the byte after it is on the guard page
+        assert_eq!(
+            run_in_child_process(|| unsafe { ptr::write_volatile(address.add(size - 1), 1) }),
+            None
+        );
+        let signal = run_in_child_process(|| unsafe { ptr::write_volatile(address.add(size), 1) });
+        assert!(
+            signal == Some(libc::SIGSEGV) || signal == Some(libc::SIGBUS),
+            "{:?}",
+            signal
+        );
+    }
+
+    /// the permissions of the mapping containing `address`, like `rw-p`, or `None` if nothing
+    /// is mapped there
+    #[cfg(target_os = "linux")]
+    fn get_permissions(address: usize) -> Option<String> {
+        use std::fs;
+        let maps = fs::read_to_string("/proc/self/maps").unwrap();
+        for line in maps.lines() {
+            let mut fields = line.split_whitespace();
+            let mut range = fields.next().unwrap().split('-');
+            let start = usize::from_str_radix(range.next().unwrap(), 16).unwrap();
+            let end = usize::from_str_radix(range.next().unwrap(), 16).unwrap();
+            if start <= address && address < end {
+                return Some(fields.next().unwrap().into());
+            }
+        }
+        None
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_placed_mapping() {
+        let page_size = get_page_size();
+        let size = page_size * 2;
+        let memory = allocate(size, &DeviceMemoryConfig::default(), true);
+        assert_eq!(memory.size(), size);
+        // memory that isn't placeable can't be placed
+        let default_memory = allocate(size, &DeviceMemoryConfig::default(), false);
+        unsafe {
+            // the application reserves the addresses, then places the memory there
+            let reserved = libc::mmap(
+                null_mut(),
+                size,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            );
+            assert_ne!(reserved, libc::MAP_FAILED);
+            let address = NonNull::new(reserved as *mut u8).unwrap();
+            match default_memory.map_placed(0, size, address) {
+                Err(PlacedMappingFailure::NotPlaceable) => {}
+                result => panic!("{:?}", result),
+            }
+            memory.map_placed(page_size, page_size, address).unwrap();
+            ptr::write_volatile(address.as_ptr().add(1), 0x12);
+            assert_eq!(*memory.get().as_ptr().add(page_size + 1), 0x12);
+            *memory.get().as_ptr().add(page_size + 2) = 0x34;
+            assert_eq!(ptr::read_volatile(address.as_ptr().add(2)), 0x34);
+            // unmapping with the reserve bit leaves the addresses reserved
+            memory.unmap_placed(true).unwrap();
+            assert_eq!(get_permissions(reserved as usize).unwrap(), "---p");
+            assert_eq!(*memory.get().as_ptr().add(page_size + 1), 0x12);
+            // and without it, unmaps them
+            memory.map_placed(0, size, address).unwrap();
+            assert_eq!(get_permissions(reserved as usize).unwrap(), "rw-s");
+            memory.unmap_placed(false).unwrap();
+            assert_eq!(get_permissions(reserved as usize), None);
+        }
+    }
 }
@@ -0,0 +1,164 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! kazan's internal configuration, reported through `VK_KAZAN_driver_configuration` and
+//! `VK_EXT_tooling_info` so it can be included in bug reports
+
+use api;
+use command_buffer;
+use compile_thread_pool::CompileThreadPool;
+use coverage;
+use device_memory::DeviceMemoryConfig;
+use pipeline;
+use shader_compiler_backend::Compiler;
+use shader_compiler_backend_llvm_7::LLVM_7_SHADER_COMPILER;
+use std::os::raw::c_char;
+use std::path::PathBuf;
+use std::ptr::null_mut;
+use submit_graph;
+use trace;
+use validation::Validation;
+use watchdog::WatchdogConfig;
+
+/// copies as much of `src` as fits, leaving room for the terminating NUL
+fn copy_str_to_char_array_truncating(dest: &mut [c_char], src: &str) {
+    let mut length = src.len().min(dest.len() - 1);
+    while !src.is_char_boundary(length) {
+        length -= 1;
+    }
+    for (dest, &src) in dest.iter_mut().zip(&src.as_bytes()[..length]) {
+        *dest = src as c_char;
+    }
+    for v in dest.iter_mut().skip(length) {
+        *v = 0;
+    }
+}
+
+/// the optional subsystems compiled into the driver, set by cargo features. tessellation,
+/// geometry shaders, ray tracing and compressed formats aren't implemented, so no build has them
+/// and their device features are always unsupported; they get features once they're implemented
+pub fn get_build_features() -> Vec<&'static str> {
+    let mut retval = Vec::new();
+    if cfg!(feature = "wsi") {
+        retval.push("wsi");
+    }
+    if cfg!(feature = "wsi-xcb") {
+        retval.push("wsi-xcb");
+    }
+    retval
+}
+
+#[derive(Clone, Debug)]
+pub struct DriverConfiguration {
+    pub compiler_name: &'static str,
+    /// from `get_build_features`
+    pub build_features: Vec<&'static str>,
+    /// the range of SIMD widths shader stages are compiled with; each stage picks its own (see
+    /// `pipeline::ShaderStage::simd_width`)
+    pub min_variable_vector_length_multiplier: u32,
+    pub max_variable_vector_length_multiplier: u32,
+    pub compile_thread_count: usize,
+    /// set by `KAZAN_SUBMIT_THREADS`
+    pub submit_thread_count: usize,
+    /// set by `KAZAN_VALIDATION`
+    pub validation: bool,
+    /// set by `KAZAN_DUMP_SHADERS`
+    pub shader_dump_directory: Option<PathBuf>,
+    /// set by `KAZAN_NULL_DRIVER`
+    pub null_driver: bool,
+    /// set by `KAZAN_DITHER_ALPHA_TO_COVERAGE`
+    pub dither_alpha_to_coverage: bool,
+    /// set by `KAZAN_ALLOCATION_ALIGNMENT`, `KAZAN_HUGE_PAGES` and `KAZAN_GUARD_PAGES`
+    pub memory: DeviceMemoryConfig,
+    /// set by `KAZAN_WATCHDOG_TIMEOUT` and `KAZAN_LOOP_ITERATION_LIMIT`
+    pub watchdog: WatchdogConfig,
+    /// set by `KAZAN_TRACE`
+    pub trace_path: Option<PathBuf>,
+    /// where compiled shaders are cached between runs; always `None`, since there's no on-disk
+    /// cache yet
+    pub cache_directory: Option<PathBuf>,
+}
+
+impl DriverConfiguration {
+    pub fn from_environment() -> Self {
+        DriverConfiguration {
+            compiler_name: LLVM_7_SHADER_COMPILER.name(),
+            build_features: get_build_features(),
+            min_variable_vector_length_multiplier: pipeline::MIN_SUBGROUP_SIZE,
+            max_variable_vector_length_multiplier: pipeline::MAX_SUBGROUP_SIZE,
+            compile_thread_count: CompileThreadPool::default_thread_count(),
+            submit_thread_count: submit_graph::get_submit_thread_count(),
+            validation: Validation::is_enabled_in_environment(),
+            shader_dump_directory: pipeline::get_shader_dump_directory(),
+            null_driver: command_buffer::is_null_driver_enabled_in_environment(),
+            dither_alpha_to_coverage:
+                coverage::is_alpha_to_coverage_dithering_enabled_in_environment(),
+            memory: DeviceMemoryConfig::from_environment(),
+            watchdog: WatchdogConfig::from_environment(),
+            trace_path: trace::get_trace_path(),
+            cache_directory: None,
+        }
+    }
+    /// kazan reports itself as the only tool, since its validation and debug reporting are
+    /// built in rather than provided by layers
+    pub fn get_tool_properties(&self) -> api::VkPhysicalDeviceToolPropertiesEXT {
+        let mut purposes = api::VK_TOOL_PURPOSE_DEBUG_REPORTING_BIT_EXT;
+        if self.validation {
+            purposes |= api::VK_TOOL_PURPOSE_VALIDATION_BIT_EXT;
+        }
+        if self.null_driver || self.trace_path.is_some() {
+            purposes |= api::VK_TOOL_PURPOSE_PROFILING_BIT_EXT;
+        }
+        let mut retval = api::VkPhysicalDeviceToolPropertiesEXT {
+            sType: api::VK_STRUCTURE_TYPE_PHYSICAL_DEVICE_TOOL_PROPERTIES_EXT,
+            pNext: null_mut(),
+            name: [0; api::VK_MAX_EXTENSION_NAME_SIZE as usize],
+            version: [0; api::VK_MAX_EXTENSION_NAME_SIZE as usize],
+            purposes,
+            description: [0; api::VK_MAX_DESCRIPTION_SIZE as usize],
+            layer: [0; api::VK_MAX_EXTENSION_NAME_SIZE as usize],
+        };
+        copy_str_to_char_array_truncating(&mut retval.name, "Kazan");
+        copy_str_to_char_array_truncating(&mut retval.version, env!("CARGO_PKG_VERSION"));
+        let build_features = if self.build_features.is_empty() {
+            "none".into()
+        } else {
+            self.build_features.join(", ")
+        };
+        copy_str_to_char_array_truncating(
+            &mut retval.description,
+            &format!(
+                "built-in debug reporting, and validation if KAZAN_VALIDATION is set. optional \
+                 features: {}",
+                build_features
+            ),
+        );
+        retval
+    }
+    pub fn get_api_configuration(&self) -> api::VkDriverConfigurationKAZAN {
+        let mut retval = api::VkDriverConfigurationKAZAN {
+            compilerBackend: [0; api::VK_MAX_DESCRIPTION_SIZE as usize],
+            minVariableVectorLengthMultiplier: self.min_variable_vector_length_multiplier,
+            maxVariableVectorLengthMultiplier: self.max_variable_vector_length_multiplier,
+            compileThreadCount: self.compile_thread_count as u32,
+            submitThreadCount: self.submit_thread_count as u32,
+            validationEnabled: self.validation as api::VkBool32,
+            shaderDumpDirectory: [0; api::VK_MAX_PATH_SIZE_KAZAN as usize],
+            cacheDirectory: [0; api::VK_MAX_PATH_SIZE_KAZAN as usize],
+        };
+        copy_str_to_char_array_truncating(&mut retval.compilerBackend, self.compiler_name);
+        if let Some(shader_dump_directory) = &self.shader_dump_directory {
+            copy_str_to_char_array_truncating(
+                &mut retval.shaderDumpDirectory,
+                &shader_dump_directory.to_string_lossy(),
+            );
+        }
+        if let Some(cache_directory) = &self.cache_directory {
+            copy_str_to_char_array_truncating(
+                &mut retval.cacheDirectory,
+                &cache_directory.to_string_lossy(),
+            );
+        }
+        retval
+    }
+}
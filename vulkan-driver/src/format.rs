@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+use api;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum FormatKind {
+    Color,
+    Depth,
+    DepthStencil,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FormatInfo {
+    pub kind: FormatKind,
+    pub texel_size_in_bytes: usize,
+}
+
+/// `None` for the formats `PhysicalDevice::get_format_properties` reports no features for.
+/// images can only be created with formats that have features, so the texel codecs below only
+/// handle the formats this returns `Some` for
+pub fn get_format_info(format: api::VkFormat) -> Option<FormatInfo> {
+    let (kind, texel_size_in_bytes) = match format {
+        api::VK_FORMAT_R8G8B8A8_UNORM
+        | api::VK_FORMAT_R8G8B8A8_SRGB
+        | api::VK_FORMAT_B8G8R8A8_UNORM
+        | api::VK_FORMAT_B8G8R8A8_SRGB => (FormatKind::Color, 4),
+        api::VK_FORMAT_D32_SFLOAT => (FormatKind::Depth, 4),
+        api::VK_FORMAT_D24_UNORM_S8_UINT => (FormatKind::DepthStencil, 4),
+        _ => return None,
+    };
+    Some(FormatInfo {
+        kind,
+        texel_size_in_bytes,
+    })
+}
+
+/// whether `format` has an alpha component; formats without one read alpha as one
+pub fn has_alpha(format: api::VkFormat) -> bool {
+    match format {
+        api::VK_FORMAT_R8G8B8A8_UNORM
+        | api::VK_FORMAT_R8G8B8A8_SRGB
+        | api::VK_FORMAT_B8G8R8A8_UNORM
+        | api::VK_FORMAT_B8G8R8A8_SRGB => true,
+        _ => false,
+    }
+}
+
+/// whether an image with format `image_format` can have a view with format `view_format`.
+/// images created with `VK_IMAGE_CREATE_MUTABLE_FORMAT_BIT` (`mutable`) can be viewed with any
+/// color format of the same size, which reinterprets the texels' bytes; depth and stencil formats
+/// can only be viewed with the image's format
+pub fn is_view_format_compatible(
+    image_format: api::VkFormat,
+    view_format: api::VkFormat,
+    mutable: bool,
+) -> bool {
+    if image_format == view_format {
+        return true;
+    }
+    if !mutable {
+        return false;
+    }
+    match (get_format_info(image_format), get_format_info(view_format)) {
+        (Some(image_format_info), Some(view_format_info)) => {
+            image_format_info.kind == FormatKind::Color
+                && view_format_info.kind == FormatKind::Color
+                && image_format_info.texel_size_in_bytes == view_format_info.texel_size_in_bytes
+        }
+        _ => false,
+    }
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn unorm8_to_f32(v: u8) -> f32 {
+    f32::from(v) / 255.0
+}
+
+fn f32_to_unorm8(v: f32) -> u8 {
+    // NaN converts to 0
+    (v.max(0.0).min(1.0) * 255.0).round() as u8
+}
+
+const UNORM24_MAX: u32 = (1 << 24) - 1;
+
+fn read_u32(texel: &[u8]) -> u32 {
+    u32::from(texel[0])
+        | u32::from(texel[1]) << 8
+        | u32::from(texel[2]) << 16
+        | u32::from(texel[3]) << 24
+}
+
+fn write_u32(texel: &mut [u8], v: u32) {
+    texel[0] = v as u8;
+    texel[1] = (v >> 8) as u8;
+    texel[2] = (v >> 16) as u8;
+    texel[3] = (v >> 24) as u8;
+}
+
+/// decode a texel into RGBA; depth formats return the depth in the red component
+pub fn decode_texel(format: api::VkFormat, texel: &[u8]) -> [f32; 4] {
+    match format {
+        api::VK_FORMAT_R8G8B8A8_UNORM => [
+            unorm8_to_f32(texel[0]),
+            unorm8_to_f32(texel[1]),
+            unorm8_to_f32(texel[2]),
+            unorm8_to_f32(texel[3]),
+        ],
+        api::VK_FORMAT_R8G8B8A8_SRGB => [
+            srgb_to_linear(unorm8_to_f32(texel[0])),
+            srgb_to_linear(unorm8_to_f32(texel[1])),
+            srgb_to_linear(unorm8_to_f32(texel[2])),
+            unorm8_to_f32(texel[3]),
+        ],
+        api::VK_FORMAT_B8G8R8A8_UNORM => [
+            unorm8_to_f32(texel[2]),
+            unorm8_to_f32(texel[1]),
+            unorm8_to_f32(texel[0]),
+            unorm8_to_f32(texel[3]),
+        ],
+        api::VK_FORMAT_B8G8R8A8_SRGB => [
+            srgb_to_linear(unorm8_to_f32(texel[2])),
+            srgb_to_linear(unorm8_to_f32(texel[1])),
+            srgb_to_linear(unorm8_to_f32(texel[0])),
+            unorm8_to_f32(texel[3]),
+        ],
+        api::VK_FORMAT_D32_SFLOAT | api::VK_FORMAT_D24_UNORM_S8_UINT => {
+            [decode_depth(format, texel), 0.0, 0.0, 1.0]
+        }
+        _ => unreachable!("decode_texel: {} has no format features", format),
+    }
+}
+
+/// encode RGBA into a texel; depth formats take the depth from the red component
+pub fn encode_texel(format: api::VkFormat, texel: &mut [u8], value: [f32; 4]) {
+    match format {
+        api::VK_FORMAT_R8G8B8A8_UNORM => {
+            for (texel, &value) in texel.iter_mut().zip(value.iter()) {
+                *texel = f32_to_unorm8(value);
+            }
+        }
+        api::VK_FORMAT_R8G8B8A8_SRGB => {
+            texel[0] = f32_to_unorm8(linear_to_srgb(value[0]));
+            texel[1] = f32_to_unorm8(linear_to_srgb(value[1]));
+            texel[2] = f32_to_unorm8(linear_to_srgb(value[2]));
+            texel[3] = f32_to_unorm8(value[3]);
+        }
+        api::VK_FORMAT_B8G8R8A8_UNORM => {
+            texel[0] = f32_to_unorm8(value[2]);
+            texel[1] = f32_to_unorm8(value[1]);
+            texel[2] = f32_to_unorm8(value[0]);
+            texel[3] = f32_to_unorm8(value[3]);
+        }
+        api::VK_FORMAT_B8G8R8A8_SRGB => {
+            texel[0] = f32_to_unorm8(linear_to_srgb(value[2]));
+            texel[1] = f32_to_unorm8(linear_to_srgb(value[1]));
+            texel[2] = f32_to_unorm8(linear_to_srgb(value[0]));
+            texel[3] = f32_to_unorm8(value[3]);
+        }
+        api::VK_FORMAT_D32_SFLOAT | api::VK_FORMAT_D24_UNORM_S8_UINT => {
+            encode_depth(format, texel, value[0])
+        }
+        _ => unreachable!("encode_texel: {} has no format features", format),
+    }
+}
+
+/// D24_UNORM_S8_UINT is stored as a little-endian `u32` with the depth in the low 24 bits
+pub fn decode_depth(format: api::VkFormat, texel: &[u8]) -> f32 {
+    match format {
+        api::VK_FORMAT_D32_SFLOAT => f32::from_bits(read_u32(texel)),
+        api::VK_FORMAT_D24_UNORM_S8_UINT => {
+            (read_u32(texel) & UNORM24_MAX) as f32 / UNORM24_MAX as f32
+        }
+        _ => unreachable!("decode_depth: {} isn't a depth format", format),
+    }
+}
+
+pub fn encode_depth(format: api::VkFormat, texel: &mut [u8], depth: f32) {
+    match format {
+        api::VK_FORMAT_D32_SFLOAT => write_u32(texel, depth.to_bits()),
+        api::VK_FORMAT_D24_UNORM_S8_UINT => {
+            let depth =
+                (f64::from(depth.max(0.0).min(1.0)) * f64::from(UNORM24_MAX)).round() as u32;
+            let stencil = read_u32(texel) & !UNORM24_MAX;
+            write_u32(texel, stencil | depth);
+        }
+        _ => unreachable!("encode_depth: {} isn't a depth format", format),
+    }
+}
+
+pub fn decode_stencil(format: api::VkFormat, texel: &[u8]) -> u8 {
+    match format {
+        api::VK_FORMAT_D24_UNORM_S8_UINT => texel[3],
+        _ => unreachable!("decode_stencil: {} has no stencil aspect", format),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unorm8_round_trip() {
+        for &format in &[
+            api::VK_FORMAT_R8G8B8A8_UNORM,
+            api::VK_FORMAT_R8G8B8A8_SRGB,
+            api::VK_FORMAT_B8G8R8A8_UNORM,
+            api::VK_FORMAT_B8G8R8A8_SRGB,
+        ] {
+            for v in 0..=255u8 {
+                let texel = [v, v.wrapping_add(1), v.wrapping_add(2), v.wrapping_add(3)];
+                let mut encoded = [0; 4];
+                encode_texel(format, &mut encoded, decode_texel(format, &texel));
+                assert_eq!(texel, encoded, "format = {:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn test_view_format_compatibility() {
+        let compatible = |image_format, view_format, mutable| {
+            is_view_format_compatible(image_format, view_format, mutable)
+        };
+        assert!(compatible(
+            api::VK_FORMAT_D32_SFLOAT,
+            api::VK_FORMAT_D32_SFLOAT,
+            false
+        ));
+        assert!(!compatible(
+            api::VK_FORMAT_R8G8B8A8_UNORM,
+            api::VK_FORMAT_B8G8R8A8_SRGB,
+            false
+        ));
+        assert!(compatible(
+            api::VK_FORMAT_R8G8B8A8_UNORM,
+            api::VK_FORMAT_B8G8R8A8_SRGB,
+            true
+        ));
+        assert!(!compatible(
+            api::VK_FORMAT_R8G8B8A8_UNORM,
+            api::VK_FORMAT_D32_SFLOAT,
+            true
+        ));
+    }
+
+    #[test]
+    fn test_bgra_order() {
+        let texel = [0, 0, 255, 255];
+        assert_eq!(
+            decode_texel(api::VK_FORMAT_B8G8R8A8_UNORM, &texel),
+            [1.0, 0.0, 0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn test_depth_stencil() {
+        let mut texel = [0, 0, 0, 0x5A];
+        encode_depth(api::VK_FORMAT_D24_UNORM_S8_UINT, &mut texel, 1.0);
+        assert_eq!(
+            decode_stencil(api::VK_FORMAT_D24_UNORM_S8_UINT, &texel),
+            0x5A
+        );
+        assert_eq!(read_u32(&texel) & UNORM24_MAX, UNORM24_MAX);
+        encode_depth(api::VK_FORMAT_D24_UNORM_S8_UINT, &mut texel, 0.5);
+        assert!((decode_depth(api::VK_FORMAT_D24_UNORM_S8_UINT, &texel) - 0.5).abs() < 1e-6);
+        assert_eq!(
+            decode_stencil(api::VK_FORMAT_D24_UNORM_S8_UINT, &texel),
+            0x5A
+        );
+        encode_depth(api::VK_FORMAT_D32_SFLOAT, &mut texel, 0.25);
+        assert_eq!(
+            decode_depth(api::VK_FORMAT_D32_SFLOAT, &texel).to_bits(),
+            0.25f32.to_bits()
+        );
+    }
+}
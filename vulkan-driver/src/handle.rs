@@ -1,10 +1,25 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later
 // Copyright 2018 Jacob Lifshay
+
+//! the representations of Vulkan handles
+//!
+//! dispatchable handles are pointers to a `DispatchableType`, which starts with the
+//! pointer-sized slot the loader stores its dispatch table in. non-dispatchable handles are
+//! 64-bit values on every target, so on 32-bit targets the pointer is in the low 32 bits and the
+//! high bits are zero.
+
 use api;
 use api_impl::{Device, Instance, PhysicalDevice, Queue};
 use buffer::Buffer;
+use command_buffer::{CommandBuffer, CommandPool};
+use debug_utils::DebugUtilsMessenger;
+use descriptor_set::{DescriptorPool, DescriptorSet, DescriptorSetLayout};
 use device_memory::DeviceMemory;
-use image::Image;
+use image::{Image, ImageView};
+use object_pool;
+use pipeline::{Pipeline, PipelineLayout};
+use query::QueryPool;
+use render_pass::{Framebuffer, RenderPass};
 use sampler::Sampler;
 use sampler::SamplerYcbcrConversion;
 use shader_module::ShaderModule;
@@ -15,6 +30,7 @@ use std::ops::Deref;
 use std::ops::DerefMut;
 use std::ptr::null_mut;
 use std::ptr::NonNull;
+use std::sync::atomic::AtomicBool;
 use swapchain::Swapchain;
 
 #[repr(C)]
@@ -56,6 +72,23 @@ pub trait HandleAllocFree: Handle {
     }
 }
 
+/// allocates the handle's objects from an `ObjectPool` instead of with `Box`, for objects that
+/// are created and destroyed often
+macro_rules! impl_pooled_handle_alloc_free {
+    ($handle:ty) => {
+        impl_pooled_object!(<$handle as Handle>::Value);
+
+        impl HandleAllocFree for $handle {
+            unsafe fn allocate<T: Into<Self::Value>>(v: T) -> Self {
+                Self::new(Some(object_pool::allocate(v.into())))
+            }
+            unsafe fn free(self) {
+                object_pool::free(self.get().unwrap());
+            }
+        }
+    };
+}
+
 pub trait Handle: Copy + Eq + fmt::Debug {
     type Value;
     fn get(&self) -> Option<NonNull<Self::Value>>;
@@ -108,10 +141,10 @@ impl<T> fmt::Debug for DispatchableHandle<T> {
 impl<T> Handle for DispatchableHandle<T> {
     type Value = DispatchableType<T>;
     fn get(&self) -> Option<NonNull<DispatchableType<T>>> {
-        unsafe { mem::transmute(self.0) }
+        self.0.map(NonNull::cast)
     }
     fn new(v: Option<NonNull<DispatchableType<T>>>) -> Self {
-        unsafe { DispatchableHandle(mem::transmute(v), PhantomData) }
+        DispatchableHandle(v.map(NonNull::cast), PhantomData)
     }
 }
 
@@ -145,13 +178,17 @@ impl<T> fmt::Debug for NondispatchableHandle<T> {
 impl<T> Handle for NondispatchableHandle<T> {
     type Value = T;
     fn get(&self) -> Option<NonNull<T>> {
-        NonNull::new(self.0 as *mut T)
+        // in debug builds, catches handles that didn't come from kazan rather than silently
+        // dropping the high bits; release builds don't pay for a panic inside the entry points
+        debug_assert!(
+            self.0 <= usize::MAX as u64,
+            "invalid non-dispatchable handle: {:#x}",
+            self.0
+        );
+        NonNull::new(self.0 as usize as *mut T)
     }
     fn new(v: Option<NonNull<T>>) -> Self {
-        NondispatchableHandle(
-            v.map(|v| v.as_ptr()).unwrap_or(null_mut()) as u64,
-            PhantomData,
-        )
+        NondispatchableHandle(v.map_or(0, |v| v.as_ptr() as usize as u64), PhantomData)
     }
 }
 
@@ -302,23 +339,24 @@ pub type VkQueue = DispatchableHandle<Queue>;
 
 impl HandleAllocFree for VkQueue {}
 
-pub struct CommandBuffer {}
-
 pub type VkCommandBuffer = DispatchableHandle<CommandBuffer>;
 
-impl HandleAllocFree for VkCommandBuffer {}
+impl_pooled_handle_alloc_free!(VkCommandBuffer);
 
 pub struct Semaphore {}
 
 pub type VkSemaphore = NondispatchableHandle<Semaphore>;
 
-impl HandleAllocFree for VkSemaphore {}
+impl_pooled_handle_alloc_free!(VkSemaphore);
 
-pub struct Fence {}
+pub struct Fence {
+    /// submissions run before `vkQueueSubmit` returns, so nothing else is needed
+    pub signaled: AtomicBool,
+}
 
 pub type VkFence = NondispatchableHandle<Fence>;
 
-impl HandleAllocFree for VkFence {}
+impl_pooled_handle_alloc_free!(VkFence);
 
 pub type VkDeviceMemory = NondispatchableHandle<DeviceMemory>;
 
@@ -338,8 +376,6 @@ pub type VkEvent = NondispatchableHandle<Event>;
 
 impl HandleAllocFree for VkEvent {}
 
-pub struct QueryPool {}
-
 pub type VkQueryPool = NondispatchableHandle<QueryPool>;
 
 impl HandleAllocFree for VkQueryPool {}
@@ -350,11 +386,9 @@ pub type VkBufferView = NondispatchableHandle<BufferView>;
 
 impl HandleAllocFree for VkBufferView {}
 
-pub struct ImageView {}
-
 pub type VkImageView = NondispatchableHandle<ImageView>;
 
-impl HandleAllocFree for VkImageView {}
+impl_pooled_handle_alloc_free!(VkImageView);
 
 pub type VkShaderModule = NondispatchableHandle<ShaderModule>;
 
@@ -366,26 +400,18 @@ pub type VkPipelineCache = NondispatchableHandle<PipelineCache>;
 
 impl HandleAllocFree for VkPipelineCache {}
 
-pub struct PipelineLayout {}
-
 pub type VkPipelineLayout = NondispatchableHandle<PipelineLayout>;
 
 impl HandleAllocFree for VkPipelineLayout {}
 
-pub struct RenderPass {}
-
 pub type VkRenderPass = NondispatchableHandle<RenderPass>;
 
 impl HandleAllocFree for VkRenderPass {}
 
-pub struct Pipeline {}
-
 pub type VkPipeline = NondispatchableHandle<Pipeline>;
 
 impl HandleAllocFree for VkPipeline {}
 
-pub struct DescriptorSetLayout {}
-
 pub type VkDescriptorSetLayout = NondispatchableHandle<DescriptorSetLayout>;
 
 impl HandleAllocFree for VkDescriptorSetLayout {}
@@ -394,26 +420,18 @@ pub type VkSampler = NondispatchableHandle<Sampler>;
 
 impl HandleAllocFree for VkSampler {}
 
-pub struct DescriptorPool {}
-
 pub type VkDescriptorPool = NondispatchableHandle<DescriptorPool>;
 
 impl HandleAllocFree for VkDescriptorPool {}
 
-pub struct DescriptorSet {}
-
 pub type VkDescriptorSet = NondispatchableHandle<DescriptorSet>;
 
-impl HandleAllocFree for VkDescriptorSet {}
-
-pub struct Framebuffer {}
+impl_pooled_handle_alloc_free!(VkDescriptorSet);
 
 pub type VkFramebuffer = NondispatchableHandle<Framebuffer>;
 
 impl HandleAllocFree for VkFramebuffer {}
 
-pub struct CommandPool {}
-
 pub type VkCommandPool = NondispatchableHandle<CommandPool>;
 
 impl HandleAllocFree for VkCommandPool {}
@@ -454,9 +472,7 @@ pub type VkDebugReportCallbackEXT = NondispatchableHandle<DebugReportCallbackEXT
 
 impl HandleAllocFree for VkDebugReportCallbackEXT {}
 
-pub struct DebugUtilsMessengerEXT {}
-
-pub type VkDebugUtilsMessengerEXT = NondispatchableHandle<DebugUtilsMessengerEXT>;
+pub type VkDebugUtilsMessengerEXT = NondispatchableHandle<DebugUtilsMessenger>;
 
 impl HandleAllocFree for VkDebugUtilsMessengerEXT {}
 
@@ -465,3 +481,62 @@ pub struct ValidationCacheEXT {}
 pub type VkValidationCacheEXT = NondispatchableHandle<ValidationCacheEXT>;
 
 impl HandleAllocFree for VkValidationCacheEXT {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub struct TestValue(u32);
+
+    impl HandleAllocFree for NondispatchableHandle<TestValue> {}
+
+    impl HandleAllocFree for DispatchableHandle<TestValue> {}
+
+    #[test]
+    fn test_nondispatchable_handle() {
+        type TestHandle = NondispatchableHandle<TestValue>;
+        assert_eq!(mem::size_of::<TestHandle>(), mem::size_of::<u64>());
+        assert_eq!(
+            unsafe { mem::transmute::<TestHandle, u64>(TestHandle::null()) },
+            0
+        );
+        let value = OwnedHandle::<TestHandle>::new(TestValue(5));
+        let handle = unsafe { value.get_handle() };
+        let raw = unsafe { mem::transmute::<TestHandle, u64>(handle) };
+        assert_eq!(raw, &*value as *const TestValue as usize as u64);
+        let round_tripped = unsafe { mem::transmute::<u64, TestHandle>(raw) };
+        assert_eq!(round_tripped, handle);
+        let value = unsafe { SharedHandle::from(round_tripped) }.unwrap();
+        assert_eq!((*value).0, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid non-dispatchable handle")]
+    #[cfg(all(target_pointer_width = "32", debug_assertions))]
+    fn test_nondispatchable_handle_out_of_range() {
+        let handle = unsafe { mem::transmute::<u64, NondispatchableHandle<TestValue>>(1 << 32) };
+        handle.get();
+    }
+
+    #[test]
+    fn test_dispatchable_handle() {
+        type TestHandle = DispatchableHandle<TestValue>;
+        assert_eq!(mem::size_of::<TestHandle>(), mem::size_of::<usize>());
+        assert_eq!(
+            unsafe { mem::transmute::<TestHandle, usize>(TestHandle::null()) },
+            0
+        );
+        let value = OwnedHandle::<TestHandle>::new(TestValue(7));
+        let handle = unsafe { value.get_handle() };
+        let raw = unsafe { mem::transmute::<TestHandle, usize>(handle) };
+        // the loader overwrites the first pointer-sized slot with its dispatch table
+        assert_eq!(
+            unsafe { *(raw as *const usize) },
+            api::ICD_LOADER_MAGIC as usize
+        );
+        let round_tripped = unsafe { mem::transmute::<usize, TestHandle>(raw) };
+        assert_eq!(round_tripped, handle);
+        let value = unsafe { SharedHandle::from(round_tripped) }.unwrap();
+        assert_eq!((*value).0, 7);
+    }
+}
@@ -6,7 +6,8 @@
 )]
 use api;
 use constants::IMAGE_ALIGNMENT;
-use device_memory::DeviceMemoryLayout;
+use device_memory::{DeviceMemoryAllocation, DeviceMemoryLayout};
+use format::{self, FormatKind};
 use handle::SharedHandle;
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
@@ -18,10 +19,15 @@ pub enum SupportedTilings {
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum Tiling {
     Linear,
-    #[allow(dead_code)]
+    /// texels are stored in `TILE_SIZE` by `TILE_SIZE` tiles with the tiles in row-major order
     Tiled,
 }
 
+pub const TILE_SIZE: u32 = 4;
+
+/// enough for the maximum image dimension supported by Vulkan implementations
+pub const MAX_MIP_LEVELS: usize = 16;
+
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub enum ImageMultisampleCount {
     Count1,
@@ -31,6 +37,8 @@ pub enum ImageMultisampleCount {
 #[derive(Copy, Clone, Debug)]
 pub struct ImageProperties {
     pub supported_tilings: SupportedTilings,
+    pub image_type: api::VkImageType,
+    pub flags: api::VkImageCreateFlags,
     pub format: api::VkFormat,
     pub extents: api::VkExtent3D,
     pub array_layers: u32,
@@ -39,37 +47,159 @@ pub struct ImageProperties {
     pub swapchain_present_tiling: Option<Tiling>,
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct MipLevelLayout {
+    pub extents: api::VkExtent3D,
+    /// offset from the start of the array layer
+    pub offset: usize,
+    pub row_pitch: usize,
+    pub depth_pitch: usize,
+    pub size: usize,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct ImageComputedProperties {
     pub pixel_size_in_bytes: usize,
+    pub tiling: Tiling,
+    pub mip_level_layouts: [MipLevelLayout; MAX_MIP_LEVELS],
+    pub array_pitch: usize,
     pub memory_layout: DeviceMemoryLayout,
 }
 
+impl ImageComputedProperties {
+    /// get the offset in bytes of a texel from the start of the image's memory
+    pub fn get_texel_offset(
+        &self,
+        x: u32,
+        y: u32,
+        z: u32,
+        array_layer: u32,
+        mip_level: u32,
+    ) -> usize {
+        let mip_level_layout = &self.mip_level_layouts[mip_level as usize];
+        debug_assert!(x < mip_level_layout.extents.width);
+        debug_assert!(y < mip_level_layout.extents.height);
+        debug_assert!(z < mip_level_layout.extents.depth);
+        let offset_in_slice = match self.tiling {
+            Tiling::Linear => {
+                x as usize * self.pixel_size_in_bytes + y as usize * mip_level_layout.row_pitch
+            }
+            Tiling::Tiled => {
+                const TILE_AREA: usize = (TILE_SIZE * TILE_SIZE) as usize;
+                let tile_x = (x / TILE_SIZE) as usize;
+                let tile_y = (y / TILE_SIZE) as usize;
+                let x_in_tile = (x % TILE_SIZE) as usize;
+                let y_in_tile = (y % TILE_SIZE) as usize;
+                let tile_pitch = TILE_AREA * self.pixel_size_in_bytes;
+                tile_x * tile_pitch
+                    + tile_y * mip_level_layout.row_pitch
+                    + (x_in_tile + y_in_tile * TILE_SIZE as usize) * self.pixel_size_in_bytes
+            }
+        };
+        array_layer as usize * self.array_pitch
+            + mip_level_layout.offset
+            + z as usize * mip_level_layout.depth_pitch
+            + offset_in_slice
+    }
+}
+
+fn get_mip_level_extents(extents: api::VkExtent3D, mip_level: u32) -> api::VkExtent3D {
+    api::VkExtent3D {
+        width: (extents.width >> mip_level).max(1),
+        height: (extents.height >> mip_level).max(1),
+        depth: (extents.depth >> mip_level).max(1),
+    }
+}
+
 impl ImageProperties {
+    pub fn tiling(&self) -> Tiling {
+        match *self {
+            Self {
+                supported_tilings: SupportedTilings::LinearOnly,
+                ..
+            } => Tiling::Linear,
+            Self {
+                swapchain_present_tiling: Some(tiling),
+                ..
+            } => tiling,
+            _ => Tiling::Tiled,
+        }
+    }
     pub fn computed_properties(&self) -> ImageComputedProperties {
         match *self {
             Self {
-                supported_tilings: SupportedTilings::Any,
-                format: api::VK_FORMAT_R8G8B8A8_UNORM,
+                supported_tilings: _,
+                image_type: _,
+                flags: _,
+                format,
                 extents,
                 array_layers,
-                mip_levels: 1,
+                mip_levels,
                 multisample_count: ImageMultisampleCount::Count1,
                 swapchain_present_tiling: _,
-            } => {
-                let pixel_size_in_bytes = 4;
+            } if (mip_levels as usize) <= MAX_MIP_LEVELS => {
+                let pixel_size_in_bytes = format::get_format_info(format)
+                    .unwrap_or_else(|| {
+                        unimplemented!("ImageProperties::computed_properties({:?})", self)
+                    })
+                    .texel_size_in_bytes;
+                let tiling = self.tiling();
+                let mut mip_level_layouts = [MipLevelLayout {
+                    extents: api::VkExtent3D {
+                        width: 0,
+                        height: 0,
+                        depth: 0,
+                    },
+                    offset: 0,
+                    row_pitch: 0,
+                    depth_pitch: 0,
+                    size: 0,
+                }; MAX_MIP_LEVELS];
+                let mut array_pitch = 0usize;
+                for (mip_level, mip_level_layout) in mip_level_layouts
+                    .iter_mut()
+                    .enumerate()
+                    .take(mip_levels as usize)
+                {
+                    let extents = get_mip_level_extents(extents, mip_level as u32);
+                    let (row_pitch, rows) = match tiling {
+                        Tiling::Linear => (
+                            pixel_size_in_bytes
+                                .checked_mul(extents.width as usize)
+                                .unwrap(),
+                            extents.height as usize,
+                        ),
+                        Tiling::Tiled => {
+                            let tiles_x = ((extents.width + TILE_SIZE - 1) / TILE_SIZE) as usize;
+                            let tiles_y = ((extents.height + TILE_SIZE - 1) / TILE_SIZE) as usize;
+                            (
+                                pixel_size_in_bytes
+                                    .checked_mul((TILE_SIZE * TILE_SIZE) as usize)
+                                    .unwrap()
+                                    .checked_mul(tiles_x)
+                                    .unwrap(),
+                                tiles_y,
+                            )
+                        }
+                    };
+                    let depth_pitch = row_pitch.checked_mul(rows).unwrap();
+                    let size = depth_pitch.checked_mul(extents.depth as usize).unwrap();
+                    *mip_level_layout = MipLevelLayout {
+                        extents,
+                        offset: array_pitch,
+                        row_pitch,
+                        depth_pitch,
+                        size,
+                    };
+                    array_pitch = array_pitch.checked_add(size).unwrap();
+                }
                 ImageComputedProperties {
                     pixel_size_in_bytes,
+                    tiling,
+                    mip_level_layouts,
+                    array_pitch,
                     memory_layout: DeviceMemoryLayout::calculate(
-                        pixel_size_in_bytes
-                            .checked_mul(extents.width as usize)
-                            .unwrap()
-                            .checked_mul(extents.height as usize)
-                            .unwrap()
-                            .checked_mul(extents.depth as usize)
-                            .unwrap()
-                            .checked_mul(array_layers as usize)
-                            .unwrap(),
+                        array_pitch.checked_mul(array_layers as usize).unwrap(),
                         IMAGE_ALIGNMENT,
                     ),
                 }
@@ -90,3 +220,169 @@ pub struct Image {
     pub properties: ImageProperties,
     pub memory: Option<ImageMemory>,
 }
+
+impl Image {
+    /// get a pointer to the first byte of a texel; the image must be bound to memory
+    pub unsafe fn get_texel_pointer(
+        &self,
+        computed_properties: &ImageComputedProperties,
+        x: u32,
+        y: u32,
+        z: u32,
+        array_layer: u32,
+        mip_level: u32,
+    ) -> *mut u8 {
+        let memory = self.memory.as_ref().expect("image not bound to memory");
+        memory.device_memory.get().as_ptr().add(
+            memory.offset + computed_properties.get_texel_offset(x, y, z, array_layer, mip_level),
+        )
+    }
+}
+
+/// what a view's array layers are in its image
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ViewLayers {
+    ArrayLayers,
+    /// the depth slices of the view's only mip level, for 2D and 2D array views of 3D images
+    /// (`VK_IMAGE_CREATE_2D_ARRAY_COMPATIBLE_BIT` and `VK_EXT_image_2d_view_of_3d`)
+    DepthSlices,
+}
+
+/// what reads of texels outside an image view or of mip levels it doesn't have return, set by the
+/// device's enabled features. reads are bounds checked either way, so they never touch memory
+/// outside the view
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ImageRobustness {
+    /// the values are undefined; zero is returned
+    Disabled,
+    /// `robustImageAccess` from `VK_EXT_image_robustness`: zero is returned
+    RobustImageAccess,
+    /// `robustImageAccess2` from `VK_EXT_robustness2`: zero is returned, except alpha is one for
+    /// formats without alpha, and then the view's component mapping is applied
+    RobustImageAccess2,
+}
+
+#[derive(Debug)]
+pub struct ImageView {
+    pub image: SharedHandle<api::VkImage>,
+    pub view_type: api::VkImageViewType,
+    pub format: api::VkFormat,
+    pub component_mapping: api::VkComponentMapping,
+    /// the level and layer counts are never `VK_REMAINING_*`. when `layers` is `DepthSlices`, the
+    /// array layer range is the range of depth slices
+    pub subresource_range: api::VkImageSubresourceRange,
+    pub layers: ViewLayers,
+    pub image_computed_properties: ImageComputedProperties,
+    /// `ImageRobustness::Disabled` unless set after creating the view
+    pub robustness: ImageRobustness,
+}
+
+impl ImageView {
+    pub fn new(
+        image: SharedHandle<api::VkImage>,
+        create_info: &api::VkImageViewCreateInfo,
+    ) -> Self {
+        assert_eq!(create_info.flags, 0);
+        let properties = &image.properties;
+        let is_2d_view = match create_info.viewType {
+            api::VK_IMAGE_VIEW_TYPE_2D | api::VK_IMAGE_VIEW_TYPE_2D_ARRAY => true,
+            _ => false,
+        };
+        let layers = if properties.image_type == api::VK_IMAGE_TYPE_3D && is_2d_view {
+            assert!(
+                properties.flags
+                    & (api::VK_IMAGE_CREATE_2D_ARRAY_COMPATIBLE_BIT
+                        | api::VK_IMAGE_CREATE_2D_VIEW_COMPATIBLE_BIT_EXT)
+                    != 0,
+                "2D view of a 3D image that isn't 2D view compatible"
+            );
+            ViewLayers::DepthSlices
+        } else {
+            ViewLayers::ArrayLayers
+        };
+        let mut subresource_range = create_info.subresourceRange;
+        if subresource_range.levelCount == api::VK_REMAINING_MIP_LEVELS as u32 {
+            subresource_range.levelCount = properties.mip_levels - subresource_range.baseMipLevel;
+        }
+        assert!(
+            subresource_range.baseMipLevel + subresource_range.levelCount <= properties.mip_levels
+        );
+        let layer_count = match layers {
+            ViewLayers::ArrayLayers => properties.array_layers,
+            ViewLayers::DepthSlices => {
+                assert_eq!(subresource_range.levelCount, 1);
+                get_mip_level_extents(properties.extents, subresource_range.baseMipLevel).depth
+            }
+        };
+        if subresource_range.layerCount == api::VK_REMAINING_ARRAY_LAYERS as u32 {
+            subresource_range.layerCount = layer_count - subresource_range.baseArrayLayer;
+        }
+        assert!(subresource_range.baseArrayLayer + subresource_range.layerCount <= layer_count);
+        let format_info = format::get_format_info(create_info.format)
+            .unwrap_or_else(|| unimplemented!("image view format: {}", create_info.format));
+        assert!(
+            format::is_view_format_compatible(
+                properties.format,
+                create_info.format,
+                properties.flags & api::VK_IMAGE_CREATE_MUTABLE_FORMAT_BIT != 0,
+            ),
+            "image view format {} isn't compatible with image format {}",
+            create_info.format,
+            properties.format
+        );
+        let valid_aspects = match format_info.kind {
+            FormatKind::Color => api::VK_IMAGE_ASPECT_COLOR_BIT,
+            FormatKind::Depth => api::VK_IMAGE_ASPECT_DEPTH_BIT,
+            FormatKind::DepthStencil => {
+                api::VK_IMAGE_ASPECT_DEPTH_BIT | api::VK_IMAGE_ASPECT_STENCIL_BIT
+            }
+        };
+        assert_eq!(subresource_range.aspectMask & !valid_aspects, 0);
+        ImageView {
+            image_computed_properties: properties.computed_properties(),
+            view_type: create_info.viewType,
+            format: create_info.format,
+            component_mapping: create_info.components,
+            subresource_range,
+            layers,
+            image,
+            robustness: ImageRobustness::Disabled,
+        }
+    }
+    /// the image's coordinates, array layer and mip level for texel `texel_coordinates` of layer
+    /// `layer` and mip level `level` of the view
+    pub fn get_image_location(
+        &self,
+        texel_coordinates: [u32; 3],
+        layer: u32,
+        level: u32,
+    ) -> ([u32; 3], u32, u32) {
+        debug_assert!(layer < self.subresource_range.layerCount);
+        debug_assert!(level < self.subresource_range.levelCount);
+        let [x, y, z] = texel_coordinates;
+        let layer = self.subresource_range.baseArrayLayer + layer;
+        let level = self.subresource_range.baseMipLevel + level;
+        match self.layers {
+            ViewLayers::ArrayLayers => ([x, y, z], layer, level),
+            ViewLayers::DepthSlices => ([x, y, layer], 0, level),
+        }
+    }
+    /// get a pointer to the first byte of a texel of the view; the image must be bound to memory
+    pub unsafe fn get_texel_pointer(
+        &self,
+        texel_coordinates: [u32; 3],
+        layer: u32,
+        level: u32,
+    ) -> *mut u8 {
+        let ([x, y, z], array_layer, mip_level) =
+            self.get_image_location(texel_coordinates, layer, level);
+        self.image.get_texel_pointer(
+            &self.image_computed_properties,
+            x,
+            y,
+            z,
+            array_layer,
+            mip_level,
+        )
+    }
+}
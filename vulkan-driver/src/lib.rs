@@ -7,23 +7,52 @@ extern crate enum_map;
 extern crate errno;
 #[cfg(unix)]
 extern crate libc;
+extern crate shader_compiler;
 extern crate shader_compiler_backend;
+extern crate shader_compiler_backend_llvm_7;
 extern crate sys_info;
 extern crate uuid;
-#[cfg(unix)]
+#[cfg(all(unix, feature = "wsi-xcb"))]
 extern crate xcb;
 mod api;
+#[macro_use]
+mod object_pool;
+#[macro_use]
 mod api_impl;
 mod buffer;
+mod command_buffer;
+mod command_stream;
+mod compile_thread_pool;
+mod coverage;
+mod debug_utils;
+mod descriptor_set;
 mod device_memory;
+mod driver_configuration;
+mod format;
 mod handle;
 mod image;
+mod mipmap;
+pub mod native;
+mod pipeline;
+mod pipeline_report;
+#[cfg(all(unix, feature = "wsi-xcb"))]
+mod present_conversion;
+mod query;
+mod render_pass;
 mod sampler;
+mod scratch_arena;
 mod shader_module;
-#[cfg(unix)]
+#[cfg(all(unix, feature = "wsi-xcb"))]
 mod shm;
+mod stencil;
+mod submit_graph;
 mod swapchain;
-#[cfg(unix)]
+mod trace;
+mod validation;
+mod vertex_format;
+mod viewport;
+mod watchdog;
+#[cfg(all(unix, feature = "wsi-xcb"))]
 mod xcb_swapchain;
 use std::ffi::CStr;
 use std::os::raw::c_char;
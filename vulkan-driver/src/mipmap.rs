@@ -0,0 +1,290 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! `VK_KAZAN_generate_mipmaps`: generating a whole mip chain in one command
+//!
+//! layered APIs implemented on top of kazan generate mip chains a lot, and doing that with a
+//! `vkCmdBlitImage` and a barrier per level per texture is most of their command overhead.
+//! `vkCmdGenerateMipmapsKAZAN` instead fills every level after the base level of a subresource
+//! range in a single pass: each level is downsampled from the level before it, which has already
+//! been written, using the same sampling code as shaders, so the result matches a chain of
+//! linear blits, including the conversion to linear for sRGB formats.
+//!
+//! mip chains can be generated for single-sampled color images whose format supports both
+//! `VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT` and `VK_FORMAT_FEATURE_TRANSFER_DST_BIT`
+//! for the image's tiling, with either filter. other calls are rejected when they're recorded.
+
+use api;
+use api_impl::PhysicalDevice;
+use format;
+use handle::SharedHandle;
+use image::{ImageMultisampleCount, ImageView, SupportedTilings};
+use sampler::Sampler;
+use std::ptr;
+use std::slice;
+
+fn get_view_type(image_type: api::VkImageType) -> api::VkImageViewType {
+    match image_type {
+        api::VK_IMAGE_TYPE_1D => api::VK_IMAGE_VIEW_TYPE_1D_ARRAY,
+        api::VK_IMAGE_TYPE_2D => api::VK_IMAGE_VIEW_TYPE_2D_ARRAY,
+        api::VK_IMAGE_TYPE_3D => api::VK_IMAGE_VIEW_TYPE_3D,
+        _ => unreachable!("invalid image type: {}", image_type),
+    }
+}
+
+/// the format features an image's format needs for its mip chain to be generated
+const REQUIRED_FORMAT_FEATURES: api::VkFormatFeatureFlags =
+    api::VK_FORMAT_FEATURE_SAMPLED_IMAGE_FILTER_LINEAR_BIT
+        | api::VK_FORMAT_FEATURE_TRANSFER_DST_BIT;
+
+/// check that the mip chain of `subresource_range` of `image` can be generated. returns why it
+/// can't otherwise
+pub fn check_generate_mipmaps(
+    image: SharedHandle<api::VkImage>,
+    subresource_range: &api::VkImageSubresourceRange,
+    filter: api::VkFilter,
+) -> Result<(), String> {
+    let properties = &image.properties;
+    let format_properties = PhysicalDevice::get_format_properties(properties.format);
+    let format_features = match properties.supported_tilings {
+        SupportedTilings::Any => format_properties.optimalTilingFeatures,
+        SupportedTilings::LinearOnly => format_properties.linearTilingFeatures,
+    };
+    if format_features & REQUIRED_FORMAT_FEATURES != REQUIRED_FORMAT_FEATURES {
+        return Err(format!(
+            "format {} doesn't support generating mipmaps",
+            properties.format
+        ));
+    }
+    if subresource_range.aspectMask != api::VK_IMAGE_ASPECT_COLOR_BIT {
+        return Err(format!(
+            "aspect mask {:#X} isn't VK_IMAGE_ASPECT_COLOR_BIT",
+            subresource_range.aspectMask
+        ));
+    }
+    match properties.multisample_count {
+        ImageMultisampleCount::Count1 => {}
+        ImageMultisampleCount::Count4 => {
+            return Err("multisampled images don't have mipmaps".into());
+        }
+    }
+    match filter {
+        api::VK_FILTER_NEAREST | api::VK_FILTER_LINEAR => Ok(()),
+        _ => Err(format!("filter {} isn't supported", filter)),
+    }
+}
+
+/// fill mip levels `baseMipLevel + 1` through the last level of `subresource_range` of `image`
+/// from `baseMipLevel`, sampling each level from the level before it with `filter`
+pub unsafe fn generate_mipmaps(
+    image: SharedHandle<api::VkImage>,
+    subresource_range: api::VkImageSubresourceRange,
+    filter: api::VkFilter,
+) {
+    let view = ImageView::new(
+        image,
+        &api::VkImageViewCreateInfo {
+            sType: api::VK_STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
+            pNext: ptr::null(),
+            flags: 0,
+            image: image.get_handle(),
+            viewType: get_view_type(image.properties.image_type),
+            format: image.properties.format,
+            components: api::VkComponentMapping {
+                r: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+                g: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+                b: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+                a: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+            },
+            subresourceRange: subresource_range,
+        },
+    );
+    // sampling the previous level at the center of each new texel with a linear filter averages
+    // the texels the new texel covers
+    let sampler = Sampler {
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_mode: api::VK_SAMPLER_MIPMAP_MODE_NEAREST,
+        address_modes: [api::VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE; 3],
+        mip_lod_bias: 0.0,
+        anisotropy: None,
+        compare_op: None,
+        min_lod: 0.0,
+        max_lod: 0.0,
+        border_color: api::VK_BORDER_COLOR_FLOAT_TRANSPARENT_BLACK,
+        unnormalized_coordinates: false,
+        sampler_ycbcr_conversion: None,
+    };
+    let is_arrayed = view.is_arrayed();
+    let pixel_size = view.image_computed_properties.pixel_size_in_bytes;
+    for level in 1..view.subresource_range.levelCount {
+        let extents = view.image_computed_properties.mip_level_layouts
+            [(view.subresource_range.baseMipLevel + level) as usize]
+            .extents;
+        for layer in 0..view.subresource_range.layerCount {
+            for z in 0..extents.depth {
+                for y in 0..extents.height {
+                    for x in 0..extents.width {
+                        let mut coordinates = [
+                            (x as f32 + 0.5) / extents.width as f32,
+                            (y as f32 + 0.5) / extents.height as f32,
+                            (z as f32 + 0.5) / extents.depth as f32,
+                            0.0,
+                        ];
+                        if is_arrayed {
+                            coordinates[view.get_dimension_count()] = layer as f32;
+                        }
+                        let value = sampler.sample_level(&view, coordinates, level - 1, filter);
+                        let pointer = view.get_texel_pointer([x, y, z], layer, level);
+                        format::encode_texel(
+                            view.format,
+                            slice::from_raw_parts_mut(pointer, pixel_size),
+                            value,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device_memory::DeviceMemory;
+    use handle::OwnedHandle;
+    use image::{Image, ImageMemory, ImageProperties};
+
+    #[test]
+    fn test_generate_mipmaps() {
+        let properties = ImageProperties {
+            supported_tilings: SupportedTilings::Any,
+            image_type: api::VK_IMAGE_TYPE_2D,
+            flags: 0,
+            format: api::VK_FORMAT_R8G8B8A8_UNORM,
+            extents: api::VkExtent3D {
+                width: 4,
+                height: 4,
+                depth: 1,
+            },
+            array_layers: 1,
+            mip_levels: 3,
+            multisample_count: ImageMultisampleCount::Count1,
+            swapchain_present_tiling: None,
+        };
+        let computed_properties = properties.computed_properties();
+        let device_memory = OwnedHandle::<api::VkDeviceMemory>::new(
+            DeviceMemory::allocate_from_default_heap(computed_properties.memory_layout).unwrap(),
+        );
+        let image = OwnedHandle::<api::VkImage>::new(Image {
+            properties,
+            memory: Some(ImageMemory {
+                device_memory: unsafe { SharedHandle::from(device_memory.get_handle()) }.unwrap(),
+                offset: 0,
+            }),
+        });
+        let texel = |x: u32, y: u32, level: u32| unsafe {
+            slice::from_raw_parts_mut(
+                image.get_texel_pointer(&computed_properties, x, y, 0, 0, level),
+                4,
+            )
+        };
+        for y in 0..4 {
+            for x in 0..4 {
+                texel(x, y, 0).copy_from_slice(&[(8 * x + 32 * y) as u8, 0, 255, 255]);
+            }
+        }
+        let subresource_range = api::VkImageSubresourceRange {
+            aspectMask: api::VK_IMAGE_ASPECT_COLOR_BIT,
+            baseMipLevel: 0,
+            levelCount: api::VK_REMAINING_MIP_LEVELS as u32,
+            baseArrayLayer: 0,
+            layerCount: 1,
+        };
+        unsafe {
+            let image = SharedHandle::from(image.get_handle()).unwrap();
+            check_generate_mipmaps(image, &subresource_range, api::VK_FILTER_LINEAR).unwrap();
+            generate_mipmaps(image, subresource_range, api::VK_FILTER_LINEAR);
+        }
+        // each texel is the average of the 2x2 texels it covers in the level before it
+        for y in 0..2 {
+            for x in 0..2 {
+                assert_eq!(texel(x, y, 1), [(16 * x + 64 * y + 20) as u8, 0, 255, 255]);
+            }
+        }
+        assert_eq!(texel(0, 0, 2), [60, 0, 255, 255]);
+    }
+
+    #[test]
+    fn test_check_generate_mipmaps() {
+        let subresource_range = api::VkImageSubresourceRange {
+            aspectMask: api::VK_IMAGE_ASPECT_COLOR_BIT,
+            baseMipLevel: 0,
+            levelCount: api::VK_REMAINING_MIP_LEVELS as u32,
+            baseArrayLayer: 0,
+            layerCount: 1,
+        };
+        for &(format, multisample_count, filter, supported) in &[
+            (
+                api::VK_FORMAT_B8G8R8A8_SRGB,
+                ImageMultisampleCount::Count1,
+                api::VK_FILTER_NEAREST,
+                true,
+            ),
+            // can't be sampled with a linear filter
+            (
+                api::VK_FORMAT_D32_SFLOAT,
+                ImageMultisampleCount::Count1,
+                api::VK_FILTER_LINEAR,
+                false,
+            ),
+            // not supported by kazan at all
+            (
+                api::VK_FORMAT_R16G16B16A16_SFLOAT,
+                ImageMultisampleCount::Count1,
+                api::VK_FILTER_LINEAR,
+                false,
+            ),
+            (
+                api::VK_FORMAT_R8G8B8A8_UNORM,
+                ImageMultisampleCount::Count4,
+                api::VK_FILTER_LINEAR,
+                false,
+            ),
+            (
+                api::VK_FORMAT_R8G8B8A8_UNORM,
+                ImageMultisampleCount::Count1,
+                api::VK_FILTER_CUBIC_IMG,
+                false,
+            ),
+        ] {
+            let image = OwnedHandle::<api::VkImage>::new(Image {
+                properties: ImageProperties {
+                    supported_tilings: SupportedTilings::Any,
+                    image_type: api::VK_IMAGE_TYPE_2D,
+                    flags: 0,
+                    format,
+                    extents: api::VkExtent3D {
+                        width: 4,
+                        height: 4,
+                        depth: 1,
+                    },
+                    array_layers: 1,
+                    mip_levels: 3,
+                    multisample_count,
+                    swapchain_present_tiling: None,
+                },
+                memory: None,
+            });
+            let image = unsafe { SharedHandle::from(image.get_handle()) }.unwrap();
+            assert_eq!(
+                check_generate_mipmaps(image, &subresource_range, filter).is_ok(),
+                supported,
+                "format = {}, multisample_count = {:?}, filter = {}",
+                format,
+                multisample_count,
+                filter
+            );
+        }
+    }
+}
@@ -0,0 +1,1084 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! safe Rust API for embedding kazan directly, without going through the Vulkan loader
+//!
+//! the objects here call the driver's entry points directly and own the handles they create,
+//! destroying them when dropped. objects keep the `Device` they were created from alive.
+//!
+//! commands run before `CommandBuffer::submit` returns, so buffers and images can be read
+//! as soon as the submission finishes.
+
+use api;
+use api_impl;
+use device_memory::DeviceMemoryType;
+use handle::{Handle, SharedHandle};
+use shader_compiler::interface::{trim_interface, DescriptorBinding};
+use shader_compiler::spirv::{self, ExecutionModel};
+use std::error;
+use std::ffi::CString;
+use std::fmt;
+use std::mem;
+use std::ptr::{self, null, null_mut};
+use std::sync::{Arc, Mutex};
+
+/// an error from the native API
+#[derive(Clone, Debug)]
+pub enum Error {
+    /// host memory allocation failed
+    OutOfHostMemory,
+    /// device memory allocation failed
+    OutOfDeviceMemory,
+    /// the device couldn't be initialized
+    InitializationFailed,
+    /// a previous submission failed, so the device can't run any more work
+    DeviceLost,
+    /// kazan can't run the command yet
+    Unimplemented { command_name: &'static str },
+    /// a `VkResult` error code without a more specific variant
+    Vulkan(i32),
+    /// the shader isn't valid SPIR-V or doesn't have the requested entry point
+    InvalidShader(spirv::Error),
+    /// an access of `size` bytes at `offset` doesn't fit in an object of `object_size` bytes
+    OutOfBounds {
+        offset: u64,
+        size: u64,
+        object_size: u64,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::OutOfHostMemory => write!(f, "out of host memory"),
+            Error::OutOfDeviceMemory => write!(f, "out of device memory"),
+            Error::InitializationFailed => write!(f, "device initialization failed"),
+            Error::DeviceLost => write!(f, "device lost"),
+            Error::Unimplemented { command_name } => {
+                write!(f, "{} isn't implemented", command_name)
+            }
+            Error::Vulkan(result) => write!(f, "Vulkan error: {}", result),
+            Error::InvalidShader(error) => write!(f, "invalid shader: {}", error),
+            Error::OutOfBounds {
+                offset,
+                size,
+                object_size,
+            } => write!(
+                f,
+                "access of {} bytes at offset {} is out of bounds of object with size {}",
+                size, offset, object_size
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<spirv::Error> for Error {
+    fn from(v: spirv::Error) -> Self {
+        Error::InvalidShader(v)
+    }
+}
+
+fn check_result(result: api::VkResult) -> Result<(), Error> {
+    match result {
+        api::VK_SUCCESS => Ok(()),
+        api::VK_ERROR_OUT_OF_HOST_MEMORY => Err(Error::OutOfHostMemory),
+        api::VK_ERROR_OUT_OF_DEVICE_MEMORY => Err(Error::OutOfDeviceMemory),
+        api::VK_ERROR_INITIALIZATION_FAILED => Err(Error::InitializationFailed),
+        api::VK_ERROR_DEVICE_LOST => Err(Error::DeviceLost),
+        result => Err(Error::Vulkan(result as i32)),
+    }
+}
+
+fn check_bounds(offset: u64, size: usize, object_size: u64) -> Result<(), Error> {
+    let size = size as u64;
+    match offset.checked_add(size) {
+        Some(end) if end <= object_size => Ok(()),
+        _ => Err(Error::OutOfBounds {
+            offset,
+            size,
+            object_size,
+        }),
+    }
+}
+
+/// the driver's only device, along with the instance it was created from and its queue
+pub struct Device {
+    instance: api::VkInstance,
+    device: api::VkDevice,
+    queue: Mutex<api::VkQueue>,
+}
+
+// the driver's device can be used from any thread; the queue is externally synchronized
+unsafe impl Send for Device {}
+unsafe impl Sync for Device {}
+
+impl Device {
+    pub fn new() -> Result<Arc<Device>, Error> {
+        unsafe {
+            let application_name = CString::new("kazan").unwrap();
+            let application_info = api::VkApplicationInfo {
+                sType: api::VK_STRUCTURE_TYPE_APPLICATION_INFO,
+                pNext: null(),
+                pApplicationName: application_name.as_ptr(),
+                applicationVersion: 0,
+                pEngineName: application_name.as_ptr(),
+                engineVersion: 0,
+                apiVersion: api_impl::make_api_version(1, 1, 0),
+            };
+            let mut instance = Handle::null();
+            check_result(api_impl::vkCreateInstance(
+                &api::VkInstanceCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_INSTANCE_CREATE_INFO,
+                    pNext: null(),
+                    flags: 0,
+                    pApplicationInfo: &application_info,
+                    enabledLayerCount: 0,
+                    ppEnabledLayerNames: null(),
+                    enabledExtensionCount: 0,
+                    ppEnabledExtensionNames: null(),
+                },
+                null(),
+                &mut instance,
+            ))?;
+            let mut physical_device = Handle::null();
+            let mut physical_device_count = 1;
+            if let Err(error) = check_result(api_impl::vkEnumeratePhysicalDevices(
+                instance,
+                &mut physical_device_count,
+                &mut physical_device,
+            )) {
+                api_impl::vkDestroyInstance(instance, null());
+                return Err(error);
+            }
+            let queue_priority = 1.0f32;
+            let queue_create_info = api::VkDeviceQueueCreateInfo {
+                sType: api::VK_STRUCTURE_TYPE_DEVICE_QUEUE_CREATE_INFO,
+                pNext: null(),
+                flags: 0,
+                queueFamilyIndex: 0,
+                queueCount: 1,
+                pQueuePriorities: &queue_priority,
+            };
+            let mut device = Handle::null();
+            if let Err(error) = check_result(api_impl::vkCreateDevice(
+                physical_device,
+                &api::VkDeviceCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_DEVICE_CREATE_INFO,
+                    pNext: null(),
+                    flags: 0,
+                    queueCreateInfoCount: 1,
+                    pQueueCreateInfos: &queue_create_info,
+                    enabledLayerCount: 0,
+                    ppEnabledLayerNames: null(),
+                    enabledExtensionCount: 0,
+                    ppEnabledExtensionNames: null(),
+                    pEnabledFeatures: null(),
+                },
+                null(),
+                &mut device,
+            )) {
+                api_impl::vkDestroyInstance(instance, null());
+                return Err(error);
+            }
+            let mut queue = Handle::null();
+            api_impl::vkGetDeviceQueue(device, 0, 0, &mut queue);
+            Ok(Arc::new(Device {
+                instance,
+                device,
+                queue: Mutex::new(queue),
+            }))
+        }
+    }
+    /// allocates host-visible memory satisfying `requirements`
+    unsafe fn allocate_memory(
+        &self,
+        requirements: &api::VkMemoryRequirements,
+    ) -> Result<api::VkDeviceMemory, Error> {
+        let memory_type = DeviceMemoryType::Main;
+        assert_ne!(requirements.memoryTypeBits & memory_type.to_bits(), 0);
+        let mut memory = Handle::null();
+        check_result(api_impl::vkAllocateMemory(
+            self.device,
+            &api::VkMemoryAllocateInfo {
+                sType: api::VK_STRUCTURE_TYPE_MEMORY_ALLOCATE_INFO,
+                pNext: null(),
+                allocationSize: requirements.size,
+                memoryTypeIndex: memory_type as u32,
+            },
+            null(),
+            &mut memory,
+        ))?;
+        Ok(memory)
+    }
+    /// maps all of `memory`; kazan's memory is always host-coherent
+    unsafe fn map_memory(&self, memory: api::VkDeviceMemory) -> Result<*mut u8, Error> {
+        let mut data = null_mut();
+        check_result(api_impl::vkMapMemory(
+            self.device,
+            memory,
+            0,
+            api::VK_WHOLE_SIZE as api::VkDeviceSize,
+            0,
+            &mut data,
+        ))?;
+        Ok(data as *mut u8)
+    }
+}
+
+impl Drop for Device {
+    fn drop(&mut self) {
+        unsafe {
+            api_impl::vkDestroyDevice(self.device, null());
+            api_impl::vkDestroyInstance(self.instance, null());
+        }
+    }
+}
+
+impl fmt::Debug for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Device")
+            .field("device", &self.device)
+            .finish()
+    }
+}
+
+/// a buffer with its own memory, which stays mapped for the buffer's lifetime
+pub struct Buffer {
+    device: Arc<Device>,
+    buffer: api::VkBuffer,
+    memory: api::VkDeviceMemory,
+    data: *mut u8,
+    size: u64,
+}
+
+unsafe impl Send for Buffer {}
+unsafe impl Sync for Buffer {}
+
+impl Buffer {
+    /// creates a zero-initialized buffer of `size` bytes usable as a storage, uniform, vertex
+    /// or index buffer.
+    /// panics if `size` is 0.
+    pub fn new(device: &Arc<Device>, size: u64) -> Result<Buffer, Error> {
+        assert_ne!(size, 0, "buffers can't be empty");
+        unsafe {
+            let mut buffer = Handle::null();
+            check_result(api_impl::vkCreateBuffer(
+                device.device,
+                &api::VkBufferCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_BUFFER_CREATE_INFO,
+                    pNext: null(),
+                    flags: 0,
+                    size,
+                    usage: api::VK_BUFFER_USAGE_TRANSFER_SRC_BIT
+                        | api::VK_BUFFER_USAGE_TRANSFER_DST_BIT
+                        | api::VK_BUFFER_USAGE_UNIFORM_BUFFER_BIT
+                        | api::VK_BUFFER_USAGE_STORAGE_BUFFER_BIT
+                        | api::VK_BUFFER_USAGE_INDEX_BUFFER_BIT
+                        | api::VK_BUFFER_USAGE_VERTEX_BUFFER_BIT,
+                    sharingMode: api::VK_SHARING_MODE_EXCLUSIVE,
+                    queueFamilyIndexCount: 0,
+                    pQueueFamilyIndices: null(),
+                },
+                null(),
+                &mut buffer,
+            ))?;
+            let mut retval = Buffer {
+                device: device.clone(),
+                buffer,
+                memory: Handle::null(),
+                data: null_mut(),
+                size,
+            };
+            let mut requirements = mem::zeroed();
+            api_impl::vkGetBufferMemoryRequirements(device.device, buffer, &mut requirements);
+            retval.memory = device.allocate_memory(&requirements)?;
+            check_result(api_impl::vkBindBufferMemory(
+                device.device,
+                buffer,
+                retval.memory,
+                0,
+            ))?;
+            retval.data = device.map_memory(retval.memory)?;
+            ptr::write_bytes(retval.data, 0, size as usize);
+            Ok(retval)
+        }
+    }
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+    /// copies `data` into the buffer starting at `offset`
+    pub fn write(&mut self, offset: u64, data: &[u8]) -> Result<(), Error> {
+        check_bounds(offset, data.len(), self.size)?;
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), self.data.offset(offset as isize), data.len());
+        }
+        Ok(())
+    }
+    /// copies the buffer's contents starting at `offset` into `data`
+    pub fn read(&self, offset: u64, data: &mut [u8]) -> Result<(), Error> {
+        check_bounds(offset, data.len(), self.size)?;
+        unsafe {
+            ptr::copy_nonoverlapping(
+                self.data.offset(offset as isize),
+                data.as_mut_ptr(),
+                data.len(),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe {
+            api_impl::vkDestroyBuffer(self.device.device, self.buffer, null());
+            api_impl::vkFreeMemory(self.device.device, self.memory, null());
+        }
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("buffer", &self.buffer)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+/// the image formats supported by `Image`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Format {
+    R8G8B8A8Unorm,
+    B8G8R8A8Unorm,
+    R32Sfloat,
+    R32G32B32A32Sfloat,
+}
+
+impl Format {
+    fn to_api(self) -> api::VkFormat {
+        match self {
+            Format::R8G8B8A8Unorm => api::VK_FORMAT_R8G8B8A8_UNORM,
+            Format::B8G8R8A8Unorm => api::VK_FORMAT_B8G8R8A8_UNORM,
+            Format::R32Sfloat => api::VK_FORMAT_R32_SFLOAT,
+            Format::R32G32B32A32Sfloat => api::VK_FORMAT_R32G32B32A32_SFLOAT,
+        }
+    }
+    pub fn texel_size(self) -> usize {
+        match self {
+            Format::R8G8B8A8Unorm | Format::B8G8R8A8Unorm | Format::R32Sfloat => 4,
+            Format::R32G32B32A32Sfloat => 16,
+        }
+    }
+}
+
+/// a linear-tiled 2D image with its own memory, which stays mapped for the image's lifetime
+pub struct Image {
+    device: Arc<Device>,
+    image: api::VkImage,
+    memory: api::VkDeviceMemory,
+    data: *mut u8,
+    format: Format,
+    width: u32,
+    height: u32,
+    row_pitch: u64,
+}
+
+unsafe impl Send for Image {}
+unsafe impl Sync for Image {}
+
+impl Image {
+    /// creates a zero-initialized `width` by `height` image.
+    /// panics if `width` or `height` is 0.
+    pub fn new(
+        device: &Arc<Device>,
+        format: Format,
+        width: u32,
+        height: u32,
+    ) -> Result<Image, Error> {
+        assert!(width != 0 && height != 0, "images can't be empty");
+        unsafe {
+            let mut image = Handle::null();
+            check_result(api_impl::vkCreateImage(
+                device.device,
+                &api::VkImageCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_IMAGE_CREATE_INFO,
+                    pNext: null(),
+                    flags: 0,
+                    imageType: api::VK_IMAGE_TYPE_2D,
+                    format: format.to_api(),
+                    extent: api::VkExtent3D {
+                        width,
+                        height,
+                        depth: 1,
+                    },
+                    mipLevels: 1,
+                    arrayLayers: 1,
+                    samples: api::VK_SAMPLE_COUNT_1_BIT,
+                    tiling: api::VK_IMAGE_TILING_LINEAR,
+                    usage: api::VK_IMAGE_USAGE_TRANSFER_SRC_BIT
+                        | api::VK_IMAGE_USAGE_TRANSFER_DST_BIT
+                        | api::VK_IMAGE_USAGE_SAMPLED_BIT
+                        | api::VK_IMAGE_USAGE_STORAGE_BIT
+                        | api::VK_IMAGE_USAGE_COLOR_ATTACHMENT_BIT,
+                    sharingMode: api::VK_SHARING_MODE_EXCLUSIVE,
+                    queueFamilyIndexCount: 0,
+                    pQueueFamilyIndices: null(),
+                    initialLayout: api::VK_IMAGE_LAYOUT_PREINITIALIZED,
+                },
+                null(),
+                &mut image,
+            ))?;
+            let mut retval = Image {
+                device: device.clone(),
+                image,
+                memory: Handle::null(),
+                data: null_mut(),
+                format,
+                width,
+                height,
+                row_pitch: 0,
+            };
+            let mut requirements = mem::zeroed();
+            api_impl::vkGetImageMemoryRequirements(device.device, image, &mut requirements);
+            retval.memory = device.allocate_memory(&requirements)?;
+            check_result(api_impl::vkBindImageMemory(
+                device.device,
+                image,
+                retval.memory,
+                0,
+            ))?;
+            let mut layout = mem::zeroed::<api::VkSubresourceLayout>();
+            api_impl::vkGetImageSubresourceLayout(
+                device.device,
+                image,
+                &api::VkImageSubresource {
+                    aspectMask: api::VK_IMAGE_ASPECT_COLOR_BIT,
+                    mipLevel: 0,
+                    arrayLayer: 0,
+                },
+                &mut layout,
+            );
+            retval.row_pitch = layout.rowPitch;
+            retval.data = device
+                .map_memory(retval.memory)?
+                .offset(layout.offset as isize);
+            ptr::write_bytes(retval.data, 0, layout.size as usize);
+            Ok(retval)
+        }
+    }
+    pub fn format(&self) -> Format {
+        self.format
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    fn row_size(&self) -> usize {
+        self.width as usize * self.format.texel_size()
+    }
+    /// copies tightly-packed rows of texels from `data` into the image, starting at row 0
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        let row_size = self.row_size();
+        check_bounds(0, data.len(), row_size as u64 * u64::from(self.height))?;
+        for (row, texels) in data.chunks(row_size).enumerate() {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    texels.as_ptr(),
+                    self.data.offset((row as u64 * self.row_pitch) as isize),
+                    texels.len(),
+                );
+            }
+        }
+        Ok(())
+    }
+    /// copies the image's texels, starting at row 0, into `data` as tightly-packed rows
+    pub fn read(&self, data: &mut [u8]) -> Result<(), Error> {
+        let row_size = self.row_size();
+        check_bounds(0, data.len(), row_size as u64 * u64::from(self.height))?;
+        for (row, texels) in data.chunks_mut(row_size).enumerate() {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    self.data.offset((row as u64 * self.row_pitch) as isize),
+                    texels.as_mut_ptr(),
+                    texels.len(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        unsafe {
+            api_impl::vkDestroyImage(self.device.device, self.image, null());
+            api_impl::vkFreeMemory(self.device.device, self.memory, null());
+        }
+    }
+}
+
+impl fmt::Debug for Image {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Image")
+            .field("image", &self.image)
+            .field("format", &self.format)
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+/// a SPIR-V module
+pub struct ShaderModule {
+    device: Arc<Device>,
+    shader_module: api::VkShaderModule,
+    code: Vec<u32>,
+}
+
+unsafe impl Send for ShaderModule {}
+unsafe impl Sync for ShaderModule {}
+
+impl ShaderModule {
+    pub fn new(device: &Arc<Device>, code: &[u32]) -> Result<ShaderModule, Error> {
+        if code.is_empty() {
+            return Err(Error::InvalidShader(spirv::Error::TooShort));
+        }
+        unsafe {
+            let mut shader_module = Handle::null();
+            check_result(api_impl::vkCreateShaderModule(
+                device.device,
+                &api::VkShaderModuleCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_SHADER_MODULE_CREATE_INFO,
+                    pNext: null(),
+                    flags: 0,
+                    codeSize: code.len() * mem::size_of::<u32>(),
+                    pCode: code.as_ptr(),
+                },
+                null(),
+                &mut shader_module,
+            ))?;
+            Ok(ShaderModule {
+                device: device.clone(),
+                shader_module,
+                code: code.to_vec(),
+            })
+        }
+    }
+}
+
+impl Drop for ShaderModule {
+    fn drop(&mut self) {
+        unsafe {
+            api_impl::vkDestroyShaderModule(self.device.device, self.shader_module, null());
+        }
+    }
+}
+
+impl fmt::Debug for ShaderModule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShaderModule")
+            .field("shader_module", &self.shader_module)
+            .finish()
+    }
+}
+
+/// a compute pipeline whose layout is taken from its shader: every descriptor binding the
+/// entry point uses is a storage buffer
+pub struct ComputePipeline {
+    device: Arc<Device>,
+    pipeline: api::VkPipeline,
+    pipeline_layout: api::VkPipelineLayout,
+    set_layouts: Vec<api::VkDescriptorSetLayout>,
+    descriptor_bindings: Vec<DescriptorBinding>,
+}
+
+unsafe impl Send for ComputePipeline {}
+unsafe impl Sync for ComputePipeline {}
+
+impl ComputePipeline {
+    /// compiles the `GLCompute` entry point named `entry_point_name` from `shader_module`.
+    /// the shader is checked before compiling, so invalid SPIR-V is reported as
+    /// `Error::InvalidShader` rather than a panic on the compile threads
+    pub fn new(
+        device: &Arc<Device>,
+        shader_module: &ShaderModule,
+        entry_point_name: &str,
+    ) -> Result<ComputePipeline, Error> {
+        let interface = trim_interface(
+            &shader_module.code,
+            entry_point_name,
+            ExecutionModel::GLCompute,
+        )?
+        .interface;
+        let entry_point_name = CString::new(entry_point_name).map_err(|_| {
+            Error::InvalidShader(spirv::Error::EntryPointNotFound {
+                name: entry_point_name.into(),
+            })
+        })?;
+        let mut retval = ComputePipeline {
+            device: device.clone(),
+            pipeline: Handle::null(),
+            pipeline_layout: Handle::null(),
+            set_layouts: Vec::new(),
+            descriptor_bindings: interface.descriptor_bindings,
+        };
+        unsafe {
+            let set_count = retval
+                .descriptor_bindings
+                .iter()
+                .map(|binding| binding.descriptor_set + 1)
+                .max()
+                .unwrap_or(0);
+            for descriptor_set in 0..set_count {
+                let bindings: Vec<_> = retval
+                    .descriptor_bindings
+                    .iter()
+                    .filter(|binding| binding.descriptor_set == descriptor_set)
+                    .map(|binding| api::VkDescriptorSetLayoutBinding {
+                        binding: binding.binding,
+                        descriptorType: api::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER,
+                        descriptorCount: 1,
+                        stageFlags: api::VK_SHADER_STAGE_COMPUTE_BIT,
+                        pImmutableSamplers: null(),
+                    })
+                    .collect();
+                let mut set_layout = Handle::null();
+                check_result(api_impl::vkCreateDescriptorSetLayout(
+                    device.device,
+                    &api::VkDescriptorSetLayoutCreateInfo {
+                        sType: api::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+                        pNext: null(),
+                        flags: 0,
+                        bindingCount: bindings.len() as u32,
+                        pBindings: bindings.as_ptr(),
+                    },
+                    null(),
+                    &mut set_layout,
+                ))?;
+                retval.set_layouts.push(set_layout);
+            }
+            check_result(api_impl::vkCreatePipelineLayout(
+                device.device,
+                &api::VkPipelineLayoutCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_PIPELINE_LAYOUT_CREATE_INFO,
+                    pNext: null(),
+                    flags: 0,
+                    setLayoutCount: retval.set_layouts.len() as u32,
+                    pSetLayouts: retval.set_layouts.as_ptr(),
+                    pushConstantRangeCount: 0,
+                    pPushConstantRanges: null(),
+                },
+                null(),
+                &mut retval.pipeline_layout,
+            ))?;
+            check_result(api_impl::vkCreateComputePipelines(
+                device.device,
+                Handle::null(),
+                1,
+                &api::VkComputePipelineCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_COMPUTE_PIPELINE_CREATE_INFO,
+                    pNext: null(),
+                    flags: 0,
+                    stage: api::VkPipelineShaderStageCreateInfo {
+                        sType: api::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+                        pNext: null(),
+                        flags: 0,
+                        stage: api::VK_SHADER_STAGE_COMPUTE_BIT,
+                        module: shader_module.shader_module,
+                        pName: entry_point_name.as_ptr(),
+                        pSpecializationInfo: null(),
+                    },
+                    layout: retval.pipeline_layout,
+                    basePipelineHandle: Handle::null(),
+                    basePipelineIndex: -1,
+                },
+                null(),
+                &mut retval.pipeline,
+            ))?;
+        }
+        Ok(retval)
+    }
+    /// the descriptor bindings the shader uses, sorted by set then binding.
+    /// `CommandBuffer::dispatch` takes a buffer for each of them, in this order
+    pub fn descriptor_bindings(&self) -> &[DescriptorBinding] {
+        &self.descriptor_bindings
+    }
+}
+
+impl Drop for ComputePipeline {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.pipeline.is_null() {
+                api_impl::vkDestroyPipeline(self.device.device, self.pipeline, null());
+            }
+            if !self.pipeline_layout.is_null() {
+                api_impl::vkDestroyPipelineLayout(self.device.device, self.pipeline_layout, null());
+            }
+            for &set_layout in &self.set_layouts {
+                api_impl::vkDestroyDescriptorSetLayout(self.device.device, set_layout, null());
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ComputePipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ComputePipeline")
+            .field("pipeline", &self.pipeline)
+            .field("descriptor_bindings", &self.descriptor_bindings)
+            .finish()
+    }
+}
+
+/// records commands, then runs them with `submit`.
+/// borrows everything it records until it's submitted or dropped
+pub struct CommandBuffer<'a> {
+    device: &'a Device,
+    command_pool: api::VkCommandPool,
+    command_buffer: api::VkCommandBuffer,
+    descriptor_pools: Vec<api::VkDescriptorPool>,
+}
+
+impl<'a> CommandBuffer<'a> {
+    pub fn new(device: &'a Device) -> Result<CommandBuffer<'a>, Error> {
+        unsafe {
+            let mut retval = CommandBuffer {
+                device,
+                command_pool: Handle::null(),
+                command_buffer: Handle::null(),
+                descriptor_pools: Vec::new(),
+            };
+            check_result(api_impl::vkCreateCommandPool(
+                device.device,
+                &api::VkCommandPoolCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_COMMAND_POOL_CREATE_INFO,
+                    pNext: null(),
+                    flags: api::VK_COMMAND_POOL_CREATE_TRANSIENT_BIT,
+                    queueFamilyIndex: 0,
+                },
+                null(),
+                &mut retval.command_pool,
+            ))?;
+            check_result(api_impl::vkAllocateCommandBuffers(
+                device.device,
+                &api::VkCommandBufferAllocateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_COMMAND_BUFFER_ALLOCATE_INFO,
+                    pNext: null(),
+                    commandPool: retval.command_pool,
+                    level: api::VK_COMMAND_BUFFER_LEVEL_PRIMARY,
+                    commandBufferCount: 1,
+                },
+                &mut retval.command_buffer,
+            ))?;
+            check_result(api_impl::vkBeginCommandBuffer(
+                retval.command_buffer,
+                &api::VkCommandBufferBeginInfo {
+                    sType: api::VK_STRUCTURE_TYPE_COMMAND_BUFFER_BEGIN_INFO,
+                    pNext: null(),
+                    flags: api::VK_COMMAND_BUFFER_USAGE_ONE_TIME_SUBMIT_BIT,
+                    pInheritanceInfo: null(),
+                },
+            ))?;
+            Ok(retval)
+        }
+    }
+    /// records a dispatch of `group_count` workgroups of `pipeline`.
+    /// `buffers` has a buffer for each of `pipeline.descriptor_bindings()`, in the same order.
+    /// panics if there are the wrong number of buffers. dispatches only run with the null driver
+    /// so far, so other dispatches with workgroups fail with `Error::Unimplemented`
+    pub fn dispatch(
+        &mut self,
+        pipeline: &'a ComputePipeline,
+        buffers: &[&'a Buffer],
+        group_count: [u32; 3],
+    ) -> Result<(), Error> {
+        assert_eq!(
+            buffers.len(),
+            pipeline.descriptor_bindings.len(),
+            "wrong number of buffers for pipeline"
+        );
+        unsafe {
+            let is_null_driver = SharedHandle::from(self.device.device)
+                .unwrap()
+                .is_null_driver();
+            if !is_null_driver && !group_count.contains(&0) {
+                return Err(Error::Unimplemented {
+                    command_name: "vkCmdDispatch",
+                });
+            }
+            let mut descriptor_sets = Vec::new();
+            if !pipeline.set_layouts.is_empty() {
+                let pool_size = api::VkDescriptorPoolSize {
+                    type_: api::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER,
+                    descriptorCount: buffers.len() as u32,
+                };
+                let mut descriptor_pool = Handle::null();
+                check_result(api_impl::vkCreateDescriptorPool(
+                    self.device.device,
+                    &api::VkDescriptorPoolCreateInfo {
+                        sType: api::VK_STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+                        pNext: null(),
+                        flags: 0,
+                        maxSets: pipeline.set_layouts.len() as u32,
+                        poolSizeCount: 1,
+                        pPoolSizes: &pool_size,
+                    },
+                    null(),
+                    &mut descriptor_pool,
+                ))?;
+                self.descriptor_pools.push(descriptor_pool);
+                descriptor_sets = vec![Handle::null(); pipeline.set_layouts.len()];
+                check_result(api_impl::vkAllocateDescriptorSets(
+                    self.device.device,
+                    &api::VkDescriptorSetAllocateInfo {
+                        sType: api::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_ALLOCATE_INFO,
+                        pNext: null(),
+                        descriptorPool: descriptor_pool,
+                        descriptorSetCount: descriptor_sets.len() as u32,
+                        pSetLayouts: pipeline.set_layouts.as_ptr(),
+                    },
+                    descriptor_sets.as_mut_ptr(),
+                ))?;
+                let buffer_infos: Vec<_> = buffers
+                    .iter()
+                    .map(|buffer| api::VkDescriptorBufferInfo {
+                        buffer: buffer.buffer,
+                        offset: 0,
+                        range: api::VK_WHOLE_SIZE as api::VkDeviceSize,
+                    })
+                    .collect();
+                let writes: Vec<_> = pipeline
+                    .descriptor_bindings
+                    .iter()
+                    .zip(&buffer_infos)
+                    .map(|(binding, buffer_info)| api::VkWriteDescriptorSet {
+                        sType: api::VK_STRUCTURE_TYPE_WRITE_DESCRIPTOR_SET,
+                        pNext: null(),
+                        dstSet: descriptor_sets[binding.descriptor_set as usize],
+                        dstBinding: binding.binding,
+                        dstArrayElement: 0,
+                        descriptorCount: 1,
+                        descriptorType: api::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER,
+                        pImageInfo: null(),
+                        pBufferInfo: buffer_info,
+                        pTexelBufferView: null(),
+                    })
+                    .collect();
+                api_impl::vkUpdateDescriptorSets(
+                    self.device.device,
+                    writes.len() as u32,
+                    writes.as_ptr(),
+                    0,
+                    null(),
+                );
+            }
+            api_impl::vkCmdBindPipeline(
+                self.command_buffer,
+                api::VK_PIPELINE_BIND_POINT_COMPUTE,
+                pipeline.pipeline,
+            );
+            if !descriptor_sets.is_empty() {
+                api_impl::vkCmdBindDescriptorSets(
+                    self.command_buffer,
+                    api::VK_PIPELINE_BIND_POINT_COMPUTE,
+                    pipeline.pipeline_layout,
+                    0,
+                    descriptor_sets.len() as u32,
+                    descriptor_sets.as_ptr(),
+                    0,
+                    null(),
+                );
+            }
+            let [x, y, z] = group_count;
+            api_impl::vkCmdDispatch(self.command_buffer, x, y, z);
+        }
+        Ok(())
+    }
+    /// runs the recorded commands, returning once they're done
+    pub fn submit(self) -> Result<(), Error> {
+        unsafe {
+            check_result(api_impl::vkEndCommandBuffer(self.command_buffer))?;
+            let queue = *self.device.queue.lock().unwrap();
+            check_result(api_impl::vkQueueSubmit(
+                queue,
+                1,
+                &api::VkSubmitInfo {
+                    sType: api::VK_STRUCTURE_TYPE_SUBMIT_INFO,
+                    pNext: null(),
+                    waitSemaphoreCount: 0,
+                    pWaitSemaphores: null(),
+                    pWaitDstStageMask: null(),
+                    commandBufferCount: 1,
+                    pCommandBuffers: &self.command_buffer,
+                    signalSemaphoreCount: 0,
+                    pSignalSemaphores: null(),
+                },
+                Handle::null(),
+            ))?;
+            check_result(api_impl::vkQueueWaitIdle(queue))
+        }
+    }
+}
+
+impl<'a> Drop for CommandBuffer<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            for &descriptor_pool in &self.descriptor_pools {
+                api_impl::vkDestroyDescriptorPool(self.device.device, descriptor_pool, null());
+            }
+            if !self.command_pool.is_null() {
+                api_impl::vkDestroyCommandPool(self.device.device, self.command_pool, null());
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Debug for CommandBuffer<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CommandBuffer")
+            .field("command_buffer", &self.command_buffer)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command_buffer::is_null_driver_enabled_in_environment;
+    use pipeline::tests::compute_module;
+
+    #[test]
+    fn test_round_trip() {
+        let device = Device::new().unwrap();
+        let mut buffer = Buffer::new(&device, 16).unwrap();
+        assert_eq!(buffer.size(), 16);
+        let mut data = [0xFF; 16];
+        buffer.read(0, &mut data).unwrap();
+        assert_eq!(data, [0; 16]);
+        buffer.write(4, &[1, 2, 3, 4]).unwrap();
+        let mut image = Image::new(&device, Format::R8G8B8A8Unorm, 2, 3).unwrap();
+        assert_eq!(
+            (image.format(), image.width(), image.height()),
+            (Format::R8G8B8A8Unorm, 2, 3)
+        );
+        let texels: Vec<u8> = (0..24).collect();
+        image.write(&texels).unwrap();
+        CommandBuffer::new(&device).unwrap().submit().unwrap();
+        let mut data = [0; 8];
+        buffer.read(2, &mut data).unwrap();
+        assert_eq!(data, [0, 0, 1, 2, 3, 4, 0, 0]);
+        let mut data = [0; 24];
+        image.read(&mut data).unwrap();
+        assert_eq!(&data[..], &texels[..]);
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let device = Device::new().unwrap();
+        let shader_module = ShaderModule::new(&device, &compute_module()).unwrap();
+        let pipeline = ComputePipeline::new(&device, &shader_module, "main").unwrap();
+        assert!(pipeline.descriptor_bindings().is_empty());
+        let mut command_buffer = CommandBuffer::new(&device).unwrap();
+        // dispatches without workgroups don't run anything
+        command_buffer.dispatch(&pipeline, &[], [1, 0, 1]).unwrap();
+        if is_null_driver_enabled_in_environment() {
+            command_buffer.dispatch(&pipeline, &[], [1, 1, 1]).unwrap();
+        } else {
+            // dispatches only run with the null driver, so they're rejected when they're
+            // recorded instead of losing the device when they're submitted
+            match command_buffer.dispatch(&pipeline, &[], [1, 1, 1]) {
+                Err(Error::Unimplemented {
+                    command_name: "vkCmdDispatch",
+                }) => {}
+                result => panic!("{:?}", result),
+            }
+        }
+        command_buffer.submit().unwrap();
+        CommandBuffer::new(&device).unwrap().submit().unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let device = Device::new().unwrap();
+        match ShaderModule::new(&device, &[]) {
+            Err(Error::InvalidShader(spirv::Error::TooShort)) => {}
+            result => panic!("{:?}", result),
+        }
+        let shader_module = ShaderModule::new(&device, &compute_module()).unwrap();
+        for &name in &["missing", "ma\0in"] {
+            match ComputePipeline::new(&device, &shader_module, name) {
+                Err(Error::InvalidShader(spirv::Error::EntryPointNotFound { name: ref v }))
+                    if v == name => {}
+                result => panic!("{:?}", result),
+            }
+        }
+        let mut buffer = Buffer::new(&device, 16).unwrap();
+        match buffer.write(12, &[0; 8]) {
+            Err(Error::OutOfBounds {
+                offset: 12,
+                size: 8,
+                object_size: 16,
+            }) => {}
+            result => panic!("{:?}", result),
+        }
+        // the end of the access overflows
+        match buffer.read(!0, &mut [0; 2]) {
+            Err(Error::OutOfBounds {
+                offset: 0xFFFF_FFFF_FFFF_FFFF,
+                size: 2,
+                object_size: 16,
+            }) => {}
+            result => panic!("{:?}", result),
+        }
+        let image = Image::new(&device, Format::R32G32B32A32Sfloat, 2, 2).unwrap();
+        match image.read(&mut [0; 65]) {
+            Err(Error::OutOfBounds {
+                offset: 0,
+                size: 65,
+                object_size: 64,
+            }) => {}
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_check_result() {
+        assert!(check_result(api::VK_SUCCESS).is_ok());
+        for &(result, message) in &[
+            (api::VK_ERROR_OUT_OF_HOST_MEMORY, "out of host memory"),
+            (api::VK_ERROR_OUT_OF_DEVICE_MEMORY, "out of device memory"),
+            (
+                api::VK_ERROR_INITIALIZATION_FAILED,
+                "device initialization failed",
+            ),
+            (api::VK_ERROR_DEVICE_LOST, "device lost"),
+            (api::VK_ERROR_FEATURE_NOT_PRESENT, "Vulkan error: -8"),
+        ] {
+            let error = check_result(result).unwrap_err();
+            assert_eq!(error.to_string(), message);
+        }
+        match check_result(api::VK_ERROR_FEATURE_NOT_PRESENT) {
+            Err(Error::Vulkan(-8)) => {}
+            result => panic!("{:?}", result),
+        }
+        let error = Error::from(spirv::Error::TooShort);
+        assert_eq!(
+            error.to_string(),
+            format!("invalid shader: {}", spirv::Error::TooShort)
+        );
+        assert_eq!(
+            Error::OutOfBounds {
+                offset: 12,
+                size: 8,
+                object_size: 16,
+            }
+            .to_string(),
+            "access of 8 bytes at offset 12 is out of bounds of object with size 16"
+        );
+    }
+}
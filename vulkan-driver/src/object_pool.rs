@@ -0,0 +1,229 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! pools for objects that applications create and destroy often, like command buffers,
+//! descriptor sets and image views
+//!
+//! each pooled type has a global `ObjectPool`, which allocates slots in slabs of `SLAB_SIZE`
+//! and never gives them back to the system allocator, so objects of the same type get packed
+//! together and freed slots are reused instead of fragmenting the heap. each thread keeps a
+//! `LocalCache` of free slots, so most allocations and frees don't lock the global pool; slots
+//! move between the two in batches of `BATCH_SIZE`. an object can be freed on a different thread
+//! than it was allocated on, its slot just ends up in that thread's cache.
+//!
+//! free slots are reused in the order they were freed, and a thread's cache is refilled before
+//! it runs low, so once a thread has allocated an object of a type, a slot it frees is only
+//! reused after at least `BATCH_SIZE - 1` other objects of that type have been allocated.
+//! validation keeps track of live objects by handle, which is the slot's address, so this is
+//! what lets it catch a command buffer that uses a destroyed object after another object of that
+//! type has been created.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::mem::MaybeUninit;
+use std::ptr::{self, NonNull};
+use std::sync::Mutex;
+use std::thread::LocalKey;
+
+/// the number of slots allocated at once when the global pool runs out
+pub const SLAB_SIZE: usize = 64;
+
+/// the number of slots moved between a thread's cache and the global pool at once
+pub const BATCH_SIZE: usize = 32;
+
+/// the most free slots a thread's cache holds before returning a batch to the global pool
+pub const LOCAL_CACHE_SIZE: usize = BATCH_SIZE * 2;
+
+struct Slot<T>(NonNull<MaybeUninit<T>>);
+
+impl<T> Clone for Slot<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Slot<T> {}
+
+// slots are uninitialized memory, so they can be handed to any thread
+unsafe impl<T> Send for Slot<T> {}
+
+pub struct ObjectPool<T> {
+    /// oldest first
+    free_slots: Mutex<VecDeque<Slot<T>>>,
+}
+
+impl<T> Default for ObjectPool<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ObjectPool<T> {
+    /// `const` so pools can be statics
+    pub const fn new() -> Self {
+        ObjectPool {
+            free_slots: Mutex::new(VecDeque::new()),
+        }
+    }
+    /// moves a batch of the oldest free slots to the end of `slots`, allocating a new slab if
+    /// there aren't enough
+    fn take_batch(&self, slots: &mut VecDeque<Slot<T>>) {
+        let mut free_slots = self.free_slots.lock().unwrap();
+        if free_slots.len() < BATCH_SIZE {
+            let slab = Box::leak(
+                (0..SLAB_SIZE)
+                    .map(|_| MaybeUninit::uninit())
+                    .collect::<Box<[_]>>(),
+            );
+            free_slots.extend(slab.iter_mut().map(|slot| Slot(NonNull::from(slot))));
+        }
+        slots.extend(free_slots.drain(..BATCH_SIZE));
+    }
+    fn return_slots<I: IntoIterator<Item = Slot<T>>>(&self, slots: I) {
+        self.free_slots.lock().unwrap().extend(slots);
+    }
+}
+
+/// implemented for pooled types by `impl_pooled_object!`
+pub trait PooledObject: Sized + 'static {
+    fn pool() -> &'static ObjectPool<Self>;
+    fn local_cache() -> &'static LocalKey<RefCell<LocalCache<Self>>>;
+}
+
+/// a thread's free slots for one pooled type
+pub struct LocalCache<T: PooledObject> {
+    /// oldest first
+    slots: VecDeque<Slot<T>>,
+}
+
+impl<T: PooledObject> Default for LocalCache<T> {
+    fn default() -> Self {
+        LocalCache {
+            slots: VecDeque::with_capacity(LOCAL_CACHE_SIZE + 1),
+        }
+    }
+}
+
+impl<T: PooledObject> LocalCache<T> {
+    fn take_slot(&mut self) -> Slot<T> {
+        if self.slots.len() < BATCH_SIZE {
+            T::pool().take_batch(&mut self.slots);
+        }
+        self.slots.pop_front().unwrap()
+    }
+    fn return_slot(&mut self, slot: Slot<T>) {
+        self.slots.push_back(slot);
+        if self.slots.len() > LOCAL_CACHE_SIZE {
+            T::pool().return_slots(self.slots.drain(..BATCH_SIZE));
+        }
+    }
+}
+
+impl<T: PooledObject> Drop for LocalCache<T> {
+    fn drop(&mut self) {
+        T::pool().return_slots(self.slots.drain(..));
+    }
+}
+
+macro_rules! impl_pooled_object {
+    ($type:ty) => {
+        impl $crate::object_pool::PooledObject for $type {
+            fn pool() -> &'static $crate::object_pool::ObjectPool<Self> {
+                static POOL: $crate::object_pool::ObjectPool<$type> =
+                    $crate::object_pool::ObjectPool::new();
+                &POOL
+            }
+            fn local_cache() -> &'static ::std::thread::LocalKey<
+                ::std::cell::RefCell<$crate::object_pool::LocalCache<Self>>,
+            > {
+                use std::cell::RefCell;
+                use $crate::object_pool::LocalCache;
+                thread_local! {
+                    static LOCAL_CACHE: RefCell<LocalCache<$type>> = RefCell::default();
+                }
+                &LOCAL_CACHE
+            }
+        }
+    };
+}
+
+pub fn allocate<T: PooledObject>(value: T) -> NonNull<T> {
+    // the thread's cache is gone if this is called from another thread-local's destructor
+    let slot = T::local_cache()
+        .try_with(|local_cache| local_cache.borrow_mut().take_slot())
+        .unwrap_or_else(|_| {
+            let mut slots = VecDeque::new();
+            T::pool().take_batch(&mut slots);
+            let slot = slots.pop_front().unwrap();
+            T::pool().return_slots(slots);
+            slot
+        });
+    unsafe {
+        ptr::write(slot.0.as_ptr(), MaybeUninit::new(value));
+    }
+    slot.0.cast()
+}
+
+/// drops the object and returns its slot to the pool
+pub unsafe fn free<T: PooledObject>(value: NonNull<T>) {
+    // dropped before the cache is borrowed, since dropping the object can free other objects
+    ptr::drop_in_place(value.as_ptr());
+    let slot = Slot(value.cast());
+    if T::local_cache()
+        .try_with(|local_cache| local_cache.borrow_mut().return_slot(slot))
+        .is_err()
+    {
+        T::pool().return_slots(Some(slot));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    static DROP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    struct TestObject(u64);
+
+    impl Drop for TestObject {
+        fn drop(&mut self) {
+            DROP_COUNT.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl_pooled_object!(TestObject);
+
+    #[test]
+    fn test_object_pool() {
+        let first = allocate(TestObject(1));
+        assert_eq!(unsafe { first.as_ref() }.0, 1);
+        let drop_count = DROP_COUNT.load(Ordering::SeqCst);
+        unsafe { free(first) };
+        assert_eq!(DROP_COUNT.load(Ordering::SeqCst), drop_count + 1);
+        // a freed slot isn't reused until other slots have been
+        let second = allocate(TestObject(2));
+        assert_ne!(second, first);
+        assert_eq!(unsafe { second.as_ref() }.0, 2);
+        let objects: Vec<_> = (0..SLAB_SIZE * 3)
+            .map(|v| allocate(TestObject(v as u64)))
+            .collect();
+        for (index, object) in objects.iter().enumerate() {
+            assert_ne!(*object, second);
+            assert_eq!(unsafe { object.as_ref() }.0, index as u64);
+        }
+        let first_index = objects.iter().position(|&object| object == first).unwrap();
+        assert!(first_index >= BATCH_SIZE - 2);
+        // objects can be freed on another thread
+        let objects: Vec<_> = objects.into_iter().map(|v| v.as_ptr() as usize).collect();
+        thread::spawn(move || {
+            for object in objects {
+                unsafe { free(NonNull::new_unchecked(object as *mut TestObject)) };
+            }
+        })
+        .join()
+        .unwrap();
+        unsafe { free(second) };
+    }
+}
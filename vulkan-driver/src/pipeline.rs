@@ -0,0 +1,1342 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+use api;
+use api_impl::parse_next_chain_const;
+use coverage::Multisample;
+use handle::SharedHandle;
+use sampler;
+use shader_compiler::abi::InvocationOutputs;
+use shader_compiler::descriptors::{self, DescriptorError};
+use shader_compiler::fragment_tests::{DepthCompare, FragmentShaderTests, FragmentTestTiming};
+use shader_compiler::interface::{trim_interface, DescriptorBinding, EntryPointInterface};
+use shader_compiler::link::link_stages;
+use shader_compiler::simd_width::{ShaderProfile, SimdWidth, SimdWidthLimit};
+use shader_compiler::spirv::{self, built_in, ExecutionModel, Id};
+use shader_compiler::varyings::{PrimitiveTopology, VaryingLayout};
+use shader_compiler_backend::{CompilerIndependentConfig, ShaderDumpConfig, ShaderDumpStage};
+use shader_compiler_backend_llvm_7::LLVM7CompilerConfig;
+use shader_module::ShaderModule;
+use std::env;
+use std::error;
+use std::ffi::CStr;
+use std::fmt;
+use std::path::PathBuf;
+use std::ptr::null;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::Arc;
+use stencil::StencilFaceState;
+use viewport::{DepthBias, Viewport};
+
+/// set to a directory to write each shader's SPIR-V, LLVM IR, and assembly to files in it
+pub const DUMP_SHADERS_ENV_VAR: &str = "KAZAN_DUMP_SHADERS";
+
+/// set to evaluate float arithmetic decorated with `RelaxedPrecision` in f16
+pub const RELAXED_PRECISION_ENV_VAR: &str = "KAZAN_RELAXED_PRECISION";
+
+/// the subgroup sizes a shader stage can require (`VK_EXT_subgroup_size_control`). a subgroup is
+/// the invocations run together in variable-length vectors, so its size is the vector length
+/// multiplier. the widest host vectors have 16 32-bit lanes
+pub const MIN_SUBGROUP_SIZE: u32 = 1;
+pub const MAX_SUBGROUP_SIZE: u32 = 16;
+
+fn get_execution_model(stage: api::VkShaderStageFlagBits) -> ExecutionModel {
+    match stage {
+        api::VK_SHADER_STAGE_VERTEX_BIT => ExecutionModel::Vertex,
+        api::VK_SHADER_STAGE_TESSELLATION_CONTROL_BIT => ExecutionModel::TessellationControl,
+        api::VK_SHADER_STAGE_TESSELLATION_EVALUATION_BIT => ExecutionModel::TessellationEvaluation,
+        api::VK_SHADER_STAGE_GEOMETRY_BIT => ExecutionModel::Geometry,
+        api::VK_SHADER_STAGE_FRAGMENT_BIT => ExecutionModel::Fragment,
+        api::VK_SHADER_STAGE_COMPUTE_BIT => ExecutionModel::GLCompute,
+        _ => unreachable!("invalid shader stage: {:#X}", stage),
+    }
+}
+
+/// the stage of the pipelines an entry point with the execution model `execution_model` can be
+/// used in
+pub fn get_shader_stage(execution_model: ExecutionModel) -> api::VkShaderStageFlagBits {
+    match execution_model {
+        ExecutionModel::Vertex => api::VK_SHADER_STAGE_VERTEX_BIT,
+        ExecutionModel::TessellationControl => api::VK_SHADER_STAGE_TESSELLATION_CONTROL_BIT,
+        ExecutionModel::TessellationEvaluation => api::VK_SHADER_STAGE_TESSELLATION_EVALUATION_BIT,
+        ExecutionModel::Geometry => api::VK_SHADER_STAGE_GEOMETRY_BIT,
+        ExecutionModel::Fragment => api::VK_SHADER_STAGE_FRAGMENT_BIT,
+        ExecutionModel::GLCompute | ExecutionModel::Kernel => api::VK_SHADER_STAGE_COMPUTE_BIT,
+    }
+}
+
+pub fn get_shader_dump_directory() -> Option<PathBuf> {
+    let directory = env::var_os(DUMP_SHADERS_ENV_VAR)?;
+    if directory.is_empty() {
+        None
+    } else {
+        Some(directory.into())
+    }
+}
+
+fn is_relaxed_precision_in_f16_enabled() -> bool {
+    match env::var(RELAXED_PRECISION_ENV_VAR) {
+        Ok(ref v) => v != "" && v != "0",
+        Err(_) => false,
+    }
+}
+
+/// returns `None` unless `KAZAN_DUMP_SHADERS` is set.
+/// the file names start with a serial number since most entry points are named `main`
+fn get_shader_dump_config(
+    entry_point_name: &str,
+    execution_model: ExecutionModel,
+) -> Option<ShaderDumpConfig> {
+    static NEXT_SERIAL_NUMBER: AtomicUsize = ATOMIC_USIZE_INIT;
+    let directory = get_shader_dump_directory()?;
+    let serial_number = NEXT_SERIAL_NUMBER.fetch_add(1, Ordering::Relaxed);
+    let entry_point_name: String = entry_point_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    Some(ShaderDumpConfig {
+        directory,
+        name: format!(
+            "{}-{:?}-{}",
+            serial_number, execution_model, entry_point_name
+        ),
+    })
+}
+
+/// why a pipeline can't be created
+#[derive(Clone, Debug)]
+pub enum PipelineError {
+    /// a shader module is invalid or uses something that isn't supported
+    InvalidShader(spirv::Error),
+    /// a stage uses a descriptor that the pipeline layout doesn't have
+    Descriptor(DescriptorError),
+    /// a stage would have to be compiled, but the pipeline is created with
+    /// `VK_PIPELINE_CREATE_FAIL_ON_PIPELINE_COMPILE_REQUIRED_BIT_EXT`
+    CompileRequired,
+    /// creating the pipeline panicked; has the panic message
+    Panicked(String),
+}
+
+impl PipelineError {
+    /// the result `vkCreate*Pipelines` returns
+    pub fn vk_result(&self) -> api::VkResult {
+        match self {
+            PipelineError::CompileRequired => api::VK_PIPELINE_COMPILE_REQUIRED_EXT,
+            _ => api::VK_ERROR_UNKNOWN,
+        }
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PipelineError::InvalidShader(error) => write!(f, "invalid shader module: {}", error),
+            PipelineError::Descriptor(error) => error.fmt(f),
+            PipelineError::CompileRequired => write!(f, "pipeline compile required"),
+            PipelineError::Panicked(message) => write!(f, "panicked: {}", message),
+        }
+    }
+}
+
+impl error::Error for PipelineError {}
+
+impl From<spirv::Error> for PipelineError {
+    fn from(v: spirv::Error) -> Self {
+        PipelineError::InvalidShader(v)
+    }
+}
+
+impl From<DescriptorError> for PipelineError {
+    fn from(v: DescriptorError) -> Self {
+        PipelineError::Descriptor(v)
+    }
+}
+
+/// check that every binding in `descriptor_bindings` is in `layout`
+fn check_descriptor_bindings(
+    layout: &descriptors::PipelineLayout,
+    descriptor_bindings: &[DescriptorBinding],
+) -> Result<(), PipelineError> {
+    for &binding in descriptor_bindings {
+        descriptors::get_layout_binding(layout, binding)?;
+    }
+    Ok(())
+}
+
+pub struct PipelineLayout {
+    pub layout: descriptors::PipelineLayout,
+    pub push_constant_ranges: Vec<api::VkPushConstantRange>,
+}
+
+impl PipelineLayout {
+    pub unsafe fn new(create_info: &api::VkPipelineLayoutCreateInfo) -> Self {
+        assert_eq!(create_info.flags, 0);
+        assert!(create_info.setLayoutCount as usize <= descriptors::MAX_DESCRIPTOR_SETS);
+        let descriptor_sets = if create_info.setLayoutCount == 0 {
+            Vec::new()
+        } else {
+            slice::from_raw_parts(create_info.pSetLayouts, create_info.setLayoutCount as usize)
+                .iter()
+                .map(|&set_layout| SharedHandle::from(set_layout).unwrap().layout.clone())
+                .collect()
+        };
+        let push_constant_ranges = if create_info.pushConstantRangeCount == 0 {
+            Vec::new()
+        } else {
+            slice::from_raw_parts(
+                create_info.pPushConstantRanges,
+                create_info.pushConstantRangeCount as usize,
+            )
+            .to_vec()
+        };
+        let push_constants_size = push_constant_ranges
+            .iter()
+            .map(|range| range.offset + range.size)
+            .max()
+            .unwrap_or(0);
+        PipelineLayout {
+            layout: descriptors::PipelineLayout {
+                descriptor_sets,
+                push_constants_size,
+            },
+            push_constant_ranges,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SpecializationInfo {
+    pub map_entries: Vec<api::VkSpecializationMapEntry>,
+    pub data: Vec<u8>,
+}
+
+impl PartialEq for SpecializationInfo {
+    fn eq(&self, rhs: &Self) -> bool {
+        self.data == rhs.data
+            && self.map_entries.len() == rhs.map_entries.len()
+            && self
+                .map_entries
+                .iter()
+                .zip(&rhs.map_entries)
+                .all(|(a, b)| (a.constantID, a.offset, a.size) == (b.constantID, b.offset, b.size))
+    }
+}
+
+/// everything a `ShaderStage` is made from, so a derivative pipeline can reuse the stages of its
+/// base pipeline that are made from the same things
+#[derive(Clone)]
+pub struct ShaderStageSource {
+    pub stage: api::VkShaderStageFlagBits,
+    pub flags: api::VkPipelineShaderStageCreateFlags,
+    /// shared with the shader module, so it's usually compared by address
+    pub module_code: Arc<Vec<u32>>,
+    pub entry_point_name: String,
+    pub specialization_info: Option<SpecializationInfo>,
+    /// from `VkPipelineShaderStageRequiredSubgroupSizeCreateInfoEXT`
+    pub subgroup_size: Option<u32>,
+}
+
+impl PartialEq for ShaderStageSource {
+    fn eq(&self, rhs: &Self) -> bool {
+        self.stage == rhs.stage
+            && self.flags == rhs.flags
+            && (Arc::ptr_eq(&self.module_code, &rhs.module_code)
+                || self.module_code == rhs.module_code)
+            && self.entry_point_name == rhs.entry_point_name
+            && self.specialization_info == rhs.specialization_info
+            && self.subgroup_size == rhs.subgroup_size
+    }
+}
+
+impl ShaderStageSource {
+    /// the source of a stage made from the entry point `entry_point_name` of a shader module
+    /// without specialization or any other options
+    pub fn for_entry_point(
+        stage: api::VkShaderStageFlagBits,
+        module_code: Arc<Vec<u32>>,
+        entry_point_name: String,
+    ) -> Self {
+        ShaderStageSource {
+            stage,
+            flags: 0,
+            module_code,
+            entry_point_name,
+            specialization_info: None,
+            subgroup_size: None,
+        }
+    }
+    pub unsafe fn new(create_info: &api::VkPipelineShaderStageCreateInfo) -> Self {
+        parse_next_chain_const!{
+            create_info as *const api::VkPipelineShaderStageCreateInfo,
+            root = api::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+            required_subgroup_size_create_info: api::VkPipelineShaderStageRequiredSubgroupSizeCreateInfoEXT = api::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_REQUIRED_SUBGROUP_SIZE_CREATE_INFO_EXT,
+        }
+        // the subgroup size is fixed when the stage is compiled, so it never varies. without a
+        // required size it's chosen for each shader (see `choose_simd_width`)
+        assert_eq!(
+            create_info.flags
+                & !(api::VK_PIPELINE_SHADER_STAGE_CREATE_ALLOW_VARYING_SUBGROUP_SIZE_BIT_EXT
+                    | api::VK_PIPELINE_SHADER_STAGE_CREATE_REQUIRE_FULL_SUBGROUPS_BIT_EXT),
+            0
+        );
+        let subgroup_size = if required_subgroup_size_create_info.is_null() {
+            None
+        } else {
+            let subgroup_size = (*required_subgroup_size_create_info).requiredSubgroupSize;
+            assert!(
+                subgroup_size.is_power_of_two()
+                    && subgroup_size >= MIN_SUBGROUP_SIZE
+                    && subgroup_size <= MAX_SUBGROUP_SIZE,
+                "invalid required subgroup size: {}",
+                subgroup_size
+            );
+            Some(subgroup_size)
+        };
+        let specialization_info = if create_info.pSpecializationInfo.is_null() {
+            None
+        } else {
+            let specialization_info = &*create_info.pSpecializationInfo;
+            Some(SpecializationInfo {
+                map_entries: if specialization_info.mapEntryCount == 0 {
+                    Vec::new()
+                } else {
+                    slice::from_raw_parts(
+                        specialization_info.pMapEntries,
+                        specialization_info.mapEntryCount as usize,
+                    )
+                    .to_vec()
+                },
+                data: if specialization_info.dataSize == 0 {
+                    Vec::new()
+                } else {
+                    slice::from_raw_parts(
+                        specialization_info.pData as *const u8,
+                        specialization_info.dataSize,
+                    )
+                    .to_vec()
+                },
+            })
+        };
+        ShaderStageSource {
+            stage: create_info.stage,
+            flags: create_info.flags,
+            module_code: SharedHandle::from(create_info.module).unwrap().code.clone(),
+            entry_point_name: CStr::from_ptr(create_info.pName).to_str().unwrap().into(),
+            specialization_info,
+            subgroup_size,
+        }
+    }
+}
+
+/// the SIMD width of a shader stage. wider widths run more invocations together, but are slower
+/// for shaders with many live values, f64 arithmetic, or divergent control flow (see
+/// `shader_compiler::simd_width`)
+fn choose_simd_width(source: &ShaderStageSource, profile: &ShaderProfile) -> SimdWidth {
+    if let Some(width) = source.subgroup_size {
+        return SimdWidth {
+            width,
+            limit: SimdWidthLimit::RequiredSubgroupSize,
+        };
+    }
+    // the default width of 1 divides every workgroup size, so subgroups are always full
+    let requires_full_subgroups =
+        source.flags & api::VK_PIPELINE_SHADER_STAGE_CREATE_REQUIRE_FULL_SUBGROUPS_BIT_EXT != 0;
+    let allows_varying_subgroup_size = source.flags
+        & api::VK_PIPELINE_SHADER_STAGE_CREATE_ALLOW_VARYING_SUBGROUP_SIZE_BIT_EXT
+        != 0;
+    if requires_full_subgroups || (profile.uses_subgroup_size && !allows_varying_subgroup_size) {
+        SimdWidth {
+            width: LLVM7CompilerConfig::default().variable_vector_length_multiplier,
+            limit: SimdWidthLimit::SubgroupSize,
+        }
+    } else {
+        profile.choose_simd_width(MAX_SUBGROUP_SIZE)
+    }
+}
+
+#[derive(Clone)]
+pub struct ShaderStage {
+    pub stage: api::VkShaderStageFlagBits,
+    pub source: ShaderStageSource,
+    /// the shader module's code with everything unused by the entry point removed
+    pub code: Vec<u32>,
+    pub interface: EntryPointInterface,
+    /// the config the code is compiled with
+    pub compiler_config: CompilerIndependentConfig,
+    pub profile: ShaderProfile,
+    /// the SIMD width the code is compiled with, and why it isn't wider
+    pub simd_width_choice: SimdWidth,
+    /// copied from the base pipeline of a derivative pipeline
+    pub from_base_pipeline: bool,
+}
+
+impl ShaderStage {
+    /// `watchdog_checks` is from the driver configuration
+    pub fn new(source: ShaderStageSource, watchdog_checks: bool) -> Result<Self, PipelineError> {
+        let execution_model = get_execution_model(source.stage);
+        let trimmed = trim_interface(
+            &source.module_code,
+            &source.entry_point_name,
+            execution_model,
+        )
+        .or_else(|error| match error {
+            // compute modules from OpenCL C compilers can use the Kernel execution model
+            spirv::Error::EntryPointNotFound { .. }
+                if execution_model == ExecutionModel::GLCompute =>
+            {
+                trim_interface(
+                    &source.module_code,
+                    &source.entry_point_name,
+                    ExecutionModel::Kernel,
+                )
+            }
+            error => Err(error),
+        })?;
+        let execution_model = trimmed.interface.execution_model;
+        let profile = ShaderProfile::new(&trimmed.code)?;
+        let simd_width_choice = choose_simd_width(&source, &profile);
+        Ok(ShaderStage {
+            stage: source.stage,
+            code: trimmed.code,
+            interface: trimmed.interface,
+            compiler_config: CompilerIndependentConfig {
+                shader_dump: get_shader_dump_config(&source.entry_point_name, execution_model),
+                relaxed_precision_in_f16: is_relaxed_precision_in_f16_enabled(),
+                variable_vector_length_multiplier: Some(simd_width_choice.width),
+                watchdog_checks,
+                host_functions: sampler::get_image_intrinsics().get_host_functions(),
+                ..Default::default()
+            },
+            profile,
+            simd_width_choice,
+            source,
+            from_base_pipeline: false,
+        })
+    }
+    /// a copy of the stage for another pipeline. it gets its own shader dump name, so the
+    /// pipeline's dumps don't replace the dumps of the pipeline it's copied from
+    pub fn copy_for_pipeline(&self) -> Self {
+        ShaderStage {
+            compiler_config: CompilerIndependentConfig {
+                shader_dump: get_shader_dump_config(
+                    &self.source.entry_point_name,
+                    self.interface.execution_model,
+                ),
+                ..self.compiler_config.clone()
+            },
+            ..self.clone()
+        }
+    }
+    /// a copy of a stage of a derivative pipeline's base pipeline
+    fn copy_from_base_pipeline(&self) -> Self {
+        ShaderStage {
+            from_base_pipeline: true,
+            ..self.copy_for_pipeline()
+        }
+    }
+    /// the number of invocations run together, which is the vector length of the compiled code
+    pub fn simd_width(&self) -> u32 {
+        self.compiler_config
+            .variable_vector_length_multiplier
+            .unwrap_or_else(|| LLVM7CompilerConfig::default().variable_vector_length_multiplier)
+    }
+    /// write the final SPIR-V disassembly if `KAZAN_DUMP_SHADERS` is set
+    fn dump_spirv(&self) {
+        if let Some(shader_dump) = &self.compiler_config.shader_dump {
+            match spirv::disassemble(&self.code) {
+                Ok(disassembly) => shader_dump.dump(ShaderDumpStage::SpirV, disassembly),
+                Err(error) => eprintln!("kazan: can't disassemble shader: {}", error),
+            }
+        }
+    }
+    /// replace the code, recomputing the interface and the SIMD width
+    fn set_code(&mut self, code: &[u32]) -> Result<(), PipelineError> {
+        let trimmed = trim_interface(code, &self.interface.name, self.interface.execution_model)?;
+        self.profile = ShaderProfile::new(&trimmed.code)?;
+        self.simd_width_choice = choose_simd_width(&self.source, &self.profile);
+        self.compiler_config.variable_vector_length_multiplier = Some(self.simd_width_choice.width);
+        self.code = trimmed.code;
+        self.interface = trimmed.interface;
+        Ok(())
+    }
+}
+
+/// how linking changed the interface between two stages
+#[derive(Clone, Debug)]
+pub struct StageLink {
+    pub producer: ExecutionModel,
+    pub consumer: ExecutionModel,
+    /// producer outputs the consumer doesn't read; sorted
+    pub demoted_outputs: Vec<Id>,
+    /// consumer inputs the producer writes constants to; sorted
+    pub promoted_inputs: Vec<Id>,
+    /// `(old_location, new_location)` for every location that was moved; sorted
+    pub location_remap: Vec<(u32, u32)>,
+}
+
+/// the indices of the last pre-rasterization stage and the fragment stage, which are linked
+fn find_linked_stages<I: IntoIterator<Item = api::VkShaderStageFlagBits>>(
+    stages: I,
+) -> Option<(usize, usize)> {
+    let stages: Vec<_> = stages.into_iter().collect();
+    let find_stage = |stage| stages.iter().position(|&v| v == stage);
+    let producer = find_stage(api::VK_SHADER_STAGE_GEOMETRY_BIT)
+        .or_else(|| find_stage(api::VK_SHADER_STAGE_TESSELLATION_EVALUATION_BIT))
+        .or_else(|| find_stage(api::VK_SHADER_STAGE_VERTEX_BIT))?;
+    let consumer = find_stage(api::VK_SHADER_STAGE_FRAGMENT_BIT)?;
+    Some((producer, consumer))
+}
+
+/// optimize the interface between the last pre-rasterization stage and the fragment stage
+fn link_graphics_stages(stages: &mut [ShaderStage]) -> Result<Option<StageLink>, PipelineError> {
+    let (producer, consumer) = match find_linked_stages(stages.iter().map(|v| v.stage)) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    let linked = link_stages(&stages[producer].code, &stages[consumer].code)?;
+    stages[producer].set_code(&linked.producer_code)?;
+    stages[consumer].set_code(&linked.consumer_code)?;
+    Ok(Some(StageLink {
+        producer: stages[producer].interface.execution_model,
+        consumer: stages[consumer].interface.execution_model,
+        demoted_outputs: linked.demoted_outputs,
+        promoted_inputs: linked.promoted_inputs,
+        location_remap: linked.location_remap,
+    }))
+}
+
+/// create the stage made from `source` with `module`, the shader module it's from. the stage
+/// created in the background when the module was created is used if it's made from the same
+/// source. if `fail_if_compile_required`
+/// (`VK_PIPELINE_CREATE_FAIL_ON_PIPELINE_COMPILE_REQUIRED_BIT_EXT`), fails instead of creating
+/// the stage or waiting for it to be created
+fn create_stage(
+    source: ShaderStageSource,
+    module: &ShaderModule,
+    fail_if_compile_required: bool,
+    watchdog_checks: bool,
+) -> Result<ShaderStage, PipelineError> {
+    match module.get_warmed_stage(&source, !fail_if_compile_required) {
+        Some(stage) => Ok(stage),
+        None if fail_if_compile_required => Err(PipelineError::CompileRequired),
+        None => ShaderStage::new(source, watchdog_checks),
+    }
+}
+
+/// create the stages of a graphics pipeline and link them. stages made from the same sources as
+/// a stage of `base`, the base pipeline of a derivative pipeline, are copied from it instead of
+/// being created again. linking changes both linked stages, so they're only copied if both
+/// linked stages of `base` are made from the same sources, and unlinked stages are only copied
+/// from unlinked stages. `create_stage` creates the rest from their index and source
+fn create_graphics_stages<F>(
+    sources: Vec<ShaderStageSource>,
+    base: Option<&GraphicsPipeline>,
+    mut create_stage: F,
+) -> Result<(Vec<ShaderStage>, Option<StageLink>), PipelineError>
+where
+    F: FnMut(usize, ShaderStageSource) -> Result<ShaderStage, PipelineError>,
+{
+    let base = match base {
+        Some(base) => base,
+        None => {
+            let mut stages = sources
+                .into_iter()
+                .enumerate()
+                .map(|(index, source)| create_stage(index, source))
+                .collect::<Result<Vec<_>, _>>()?;
+            let stage_link = link_graphics_stages(&mut stages)?;
+            return Ok((stages, stage_link));
+        }
+    };
+    let linked = find_linked_stages(sources.iter().map(|v| v.stage));
+    let base_linked = find_linked_stages(base.stages.iter().map(|v| v.stage));
+    let is_linked = |linked: Option<(usize, usize)>, index| {
+        linked.map_or(false, |(producer, consumer)| {
+            index == producer || index == consumer
+        })
+    };
+    let copy_linked = match (linked, base_linked) {
+        (Some((producer, consumer)), Some((base_producer, base_consumer))) => {
+            sources[producer] == base.stages[base_producer].source
+                && sources[consumer] == base.stages[base_consumer].source
+        }
+        _ => false,
+    };
+    let mut stages: Vec<_> = sources
+        .into_iter()
+        .enumerate()
+        .map(|(index, source)| {
+            let linked_stage = is_linked(linked, index);
+            if linked_stage && !copy_linked {
+                return create_stage(index, source);
+            }
+            let base_stage = base
+                .stages
+                .iter()
+                .enumerate()
+                .find(|&(base_index, base_stage)| {
+                    is_linked(base_linked, base_index) == linked_stage
+                        && base_stage.source == source
+                });
+            match base_stage {
+                Some((_, base_stage)) => Ok(base_stage.copy_from_base_pipeline()),
+                None => create_stage(index, source),
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let stage_link = if copy_linked {
+        base.stage_link.clone()
+    } else {
+        link_graphics_stages(&mut stages)?
+    };
+    Ok((stages, stage_link))
+}
+
+/// the base pipeline of a derivative pipeline. only `basePipelineHandle` is supported, since the
+/// pipelines of a `vkCreate*Pipelines` call are created in parallel, so the pipeline
+/// `basePipelineIndex` refers to may not be created yet; those pipelines are created without
+/// copying anything
+unsafe fn get_base_pipeline(
+    flags: api::VkPipelineCreateFlags,
+    base_pipeline_handle: api::VkPipeline,
+) -> Option<SharedHandle<api::VkPipeline>> {
+    if flags & api::VK_PIPELINE_CREATE_DERIVATIVE_BIT == 0 {
+        return None;
+    }
+    SharedHandle::from(base_pipeline_handle)
+}
+
+fn get_depth_compare(compare_op: api::VkCompareOp) -> DepthCompare {
+    match compare_op {
+        api::VK_COMPARE_OP_NEVER => DepthCompare::Never,
+        api::VK_COMPARE_OP_LESS => DepthCompare::Less,
+        api::VK_COMPARE_OP_EQUAL => DepthCompare::Equal,
+        api::VK_COMPARE_OP_LESS_OR_EQUAL => DepthCompare::LessOrEqual,
+        api::VK_COMPARE_OP_GREATER => DepthCompare::Greater,
+        api::VK_COMPARE_OP_NOT_EQUAL => DepthCompare::NotEqual,
+        api::VK_COMPARE_OP_GREATER_OR_EQUAL => DepthCompare::GreaterOrEqual,
+        api::VK_COMPARE_OP_ALWAYS => DepthCompare::Always,
+        _ => panic!("invalid compare op: {}", compare_op),
+    }
+}
+
+fn get_primitive_topology(topology: api::VkPrimitiveTopology) -> PrimitiveTopology {
+    match topology {
+        api::VK_PRIMITIVE_TOPOLOGY_POINT_LIST => PrimitiveTopology::PointList,
+        api::VK_PRIMITIVE_TOPOLOGY_LINE_LIST => PrimitiveTopology::LineList,
+        api::VK_PRIMITIVE_TOPOLOGY_LINE_STRIP => PrimitiveTopology::LineStrip,
+        api::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST => PrimitiveTopology::TriangleList,
+        api::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_STRIP => PrimitiveTopology::TriangleStrip,
+        api::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_FAN => PrimitiveTopology::TriangleFan,
+        api::VK_PRIMITIVE_TOPOLOGY_LINE_LIST_WITH_ADJACENCY => {
+            PrimitiveTopology::LineListWithAdjacency
+        }
+        api::VK_PRIMITIVE_TOPOLOGY_LINE_STRIP_WITH_ADJACENCY => {
+            PrimitiveTopology::LineStripWithAdjacency
+        }
+        api::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_LIST_WITH_ADJACENCY => {
+            PrimitiveTopology::TriangleListWithAdjacency
+        }
+        api::VK_PRIMITIVE_TOPOLOGY_TRIANGLE_STRIP_WITH_ADJACENCY => {
+            PrimitiveTopology::TriangleStripWithAdjacency
+        }
+        api::VK_PRIMITIVE_TOPOLOGY_PATCH_LIST => PrimitiveTopology::PatchList,
+        _ => panic!("invalid primitive topology: {}", topology),
+    }
+}
+
+pub struct GraphicsPipeline {
+    pub stages: Vec<ShaderStage>,
+    /// `None` if there's no fragment shader
+    pub stage_link: Option<StageLink>,
+    /// only the vertex bindings that are read by the vertex shader
+    pub vertex_bindings: Vec<api::VkVertexInputBindingDescription>,
+    /// only the vertex attributes that are read by the vertex shader
+    pub vertex_attributes: Vec<api::VkVertexInputAttributeDescription>,
+    /// the union of the descriptor bindings used by all stages; sorted and deduplicated
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    pub uses_push_constants: bool,
+    /// copied from the `PipelineLayout` since it can be destroyed before the pipeline
+    pub layout: descriptors::PipelineLayout,
+    /// decides which vertex flat varyings come from
+    pub topology: PrimitiveTopology,
+    /// the fragment shader's inputs, laid out for its SIMD width; empty if there's no fragment
+    /// shader
+    pub varying_layout: VaryingLayout,
+    /// the fragment shader writes `FragStencilRefEXT` (`VK_EXT_shader_stencil_export`)
+    pub exports_stencil_reference: bool,
+    /// the fragment shader writes `SampleMask`
+    pub writes_sample_mask: bool,
+    /// `None` if there's no fragment shader
+    pub fragment_shader_tests: Option<FragmentShaderTests>,
+    /// when the rasterizer runs the depth and stencil tests
+    pub fragment_test_timing: FragmentTestTiming,
+    /// the front and back face states; `None` if the stencil test is disabled
+    pub stencil_test: Option<[StencilFaceState; 2]>,
+    pub dynamic_states: Vec<api::VkDynamicState>,
+    /// empty if the viewports are dynamic state or rasterization is disabled
+    pub viewports: Vec<Viewport>,
+    /// `None` if depth bias is disabled
+    pub depth_bias: Option<DepthBias>,
+    pub depth_clamp_enable: bool,
+    /// the default if rasterization is disabled
+    pub multisample: Multisample,
+}
+
+impl GraphicsPipeline {
+    /// `dither_alpha_to_coverage` and `watchdog_checks` are from the driver configuration
+    pub unsafe fn new(
+        create_info: &api::VkGraphicsPipelineCreateInfo,
+        dither_alpha_to_coverage: bool,
+        watchdog_checks: bool,
+    ) -> Result<Self, PipelineError> {
+        assert_ne!(create_info.stageCount, 0);
+        let stage_create_infos =
+            slice::from_raw_parts(create_info.pStages, create_info.stageCount as usize);
+        let sources = stage_create_infos
+            .iter()
+            .map(|v| ShaderStageSource::new(v))
+            .collect();
+        let base_pipeline = get_base_pipeline(create_info.flags, create_info.basePipelineHandle);
+        let base_pipeline = match base_pipeline.as_ref().map(|v| &**v) {
+            Some(Pipeline::Graphics(base_pipeline)) => Some(base_pipeline),
+            _ => None,
+        };
+        let fail_if_compile_required = create_info.flags
+            & api::VK_PIPELINE_CREATE_FAIL_ON_PIPELINE_COMPILE_REQUIRED_BIT_EXT
+            != 0;
+        let (stages, stage_link) =
+            create_graphics_stages(sources, base_pipeline, |index, source| {
+                let module = SharedHandle::from(stage_create_infos[index].module).unwrap();
+                create_stage(source, &module, fail_if_compile_required, watchdog_checks)
+            })?;
+        for stage in &stages {
+            stage.dump_spirv();
+        }
+        let vertex_stage = stages
+            .iter()
+            .find(|v| v.stage == api::VK_SHADER_STAGE_VERTEX_BIT)
+            .expect("graphics pipeline has no vertex shader");
+        let mut vertex_bindings = Vec::new();
+        let mut vertex_attributes = Vec::new();
+        if !create_info.pVertexInputState.is_null() {
+            let vertex_input_state = &*create_info.pVertexInputState;
+            assert_eq!(
+                vertex_input_state.sType,
+                api::VK_STRUCTURE_TYPE_PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO
+            );
+            assert!(vertex_input_state.pNext.is_null());
+            if vertex_input_state.vertexAttributeDescriptionCount != 0 {
+                vertex_attributes.extend(
+                    slice::from_raw_parts(
+                        vertex_input_state.pVertexAttributeDescriptions,
+                        vertex_input_state.vertexAttributeDescriptionCount as usize,
+                    )
+                    .iter()
+                    .filter(|attribute| {
+                        vertex_stage
+                            .interface
+                            .input_locations()
+                            .any(|location| location == attribute.location)
+                    }),
+                );
+            }
+            if vertex_input_state.vertexBindingDescriptionCount != 0 {
+                vertex_bindings.extend(
+                    slice::from_raw_parts(
+                        vertex_input_state.pVertexBindingDescriptions,
+                        vertex_input_state.vertexBindingDescriptionCount as usize,
+                    )
+                    .iter()
+                    .filter(|binding| {
+                        vertex_attributes
+                            .iter()
+                            .any(|attribute| attribute.binding == binding.binding)
+                    }),
+                );
+            }
+        }
+        let mut descriptor_bindings = Vec::new();
+        let mut uses_push_constants = false;
+        for stage in &stages {
+            descriptor_bindings.extend_from_slice(&stage.interface.descriptor_bindings);
+            uses_push_constants |= stage.interface.uses_push_constants;
+        }
+        descriptor_bindings.sort();
+        descriptor_bindings.dedup();
+        let layout = SharedHandle::from(create_info.layout)
+            .unwrap()
+            .layout
+            .clone();
+        check_descriptor_bindings(&layout, &descriptor_bindings)?;
+        assert!(!create_info.pInputAssemblyState.is_null());
+        let input_assembly_state = &*create_info.pInputAssemblyState;
+        assert_eq!(
+            input_assembly_state.sType,
+            api::VK_STRUCTURE_TYPE_PIPELINE_INPUT_ASSEMBLY_STATE_CREATE_INFO
+        );
+        let fragment_stage = stages
+            .iter()
+            .find(|v| v.stage == api::VK_SHADER_STAGE_FRAGMENT_BIT);
+        let varying_layout = fragment_stage
+            .map(|v| VaryingLayout::new(&v.interface.inputs, v.simd_width()))
+            .unwrap_or_default();
+        let fragment_shader_tests = fragment_stage.map(|v| FragmentShaderTests::new(&v.interface));
+        let dynamic_states = if create_info.pDynamicState.is_null() {
+            Vec::new()
+        } else {
+            let dynamic_state = &*create_info.pDynamicState;
+            assert_eq!(
+                dynamic_state.sType,
+                api::VK_STRUCTURE_TYPE_PIPELINE_DYNAMIC_STATE_CREATE_INFO
+            );
+            if dynamic_state.dynamicStateCount == 0 {
+                Vec::new()
+            } else {
+                slice::from_raw_parts(
+                    dynamic_state.pDynamicStates,
+                    dynamic_state.dynamicStateCount as usize,
+                )
+                .to_vec()
+            }
+        };
+        assert!(!create_info.pRasterizationState.is_null());
+        let rasterization_state = &*create_info.pRasterizationState;
+        assert_eq!(
+            rasterization_state.sType,
+            api::VK_STRUCTURE_TYPE_PIPELINE_RASTERIZATION_STATE_CREATE_INFO
+        );
+        let viewports = if rasterization_state.rasterizerDiscardEnable != api::VK_FALSE
+            || dynamic_states.contains(&api::VK_DYNAMIC_STATE_VIEWPORT)
+        {
+            Vec::new()
+        } else {
+            assert!(!create_info.pViewportState.is_null());
+            let viewport_state = &*create_info.pViewportState;
+            assert_eq!(
+                viewport_state.sType,
+                api::VK_STRUCTURE_TYPE_PIPELINE_VIEWPORT_STATE_CREATE_INFO
+            );
+            slice::from_raw_parts(
+                viewport_state.pViewports,
+                viewport_state.viewportCount as usize,
+            )
+            .iter()
+            .map(Viewport::new)
+            .collect()
+        };
+        let depth_bias = if rasterization_state.depthBiasEnable != api::VK_FALSE {
+            Some(DepthBias::new(
+                rasterization_state.depthBiasConstantFactor,
+                rasterization_state.depthBiasClamp,
+                rasterization_state.depthBiasSlopeFactor,
+            ))
+        } else {
+            None
+        };
+        // the depth stencil state is ignored when rasterization is disabled
+        let (depth_compare, stencil_test) = if create_info.pDepthStencilState.is_null()
+            || rasterization_state.rasterizerDiscardEnable != api::VK_FALSE
+        {
+            (None, None)
+        } else {
+            let depth_stencil_state = &*create_info.pDepthStencilState;
+            assert_eq!(
+                depth_stencil_state.sType,
+                api::VK_STRUCTURE_TYPE_PIPELINE_DEPTH_STENCIL_STATE_CREATE_INFO
+            );
+            (
+                if depth_stencil_state.depthTestEnable != api::VK_FALSE {
+                    Some(get_depth_compare(depth_stencil_state.depthCompareOp))
+                } else {
+                    None
+                },
+                if depth_stencil_state.stencilTestEnable != api::VK_FALSE {
+                    Some([
+                        StencilFaceState::new(&depth_stencil_state.front),
+                        StencilFaceState::new(&depth_stencil_state.back),
+                    ])
+                } else {
+                    None
+                },
+            )
+        };
+        let multisample = if rasterization_state.rasterizerDiscardEnable != api::VK_FALSE {
+            Multisample::default()
+        } else {
+            assert!(!create_info.pMultisampleState.is_null());
+            Multisample::new(&*create_info.pMultisampleState, dither_alpha_to_coverage)
+        };
+        let fragment_test_timing = fragment_shader_tests.map_or(FragmentTestTiming::Early, |v| {
+            v.get_timing(depth_compare, stencil_test.is_some())
+        });
+        Ok(GraphicsPipeline {
+            stages,
+            stage_link,
+            vertex_bindings,
+            vertex_attributes,
+            descriptor_bindings,
+            uses_push_constants,
+            layout,
+            topology: get_primitive_topology(input_assembly_state.topology),
+            varying_layout,
+            exports_stencil_reference: fragment_shader_tests
+                .map_or(false, |v| v.exports_stencil_reference),
+            writes_sample_mask: fragment_shader_tests.map_or(false, |v| v.writes_sample_mask),
+            fragment_shader_tests,
+            fragment_test_timing,
+            stencil_test,
+            dynamic_states,
+            viewports,
+            depth_bias,
+            depth_clamp_enable: rasterization_state.depthClampEnable != api::VK_FALSE,
+            multisample,
+        })
+    }
+    /// the viewports for a draw; `dynamic_viewports` are from `vkCmdSetViewport`
+    #[allow(dead_code)]
+    pub fn get_viewports<'a>(&'a self, dynamic_viewports: &'a [Viewport]) -> &'a [Viewport] {
+        if self
+            .dynamic_states
+            .contains(&api::VK_DYNAMIC_STATE_VIEWPORT)
+        {
+            dynamic_viewports
+        } else {
+            &self.viewports
+        }
+    }
+    /// the depth bias for a draw; `dynamic_depth_bias` is from `vkCmdSetDepthBias*`
+    #[allow(dead_code)]
+    pub fn get_depth_bias(&self, dynamic_depth_bias: DepthBias) -> Option<DepthBias> {
+        if self
+            .dynamic_states
+            .contains(&api::VK_DYNAMIC_STATE_DEPTH_BIAS)
+        {
+            self.depth_bias.map(|_| dynamic_depth_bias)
+        } else {
+            self.depth_bias
+        }
+    }
+    /// the reference value for a fragment's stencil test and `VK_STENCIL_OP_REPLACE`: the
+    /// fragment shader's `FragStencilRefEXT` if it writes it, otherwise `reference` from the
+    /// pipeline or dynamic state. only the attachment's stencil bits of the result are used
+    #[allow(dead_code)]
+    pub fn get_stencil_reference(&self, outputs: &InvocationOutputs, reference: u32) -> u32 {
+        if self.exports_stencil_reference {
+            outputs.built_in(built_in::FRAG_STENCIL_REF_EXT).unwrap()[0]
+        } else {
+            reference
+        }
+    }
+    /// the samples of a fragment that are written, after the fragment shader wrote `outputs`.
+    /// `sample_mask_input` is the `SampleMask` input from `Multisample::get_sample_mask_input`,
+    /// and `alpha` is the alpha written to color attachment 0
+    #[allow(dead_code)]
+    pub fn get_coverage(
+        &self,
+        outputs: &InvocationOutputs,
+        sample_mask_input: u32,
+        alpha: f32,
+        x: u32,
+        y: u32,
+    ) -> u32 {
+        let shader_sample_mask = if self.writes_sample_mask {
+            Some(outputs.built_in(built_in::SAMPLE_MASK).unwrap()[0])
+        } else {
+            None
+        };
+        self.multisample
+            .get_coverage(sample_mask_input, shader_sample_mask, alpha, x, y)
+    }
+}
+
+pub struct ComputePipeline {
+    pub stage: ShaderStage,
+    /// copied from the `PipelineLayout` since it can be destroyed before the pipeline
+    pub layout: descriptors::PipelineLayout,
+}
+
+impl ComputePipeline {
+    /// `watchdog_checks` is from the driver configuration
+    pub unsafe fn new(
+        create_info: &api::VkComputePipelineCreateInfo,
+        watchdog_checks: bool,
+    ) -> Result<Self, PipelineError> {
+        assert_eq!(create_info.stage.stage, api::VK_SHADER_STAGE_COMPUTE_BIT);
+        let source = ShaderStageSource::new(&create_info.stage);
+        let base_pipeline = get_base_pipeline(create_info.flags, create_info.basePipelineHandle);
+        let fail_if_compile_required = create_info.flags
+            & api::VK_PIPELINE_CREATE_FAIL_ON_PIPELINE_COMPILE_REQUIRED_BIT_EXT
+            != 0;
+        let stage = match base_pipeline.as_ref().map(|v| &**v) {
+            Some(Pipeline::Compute(base_pipeline)) if base_pipeline.stage.source == source => {
+                base_pipeline.stage.copy_from_base_pipeline()
+            }
+            _ => {
+                let module = SharedHandle::from(create_info.stage.module).unwrap();
+                create_stage(source, &module, fail_if_compile_required, watchdog_checks)?
+            }
+        };
+        stage.dump_spirv();
+        let layout = SharedHandle::from(create_info.layout)
+            .unwrap()
+            .layout
+            .clone();
+        check_descriptor_bindings(&layout, &stage.interface.descriptor_bindings)?;
+        Ok(ComputePipeline { stage, layout })
+    }
+}
+
+pub enum Pipeline {
+    Graphics(GraphicsPipeline),
+    Compute(ComputePipeline),
+}
+
+impl Pipeline {
+    pub fn stages(&self) -> &[ShaderStage] {
+        match self {
+            Pipeline::Graphics(pipeline) => &pipeline.stages,
+            Pipeline::Compute(pipeline) => slice::from_ref(&pipeline.stage),
+        }
+    }
+    /// the descriptor bindings that need to be flushed before running this pipeline
+    pub fn descriptor_bindings(&self) -> &[DescriptorBinding] {
+        match self {
+            Pipeline::Graphics(pipeline) => &pipeline.descriptor_bindings,
+            Pipeline::Compute(pipeline) => &pipeline.stage.interface.descriptor_bindings,
+        }
+    }
+    pub fn layout(&self) -> &descriptors::PipelineLayout {
+        match self {
+            Pipeline::Graphics(pipeline) => &pipeline.layout,
+            Pipeline::Compute(pipeline) => &pipeline.layout,
+        }
+    }
+    pub fn get_bind_point(&self) -> api::VkPipelineBindPoint {
+        match self {
+            Pipeline::Graphics(_) => api::VK_PIPELINE_BIND_POINT_GRAPHICS,
+            Pipeline::Compute(_) => api::VK_PIPELINE_BIND_POINT_COMPUTE,
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use compile_thread_pool::CompileThreadPool;
+    use handle::OwnedHandle;
+    use shader_compiler::spirv::{op, write_instruction, write_literal_string, MAGIC_NUMBER};
+    use std::os::raw::c_char;
+    use std::ptr::null_mut;
+
+    const OP_TYPE_VOID: u16 = 19;
+    const OP_TYPE_FUNCTION: u16 = 33;
+    const OP_LABEL: u16 = 248;
+
+    /// a compute shader module with an empty entry point named `main`; also used by the tests
+    /// of the native API
+    pub fn compute_module() -> Vec<u32> {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 5, 0];
+        let mut write = |opcode, operands: &[u32]| write_instruction(&mut code, opcode, operands);
+        // Shader
+        write(op::CAPABILITY, &[1]);
+        // Logical GLSL450
+        write(op::MEMORY_MODEL, &[0, 1]);
+        let mut operands = vec![ExecutionModel::GLCompute as u32, 3];
+        write_literal_string(&mut operands, "main");
+        write(op::ENTRY_POINT, &operands);
+        // LocalSize 1 1 1
+        write(op::EXECUTION_MODE, &[3, 17, 1, 1, 1]);
+        write(OP_TYPE_VOID, &[1]);
+        write(OP_TYPE_FUNCTION, &[2, 1]);
+        write(op::FUNCTION, &[1, 3, 0, 2]);
+        write(OP_LABEL, &[4]);
+        write(op::RETURN, &[]);
+        write(op::FUNCTION_END, &[]);
+        code
+    }
+
+    /// a shader module with empty vertex, geometry, and fragment entry points named `vert`,
+    /// `geom`, and `frag`
+    fn graphics_module() -> Vec<u32> {
+        let mut code = vec![MAGIC_NUMBER, 0x0001_0000, 0, 9, 0];
+        let mut write = |opcode, operands: &[u32]| write_instruction(&mut code, opcode, operands);
+        // Shader
+        write(op::CAPABILITY, &[1]);
+        // Geometry
+        write(op::CAPABILITY, &[2]);
+        // Logical GLSL450
+        write(op::MEMORY_MODEL, &[0, 1]);
+        let entry_points = [
+            (ExecutionModel::Vertex, 3, "vert"),
+            (ExecutionModel::Geometry, 5, "geom"),
+            (ExecutionModel::Fragment, 7, "frag"),
+        ];
+        for &(execution_model, function, name) in &entry_points {
+            let mut operands = vec![execution_model as u32, function];
+            write_literal_string(&mut operands, name);
+            write(op::ENTRY_POINT, &operands);
+        }
+        // InputPoints, Invocations 1, OutputPoints, OutputVertices 1
+        write(op::EXECUTION_MODE, &[5, 19]);
+        write(op::EXECUTION_MODE, &[5, 0, 1]);
+        write(op::EXECUTION_MODE, &[5, 27]);
+        write(op::EXECUTION_MODE, &[5, 26, 1]);
+        // OriginUpperLeft
+        write(op::EXECUTION_MODE, &[7, 7]);
+        write(OP_TYPE_VOID, &[1]);
+        write(OP_TYPE_FUNCTION, &[2, 1]);
+        for &(_, function, _) in &entry_points {
+            write(op::FUNCTION, &[1, function, 0, 2]);
+            write(OP_LABEL, &[function + 1]);
+            write(op::RETURN, &[]);
+            write(op::FUNCTION_END, &[]);
+        }
+        code
+    }
+
+    /// the sources of the stages of `graphics_module` named by `names`; the stages whose
+    /// names are in `specialized` get the specialization constant 0 set to `constant`
+    fn graphics_sources(
+        module_code: &Arc<Vec<u32>>,
+        names: &[&str],
+        specialized: &[&str],
+        constant: u32,
+    ) -> Vec<ShaderStageSource> {
+        names
+            .iter()
+            .map(|&name| {
+                let stage = match name {
+                    "vert" => api::VK_SHADER_STAGE_VERTEX_BIT,
+                    "geom" => api::VK_SHADER_STAGE_GEOMETRY_BIT,
+                    "frag" => api::VK_SHADER_STAGE_FRAGMENT_BIT,
+                    _ => unreachable!(),
+                };
+                let mut source =
+                    ShaderStageSource::for_entry_point(stage, module_code.clone(), name.into());
+                if specialized.contains(&name) {
+                    source.specialization_info = Some(SpecializationInfo {
+                        map_entries: vec![api::VkSpecializationMapEntry {
+                            constantID: 0,
+                            offset: 0,
+                            size: 4,
+                        }],
+                        data: (0..4).map(|i| (constant >> (i * 8)) as u8).collect(),
+                    });
+                }
+                source
+            })
+            .collect()
+    }
+
+    fn create_base_pipeline(sources: Vec<ShaderStageSource>) -> GraphicsPipeline {
+        let (stages, stage_link) =
+            create_graphics_stages(sources, None, |_, source| ShaderStage::new(source, false))
+                .unwrap();
+        GraphicsPipeline {
+            stages,
+            stage_link,
+            vertex_bindings: Vec::new(),
+            vertex_attributes: Vec::new(),
+            descriptor_bindings: Vec::new(),
+            uses_push_constants: false,
+            layout: Default::default(),
+            topology: PrimitiveTopology::PointList,
+            varying_layout: VaryingLayout::default(),
+            fragment_shader_tests: None,
+            fragment_test_timing: FragmentTestTiming::Early,
+            stencil_test: None,
+            exports_stencil_reference: false,
+            dynamic_states: Vec::new(),
+            viewports: Vec::new(),
+            depth_bias: None,
+            depth_clamp_enable: false,
+        }
+    }
+
+    /// create the stages of a derivative pipeline of `base`, returning them and the indices of
+    /// the stages that weren't copied from `base`
+    fn create_derivative_stages(
+        sources: Vec<ShaderStageSource>,
+        base: &GraphicsPipeline,
+    ) -> (Vec<ShaderStage>, Option<StageLink>, Vec<usize>) {
+        let mut created = Vec::new();
+        let (stages, stage_link) = create_graphics_stages(sources, Some(base), |index, source| {
+            created.push(index);
+            ShaderStage::new(source, false)
+        })
+        .unwrap();
+        for (index, stage) in stages.iter().enumerate() {
+            assert_eq!(stage.from_base_pipeline, !created.contains(&index));
+        }
+        (stages, stage_link, created)
+    }
+
+    #[test]
+    fn test_derivative_stages_copied() {
+        let module_code = Arc::new(graphics_module());
+        let names = ["vert", "geom", "frag"];
+        let base = create_base_pipeline(graphics_sources(&module_code, &names, &["frag"], 1));
+        assert!(base.stages.iter().all(|stage| !stage.from_base_pipeline));
+        // a copy of the module's code is still the same source
+        let module_code = Arc::new(graphics_module());
+        let (stages, stage_link, created) =
+            create_derivative_stages(graphics_sources(&module_code, &names, &["frag"], 1), &base);
+        assert_eq!(created, Vec::<usize>::new());
+        for (stage, base_stage) in stages.iter().zip(&base.stages) {
+            assert_eq!(stage.stage, base_stage.stage);
+            assert_eq!(stage.code, base_stage.code);
+        }
+        let stage_link = stage_link.unwrap();
+        assert_eq!(stage_link.producer, ExecutionModel::Geometry);
+        assert_eq!(stage_link.consumer, ExecutionModel::Fragment);
+    }
+
+    #[test]
+    fn test_derivative_specialization_changed() {
+        let module_code = Arc::new(graphics_module());
+        let names = ["vert", "geom", "frag"];
+        let base = create_base_pipeline(graphics_sources(&module_code, &names, &["vert"], 1));
+        let (_, _, created) =
+            create_derivative_stages(graphics_sources(&module_code, &names, &["vert"], 1), &base);
+        assert_eq!(created, Vec::<usize>::new());
+        let (_, stage_link, created) =
+            create_derivative_stages(graphics_sources(&module_code, &names, &["vert"], 2), &base);
+        assert_eq!(created, vec![0]);
+        assert!(stage_link.is_some());
+        // removing the specialization changes the source too
+        let (_, _, created) =
+            create_derivative_stages(graphics_sources(&module_code, &names, &[], 1), &base);
+        assert_eq!(created, vec![0]);
+    }
+
+    #[test]
+    fn test_derivative_linked_stages() {
+        let module_code = Arc::new(graphics_module());
+        let names = ["vert", "geom", "frag"];
+        let base = create_base_pipeline(graphics_sources(&module_code, &names, &[], 1));
+        // changing either stage of the linked pair recreates both, since their code is linked
+        // to each other
+        for &changed in &["geom", "frag"] {
+            let (stages, stage_link, created) = create_derivative_stages(
+                graphics_sources(&module_code, &names, &[changed], 1),
+                &base,
+            );
+            assert_eq!(created, vec![1, 2]);
+            assert_eq!(stages[0].code, base.stages[0].code);
+            assert!(stage_link.is_some());
+        }
+        // the vertex stage is linked to the fragment stage without a geometry stage, so it
+        // can't be copied to or from a pipeline with one, even though its source is the same
+        let (_, _, created) = create_derivative_stages(
+            graphics_sources(&module_code, &["vert", "frag"], &[], 1),
+            &base,
+        );
+        assert_eq!(created, vec![0, 1]);
+        let vertex_linked_base =
+            create_base_pipeline(graphics_sources(&module_code, &["vert", "frag"], &[], 1));
+        let (_, _, created) = create_derivative_stages(
+            graphics_sources(&module_code, &names, &[], 1),
+            &vertex_linked_base,
+        );
+        assert_eq!(created, vec![0, 1, 2]);
+        // without a fragment stage nothing is linked, so the vertex stage is copied, but not the
+        // geometry stage, which is linked in the base pipeline
+        let (_, stage_link, created) = create_derivative_stages(
+            graphics_sources(&module_code, &["vert", "geom"], &[], 1),
+            &base,
+        );
+        assert_eq!(created, vec![1]);
+        assert!(stage_link.is_none());
+    }
+
+    #[test]
+    fn test_dynamic_viewports_and_depth_bias() {
+        let viewport = |x| Viewport {
+            x,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let mut pipeline = create_base_pipeline(Vec::new());
+        pipeline.viewports = vec![viewport(1.0)];
+        pipeline.depth_bias = Some(DepthBias::new(1.0, 0.0, 0.0));
+        let dynamic_viewports = [viewport(2.0)];
+        let dynamic_depth_bias = DepthBias::new(2.0, 0.0, 0.0);
+        assert_eq!(pipeline.get_viewports(&dynamic_viewports), [viewport(1.0)]);
+        assert_eq!(
+            pipeline.get_depth_bias(dynamic_depth_bias),
+            Some(DepthBias::new(1.0, 0.0, 0.0))
+        );
+        pipeline.dynamic_states = vec![
+            api::VK_DYNAMIC_STATE_VIEWPORT,
+            api::VK_DYNAMIC_STATE_DEPTH_BIAS,
+        ];
+        assert_eq!(pipeline.get_viewports(&dynamic_viewports), [viewport(2.0)]);
+        assert_eq!(
+            pipeline.get_depth_bias(dynamic_depth_bias),
+            Some(dynamic_depth_bias)
+        );
+        // dynamic depth bias doesn't enable it
+        pipeline.depth_bias = None;
+        assert_eq!(pipeline.get_depth_bias(dynamic_depth_bias), None);
+    }
+
+    #[test]
+    fn test_required_subgroup_size() {
+        let compile_thread_pool = CompileThreadPool::new(1);
+        let module = OwnedHandle::<api::VkShaderModule>::new(ShaderModule::new(
+            compute_module(),
+            &compile_thread_pool,
+            false,
+        ));
+        let create_stage = |required_subgroup_size: Option<u32>| {
+            let required_subgroup_size_create_info =
+                api::VkPipelineShaderStageRequiredSubgroupSizeCreateInfoEXT {
+                    sType:
+                        api::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_REQUIRED_SUBGROUP_SIZE_CREATE_INFO_EXT,
+                    pNext: null_mut(),
+                    requiredSubgroupSize: required_subgroup_size.unwrap_or(0),
+                };
+            let create_info = api::VkPipelineShaderStageCreateInfo {
+                sType: api::VK_STRUCTURE_TYPE_PIPELINE_SHADER_STAGE_CREATE_INFO,
+                pNext: if required_subgroup_size.is_some() {
+                    &required_subgroup_size_create_info as *const _ as *const _
+                } else {
+                    null()
+                },
+                flags: 0,
+                stage: api::VK_SHADER_STAGE_COMPUTE_BIT,
+                module: module.get_handle(),
+                pName: b"main\0".as_ptr() as *const c_char,
+                pSpecializationInfo: null(),
+            };
+            ShaderStage::new(unsafe { ShaderStageSource::new(&create_info) }, false).unwrap()
+        };
+        // the empty shader would get the widest SIMD width if no size was required
+        let stage = create_stage(None);
+        assert_eq!(stage.simd_width(), MAX_SUBGROUP_SIZE);
+        let mut subgroup_size = MIN_SUBGROUP_SIZE;
+        while subgroup_size <= MAX_SUBGROUP_SIZE {
+            let mut stage = create_stage(Some(subgroup_size));
+            assert_eq!(
+                stage.simd_width_choice,
+                SimdWidth {
+                    width: subgroup_size,
+                    limit: SimdWidthLimit::RequiredSubgroupSize,
+                }
+            );
+            assert_eq!(
+                stage.compiler_config.variable_vector_length_multiplier,
+                Some(subgroup_size)
+            );
+            // replacing the code chooses the width again
+            let code = stage.code.clone();
+            stage.set_code(&code).unwrap();
+            assert_eq!(stage.simd_width(), subgroup_size);
+            subgroup_size *= 2;
+        }
+    }
+}
@@ -0,0 +1,342 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! a readable description of the decisions made when creating a pipeline
+//!
+//! the shaders' code doesn't show how the driver connects them to each other and to the
+//! application: how linking moved and removed varyings, where each varying is in the varying
+//! buffers, how each vertex attribute is fetched, and where each descriptor ends up in the
+//! flattened `DescriptorTable`. `PipelineReport` describes all of that, so ABI mismatches and
+//! linking bugs can be tracked down. when `KAZAN_DUMP_SHADERS` is set, the report is written next
+//! to the pipeline's shader dumps.
+
+use api;
+use pipeline::{GraphicsPipeline, Pipeline, ShaderStage};
+use shader_compiler::abi::varying_buffer_index;
+use shader_compiler::descriptors;
+use shader_compiler::interface::DescriptorBinding;
+use shader_compiler::simd_width::SimdWidthLimit;
+use std::fmt;
+use std::fs;
+use vertex_format::VertexFormat;
+
+/// describes a pipeline when formatted with `Display`
+pub struct PipelineReport<'a>(pub &'a Pipeline);
+
+impl<'a> fmt::Display for PipelineReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_stages(f, self.0.stages())?;
+        let uses_push_constants = match self.0 {
+            Pipeline::Graphics(pipeline) => {
+                write_stage_link(f, pipeline)?;
+                write_varyings(f, pipeline)?;
+                write_vertex_input(f, pipeline)?;
+                pipeline.uses_push_constants
+            }
+            Pipeline::Compute(pipeline) => pipeline.stage.interface.uses_push_constants,
+        };
+        write!(
+            f,
+            "{}",
+            DescriptorReport {
+                layout: self.0.layout(),
+                used_bindings: self.0.descriptor_bindings(),
+                uses_push_constants,
+            }
+        )
+    }
+}
+
+fn write_stages(f: &mut fmt::Formatter, stages: &[ShaderStage]) -> fmt::Result {
+    writeln!(f, "stages:")?;
+    for stage in stages {
+        write!(
+            f,
+            "    {:?} {:?}, SIMD width {}",
+            stage.interface.execution_model,
+            stage.interface.name,
+            stage.simd_width()
+        )?;
+        if stage.simd_width_choice.limit != SimdWidthLimit::None {
+            write!(f, ", limited by {}", stage.simd_width_choice.limit)?;
+        }
+        if let Some(shader_dump) = &stage.compiler_config.shader_dump {
+            write!(f, ", dumped as {}", shader_dump.name)?;
+        }
+        if stage.from_base_pipeline {
+            write!(f, ", copied from the base pipeline")?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+fn write_stage_link(f: &mut fmt::Formatter, pipeline: &GraphicsPipeline) -> fmt::Result {
+    let stage_link = match &pipeline.stage_link {
+        Some(stage_link) => stage_link,
+        None => return Ok(()),
+    };
+    writeln!(
+        f,
+        "linking {:?} to {:?}:",
+        stage_link.producer, stage_link.consumer
+    )?;
+    for &(old_location, new_location) in &stage_link.location_remap {
+        writeln!(f, "    location {} moved to {}", old_location, new_location)?;
+    }
+    for id in &stage_link.demoted_outputs {
+        writeln!(
+            f,
+            "    output %{} isn't read, demoted to a private variable",
+            id
+        )?;
+    }
+    for id in &stage_link.promoted_inputs {
+        writeln!(
+            f,
+            "    input %{} replaced by the constant the producer writes",
+            id
+        )?;
+    }
+    if stage_link.location_remap.is_empty()
+        && stage_link.demoted_outputs.is_empty()
+        && stage_link.promoted_inputs.is_empty()
+    {
+        writeln!(f, "    unchanged")?;
+    }
+    Ok(())
+}
+
+fn write_varyings(f: &mut fmt::Formatter, pipeline: &GraphicsPipeline) -> fmt::Result {
+    // there's no fragment shader
+    if pipeline.fragment_shader_tests.is_none() {
+        return Ok(());
+    }
+    let layout = &pipeline.varying_layout;
+    writeln!(
+        f,
+        "varyings: {} words per vertex, {} words per group of {} fragments, {:?} topology:",
+        layout.words_per_vertex,
+        layout.words_per_group(),
+        layout.simd_width,
+        pipeline.topology
+    )?;
+    for words in &layout.words {
+        let end = words.start + words.count;
+        writeln!(
+            f,
+            "    vertex words {}..{}: {:?}, group words {}..{}",
+            words.start,
+            end,
+            words.interpolation,
+            varying_buffer_index(words.start, 0, layout.simd_width),
+            varying_buffer_index(end, 0, layout.simd_width)
+        )?;
+    }
+    Ok(())
+}
+
+fn write_vertex_input(f: &mut fmt::Formatter, pipeline: &GraphicsPipeline) -> fmt::Result {
+    writeln!(f, "vertex attributes:")?;
+    for attribute in &pipeline.vertex_attributes {
+        write!(
+            f,
+            "    location {}: format {}",
+            attribute.location, attribute.format
+        )?;
+        match VertexFormat::from(attribute.format) {
+            Some(format) => write!(
+                f,
+                " ({} {:?} components in {} bytes)",
+                format.component_count, format.numeric_format, format.element_size
+            )?,
+            None => write!(f, " (can't be decoded)")?,
+        }
+        match pipeline
+            .vertex_bindings
+            .iter()
+            .find(|binding| binding.binding == attribute.binding)
+        {
+            Some(binding) => writeln!(
+                f,
+                ", read from binding {} at offset {} + {} * {}",
+                binding.binding,
+                attribute.offset,
+                binding.stride,
+                match binding.inputRate {
+                    api::VK_VERTEX_INPUT_RATE_VERTEX => "vertex index",
+                    api::VK_VERTEX_INPUT_RATE_INSTANCE => "instance index",
+                    _ => "invalid input rate",
+                }
+            )?,
+            None => writeln!(f, ", binding {} isn't described", attribute.binding)?,
+        }
+    }
+    Ok(())
+}
+
+/// the descriptors of a `DescriptorTable`: the bound descriptor sets are copied one after
+/// another, so the offsets shown for each set assume every set before it is bound
+struct DescriptorReport<'a> {
+    layout: &'a descriptors::PipelineLayout,
+    /// sorted
+    used_bindings: &'a [DescriptorBinding],
+    uses_push_constants: bool,
+}
+
+impl<'a> fmt::Display for DescriptorReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let is_used = |descriptor_set, binding| {
+            self.used_bindings
+                .binary_search(&DescriptorBinding {
+                    descriptor_set,
+                    binding,
+                })
+                .is_ok()
+        };
+        writeln!(f, "descriptor sets:")?;
+        let mut set_offset = 0;
+        for (descriptor_set, set_layout) in self.layout.descriptor_sets.iter().enumerate() {
+            let descriptor_set = descriptor_set as u32;
+            writeln!(
+                f,
+                "    set {}: {} descriptors at table index {}",
+                descriptor_set, set_layout.descriptor_count, set_offset
+            )?;
+            for (binding, set_layout_binding) in set_layout.bindings.iter().enumerate() {
+                let binding = binding as u32;
+                let set_layout_binding = match set_layout_binding {
+                    Some(set_layout_binding) => set_layout_binding,
+                    None => continue,
+                };
+                writeln!(
+                    f,
+                    "        binding {}: {} {:?} at table index {}{}",
+                    binding,
+                    set_layout_binding.descriptor_count,
+                    set_layout_binding.descriptor_type,
+                    set_offset + set_layout_binding.descriptor_index,
+                    if is_used(descriptor_set, binding) {
+                        ""
+                    } else {
+                        ", unused"
+                    }
+                )?;
+            }
+            set_offset += set_layout.descriptor_count;
+        }
+        for used_binding in self.used_bindings {
+            let in_layout = self
+                .layout
+                .descriptor_sets
+                .get(used_binding.descriptor_set as usize)
+                .and_then(|set_layout| set_layout.get_binding(used_binding.binding))
+                .is_some();
+            if !in_layout {
+                writeln!(
+                    f,
+                    "    set {} binding {} is used but isn't in the pipeline layout",
+                    used_binding.descriptor_set, used_binding.binding
+                )?;
+            }
+        }
+        writeln!(
+            f,
+            "push constants: {} bytes{}",
+            self.layout.push_constants_size,
+            if self.uses_push_constants {
+                ""
+            } else {
+                ", unused"
+            }
+        )
+    }
+}
+
+/// write the report next to the shader dumps of the pipeline's first stage, if
+/// `KAZAN_DUMP_SHADERS` is set
+pub fn dump_pipeline_report(pipeline: &Pipeline) {
+    let shader_dump = match &pipeline.stages()[0].compiler_config.shader_dump {
+        Some(shader_dump) => shader_dump,
+        None => return,
+    };
+    let path = shader_dump
+        .directory
+        .join(format!("{}.pipeline.txt", shader_dump.name));
+    if let Err(error) = fs::create_dir_all(&shader_dump.directory)
+        .and_then(|_| fs::write(&path, PipelineReport(pipeline).to_string()))
+    {
+        eprintln!("kazan: can't write {}: {}", path.display(), error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shader_compiler::descriptors::{
+        DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorType,
+    };
+
+    #[test]
+    fn test_descriptor_report() {
+        let set_layout = |bindings: Vec<Option<(DescriptorType, u32)>>| {
+            let mut descriptor_count = 0;
+            let bindings = bindings
+                .into_iter()
+                .map(|binding| {
+                    binding.map(|(descriptor_type, count)| {
+                        let descriptor_index = descriptor_count;
+                        descriptor_count += count;
+                        DescriptorSetLayoutBinding {
+                            descriptor_type,
+                            descriptor_index,
+                            descriptor_count: count,
+                        }
+                    })
+                })
+                .collect();
+            DescriptorSetLayout {
+                bindings,
+                descriptor_count,
+            }
+        };
+        let layout = descriptors::PipelineLayout {
+            descriptor_sets: vec![
+                set_layout(vec![Some((DescriptorType::UniformBuffer, 1))]),
+                set_layout(vec![
+                    Some((DescriptorType::SampledImage, 3)),
+                    None,
+                    Some((DescriptorType::StorageBufferDynamic, 2)),
+                ]),
+            ],
+            push_constants_size: 16,
+        };
+        let used_bindings = [
+            DescriptorBinding {
+                descriptor_set: 1,
+                binding: 2,
+            },
+            DescriptorBinding {
+                descriptor_set: 2,
+                binding: 0,
+            },
+        ];
+        let report = DescriptorReport {
+            layout: &layout,
+            used_bindings: &used_bindings,
+            uses_push_constants: true,
+        };
+        assert_eq!(
+            report.to_string(),
+            "descriptor sets:
+    set 0: 1 descriptors at table index 0
+        binding 0: 1 UniformBuffer at table index 0, unused
+    set 1: 5 descriptors at table index 1
+        binding 0: 3 SampledImage at table index 1, unused
+        binding 2: 2 StorageBufferDynamic at table index 4
+    set 2 binding 0 is used but isn't in the pipeline layout
+push constants: 16 bytes
+"
+        );
+    }
+}
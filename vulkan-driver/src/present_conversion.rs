@@ -0,0 +1,375 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! converting swapchain images to the surface's pixels when presenting
+//!
+//! X11 windows only have 8-bit sRGB channels, so 10-bit and half-float swapchain images, and the
+//! color spaces from `VK_EXT_swapchain_colorspace`, are converted by the present blit instead of
+//! being passed through. HDR values are converted to linear BT.709 relative to SDR white, then
+//! tone mapped: values below `TONE_MAP_KNEE` are unchanged, so SDR content looks the same as in an
+//! sRGB swapchain, and brighter values are compressed so highlights don't clip.
+
+use api;
+use format::{linear_to_srgb, srgb_to_linear};
+use vertex_format::{self, VertexFormat, LANES};
+
+/// the formats that swapchain images can have, with the byte order of their pixels
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SwapchainPixelFormat {
+    R8G8B8A8,
+    B8G8R8A8,
+    /// `VK_FORMAT_A2R10G10B10_UNORM_PACK32`
+    A2R10G10B10,
+    /// `VK_FORMAT_A2B10G10R10_UNORM_PACK32`
+    A2B10G10R10,
+    R16G16B16A16Float,
+}
+
+impl SwapchainPixelFormat {
+    pub fn from(format: api::VkFormat) -> Option<Self> {
+        match format {
+            api::VK_FORMAT_R8G8B8A8_UNORM | api::VK_FORMAT_R8G8B8A8_SRGB => {
+                Some(SwapchainPixelFormat::R8G8B8A8)
+            }
+            api::VK_FORMAT_B8G8R8A8_UNORM | api::VK_FORMAT_B8G8R8A8_SRGB => {
+                Some(SwapchainPixelFormat::B8G8R8A8)
+            }
+            api::VK_FORMAT_A2R10G10B10_UNORM_PACK32 => Some(SwapchainPixelFormat::A2R10G10B10),
+            api::VK_FORMAT_A2B10G10R10_UNORM_PACK32 => Some(SwapchainPixelFormat::A2B10G10R10),
+            api::VK_FORMAT_R16G16B16A16_SFLOAT => Some(SwapchainPixelFormat::R16G16B16A16Float),
+            _ => None,
+        }
+    }
+    pub fn get_pixel_size(self) -> usize {
+        match self {
+            SwapchainPixelFormat::R16G16B16A16Float => 8,
+            _ => 4,
+        }
+    }
+    /// the format that decodes pixels to their channels as stored, before decoding the color
+    /// space
+    fn get_vertex_format(self) -> VertexFormat {
+        let format = match self {
+            SwapchainPixelFormat::R8G8B8A8 => api::VK_FORMAT_R8G8B8A8_UNORM,
+            SwapchainPixelFormat::B8G8R8A8 => api::VK_FORMAT_B8G8R8A8_UNORM,
+            SwapchainPixelFormat::A2R10G10B10 => api::VK_FORMAT_A2R10G10B10_UNORM_PACK32,
+            SwapchainPixelFormat::A2B10G10R10 => api::VK_FORMAT_A2B10G10R10_UNORM_PACK32,
+            SwapchainPixelFormat::R16G16B16A16Float => api::VK_FORMAT_R16G16B16A16_SFLOAT,
+        };
+        VertexFormat::from(format).unwrap()
+    }
+}
+
+/// the color spaces that presented images can be in
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum SwapchainColorSpace {
+    SrgbNonlinear,
+    /// scRGB: linear BT.709, where 1.0 is SDR white and values can be outside of [0, 1]
+    ExtendedSrgbLinear,
+    ExtendedSrgbNonlinear,
+    /// HDR10: BT.2020 encoded with the SMPTE ST 2084 (PQ) transfer function
+    Hdr10St2084,
+    Bt2020Linear,
+    /// the values are sent to the surface unchanged
+    PassThrough,
+}
+
+impl SwapchainColorSpace {
+    pub fn from(color_space: api::VkColorSpaceKHR) -> Option<Self> {
+        match color_space {
+            api::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR => Some(SwapchainColorSpace::SrgbNonlinear),
+            api::VK_COLOR_SPACE_EXTENDED_SRGB_LINEAR_EXT => {
+                Some(SwapchainColorSpace::ExtendedSrgbLinear)
+            }
+            api::VK_COLOR_SPACE_EXTENDED_SRGB_NONLINEAR_EXT => {
+                Some(SwapchainColorSpace::ExtendedSrgbNonlinear)
+            }
+            api::VK_COLOR_SPACE_HDR10_ST2084_EXT => Some(SwapchainColorSpace::Hdr10St2084),
+            api::VK_COLOR_SPACE_BT2020_LINEAR_EXT => Some(SwapchainColorSpace::Bt2020Linear),
+            api::VK_COLOR_SPACE_PASS_THROUGH_EXT => Some(SwapchainColorSpace::PassThrough),
+            _ => None,
+        }
+    }
+    /// decode to linear BT.709 relative to SDR white. returns `None` for color spaces that are
+    /// already encoded for the surface
+    fn to_linear_bt709(self, rgb: [f32; 3]) -> Option<[f32; 3]> {
+        match self {
+            SwapchainColorSpace::SrgbNonlinear | SwapchainColorSpace::PassThrough => None,
+            SwapchainColorSpace::ExtendedSrgbLinear => Some(rgb),
+            SwapchainColorSpace::ExtendedSrgbNonlinear => {
+                Some(map_rgb(rgb, extended_srgb_to_linear))
+            }
+            SwapchainColorSpace::Hdr10St2084 => Some(bt2020_to_bt709(map_rgb(rgb, |v| {
+                pq_to_nits(v) / SDR_WHITE_NITS
+            }))),
+            SwapchainColorSpace::Bt2020Linear => Some(bt2020_to_bt709(rgb)),
+        }
+    }
+}
+
+/// the swapchain formats that are converted when presenting; reported along with the surface's
+/// native 8-bit formats. the color spaces other than sRGB need `VK_EXT_swapchain_colorspace`
+pub const CONVERTED_SURFACE_FORMATS: &[api::VkSurfaceFormatKHR] = &[
+    api::VkSurfaceFormatKHR {
+        format: api::VK_FORMAT_A2B10G10R10_UNORM_PACK32,
+        colorSpace: api::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
+    },
+    api::VkSurfaceFormatKHR {
+        format: api::VK_FORMAT_A2R10G10B10_UNORM_PACK32,
+        colorSpace: api::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
+    },
+    api::VkSurfaceFormatKHR {
+        format: api::VK_FORMAT_R16G16B16A16_SFLOAT,
+        colorSpace: api::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
+    },
+    api::VkSurfaceFormatKHR {
+        format: api::VK_FORMAT_R16G16B16A16_SFLOAT,
+        colorSpace: api::VK_COLOR_SPACE_EXTENDED_SRGB_LINEAR_EXT,
+    },
+    api::VkSurfaceFormatKHR {
+        format: api::VK_FORMAT_A2B10G10R10_UNORM_PACK32,
+        colorSpace: api::VK_COLOR_SPACE_HDR10_ST2084_EXT,
+    },
+    api::VkSurfaceFormatKHR {
+        format: api::VK_FORMAT_A2R10G10B10_UNORM_PACK32,
+        colorSpace: api::VK_COLOR_SPACE_HDR10_ST2084_EXT,
+    },
+    api::VkSurfaceFormatKHR {
+        format: api::VK_FORMAT_R16G16B16A16_SFLOAT,
+        colorSpace: api::VK_COLOR_SPACE_BT2020_LINEAR_EXT,
+    },
+];
+
+/// the brightness of SDR white in HDR10 content, from ITU-R BT.2408
+const SDR_WHITE_NITS: f32 = 203.0;
+
+/// tone mapping leaves values below this unchanged
+const TONE_MAP_KNEE: f32 = 0.75;
+
+fn map_rgb<F: Fn(f32) -> f32>(rgb: [f32; 3], f: F) -> [f32; 3] {
+    [f(rgb[0]), f(rgb[1]), f(rgb[2])]
+}
+
+/// the sRGB EOTF, extended to negative values by symmetry, as scRGB uses it
+fn extended_srgb_to_linear(value: f32) -> f32 {
+    let linear = srgb_to_linear(value.abs());
+    if value < 0.0 {
+        -linear
+    } else {
+        linear
+    }
+}
+
+/// the SMPTE ST 2084 EOTF, returning nits
+fn pq_to_nits(value: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+    let power = value.max(0.0).powf(1.0 / M2);
+    10000.0 * ((power - C1).max(0.0) / (C2 - C3 * power)).powf(1.0 / M1)
+}
+
+fn bt2020_to_bt709(rgb: [f32; 3]) -> [f32; 3] {
+    const MATRIX: [[f32; 3]; 3] = [
+        [1.660_491, -0.587_641, -0.072_850],
+        [-0.124_55, 1.132_9, -0.008_349],
+        [-0.018_151, -0.100_579, 1.118_73],
+    ];
+    let row = |row: &[f32; 3]| row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+    [row(&MATRIX[0]), row(&MATRIX[1]), row(&MATRIX[2])]
+}
+
+/// compress values above `TONE_MAP_KNEE` into [`TONE_MAP_KNEE`, 1), scaling all the channels by
+/// the same amount so the hue doesn't change
+fn tone_map(rgb: [f32; 3]) -> [f32; 3] {
+    let rgb = map_rgb(rgb, |v| v.max(0.0));
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    if max <= TONE_MAP_KNEE {
+        return rgb;
+    }
+    let excess = max - TONE_MAP_KNEE;
+    let headroom = 1.0 - TONE_MAP_KNEE;
+    let mapped = TONE_MAP_KNEE + headroom * excess / (excess + headroom);
+    map_rgb(rgb, |v| v * mapped / max)
+}
+
+fn quantize(value: f32) -> u8 {
+    if value <= 0.0 {
+        0
+    } else if value >= 1.0 {
+        0xFF
+    } else {
+        (value * 255.0).round() as u8
+    }
+}
+
+/// converts swapchain pixels to the surface's 8-bit sRGB pixels
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct PresentConversion {
+    pub source_format: SwapchainPixelFormat,
+    pub color_space: SwapchainColorSpace,
+    /// the surface stores blue in the first byte
+    pub destination_is_bgr: bool,
+}
+
+#[allow(dead_code)]
+impl PresentConversion {
+    /// returns `None` for unsupported formats and color spaces
+    pub fn new(surface_format: api::VkSurfaceFormatKHR, destination_is_bgr: bool) -> Option<Self> {
+        Some(PresentConversion {
+            source_format: SwapchainPixelFormat::from(surface_format.format)?,
+            color_space: SwapchainColorSpace::from(surface_format.colorSpace)?,
+            destination_is_bgr,
+        })
+    }
+    /// true if pixels can be copied without converting
+    pub fn is_copy(&self) -> bool {
+        let source_is_bgr = match self.source_format {
+            SwapchainPixelFormat::R8G8B8A8 => false,
+            SwapchainPixelFormat::B8G8R8A8 => true,
+            _ => return false,
+        };
+        match self.color_space {
+            SwapchainColorSpace::SrgbNonlinear | SwapchainColorSpace::PassThrough => {
+                source_is_bgr == self.destination_is_bgr
+            }
+            _ => false,
+        }
+    }
+    /// convert a decoded pixel to its RGB bytes in the surface's order
+    fn convert_decoded(&self, decoded: [u32; 4]) -> [u8; 3] {
+        let rgb = [
+            f32::from_bits(decoded[0]),
+            f32::from_bits(decoded[1]),
+            f32::from_bits(decoded[2]),
+        ];
+        let rgb = match self.color_space.to_linear_bt709(rgb) {
+            Some(linear) => map_rgb(tone_map(linear), linear_to_srgb),
+            None => rgb,
+        };
+        let [r, g, b] = rgb;
+        if self.destination_is_bgr {
+            [quantize(b), quantize(g), quantize(r)]
+        } else {
+            [quantize(r), quantize(g), quantize(b)]
+        }
+    }
+    /// convert a pixel to its RGB bytes in the surface's order
+    pub fn convert_pixel(&self, source: &[u8]) -> [u8; 3] {
+        let mut decoded = [[0; 4]];
+        vertex_format::decode(
+            &self.source_format.get_vertex_format(),
+            source,
+            self.source_format.get_pixel_size(),
+            &mut decoded,
+        );
+        self.convert_decoded(decoded[0])
+    }
+    /// convert a row of pixels. surface pixels are 3 or 4 bytes; the fourth byte is set to opaque
+    /// alpha, since surfaces only support `VK_COMPOSITE_ALPHA_OPAQUE_BIT_KHR`
+    pub fn convert_row(
+        &self,
+        source: &[u8],
+        destination: &mut [u8],
+        destination_pixel_size: usize,
+    ) {
+        if self.is_copy() && destination_pixel_size == 4 {
+            destination.copy_from_slice(source);
+            return;
+        }
+        let vertex_format = self.source_format.get_vertex_format();
+        let source_pixel_size = self.source_format.get_pixel_size();
+        let mut decoded = [[0; 4]; LANES];
+        // decode a chunk of pixels at once, so the decoding is vectorized
+        for (source, destination) in source
+            .chunks(source_pixel_size * LANES)
+            .zip(destination.chunks_mut(destination_pixel_size * LANES))
+        {
+            let decoded = &mut decoded[..source.len() / source_pixel_size];
+            vertex_format::decode(&vertex_format, source, source_pixel_size, decoded);
+            for (decoded, destination) in decoded
+                .iter()
+                .zip(destination.chunks_mut(destination_pixel_size))
+            {
+                destination[..3].copy_from_slice(&self.convert_decoded(*decoded));
+                if destination_pixel_size == 4 {
+                    destination[3] = 0xFF;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn le_bytes(value: u32) -> [u8; 4] {
+        [
+            value as u8,
+            (value >> 8) as u8,
+            (value >> 16) as u8,
+            (value >> 24) as u8,
+        ]
+    }
+
+    fn conversion(format: api::VkFormat, color_space: api::VkColorSpaceKHR) -> PresentConversion {
+        PresentConversion::new(
+            api::VkSurfaceFormatKHR {
+                format,
+                colorSpace: color_space,
+            },
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sdr_is_unchanged() {
+        let unorm = conversion(
+            api::VK_FORMAT_B8G8R8A8_UNORM,
+            api::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
+        );
+        assert!(!unorm.is_copy());
+        assert_eq!(unorm.convert_pixel(&[1, 2, 3, 4]), [3, 2, 1]);
+        let packed = conversion(
+            api::VK_FORMAT_A2B10G10R10_UNORM_PACK32,
+            api::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
+        );
+        // red is 1023, green is 0, and blue is 512
+        let pixel = le_bytes(1023 | 512 << 20);
+        assert_eq!(packed.convert_pixel(&pixel), [255, 0, 128]);
+        // scRGB values at or below the knee only get sRGB encoded
+        let linear = conversion(
+            api::VK_FORMAT_R16G16B16A16_SFLOAT,
+            api::VK_COLOR_SPACE_EXTENDED_SRGB_LINEAR_EXT,
+        );
+        let half = [0x00, 0x3A, 0x00, 0x00, 0x00, 0x38, 0x00, 0x3C];
+        assert_eq!(linear.convert_pixel(&half), [225, 0, 188]);
+    }
+
+    #[test]
+    fn test_hdr_is_tone_mapped() {
+        let linear = conversion(
+            api::VK_FORMAT_R16G16B16A16_SFLOAT,
+            api::VK_COLOR_SPACE_EXTENDED_SRGB_LINEAR_EXT,
+        );
+        // 4.0 and 2.0 are compressed below 1.0 without clipping, and keep their ratio
+        let pixel = linear.convert_pixel(&[0x00, 0x44, 0x00, 0x40, 0x00, 0x00, 0x00, 0x3C]);
+        assert!(pixel[0] < 255 && pixel[0] > pixel[1] && pixel[2] == 0);
+        let hdr10 = conversion(
+            api::VK_FORMAT_A2R10G10B10_UNORM_PACK32,
+            api::VK_COLOR_SPACE_HDR10_ST2084_EXT,
+        );
+        // PQ 0.58 is about 203 nits, which is SDR white
+        let grey = 593u32;
+        let pixel = le_bytes(grey | grey << 10 | grey << 20);
+        let white = hdr10.convert_pixel(&pixel);
+        assert!(white.iter().all(|&v| v > 230) && white != [255; 3]);
+        let mut row = [0; 6];
+        hdr10.convert_row(&[0; 8], &mut row, 3);
+        assert_eq!(row, [0; 6]);
+    }
+}
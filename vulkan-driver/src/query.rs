@@ -0,0 +1,205 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! query pools for occlusion and timestamp queries. pipeline statistics queries are optional and
+//! `pipelineStatisticsQuery` isn't supported, so they can't be created
+//!
+//! draws don't rasterize anything yet (only the null driver runs them, and it doesn't write any
+//! samples), so occlusion queries always count 0 samples. `occlusionQueryPrecise` isn't
+//! supported, but a count of 0 is exact, so `VK_QUERY_CONTROL_PRECISE_BIT` makes no difference.
+//!
+//! timestamps are in nanoseconds since the device was created, so `timestampPeriod` is 1. a
+//! timestamp is written once every command before it has finished the stage passed to
+//! `vkCmdWriteTimestamp`. each command finishes before the next one starts, so that's when the
+//! timestamp command is reached, whatever the stage is; validation still checks that the stage
+//! is a single stage the device supports. the time spent in each stage of a pass is traced
+//! separately instead (see `trace`).
+
+use api;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
+
+/// the value of a timestamp written at `time`
+pub fn get_timestamp(epoch: Instant, time: Instant) -> u64 {
+    let duration = time.duration_since(epoch);
+    duration.as_secs() * 1_000_000_000 + u64::from(duration.subsec_nanos())
+}
+
+struct Query {
+    value: AtomicU64,
+    available: AtomicBool,
+}
+
+pub struct QueryPool {
+    queries: Vec<Query>,
+}
+
+impl QueryPool {
+    pub fn new(create_info: &api::VkQueryPoolCreateInfo) -> Self {
+        assert_eq!(create_info.flags, 0);
+        match create_info.queryType {
+            api::VK_QUERY_TYPE_OCCLUSION | api::VK_QUERY_TYPE_TIMESTAMP => {}
+            api::VK_QUERY_TYPE_PIPELINE_STATISTICS => {
+                panic!("pipeline statistics queries need pipelineStatisticsQuery")
+            }
+            query_type => panic!("invalid query type: {}", query_type),
+        }
+        QueryPool {
+            queries: (0..create_info.queryCount)
+                .map(|_| Query {
+                    value: AtomicU64::new(0),
+                    available: AtomicBool::new(false),
+                })
+                .collect(),
+        }
+    }
+    pub fn reset(&self, first_query: u32, query_count: u32) {
+        let end = (first_query + query_count) as usize;
+        for query in &self.queries[first_query as usize..end] {
+            query.available.store(false, Ordering::Relaxed);
+        }
+    }
+    /// start counting the samples that pass for an occlusion query
+    pub fn begin_query(&self, query: u32) {
+        self.queries[query as usize]
+            .value
+            .store(0, Ordering::Relaxed);
+    }
+    /// finish an occlusion query; nothing is rasterized yet, so no samples were counted
+    pub fn end_query(&self, query: u32) {
+        self.queries[query as usize]
+            .available
+            .store(true, Ordering::Release);
+    }
+    pub fn write_timestamp(&self, query: u32, timestamp: u64) {
+        let query = &self.queries[query as usize];
+        query.value.store(timestamp, Ordering::Relaxed);
+        query.available.store(true, Ordering::Release);
+    }
+    /// write the results of `query_count` queries to `data`, `stride` bytes apart, as done by
+    /// `vkGetQueryPoolResults` and `vkCmdCopyQueryPoolResults`. returns `VK_NOT_READY` if any of
+    /// the queries aren't available. submissions finish before `vkQueueSubmit` returns, so
+    /// `VK_QUERY_RESULT_WAIT_BIT` can't make a query available and is ignored
+    pub unsafe fn write_results(
+        &self,
+        first_query: u32,
+        query_count: u32,
+        data: *mut u8,
+        stride: usize,
+        flags: api::VkQueryResultFlags,
+    ) -> api::VkResult {
+        let mut retval = api::VK_SUCCESS;
+        for index in 0..query_count as usize {
+            let query = &self.queries[first_query as usize + index];
+            let available = query.available.load(Ordering::Acquire);
+            let values = [
+                if available || flags & api::VK_QUERY_RESULT_PARTIAL_BIT != 0 {
+                    Some(query.value.load(Ordering::Relaxed))
+                } else {
+                    None
+                },
+                if flags & api::VK_QUERY_RESULT_WITH_AVAILABILITY_BIT != 0 {
+                    Some(available as u64)
+                } else {
+                    None
+                },
+            ];
+            if !available {
+                retval = api::VK_NOT_READY;
+            }
+            let result = data.add(index * stride);
+            for (value_index, value) in values.iter().enumerate() {
+                let value = match *value {
+                    Some(value) => value,
+                    None => continue,
+                };
+                if flags & api::VK_QUERY_RESULT_64_BIT != 0 {
+                    (result as *mut u64).add(value_index).write_unaligned(value);
+                } else {
+                    (result as *mut u32)
+                        .add(value_index)
+                        .write_unaligned(value as u32);
+                }
+            }
+        }
+        retval
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr::null;
+
+    fn create_query_pool(query_type: api::VkQueryType, query_count: u32) -> QueryPool {
+        QueryPool::new(&api::VkQueryPoolCreateInfo {
+            sType: api::VK_STRUCTURE_TYPE_QUERY_POOL_CREATE_INFO,
+            pNext: null(),
+            flags: 0,
+            queryType: query_type,
+            queryCount: query_count,
+            pipelineStatistics: 0,
+        })
+    }
+
+    fn query_pool(query_count: u32) -> QueryPool {
+        create_query_pool(api::VK_QUERY_TYPE_TIMESTAMP, query_count)
+    }
+
+    #[test]
+    #[should_panic(expected = "pipelineStatisticsQuery")]
+    fn test_pipeline_statistics_query() {
+        create_query_pool(api::VK_QUERY_TYPE_PIPELINE_STATISTICS, 1);
+    }
+
+    #[test]
+    fn test_occlusion_results() {
+        let query_pool = create_query_pool(api::VK_QUERY_TYPE_OCCLUSION, 2);
+        query_pool.begin_query(0);
+        query_pool.end_query(0);
+        query_pool.begin_query(1);
+        let mut results = [!0u32; 4];
+        let result = unsafe {
+            query_pool.write_results(
+                0,
+                2,
+                results.as_mut_ptr() as *mut u8,
+                8,
+                api::VK_QUERY_RESULT_WITH_AVAILABILITY_BIT,
+            )
+        };
+        assert_eq!(result, api::VK_NOT_READY);
+        assert_eq!(results, [0, 1, !0, 0]);
+    }
+
+    #[test]
+    fn test_timestamp_results() {
+        let query_pool = query_pool(3);
+        query_pool.write_timestamp(0, 5);
+        query_pool.write_timestamp(2, 1 << 40);
+        let mut results = [!0u64; 6];
+        let result = unsafe {
+            query_pool.write_results(
+                0,
+                3,
+                results.as_mut_ptr() as *mut u8,
+                16,
+                api::VK_QUERY_RESULT_64_BIT | api::VK_QUERY_RESULT_WITH_AVAILABILITY_BIT,
+            )
+        };
+        assert_eq!(result, api::VK_NOT_READY);
+        assert_eq!(results, [5, 1, !0, 0, 1 << 40, 1]);
+        query_pool.reset(0, 1);
+        let mut results = [!0u32; 2];
+        let result =
+            unsafe { query_pool.write_results(1, 2, results.as_mut_ptr() as *mut u8, 4, 0) };
+        assert_eq!(result, api::VK_NOT_READY);
+        // truncated to 32 bits
+        assert_eq!(results, [!0, 0]);
+        query_pool.write_timestamp(1, 7);
+        let result =
+            unsafe { query_pool.write_results(1, 2, results.as_mut_ptr() as *mut u8, 4, 0) };
+        assert_eq!(result, api::VK_SUCCESS);
+        assert_eq!(results, [7, 0]);
+    }
+}
@@ -0,0 +1,867 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! render passes. `vkCreateRenderPass` and `vkCreateRenderPass2KHR` both produce a `RenderPass`
+//! in the form used by `VK_KHR_create_renderpass2`, so the executor only has one form to handle
+
+use api;
+use api_impl::parse_next_chain_const;
+use handle::SharedHandle;
+use std::ptr::null;
+use std::slice;
+
+unsafe fn make_slice<'a, T>(pointer: *const T, count: u32) -> &'a [T] {
+    if count == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(pointer, count as usize)
+    }
+}
+
+/// how the samples of a multisample depth/stencil attachment are combined when it's resolved
+///
+/// `VK_KHR_depth_stencil_resolve` isn't advertised until subpasses are run, so the resolve
+/// attachment is only parsed and checked for now
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ResolveMode {
+    SampleZero,
+    Average,
+    Min,
+    Max,
+}
+
+impl ResolveMode {
+    /// returns `None` for `VK_RESOLVE_MODE_NONE_KHR`
+    pub fn from_api(mode: api::VkResolveModeFlagBitsKHR) -> Option<Self> {
+        match mode {
+            api::VK_RESOLVE_MODE_NONE_KHR => None,
+            api::VK_RESOLVE_MODE_SAMPLE_ZERO_BIT_KHR => Some(ResolveMode::SampleZero),
+            api::VK_RESOLVE_MODE_AVERAGE_BIT_KHR => Some(ResolveMode::Average),
+            api::VK_RESOLVE_MODE_MIN_BIT_KHR => Some(ResolveMode::Min),
+            api::VK_RESOLVE_MODE_MAX_BIT_KHR => Some(ResolveMode::Max),
+            _ => panic!("invalid resolve mode: {:#X}", mode),
+        }
+    }
+    #[allow(dead_code)]
+    pub fn resolve_depth(self, samples: &[f32]) -> f32 {
+        assert!(!samples.is_empty());
+        match self {
+            ResolveMode::SampleZero => samples[0],
+            ResolveMode::Average => samples.iter().sum::<f32>() / samples.len() as f32,
+            ResolveMode::Min => samples.iter().cloned().fold(samples[0], f32::min),
+            ResolveMode::Max => samples.iter().cloned().fold(samples[0], f32::max),
+        }
+    }
+    #[allow(dead_code)]
+    pub fn resolve_stencil(self, samples: &[u8]) -> u8 {
+        assert!(!samples.is_empty());
+        match self {
+            ResolveMode::SampleZero => samples[0],
+            ResolveMode::Average => panic!("stencil can't be resolved by averaging"),
+            ResolveMode::Min => *samples.iter().min().unwrap(),
+            ResolveMode::Max => *samples.iter().max().unwrap(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct Attachment {
+    pub flags: api::VkAttachmentDescriptionFlags,
+    pub format: api::VkFormat,
+    pub samples: api::VkSampleCountFlagBits,
+    pub load_op: api::VkAttachmentLoadOp,
+    pub store_op: api::VkAttachmentStoreOp,
+    pub stencil_load_op: api::VkAttachmentLoadOp,
+    pub stencil_store_op: api::VkAttachmentStoreOp,
+    pub initial_layout: api::VkImageLayout,
+    pub final_layout: api::VkImageLayout,
+    /// the first and last subpasses that use or preserve the attachment, or `None` if no
+    /// subpass does. the attachment's contents are loaded in the first subpass and have to be
+    /// kept through the last one, even by subpasses that only preserve it. they can be
+    /// discarded after the last one unless they're stored
+    pub used_subpasses: Option<(u32, u32)>,
+}
+
+macro_rules! attachment_from_description {
+    ($description:expr) => {{
+        let description = $description;
+        Attachment {
+            flags: description.flags,
+            format: description.format,
+            samples: description.samples,
+            load_op: description.loadOp,
+            store_op: description.storeOp,
+            stencil_load_op: description.stencilLoadOp,
+            stencil_store_op: description.stencilStoreOp,
+            initial_layout: description.initialLayout,
+            final_layout: description.finalLayout,
+            used_subpasses: None,
+        }
+    }};
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AttachmentReference {
+    pub attachment: u32,
+    pub layout: api::VkImageLayout,
+    /// only used by input attachments; 0 means every aspect of the attachment's format
+    pub aspect_mask: api::VkImageAspectFlags,
+}
+
+impl AttachmentReference {
+    /// returns `None` for `VK_ATTACHMENT_UNUSED`
+    fn new(
+        attachment: u32,
+        layout: api::VkImageLayout,
+        aspect_mask: api::VkImageAspectFlags,
+    ) -> Option<Self> {
+        if attachment == api::VK_ATTACHMENT_UNUSED as u32 {
+            None
+        } else {
+            Some(AttachmentReference {
+                attachment,
+                layout,
+                aspect_mask,
+            })
+        }
+    }
+    fn from_api(reference: &api::VkAttachmentReference) -> Option<Self> {
+        Self::new(reference.attachment, reference.layout, 0)
+    }
+    unsafe fn from_api2(reference: &api::VkAttachmentReference2KHR) -> Option<Self> {
+        parse_next_chain_const!{
+            reference as *const api::VkAttachmentReference2KHR,
+            root = api::VK_STRUCTURE_TYPE_ATTACHMENT_REFERENCE_2_KHR,
+        }
+        Self::new(reference.attachment, reference.layout, reference.aspectMask)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DepthStencilResolve {
+    pub attachment: AttachmentReference,
+    /// `None` leaves the depth aspect of `attachment` unchanged
+    pub depth_mode: Option<ResolveMode>,
+    /// `None` leaves the stencil aspect of `attachment` unchanged
+    pub stencil_mode: Option<ResolveMode>,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct Subpass {
+    pub view_mask: u32,
+    pub input_attachments: Vec<Option<AttachmentReference>>,
+    pub color_attachments: Vec<Option<AttachmentReference>>,
+    /// either empty or has an entry for each color attachment
+    pub resolve_attachments: Vec<Option<AttachmentReference>>,
+    pub depth_stencil_attachment: Option<AttachmentReference>,
+    pub depth_stencil_resolve: Option<DepthStencilResolve>,
+    pub preserve_attachments: Vec<u32>,
+    /// there's a dependency from this subpass to itself, so draws can read what earlier draws in
+    /// the subpass wrote to `feedback_attachments` through input attachments. the executor has
+    /// to finish the attachment writes of earlier draws before running a later draw's fragment
+    /// shader instead of reordering the draws
+    pub has_self_dependency: bool,
+    /// attachments that are both input attachments and color or depth/stencil attachments
+    pub feedback_attachments: Vec<u32>,
+}
+
+impl Subpass {
+    /// every attachment the subpass reads or writes, including resolve attachments but not
+    /// preserve attachments
+    pub fn used_attachments<'a>(&'a self) -> impl Iterator<Item = u32> + 'a {
+        self.input_attachments
+            .iter()
+            .chain(&self.color_attachments)
+            .chain(&self.resolve_attachments)
+            .chain(Some(&self.depth_stencil_attachment))
+            .filter_map(|reference| reference.map(|reference| reference.attachment))
+            .chain(
+                self.depth_stencil_resolve
+                    .map(|resolve| resolve.attachment.attachment),
+            )
+    }
+    /// the color attachments that are resolved at the end of the subpass, as pairs of the
+    /// multisample attachment and the attachment it's resolved into
+    #[allow(dead_code)]
+    pub fn color_resolves<'a>(&'a self) -> impl Iterator<Item = (u32, u32)> + 'a {
+        self.color_attachments
+            .iter()
+            .zip(&self.resolve_attachments)
+            .filter_map(|(color, resolve)| match (color, resolve) {
+                (Some(color), Some(resolve)) => Some((color.attachment, resolve.attachment)),
+                _ => None,
+            })
+    }
+    unsafe fn from_api(description: &api::VkSubpassDescription) -> Self {
+        assert_eq!(description.flags, 0);
+        assert_eq!(
+            description.pipelineBindPoint,
+            api::VK_PIPELINE_BIND_POINT_GRAPHICS
+        );
+        let color_attachments: Vec<_> = make_slice(
+            description.pColorAttachments,
+            description.colorAttachmentCount,
+        )
+        .iter()
+        .map(AttachmentReference::from_api)
+        .collect();
+        let resolve_attachments = if description.pResolveAttachments.is_null() {
+            Vec::new()
+        } else {
+            make_slice(
+                description.pResolveAttachments,
+                description.colorAttachmentCount,
+            )
+            .iter()
+            .map(AttachmentReference::from_api)
+            .collect()
+        };
+        Subpass {
+            view_mask: 0,
+            input_attachments: make_slice(
+                description.pInputAttachments,
+                description.inputAttachmentCount,
+            )
+            .iter()
+            .map(AttachmentReference::from_api)
+            .collect(),
+            color_attachments,
+            resolve_attachments,
+            depth_stencil_attachment: description
+                .pDepthStencilAttachment
+                .as_ref()
+                .and_then(AttachmentReference::from_api),
+            depth_stencil_resolve: None,
+            preserve_attachments: make_slice(
+                description.pPreserveAttachments,
+                description.preserveAttachmentCount,
+            )
+            .to_vec(),
+            has_self_dependency: false,
+            feedback_attachments: Vec::new(),
+        }
+    }
+    unsafe fn from_api2(description: &api::VkSubpassDescription2KHR) -> Self {
+        parse_next_chain_const!{
+            description as *const api::VkSubpassDescription2KHR,
+            root = api::VK_STRUCTURE_TYPE_SUBPASS_DESCRIPTION_2_KHR,
+            depth_stencil_resolve: api::VkSubpassDescriptionDepthStencilResolveKHR = api::VK_STRUCTURE_TYPE_SUBPASS_DESCRIPTION_DEPTH_STENCIL_RESOLVE_KHR,
+        }
+        assert_eq!(description.flags, 0);
+        assert_eq!(
+            description.pipelineBindPoint,
+            api::VK_PIPELINE_BIND_POINT_GRAPHICS
+        );
+        let color_attachments: Vec<_> = make_slice(
+            description.pColorAttachments,
+            description.colorAttachmentCount,
+        )
+        .iter()
+        .map(|reference| AttachmentReference::from_api2(reference))
+        .collect();
+        let resolve_attachments = if description.pResolveAttachments.is_null() {
+            Vec::new()
+        } else {
+            make_slice(
+                description.pResolveAttachments,
+                description.colorAttachmentCount,
+            )
+            .iter()
+            .map(|reference| AttachmentReference::from_api2(reference))
+            .collect()
+        };
+        let depth_stencil_resolve = depth_stencil_resolve.as_ref().and_then(|resolve| {
+            let attachment = resolve
+                .pDepthStencilResolveAttachment
+                .as_ref()
+                .and_then(|reference| AttachmentReference::from_api2(reference))?;
+            Some(DepthStencilResolve {
+                attachment,
+                depth_mode: ResolveMode::from_api(resolve.depthResolveMode),
+                stencil_mode: ResolveMode::from_api(resolve.stencilResolveMode),
+            })
+        });
+        Subpass {
+            view_mask: description.viewMask,
+            input_attachments: make_slice(
+                description.pInputAttachments,
+                description.inputAttachmentCount,
+            )
+            .iter()
+            .map(|reference| AttachmentReference::from_api2(reference))
+            .collect(),
+            color_attachments,
+            resolve_attachments,
+            depth_stencil_attachment: description
+                .pDepthStencilAttachment
+                .as_ref()
+                .and_then(|reference| AttachmentReference::from_api2(reference)),
+            depth_stencil_resolve,
+            preserve_attachments: make_slice(
+                description.pPreserveAttachments,
+                description.preserveAttachmentCount,
+            )
+            .to_vec(),
+            has_self_dependency: false,
+            feedback_attachments: Vec::new(),
+        }
+    }
+    fn validate(&self, attachments: &[Attachment]) {
+        for attachment in self
+            .used_attachments()
+            .chain(self.preserve_attachments.clone())
+        {
+            assert!(
+                (attachment as usize) < attachments.len(),
+                "attachment index out of range: {}",
+                attachment
+            );
+        }
+        if !self.resolve_attachments.is_empty() {
+            assert_eq!(self.resolve_attachments.len(), self.color_attachments.len());
+        }
+        for (color, resolve) in self.color_resolves() {
+            let color = &attachments[color as usize];
+            let resolve = &attachments[resolve as usize];
+            assert_ne!(
+                color.samples,
+                api::VK_SAMPLE_COUNT_1_BIT,
+                "resolving a single-sample color attachment"
+            );
+            assert_eq!(resolve.samples, api::VK_SAMPLE_COUNT_1_BIT);
+            assert_eq!(color.format, resolve.format);
+        }
+        if let Some(resolve) = self.depth_stencil_resolve {
+            let depth_stencil = self
+                .depth_stencil_attachment
+                .expect("depth/stencil resolve without a depth/stencil attachment");
+            assert_ne!(
+                attachments[depth_stencil.attachment as usize].samples,
+                api::VK_SAMPLE_COUNT_1_BIT,
+                "resolving a single-sample depth/stencil attachment"
+            );
+            assert_eq!(
+                attachments[resolve.attachment.attachment as usize].samples,
+                api::VK_SAMPLE_COUNT_1_BIT
+            );
+            assert!(
+                resolve.depth_mode.is_some() || resolve.stencil_mode.is_some(),
+                "depth/stencil resolve attachment with no resolve modes"
+            );
+            assert_ne!(resolve.stencil_mode, Some(ResolveMode::Average));
+        }
+        let used_attachments: Vec<_> = self.used_attachments().collect();
+        for preserve_attachment in &self.preserve_attachments {
+            assert!(
+                !used_attachments.contains(preserve_attachment),
+                "preserve attachment {} is also used by the subpass",
+                preserve_attachment
+            );
+        }
+    }
+}
+
+/// the graphics pipeline stages in logical order
+const GRAPHICS_STAGE_ORDER: &[api::VkPipelineStageFlagBits] = &[
+    api::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT,
+    api::VK_PIPELINE_STAGE_DRAW_INDIRECT_BIT,
+    api::VK_PIPELINE_STAGE_VERTEX_INPUT_BIT,
+    api::VK_PIPELINE_STAGE_VERTEX_SHADER_BIT,
+    api::VK_PIPELINE_STAGE_TESSELLATION_CONTROL_SHADER_BIT,
+    api::VK_PIPELINE_STAGE_TESSELLATION_EVALUATION_SHADER_BIT,
+    api::VK_PIPELINE_STAGE_GEOMETRY_SHADER_BIT,
+    api::VK_PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT,
+    api::VK_PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+    api::VK_PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT,
+    api::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+    api::VK_PIPELINE_STAGE_BOTTOM_OF_PIPE_BIT,
+];
+
+const FRAMEBUFFER_SPACE_STAGES: api::VkPipelineStageFlags =
+    api::VK_PIPELINE_STAGE_EARLY_FRAGMENT_TESTS_BIT
+        | api::VK_PIPELINE_STAGE_FRAGMENT_SHADER_BIT
+        | api::VK_PIPELINE_STAGE_LATE_FRAGMENT_TESTS_BIT
+        | api::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT;
+
+/// replaces `ALL_GRAPHICS` and `ALL_COMMANDS` with the graphics stages they include
+fn expand_stage_mask(mask: api::VkPipelineStageFlags) -> api::VkPipelineStageFlags {
+    if mask & (api::VK_PIPELINE_STAGE_ALL_GRAPHICS_BIT | api::VK_PIPELINE_STAGE_ALL_COMMANDS_BIT)
+        == 0
+    {
+        mask
+    } else {
+        GRAPHICS_STAGE_ORDER
+            .iter()
+            .fold(mask, |mask, &stage| mask | stage)
+    }
+}
+
+/// the positions of the logically earliest and latest graphics stages in `mask`
+fn stage_order_range(mask: api::VkPipelineStageFlags) -> Option<(usize, usize)> {
+    let mut positions = GRAPHICS_STAGE_ORDER
+        .iter()
+        .enumerate()
+        .filter(|&(_, &stage)| mask & stage != 0)
+        .map(|(position, _)| position);
+    let first = positions.next()?;
+    Some((first, positions.next_back().unwrap_or(first)))
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct Dependency {
+    /// `None` for `VK_SUBPASS_EXTERNAL`
+    pub src_subpass: Option<u32>,
+    /// `None` for `VK_SUBPASS_EXTERNAL`
+    pub dst_subpass: Option<u32>,
+    pub src_stage_mask: api::VkPipelineStageFlags,
+    pub dst_stage_mask: api::VkPipelineStageFlags,
+    pub src_access_mask: api::VkAccessFlags,
+    pub dst_access_mask: api::VkAccessFlags,
+    pub dependency_flags: api::VkDependencyFlags,
+    pub view_offset: i32,
+}
+
+impl Dependency {
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    fn new(
+        src_subpass: u32,
+        dst_subpass: u32,
+        src_stage_mask: api::VkPipelineStageFlags,
+        dst_stage_mask: api::VkPipelineStageFlags,
+        src_access_mask: api::VkAccessFlags,
+        dst_access_mask: api::VkAccessFlags,
+        dependency_flags: api::VkDependencyFlags,
+        view_offset: i32,
+    ) -> Self {
+        let subpass = |subpass| {
+            if subpass == api::VK_SUBPASS_EXTERNAL as u32 {
+                None
+            } else {
+                Some(subpass)
+            }
+        };
+        Dependency {
+            src_subpass: subpass(src_subpass),
+            dst_subpass: subpass(dst_subpass),
+            src_stage_mask,
+            dst_stage_mask,
+            src_access_mask,
+            dst_access_mask,
+            dependency_flags,
+            view_offset,
+        }
+    }
+    pub fn is_self_dependency(&self) -> bool {
+        self.src_subpass.is_some() && self.src_subpass == self.dst_subpass
+    }
+    fn validate(&self, subpasses: &[Subpass]) {
+        for subpass in self.src_subpass.iter().chain(&self.dst_subpass) {
+            assert!(
+                (*subpass as usize) < subpasses.len(),
+                "subpass index out of range: {}",
+                subpass
+            );
+        }
+        match (self.src_subpass, self.dst_subpass) {
+            (None, None) => panic!("dependency from VK_SUBPASS_EXTERNAL to itself"),
+            (Some(src_subpass), Some(dst_subpass)) => assert!(
+                src_subpass <= dst_subpass,
+                "dependency from subpass {} to earlier subpass {}",
+                src_subpass,
+                dst_subpass
+            ),
+            _ => {}
+        }
+        if !self.is_self_dependency() {
+            return;
+        }
+        let subpass = &subpasses[self.src_subpass.unwrap() as usize];
+        let src_stage_mask = expand_stage_mask(self.src_stage_mask);
+        let dst_stage_mask = expand_stage_mask(self.dst_stage_mask);
+        if src_stage_mask & FRAMEBUFFER_SPACE_STAGES != 0
+            && dst_stage_mask & FRAMEBUFFER_SPACE_STAGES != 0
+        {
+            assert_ne!(
+                self.dependency_flags & api::VK_DEPENDENCY_BY_REGION_BIT,
+                0,
+                "framebuffer-space self-dependency must be by region"
+            );
+        }
+        if (src_stage_mask | dst_stage_mask) & !FRAMEBUFFER_SPACE_STAGES != 0 {
+            if let (Some((_, src_latest)), Some((dst_earliest, _))) = (
+                stage_order_range(src_stage_mask),
+                stage_order_range(dst_stage_mask),
+            ) {
+                assert!(
+                    src_latest <= dst_earliest,
+                    "self-dependency's source stages must not be logically later than its \
+                     destination stages"
+                );
+            }
+        }
+        if subpass.view_mask.count_ones() > 1 {
+            assert_ne!(
+                self.dependency_flags & api::VK_DEPENDENCY_VIEW_LOCAL_BIT,
+                0,
+                "self-dependency of a subpass with multiple views must be view-local"
+            );
+        }
+        if self.dependency_flags & api::VK_DEPENDENCY_VIEW_LOCAL_BIT != 0 {
+            assert_eq!(self.view_offset, 0);
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct RenderPass {
+    pub attachments: Vec<Attachment>,
+    pub subpasses: Vec<Subpass>,
+    pub dependencies: Vec<Dependency>,
+    pub correlated_view_masks: Vec<u32>,
+}
+
+impl RenderPass {
+    pub unsafe fn new(create_info: &api::VkRenderPassCreateInfo) -> Self {
+        parse_next_chain_const!{
+            create_info as *const api::VkRenderPassCreateInfo,
+            root = api::VK_STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO,
+            multiview_create_info: api::VkRenderPassMultiviewCreateInfo = api::VK_STRUCTURE_TYPE_RENDER_PASS_MULTIVIEW_CREATE_INFO,
+            input_attachment_aspect_create_info: api::VkRenderPassInputAttachmentAspectCreateInfo = api::VK_STRUCTURE_TYPE_RENDER_PASS_INPUT_ATTACHMENT_ASPECT_CREATE_INFO,
+        }
+        assert_eq!(create_info.flags, 0);
+        let attachments = make_slice(create_info.pAttachments, create_info.attachmentCount)
+            .iter()
+            .map(|description| attachment_from_description!(description))
+            .collect();
+        let mut subpasses: Vec<_> = make_slice(create_info.pSubpasses, create_info.subpassCount)
+            .iter()
+            .map(|description| Subpass::from_api(description))
+            .collect();
+        let mut dependencies: Vec<_> =
+            make_slice(create_info.pDependencies, create_info.dependencyCount)
+                .iter()
+                .map(|dependency| {
+                    Dependency::new(
+                        dependency.srcSubpass,
+                        dependency.dstSubpass,
+                        dependency.srcStageMask,
+                        dependency.dstStageMask,
+                        dependency.srcAccessMask,
+                        dependency.dstAccessMask,
+                        dependency.dependencyFlags,
+                        0,
+                    )
+                })
+                .collect();
+        let mut correlated_view_masks = Vec::new();
+        if let Some(multiview_create_info) = multiview_create_info.as_ref() {
+            let view_masks = make_slice(
+                multiview_create_info.pViewMasks,
+                multiview_create_info.subpassCount,
+            );
+            if !view_masks.is_empty() {
+                assert_eq!(view_masks.len(), subpasses.len());
+                for (subpass, &view_mask) in subpasses.iter_mut().zip(view_masks) {
+                    subpass.view_mask = view_mask;
+                }
+            }
+            let view_offsets = make_slice(
+                multiview_create_info.pViewOffsets,
+                multiview_create_info.dependencyCount,
+            );
+            if !view_offsets.is_empty() {
+                assert_eq!(view_offsets.len(), dependencies.len());
+                for (dependency, &view_offset) in dependencies.iter_mut().zip(view_offsets) {
+                    dependency.view_offset = view_offset;
+                }
+            }
+            correlated_view_masks = make_slice(
+                multiview_create_info.pCorrelationMasks,
+                multiview_create_info.correlationMaskCount,
+            )
+            .to_vec();
+        }
+        if let Some(input_attachment_aspect_create_info) =
+            input_attachment_aspect_create_info.as_ref()
+        {
+            for aspect_reference in make_slice(
+                input_attachment_aspect_create_info.pAspectReferences,
+                input_attachment_aspect_create_info.aspectReferenceCount,
+            ) {
+                let input_attachment = subpasses[aspect_reference.subpass as usize]
+                    .input_attachments[aspect_reference.inputAttachmentIndex as usize]
+                    .as_mut();
+                if let Some(input_attachment) = input_attachment {
+                    input_attachment.aspect_mask = aspect_reference.aspectMask;
+                }
+            }
+        }
+        Self::from_parts(attachments, subpasses, dependencies, correlated_view_masks)
+    }
+    pub unsafe fn new2(create_info: &api::VkRenderPassCreateInfo2KHR) -> Self {
+        parse_next_chain_const!{
+            create_info as *const api::VkRenderPassCreateInfo2KHR,
+            root = api::VK_STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO_2_KHR,
+        }
+        assert_eq!(create_info.flags, 0);
+        let attachments = make_slice(create_info.pAttachments, create_info.attachmentCount)
+            .iter()
+            .map(|description| {
+                parse_next_chain_const!{
+                    description as *const api::VkAttachmentDescription2KHR,
+                    root = api::VK_STRUCTURE_TYPE_ATTACHMENT_DESCRIPTION_2_KHR,
+                }
+                attachment_from_description!(description)
+            })
+            .collect();
+        let subpasses = make_slice(create_info.pSubpasses, create_info.subpassCount)
+            .iter()
+            .map(|description| Subpass::from_api2(description))
+            .collect();
+        let dependencies = make_slice(create_info.pDependencies, create_info.dependencyCount)
+            .iter()
+            .map(|dependency| {
+                parse_next_chain_const!{
+                    dependency as *const api::VkSubpassDependency2KHR,
+                    root = api::VK_STRUCTURE_TYPE_SUBPASS_DEPENDENCY_2_KHR,
+                }
+                Dependency::new(
+                    dependency.srcSubpass,
+                    dependency.dstSubpass,
+                    dependency.srcStageMask,
+                    dependency.dstStageMask,
+                    dependency.srcAccessMask,
+                    dependency.dstAccessMask,
+                    dependency.dependencyFlags,
+                    dependency.viewOffset,
+                )
+            })
+            .collect();
+        Self::from_parts(
+            attachments,
+            subpasses,
+            dependencies,
+            make_slice(
+                create_info.pCorrelatedViewMasks,
+                create_info.correlatedViewMaskCount,
+            )
+            .to_vec(),
+        )
+    }
+    /// checks the render pass and fills in `Attachment::used_subpasses`,
+    /// `Subpass::has_self_dependency` and `Subpass::feedback_attachments`
+    fn from_parts(
+        mut attachments: Vec<Attachment>,
+        mut subpasses: Vec<Subpass>,
+        dependencies: Vec<Dependency>,
+        correlated_view_masks: Vec<u32>,
+    ) -> Self {
+        assert!(!subpasses.is_empty());
+        for subpass in &subpasses {
+            subpass.validate(&attachments);
+        }
+        for dependency in &dependencies {
+            dependency.validate(&subpasses);
+            if dependency.is_self_dependency() {
+                subpasses[dependency.src_subpass.unwrap() as usize].has_self_dependency = true;
+            }
+        }
+        for (subpass_index, subpass) in subpasses.iter_mut().enumerate() {
+            let subpass_index = subpass_index as u32;
+            for attachment in subpass
+                .used_attachments()
+                .chain(subpass.preserve_attachments.iter().cloned())
+            {
+                let used_subpasses = &mut attachments[attachment as usize].used_subpasses;
+                *used_subpasses = Some(match *used_subpasses {
+                    None => (subpass_index, subpass_index),
+                    Some((first, _)) => (first, subpass_index),
+                });
+            }
+            let mut feedback_attachments: Vec<_> = subpass
+                .input_attachments
+                .iter()
+                .filter_map(|input| input.map(|input| input.attachment))
+                .filter(|&input| {
+                    subpass
+                        .color_attachments
+                        .iter()
+                        .chain(Some(&subpass.depth_stencil_attachment))
+                        .any(|output| output.map(|output| output.attachment) == Some(input))
+                })
+                .collect();
+            feedback_attachments.sort_unstable();
+            feedback_attachments.dedup();
+            subpass.feedback_attachments = feedback_attachments;
+        }
+        RenderPass {
+            attachments,
+            subpasses,
+            dependencies,
+            correlated_view_masks,
+        }
+    }
+}
+
+/// the image views used as a render pass's attachments
+#[allow(dead_code)]
+pub struct Framebuffer {
+    /// indexed by attachment number
+    pub attachments: Vec<SharedHandle<api::VkImageView>>,
+    pub width: u32,
+    pub height: u32,
+    pub layers: u32,
+}
+
+impl Framebuffer {
+    pub unsafe fn new(create_info: &api::VkFramebufferCreateInfo) -> Self {
+        parse_next_chain_const!{
+            create_info as *const api::VkFramebufferCreateInfo,
+            root = api::VK_STRUCTURE_TYPE_FRAMEBUFFER_CREATE_INFO,
+        }
+        assert_eq!(create_info.flags, 0);
+        Framebuffer {
+            attachments: make_slice(create_info.pAttachments, create_info.attachmentCount)
+                .iter()
+                .map(|&attachment| SharedHandle::from(attachment).unwrap())
+                .collect(),
+            width: create_info.width,
+            height: create_info.height,
+            layers: create_info.layers,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(samples: api::VkSampleCountFlagBits) -> Attachment {
+        Attachment {
+            flags: 0,
+            format: api::VK_FORMAT_R8G8B8A8_UNORM,
+            samples,
+            load_op: api::VK_ATTACHMENT_LOAD_OP_LOAD,
+            store_op: api::VK_ATTACHMENT_STORE_OP_STORE,
+            stencil_load_op: api::VK_ATTACHMENT_LOAD_OP_DONT_CARE,
+            stencil_store_op: api::VK_ATTACHMENT_STORE_OP_DONT_CARE,
+            initial_layout: api::VK_IMAGE_LAYOUT_GENERAL,
+            final_layout: api::VK_IMAGE_LAYOUT_GENERAL,
+            used_subpasses: None,
+        }
+    }
+
+    fn reference(attachment: u32) -> Option<AttachmentReference> {
+        AttachmentReference::new(attachment, api::VK_IMAGE_LAYOUT_GENERAL, 0)
+    }
+
+    fn subpass(
+        input_attachments: &[u32],
+        color_attachments: &[u32],
+        preserve_attachments: &[u32],
+    ) -> Subpass {
+        Subpass {
+            view_mask: 0,
+            input_attachments: input_attachments.iter().map(|&v| reference(v)).collect(),
+            color_attachments: color_attachments.iter().map(|&v| reference(v)).collect(),
+            resolve_attachments: Vec::new(),
+            depth_stencil_attachment: None,
+            depth_stencil_resolve: None,
+            preserve_attachments: preserve_attachments.to_vec(),
+            has_self_dependency: false,
+            feedback_attachments: Vec::new(),
+        }
+    }
+
+    fn self_dependency(subpass: u32, dependency_flags: api::VkDependencyFlags) -> Dependency {
+        Dependency::new(
+            subpass,
+            subpass,
+            api::VK_PIPELINE_STAGE_COLOR_ATTACHMENT_OUTPUT_BIT,
+            api::VK_PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+            api::VK_ACCESS_COLOR_ATTACHMENT_WRITE_BIT,
+            api::VK_ACCESS_INPUT_ATTACHMENT_READ_BIT,
+            dependency_flags,
+            0,
+        )
+    }
+
+    #[test]
+    fn test_resolve_modes() {
+        let depth = [0.5, 0.25, 1.0, 0.25];
+        assert_eq!(ResolveMode::SampleZero.resolve_depth(&depth), 0.5);
+        assert_eq!(ResolveMode::Average.resolve_depth(&depth), 0.5);
+        assert_eq!(ResolveMode::Min.resolve_depth(&depth), 0.25);
+        assert_eq!(ResolveMode::Max.resolve_depth(&depth), 1.0);
+        let stencil = [3, 1, 7, 2];
+        assert_eq!(ResolveMode::SampleZero.resolve_stencil(&stencil), 3);
+        assert_eq!(ResolveMode::Min.resolve_stencil(&stencil), 1);
+        assert_eq!(ResolveMode::Max.resolve_stencil(&stencil), 7);
+    }
+
+    #[test]
+    fn test_self_dependency() {
+        let render_pass = RenderPass::from_parts(
+            vec![attachment(api::VK_SAMPLE_COUNT_1_BIT)],
+            vec![subpass(&[0], &[0], &[])],
+            vec![self_dependency(0, api::VK_DEPENDENCY_BY_REGION_BIT)],
+            Vec::new(),
+        );
+        assert!(render_pass.subpasses[0].has_self_dependency);
+        assert_eq!(render_pass.subpasses[0].feedback_attachments, [0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be by region")]
+    fn test_self_dependency_not_by_region() {
+        RenderPass::from_parts(
+            vec![attachment(api::VK_SAMPLE_COUNT_1_BIT)],
+            vec![subpass(&[0], &[0], &[])],
+            vec![self_dependency(0, 0)],
+            Vec::new(),
+        );
+    }
+
+    #[test]
+    fn test_preserved_attachment_uses() {
+        let render_pass = RenderPass::from_parts(
+            vec![
+                attachment(api::VK_SAMPLE_COUNT_1_BIT),
+                attachment(api::VK_SAMPLE_COUNT_1_BIT),
+                attachment(api::VK_SAMPLE_COUNT_1_BIT),
+            ],
+            vec![
+                subpass(&[], &[0], &[]),
+                subpass(&[], &[1], &[0]),
+                subpass(&[0], &[1], &[]),
+            ],
+            Vec::new(),
+            Vec::new(),
+        );
+        assert_eq!(render_pass.attachments[0].used_subpasses, Some((0, 2)));
+        assert_eq!(render_pass.attachments[1].used_subpasses, Some((1, 2)));
+        assert_eq!(render_pass.attachments[2].used_subpasses, None);
+    }
+
+    #[test]
+    fn test_color_resolve() {
+        let mut resolving_subpass = subpass(&[], &[0], &[]);
+        resolving_subpass.resolve_attachments = vec![reference(1)];
+        let render_pass = RenderPass::from_parts(
+            vec![
+                attachment(api::VK_SAMPLE_COUNT_4_BIT),
+                attachment(api::VK_SAMPLE_COUNT_1_BIT),
+            ],
+            vec![resolving_subpass],
+            Vec::new(),
+            Vec::new(),
+        );
+        let resolves: Vec<_> = render_pass.subpasses[0].color_resolves().collect();
+        assert_eq!(resolves, [(0, 1)]);
+        assert_eq!(render_pass.attachments[1].used_subpasses, Some((0, 0)));
+    }
+}
@@ -1,7 +1,12 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later
 // Copyright 2018 Jacob Lifshay
 use api;
+use format;
 use handle::SharedHandle;
+use image::{ImageRobustness, ImageView};
+use shader_compiler::image::{ImageHandle, ImageIntrinsics, SamplerHandle};
+use std::slice;
+use std::sync::atomic::AtomicUsize;
 
 pub struct AnisotropySettings {
     pub max: f32,
@@ -23,3 +28,827 @@ pub struct Sampler {
 }
 
 pub struct SamplerYcbcrConversion {}
+
+fn apply_address_mode(address_mode: api::VkSamplerAddressMode, v: i32, size: u32) -> Option<u32> {
+    let size = size as i32;
+    let mirror = |v: i32| if v >= 0 { v } else { -1 - v };
+    let modulo = |v: i32, m: i32| ((v % m) + m) % m;
+    let v = match address_mode {
+        api::VK_SAMPLER_ADDRESS_MODE_REPEAT => modulo(v, size),
+        api::VK_SAMPLER_ADDRESS_MODE_MIRRORED_REPEAT => {
+            let v = modulo(v, 2 * size);
+            if v >= size {
+                2 * size - 1 - v
+            } else {
+                v
+            }
+        }
+        api::VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE => v.max(0).min(size - 1),
+        api::VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_BORDER => {
+            if v < 0 || v >= size {
+                return None;
+            }
+            v
+        }
+        api::VK_SAMPLER_ADDRESS_MODE_MIRROR_CLAMP_TO_EDGE => mirror(v).min(size - 1),
+        _ => unreachable!("invalid sampler address mode: {}", address_mode),
+    };
+    Some(v as u32)
+}
+
+fn apply_component_swizzle(
+    swizzle: api::VkComponentSwizzle,
+    identity: f32,
+    color: [f32; 4],
+) -> f32 {
+    match swizzle {
+        api::VK_COMPONENT_SWIZZLE_IDENTITY => identity,
+        api::VK_COMPONENT_SWIZZLE_ZERO => 0.0,
+        api::VK_COMPONENT_SWIZZLE_ONE => 1.0,
+        api::VK_COMPONENT_SWIZZLE_R => color[0],
+        api::VK_COMPONENT_SWIZZLE_G => color[1],
+        api::VK_COMPONENT_SWIZZLE_B => color[2],
+        api::VK_COMPONENT_SWIZZLE_A => color[3],
+        _ => unreachable!("invalid component swizzle: {}", swizzle),
+    }
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ]
+}
+
+impl ImageView {
+    pub fn get_dimension_count(&self) -> usize {
+        match self.view_type {
+            api::VK_IMAGE_VIEW_TYPE_1D | api::VK_IMAGE_VIEW_TYPE_1D_ARRAY => 1,
+            api::VK_IMAGE_VIEW_TYPE_2D
+            | api::VK_IMAGE_VIEW_TYPE_2D_ARRAY
+            | api::VK_IMAGE_VIEW_TYPE_CUBE
+            | api::VK_IMAGE_VIEW_TYPE_CUBE_ARRAY => 2,
+            api::VK_IMAGE_VIEW_TYPE_3D => 3,
+            _ => unreachable!("invalid image view type: {}", self.view_type),
+        }
+    }
+    /// cube views are arrayed too: their texels are addressed by the face coordinates followed
+    /// by the layer of the face
+    pub fn is_arrayed(&self) -> bool {
+        match self.view_type {
+            api::VK_IMAGE_VIEW_TYPE_1D_ARRAY
+            | api::VK_IMAGE_VIEW_TYPE_2D_ARRAY
+            | api::VK_IMAGE_VIEW_TYPE_CUBE
+            | api::VK_IMAGE_VIEW_TYPE_CUBE_ARRAY => true,
+            _ => false,
+        }
+    }
+    pub fn is_cube(&self) -> bool {
+        match self.view_type {
+            api::VK_IMAGE_VIEW_TYPE_CUBE | api::VK_IMAGE_VIEW_TYPE_CUBE_ARRAY => true,
+            _ => false,
+        }
+    }
+    /// convert the direction in `coordinates`, followed by the cube index if the view is a cube
+    /// array, into the coordinates on the face the direction points at followed by the layer of
+    /// the face
+    fn get_cube_face_coordinates(&self, coordinates: [f32; 4]) -> [f32; 4] {
+        let [x, y, z, cube] = coordinates;
+        // ties pick z over y over x
+        let (face, major_axis, s, t) = if z.abs() >= x.abs() && z.abs() >= y.abs() {
+            if z >= 0.0 {
+                (4, z, x, -y)
+            } else {
+                (5, z, -x, -y)
+            }
+        } else if y.abs() >= x.abs() {
+            if y >= 0.0 {
+                (2, y, x, z)
+            } else {
+                (3, y, x, -z)
+            }
+        } else if x >= 0.0 {
+            (0, x, -z, -y)
+        } else {
+            (1, x, z, -y)
+        };
+        let major_axis = major_axis.abs();
+        let cube = if self.view_type == api::VK_IMAGE_VIEW_TYPE_CUBE_ARRAY {
+            let cube_count = self.subresource_range.layerCount / 6;
+            cube.round().max(0.0).min((cube_count - 1) as f32)
+        } else {
+            0.0
+        };
+        [
+            0.5 * s / major_axis + 0.5,
+            0.5 * t / major_axis + 0.5,
+            cube * 6.0 + face as f32,
+            0.0,
+        ]
+    }
+    fn get_mip_level_extents(&self, level: u32) -> [u32; 3] {
+        let extents = self.image_computed_properties.mip_level_layouts
+            [(self.subresource_range.baseMipLevel + level) as usize]
+            .extents;
+        [extents.width, extents.height, extents.depth]
+    }
+    /// the layer of the view, not of the image
+    fn get_array_layer(&self, coordinates: [f32; 4]) -> u32 {
+        if !self.is_arrayed() {
+            return 0;
+        }
+        let layer = coordinates[self.get_dimension_count()].round().max(0.0) as u32;
+        layer.min(self.subresource_range.layerCount - 1)
+    }
+    /// check integer `coordinates`, followed by the array layer if the view is arrayed, against
+    /// mip level `level`. returns the texel coordinates and the view's array layer
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_range_loop))]
+    fn get_texel_location(&self, coordinates: [i32; 4], level: u32) -> Option<([u32; 3], u32)> {
+        if level >= self.subresource_range.levelCount {
+            return None;
+        }
+        let dimension_count = self.get_dimension_count();
+        let extents = self.get_mip_level_extents(level);
+        let mut texel_coordinates = [0; 3];
+        for dimension in 0..dimension_count {
+            let v = coordinates[dimension];
+            if v < 0 || v as u32 >= extents[dimension] {
+                return None;
+            }
+            texel_coordinates[dimension] = v as u32;
+        }
+        if !self.is_arrayed() {
+            return Some((texel_coordinates, 0));
+        }
+        let layer = coordinates[dimension_count];
+        if layer < 0 || layer as u32 >= self.subresource_range.layerCount {
+            return None;
+        }
+        Some((texel_coordinates, layer as u32))
+    }
+    fn apply_component_mapping(&self, color: [f32; 4]) -> [f32; 4] {
+        let mapping = &self.component_mapping;
+        [
+            apply_component_swizzle(mapping.r, color[0], color),
+            apply_component_swizzle(mapping.g, color[1], color),
+            apply_component_swizzle(mapping.b, color[2], color),
+            apply_component_swizzle(mapping.a, color[3], color),
+        ]
+    }
+    /// read and decode a texel of layer `layer` of the view, applying the view's component
+    /// mapping. views of the stencil aspect return the stencil value in the red component
+    pub unsafe fn fetch(&self, texel_coordinates: [u32; 3], layer: u32, level: u32) -> [f32; 4] {
+        let pointer = self.get_texel_pointer(texel_coordinates, layer, level);
+        let texel =
+            slice::from_raw_parts(pointer, self.image_computed_properties.pixel_size_in_bytes);
+        let color = if self.subresource_range.aspectMask == api::VK_IMAGE_ASPECT_STENCIL_BIT {
+            [
+                f32::from(format::decode_stencil(self.format, texel)),
+                0.0,
+                0.0,
+                1.0,
+            ]
+        } else {
+            format::decode_texel(self.format, texel)
+        };
+        self.apply_component_mapping(color)
+    }
+    /// what reading a texel outside the view or a mip level the view doesn't have returns
+    pub fn get_invalid_texel(&self) -> [f32; 4] {
+        match self.robustness {
+            ImageRobustness::Disabled | ImageRobustness::RobustImageAccess => [0.0; 4],
+            ImageRobustness::RobustImageAccess2 => {
+                let alpha = if format::has_alpha(self.format) {
+                    0.0
+                } else {
+                    1.0
+                };
+                self.apply_component_mapping([0.0, 0.0, 0.0, alpha])
+            }
+        }
+    }
+}
+
+impl Sampler {
+    fn get_border_color(&self) -> [f32; 4] {
+        match self.border_color {
+            api::VK_BORDER_COLOR_FLOAT_TRANSPARENT_BLACK
+            | api::VK_BORDER_COLOR_INT_TRANSPARENT_BLACK => [0.0, 0.0, 0.0, 0.0],
+            api::VK_BORDER_COLOR_FLOAT_OPAQUE_BLACK | api::VK_BORDER_COLOR_INT_OPAQUE_BLACK => {
+                [0.0, 0.0, 0.0, 1.0]
+            }
+            api::VK_BORDER_COLOR_FLOAT_OPAQUE_WHITE | api::VK_BORDER_COLOR_INT_OPAQUE_WHITE => {
+                [1.0, 1.0, 1.0, 1.0]
+            }
+            _ => unreachable!("invalid border color: {}", self.border_color),
+        }
+    }
+    /// sample a single mip level of `image_view`, relative to the view's base mip level
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_range_loop))]
+    pub unsafe fn sample_level(
+        &self,
+        image_view: &ImageView,
+        coordinates: [f32; 4],
+        level: u32,
+        filter: api::VkFilter,
+    ) -> [f32; 4] {
+        let dimension_count = image_view.get_dimension_count();
+        let extents = image_view.get_mip_level_extents(level);
+        let array_layer = image_view.get_array_layer(coordinates);
+        // texel coordinates and weights for the lower and upper texels in each dimension
+        let mut texel_coordinates = [[Some(0); 2]; 3];
+        let mut weights = [0.0f32; 3];
+        for dimension in 0..dimension_count {
+            let size = extents[dimension];
+            let mut v = coordinates[dimension];
+            if !self.unnormalized_coordinates {
+                v *= size as f32;
+            }
+            // cube views ignore the address modes. filtering doesn't cross into the neighboring
+            // faces yet, so the edge texels are repeated instead
+            let address_mode = if image_view.is_cube() {
+                api::VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE
+            } else {
+                self.address_modes[dimension]
+            };
+            match filter {
+                api::VK_FILTER_NEAREST => {
+                    let v = apply_address_mode(address_mode, v.floor() as i32, size);
+                    texel_coordinates[dimension] = [v, v];
+                }
+                api::VK_FILTER_LINEAR => {
+                    let v = v - 0.5;
+                    let lower = v.floor();
+                    weights[dimension] = v - lower;
+                    // huge and infinite coordinates saturate
+                    let lower = lower as i32;
+                    texel_coordinates[dimension] = [
+                        apply_address_mode(address_mode, lower, size),
+                        apply_address_mode(address_mode, lower.saturating_add(1), size),
+                    ];
+                }
+                _ => unreachable!("invalid sampler filter: {}", filter),
+            }
+        }
+        let border_color = self.get_border_color();
+        let fetch_texel = |x: Option<u32>, y: Option<u32>, z: Option<u32>| match (x, y, z) {
+            (Some(x), Some(y), Some(z)) => image_view.fetch([x, y, z], array_layer, level),
+            _ => border_color,
+        };
+        let x = texel_coordinates[0];
+        let y = texel_coordinates[1];
+        let z = texel_coordinates[2];
+        let fetch_row = |y: Option<u32>, z: Option<u32>| {
+            lerp(fetch_texel(x[0], y, z), fetch_texel(x[1], y, z), weights[0])
+        };
+        let fetch_slice = |z: Option<u32>| lerp(fetch_row(y[0], z), fetch_row(y[1], z), weights[1]);
+        lerp(fetch_slice(z[0]), fetch_slice(z[1]), weights[2])
+    }
+    /// sample `image_view` at `coordinates` with the level of detail `lod`.
+    /// `coordinates` holds the texture coordinates followed by the array layer, if any; for cube
+    /// views, the direction followed by the cube index, if any.
+    /// samplers with depth comparison or anisotropic filtering are rejected by `vkCreateSampler`,
+    /// so they're never used here
+    pub unsafe fn sample(
+        &self,
+        image_view: &ImageView,
+        coordinates: [f32; 4],
+        lod: f32,
+    ) -> [f32; 4] {
+        let coordinates = if image_view.is_cube() {
+            image_view.get_cube_face_coordinates(coordinates)
+        } else {
+            coordinates
+        };
+        let lod = (lod + self.mip_lod_bias)
+            .max(self.min_lod)
+            .min(self.max_lod);
+        let filter = if lod <= 0.0 {
+            self.mag_filter
+        } else {
+            self.min_filter
+        };
+        let max_level = image_view.subresource_range.levelCount - 1;
+        let lod = lod.max(0.0);
+        match self.mipmap_mode {
+            api::VK_SAMPLER_MIPMAP_MODE_NEAREST => {
+                let level = ((lod + 0.5).ceil() - 1.0).max(0.0) as u32;
+                self.sample_level(image_view, coordinates, level.min(max_level), filter)
+            }
+            api::VK_SAMPLER_MIPMAP_MODE_LINEAR => {
+                let lower_level = (lod.floor() as u32).min(max_level);
+                let upper_level = (lower_level + 1).min(max_level);
+                let lower = self.sample_level(image_view, coordinates, lower_level, filter);
+                if lower_level == upper_level {
+                    return lower;
+                }
+                let upper = self.sample_level(image_view, coordinates, upper_level, filter);
+                lerp(lower, upper, lod.fract())
+            }
+            _ => unreachable!("invalid sampler mipmap mode: {}", self.mipmap_mode),
+        }
+    }
+    /// compute the level of detail from the derivatives of the texture coordinates
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::needless_range_loop))]
+    pub fn compute_lod(&self, image_view: &ImageView, derivatives: [[f32; 4]; 2]) -> f32 {
+        let extents = image_view.get_mip_level_extents(0);
+        let (dimension_count, sizes) = if image_view.is_cube() {
+            // the derivatives are of the direction, which is treated as being about unit length.
+            // a face spans a change of 2 in each component
+            let size = extents[0] as f32 / 2.0;
+            (3, [size; 3])
+        } else {
+            (
+                image_view.get_dimension_count(),
+                [extents[0] as f32, extents[1] as f32, extents[2] as f32],
+            )
+        };
+        let scale = |derivative: [f32; 4]| {
+            let mut sum = 0.0;
+            for dimension in 0..dimension_count {
+                let mut v = derivative[dimension];
+                if !self.unnormalized_coordinates {
+                    v *= sizes[dimension];
+                }
+                sum += v * v;
+            }
+            sum.sqrt()
+        };
+        scale(derivatives[0]).max(scale(derivatives[1])).log2()
+    }
+}
+
+/// implements `ImageIntrinsics::sample_implicit_lod`
+pub unsafe extern "C" fn sample_implicit_lod(
+    image_view: ImageHandle,
+    sampler: SamplerHandle,
+    coordinates: *const [f32; 4],
+    derivatives: *const [[f32; 4]; 2],
+    lod_bias: f32,
+    result: *mut [f32; 4],
+) {
+    // null descriptors (`VK_EXT_robustness2`) read zero
+    if image_view.is_null() {
+        *result = [0.0; 4];
+        return;
+    }
+    let image_view = &*(image_view as *const ImageView);
+    let sampler = &*(sampler as *const Sampler);
+    let lod = sampler.compute_lod(image_view, *derivatives) + lod_bias;
+    *result = sampler.sample(image_view, *coordinates, lod);
+}
+
+/// implements `ImageIntrinsics::sample_explicit_lod`
+pub unsafe extern "C" fn sample_explicit_lod(
+    image_view: ImageHandle,
+    sampler: SamplerHandle,
+    coordinates: *const [f32; 4],
+    lod: f32,
+    result: *mut [f32; 4],
+) {
+    if image_view.is_null() {
+        *result = [0.0; 4];
+        return;
+    }
+    let image_view = &*(image_view as *const ImageView);
+    let sampler = &*(sampler as *const Sampler);
+    *result = sampler.sample(image_view, *coordinates, lod);
+}
+
+/// implements `ImageIntrinsics::fetch`; out of bounds fetches and fetches of invalid levels
+/// return `ImageView::get_invalid_texel`, and null descriptors read zero
+pub unsafe extern "C" fn fetch(
+    image_view: ImageHandle,
+    coordinates: *const [i32; 4],
+    level: i32,
+    result: *mut [f32; 4],
+) {
+    if image_view.is_null() {
+        *result = [0.0; 4];
+        return;
+    }
+    let image_view = &*(image_view as *const ImageView);
+    let location = if level < 0 {
+        None
+    } else {
+        image_view.get_texel_location(*coordinates, level as u32)
+    };
+    *result = match location {
+        Some((texel_coordinates, array_layer)) => {
+            image_view.fetch(texel_coordinates, array_layer, level as u32)
+        }
+        None => image_view.get_invalid_texel(),
+    };
+}
+
+/// where out of bounds atomics go, since they must not write to the image. big and aligned
+/// enough for any texel format that supports atomics
+#[allow(dead_code)]
+#[repr(align(16))]
+struct ScratchTexel([AtomicUsize; 4]);
+
+static SCRATCH_TEXEL: ScratchTexel = ScratchTexel([
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+]);
+
+/// implements `ImageIntrinsics::texel_pointer`. storage images are single-sampled, so nonzero
+/// samples are out of bounds, and so is every texel of a null descriptor
+pub unsafe extern "C" fn texel_pointer(
+    image_view: ImageHandle,
+    coordinates: *const [i32; 4],
+    sample: i32,
+) -> *mut u8 {
+    let scratch_texel = &SCRATCH_TEXEL as *const ScratchTexel as *mut u8;
+    if image_view.is_null() || sample != 0 {
+        return scratch_texel;
+    }
+    let image_view = &*(image_view as *const ImageView);
+    match image_view.get_texel_location(*coordinates, 0) {
+        Some((texel_coordinates, layer)) => {
+            image_view.get_texel_pointer(texel_coordinates, layer, 0)
+        }
+        None => scratch_texel,
+    }
+}
+
+pub fn get_image_intrinsics() -> ImageIntrinsics {
+    ImageIntrinsics {
+        sample_implicit_lod,
+        sample_explicit_lod,
+        fetch,
+        texel_pointer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device_memory::{DeviceMemory, DeviceMemoryAllocation};
+    use handle::OwnedHandle;
+    use image::{Image, ImageMemory, ImageMultisampleCount, ImageProperties, SupportedTilings};
+    use std::ptr::null;
+
+    const VIEW_TYPES: &[api::VkImageViewType] = &[
+        api::VK_IMAGE_VIEW_TYPE_1D,
+        api::VK_IMAGE_VIEW_TYPE_1D_ARRAY,
+        api::VK_IMAGE_VIEW_TYPE_2D,
+        api::VK_IMAGE_VIEW_TYPE_2D_ARRAY,
+        api::VK_IMAGE_VIEW_TYPE_3D,
+        api::VK_IMAGE_VIEW_TYPE_CUBE,
+        api::VK_IMAGE_VIEW_TYPE_CUBE_ARRAY,
+    ];
+
+    /// a format and aspect of each format class: color in both component orders, depth, and the
+    /// depth and stencil aspects of a depth/stencil format
+    const FORMATS: &[(api::VkFormat, api::VkImageAspectFlags)] = &[
+        (
+            api::VK_FORMAT_R8G8B8A8_UNORM,
+            api::VK_IMAGE_ASPECT_COLOR_BIT,
+        ),
+        (api::VK_FORMAT_B8G8R8A8_SRGB, api::VK_IMAGE_ASPECT_COLOR_BIT),
+        (api::VK_FORMAT_D32_SFLOAT, api::VK_IMAGE_ASPECT_DEPTH_BIT),
+        (
+            api::VK_FORMAT_D24_UNORM_S8_UINT,
+            api::VK_IMAGE_ASPECT_DEPTH_BIT,
+        ),
+        (
+            api::VK_FORMAT_D24_UNORM_S8_UINT,
+            api::VK_IMAGE_ASPECT_STENCIL_BIT,
+        ),
+    ];
+
+    struct TestImageView {
+        view: ImageView,
+        _image: OwnedHandle<api::VkImage>,
+        _device_memory: OwnedHandle<api::VkDeviceMemory>,
+    }
+
+    impl TestImageView {
+        /// a view of a whole image with 2 mip levels, 4 texels in each of its dimensions and 2
+        /// layers (or cubes) if it's arrayed, with every byte set to `0x3F`
+        unsafe fn new(
+            view_type: api::VkImageViewType,
+            format: api::VkFormat,
+            components: api::VkComponentMapping,
+        ) -> Self {
+            let (image_type, extents) = match view_type {
+                api::VK_IMAGE_VIEW_TYPE_1D | api::VK_IMAGE_VIEW_TYPE_1D_ARRAY => {
+                    (api::VK_IMAGE_TYPE_1D, (4, 1, 1))
+                }
+                api::VK_IMAGE_VIEW_TYPE_2D
+                | api::VK_IMAGE_VIEW_TYPE_2D_ARRAY
+                | api::VK_IMAGE_VIEW_TYPE_CUBE
+                | api::VK_IMAGE_VIEW_TYPE_CUBE_ARRAY => (api::VK_IMAGE_TYPE_2D, (4, 4, 1)),
+                _ => (api::VK_IMAGE_TYPE_3D, (4, 4, 4)),
+            };
+            let (array_layers, flags) = match view_type {
+                api::VK_IMAGE_VIEW_TYPE_1D_ARRAY | api::VK_IMAGE_VIEW_TYPE_2D_ARRAY => (2, 0),
+                api::VK_IMAGE_VIEW_TYPE_CUBE => (6, api::VK_IMAGE_CREATE_CUBE_COMPATIBLE_BIT),
+                api::VK_IMAGE_VIEW_TYPE_CUBE_ARRAY => {
+                    (12, api::VK_IMAGE_CREATE_CUBE_COMPATIBLE_BIT)
+                }
+                _ => (1, 0),
+            };
+            let properties = ImageProperties {
+                supported_tilings: SupportedTilings::Any,
+                image_type,
+                flags,
+                format,
+                extents: api::VkExtent3D {
+                    width: extents.0,
+                    height: extents.1,
+                    depth: extents.2,
+                },
+                array_layers,
+                mip_levels: 2,
+                multisample_count: ImageMultisampleCount::Count1,
+                swapchain_present_tiling: None,
+            };
+            let device_memory = OwnedHandle::<api::VkDeviceMemory>::new(
+                DeviceMemory::allocate_from_default_heap(
+                    properties.computed_properties().memory_layout,
+                )
+                .unwrap(),
+            );
+            slice::from_raw_parts_mut(device_memory.get().as_ptr(), device_memory.size())
+                .iter_mut()
+                .for_each(|v| *v = 0x3F);
+            let image = OwnedHandle::<api::VkImage>::new(Image {
+                properties,
+                memory: Some(ImageMemory {
+                    device_memory: SharedHandle::from(device_memory.get_handle()).unwrap(),
+                    offset: 0,
+                }),
+            });
+            let aspect_mask = match format::get_format_info(format).unwrap().kind {
+                format::FormatKind::Color => api::VK_IMAGE_ASPECT_COLOR_BIT,
+                _ => api::VK_IMAGE_ASPECT_DEPTH_BIT,
+            };
+            let view = ImageView::new(
+                SharedHandle::from(image.get_handle()).unwrap(),
+                &api::VkImageViewCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
+                    pNext: null(),
+                    flags: 0,
+                    image: image.get_handle(),
+                    viewType: view_type,
+                    format,
+                    components,
+                    subresourceRange: api::VkImageSubresourceRange {
+                        aspectMask: aspect_mask,
+                        baseMipLevel: 0,
+                        levelCount: api::VK_REMAINING_MIP_LEVELS as u32,
+                        baseArrayLayer: 0,
+                        layerCount: api::VK_REMAINING_ARRAY_LAYERS as u32,
+                    },
+                },
+            );
+            TestImageView {
+                view,
+                _image: image,
+                _device_memory: device_memory,
+            }
+        }
+        fn get_handle(&self) -> ImageHandle {
+            &self.view as *const ImageView as ImageHandle
+        }
+    }
+
+    const IDENTITY_MAPPING: api::VkComponentMapping = api::VkComponentMapping {
+        r: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+        g: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+        b: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+        a: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+    };
+
+    fn fetch_texel(image_view: ImageHandle, coordinates: [i32; 4], level: i32) -> [f32; 4] {
+        let mut result = [f32::NAN; 4];
+        unsafe { fetch(image_view, &coordinates, level, &mut result) };
+        result
+    }
+
+    #[test]
+    fn test_robust_fetch() {
+        for &view_type in VIEW_TYPES {
+            for &(format, aspect_mask) in FORMATS {
+                for &robustness in &[
+                    ImageRobustness::Disabled,
+                    ImageRobustness::RobustImageAccess,
+                    ImageRobustness::RobustImageAccess2,
+                ] {
+                    let mut image_view =
+                        unsafe { TestImageView::new(view_type, format, IDENTITY_MAPPING) };
+                    image_view.view.robustness = robustness;
+                    image_view.view.subresource_range.aspectMask = aspect_mask;
+                    let handle = image_view.get_handle();
+                    let invalid_texel = if robustness == ImageRobustness::RobustImageAccess2
+                        && !format::has_alpha(format)
+                    {
+                        [0.0, 0.0, 0.0, 1.0]
+                    } else {
+                        [0.0; 4]
+                    };
+                    let message =
+                        format!("{} {} {} {:?}", view_type, format, aspect_mask, robustness);
+                    // texel 1 of layer 1 (a face of cube views) is in every view at both levels
+                    let inside = [1; 4];
+                    for level in 0..2 {
+                        let texel = fetch_texel(handle, inside, level);
+                        assert!(texel[0] > 0.0, "{}: {:?}", message, texel);
+                    }
+                    let dimension_count = image_view.view.get_dimension_count();
+                    let mut outside = Vec::new();
+                    for dimension in 0..dimension_count {
+                        for &(v, level) in &[(-1, 0), (4, 0), (2, 1)] {
+                            let mut coordinates = inside;
+                            coordinates[dimension] = v;
+                            outside.push((coordinates, level));
+                        }
+                    }
+                    if image_view.view.is_arrayed() {
+                        let layer_count = image_view.view.subresource_range.layerCount as i32;
+                        for &layer in &[-1, layer_count] {
+                            let mut coordinates = inside;
+                            coordinates[dimension_count] = layer;
+                            outside.push((coordinates, 0));
+                        }
+                    }
+                    outside.push((inside, -1));
+                    outside.push((inside, 2));
+                    for (coordinates, level) in outside {
+                        assert_eq!(
+                            fetch_texel(handle, coordinates, level),
+                            invalid_texel,
+                            "{}: coordinates = {:?}, level = {}",
+                            message,
+                            coordinates,
+                            level
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_texel_component_mapping() {
+        let mapping = api::VkComponentMapping {
+            r: api::VK_COMPONENT_SWIZZLE_A,
+            g: api::VK_COMPONENT_SWIZZLE_ONE,
+            b: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+            a: api::VK_COMPONENT_SWIZZLE_R,
+        };
+        for &(format, expected) in &[
+            (api::VK_FORMAT_R8G8B8A8_UNORM, [0.0, 1.0, 0.0, 0.0]),
+            (api::VK_FORMAT_D32_SFLOAT, [1.0, 1.0, 0.0, 0.0]),
+        ] {
+            let mut image_view =
+                unsafe { TestImageView::new(api::VK_IMAGE_VIEW_TYPE_2D, format, mapping) };
+            image_view.view.robustness = ImageRobustness::RobustImageAccess2;
+            assert_eq!(
+                fetch_texel(image_view.get_handle(), [4, 0, 0, 0], 0),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_null_descriptor() {
+        assert_eq!(fetch_texel(null(), [0; 4], 0), [0.0; 4]);
+    }
+
+    #[test]
+    fn test_sample_invalid_lod() {
+        let image_view = unsafe {
+            TestImageView::new(
+                api::VK_IMAGE_VIEW_TYPE_2D,
+                api::VK_FORMAT_R8G8B8A8_UNORM,
+                IDENTITY_MAPPING,
+            )
+        };
+        let sampler = Sampler {
+            mag_filter: api::VK_FILTER_LINEAR,
+            min_filter: api::VK_FILTER_LINEAR,
+            mipmap_mode: api::VK_SAMPLER_MIPMAP_MODE_LINEAR,
+            address_modes: [api::VK_SAMPLER_ADDRESS_MODE_CLAMP_TO_EDGE; 3],
+            mip_lod_bias: 0.0,
+            anisotropy: None,
+            compare_op: None,
+            min_lod: 0.0,
+            // VK_LOD_CLAMP_NONE
+            max_lod: 1000.0,
+            border_color: api::VK_BORDER_COLOR_FLOAT_TRANSPARENT_BLACK,
+            unnormalized_coordinates: false,
+            sampler_ycbcr_conversion: None,
+        };
+        let texel = [f32::from(0x3Fu8) / 255.0; 4];
+        for &lod in &[f32::NAN, f32::INFINITY, f32::NEG_INFINITY, -1.0, 1000.0] {
+            for &coordinates in &[[0.5, 0.5, 0.0, 0.0], [1e30, -1e30, 0.0, 0.0]] {
+                let mut result = [f32::NAN; 4];
+                unsafe {
+                    sample_explicit_lod(
+                        image_view.get_handle(),
+                        &sampler as *const Sampler as SamplerHandle,
+                        &coordinates,
+                        lod,
+                        &mut result,
+                    );
+                }
+                assert_eq!(
+                    result, texel,
+                    "lod = {}, coordinates = {:?}",
+                    lod, coordinates
+                );
+            }
+        }
+    }
+
+    fn make_nearest_sampler() -> Sampler {
+        Sampler {
+            mag_filter: api::VK_FILTER_NEAREST,
+            min_filter: api::VK_FILTER_NEAREST,
+            mipmap_mode: api::VK_SAMPLER_MIPMAP_MODE_NEAREST,
+            address_modes: [api::VK_SAMPLER_ADDRESS_MODE_REPEAT; 3],
+            mip_lod_bias: 0.0,
+            anisotropy: None,
+            compare_op: None,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: api::VK_BORDER_COLOR_FLOAT_TRANSPARENT_BLACK,
+            unnormalized_coordinates: false,
+            sampler_ycbcr_conversion: None,
+        }
+    }
+
+    #[test]
+    fn test_sample_cube() {
+        let image_view = unsafe {
+            TestImageView::new(
+                api::VK_IMAGE_VIEW_TYPE_CUBE_ARRAY,
+                api::VK_FORMAT_R8G8B8A8_UNORM,
+                IDENTITY_MAPPING,
+            )
+        };
+        // the red component of each texel is its layer
+        for layer in 0..12 {
+            for y in 0..4 {
+                for x in 0..4 {
+                    unsafe {
+                        *image_view.view.get_texel_pointer([x, y, 0], layer, 0) = layer as u8;
+                    }
+                }
+            }
+        }
+        let sampler = make_nearest_sampler();
+        let directions = [
+            [1.0, 0.5, -0.5],
+            [-1.0, 0.5, 0.5],
+            [0.5, 1.0, 0.5],
+            [0.5, -1.0, -0.5],
+            [0.5, -0.5, 1.0],
+            [-0.5, 0.5, -1.0],
+        ];
+        for cube in 0..2 {
+            for (face, direction) in directions.iter().enumerate() {
+                let coordinates = [direction[0], direction[1], direction[2], cube as f32];
+                let mut result = [f32::NAN; 4];
+                unsafe {
+                    sample_explicit_lod(
+                        image_view.get_handle(),
+                        &sampler as *const Sampler as SamplerHandle,
+                        &coordinates,
+                        0.0,
+                        &mut result,
+                    );
+                }
+                assert_eq!(
+                    result[0],
+                    f32::from((cube * 6 + face) as u8) / 255.0,
+                    "coordinates = {:?}",
+                    coordinates
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_fetch_stencil() {
+        let mut image_view = unsafe {
+            TestImageView::new(
+                api::VK_IMAGE_VIEW_TYPE_2D,
+                api::VK_FORMAT_D24_UNORM_S8_UINT,
+                IDENTITY_MAPPING,
+            )
+        };
+        image_view.view.subresource_range.aspectMask = api::VK_IMAGE_ASPECT_STENCIL_BIT;
+        assert_eq!(
+            fetch_texel(image_view.get_handle(), [1, 1, 0, 0], 0),
+            [f32::from(0x3Fu8), 0.0, 0.0, 1.0]
+        );
+    }
+}
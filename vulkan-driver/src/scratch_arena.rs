@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! bump allocation for the temporary data of a command buffer
+//!
+//! recording and running commands needs short-lived memory, like the handles a command validates,
+//! the barriers and clear values it copies before encoding them, and the descriptor and push
+//! constant snapshots a draw runs with. a `ScratchArena` hands that memory out by bumping an
+//! offset into a chunk, instead of going to the heap for every command. nothing allocated from an
+//! arena is dropped, so it only holds `Copy` types, and resetting the arena frees everything at
+//! once. the largest chunk is kept, so a command buffer that's recorded the same way every frame
+//! stops allocating after the first frame.
+
+use std::alloc::{self, Layout};
+use std::cell::{Cell, UnsafeCell};
+use std::mem;
+use std::ptr::{self, NonNull};
+use std::slice;
+
+/// the size of the first chunk; each chunk after that is at least twice as big as the last one
+pub const MIN_CHUNK_SIZE: usize = 4096;
+
+/// the alignment of every chunk, enough for everything allocated from the arena, including
+/// `PushConstantBlock`
+pub const MAX_ALIGNMENT: usize = 32;
+
+pub struct ScratchArena {
+    /// allocated with `chunk_layout`. only the last chunk is allocated from
+    chunks: UnsafeCell<Vec<(NonNull<u8>, usize)>>,
+    /// the number of bytes used in the last chunk
+    used: Cell<usize>,
+}
+
+// the chunks are only reachable through the arena, so it can be moved to another thread
+unsafe impl Send for ScratchArena {}
+
+impl Default for ScratchArena {
+    fn default() -> Self {
+        ScratchArena {
+            chunks: UnsafeCell::new(Vec::new()),
+            used: Cell::new(0),
+        }
+    }
+}
+
+fn chunk_layout(size: usize) -> Layout {
+    Layout::from_size_align(size, MAX_ALIGNMENT).unwrap()
+}
+
+// allocations only borrow the arena immutably, so they can be made while earlier allocations are
+// still in use; they never overlap
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::mut_from_ref))]
+impl ScratchArena {
+    fn allocate_bytes(&self, size: usize, alignment: usize) -> NonNull<u8> {
+        assert!(alignment <= MAX_ALIGNMENT);
+        // the `Vec` isn't borrowed outside of this function, only the chunks it points to
+        let chunks = unsafe { &mut *self.chunks.get() };
+        if let Some(&(chunk, chunk_size)) = chunks.last() {
+            let start = self.used.get().next_multiple_of(alignment);
+            if start + size <= chunk_size {
+                self.used.set(start + size);
+                return unsafe { NonNull::new_unchecked(chunk.as_ptr().add(start)) };
+            }
+        }
+        let last_chunk_size = chunks.last().map_or(0, |&(_, chunk_size)| chunk_size);
+        let chunk_size = (last_chunk_size * 2)
+            .max(MIN_CHUNK_SIZE)
+            .max(size.next_multiple_of(MAX_ALIGNMENT));
+        let layout = chunk_layout(chunk_size);
+        let chunk = NonNull::new(unsafe { alloc::alloc(layout) })
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        chunks.push((chunk, chunk_size));
+        self.used.set(size);
+        chunk
+    }
+    pub fn alloc<T: Copy>(&self, value: T) -> &mut T {
+        let pointer = self
+            .allocate_bytes(mem::size_of::<T>(), mem::align_of::<T>())
+            .cast::<T>();
+        unsafe {
+            ptr::write(pointer.as_ptr(), value);
+            &mut *pointer.as_ptr()
+        }
+    }
+    pub fn alloc_slice_copy<T: Copy>(&self, values: &[T]) -> &mut [T] {
+        self.alloc_slice_from_iter(values.len(), values.iter().cloned())
+    }
+    /// `values` has to have exactly `len` items
+    pub fn alloc_slice_from_iter<T: Copy, I: IntoIterator<Item = T>>(
+        &self,
+        len: usize,
+        values: I,
+    ) -> &mut [T] {
+        if len == 0 {
+            assert!(values.into_iter().next().is_none());
+            return &mut [];
+        }
+        let pointer = self
+            .allocate_bytes(mem::size_of::<T>() * len, mem::align_of::<T>())
+            .cast::<T>();
+        let mut values = values.into_iter();
+        for index in 0..len {
+            let value = values.next().expect("too few values");
+            unsafe { ptr::write(pointer.as_ptr().add(index), value) };
+        }
+        assert!(values.next().is_none(), "too many values");
+        unsafe { slice::from_raw_parts_mut(pointer.as_ptr(), len) }
+    }
+    /// free everything allocated from the arena, keeping the largest chunk for reuse
+    pub fn reset(&mut self) {
+        let chunks = self.chunks.get_mut();
+        let last = chunks.pop();
+        for (chunk, chunk_size) in chunks.drain(..) {
+            unsafe { alloc::dealloc(chunk.as_ptr(), chunk_layout(chunk_size)) };
+        }
+        chunks.extend(last);
+        self.used.set(0);
+    }
+    /// the total size of the chunks
+    #[allow(dead_code)]
+    pub fn capacity(&self) -> usize {
+        unsafe { &*self.chunks.get() }
+            .iter()
+            .map(|&(_, chunk_size)| chunk_size)
+            .sum()
+    }
+}
+
+impl Drop for ScratchArena {
+    fn drop(&mut self) {
+        for (chunk, chunk_size) in self.chunks.get_mut().drain(..) {
+            unsafe { alloc::dealloc(chunk.as_ptr(), chunk_layout(chunk_size)) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    #[repr(align(32))]
+    struct Aligned(u8);
+
+    #[test]
+    fn test_scratch_arena() {
+        let mut arena = ScratchArena::default();
+        assert_eq!(arena.capacity(), 0);
+        let byte = arena.alloc(1u8);
+        let aligned = arena.alloc(Aligned(2));
+        let words = arena.alloc_slice_copy(&[3u32, 4, 5]);
+        let empty = arena.alloc_slice_from_iter::<u64, _>(0, None);
+        assert_eq!(aligned as *const Aligned as usize % 32, 0);
+        assert_eq!(
+            (*byte, *aligned, &*words, &*empty),
+            (1, Aligned(2), &[3, 4, 5][..], &[][..])
+        );
+        // doesn't fit in the first chunk
+        let bytes = (0..MIN_CHUNK_SIZE).map(|v| v as u8);
+        let big = arena.alloc_slice_from_iter(MIN_CHUNK_SIZE, bytes);
+        assert_eq!(big[MIN_CHUNK_SIZE - 1], (MIN_CHUNK_SIZE - 1) as u8);
+        for index in 0..1000u32 {
+            assert_eq!(*arena.alloc(index), index);
+        }
+        // earlier allocations aren't overwritten
+        assert_eq!(*byte, 1);
+        assert_eq!(words, [3, 4, 5]);
+        let capacity = arena.capacity();
+        assert!(capacity > MIN_CHUNK_SIZE * 2);
+        arena.reset();
+        let largest_chunk = arena.capacity();
+        assert!(largest_chunk >= MIN_CHUNK_SIZE && largest_chunk < capacity);
+        // the kept chunk is reused, so the same allocations don't need more memory
+        arena.alloc_slice_copy(&[0u8; MIN_CHUNK_SIZE]);
+        assert_eq!(arena.capacity(), largest_chunk);
+    }
+}
@@ -1,5 +1,95 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later
 // Copyright 2018 Jacob Lifshay
+use compile_thread_pool::{CompileJob, CompilePriority, CompileThreadPool};
+use pipeline::{self, PipelineError, ShaderStage, ShaderStageSource};
+use shader_compiler::interface::get_entry_points;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+enum WarmedStageState {
+    Creating(CompileJob<Result<ShaderStage, PipelineError>>),
+    /// `None` if the stage couldn't be created; pipeline creation creates it again to report the
+    /// error
+    Created(Option<ShaderStage>),
+}
+
+/// a stage made from one of the module's entry points without specialization or any other
+/// options, created in the background when the module is created, so pipelines made from the
+/// entry point don't have to wait for it
+struct WarmedStage {
+    source: ShaderStageSource,
+    state: Mutex<WarmedStageState>,
+}
+
 pub struct ShaderModule {
-    pub code: Vec<u32>,
+    /// shared with the `ShaderStageSource`s of the pipelines created from the module
+    pub code: Arc<Vec<u32>>,
+    warmed_stages: Vec<WarmedStage>,
+}
+
+impl ShaderModule {
+    /// starts creating the stages of every entry point in `code` on `compile_thread_pool`.
+    /// `watchdog_checks` is from the driver configuration
+    pub fn new(
+        code: Vec<u32>,
+        compile_thread_pool: &CompileThreadPool,
+        watchdog_checks: bool,
+    ) -> Self {
+        let code = Arc::new(code);
+        // an invalid module is reported when a pipeline is created from it
+        let entry_points = get_entry_points(&code).unwrap_or_default();
+        let warmed_stages = entry_points
+            .into_iter()
+            .map(|(execution_model, entry_point_name)| {
+                let source = ShaderStageSource::for_entry_point(
+                    pipeline::get_shader_stage(execution_model),
+                    code.clone(),
+                    entry_point_name,
+                );
+                let job = {
+                    let source = source.clone();
+                    compile_thread_pool.submit(CompilePriority::Background, move || {
+                        ShaderStage::new(source, watchdog_checks)
+                    })
+                };
+                WarmedStage {
+                    source,
+                    state: Mutex::new(WarmedStageState::Creating(job)),
+                }
+            })
+            .collect();
+        ShaderModule {
+            code,
+            warmed_stages,
+        }
+    }
+    /// a copy of the stage created in the background that's made from `source`. if it isn't
+    /// created yet, waits for it if `wait` is true, otherwise returns `None`. also returns `None`
+    /// if no stage is made from `source` or it couldn't be created
+    pub fn get_warmed_stage(&self, source: &ShaderStageSource, wait: bool) -> Option<ShaderStage> {
+        let warmed_stage = self.warmed_stages.iter().find(|v| v.source == *source)?;
+        let mut state = warmed_stage.state.lock().unwrap();
+        if let WarmedStageState::Creating(_) = *state {
+            let job = match mem::replace(&mut *state, WarmedStageState::Created(None)) {
+                WarmedStageState::Creating(job) => job,
+                WarmedStageState::Created(_) => unreachable!(),
+            };
+            let result = if wait {
+                job.wait()
+            } else {
+                match job.try_get() {
+                    Ok(result) => result,
+                    Err(job) => {
+                        *state = WarmedStageState::Creating(job);
+                        return None;
+                    }
+                }
+            };
+            *state = WarmedStageState::Created(result.ok().and_then(Result::ok));
+        }
+        match &*state {
+            WarmedStageState::Created(stage) => stage.as_ref().map(ShaderStage::copy_for_pipeline),
+            WarmedStageState::Creating(_) => unreachable!(),
+        }
+    }
 }
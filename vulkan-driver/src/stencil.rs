@@ -0,0 +1,159 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! the stencil test and the stencil values written after it
+//!
+//! the reference value is the pipeline's or the one set by `vkCmdSetStencilReference`, unless
+//! the fragment shader writes `FragStencilRefEXT` (`VK_EXT_shader_stencil_export`), in which case
+//! each fragment has its own. `VK_EXT_shader_stencil_export` isn't advertised until draws run the
+//! stencil test through here.
+
+use api;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum StencilOp {
+    Keep,
+    Zero,
+    Replace,
+    IncrementAndClamp,
+    DecrementAndClamp,
+    Invert,
+    IncrementAndWrap,
+    DecrementAndWrap,
+}
+
+impl StencilOp {
+    pub fn new(op: api::VkStencilOp) -> Self {
+        match op {
+            api::VK_STENCIL_OP_KEEP => StencilOp::Keep,
+            api::VK_STENCIL_OP_ZERO => StencilOp::Zero,
+            api::VK_STENCIL_OP_REPLACE => StencilOp::Replace,
+            api::VK_STENCIL_OP_INCREMENT_AND_CLAMP => StencilOp::IncrementAndClamp,
+            api::VK_STENCIL_OP_DECREMENT_AND_CLAMP => StencilOp::DecrementAndClamp,
+            api::VK_STENCIL_OP_INVERT => StencilOp::Invert,
+            api::VK_STENCIL_OP_INCREMENT_AND_WRAP => StencilOp::IncrementAndWrap,
+            api::VK_STENCIL_OP_DECREMENT_AND_WRAP => StencilOp::DecrementAndWrap,
+            _ => panic!("invalid stencil op: {}", op),
+        }
+    }
+    /// the new stencil value; `reference` is already truncated to the stencil bits
+    #[allow(dead_code)]
+    pub fn apply(self, stencil: u8, reference: u8) -> u8 {
+        match self {
+            StencilOp::Keep => stencil,
+            StencilOp::Zero => 0,
+            StencilOp::Replace => reference,
+            StencilOp::IncrementAndClamp => stencil.saturating_add(1),
+            StencilOp::DecrementAndClamp => stencil.saturating_sub(1),
+            StencilOp::Invert => !stencil,
+            StencilOp::IncrementAndWrap => stencil.wrapping_add(1),
+            StencilOp::DecrementAndWrap => stencil.wrapping_sub(1),
+        }
+    }
+}
+
+/// the stencil state of one face
+#[allow(dead_code)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct StencilFaceState {
+    pub fail_op: StencilOp,
+    pub pass_op: StencilOp,
+    pub depth_fail_op: StencilOp,
+    pub compare_op: api::VkCompareOp,
+    pub compare_mask: u32,
+    pub write_mask: u32,
+    /// used unless the fragment shader writes `FragStencilRefEXT`
+    pub reference: u32,
+}
+
+impl StencilFaceState {
+    pub fn new(state: &api::VkStencilOpState) -> Self {
+        StencilFaceState {
+            fail_op: StencilOp::new(state.failOp),
+            pass_op: StencilOp::new(state.passOp),
+            depth_fail_op: StencilOp::new(state.depthFailOp),
+            compare_op: state.compareOp,
+            compare_mask: state.compareMask,
+            write_mask: state.writeMask,
+            reference: state.reference,
+        }
+    }
+    /// the reference value is on the left of the comparison
+    #[allow(dead_code)]
+    pub fn test(&self, stencil: u8, reference: u32) -> bool {
+        let reference = reference & self.compare_mask;
+        let stencil = u32::from(stencil) & self.compare_mask;
+        match self.compare_op {
+            api::VK_COMPARE_OP_NEVER => false,
+            api::VK_COMPARE_OP_LESS => reference < stencil,
+            api::VK_COMPARE_OP_EQUAL => reference == stencil,
+            api::VK_COMPARE_OP_LESS_OR_EQUAL => reference <= stencil,
+            api::VK_COMPARE_OP_GREATER => reference > stencil,
+            api::VK_COMPARE_OP_NOT_EQUAL => reference != stencil,
+            api::VK_COMPARE_OP_GREATER_OR_EQUAL => reference >= stencil,
+            api::VK_COMPARE_OP_ALWAYS => true,
+            _ => panic!("invalid compare op: {}", self.compare_op),
+        }
+    }
+    /// the stencil value written for a fragment. `reference` is the fragment's reference value,
+    /// from `GraphicsPipeline::get_stencil_reference`, and `depth_passed` is `None` if the
+    /// stencil test failed, so the depth test wasn't run
+    #[allow(dead_code)]
+    pub fn get_written_value(&self, stencil: u8, reference: u32, depth_passed: Option<bool>) -> u8 {
+        let op = match depth_passed {
+            None => self.fail_op,
+            Some(false) => self.depth_fail_op,
+            Some(true) => self.pass_op,
+        };
+        let write_mask = self.write_mask as u8;
+        op.apply(stencil, reference as u8) & write_mask | stencil & !write_mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn face_state(compare_op: api::VkCompareOp, pass_op: StencilOp) -> StencilFaceState {
+        StencilFaceState {
+            fail_op: StencilOp::Zero,
+            pass_op,
+            depth_fail_op: StencilOp::IncrementAndClamp,
+            compare_op,
+            compare_mask: 0x0F,
+            write_mask: 0xFF,
+            reference: 3,
+        }
+    }
+
+    #[test]
+    fn test_stencil_ops() {
+        assert_eq!(StencilOp::IncrementAndClamp.apply(0xFF, 0), 0xFF);
+        assert_eq!(StencilOp::IncrementAndWrap.apply(0xFF, 0), 0);
+        assert_eq!(StencilOp::DecrementAndClamp.apply(0, 0), 0);
+        assert_eq!(StencilOp::DecrementAndWrap.apply(0, 0), 0xFF);
+        assert_eq!(StencilOp::Invert.apply(0x0F, 0), 0xF0);
+        assert_eq!(StencilOp::Replace.apply(1, 7), 7);
+    }
+
+    #[test]
+    fn test_stencil_test() {
+        let state = face_state(api::VK_COMPARE_OP_LESS, StencilOp::Keep);
+        assert!(state.test(4, 3));
+        assert!(!state.test(3, 3));
+        // only the compare mask's bits are compared
+        assert!(!state.test(0x13, 0x23));
+        assert!(face_state(api::VK_COMPARE_OP_EQUAL, StencilOp::Keep).test(0x13, 0x23));
+    }
+
+    #[test]
+    fn test_written_value() {
+        let mut state = face_state(api::VK_COMPARE_OP_ALWAYS, StencilOp::Replace);
+        // a reference exported by the fragment shader replaces the pipeline's
+        assert_eq!(state.get_written_value(1, 0x1A5, Some(true)), 0xA5);
+        assert_eq!(state.get_written_value(1, 0x1A5, Some(false)), 2);
+        assert_eq!(state.get_written_value(1, 0x1A5, None), 0);
+        state.write_mask = 0x0F;
+        assert_eq!(state.get_written_value(0x30, 0xA5, Some(true)), 0x35);
+    }
+}
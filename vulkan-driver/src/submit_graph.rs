@@ -0,0 +1,262 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! running the command buffers of a `vkQueueSubmit` out of order
+//!
+//! command buffers only have to run in submission order when something orders them:
+//!
+//! * a batch that waits on a semaphore signaled by an earlier batch of the same submission runs
+//!   after every command buffer up to the signal, since a semaphore signal includes every command
+//!   submitted before it.
+//! * a command buffer with ordered commands (see `CommandStream::has_ordered_commands`), such as
+//!   a pipeline barrier, runs after every command buffer submitted before it and before every one
+//!   submitted after it.
+//!
+//! each submission is turned into a graph with a node per command buffer and an edge for each of
+//! those dependencies. the nodes are run in waves on the device's submit threads: a wave is the
+//! nodes whose dependencies are all in earlier waves, and it starts once the wave before it has
+//! finished. so independent command buffers, such as ones recorded by different application
+//! threads, run in parallel, and a submit thread never waits for another node, which could take
+//! every thread of the pool when queues submit at the same time.
+//!
+//! the graph only covers one `vkQueueSubmit`. submissions finish before `vkQueueSubmit` returns,
+//! so semaphores signaled by earlier submissions are already signaled and don't need a graph
+//! across submissions, and the fence is signaled once every node has finished. events aren't
+//! implemented yet, so they don't order anything; `vkCmdWaitEvents` will have to be an ordered
+//! command when they are.
+
+use compile_thread_pool::{CompilePriority, CompileThreadPool};
+use std::env;
+use std::panic;
+use sys_info;
+
+/// the number of threads running the command buffers of submissions; 1 runs them in submission
+/// order on the submitting thread
+pub const SUBMIT_THREADS_ENV_VAR: &str = "KAZAN_SUBMIT_THREADS";
+
+/// `KAZAN_SUBMIT_THREADS`, which defaults to the cores the compile threads leave free
+pub fn get_submit_thread_count() -> usize {
+    let cpu_count = sys_info::cpu_num().map(|v| v as usize).unwrap_or(1);
+    let default = cpu_count
+        .saturating_sub(CompileThreadPool::default_thread_count())
+        .max(1);
+    let value = match env::var(SUBMIT_THREADS_ENV_VAR) {
+        Ok(value) => value,
+        Err(_) => return default,
+    };
+    match value.parse::<usize>() {
+        Ok(thread_count) if thread_count != 0 => thread_count,
+        _ => {
+            eprintln!(
+                "{} must be a number that's at least 1, ignoring: {:?}",
+                SUBMIT_THREADS_ENV_VAR, value
+            );
+            default
+        }
+    }
+}
+
+struct Node<T> {
+    value: T,
+    /// the nodes that have to finish first, which are all earlier nodes
+    dependencies: Vec<usize>,
+}
+
+/// the command buffers of a submission and what each has to run after. `S` is the semaphore type
+pub struct SubmitGraph<T, S> {
+    nodes: Vec<Node<T>>,
+    /// the semaphores signaled so far and not waited on yet, with the number of nodes added
+    /// before each signal. submissions have few semaphores, so this isn't a map
+    signaled_semaphores: Vec<(S, usize)>,
+    /// the nodes before this are waited on by a semaphore, so every node added from now on
+    /// depends on them
+    waited_node_count: usize,
+    /// the last node with ordered commands, which every later node depends on
+    last_ordered_node: Option<usize>,
+}
+
+impl<T, S> Default for SubmitGraph<T, S> {
+    fn default() -> Self {
+        SubmitGraph {
+            nodes: Vec::new(),
+            signaled_semaphores: Vec::new(),
+            waited_node_count: 0,
+            last_ordered_node: None,
+        }
+    }
+}
+
+impl<T, S: PartialEq> SubmitGraph<T, S> {
+    /// start a batch that waits on `semaphores`
+    pub fn wait_semaphores<I: IntoIterator<Item = S>>(&mut self, semaphores: I) {
+        for semaphore in semaphores {
+            // semaphores that weren't signaled by this submission were signaled by an earlier
+            // submission, which has already finished
+            if let Some(index) = self
+                .signaled_semaphores
+                .iter()
+                .rposition(|v| v.0 == semaphore)
+            {
+                let (_, node_count) = self.signaled_semaphores.remove(index);
+                self.waited_node_count = self.waited_node_count.max(node_count);
+            }
+        }
+    }
+    /// add a command buffer to the current batch. `has_ordered_commands` is from
+    /// `CommandStream::has_ordered_commands`
+    pub fn add(&mut self, value: T, has_ordered_commands: bool) {
+        let index = self.nodes.len();
+        // the last ordered node depends on everything before it
+        let start = self.last_ordered_node.map_or(0, |node| node + 1);
+        let end = if has_ordered_commands {
+            index
+        } else {
+            self.waited_node_count
+        };
+        let dependencies = self
+            .last_ordered_node
+            .into_iter()
+            .chain(start..end)
+            .collect();
+        if has_ordered_commands {
+            self.last_ordered_node = Some(index);
+        }
+        self.nodes.push(Node {
+            value,
+            dependencies,
+        });
+    }
+    /// end the current batch, signaling `semaphores`
+    pub fn signal_semaphores<I: IntoIterator<Item = S>>(&mut self, semaphores: I) {
+        let node_count = self.nodes.len();
+        self.signaled_semaphores.extend(
+            semaphores
+                .into_iter()
+                .map(|semaphore| (semaphore, node_count)),
+        );
+    }
+    /// run `run_node` on every node, on `thread_pool` if there is one, and in submission order on
+    /// the current thread otherwise. once a node fails, the waves that haven't started yet are
+    /// skipped. returns the first failure in submission order
+    pub fn run<E, F>(&self, thread_pool: Option<&CompileThreadPool>, run_node: F) -> Result<(), E>
+    where
+        T: Sync,
+        E: Send,
+        F: Fn(&T) -> Result<(), E> + Sync,
+    {
+        let thread_pool = match thread_pool {
+            Some(thread_pool) if self.nodes.len() > 1 => thread_pool,
+            _ => {
+                for node in &self.nodes {
+                    run_node(&node.value)?;
+                }
+                return Ok(());
+            }
+        };
+        let (nodes, run_node) = (&self.nodes, &run_node);
+        for wave in self.waves() {
+            let results = thread_pool.run_all(
+                CompilePriority::Blocking,
+                wave.into_iter()
+                    .map(|node| move || run_node(&nodes[node].value)),
+            );
+            // a panic in `run_node` unwinds from here, the same as when the nodes are run on the
+            // current thread
+            results
+                .into_iter()
+                .map(|result| result.unwrap_or_else(|payload| panic::resume_unwind(payload)))
+                .collect::<Result<(), E>>()?;
+        }
+        Ok(())
+    }
+    /// the nodes of each wave, in submission order. a node is in the wave after the last wave
+    /// of its dependencies
+    fn waves(&self) -> Vec<Vec<usize>> {
+        let mut node_waves: Vec<usize> = Vec::with_capacity(self.nodes.len());
+        let mut waves: Vec<Vec<usize>> = Vec::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            let wave = node
+                .dependencies
+                .iter()
+                .map(|&dependency| node_waves[dependency] + 1)
+                .max()
+                .unwrap_or(0);
+            node_waves.push(wave);
+            if wave == waves.len() {
+                waves.push(Vec::new());
+            }
+            waves[wave].push(index);
+        }
+        waves
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn get_dependencies<T, S>(graph: &SubmitGraph<T, S>) -> Vec<Vec<usize>> {
+        graph
+            .nodes
+            .iter()
+            .map(|node| node.dependencies.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let mut graph = SubmitGraph::default();
+        graph.add(0, false);
+        graph.add(1, false);
+        graph.signal_semaphores(vec!["a"]);
+        graph.add(2, false);
+        graph.signal_semaphores(vec!["b"]);
+        // "c" was signaled by an earlier submission
+        graph.wait_semaphores(vec!["a", "c"]);
+        graph.add(3, false);
+        graph.add(4, true);
+        graph.add(5, false);
+        graph.wait_semaphores(vec!["b"]);
+        graph.add(6, false);
+        assert_eq!(
+            get_dependencies(&graph),
+            [
+                vec![],
+                vec![],
+                vec![],
+                vec![0, 1],
+                vec![0, 1, 2, 3],
+                vec![4],
+                vec![4],
+            ]
+        );
+        assert_eq!(graph.waves(), [vec![0, 1, 2], vec![3], vec![4], vec![5, 6]]);
+    }
+
+    #[test]
+    fn test_run() {
+        let thread_pool = CompileThreadPool::new(4);
+        let mut graph = SubmitGraph::<_, ()>::default();
+        for index in 0..8 {
+            graph.add(index, index == 5);
+        }
+        let started = Mutex::new(Vec::new());
+        let result = graph.run(Some(&thread_pool), |&index| {
+            started.lock().unwrap().push(index);
+            if index == 3 {
+                Err(index)
+            } else {
+                Ok(())
+            }
+        });
+        assert_eq!(result, Err(3));
+        // node 5 waits for node 3, so it and the nodes after it are skipped
+        let mut started = started.into_inner().unwrap();
+        started.sort();
+        assert_eq!(started[..4], [0, 1, 2, 3]);
+        assert!(!started.contains(&5) && !started.contains(&7));
+        let result = graph.run(None, |&index| if index == 6 { Err(index) } else { Ok(()) });
+        assert_eq!(result, Err(6));
+    }
+}
@@ -1,12 +1,20 @@
 // SPDX-License-Identifier: LGPL-2.1-or-later
 // Copyright 2018 Jacob Lifshay
+// the swapchain handle type needs this module even when no surface is compiled in
+#![cfg_attr(not(all(unix, feature = "wsi-xcb")), allow(dead_code))]
 use api;
+#[cfg(unix)]
+use libc;
 use std::any::Any;
 use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt::{self, Debug};
 use std::ptr::NonNull;
-#[cfg(unix)]
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+#[cfg(all(unix, feature = "wsi-xcb"))]
 use xcb_swapchain::XcbSurfaceImplementation;
 
 #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Enum)]
@@ -50,10 +58,10 @@ impl SurfacePlatform {
         }
     }
     pub fn get_surface_implementation(self) -> Cow<'static, dyn SurfaceImplementation> {
-        #[cfg(unix)]
+        #[cfg(all(unix, feature = "wsi-xcb"))]
         const XCB_SURFACE_IMPLEMENTATION: XcbSurfaceImplementation = XcbSurfaceImplementation;
         match self {
-            #[cfg(unix)]
+            #[cfg(all(unix, feature = "wsi-xcb"))]
             SurfacePlatform::VK_ICD_WSI_PLATFORM_XCB => Cow::Borrowed(&XCB_SURFACE_IMPLEMENTATION),
             _ => Cow::Owned(FallbackSurfaceImplementation(self).duplicate()),
         }
@@ -76,7 +84,118 @@ impl From<SurfacePlatform> for api::VkIcdWsiPlatform {
     }
 }
 
-pub trait Swapchain: Any + Sync + Send + Debug {}
+/// the refresh duration reported by `vkGetRefreshCycleDurationGOOGLE`, in nanoseconds. images
+/// are copied to the surface as soon as they're presented instead of at vertical blank, so this
+/// is only the granularity that applications schedule `desiredPresentTime` with
+pub const DEFAULT_REFRESH_DURATION: u64 = 16_666_667;
+
+/// the oldest timings are dropped when the application doesn't get them
+const MAX_PAST_PRESENT_TIMINGS: usize = 64;
+
+/// the current time in nanoseconds on the clock that `VK_GOOGLE_display_timing` uses
+#[cfg(unix)]
+pub fn get_present_time() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe {
+        libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time);
+    }
+    time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64
+}
+
+#[cfg(not(unix))]
+pub fn get_present_time() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    time.as_secs() * 1_000_000_000 + u64::from(time.subsec_nanos())
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct PastPresentTiming {
+    /// 0 if the present had no `VkPresentTimeGOOGLE`
+    pub present_id: u32,
+    pub desired_present_time: u64,
+    pub actual_present_time: u64,
+    pub earliest_present_time: u64,
+    pub present_margin: u64,
+}
+
+impl From<PastPresentTiming> for api::VkPastPresentationTimingGOOGLE {
+    fn from(v: PastPresentTiming) -> api::VkPastPresentationTimingGOOGLE {
+        api::VkPastPresentationTimingGOOGLE {
+            presentID: v.present_id,
+            desiredPresentTime: v.desired_present_time,
+            actualPresentTime: v.actual_present_time,
+            earliestPresentTime: v.earliest_present_time,
+            presentMargin: v.present_margin,
+        }
+    }
+}
+
+/// the timings of presents that haven't been returned by `vkGetPastPresentationTimingGOOGLE`
+#[derive(Debug, Default)]
+pub struct PresentTimingHistory {
+    timings: Mutex<VecDeque<PastPresentTiming>>,
+}
+
+impl PresentTimingHistory {
+    pub fn record(&self, timing: PastPresentTiming) {
+        let timings = &mut *self.timings.lock().unwrap();
+        if timings.len() >= MAX_PAST_PRESENT_TIMINGS {
+            timings.pop_front();
+        }
+        timings.push_back(timing);
+    }
+    pub fn len(&self) -> usize {
+        self.timings.lock().unwrap().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.timings.lock().unwrap().is_empty()
+    }
+    /// remove and return up to `max_count` of the oldest timings
+    pub fn take(&self, max_count: usize) -> Vec<PastPresentTiming> {
+        let timings = &mut *self.timings.lock().unwrap();
+        let count = max_count.min(timings.len());
+        timings.drain(..count).collect()
+    }
+}
+
+pub trait Swapchain: Any + Sync + Send + Debug {
+    /// copy the image at `image_index` to the surface
+    unsafe fn present_image(&self, image_index: u32) -> Result<(), api::VkResult>;
+    fn get_present_timing_history(&self) -> &PresentTimingHistory;
+    fn get_refresh_duration(&self) -> u64 {
+        DEFAULT_REFRESH_DURATION
+    }
+    /// present the image at `image_index`, waiting until `present_time`'s `desiredPresentTime`,
+    /// and record when it was presented
+    unsafe fn present(
+        &self,
+        image_index: u32,
+        present_time: Option<api::VkPresentTimeGOOGLE>,
+    ) -> Result<(), api::VkResult> {
+        let (present_id, desired_present_time) = present_time.map_or((0, 0), |present_time| {
+            (present_time.presentID, present_time.desiredPresentTime)
+        });
+        let queued_time = get_present_time();
+        if desired_present_time > queued_time {
+            thread::sleep(Duration::from_nanos(desired_present_time - queued_time));
+        }
+        self.present_image(image_index)?;
+        let actual_present_time = get_present_time();
+        self.get_present_timing_history().record(PastPresentTiming {
+            present_id,
+            desired_present_time,
+            actual_present_time,
+            // nothing but the wait for `desiredPresentTime` delays presenting
+            earliest_present_time: actual_present_time,
+            present_margin: desired_present_time.saturating_sub(queued_time),
+        });
+        Ok(())
+    }
+}
 
 pub trait SurfaceImplementation: Any + Sync + Send + Debug {
     fn get_platform(&self) -> SurfacePlatform;
@@ -156,3 +275,31 @@ impl SurfaceImplementation for FallbackSurfaceImplementation {
         Box::new(FallbackSurfaceImplementation(self.0))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(present_id: u32) -> PastPresentTiming {
+        PastPresentTiming {
+            present_id,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_present_timing_history() {
+        let history = PresentTimingHistory::default();
+        for present_id in 0..MAX_PAST_PRESENT_TIMINGS as u32 + 2 {
+            history.record(timing(present_id));
+        }
+        // the oldest timings were dropped
+        assert_eq!(history.len(), MAX_PAST_PRESENT_TIMINGS);
+        assert_eq!(history.take(2), [timing(2), timing(3)]);
+        assert_eq!(
+            history.take(usize::max_value()).len(),
+            MAX_PAST_PRESENT_TIMINGS - 2
+        );
+        assert!(history.is_empty());
+    }
+}
@@ -0,0 +1,252 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! CPU timing of submitted work, for finding which pass dominates the frame time
+//!
+//! when `KAZAN_TRACE` is set to a file name, each submitted command buffer and each pass and
+//! dispatch in it are written to that file in the Chrome trace event format, which can be opened
+//! in `chrome://tracing` or Perfetto. each pass is broken down into the time spent in each
+//! `TraceStage`, both as nested events and as totals in the pass's arguments.
+//!
+//! the array of events is never closed, which the format allows, so the file can be read while
+//! the application is still running or after it crashes.
+
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// the file to write the trace to; every device truncates it
+pub const TRACE_ENV_VAR: &str = "KAZAN_TRACE";
+
+pub fn get_trace_path() -> Option<PathBuf> {
+    let path = env::var_os(TRACE_ENV_VAR)?;
+    if path.is_empty() {
+        None
+    } else {
+        Some(path.into())
+    }
+}
+
+/// the parts of a pass that are timed separately, in the order of `VALUES`. kazan only runs its
+/// own dispatches so far; the rasterizer's stages get added along with draws
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TraceStage {
+    Dispatch,
+}
+
+impl TraceStage {
+    pub const VALUES: [TraceStage; 1] = [TraceStage::Dispatch];
+    pub fn name(self) -> &'static str {
+        match self {
+            TraceStage::Dispatch => "dispatch",
+        }
+    }
+}
+
+fn write_json_string(output: &mut String, value: &str) {
+    output.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            c if c < ' ' => write!(output, "\\u{:04x}", c as u32).unwrap(),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+/// a small number for the current thread, since `ThreadId` can't be converted to one
+fn get_thread_index() -> usize {
+    static NEXT_THREAD_INDEX: AtomicUsize = AtomicUsize::new(1);
+    thread_local! {
+        static THREAD_INDEX: usize = NEXT_THREAD_INDEX.fetch_add(1, Ordering::Relaxed);
+    }
+    THREAD_INDEX.with(|v| *v)
+}
+
+fn to_microseconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1e6 + f64::from(duration.subsec_nanos()) / 1e3
+}
+
+pub struct Tracer {
+    output: Mutex<Box<dyn Write + Send>>,
+    /// the time trace timestamps are relative to
+    start: Instant,
+}
+
+impl Tracer {
+    pub fn new(mut output: Box<dyn Write + Send>) -> io::Result<Self> {
+        output.write_all(b"[\n")?;
+        Ok(Tracer {
+            output: Mutex::new(output),
+            start: Instant::now(),
+        })
+    }
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Self::new(Box::new(BufWriter::new(File::create(path)?)))
+    }
+    /// create the tracer for `KAZAN_TRACE`, reporting errors rather than failing
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match Self::create(path) {
+            Ok(tracer) => Some(tracer),
+            Err(error) => {
+                eprintln!("kazan: can't write {}: {}", path.display(), error);
+                None
+            }
+        }
+    }
+    /// write a complete event, with `args` as milliseconds
+    fn write_event(
+        &self,
+        name: &str,
+        category: &str,
+        start: Instant,
+        end: Instant,
+        args: &[(&str, Duration)],
+    ) {
+        let mut event = String::new();
+        event.push_str("{\"name\":");
+        write_json_string(&mut event, name);
+        event.push_str(",\"cat\":");
+        write_json_string(&mut event, category);
+        write!(
+            event,
+            ",\"ph\":\"X\",\"pid\":{},\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}",
+            process::id(),
+            get_thread_index(),
+            to_microseconds(start.duration_since(self.start)),
+            to_microseconds(end.duration_since(start))
+        )
+        .unwrap();
+        if !args.is_empty() {
+            event.push_str(",\"args\":{");
+            for (index, &(name, duration)) in args.iter().enumerate() {
+                if index != 0 {
+                    event.push(',');
+                }
+                write_json_string(&mut event, name);
+                write!(event, ":{:.3}", to_microseconds(duration) / 1e3).unwrap();
+            }
+            event.push('}');
+        }
+        event.push_str("},\n");
+        let mut output = self.output.lock().unwrap();
+        // flushed after every event so the trace survives the process being killed
+        let result = output
+            .write_all(event.as_bytes())
+            .and_then(|_| output.flush());
+        if let Err(error) = result {
+            eprintln!("kazan: can't write trace: {}", error);
+        }
+    }
+}
+
+/// times a pass or a dispatch and its stages. does nothing without a tracer, so it can be used
+/// unconditionally
+pub struct PassTrace<'a> {
+    tracer: Option<&'a Tracer>,
+    name: &'a str,
+    start: Option<Instant>,
+    stage_durations: [Duration; TraceStage::VALUES.len()],
+}
+
+impl<'a> PassTrace<'a> {
+    pub fn begin(tracer: Option<&'a Tracer>, name: &'a str) -> Self {
+        PassTrace {
+            tracer,
+            name,
+            start: tracer.map(|_| Instant::now()),
+            stage_durations: [Duration::from_secs(0); TraceStage::VALUES.len()],
+        }
+    }
+    /// run `f` as part of `stage` of the pass
+    pub fn time<R, F: FnOnce() -> R>(&mut self, stage: TraceStage, f: F) -> R {
+        let tracer = match self.tracer {
+            Some(tracer) => tracer,
+            None => return f(),
+        };
+        let start = Instant::now();
+        let retval = f();
+        let end = Instant::now();
+        tracer.write_event(stage.name(), "stage", start, end, &[]);
+        self.stage_durations[stage as usize] += end.duration_since(start);
+        retval
+    }
+    /// write the pass with the total time of each stage that ran
+    pub fn end(self) {
+        let (tracer, start) = match (self.tracer, self.start) {
+            (Some(tracer), Some(start)) => (tracer, start),
+            _ => return,
+        };
+        let args: Vec<_> = TraceStage::VALUES
+            .iter()
+            .zip(&self.stage_durations)
+            .filter(|&(_, &duration)| duration != Duration::from_secs(0))
+            .map(|(stage, &duration)| (stage.name(), duration))
+            .collect();
+        tracer.write_event(self.name, "pass", start, Instant::now(), &args);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(bytes)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_string() {
+        let mut output = String::new();
+        write_json_string(&mut output, "a \"b\"\\\n\u{1}");
+        assert_eq!(output, r#""a \"b\"\\\n\u0001""#);
+    }
+
+    #[test]
+    fn test_pass_trace() {
+        let output = SharedOutput::default();
+        let tracer = Tracer::new(Box::new(output.clone())).unwrap();
+        let mut pass = PassTrace::begin(Some(&tracer), "pass \"1\"");
+        assert_eq!(pass.time(TraceStage::Dispatch, || 5), 5);
+        // long enough that the stage's total isn't 0
+        pass.time(TraceStage::Dispatch, || {
+            thread::sleep(Duration::from_millis(1))
+        });
+        pass.end();
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<_> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "[");
+        assert!(lines[1].starts_with(r#"{"name":"dispatch","cat":"stage","ph":"X","#));
+        assert!(lines[2].starts_with(r#"{"name":"dispatch","cat":"stage","ph":"X","#));
+        assert!(lines[3].starts_with(r#"{"name":"pass \"1\"","cat":"pass","ph":"X","#));
+        assert!(lines[3].contains(r#""dispatch":"#));
+        assert!(lines.iter().skip(1).all(|line| line.ends_with("},")));
+    }
+
+    #[test]
+    fn test_pass_trace_without_tracer() {
+        let mut pass = PassTrace::begin(None, "pass");
+        assert_eq!(pass.time(TraceStage::Dispatch, || 3), 3);
+        pass.end();
+    }
+}
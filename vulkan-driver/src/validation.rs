@@ -0,0 +1,1267 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! validation done inside the driver, enabled by setting `KAZAN_VALIDATION=1`
+//!
+//! catches API misuse that would otherwise crash or corrupt the rasterizer, even when the
+//! validation layers aren't installed. errors go to the instance's `VK_EXT_debug_utils`
+//! messengers, or to stderr if there aren't any.
+
+use api;
+use command_buffer::CommandBufferState;
+use debug_utils::{DebugUtilsMessengers, MessageObject};
+use descriptor_set::DescriptorSet;
+use handle::SharedHandle;
+use image::{ImageView, ViewLayers};
+use pipeline::PipelineLayout;
+use shader_compiler::abi::{MAX_PUSH_CONSTANTS_SIZE, PUSH_CONSTANTS_GRANULARITY};
+use shader_compiler::descriptors;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, Mutex};
+
+pub const VALIDATION_ENV_VAR: &str = "KAZAN_VALIDATION";
+
+pub struct Validation {
+    messengers: Arc<DebugUtilsMessengers>,
+    /// the objects that can be referenced by commands and haven't been destroyed yet
+    live_objects: Mutex<HashSet<MessageObject>>,
+}
+
+/// the render pass instance a command buffer is recording
+struct ActiveRenderPass {
+    render_pass: SharedHandle<api::VkRenderPass>,
+    framebuffer: SharedHandle<api::VkFramebuffer>,
+    subpass: u32,
+}
+
+/// what validation keeps track of while a command buffer is recorded
+#[derive(Default)]
+pub struct RecordingState {
+    render_pass: Option<ActiveRenderPass>,
+    /// the layouts image subresources were left in by earlier commands in the command buffer,
+    /// indexed by image, mip level and array layer. subresources that the command buffer hasn't
+    /// transitioned aren't checked, since their layout depends on what was submitted before
+    image_layouts: HashMap<(MessageObject, u32, u32), api::VkImageLayout>,
+}
+
+impl Validation {
+    pub fn new(messengers: &Arc<DebugUtilsMessengers>) -> Self {
+        Validation {
+            messengers: messengers.clone(),
+            live_objects: Mutex::default(),
+        }
+    }
+    pub fn is_enabled_in_environment() -> bool {
+        match env::var(VALIDATION_ENV_VAR) {
+            Ok(ref v) => v != "" && v != "0",
+            Err(_) => false,
+        }
+    }
+    /// returns `None` unless validation is enabled
+    pub fn from_environment(messengers: &Arc<DebugUtilsMessengers>) -> Option<Arc<Self>> {
+        if !Self::is_enabled_in_environment() {
+            return None;
+        }
+        Some(Arc::new(Self::new(messengers)))
+    }
+    pub fn error(&self, message_id_name: &str, message: &str, objects: &[MessageObject]) {
+        if self.messengers.is_empty() {
+            eprintln!("kazan validation error: [{}] {}", message_id_name, message);
+            return;
+        }
+        self.messengers.send(
+            api::VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT,
+            api::VK_DEBUG_UTILS_MESSAGE_TYPE_VALIDATION_BIT_EXT,
+            message_id_name,
+            message,
+            objects,
+        );
+    }
+    pub fn add_object(&self, object: MessageObject) {
+        self.live_objects.lock().unwrap().insert(object);
+    }
+    pub fn remove_object(&self, object: MessageObject) {
+        self.live_objects.lock().unwrap().remove(&object);
+    }
+    /// report an error if `object` has been destroyed; returns true if it's still alive
+    pub fn check_live(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        object: MessageObject,
+        usage: &str,
+    ) -> bool {
+        if self.live_objects.lock().unwrap().contains(&object) {
+            return true;
+        }
+        self.error(
+            "kazan-object-destroyed",
+            &format!(
+                "{} uses {} {:#X}, which has been destroyed",
+                usage,
+                object_type_name(object.object_type),
+                object.object_handle
+            ),
+            &[command_buffer_object(command_buffer), object],
+        );
+        false
+    }
+    pub fn check_recording(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        state: CommandBufferState,
+        command_name: &str,
+    ) {
+        if state != CommandBufferState::Recording {
+            self.error(
+                "kazan-command-buffer-not-recording",
+                &format!(
+                    "{} called on a command buffer that is not recording (state is {:?})",
+                    command_name, state
+                ),
+                &[command_buffer_object(command_buffer)],
+            );
+        }
+    }
+    pub fn check_pipeline_bind_point(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        pipeline_bind_point: api::VkPipelineBindPoint,
+        pipeline: api::VkPipeline,
+        expected_bind_point: api::VkPipelineBindPoint,
+    ) {
+        if pipeline_bind_point != expected_bind_point {
+            self.error(
+                "kazan-pipeline-bind-point-mismatch",
+                &format!(
+                    "vkCmdBindPipeline: pipeline bind point {} doesn't match the pipeline's \
+                     bind point {}",
+                    pipeline_bind_point, expected_bind_point
+                ),
+                &[
+                    command_buffer_object(command_buffer),
+                    pipeline_object(pipeline),
+                ],
+            );
+        }
+    }
+    pub fn check_bind_descriptor_sets(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        layout: &PipelineLayout,
+        first_set: u32,
+        descriptor_sets: &[SharedHandle<api::VkDescriptorSet>],
+        dynamic_offset_count: usize,
+    ) {
+        let set_count = layout.layout.descriptor_sets.len();
+        if first_set as usize + descriptor_sets.len() > set_count {
+            self.error(
+                "kazan-descriptor-set-out-of-range",
+                &format!(
+                    "vkCmdBindDescriptorSets: binding sets {}..{} but the pipeline layout only \
+                     has {} sets",
+                    first_set,
+                    first_set as usize + descriptor_sets.len(),
+                    set_count
+                ),
+                &[command_buffer_object(command_buffer)],
+            );
+            return;
+        }
+        let mut expected_dynamic_offset_count = 0;
+        for (index, descriptor_set) in descriptor_sets.iter().enumerate() {
+            let set_index = first_set as usize + index;
+            let descriptor_set: &DescriptorSet = descriptor_set;
+            expected_dynamic_offset_count += descriptor_set.dynamic_descriptor_indices().count();
+            if !descriptor_set
+                .layout
+                .is_compatible(&layout.layout.descriptor_sets[set_index])
+            {
+                self.error(
+                    "kazan-descriptor-set-layout-incompatible",
+                    &format!(
+                        "vkCmdBindDescriptorSets: the layout of descriptor set {} is not \
+                         compatible with set {} of the pipeline layout",
+                        index, set_index
+                    ),
+                    &[command_buffer_object(command_buffer)],
+                );
+            }
+        }
+        if dynamic_offset_count != expected_dynamic_offset_count {
+            self.error(
+                "kazan-dynamic-offset-count",
+                &format!(
+                    "vkCmdBindDescriptorSets: {} dynamic offsets passed but the descriptor sets \
+                     have {} dynamic descriptors",
+                    dynamic_offset_count, expected_dynamic_offset_count
+                ),
+                &[command_buffer_object(command_buffer)],
+            );
+        }
+    }
+    /// report the sets used by a draw or dispatch that don't have a descriptor set bound or
+    /// have one that isn't compatible with `layout`. `bound_set_layout` returns the layout of the
+    /// descriptor set bound to a set. returns false if anything is reported
+    pub fn check_bound_descriptor_sets<'a>(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        command_name: &str,
+        layout: &descriptors::PipelineLayout,
+        used_sets: impl IntoIterator<Item = usize>,
+        bound_set_layout: impl Fn(usize) -> Option<&'a descriptors::DescriptorSetLayout>,
+    ) -> bool {
+        let mut is_valid = true;
+        for index in used_sets {
+            match bound_set_layout(index) {
+                Some(bound_layout)
+                    if bound_layout.is_compatible(&layout.descriptor_sets[index]) => {}
+                Some(_) => {
+                    self.error(
+                        "kazan-descriptor-set-layout-incompatible",
+                        &format!(
+                            "{}: the descriptor set bound to set {} is not compatible with the \
+                             bound pipeline's layout",
+                            command_name, index
+                        ),
+                        &[command_buffer_object(command_buffer)],
+                    );
+                    is_valid = false;
+                }
+                None => {
+                    self.error(
+                        "kazan-descriptor-set-not-bound",
+                        &format!(
+                            "{}: set {} is used by the bound pipeline but no descriptor set is \
+                             bound to it",
+                            command_name, index
+                        ),
+                        &[command_buffer_object(command_buffer)],
+                    );
+                    is_valid = false;
+                }
+            }
+        }
+        is_valid
+    }
+    pub fn check_push_constants(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        layout: &PipelineLayout,
+        stage_flags: api::VkShaderStageFlags,
+        offset: u32,
+        size: u32,
+    ) {
+        if offset % PUSH_CONSTANTS_GRANULARITY != 0 || size % PUSH_CONSTANTS_GRANULARITY != 0 {
+            self.error(
+                "kazan-push-constants-alignment",
+                &format!(
+                    "vkCmdPushConstants: offset {} and size {} must be multiples of {}",
+                    offset, size, PUSH_CONSTANTS_GRANULARITY
+                ),
+                &[command_buffer_object(command_buffer)],
+            );
+        }
+        let end = u64::from(offset) + u64::from(size);
+        if end > u64::from(MAX_PUSH_CONSTANTS_SIZE) {
+            self.error(
+                "kazan-push-constants-size",
+                &format!(
+                    "vkCmdPushConstants: bytes {}..{} are past maxPushConstantsSize ({})",
+                    offset, end, MAX_PUSH_CONSTANTS_SIZE
+                ),
+                &[command_buffer_object(command_buffer)],
+            );
+        }
+        // every byte must be in a range for each of the stages
+        let is_covered = (u64::from(offset)..end).all(|byte| {
+            (0..32)
+                .map(|bit| 1 << bit)
+                .filter(|&stage| stage_flags & stage != 0)
+                .all(|stage| {
+                    layout.push_constant_ranges.iter().any(|range| {
+                        range.stageFlags & stage != 0
+                            && u64::from(range.offset) <= byte
+                            && byte < u64::from(range.offset) + u64::from(range.size)
+                    })
+                })
+        });
+        if !is_covered {
+            self.error(
+                "kazan-push-constants-range",
+                &format!(
+                    "vkCmdPushConstants: bytes {}..{} for stages {:#X} are not in a push \
+                     constant range of the pipeline layout",
+                    offset, end, stage_flags
+                ),
+                &[command_buffer_object(command_buffer)],
+            );
+        }
+    }
+    /// report commands that have to be recorded inside a render pass instance but aren't
+    pub fn check_inside_render_pass(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        state: &RecordingState,
+        command_name: &str,
+    ) {
+        if state.render_pass.is_none() {
+            self.error(
+                "kazan-command-outside-render-pass",
+                &format!(
+                    "{} must be recorded inside a render pass instance",
+                    command_name
+                ),
+                &[command_buffer_object(command_buffer)],
+            );
+        }
+    }
+    /// report commands that have to be recorded outside of render pass instances but aren't
+    pub fn check_outside_render_pass(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        state: &RecordingState,
+        command_name: &str,
+    ) {
+        if state.render_pass.is_some() {
+            self.error(
+                "kazan-command-inside-render-pass",
+                &format!(
+                    "{} must not be recorded inside a render pass instance",
+                    command_name
+                ),
+                &[command_buffer_object(command_buffer)],
+            );
+        }
+    }
+    /// report a `vkCmdWriteTimestamp` stage that isn't a single stage the device supports.
+    /// `geometryShader` and `tessellationShader` aren't supported, so their stages aren't either
+    pub fn check_timestamp_stage(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        stage: api::VkPipelineStageFlagBits,
+    ) {
+        const UNSUPPORTED_STAGES: api::VkPipelineStageFlags =
+            api::VK_PIPELINE_STAGE_TESSELLATION_CONTROL_SHADER_BIT
+                | api::VK_PIPELINE_STAGE_TESSELLATION_EVALUATION_SHADER_BIT
+                | api::VK_PIPELINE_STAGE_GEOMETRY_SHADER_BIT;
+        let message = if !stage.is_power_of_two() || stage > api::VK_PIPELINE_STAGE_ALL_COMMANDS_BIT
+        {
+            "isn't a single pipeline stage"
+        } else if stage & UNSUPPORTED_STAGES != 0 {
+            "is a stage of a feature that isn't supported"
+        } else {
+            return;
+        };
+        self.error(
+            "kazan-timestamp-stage",
+            &format!(
+                "vkCmdWriteTimestamp: pipeline stage {:#X} {}",
+                stage, message
+            ),
+            &[command_buffer_object(command_buffer)],
+        );
+    }
+    /// report if the command buffer is still in a render pass instance when it's ended
+    pub fn check_render_pass_ended(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        state: &RecordingState,
+    ) {
+        if state.render_pass.is_some() {
+            self.error(
+                "kazan-render-pass-not-ended",
+                "vkEndCommandBuffer called inside a render pass instance",
+                &[command_buffer_object(command_buffer)],
+            );
+        }
+    }
+    /// check that `framebuffer` can be used with `render_pass` and that the attachments are in
+    /// their initial layouts, then start the render pass instance at its first subpass
+    pub fn begin_render_pass(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        state: &mut RecordingState,
+        render_pass: SharedHandle<api::VkRenderPass>,
+        framebuffer: SharedHandle<api::VkFramebuffer>,
+    ) {
+        self.check_outside_render_pass(command_buffer, state, "vkCmdBeginRenderPass");
+        let is_compatible = framebuffer.attachments.len() == render_pass.attachments.len()
+            && framebuffer
+                .attachments
+                .iter()
+                .zip(&render_pass.attachments)
+                .all(|(view, attachment)| view.format == attachment.format);
+        if !is_compatible {
+            self.error(
+                "kazan-framebuffer-incompatible",
+                "vkCmdBeginRenderPass: the framebuffer's attachments don't match the render \
+                 pass's attachments",
+                &[
+                    command_buffer_object(command_buffer),
+                    render_pass_object(render_pass.get_handle()),
+                    framebuffer_object(framebuffer.get_handle()),
+                ],
+            );
+        }
+        for (view, attachment) in framebuffer.attachments.iter().zip(&render_pass.attachments) {
+            self.transition_image_view_layout(
+                command_buffer,
+                state,
+                "vkCmdBeginRenderPass",
+                view,
+                attachment.initial_layout,
+                attachment.initial_layout,
+            );
+        }
+        state.render_pass = Some(ActiveRenderPass {
+            render_pass,
+            framebuffer,
+            subpass: 0,
+        });
+    }
+    pub fn next_subpass(&self, command_buffer: api::VkCommandBuffer, state: &mut RecordingState) {
+        self.check_inside_render_pass(command_buffer, state, "vkCmdNextSubpass");
+        let active_render_pass = match state.render_pass {
+            Some(ref mut active_render_pass) => active_render_pass,
+            None => return,
+        };
+        let subpass_count = active_render_pass.render_pass.subpasses.len() as u32;
+        if active_render_pass.subpass + 1 >= subpass_count {
+            self.error(
+                "kazan-subpass-out-of-range",
+                &format!(
+                    "vkCmdNextSubpass: the render pass only has {} subpasses",
+                    subpass_count
+                ),
+                &[
+                    command_buffer_object(command_buffer),
+                    render_pass_object(active_render_pass.render_pass.get_handle()),
+                ],
+            );
+            return;
+        }
+        active_render_pass.subpass += 1;
+    }
+    /// check that the render pass instance is in its last subpass, then end it, leaving the
+    /// attachments in their final layouts
+    pub fn end_render_pass(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        state: &mut RecordingState,
+    ) {
+        self.check_inside_render_pass(command_buffer, state, "vkCmdEndRenderPass");
+        let active_render_pass = match state.render_pass.take() {
+            Some(active_render_pass) => active_render_pass,
+            None => return,
+        };
+        let render_pass = active_render_pass.render_pass;
+        let last_subpass = render_pass.subpasses.len() as u32 - 1;
+        if active_render_pass.subpass != last_subpass {
+            self.error(
+                "kazan-render-pass-not-in-last-subpass",
+                &format!(
+                    "vkCmdEndRenderPass: called in subpass {} but the last subpass is {}",
+                    active_render_pass.subpass, last_subpass
+                ),
+                &[
+                    command_buffer_object(command_buffer),
+                    render_pass_object(render_pass.get_handle()),
+                ],
+            );
+        }
+        for (view, attachment) in active_render_pass
+            .framebuffer
+            .attachments
+            .iter()
+            .zip(&render_pass.attachments)
+        {
+            self.transition_image_view_layout(
+                command_buffer,
+                state,
+                "vkCmdEndRenderPass",
+                view,
+                api::VK_IMAGE_LAYOUT_UNDEFINED,
+                attachment.final_layout,
+            );
+        }
+    }
+    /// check that the subresources of `image` in `subresource_range` are in `old_layout`, then
+    /// record that they're in `new_layout`. nothing is checked if `old_layout` is
+    /// `VK_IMAGE_LAYOUT_UNDEFINED`, since that discards the contents instead
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    pub fn transition_image_layout(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        state: &mut RecordingState,
+        command_name: &str,
+        image: SharedHandle<api::VkImage>,
+        subresource_range: &api::VkImageSubresourceRange,
+        old_layout: api::VkImageLayout,
+        new_layout: api::VkImageLayout,
+    ) {
+        let properties = &image.properties;
+        let level_count = if subresource_range.levelCount == api::VK_REMAINING_MIP_LEVELS as u32 {
+            properties.mip_levels - subresource_range.baseMipLevel
+        } else {
+            subresource_range.levelCount
+        };
+        let layer_count = if subresource_range.layerCount == api::VK_REMAINING_ARRAY_LAYERS as u32 {
+            properties.array_layers - subresource_range.baseArrayLayer
+        } else {
+            subresource_range.layerCount
+        };
+        let image = image_object(image.get_handle());
+        let mut mismatch = None;
+        for level in subresource_range.baseMipLevel..subresource_range.baseMipLevel + level_count {
+            for layer in
+                subresource_range.baseArrayLayer..subresource_range.baseArrayLayer + layer_count
+            {
+                let layout = state
+                    .image_layouts
+                    .insert((image, level, layer), new_layout);
+                match layout {
+                    Some(layout)
+                        if old_layout != api::VK_IMAGE_LAYOUT_UNDEFINED && layout != old_layout =>
+                    {
+                        mismatch = mismatch.or(Some((level, layer, layout)));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        if let Some((level, layer, layout)) = mismatch {
+            self.error(
+                "kazan-image-layout-mismatch",
+                &format!(
+                    "{}: mip level {} of array layer {} of the image is expected to be in \
+                     layout {} but it's in layout {}",
+                    command_name, level, layer, old_layout, layout
+                ),
+                &[command_buffer_object(command_buffer), image],
+            );
+        }
+    }
+    /// `transition_image_layout` for the subresources in `view`
+    fn transition_image_view_layout(
+        &self,
+        command_buffer: api::VkCommandBuffer,
+        state: &mut RecordingState,
+        command_name: &str,
+        view: &ImageView,
+        old_layout: api::VkImageLayout,
+        new_layout: api::VkImageLayout,
+    ) {
+        let mut subresource_range = view.subresource_range;
+        if view.layers == ViewLayers::DepthSlices {
+            // the view's layers are depth slices of the image's only array layer
+            subresource_range.baseArrayLayer = 0;
+            subresource_range.layerCount = 1;
+        }
+        self.transition_image_layout(
+            command_buffer,
+            state,
+            command_name,
+            view.image,
+            &subresource_range,
+            old_layout,
+            new_layout,
+        );
+    }
+}
+
+fn object_type_name(object_type: api::VkObjectType) -> &'static str {
+    match object_type {
+        api::VK_OBJECT_TYPE_COMMAND_BUFFER => "command buffer",
+        api::VK_OBJECT_TYPE_DESCRIPTOR_SET => "descriptor set",
+        api::VK_OBJECT_TYPE_FRAMEBUFFER => "framebuffer",
+        api::VK_OBJECT_TYPE_IMAGE => "image",
+        api::VK_OBJECT_TYPE_PIPELINE => "pipeline",
+        api::VK_OBJECT_TYPE_PIPELINE_LAYOUT => "pipeline layout",
+        api::VK_OBJECT_TYPE_RENDER_PASS => "render pass",
+        _ => "object",
+    }
+}
+
+pub fn command_buffer_object(command_buffer: api::VkCommandBuffer) -> MessageObject {
+    MessageObject::new(api::VK_OBJECT_TYPE_COMMAND_BUFFER, command_buffer)
+}
+
+pub fn pipeline_object(pipeline: api::VkPipeline) -> MessageObject {
+    MessageObject::new(api::VK_OBJECT_TYPE_PIPELINE, pipeline)
+}
+
+pub fn pipeline_layout_object(pipeline_layout: api::VkPipelineLayout) -> MessageObject {
+    MessageObject::new(api::VK_OBJECT_TYPE_PIPELINE_LAYOUT, pipeline_layout)
+}
+
+pub fn descriptor_set_object(descriptor_set: api::VkDescriptorSet) -> MessageObject {
+    MessageObject::new(api::VK_OBJECT_TYPE_DESCRIPTOR_SET, descriptor_set)
+}
+
+pub fn image_object(image: api::VkImage) -> MessageObject {
+    MessageObject::new(api::VK_OBJECT_TYPE_IMAGE, image)
+}
+
+pub fn render_pass_object(render_pass: api::VkRenderPass) -> MessageObject {
+    MessageObject::new(api::VK_OBJECT_TYPE_RENDER_PASS, render_pass)
+}
+
+pub fn framebuffer_object(framebuffer: api::VkFramebuffer) -> MessageObject {
+    MessageObject::new(api::VK_OBJECT_TYPE_FRAMEBUFFER, framebuffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command_buffer::{BoundState, CommandBuffer};
+    use debug_utils::DebugUtilsMessenger;
+    use descriptor_set::{DescriptorPool, DescriptorSetLayout};
+    use handle::{Handle, OwnedHandle, Semaphore};
+    use image::{Image, ImageMultisampleCount, ImageProperties, SupportedTilings};
+    use render_pass::{Framebuffer, RenderPass};
+    use std::ffi::CStr;
+    use std::mem;
+    use std::os::raw::c_void;
+    use std::ptr::null;
+
+    unsafe extern "system" fn record_message_id(
+        _message_severity: api::VkDebugUtilsMessageSeverityFlagBitsEXT,
+        _message_type: api::VkDebugUtilsMessageTypeFlagsEXT,
+        callback_data: *const api::VkDebugUtilsMessengerCallbackDataEXT,
+        user_data: *mut c_void,
+    ) -> api::VkBool32 {
+        let message_ids = &*(user_data as *const Mutex<Vec<String>>);
+        let message_id = CStr::from_ptr((*callback_data).pMessageIdName);
+        message_ids
+            .lock()
+            .unwrap()
+            .push(message_id.to_string_lossy().into_owned());
+        api::VK_FALSE
+    }
+
+    /// a `Validation` whose messages go to a messenger that records their IDs
+    struct TestValidation {
+        validation: Arc<Validation>,
+        message_ids: Box<Mutex<Vec<String>>>,
+        _messenger: OwnedHandle<api::VkDebugUtilsMessengerEXT>,
+    }
+
+    impl TestValidation {
+        fn new() -> Self {
+            let message_ids = Box::new(Mutex::new(Vec::new()));
+            let messenger = OwnedHandle::<api::VkDebugUtilsMessengerEXT>::new(unsafe {
+                DebugUtilsMessenger::new(&api::VkDebugUtilsMessengerCreateInfoEXT {
+                    sType: api::VK_STRUCTURE_TYPE_DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
+                    pNext: null(),
+                    flags: 0,
+                    messageSeverity: api::VK_DEBUG_UTILS_MESSAGE_SEVERITY_ERROR_BIT_EXT,
+                    messageType: api::VK_DEBUG_UTILS_MESSAGE_TYPE_VALIDATION_BIT_EXT,
+                    pfnUserCallback: Some(record_message_id),
+                    pUserData: &*message_ids as *const Mutex<Vec<String>> as *mut c_void,
+                })
+            });
+            let messengers = Arc::new(DebugUtilsMessengers::default());
+            messengers.add(unsafe { messenger.get_handle() });
+            TestValidation {
+                validation: Arc::new(Validation::new(&messengers)),
+                message_ids,
+                _messenger: messenger,
+            }
+        }
+        /// the IDs of the messages sent since the last call
+        fn take_message_ids(&self) -> Vec<String> {
+            mem::replace(&mut *self.message_ids.lock().unwrap(), Vec::new())
+        }
+    }
+
+    unsafe fn create_set_layout(descriptor_type: api::VkDescriptorType) -> DescriptorSetLayout {
+        DescriptorSetLayout::new(&api::VkDescriptorSetLayoutCreateInfo {
+            sType: api::VK_STRUCTURE_TYPE_DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            pNext: null(),
+            flags: 0,
+            bindingCount: 1,
+            pBindings: &api::VkDescriptorSetLayoutBinding {
+                binding: 0,
+                descriptorType: descriptor_type,
+                descriptorCount: 1,
+                stageFlags: api::VK_SHADER_STAGE_ALL,
+                pImmutableSamplers: null(),
+            },
+        })
+        .unwrap()
+    }
+
+    unsafe fn create_descriptor_pool() -> DescriptorPool {
+        let pool_sizes = [
+            api::VkDescriptorPoolSize {
+                type_: api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC,
+                descriptorCount: 2,
+            },
+            api::VkDescriptorPoolSize {
+                type_: api::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER,
+                descriptorCount: 1,
+            },
+        ];
+        DescriptorPool::new(&api::VkDescriptorPoolCreateInfo {
+            sType: api::VK_STRUCTURE_TYPE_DESCRIPTOR_POOL_CREATE_INFO,
+            pNext: null(),
+            flags: 0,
+            maxSets: 3,
+            poolSizeCount: pool_sizes.len() as u32,
+            pPoolSizes: pool_sizes.as_ptr(),
+        })
+    }
+
+    fn make_pipeline_layout(set_layout: &DescriptorSetLayout) -> PipelineLayout {
+        PipelineLayout {
+            layout: descriptors::PipelineLayout {
+                descriptor_sets: vec![set_layout.layout.clone()],
+                push_constants_size: 16,
+            },
+            push_constant_ranges: vec![api::VkPushConstantRange {
+                stageFlags: api::VK_SHADER_STAGE_VERTEX_BIT,
+                offset: 0,
+                size: 16,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_command_buffer_state() {
+        let test_validation = TestValidation::new();
+        let command_buffer = api::VkCommandBuffer::null();
+        let mut buffer = CommandBuffer::new(Some(test_validation.validation.clone()));
+        assert!(!unsafe { buffer.validate_submit(command_buffer) });
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-command-buffer-not-executable"]
+        );
+        buffer.validate_command(command_buffer, "vkCmdDraw", &[]);
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-command-buffer-not-recording"]
+        );
+        buffer.begin(command_buffer);
+        buffer.begin(command_buffer);
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-command-buffer-already-recording"]
+        );
+        buffer.end(command_buffer);
+        assert!(unsafe { buffer.validate_submit(command_buffer) });
+        assert!(test_validation.take_message_ids().is_empty());
+    }
+
+    #[test]
+    fn test_object_destroyed() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        let object = MessageObject {
+            object_type: api::VK_OBJECT_TYPE_PIPELINE,
+            object_handle: 0x1000,
+        };
+        validation.add_object(object);
+        assert!(validation.check_live(command_buffer, object, "vkCmdBindPipeline"));
+        assert!(test_validation.take_message_ids().is_empty());
+        validation.remove_object(object);
+        assert!(!validation.check_live(command_buffer, object, "vkCmdBindPipeline"));
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-object-destroyed"]
+        );
+    }
+
+    #[test]
+    fn test_pooled_object_destroyed() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        let create_semaphore = || {
+            let semaphore = OwnedHandle::<api::VkSemaphore>::new(Semaphore {});
+            let object = MessageObject::new(api::VK_OBJECT_TYPE_SEMAPHORE, unsafe {
+                semaphore.get_handle()
+            });
+            validation.add_object(object);
+            (semaphore, object)
+        };
+        let (semaphore, object) = create_semaphore();
+        validation.remove_object(object);
+        drop(semaphore);
+        // the destroyed semaphore's handle isn't given to the next semaphore
+        let (_semaphore, _) = create_semaphore();
+        assert!(!validation.check_live(command_buffer, object, "vkQueueSubmit"));
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-object-destroyed"]
+        );
+    }
+
+    #[test]
+    fn test_timestamp_stage() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        for &stage in &[
+            api::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT,
+            api::VK_PIPELINE_STAGE_FRAGMENT_SHADER_BIT,
+            api::VK_PIPELINE_STAGE_ALL_COMMANDS_BIT,
+        ] {
+            validation.check_timestamp_stage(command_buffer, stage);
+        }
+        assert!(test_validation.take_message_ids().is_empty());
+        for &stage in &[
+            0,
+            api::VK_PIPELINE_STAGE_TOP_OF_PIPE_BIT | api::VK_PIPELINE_STAGE_TRANSFER_BIT,
+            api::VK_PIPELINE_STAGE_GEOMETRY_SHADER_BIT,
+        ] {
+            validation.check_timestamp_stage(command_buffer, stage);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-timestamp-stage"],
+                "stage = {:#X}",
+                stage
+            );
+        }
+    }
+
+    #[test]
+    fn test_pipeline_bind_point() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        let pipeline = api::VkPipeline::null();
+        validation.check_pipeline_bind_point(
+            command_buffer,
+            api::VK_PIPELINE_BIND_POINT_COMPUTE,
+            pipeline,
+            api::VK_PIPELINE_BIND_POINT_COMPUTE,
+        );
+        assert!(test_validation.take_message_ids().is_empty());
+        validation.check_pipeline_bind_point(
+            command_buffer,
+            api::VK_PIPELINE_BIND_POINT_GRAPHICS,
+            pipeline,
+            api::VK_PIPELINE_BIND_POINT_COMPUTE,
+        );
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-pipeline-bind-point-mismatch"]
+        );
+    }
+
+    #[test]
+    fn test_bind_descriptor_sets() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        unsafe {
+            let dynamic_layout = create_set_layout(api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC);
+            // created separately from the same bindings, so it's compatible
+            let same_dynamic_layout =
+                create_set_layout(api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC);
+            let storage_layout = create_set_layout(api::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER);
+            let mut pool = create_descriptor_pool();
+            let mut allocate =
+                |set_layout| SharedHandle::from(pool.allocate(set_layout).unwrap()).unwrap();
+            let dynamic_set = allocate(&dynamic_layout);
+            let same_dynamic_set = allocate(&same_dynamic_layout);
+            let storage_set = allocate(&storage_layout);
+            let layout = make_pipeline_layout(&dynamic_layout);
+            for &descriptor_set in &[dynamic_set, same_dynamic_set] {
+                validation.check_bind_descriptor_sets(
+                    command_buffer,
+                    &layout,
+                    0,
+                    &[descriptor_set],
+                    1,
+                );
+            }
+            assert!(test_validation.take_message_ids().is_empty());
+            validation.check_bind_descriptor_sets(command_buffer, &layout, 1, &[dynamic_set], 1);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-descriptor-set-out-of-range"]
+            );
+            validation.check_bind_descriptor_sets(command_buffer, &layout, 0, &[storage_set], 0);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-descriptor-set-layout-incompatible"]
+            );
+            validation.check_bind_descriptor_sets(command_buffer, &layout, 0, &[dynamic_set], 0);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-dynamic-offset-count"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_bound_descriptor_sets() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        let (dynamic_layout, same_dynamic_layout, storage_layout) = unsafe {
+            (
+                create_set_layout(api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC),
+                create_set_layout(api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC),
+                create_set_layout(api::VK_DESCRIPTOR_TYPE_STORAGE_BUFFER),
+            )
+        };
+        let layout = make_pipeline_layout(&dynamic_layout).layout;
+        let check = |bound_layout: Option<&DescriptorSetLayout>| {
+            validation.check_bound_descriptor_sets(
+                command_buffer,
+                "vkCmdDraw",
+                &layout,
+                vec![0],
+                |_| bound_layout.map(|bound_layout| &bound_layout.layout),
+            )
+        };
+        assert!(check(Some(&same_dynamic_layout)));
+        assert!(test_validation.take_message_ids().is_empty());
+        assert!(!check(Some(&storage_layout)));
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-descriptor-set-layout-incompatible"]
+        );
+        assert!(!check(None));
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-descriptor-set-not-bound"]
+        );
+        assert!(!BoundState::default().validate(
+            validation,
+            command_buffer,
+            api::VK_PIPELINE_BIND_POINT_GRAPHICS,
+            "vkCmdDraw",
+        ));
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-no-pipeline-bound"]
+        );
+    }
+
+    #[test]
+    fn test_push_constants() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        let layout = make_pipeline_layout(unsafe {
+            &create_set_layout(api::VK_DESCRIPTOR_TYPE_UNIFORM_BUFFER_DYNAMIC)
+        });
+        let vertex = api::VK_SHADER_STAGE_VERTEX_BIT;
+        validation.check_push_constants(command_buffer, &layout, vertex, 4, 8);
+        assert!(test_validation.take_message_ids().is_empty());
+        validation.check_push_constants(command_buffer, &layout, vertex, 2, 4);
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-push-constants-alignment"]
+        );
+        validation.check_push_constants(
+            command_buffer,
+            &layout,
+            vertex,
+            0,
+            MAX_PUSH_CONSTANTS_SIZE + PUSH_CONSTANTS_GRANULARITY,
+        );
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-push-constants-size", "kazan-push-constants-range"]
+        );
+        validation.check_push_constants(
+            command_buffer,
+            &layout,
+            api::VK_SHADER_STAGE_FRAGMENT_BIT,
+            0,
+            4,
+        );
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-push-constants-range"]
+        );
+    }
+
+    /// a 2D color image with one mip level and layer, and a view of it
+    struct TestImage {
+        image: OwnedHandle<api::VkImage>,
+        view: OwnedHandle<api::VkImageView>,
+    }
+
+    impl TestImage {
+        unsafe fn new() -> Self {
+            let format = api::VK_FORMAT_R8G8B8A8_UNORM;
+            let image = OwnedHandle::<api::VkImage>::new(Image {
+                properties: ImageProperties {
+                    supported_tilings: SupportedTilings::Any,
+                    image_type: api::VK_IMAGE_TYPE_2D,
+                    flags: 0,
+                    format,
+                    extents: api::VkExtent3D {
+                        width: 4,
+                        height: 4,
+                        depth: 1,
+                    },
+                    array_layers: 1,
+                    mip_levels: 1,
+                    multisample_count: ImageMultisampleCount::Count1,
+                    swapchain_present_tiling: None,
+                },
+                memory: None,
+            });
+            let view = OwnedHandle::<api::VkImageView>::new(ImageView::new(
+                SharedHandle::from(image.get_handle()).unwrap(),
+                &api::VkImageViewCreateInfo {
+                    sType: api::VK_STRUCTURE_TYPE_IMAGE_VIEW_CREATE_INFO,
+                    pNext: null(),
+                    flags: 0,
+                    image: image.get_handle(),
+                    viewType: api::VK_IMAGE_VIEW_TYPE_2D,
+                    format,
+                    components: api::VkComponentMapping {
+                        r: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+                        g: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+                        b: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+                        a: api::VK_COMPONENT_SWIZZLE_IDENTITY,
+                    },
+                    subresourceRange: api::VkImageSubresourceRange {
+                        aspectMask: api::VK_IMAGE_ASPECT_COLOR_BIT,
+                        baseMipLevel: 0,
+                        levelCount: 1,
+                        baseArrayLayer: 0,
+                        layerCount: 1,
+                    },
+                },
+            ));
+            TestImage { image, view }
+        }
+        unsafe fn transition(
+            &self,
+            validation: &Validation,
+            state: &mut RecordingState,
+            old_layout: api::VkImageLayout,
+            new_layout: api::VkImageLayout,
+        ) {
+            validation.transition_image_layout(
+                api::VkCommandBuffer::null(),
+                state,
+                "vkCmdPipelineBarrier",
+                SharedHandle::from(self.image.get_handle()).unwrap(),
+                &api::VkImageSubresourceRange {
+                    aspectMask: api::VK_IMAGE_ASPECT_COLOR_BIT,
+                    baseMipLevel: 0,
+                    levelCount: api::VK_REMAINING_MIP_LEVELS as u32,
+                    baseArrayLayer: 0,
+                    layerCount: api::VK_REMAINING_ARRAY_LAYERS as u32,
+                },
+                old_layout,
+                new_layout,
+            );
+        }
+    }
+
+    /// a render pass with two subpasses that both write to its one color attachment
+    unsafe fn create_render_pass() -> OwnedHandle<api::VkRenderPass> {
+        let color_attachment = api::VkAttachmentReference {
+            attachment: 0,
+            layout: api::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+        };
+        let subpass = api::VkSubpassDescription {
+            flags: 0,
+            pipelineBindPoint: api::VK_PIPELINE_BIND_POINT_GRAPHICS,
+            inputAttachmentCount: 0,
+            pInputAttachments: null(),
+            colorAttachmentCount: 1,
+            pColorAttachments: &color_attachment,
+            pResolveAttachments: null(),
+            pDepthStencilAttachment: null(),
+            preserveAttachmentCount: 0,
+            pPreserveAttachments: null(),
+        };
+        let subpasses = [subpass, subpass];
+        OwnedHandle::<api::VkRenderPass>::new(RenderPass::new(&api::VkRenderPassCreateInfo {
+            sType: api::VK_STRUCTURE_TYPE_RENDER_PASS_CREATE_INFO,
+            pNext: null(),
+            flags: 0,
+            attachmentCount: 1,
+            pAttachments: &api::VkAttachmentDescription {
+                flags: 0,
+                format: api::VK_FORMAT_R8G8B8A8_UNORM,
+                samples: api::VK_SAMPLE_COUNT_1_BIT,
+                loadOp: api::VK_ATTACHMENT_LOAD_OP_LOAD,
+                storeOp: api::VK_ATTACHMENT_STORE_OP_STORE,
+                stencilLoadOp: api::VK_ATTACHMENT_LOAD_OP_DONT_CARE,
+                stencilStoreOp: api::VK_ATTACHMENT_STORE_OP_DONT_CARE,
+                initialLayout: api::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                finalLayout: api::VK_IMAGE_LAYOUT_SHADER_READ_ONLY_OPTIMAL,
+            },
+            subpassCount: subpasses.len() as u32,
+            pSubpasses: subpasses.as_ptr(),
+            dependencyCount: 0,
+            pDependencies: null(),
+        }))
+    }
+
+    #[test]
+    fn test_render_pass_scope() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        let mut state = RecordingState::default();
+        unsafe {
+            let test_image = TestImage::new();
+            let render_pass = create_render_pass();
+            let render_pass = SharedHandle::from(render_pass.get_handle()).unwrap();
+            let framebuffer = OwnedHandle::<api::VkFramebuffer>::new(Framebuffer {
+                attachments: vec![SharedHandle::from(test_image.view.get_handle()).unwrap()],
+                width: 4,
+                height: 4,
+                layers: 1,
+            });
+            let framebuffer = SharedHandle::from(framebuffer.get_handle()).unwrap();
+            validation.check_inside_render_pass(command_buffer, &state, "vkCmdDraw");
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-command-outside-render-pass"]
+            );
+            validation.begin_render_pass(command_buffer, &mut state, render_pass, framebuffer);
+            validation.check_inside_render_pass(command_buffer, &state, "vkCmdDraw");
+            assert!(test_validation.take_message_ids().is_empty());
+            validation.check_outside_render_pass(command_buffer, &state, "vkCmdDispatch");
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-command-inside-render-pass"]
+            );
+            validation.begin_render_pass(command_buffer, &mut state, render_pass, framebuffer);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-command-inside-render-pass"]
+            );
+            validation.check_render_pass_ended(command_buffer, &state);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-render-pass-not-ended"]
+            );
+            validation.end_render_pass(command_buffer, &mut state);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-render-pass-not-in-last-subpass"]
+            );
+            validation.end_render_pass(command_buffer, &mut state);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-command-outside-render-pass"]
+            );
+            validation.check_render_pass_ended(command_buffer, &state);
+            test_image.transition(
+                validation,
+                &mut state,
+                api::VK_IMAGE_LAYOUT_UNDEFINED,
+                api::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+            );
+            validation.begin_render_pass(command_buffer, &mut state, render_pass, framebuffer);
+            validation.next_subpass(command_buffer, &mut state);
+            assert!(test_validation.take_message_ids().is_empty());
+            validation.next_subpass(command_buffer, &mut state);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-subpass-out-of-range"]
+            );
+            validation.end_render_pass(command_buffer, &mut state);
+            assert!(test_validation.take_message_ids().is_empty());
+            validation.next_subpass(command_buffer, &mut state);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-command-outside-render-pass"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_framebuffer_incompatible() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        let mut state = RecordingState::default();
+        unsafe {
+            let render_pass = create_render_pass();
+            let framebuffer = OwnedHandle::<api::VkFramebuffer>::new(Framebuffer {
+                attachments: Vec::new(),
+                width: 4,
+                height: 4,
+                layers: 1,
+            });
+            validation.begin_render_pass(
+                command_buffer,
+                &mut state,
+                SharedHandle::from(render_pass.get_handle()).unwrap(),
+                SharedHandle::from(framebuffer.get_handle()).unwrap(),
+            );
+        }
+        assert_eq!(
+            test_validation.take_message_ids(),
+            ["kazan-framebuffer-incompatible"]
+        );
+    }
+
+    #[test]
+    fn test_image_layouts() {
+        let test_validation = TestValidation::new();
+        let validation = &test_validation.validation;
+        let command_buffer = api::VkCommandBuffer::null();
+        let mut state = RecordingState::default();
+        unsafe {
+            let test_image = TestImage::new();
+            let render_pass = create_render_pass();
+            let render_pass = SharedHandle::from(render_pass.get_handle()).unwrap();
+            let framebuffer = OwnedHandle::<api::VkFramebuffer>::new(Framebuffer {
+                attachments: vec![SharedHandle::from(test_image.view.get_handle()).unwrap()],
+                width: 4,
+                height: 4,
+                layers: 1,
+            });
+            let framebuffer = SharedHandle::from(framebuffer.get_handle()).unwrap();
+            // the layout at the start of the command buffer isn't known, so it isn't checked
+            test_image.transition(
+                validation,
+                &mut state,
+                api::VK_IMAGE_LAYOUT_GENERAL,
+                api::VK_IMAGE_LAYOUT_TRANSFER_DST_OPTIMAL,
+            );
+            assert!(test_validation.take_message_ids().is_empty());
+            validation.begin_render_pass(command_buffer, &mut state, render_pass, framebuffer);
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-image-layout-mismatch"]
+            );
+            validation.next_subpass(command_buffer, &mut state);
+            validation.end_render_pass(command_buffer, &mut state);
+            assert!(test_validation.take_message_ids().is_empty());
+            // the render pass left the attachment in its final layout
+            test_image.transition(
+                validation,
+                &mut state,
+                api::VK_IMAGE_LAYOUT_COLOR_ATTACHMENT_OPTIMAL,
+                api::VK_IMAGE_LAYOUT_GENERAL,
+            );
+            assert_eq!(
+                test_validation.take_message_ids(),
+                ["kazan-image-layout-mismatch"]
+            );
+            test_image.transition(
+                validation,
+                &mut state,
+                api::VK_IMAGE_LAYOUT_GENERAL,
+                api::VK_IMAGE_LAYOUT_TRANSFER_SRC_OPTIMAL,
+            );
+            test_image.transition(
+                validation,
+                &mut state,
+                api::VK_IMAGE_LAYOUT_UNDEFINED,
+                api::VK_IMAGE_LAYOUT_GENERAL,
+            );
+            assert!(test_validation.take_message_ids().is_empty());
+        }
+    }
+}
@@ -0,0 +1,438 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! decoding vertex input formats
+//!
+//! used by vertex fetch, and by conversions that read formatted buffer or image data, like the
+//! present blit. elements are decoded `LANES` at a time: each component is gathered from all the
+//! elements into an array, then converted with the same branch-free operation on every lane, so
+//! the conversions compile to SIMD code instead of converting one component at a time.
+//!
+//! decoded elements are the 4 words a vertex shader input gets: integer formats give integers,
+//! every other format gives the bits of `f32`s. components the format doesn't have are filled in
+//! with (0, 0, 0, 1).
+
+// the present blit is the only user of the decoder, until there's a vertex fetch stage
+#![cfg_attr(not(all(unix, feature = "wsi-xcb")), allow(dead_code))]
+
+use api;
+
+/// the number of elements decoded at once
+pub const LANES: usize = 8;
+
+/// how a component's bits are interpreted
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum NumericFormat {
+    Unorm,
+    Snorm,
+    Uscaled,
+    Sscaled,
+    Uint,
+    Sint,
+    /// unsigned floats with a 5-bit exponent, like in `VK_FORMAT_B10G11R11_UFLOAT_PACK32`
+    Ufloat,
+    /// 16-bit or 32-bit floats
+    Sfloat,
+}
+
+impl NumericFormat {
+    /// true if components are decoded to integers instead of floats
+    pub fn is_integer(self) -> bool {
+        self == NumericFormat::Uint || self == NumericFormat::Sint
+    }
+}
+
+/// where a component is stored in an element
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+struct Component {
+    /// the offset, in bits, from the start of the element
+    offset: u32,
+    bits: u32,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct VertexFormat {
+    pub numeric_format: NumericFormat,
+    /// the size of an element, in bytes
+    pub element_size: usize,
+    pub component_count: usize,
+    /// in RGBA order
+    components: [Component; 4],
+}
+
+impl VertexFormat {
+    /// `component_count` components of `bits` bits each, stored in RGBA order
+    fn array(numeric_format: NumericFormat, bits: u32, component_count: usize) -> Self {
+        let mut components = [Component { offset: 0, bits }; 4];
+        for (index, component) in components.iter_mut().enumerate() {
+            component.offset = index as u32 * bits;
+        }
+        VertexFormat {
+            numeric_format,
+            element_size: component_count * bits as usize / 8,
+            component_count,
+            components,
+        }
+    }
+    /// 8-bit components stored in BGRA order
+    fn bgra8(numeric_format: NumericFormat, component_count: usize) -> Self {
+        let mut retval = Self::array(numeric_format, 8, component_count);
+        retval.components.swap(0, 2);
+        retval
+    }
+    /// components packed into a 32-bit word, given as `(offset, bits)` in RGBA order
+    fn pack32(numeric_format: NumericFormat, components: &[(u32, u32)]) -> Self {
+        let mut retval = Self::array(numeric_format, 8, 4);
+        retval.component_count = components.len();
+        for (component, &(offset, bits)) in retval.components.iter_mut().zip(components) {
+            *component = Component { offset, bits };
+        }
+        retval
+    }
+    fn a2r10g10b10(numeric_format: NumericFormat) -> Self {
+        Self::pack32(numeric_format, &[(20, 10), (10, 10), (0, 10), (30, 2)])
+    }
+    fn a2b10g10r10(numeric_format: NumericFormat) -> Self {
+        Self::pack32(numeric_format, &[(0, 10), (10, 10), (20, 10), (30, 2)])
+    }
+    /// returns `None` if `format` isn't a vertex input format
+    pub fn from(format: api::VkFormat) -> Option<Self> {
+        use self::NumericFormat::*;
+        Some(match format {
+            api::VK_FORMAT_R8_UNORM => Self::array(Unorm, 8, 1),
+            api::VK_FORMAT_R8_SNORM => Self::array(Snorm, 8, 1),
+            api::VK_FORMAT_R8_USCALED => Self::array(Uscaled, 8, 1),
+            api::VK_FORMAT_R8_SSCALED => Self::array(Sscaled, 8, 1),
+            api::VK_FORMAT_R8_UINT => Self::array(Uint, 8, 1),
+            api::VK_FORMAT_R8_SINT => Self::array(Sint, 8, 1),
+            api::VK_FORMAT_R8G8_UNORM => Self::array(Unorm, 8, 2),
+            api::VK_FORMAT_R8G8_SNORM => Self::array(Snorm, 8, 2),
+            api::VK_FORMAT_R8G8_USCALED => Self::array(Uscaled, 8, 2),
+            api::VK_FORMAT_R8G8_SSCALED => Self::array(Sscaled, 8, 2),
+            api::VK_FORMAT_R8G8_UINT => Self::array(Uint, 8, 2),
+            api::VK_FORMAT_R8G8_SINT => Self::array(Sint, 8, 2),
+            api::VK_FORMAT_R8G8B8_UNORM => Self::array(Unorm, 8, 3),
+            api::VK_FORMAT_R8G8B8_SNORM => Self::array(Snorm, 8, 3),
+            api::VK_FORMAT_R8G8B8_USCALED => Self::array(Uscaled, 8, 3),
+            api::VK_FORMAT_R8G8B8_SSCALED => Self::array(Sscaled, 8, 3),
+            api::VK_FORMAT_R8G8B8_UINT => Self::array(Uint, 8, 3),
+            api::VK_FORMAT_R8G8B8_SINT => Self::array(Sint, 8, 3),
+            api::VK_FORMAT_B8G8R8_UNORM => Self::bgra8(Unorm, 3),
+            api::VK_FORMAT_B8G8R8_SNORM => Self::bgra8(Snorm, 3),
+            api::VK_FORMAT_B8G8R8_USCALED => Self::bgra8(Uscaled, 3),
+            api::VK_FORMAT_B8G8R8_SSCALED => Self::bgra8(Sscaled, 3),
+            api::VK_FORMAT_B8G8R8_UINT => Self::bgra8(Uint, 3),
+            api::VK_FORMAT_B8G8R8_SINT => Self::bgra8(Sint, 3),
+            api::VK_FORMAT_R8G8B8A8_UNORM | api::VK_FORMAT_A8B8G8R8_UNORM_PACK32 => {
+                Self::array(Unorm, 8, 4)
+            }
+            api::VK_FORMAT_R8G8B8A8_SNORM | api::VK_FORMAT_A8B8G8R8_SNORM_PACK32 => {
+                Self::array(Snorm, 8, 4)
+            }
+            api::VK_FORMAT_R8G8B8A8_USCALED | api::VK_FORMAT_A8B8G8R8_USCALED_PACK32 => {
+                Self::array(Uscaled, 8, 4)
+            }
+            api::VK_FORMAT_R8G8B8A8_SSCALED | api::VK_FORMAT_A8B8G8R8_SSCALED_PACK32 => {
+                Self::array(Sscaled, 8, 4)
+            }
+            api::VK_FORMAT_R8G8B8A8_UINT | api::VK_FORMAT_A8B8G8R8_UINT_PACK32 => {
+                Self::array(Uint, 8, 4)
+            }
+            api::VK_FORMAT_R8G8B8A8_SINT | api::VK_FORMAT_A8B8G8R8_SINT_PACK32 => {
+                Self::array(Sint, 8, 4)
+            }
+            api::VK_FORMAT_B8G8R8A8_UNORM => Self::bgra8(Unorm, 4),
+            api::VK_FORMAT_B8G8R8A8_SNORM => Self::bgra8(Snorm, 4),
+            api::VK_FORMAT_B8G8R8A8_USCALED => Self::bgra8(Uscaled, 4),
+            api::VK_FORMAT_B8G8R8A8_SSCALED => Self::bgra8(Sscaled, 4),
+            api::VK_FORMAT_B8G8R8A8_UINT => Self::bgra8(Uint, 4),
+            api::VK_FORMAT_B8G8R8A8_SINT => Self::bgra8(Sint, 4),
+            api::VK_FORMAT_A2R10G10B10_UNORM_PACK32 => Self::a2r10g10b10(Unorm),
+            api::VK_FORMAT_A2R10G10B10_SNORM_PACK32 => Self::a2r10g10b10(Snorm),
+            api::VK_FORMAT_A2R10G10B10_USCALED_PACK32 => Self::a2r10g10b10(Uscaled),
+            api::VK_FORMAT_A2R10G10B10_SSCALED_PACK32 => Self::a2r10g10b10(Sscaled),
+            api::VK_FORMAT_A2R10G10B10_UINT_PACK32 => Self::a2r10g10b10(Uint),
+            api::VK_FORMAT_A2R10G10B10_SINT_PACK32 => Self::a2r10g10b10(Sint),
+            api::VK_FORMAT_A2B10G10R10_UNORM_PACK32 => Self::a2b10g10r10(Unorm),
+            api::VK_FORMAT_A2B10G10R10_SNORM_PACK32 => Self::a2b10g10r10(Snorm),
+            api::VK_FORMAT_A2B10G10R10_USCALED_PACK32 => Self::a2b10g10r10(Uscaled),
+            api::VK_FORMAT_A2B10G10R10_SSCALED_PACK32 => Self::a2b10g10r10(Sscaled),
+            api::VK_FORMAT_A2B10G10R10_UINT_PACK32 => Self::a2b10g10r10(Uint),
+            api::VK_FORMAT_A2B10G10R10_SINT_PACK32 => Self::a2b10g10r10(Sint),
+            api::VK_FORMAT_R16_UNORM => Self::array(Unorm, 16, 1),
+            api::VK_FORMAT_R16_SNORM => Self::array(Snorm, 16, 1),
+            api::VK_FORMAT_R16_USCALED => Self::array(Uscaled, 16, 1),
+            api::VK_FORMAT_R16_SSCALED => Self::array(Sscaled, 16, 1),
+            api::VK_FORMAT_R16_UINT => Self::array(Uint, 16, 1),
+            api::VK_FORMAT_R16_SINT => Self::array(Sint, 16, 1),
+            api::VK_FORMAT_R16_SFLOAT => Self::array(Sfloat, 16, 1),
+            api::VK_FORMAT_R16G16_UNORM => Self::array(Unorm, 16, 2),
+            api::VK_FORMAT_R16G16_SNORM => Self::array(Snorm, 16, 2),
+            api::VK_FORMAT_R16G16_USCALED => Self::array(Uscaled, 16, 2),
+            api::VK_FORMAT_R16G16_SSCALED => Self::array(Sscaled, 16, 2),
+            api::VK_FORMAT_R16G16_UINT => Self::array(Uint, 16, 2),
+            api::VK_FORMAT_R16G16_SINT => Self::array(Sint, 16, 2),
+            api::VK_FORMAT_R16G16_SFLOAT => Self::array(Sfloat, 16, 2),
+            api::VK_FORMAT_R16G16B16_UNORM => Self::array(Unorm, 16, 3),
+            api::VK_FORMAT_R16G16B16_SNORM => Self::array(Snorm, 16, 3),
+            api::VK_FORMAT_R16G16B16_USCALED => Self::array(Uscaled, 16, 3),
+            api::VK_FORMAT_R16G16B16_SSCALED => Self::array(Sscaled, 16, 3),
+            api::VK_FORMAT_R16G16B16_UINT => Self::array(Uint, 16, 3),
+            api::VK_FORMAT_R16G16B16_SINT => Self::array(Sint, 16, 3),
+            api::VK_FORMAT_R16G16B16_SFLOAT => Self::array(Sfloat, 16, 3),
+            api::VK_FORMAT_R16G16B16A16_UNORM => Self::array(Unorm, 16, 4),
+            api::VK_FORMAT_R16G16B16A16_SNORM => Self::array(Snorm, 16, 4),
+            api::VK_FORMAT_R16G16B16A16_USCALED => Self::array(Uscaled, 16, 4),
+            api::VK_FORMAT_R16G16B16A16_SSCALED => Self::array(Sscaled, 16, 4),
+            api::VK_FORMAT_R16G16B16A16_UINT => Self::array(Uint, 16, 4),
+            api::VK_FORMAT_R16G16B16A16_SINT => Self::array(Sint, 16, 4),
+            api::VK_FORMAT_R16G16B16A16_SFLOAT => Self::array(Sfloat, 16, 4),
+            api::VK_FORMAT_R32_UINT => Self::array(Uint, 32, 1),
+            api::VK_FORMAT_R32_SINT => Self::array(Sint, 32, 1),
+            api::VK_FORMAT_R32_SFLOAT => Self::array(Sfloat, 32, 1),
+            api::VK_FORMAT_R32G32_UINT => Self::array(Uint, 32, 2),
+            api::VK_FORMAT_R32G32_SINT => Self::array(Sint, 32, 2),
+            api::VK_FORMAT_R32G32_SFLOAT => Self::array(Sfloat, 32, 2),
+            api::VK_FORMAT_R32G32B32_UINT => Self::array(Uint, 32, 3),
+            api::VK_FORMAT_R32G32B32_SINT => Self::array(Sint, 32, 3),
+            api::VK_FORMAT_R32G32B32_SFLOAT => Self::array(Sfloat, 32, 3),
+            api::VK_FORMAT_R32G32B32A32_UINT => Self::array(Uint, 32, 4),
+            api::VK_FORMAT_R32G32B32A32_SINT => Self::array(Sint, 32, 4),
+            api::VK_FORMAT_R32G32B32A32_SFLOAT => Self::array(Sfloat, 32, 4),
+            api::VK_FORMAT_B10G11R11_UFLOAT_PACK32 => {
+                Self::pack32(Ufloat, &[(0, 11), (11, 11), (22, 10)])
+            }
+            _ => return None,
+        })
+    }
+    /// the value of components the format doesn't have
+    fn default_component(&self, index: usize) -> u32 {
+        match (index, self.numeric_format.is_integer()) {
+            (3, true) => 1,
+            (3, false) => 1.0f32.to_bits(),
+            _ => 0,
+        }
+    }
+}
+
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) as i32 >> shift
+}
+
+/// converts an unsigned float with a 5-bit exponent and `mantissa_bits` bits of mantissa, like
+/// the magnitude of a half float, to the bits of a `f32`
+fn small_float_to_f32_bits(value: u32, mantissa_bits: u32) -> u32 {
+    let exponent = value >> mantissa_bits;
+    let mantissa = value & ((1 << mantissa_bits) - 1);
+    let mantissa = mantissa << (23 - mantissa_bits);
+    // subnormal values are `mantissa * 2^(1 - 15 - mantissa_bits)`, and the shifted mantissa
+    // already has a factor of `2^(23 - mantissa_bits)`
+    let subnormal_scale = f32::from_bits((127 - 14 - 23) << 23);
+    if exponent == 0 {
+        (mantissa as f32 * subnormal_scale).to_bits()
+    } else if exponent == 0x1F {
+        0x7F80_0000 | mantissa
+    } else {
+        (exponent + 127 - 15) << 23 | mantissa
+    }
+}
+
+/// converts the components in `lanes`, which have `bits` bits each, to decoded words. every lane
+/// is converted the same way, so LLVM can vectorize the loops
+fn convert_lanes(numeric_format: NumericFormat, bits: u32, lanes: &mut [u32; LANES]) {
+    match numeric_format {
+        NumericFormat::Unorm => {
+            let max = (u32::MAX >> (32 - bits)) as f32;
+            for lane in lanes.iter_mut() {
+                *lane = (*lane as f32 / max).to_bits();
+            }
+        }
+        NumericFormat::Snorm => {
+            // the most negative value is also -1.0
+            let max = ((1u32 << (bits - 1)) - 1) as f32;
+            for lane in lanes.iter_mut() {
+                *lane = (sign_extend(*lane, bits) as f32 / max).max(-1.0).to_bits();
+            }
+        }
+        NumericFormat::Uscaled => {
+            for lane in lanes.iter_mut() {
+                *lane = (*lane as f32).to_bits();
+            }
+        }
+        NumericFormat::Sscaled => {
+            for lane in lanes.iter_mut() {
+                *lane = (sign_extend(*lane, bits) as f32).to_bits();
+            }
+        }
+        NumericFormat::Uint => {}
+        NumericFormat::Sint => {
+            for lane in lanes.iter_mut() {
+                *lane = sign_extend(*lane, bits) as u32;
+            }
+        }
+        NumericFormat::Ufloat => {
+            for lane in lanes.iter_mut() {
+                *lane = small_float_to_f32_bits(*lane, bits - 5);
+            }
+        }
+        NumericFormat::Sfloat if bits == 32 => {}
+        NumericFormat::Sfloat => {
+            assert_eq!(bits, 16);
+            for lane in lanes.iter_mut() {
+                let sign = (*lane & 0x8000) << 16;
+                *lane = sign | small_float_to_f32_bits(*lane & 0x7FFF, 10);
+            }
+        }
+    }
+}
+
+/// decodes `destination.len()` elements of `format` from `source`, where element `i` starts at
+/// byte `i * stride`
+pub fn decode(format: &VertexFormat, source: &[u8], stride: usize, destination: &mut [[u32; 4]]) {
+    if let Some(last) = destination.len().checked_sub(1) {
+        assert!(last * stride + format.element_size <= source.len());
+    }
+    for (chunk_index, destination) in destination.chunks_mut(LANES).enumerate() {
+        let first_element = chunk_index * LANES;
+        let mut lanes = [[0u32; LANES]; 4];
+        for (index, lanes) in lanes.iter_mut().enumerate() {
+            if index >= format.component_count {
+                *lanes = [format.default_component(index); LANES];
+                continue;
+            }
+            let Component { offset, bits } = format.components[index];
+            let byte_offset = offset as usize / 8;
+            let shift = offset % 8;
+            let byte_count = (shift + bits + 7) as usize / 8;
+            let mask = u32::MAX >> (32 - bits);
+            for (element, lane) in lanes.iter_mut().take(destination.len()).enumerate() {
+                let start = (first_element + element) * stride + byte_offset;
+                let mut value = 0;
+                for (byte_index, &byte) in source[start..start + byte_count].iter().enumerate() {
+                    value |= u32::from(byte) << (byte_index * 8);
+                }
+                *lane = value >> shift & mask;
+            }
+            convert_lanes(format.numeric_format, bits, lanes);
+        }
+        for (element, destination) in destination.iter_mut().enumerate() {
+            *destination = [
+                lanes[0][element],
+                lanes[1][element],
+                lanes[2][element],
+                lanes[3][element],
+            ];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_f32(format: api::VkFormat, source: &[u8], stride: usize) -> Vec<[f32; 4]> {
+        let format = VertexFormat::from(format).unwrap();
+        let mut decoded = vec![[0; 4]; source.len() / stride];
+        decode(&format, source, stride, &mut decoded);
+        decoded
+            .iter()
+            .map(|v| {
+                [
+                    f32::from_bits(v[0]),
+                    f32::from_bits(v[1]),
+                    f32::from_bits(v[2]),
+                    f32::from_bits(v[3]),
+                ]
+            })
+            .collect()
+    }
+
+    /// the scalar half float conversion, to check the vectorized one against
+    fn reference_f16_to_f32(value: u16) -> f32 {
+        let sign = if value & 0x8000 != 0 { -1.0 } else { 1.0 };
+        let exponent = i32::from((value >> 10) & 0x1F);
+        let mantissa = f32::from(value & 0x3FF);
+        match exponent {
+            0 => sign * mantissa * 2.0f32.powi(-24),
+            0x1F if mantissa == 0.0 => sign * f32::INFINITY,
+            0x1F => f32::NAN,
+            _ => sign * (1.0 + mantissa / 1024.0) * 2.0f32.powi(exponent - 15),
+        }
+    }
+
+    #[test]
+    fn test_half_float() {
+        let source: Vec<u8> = (0..=0xFFFFu32)
+            .flat_map(|v| vec![v as u8, (v >> 8) as u8])
+            .collect();
+        let decoded = decode_f32(api::VK_FORMAT_R16_SFLOAT, &source, 2);
+        assert_eq!(decoded.len(), 0x10000);
+        assert_eq!(decoded[0x3C00][0], 1.0);
+        assert_eq!(decoded[0xC000][0], -2.0);
+        assert_eq!(decoded[0x3800][0], 0.5);
+        assert_eq!(decoded[0x0001][0], 2.0f32.powi(-24));
+        for (value, decoded) in decoded.iter().enumerate() {
+            let expected = reference_f16_to_f32(value as u16);
+            if expected.is_nan() {
+                assert!(decoded[0].is_nan(), "{:#06X}", value);
+            } else {
+                assert_eq!(decoded[0].to_bits(), expected.to_bits(), "{:#06X}", value);
+            }
+            assert_eq!(decoded[1..], [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn test_8_bit() {
+        // 10 elements, so the second chunk is partial, with a stride larger than the elements
+        let source: Vec<u8> = (0..10u8)
+            .flat_map(|v| vec![v, 255 - v, 0x80, 0x7F, 0xAA])
+            .collect();
+        let decoded = decode_f32(api::VK_FORMAT_B8G8R8A8_UNORM, &source, 5);
+        assert_eq!(decoded.len(), 10);
+        for (index, decoded) in decoded.iter().enumerate() {
+            let expected = [
+                128.0 / 255.0,
+                (255 - index) as f32 / 255.0,
+                index as f32 / 255.0,
+            ];
+            assert_eq!(decoded[..3], expected);
+            assert_eq!(decoded[3], 127.0 / 255.0);
+        }
+        let decoded = decode_f32(api::VK_FORMAT_R8G8_SNORM, &[0x80, 0x81, 0x7F, 0x00], 4);
+        assert_eq!(decoded, [[-1.0, -1.0, 0.0, 1.0]]);
+        let format = VertexFormat::from(api::VK_FORMAT_R8G8B8_SINT).unwrap();
+        let mut decoded = [[0; 4]; 1];
+        decode(&format, &[0xFF, 0x80, 0x05], 3, &mut decoded);
+        assert_eq!(decoded, [[-1i32 as u32, -128i32 as u32, 5, 1]]);
+    }
+
+    #[test]
+    fn test_packed() {
+        // red is 1023, green is 512, blue is 1, and alpha is 2
+        let value: u32 = 1023 | 512 << 10 | 1 << 20 | 2 << 30;
+        let bytes = [
+            value as u8,
+            (value >> 8) as u8,
+            (value >> 16) as u8,
+            (value >> 24) as u8,
+        ];
+        let decoded = decode_f32(api::VK_FORMAT_A2B10G10R10_SNORM_PACK32, &bytes, 4);
+        assert_eq!(decoded, [[-1.0 / 511.0, -1.0, 1.0 / 511.0, -1.0]]);
+        let format = VertexFormat::from(api::VK_FORMAT_A2R10G10B10_UINT_PACK32).unwrap();
+        let mut decoded = [[0; 4]; 1];
+        decode(&format, &bytes, 4, &mut decoded);
+        assert_eq!(decoded, [[1, 512, 1023, 2]]);
+        // red is 1.0, green is 0.5, and blue is 2^-14 * 31 / 32, the largest subnormal value
+        let value: u32 = 15 << 6 | 14 << 6 << 11 | 31 << 22;
+        let bytes = [
+            value as u8,
+            (value >> 8) as u8,
+            (value >> 16) as u8,
+            (value >> 24) as u8,
+        ];
+        let decoded = decode_f32(api::VK_FORMAT_B10G11R11_UFLOAT_PACK32, &bytes, 4);
+        assert_eq!(decoded, [[1.0, 0.5, 2.0f32.powi(-14) * 31.0 / 32.0, 1.0]]);
+    }
+}
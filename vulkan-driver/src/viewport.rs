@@ -0,0 +1,250 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! the viewport transform and depth bias
+//!
+//! `VK_EXT_depth_range_unrestricted` allows viewport depth ranges outside [0, 1]. when it's
+//! enabled, framebuffer depths are only clamped to [0, 1] for fixed-point depth attachments,
+//! since floating-point attachments can store the depths outside it.
+//!
+//! the depth bias added to a polygon's depths is `m * slope_factor + r * constant_factor`,
+//! clamped by `clamp`, where `m` is the polygon's maximum depth slope and `r` is the unit picked
+//! by the depth bias representation. `r` is always computed exactly. neither
+//! `VK_EXT_depth_range_unrestricted` nor `VK_EXT_depth_bias_control` is advertised until draws
+//! run, so `vkCmdSetDepthBias` and the pipeline only give the default representation for now.
+
+use api;
+
+/// `minDepth` can be greater than `maxDepth`. `repr(C)` since command streams store viewports
+/// as plain data
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(C)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub min_depth: f32,
+    pub max_depth: f32,
+}
+
+impl Viewport {
+    /// the depth range isn't checked, since only valid usage depends on whether
+    /// `VK_EXT_depth_range_unrestricted` is enabled
+    pub fn new(viewport: &api::VkViewport) -> Self {
+        Viewport {
+            x: viewport.x,
+            y: viewport.y,
+            width: viewport.width,
+            height: viewport.height,
+            min_depth: viewport.minDepth,
+            max_depth: viewport.maxDepth,
+        }
+    }
+    /// convert normalized device coordinates to framebuffer coordinates
+    #[allow(dead_code)]
+    pub fn transform(&self, position: [f32; 3]) -> [f32; 3] {
+        [
+            self.x + (position[0] + 1.0) * 0.5 * self.width,
+            self.y + (position[1] + 1.0) * 0.5 * self.height,
+            self.min_depth + position[2] * (self.max_depth - self.min_depth),
+        ]
+    }
+    /// clamp a fragment's depth, after the depth bias, before the depth test and write
+    #[allow(dead_code)]
+    pub fn clamp_depth(
+        &self,
+        depth: f32,
+        depth_clamp_enable: bool,
+        format: DepthFormat,
+        depth_range_unrestricted: bool,
+    ) -> f32 {
+        let mut depth = depth;
+        if depth_clamp_enable {
+            depth = depth.clamp(
+                self.min_depth.min(self.max_depth),
+                self.min_depth.max(self.max_depth),
+            );
+        }
+        match format {
+            DepthFormat::Float32 if depth_range_unrestricted => depth,
+            _ => depth.clamp(0.0, 1.0),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum DepthFormat {
+    Unorm { bits: u32 },
+    Float32,
+}
+
+impl DepthFormat {
+    /// panics if `format` has no depth aspect
+    #[allow(dead_code)]
+    pub fn new(format: api::VkFormat) -> Self {
+        match format {
+            api::VK_FORMAT_D16_UNORM | api::VK_FORMAT_D16_UNORM_S8_UINT => {
+                DepthFormat::Unorm { bits: 16 }
+            }
+            api::VK_FORMAT_X8_D24_UNORM_PACK32 | api::VK_FORMAT_D24_UNORM_S8_UINT => {
+                DepthFormat::Unorm { bits: 24 }
+            }
+            api::VK_FORMAT_D32_SFLOAT | api::VK_FORMAT_D32_SFLOAT_S8_UINT => DepthFormat::Float32,
+            _ => panic!("not a depth format: {}", format),
+        }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum DepthBiasRepresentation {
+    /// `r` is the smallest difference the depth attachment can represent at the polygon's depths
+    LeastRepresentableValueFormat,
+    /// like `LeastRepresentableValueFormat`, except floating-point attachments use the unit of a
+    /// 24-bit fixed-point attachment
+    LeastRepresentableValueForceUnorm,
+    /// `r` is 1, so the constant factor is added to the depth as is
+    Float,
+}
+
+/// the depth bias parameters, from the pipeline or `vkCmdSetDepthBias*`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    /// the largest magnitude of the bias; 0 doesn't clamp
+    pub clamp: f32,
+    pub slope_factor: f32,
+    pub representation: DepthBiasRepresentation,
+}
+
+impl Default for DepthBias {
+    fn default() -> Self {
+        DepthBias {
+            constant_factor: 0.0,
+            clamp: 0.0,
+            slope_factor: 0.0,
+            representation: DepthBiasRepresentation::LeastRepresentableValueFormat,
+        }
+    }
+}
+
+impl DepthBias {
+    /// uses the `LeastRepresentableValueFormat` representation, like `vkCmdSetDepthBias`
+    pub fn new(constant_factor: f32, clamp: f32, slope_factor: f32) -> Self {
+        DepthBias {
+            constant_factor,
+            clamp,
+            slope_factor,
+            representation: DepthBiasRepresentation::LeastRepresentableValueFormat,
+        }
+    }
+    /// `r`. `max_depth` is the largest depth magnitude of the polygon, which decides the unit of
+    /// floating-point attachments
+    #[allow(dead_code)]
+    pub fn get_unit(&self, format: DepthFormat, max_depth: f32) -> f32 {
+        match (self.representation, format) {
+            (DepthBiasRepresentation::Float, _) => 1.0,
+            (_, DepthFormat::Unorm { bits }) => (-(bits as f32)).exp2(),
+            (DepthBiasRepresentation::LeastRepresentableValueForceUnorm, DepthFormat::Float32) => {
+                (-24.0f32).exp2()
+            }
+            (DepthBiasRepresentation::LeastRepresentableValueFormat, DepthFormat::Float32) => {
+                // the unit of the last mantissa bit at the largest depth's exponent, where
+                // subnormals use the smallest normal exponent
+                let biased_exponent = ((max_depth.abs().to_bits() >> 23) & 0xFF).max(1) as i32;
+                // computed in f64 since 2^149 overflows f32
+                2.0f64.powi(biased_exponent - 127 - 23) as f32
+            }
+        }
+    }
+    /// the offset added to the depths of a polygon's fragments
+    #[allow(dead_code)]
+    pub fn get_offset(&self, max_depth_slope: f32, format: DepthFormat, max_depth: f32) -> f32 {
+        let offset = max_depth_slope * self.slope_factor
+            + self.get_unit(format, max_depth) * self.constant_factor;
+        if self.clamp > 0.0 {
+            offset.min(self.clamp)
+        } else if self.clamp < 0.0 {
+            offset.max(self.clamp)
+        } else {
+            offset
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport(min_depth: f32, max_depth: f32) -> Viewport {
+        Viewport {
+            x: 10.0,
+            y: 20.0,
+            width: 100.0,
+            height: 50.0,
+            min_depth,
+            max_depth,
+        }
+    }
+
+    #[test]
+    fn test_transform() {
+        assert_eq!(
+            viewport(0.0, 1.0).transform([-1.0, 1.0, 0.25]),
+            [10.0, 70.0, 0.25]
+        );
+        assert_eq!(
+            viewport(1.0, 0.0).transform([0.0, 0.0, 0.25]),
+            [60.0, 45.0, 0.75]
+        );
+        assert_eq!(
+            viewport(-2.0, 2.0).transform([0.0, 0.0, 1.0]),
+            [60.0, 45.0, 2.0]
+        );
+    }
+
+    #[test]
+    fn test_clamp_depth() {
+        let unorm = DepthFormat::Unorm { bits: 24 };
+        let float = DepthFormat::Float32;
+        let viewport = viewport(2.0, -2.0);
+        assert_eq!(viewport.clamp_depth(3.0, false, float, true), 3.0);
+        assert_eq!(viewport.clamp_depth(3.0, true, float, true), 2.0);
+        assert_eq!(viewport.clamp_depth(-3.0, true, float, true), -2.0);
+        assert_eq!(viewport.clamp_depth(3.0, false, float, false), 1.0);
+        assert_eq!(viewport.clamp_depth(-3.0, true, unorm, true), 0.0);
+    }
+
+    #[test]
+    fn test_depth_bias() {
+        let mut depth_bias = DepthBias {
+            constant_factor: 2.0,
+            clamp: 0.0,
+            slope_factor: 0.5,
+            representation: DepthBiasRepresentation::LeastRepresentableValueFormat,
+        };
+        let unorm = DepthFormat::Unorm { bits: 16 };
+        let float = DepthFormat::Float32;
+        assert_eq!(depth_bias.get_unit(unorm, 0.5), 1.0 / 65536.0);
+        assert_eq!(depth_bias.get_unit(float, 0.75), (-24.0f32).exp2());
+        assert_eq!(depth_bias.get_unit(float, 1.0), (-23.0f32).exp2());
+        assert_eq!(depth_bias.get_unit(float, 0.0), (-149.0f32).exp2());
+        assert_eq!(
+            depth_bias.get_offset(0.25, unorm, 0.5),
+            0.125 + 2.0 / 65536.0
+        );
+        depth_bias.clamp = 0.1;
+        assert_eq!(depth_bias.get_offset(0.25, unorm, 0.5), 0.1);
+        depth_bias.clamp = -0.1;
+        depth_bias.slope_factor = -0.5;
+        assert_eq!(depth_bias.get_offset(0.25, unorm, 0.5), -0.1);
+        depth_bias.representation = DepthBiasRepresentation::LeastRepresentableValueForceUnorm;
+        assert_eq!(depth_bias.get_unit(float, 1.0), (-24.0f32).exp2());
+        assert_eq!(depth_bias.get_unit(unorm, 1.0), 1.0 / 65536.0);
+        depth_bias.representation = DepthBiasRepresentation::Float;
+        depth_bias.clamp = 0.0;
+        assert_eq!(depth_bias.get_offset(0.0, unorm, 0.5), 2.0);
+    }
+}
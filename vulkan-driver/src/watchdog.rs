@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! the queue watchdog, which turns submissions that never finish into device losses
+//!
+//! each submission gets a `Watchdog` that's passed to every shader invocation. a thread per
+//! queue cancels the watchdog if the submission is still running after the timeout, which stops
+//! the executor before its next command. the submission then fails with `VK_ERROR_DEVICE_LOST`
+//! and the reason is reported like any other device loss, instead of the host process hanging
+//! forever. shaders aren't instrumented to stop at loop back edges yet (see
+//! `shader_compiler::watchdog`), so a single draw or dispatch that never finishes still hangs.
+
+use shader_compiler::watchdog::Watchdog;
+use std::env;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// the number of seconds a submission can run before it's stopped; 0 disables the timeout
+pub const WATCHDOG_TIMEOUT_ENV_VAR: &str = "KAZAN_WATCHDOG_TIMEOUT";
+
+/// the most loop iterations each shader invocation can run before it's stopped; 0 disables the
+/// limit. by default, the limit is only set in debug builds
+pub const LOOP_ITERATION_LIMIT_ENV_VAR: &str = "KAZAN_LOOP_ITERATION_LIMIT";
+
+pub const DEFAULT_WATCHDOG_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// high enough that shaders that finish don't hit it, low enough that infinite loops are caught
+/// in a fraction of a second
+pub const DEFAULT_LOOP_ITERATION_LIMIT: u64 = 1 << 24;
+
+/// reads a number from an environment variable, where 0 means `None`
+fn get_optional_number_from_env_var(name: &str, default: Option<u64>) -> Option<u64> {
+    let value = match env::var(name) {
+        Ok(value) => value,
+        Err(_) => return default,
+    };
+    match value.parse::<u64>() {
+        Ok(0) => None,
+        Ok(value) => Some(value),
+        Err(_) => {
+            eprintln!("{} must be a number, ignoring: {:?}", name, value);
+            default
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct WatchdogConfig {
+    /// set by `KAZAN_WATCHDOG_TIMEOUT`
+    pub timeout: Option<Duration>,
+    /// set by `KAZAN_LOOP_ITERATION_LIMIT`
+    pub loop_iteration_limit: Option<u64>,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            timeout: Some(DEFAULT_WATCHDOG_TIMEOUT),
+            loop_iteration_limit: if cfg!(debug_assertions) {
+                Some(DEFAULT_LOOP_ITERATION_LIMIT)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+impl WatchdogConfig {
+    pub fn from_environment() -> Self {
+        let default = Self::default();
+        WatchdogConfig {
+            timeout: get_optional_number_from_env_var(
+                WATCHDOG_TIMEOUT_ENV_VAR,
+                default.timeout.map(|timeout| timeout.as_secs()),
+            )
+            .map(Duration::from_secs),
+            loop_iteration_limit: get_optional_number_from_env_var(
+                LOOP_ITERATION_LIMIT_ENV_VAR,
+                default.loop_iteration_limit,
+            ),
+        }
+    }
+    /// whether shaders have to be compiled with `watchdog_checks`
+    pub fn needs_shader_checks(&self) -> bool {
+        self.timeout.is_some() || self.loop_iteration_limit.is_some()
+    }
+}
+
+#[derive(Default)]
+struct QueueWatchdogState {
+    /// the running submission's deadline and watchdog
+    watched: Option<(Instant, Arc<Watchdog>)>,
+    exit: bool,
+}
+
+/// the thread that cancels a queue's submissions when they time out
+pub struct QueueWatchdog {
+    timeout: Duration,
+    state: Arc<(Mutex<QueueWatchdogState>, Condvar)>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl QueueWatchdog {
+    pub fn new(timeout: Duration) -> Self {
+        let state = Arc::new((Mutex::new(QueueWatchdogState::default()), Condvar::new()));
+        let thread_state = state.clone();
+        let thread = thread::Builder::new()
+            .name("kazan-watchdog".into())
+            .spawn(move || {
+                let (ref state, ref condition_variable) = *thread_state;
+                let mut state = state.lock().unwrap();
+                while !state.exit {
+                    let deadline = match state.watched {
+                        Some((deadline, _)) => deadline,
+                        None => {
+                            state = condition_variable.wait(state).unwrap();
+                            continue;
+                        }
+                    };
+                    let now = Instant::now();
+                    if now >= deadline {
+                        let (_, watchdog) = state.watched.take().unwrap();
+                        watchdog.cancel();
+                    } else {
+                        state = condition_variable
+                            .wait_timeout(state, deadline - now)
+                            .unwrap()
+                            .0;
+                    }
+                }
+            })
+            .expect("can't start the watchdog thread");
+        QueueWatchdog {
+            timeout,
+            state,
+            thread: Some(thread),
+        }
+    }
+    fn set_watched(&self, watched: Option<(Instant, Arc<Watchdog>)>) {
+        let (ref state, ref condition_variable) = *self.state;
+        state.lock().unwrap().watched = watched;
+        condition_variable.notify_one();
+    }
+    /// cancel `watchdog` if the returned `Watch` isn't dropped before the timeout
+    pub fn watch(&self, watchdog: Arc<Watchdog>) -> Watch<'_> {
+        self.set_watched(Some((Instant::now() + self.timeout, watchdog)));
+        Watch(self)
+    }
+}
+
+impl Drop for QueueWatchdog {
+    fn drop(&mut self) {
+        {
+            let (ref state, ref condition_variable) = *self.state;
+            state.lock().unwrap().exit = true;
+            condition_variable.notify_one();
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// stops watching the submission when dropped
+pub struct Watch<'a>(&'a QueueWatchdog);
+
+impl<'a> Drop for Watch<'a> {
+    fn drop(&mut self) {
+        self.0.set_watched(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shader_compiler::watchdog::WatchdogTrip;
+
+    #[test]
+    fn test_queue_watchdog() {
+        let queue_watchdog = QueueWatchdog::new(Duration::from_millis(10));
+        let finished = Arc::new(Watchdog::new(None));
+        drop(queue_watchdog.watch(finished.clone()));
+        let timed_out = Arc::new(Watchdog::new(None));
+        let watch = queue_watchdog.watch(timed_out.clone());
+        let mut iterations = 0;
+        // an infinite loop, as far as the watchdog can tell
+        while timed_out.check_back_edge(&mut iterations) {
+            thread::yield_now();
+        }
+        drop(watch);
+        assert_eq!(timed_out.get_trip(), Some(WatchdogTrip::Cancel));
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(finished.get_trip(), None);
+    }
+}
@@ -4,19 +4,32 @@ use api;
 use handle::Handle;
 use image::{ImageMultisampleCount, ImageProperties, SupportedTilings, Tiling};
 use libc;
+use present_conversion::{PresentConversion, CONVERTED_SURFACE_FORMATS};
 use std::borrow::Cow;
 use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::os::raw::c_char;
 use std::ptr::null_mut;
 use std::ptr::NonNull;
-use swapchain::{SurfaceImplementation, SurfacePlatform, Swapchain};
+use swapchain::{PresentTimingHistory, SurfaceImplementation, SurfacePlatform, Swapchain};
 use xcb;
 
 #[derive(Debug)]
-pub struct XcbSwapchain {}
+pub struct XcbSwapchain {
+    present_timing_history: PresentTimingHistory,
+    /// converts images from the swapchain's format and color space to the window's pixels
+    #[allow(dead_code)]
+    present_conversion: PresentConversion,
+}
 
-impl Swapchain for XcbSwapchain {}
+impl Swapchain for XcbSwapchain {
+    unsafe fn present_image(&self, _image_index: u32) -> Result<(), api::VkResult> {
+        unimplemented!()
+    }
+    fn get_present_timing_history(&self) -> &PresentTimingHistory {
+        &self.present_timing_history
+    }
+}
 
 struct ReplyObject<T>(NonNull<T>);
 
@@ -324,6 +337,8 @@ impl SwapchainSetupFirstStage {
             shm_version,
             image_properties: ImageProperties {
                 supported_tilings: SupportedTilings::Any,
+                image_type: api::VK_IMAGE_TYPE_2D,
+                flags: 0,
                 format: api::VK_FORMAT_UNDEFINED,
                 extents: api::VkExtent3D {
                     width: image_extent.width,
@@ -375,7 +390,8 @@ impl SurfaceImplementation for XcbSurfaceImplementation {
                     api::VK_ERROR_SURFACE_LOST_KHR
                 }
             })?;
-        match first_stage.surface_format_group {
+        // the window's formats come first, since presenting them doesn't need converting
+        let native_formats = match first_stage.surface_format_group {
             SurfaceFormatGroup::B8G8R8A8 => {
                 const SURFACE_FORMATS: &[api::VkSurfaceFormatKHR] = &[
                     api::VkSurfaceFormatKHR {
@@ -387,7 +403,7 @@ impl SurfaceImplementation for XcbSurfaceImplementation {
                         colorSpace: api::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
                     },
                 ];
-                Ok(Cow::Borrowed(SURFACE_FORMATS))
+                SURFACE_FORMATS
             }
             SurfaceFormatGroup::R8G8B8A8 => {
                 const SURFACE_FORMATS: &[api::VkSurfaceFormatKHR] = &[
@@ -400,9 +416,14 @@ impl SurfaceImplementation for XcbSurfaceImplementation {
                         colorSpace: api::VK_COLOR_SPACE_SRGB_NONLINEAR_KHR,
                     },
                 ];
-                Ok(Cow::Borrowed(SURFACE_FORMATS))
+                SURFACE_FORMATS
             }
-        }
+        };
+        Ok(native_formats
+            .iter()
+            .chain(CONVERTED_SURFACE_FORMATS)
+            .cloned()
+            .collect())
     }
     unsafe fn get_present_modes(
         &self,
@@ -4,7 +4,7 @@
 #ifdef __ANDROID__
 #error not supported on Android; need to fix ABI
 #endif
-#ifdef __unix
+#if defined(__unix) && defined(KAZAN_WSI_XCB)
 #define VK_USE_PLATFORM_XCB_KHR
 #endif
 #define VK_NO_PROTOTYPES
@@ -0,0 +1,590 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+use registry::{Element, Node};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use Command;
+use CommandScope;
+use Error;
+
+/// the first offset of the enumerants added by extensions
+const EXTENSION_ENUM_BASE: i64 = 1_000_000_000;
+const EXTENSION_ENUM_BLOCK_SIZE: i64 = 1000;
+
+fn is_vulkan_api(element: &Element) -> bool {
+    match element.get_attribute("api") {
+        Some(api) => api.split(',').any(|v| v == "vulkan"),
+        None => true,
+    }
+}
+
+/// every command in `versions`, in registry order. the scope a command can be looked up from
+/// comes from the dispatchable handle its first parameter is, or belongs to: `Device` for
+/// `VkDevice` and its children, `Instance` for other dispatchable handles, and `Global` for
+/// commands without one.
+pub fn get_core_commands(registry: &Element, versions: &[String]) -> Result<Vec<Command>, Error> {
+    let types = registry
+        .get_child("types")
+        .ok_or_else(|| Error::InvalidRegistry("missing <types>".into()))?;
+    let mut handle_parents = HashMap::new();
+    let mut dispatchable_handles = HashSet::new();
+    for t in types.elements_named("type") {
+        if t.get_attribute("category") != Some("handle") || t.get_attribute("alias").is_some() {
+            continue;
+        }
+        let name = match t.get_child("name") {
+            Some(name) => name.get_text(),
+            None => continue,
+        };
+        if t.get_child("type").map(Element::get_text) == Some("VK_DEFINE_HANDLE".into()) {
+            dispatchable_handles.insert(name.clone());
+        }
+        handle_parents.insert(name, t.get_attribute("parent"));
+    }
+    let get_scope = |first_parameter_type: &str| {
+        let mut handle = first_parameter_type;
+        if !dispatchable_handles.contains(handle) {
+            return CommandScope::Global;
+        }
+        loop {
+            if handle == "VkDevice" {
+                return CommandScope::Device;
+            }
+            match handle_parents.get(handle) {
+                Some(&Some(parent)) => handle = parent,
+                _ => return CommandScope::Instance,
+            }
+        }
+    };
+    let mut first_parameter_types = HashMap::new();
+    for command in registry
+        .elements_named("commands")
+        .flat_map(|v| v.elements_named("command"))
+        .filter(|v| is_vulkan_api(v))
+    {
+        let proto = match command.get_child("proto") {
+            Some(proto) => proto,
+            None => continue, // aliases of other commands
+        };
+        let name = proto
+            .get_child("name")
+            .ok_or_else(|| Error::InvalidRegistry("<proto> without <name>".into()))?
+            .get_text();
+        let first_parameter_type = command
+            .elements_named("param")
+            .find(|v| is_vulkan_api(v))
+            .and_then(|v| v.get_child("type"))
+            .map(Element::get_text)
+            .unwrap_or_default();
+        first_parameter_types.insert(name, first_parameter_type);
+    }
+    let mut retval = Vec::new();
+    let mut generated_commands = HashSet::new();
+    for version in versions {
+        let feature = registry
+            .elements_named("feature")
+            .find(|v| v.get_attribute("name") == Some(version) && is_vulkan_api(v))
+            .ok_or_else(|| Error::InvalidRegistry(format!("unknown version: {}", version)))?;
+        for command in feature
+            .elements_named("require")
+            .flat_map(|v| v.elements_named("command"))
+        {
+            let name = command
+                .get_name()
+                .ok_or_else(|| Error::InvalidRegistry("<command> without name".into()))?;
+            if !generated_commands.insert(name.clone()) {
+                continue;
+            }
+            let scope = match first_parameter_types.get(&name) {
+                Some(first_parameter_type) => get_scope(first_parameter_type),
+                None => return Err(Error::InvalidRegistry(format!("unknown command: {}", name))),
+            };
+            retval.push(Command { name, scope });
+        }
+    }
+    Ok(retval)
+}
+
+/// a block with a `proc_address!` invocation for every command, for `get_proc_address` to
+/// include
+pub fn generate_core_commands(commands: &[Command]) -> String {
+    let mut code = String::from("{\n");
+    for command in commands {
+        writeln!(
+            code,
+            "    proc_address!({0}, PFN_{0}, {1}, true);",
+            command.name,
+            command.scope.get_proc_address_scope_name()
+        )
+        .unwrap();
+    }
+    code += "}\n";
+    code
+}
+
+fn get_rust_type(c_type: &str) -> &str {
+    match c_type {
+        "void" => "::std::os::raw::c_void",
+        "char" => "::std::os::raw::c_char",
+        "int" => "::std::os::raw::c_int",
+        "float" => "f32",
+        "double" => "f64",
+        "size_t" => "usize",
+        "int8_t" => "i8",
+        "int16_t" => "i16",
+        "int32_t" => "i32",
+        "int64_t" => "i64",
+        "uint8_t" => "u8",
+        "uint16_t" => "u16",
+        "uint32_t" => "u32",
+        "uint64_t" => "u64",
+        _ => c_type,
+    }
+}
+
+fn get_rust_name(name: &str) -> String {
+    match name {
+        "type" | "ref" | "mod" | "fn" | "impl" | "match" | "self" | "box" | "loop" => {
+            format!("{}_", name)
+        }
+        _ => name.into(),
+    }
+}
+
+/// a struct member, or a command parameter or prototype
+struct Declaration {
+    name: String,
+    c_type: String,
+    rust_type: String,
+    is_array: bool,
+    comment: Option<String>,
+}
+
+fn parse_declaration(element: &Element) -> Result<Declaration, Error> {
+    let invalid = || {
+        Error::InvalidRegistry(format!(
+            "can't parse <{}>: {}",
+            element.name,
+            element.get_text().trim()
+        ))
+    };
+    let mut is_const = false;
+    let mut c_type = None;
+    let mut pointers = String::new();
+    let mut name = None;
+    let mut array_length = String::new();
+    let mut comment = None;
+    for child in &element.children {
+        match child {
+            Node::Text(text) => {
+                if name.is_some() {
+                    array_length += text;
+                } else if c_type.is_some() {
+                    pointers += text;
+                } else {
+                    for token in text.split_whitespace() {
+                        match token {
+                            "const" => is_const = true,
+                            "struct" => {}
+                            _ => return Err(invalid()),
+                        }
+                    }
+                }
+            }
+            Node::Element(child) => match &*child.name {
+                "type" => c_type = Some(child.get_text()),
+                "name" => name = Some(child.get_text()),
+                "enum" if name.is_some() => array_length += &child.get_text(),
+                "comment" => comment = Some(child.get_text()),
+                _ => return Err(invalid()),
+            },
+        }
+    }
+    let c_type = c_type.ok_or_else(invalid)?;
+    let name = name.ok_or_else(invalid)?;
+    let mut rust_type = String::from(get_rust_type(&c_type));
+    let mut is_pointee_const = is_const;
+    for token in pointers.replace('*', " * ").split_whitespace() {
+        match token {
+            "*" => {
+                let mutability = if is_pointee_const { "const" } else { "mut" };
+                rust_type = format!("*{} {}", mutability, rust_type);
+                is_pointee_const = false;
+            }
+            "const" => is_pointee_const = true,
+            _ => return Err(invalid()),
+        }
+    }
+    let array_length = array_length.trim();
+    let is_array = !array_length.is_empty();
+    if is_array {
+        if !array_length.starts_with('[') || !array_length.ends_with(']') {
+            return Err(invalid());
+        }
+        let array_length = array_length[1..array_length.len() - 1].trim();
+        rust_type = if array_length.bytes().all(|v| v.is_ascii_digit()) {
+            format!("[{}; {}]", rust_type, array_length)
+        } else {
+            format!("[{}; {} as usize]", rust_type, array_length)
+        };
+    }
+    Ok(Declaration {
+        name,
+        c_type,
+        rust_type,
+        is_array,
+        comment,
+    })
+}
+
+/// the names `vk.xml` already has, which bindgen generates from the headers
+struct DefinedNames {
+    types: HashSet<String>,
+    constants: HashSet<String>,
+    commands: HashSet<String>,
+    features_and_extensions: HashSet<String>,
+}
+
+impl DefinedNames {
+    fn new(registry: &Element) -> Self {
+        let mut retval = DefinedNames {
+            types: HashSet::new(),
+            constants: HashSet::new(),
+            commands: HashSet::new(),
+            features_and_extensions: HashSet::new(),
+        };
+        for element in registry.elements() {
+            let features_and_extensions: Vec<&Element> = match &*element.name {
+                "types" => {
+                    retval
+                        .types
+                        .extend(element.elements_named("type").filter_map(Element::get_name));
+                    continue;
+                }
+                "enums" => {
+                    retval
+                        .constants
+                        .extend(element.elements_named("enum").filter_map(Element::get_name));
+                    continue;
+                }
+                "commands" => {
+                    retval.commands.extend(
+                        element
+                            .elements_named("command")
+                            .filter_map(Element::get_name),
+                    );
+                    continue;
+                }
+                "feature" => vec![element],
+                "extensions" => element.elements_named("extension").collect(),
+                _ => continue,
+            };
+            for feature_or_extension in features_and_extensions {
+                retval
+                    .features_and_extensions
+                    .extend(feature_or_extension.get_name());
+                // constants added by features and extensions are only declared in `<require>`
+                retval.constants.extend(
+                    feature_or_extension
+                        .elements_named("require")
+                        .flat_map(|v| v.elements_named("enum"))
+                        .filter_map(Element::get_name),
+                );
+            }
+        }
+        retval
+    }
+}
+
+struct BindingsGenerator<'a> {
+    defined_names: DefinedNames,
+    base_unions: HashSet<String>,
+    types: HashMap<String, &'a Element>,
+    enums: HashMap<String, &'a Element>,
+    commands: HashMap<String, &'a Element>,
+    generated: HashSet<String>,
+    code: String,
+}
+
+impl<'a> BindingsGenerator<'a> {
+    fn is_union(&self, name: &str) -> bool {
+        self.base_unions.contains(name)
+            || self
+                .types
+                .get(name)
+                .is_some_and(|v| v.get_attribute("category") == Some("union"))
+    }
+    fn generate_constant(
+        &mut self,
+        element: &Element,
+        default_type: &str,
+        extension_number: Option<&str>,
+    ) -> Result<(), Error> {
+        let name = element
+            .get_name()
+            .ok_or_else(|| Error::InvalidRegistry("<enum> without name".into()))?;
+        let invalid = || Error::InvalidRegistry(format!("invalid <enum>: {}", name));
+        let value = if let Some(alias) = element.get_attribute("alias") {
+            alias.to_string()
+        } else if let Some(value) = element.get_attribute("value") {
+            value.to_string()
+        } else if let Some(bit_position) = element.get_attribute("bitpos") {
+            let bit_position: u32 = bit_position.parse().map_err(|_| invalid())?;
+            format!("{:#x}", 1u64 << bit_position)
+        } else if let Some(offset) = element.get_attribute("offset") {
+            let offset: i64 = offset.parse().map_err(|_| invalid())?;
+            let extension_number: i64 = element
+                .get_attribute("extnumber")
+                .or(extension_number)
+                .ok_or_else(invalid)?
+                .parse()
+                .map_err(|_| invalid())?;
+            let value =
+                EXTENSION_ENUM_BASE + (extension_number - 1) * EXTENSION_ENUM_BLOCK_SIZE + offset;
+            if element.get_attribute("dir") == Some("-") {
+                (-value).to_string()
+            } else {
+                value.to_string()
+            }
+        } else {
+            // references a constant defined elsewhere
+            return Ok(());
+        };
+        if self.defined_names.constants.contains(&name) || !self.generated.insert(name.clone()) {
+            return Ok(());
+        }
+        let constant_type = element.get_attribute("extends").unwrap_or(default_type);
+        if value.starts_with('"') {
+            let value = value.trim_matches('"');
+            writeln!(
+                self.code,
+                "pub const {}: &'static [u8; {}] = b\"{}\\0\";",
+                name,
+                value.len() + 1,
+                value
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                self.code,
+                "pub const {}: {} = {};",
+                name, constant_type, value
+            )
+            .unwrap();
+        }
+        Ok(())
+    }
+    fn generate_struct(&mut self, name: &str, definition: &Element) -> Result<(), Error> {
+        let is_union = definition.get_attribute("category") == Some("union");
+        let mut members = Vec::new();
+        for member in definition.elements_named("member") {
+            members.push(parse_declaration(member)?);
+        }
+        let can_derive_debug = !is_union
+            && members
+                .iter()
+                .all(|member| !member.is_array && !self.is_union(&member.c_type));
+        writeln!(self.code, "#[repr(C)]").unwrap();
+        if can_derive_debug {
+            writeln!(self.code, "#[derive(Copy, Clone, Debug)]").unwrap();
+        } else {
+            writeln!(self.code, "#[derive(Copy, Clone)]").unwrap();
+        }
+        let keyword = if is_union { "union" } else { "struct" };
+        writeln!(self.code, "pub {} {} {{", keyword, name).unwrap();
+        for member in members {
+            if let Some(comment) = member.comment {
+                writeln!(self.code, "    /// {}", comment.trim()).unwrap();
+            }
+            writeln!(
+                self.code,
+                "    pub {}: {},",
+                get_rust_name(&member.name),
+                member.rust_type
+            )
+            .unwrap();
+        }
+        writeln!(self.code, "}}").unwrap();
+        Ok(())
+    }
+    fn generate_type(&mut self, name: &str) -> Result<(), Error> {
+        if self.defined_names.types.contains(name) || !self.generated.insert(name.into()) {
+            return Ok(());
+        }
+        let definition = *self
+            .types
+            .get(name)
+            .ok_or_else(|| Error::InvalidRegistry(format!("unknown type: {}", name)))?;
+        writeln!(self.code).unwrap();
+        match definition.get_attribute("category") {
+            Some("struct") | Some("union") => self.generate_struct(name, definition),
+            Some("bitmask") => {
+                let base_type = definition
+                    .get_child("type")
+                    .ok_or_else(|| Error::InvalidRegistry(format!("invalid bitmask: {}", name)))?
+                    .get_text();
+                writeln!(self.code, "pub type {} = {};", name, base_type).unwrap();
+                Ok(())
+            }
+            Some("enum") => {
+                let values = self.enums.get(name).cloned();
+                let is_signed = values.is_some_and(|values| {
+                    values
+                        .elements_named("enum")
+                        .any(|v| v.get_attribute("value").is_some_and(|v| v.starts_with('-')))
+                });
+                let base_type = if is_signed { "i32" } else { "u32" };
+                writeln!(self.code, "pub type {} = {};", name, base_type).unwrap();
+                if let Some(values) = values {
+                    for value in values.elements_named("enum") {
+                        self.generate_constant(value, name, None)?;
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(Error::InvalidRegistry(format!(
+                "can't generate type: {}",
+                name
+            ))),
+        }
+    }
+    fn generate_command(&mut self, name: &str) -> Result<(), Error> {
+        if self.defined_names.commands.contains(name) || !self.generated.insert(name.into()) {
+            return Ok(());
+        }
+        let definition = *self
+            .commands
+            .get(name)
+            .ok_or_else(|| Error::InvalidRegistry(format!("unknown command: {}", name)))?;
+        let proto = parse_declaration(
+            definition
+                .get_child("proto")
+                .ok_or_else(|| Error::InvalidRegistry(format!("invalid command: {}", name)))?,
+        )?;
+        writeln!(self.code).unwrap();
+        writeln!(self.code, "pub type PFN_{} = Option<", name).unwrap();
+        writeln!(self.code, "    unsafe extern \"system\" fn(").unwrap();
+        for parameter in definition.elements_named("param") {
+            let parameter = parse_declaration(parameter)?;
+            writeln!(
+                self.code,
+                "        {}: {},",
+                get_rust_name(&parameter.name),
+                parameter.rust_type
+            )
+            .unwrap();
+        }
+        if proto.rust_type == get_rust_type("void") {
+            writeln!(self.code, "    ),").unwrap();
+        } else {
+            writeln!(self.code, "    ) -> {},", proto.rust_type).unwrap();
+        }
+        writeln!(self.code, ">;").unwrap();
+        Ok(())
+    }
+    fn generate_requirements(&mut self, feature_or_extension: &Element) -> Result<(), Error> {
+        let extension_number = feature_or_extension.get_attribute("number");
+        let extension_number = if feature_or_extension.name == "extension" {
+            extension_number
+        } else {
+            None
+        };
+        for require in feature_or_extension.elements_named("require") {
+            let mut is_first_constant = true;
+            for item in require.elements() {
+                match &*item.name {
+                    "enum" => {
+                        if is_first_constant {
+                            writeln!(self.code).unwrap();
+                            is_first_constant = false;
+                        }
+                        self.generate_constant(item, "u32", extension_number)?
+                    }
+                    "type" => {
+                        let name = item
+                            .get_name()
+                            .ok_or_else(|| Error::InvalidRegistry("<type> without name".into()))?;
+                        self.generate_type(&name)?;
+                        is_first_constant = true;
+                    }
+                    "command" => {
+                        let name = item.get_name().ok_or_else(|| {
+                            Error::InvalidRegistry("<command> without name".into())
+                        })?;
+                        self.generate_command(&name)?;
+                        is_first_constant = true;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the constants, types and entry point declarations of the features and extensions in the
+/// supplements that `registry` doesn't have
+pub fn generate_bindings(registry: &Element, supplements: &[Element]) -> Result<String, Error> {
+    let mut generator = BindingsGenerator {
+        defined_names: DefinedNames::new(registry),
+        base_unions: registry
+            .elements_named("types")
+            .flat_map(|v| v.elements_named("type"))
+            .filter(|v| v.get_attribute("category") == Some("union"))
+            .filter_map(Element::get_name)
+            .collect(),
+        types: HashMap::new(),
+        enums: HashMap::new(),
+        commands: HashMap::new(),
+        generated: HashSet::new(),
+        code: String::new(),
+    };
+    for supplement in supplements {
+        for t in supplement
+            .elements_named("types")
+            .flat_map(|v| v.elements_named("type"))
+        {
+            if let Some(name) = t.get_name() {
+                generator.types.insert(name, t);
+            }
+        }
+        for enums in supplement.elements_named("enums") {
+            if let Some(name) = enums.get_name() {
+                generator.enums.insert(name, enums);
+            }
+        }
+        for command in supplement
+            .elements_named("commands")
+            .flat_map(|v| v.elements_named("command"))
+        {
+            if let Some(name) = command.get_name() {
+                generator.commands.insert(name, command);
+            }
+        }
+    }
+    for supplement in supplements {
+        for element in supplement.elements() {
+            let features_and_extensions: Vec<&Element> = match &*element.name {
+                "feature" => vec![element],
+                "extensions" => element.elements_named("extension").collect(),
+                _ => continue,
+            };
+            for feature_or_extension in features_and_extensions {
+                let name = feature_or_extension.get_name().ok_or_else(|| {
+                    Error::InvalidRegistry(format!("<{}> without name", feature_or_extension.name))
+                })?;
+                if generator
+                    .defined_names
+                    .features_and_extensions
+                    .contains(&name)
+                {
+                    continue;
+                }
+                writeln!(generator.code, "\n// {}", name).unwrap();
+                generator.generate_requirements(feature_or_extension)?;
+            }
+        }
+    }
+    Ok(generator.code)
+}
@@ -0,0 +1,562 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! generates the driver's Vulkan bindings from the registry (`vk.xml`):
+//!
+//! * the table of core commands that `vkGetInstanceProcAddr` and `vkGetDeviceProcAddr` look up
+//! * the constants, structs and entry point declarations (`PFN_vk*` types) of the features and
+//!   extensions that are newer than `vk.xml`, or aren't registered with Khronos at all, which
+//!   are described by supplements in the registry format
+
+extern crate xml;
+
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::path::PathBuf;
+
+mod generate;
+mod registry;
+
+pub const VK_XML_FILE_NAME: &str = "vk.xml";
+
+/// the scope of the dispatchable handle a command is called on
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum CommandScope {
+    Global,
+    Instance,
+    Device,
+}
+
+impl CommandScope {
+    /// the member of the `Scope` struct in `get_proc_address`
+    pub fn get_proc_address_scope_name(self) -> &'static str {
+        match self {
+            CommandScope::Global => "global",
+            CommandScope::Instance => "instance",
+            CommandScope::Device => "device",
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Command {
+    pub name: String,
+    pub scope: CommandScope,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    IOError(io::Error),
+    XMLError(xml::reader::Error),
+    InvalidRegistry(String),
+}
+
+impl From<io::Error> for Error {
+    fn from(v: io::Error) -> Error {
+        Error::IOError(v)
+    }
+}
+
+impl From<xml::reader::Error> for Error {
+    fn from(v: xml::reader::Error) -> Error {
+        Error::XMLError(v)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::IOError(v) => fmt::Display::fmt(v, f),
+            Error::XMLError(v) => fmt::Display::fmt(v, f),
+            Error::InvalidRegistry(v) => write!(f, "invalid registry: {}", v),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::IOError(v) => v,
+            error => io::Error::other(format!("{}", error)),
+        }
+    }
+}
+
+/// the generated code
+pub struct Output {
+    core_commands: Vec<Command>,
+    core_commands_text: String,
+    bindings_text: String,
+}
+
+impl Output {
+    pub fn get_core_commands(&self) -> &[Command] {
+        &self.core_commands
+    }
+    /// a block with a `proc_address!` invocation for every core command
+    pub fn write_core_commands<W: Write>(&self, mut output: W) -> io::Result<()> {
+        write!(output, "{}", self.core_commands_text)
+    }
+    pub fn write_core_commands_to_file<T: AsRef<Path>>(&self, path: T) -> io::Result<()> {
+        self.write_core_commands(File::create(path)?)
+    }
+    /// the items generated from the supplements
+    pub fn write_bindings<W: Write>(&self, mut output: W) -> io::Result<()> {
+        write!(output, "{}", self.bindings_text)
+    }
+    pub fn write_bindings_to_file<T: AsRef<Path>>(&self, path: T) -> io::Result<()> {
+        self.write_bindings(File::create(path)?)
+    }
+}
+
+pub struct Input {
+    vk_xml_path: PathBuf,
+    supplement_paths: Vec<PathBuf>,
+    core_versions: Vec<String>,
+}
+
+impl Input {
+    pub fn new<T: AsRef<Path>>(vk_xml_path: T) -> Input {
+        Input {
+            vk_xml_path: vk_xml_path.as_ref().into(),
+            supplement_paths: Vec::new(),
+            core_versions: Vec::new(),
+        }
+    }
+    /// adds a file in the registry format. bindings are only generated for the features and
+    /// extensions `vk.xml` doesn't have, so they can stay in the supplement when the headers are
+    /// updated
+    pub fn add_supplement<T: AsRef<Path>>(mut self, path: T) -> Self {
+        self.supplement_paths.push(path.as_ref().into());
+        self
+    }
+    /// adds a core version, like `VK_VERSION_1_0`, to the command table
+    pub fn add_core_version<T: Into<String>>(mut self, name: T) -> Self {
+        self.core_versions.push(name.into());
+        self
+    }
+    pub fn generate(self) -> Result<Output, Error> {
+        let Input {
+            vk_xml_path,
+            supplement_paths,
+            core_versions,
+        } = self;
+        let registry = registry::Element::parse(File::open(vk_xml_path)?)?;
+        let mut supplements = Vec::new();
+        for path in supplement_paths {
+            supplements.push(registry::Element::parse(File::open(path)?)?);
+        }
+        let core_commands = generate::get_core_commands(&registry, &core_versions)?;
+        let core_commands_text = generate::generate_core_commands(&core_commands);
+        let bindings = generate::generate_bindings(&registry, &supplements)?;
+        Ok(Output {
+            core_commands,
+            core_commands_text: format!(
+                "// automatically generated by vulkan-registry-generator from {}\n{}",
+                VK_XML_FILE_NAME, core_commands_text
+            ),
+            bindings_text: format!(
+                "// automatically generated by vulkan-registry-generator\n{}",
+                bindings
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use registry::Element;
+    use std::collections::HashSet;
+
+    const REGISTRY: &str = r#"<registry>
+    <types>
+        <type category="basetype">typedef <type>uint32_t</type> <name>VkFlags</name>;</type>
+        <type category="handle"><type>VK_DEFINE_HANDLE</type>(<name>VkInstance</name>)</type>
+        <type category="handle" parent="VkInstance"><type>VK_DEFINE_HANDLE</type>(<name>VkPhysicalDevice</name>)</type>
+        <type category="handle" parent="VkPhysicalDevice"><type>VK_DEFINE_HANDLE</type>(<name>VkDevice</name>)</type>
+        <type category="handle" parent="VkDevice"><type>VK_DEFINE_NON_DISPATCHABLE_HANDLE</type>(<name>VkCommandPool</name>)</type>
+        <type category="handle" parent="VkCommandPool"><type>VK_DEFINE_HANDLE</type>(<name>VkCommandBuffer</name>)</type>
+        <type category="handle" name="VkCommandPoolKHR" alias="VkCommandPool"/>
+    </types>
+    <commands>
+        <command>
+            <proto><type>VkResult</type> <name>vkCreateInstance</name></proto>
+            <param>const <type>VkInstanceCreateInfo</type>* <name>pCreateInfo</name></param>
+            <param><type>VkInstance</type>* <name>pInstance</name></param>
+        </command>
+        <command>
+            <proto><type>VkResult</type> <name>vkEnumeratePhysicalDevices</name></proto>
+            <param><type>VkInstance</type> <name>instance</name></param>
+            <param><type>uint32_t</type>* <name>pPhysicalDeviceCount</name></param>
+            <param><type>VkPhysicalDevice</type>* <name>pPhysicalDevices</name></param>
+        </command>
+        <command>
+            <proto><type>void</type> <name>vkGetPhysicalDeviceFeatures</name></proto>
+            <param><type>VkPhysicalDevice</type> <name>physicalDevice</name></param>
+            <param><type>VkPhysicalDeviceFeatures</type>* <name>pFeatures</name></param>
+        </command>
+        <command>
+            <proto><type>void</type> <name>vkDestroyCommandPool</name></proto>
+            <param><type>VkDevice</type> <name>device</name></param>
+            <param><type>VkCommandPool</type> <name>commandPool</name></param>
+        </command>
+        <command>
+            <proto><type>void</type> <name>vkCmdDraw</name></proto>
+            <param><type>VkCommandBuffer</type> <name>commandBuffer</name></param>
+            <param><type>uint32_t</type> <name>vertexCount</name></param>
+        </command>
+        <command>
+            <proto><type>VkResult</type> <name>vkEnumerateInstanceVersion</name></proto>
+            <param><type>uint32_t</type>* <name>pApiVersion</name></param>
+        </command>
+        <command name="vkTrimCommandPoolKHR" alias="vkTrimCommandPool"/>
+        <command>
+            <proto><type>void</type> <name>vkTrimCommandPool</name></proto>
+            <param><type>VkDevice</type> <name>device</name></param>
+            <param><type>VkCommandPool</type> <name>commandPool</name></param>
+        </command>
+        <command api="vulkansc">
+            <proto><type>VkResult</type> <name>vkGetFaultData</name></proto>
+            <param><type>VkDevice</type> <name>device</name></param>
+        </command>
+    </commands>
+    <feature api="vulkan" name="VK_VERSION_1_0" number="1.0">
+        <require>
+            <command name="vkCreateInstance"/>
+            <command name="vkEnumeratePhysicalDevices"/>
+            <command name="vkGetPhysicalDeviceFeatures"/>
+        </require>
+        <require>
+            <command name="vkDestroyCommandPool"/>
+            <command name="vkCmdDraw"/>
+        </require>
+    </feature>
+    <feature api="vulkan" name="VK_VERSION_1_1" number="1.1">
+        <require>
+            <command name="vkEnumerateInstanceVersion"/>
+            <command name="vkTrimCommandPool"/>
+            <command name="vkCmdDraw"/>
+        </require>
+    </feature>
+    <feature api="vulkansc" name="VKSC_VERSION_1_0" number="1.0">
+        <require>
+            <command name="vkGetFaultData"/>
+        </require>
+    </feature>
+    <extensions>
+        <extension name="VK_KHR_maintenance1" number="70" type="device" supported="vulkan">
+            <require>
+                <enum value="2" name="VK_KHR_MAINTENANCE1_SPEC_VERSION"/>
+                <command name="vkTrimCommandPoolKHR"/>
+            </require>
+        </extension>
+    </extensions>
+</registry>
+"#;
+
+    fn parse(text: &str) -> Element {
+        Element::parse(text.as_bytes()).unwrap()
+    }
+
+    fn get_core_commands(versions: &[&str]) -> Result<Vec<Command>, Error> {
+        let versions: Vec<String> = versions.iter().map(|&v| v.into()).collect();
+        generate::get_core_commands(&parse(REGISTRY), &versions)
+    }
+
+    #[test]
+    fn core_commands() {
+        let expected: Vec<Command> = [
+            ("vkCreateInstance", CommandScope::Global),
+            ("vkEnumeratePhysicalDevices", CommandScope::Instance),
+            ("vkGetPhysicalDeviceFeatures", CommandScope::Instance),
+            ("vkDestroyCommandPool", CommandScope::Device),
+            ("vkCmdDraw", CommandScope::Device),
+            ("vkEnumerateInstanceVersion", CommandScope::Global),
+            ("vkTrimCommandPool", CommandScope::Device),
+        ]
+        .iter()
+        .map(|&(name, scope)| Command {
+            name: name.into(),
+            scope,
+        })
+        .collect();
+        assert_eq!(
+            get_core_commands(&["VK_VERSION_1_0", "VK_VERSION_1_1"]).unwrap(),
+            expected
+        );
+        assert_eq!(
+            get_core_commands(&["VK_VERSION_1_0"]).unwrap(),
+            &expected[..5]
+        );
+    }
+
+    #[test]
+    fn core_commands_table() {
+        let commands = get_core_commands(&["VK_VERSION_1_1"]).unwrap();
+        assert_eq!(
+            generate::generate_core_commands(&commands),
+            concat!(
+                "{\n",
+                "    proc_address!(vkEnumerateInstanceVersion, PFN_vkEnumerateInstanceVersion, ",
+                "global, true);\n",
+                "    proc_address!(vkTrimCommandPool, PFN_vkTrimCommandPool, device, true);\n",
+                "    proc_address!(vkCmdDraw, PFN_vkCmdDraw, device, true);\n",
+                "}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_core_version() {
+        assert!(get_core_commands(&["VK_VERSION_1_2"]).is_err());
+        assert!(get_core_commands(&["VKSC_VERSION_1_0"]).is_err());
+    }
+
+    const SUPPLEMENT: &str = r#"<registry>
+    <types>
+        <type category="enum" name="VkTestModeEXT"/>
+        <type requires="VkTestFlagBitsEXT" category="bitmask">typedef <type>VkFlags</type> <name>VkTestFlagsEXT</name>;</type>
+        <type category="union" name="VkTestValueEXT">
+            <member><type>uint32_t</type> <name>u32</name></member>
+            <member><type>float</type> <name>f32</name></member>
+        </type>
+        <type category="struct" name="VkTestInfoEXT">
+            <member><type>VkStructureType</type> <name>sType</name></member>
+            <member>const <type>void</type>* <name>pNext</name></member>
+            <member>const <type>char</type>* const* <name>ppNames</name></member>
+            <member><type>VkTestModeEXT</type> <name>type</name><comment>how to test</comment></member>
+        </type>
+        <type category="struct" name="VkTestPropertiesEXT">
+            <member><type>VkStructureType</type> <name>sType</name></member>
+            <member><type>void</type>* <name>pNext</name></member>
+            <member><type>float</type> <name>weights</name>[4]</member>
+            <member><type>char</type> <name>name</name>[<enum>VK_MAX_DESCRIPTION_SIZE</enum>]</member>
+        </type>
+        <type category="struct" name="VkTestResultEXT">
+            <member><type>VkTestValueEXT</type> <name>value</name></member>
+        </type>
+    </types>
+    <enums name="VkTestModeEXT" type="enum">
+        <enum value="0" name="VK_TEST_MODE_FAST_EXT"/>
+        <enum value="1" name="VK_TEST_MODE_SLOW_EXT"/>
+    </enums>
+    <commands>
+        <command>
+            <proto><type>void</type> <name>vkCmdTestEXT</name></proto>
+            <param><type>VkCommandBuffer</type> <name>commandBuffer</name></param>
+            <param>const <type>VkTestInfoEXT</type>* <name>pInfo</name></param>
+        </command>
+        <command>
+            <proto><type>VkResult</type> <name>vkMapTestEXT</name></proto>
+            <param><type>VkDevice</type> <name>device</name></param>
+            <param><type>void</type>** <name>ppData</name></param>
+        </command>
+        <command>
+            <proto><type>void</type> <name>vkTrimCommandPool</name></proto>
+            <param><type>VkDevice</type> <name>device</name></param>
+            <param><type>VkCommandPool</type> <name>commandPool</name></param>
+        </command>
+    </commands>
+    <feature api="vulkan" name="VK_VERSION_1_1" number="1.1">
+        <require>
+            <command name="vkTrimCommandPool"/>
+        </require>
+    </feature>
+    <extensions>
+        <extension name="VK_KHR_maintenance1" number="70" type="device" supported="vulkan">
+            <require>
+                <enum value="2" name="VK_KHR_MAINTENANCE1_SPEC_VERSION"/>
+            </require>
+        </extension>
+        <extension name="VK_EXT_test" number="5" type="device" supported="vulkan">
+            <require>
+                <enum value="1" name="VK_EXT_TEST_SPEC_VERSION"/>
+                <enum value="&quot;VK_EXT_test&quot;" name="VK_EXT_TEST_EXTENSION_NAME"/>
+                <enum name="VK_MAX_DESCRIPTION_SIZE"/>
+                <enum offset="0" extends="VkStructureType" name="VK_STRUCTURE_TYPE_TEST_INFO_EXT"/>
+                <enum offset="1" extends="VkStructureType" name="VK_STRUCTURE_TYPE_TEST_PROPERTIES_EXT"/>
+                <enum offset="0" extends="VkResult" dir="-" name="VK_ERROR_TEST_FAILED_EXT"/>
+                <enum offset="0" extends="VkResult" extnumber="2" name="VK_TEST_PENDING_EXT"/>
+                <enum bitpos="20" extends="VkImageCreateFlagBits" name="VK_IMAGE_CREATE_TEST_BIT_EXT"/>
+                <enum extends="VkResult" name="VK_TEST_PENDING_ALIAS_EXT" alias="VK_TEST_PENDING_EXT"/>
+                <type name="VkFlags"/>
+                <type name="VkTestModeEXT"/>
+                <type name="VkTestFlagsEXT"/>
+                <type name="VkTestValueEXT"/>
+                <type name="VkTestInfoEXT"/>
+                <type name="VkTestPropertiesEXT"/>
+                <type name="VkTestResultEXT"/>
+                <command name="vkCmdTestEXT"/>
+                <command name="vkMapTestEXT"/>
+                <command name="vkTrimCommandPool"/>
+            </require>
+        </extension>
+    </extensions>
+</registry>
+"#;
+
+    #[test]
+    fn bindings() {
+        let code = generate::generate_bindings(&parse(REGISTRY), &[parse(SUPPLEMENT)]).unwrap();
+        assert_eq!(
+            code,
+            r#"
+// VK_EXT_test
+
+pub const VK_EXT_TEST_SPEC_VERSION: u32 = 1;
+pub const VK_EXT_TEST_EXTENSION_NAME: &'static [u8; 12] = b"VK_EXT_test\0";
+pub const VK_STRUCTURE_TYPE_TEST_INFO_EXT: VkStructureType = 1000004000;
+pub const VK_STRUCTURE_TYPE_TEST_PROPERTIES_EXT: VkStructureType = 1000004001;
+pub const VK_ERROR_TEST_FAILED_EXT: VkResult = -1000004000;
+pub const VK_TEST_PENDING_EXT: VkResult = 1000001000;
+pub const VK_IMAGE_CREATE_TEST_BIT_EXT: VkImageCreateFlagBits = 0x100000;
+pub const VK_TEST_PENDING_ALIAS_EXT: VkResult = VK_TEST_PENDING_EXT;
+
+pub type VkTestModeEXT = u32;
+pub const VK_TEST_MODE_FAST_EXT: VkTestModeEXT = 0;
+pub const VK_TEST_MODE_SLOW_EXT: VkTestModeEXT = 1;
+
+pub type VkTestFlagsEXT = VkFlags;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union VkTestValueEXT {
+    pub u32: u32,
+    pub f32: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct VkTestInfoEXT {
+    pub sType: VkStructureType,
+    pub pNext: *const ::std::os::raw::c_void,
+    pub ppNames: *const *const ::std::os::raw::c_char,
+    /// how to test
+    pub type_: VkTestModeEXT,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct VkTestPropertiesEXT {
+    pub sType: VkStructureType,
+    pub pNext: *mut ::std::os::raw::c_void,
+    pub weights: [f32; 4],
+    pub name: [::std::os::raw::c_char; VK_MAX_DESCRIPTION_SIZE as usize],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct VkTestResultEXT {
+    pub value: VkTestValueEXT,
+}
+
+pub type PFN_vkCmdTestEXT = Option<
+    unsafe extern "system" fn(
+        commandBuffer: VkCommandBuffer,
+        pInfo: *const VkTestInfoEXT,
+    ),
+>;
+
+pub type PFN_vkMapTestEXT = Option<
+    unsafe extern "system" fn(
+        device: VkDevice,
+        ppData: *mut *mut ::std::os::raw::c_void,
+    ) -> VkResult,
+>;
+"#
+        );
+    }
+
+    #[test]
+    fn bindings_unknown_type() {
+        let supplement = parse(
+            r#"<registry>
+    <extensions>
+        <extension name="VK_EXT_test" number="5" type="device" supported="vulkan">
+            <require>
+                <type name="VkTestInfoEXT"/>
+            </require>
+        </extension>
+    </extensions>
+</registry>"#,
+        );
+        assert!(generate::generate_bindings(&parse(REGISTRY), &[supplement]).is_err());
+    }
+
+    #[test]
+    fn driver_supplement() {
+        let supplement = Element::parse(
+            File::open(
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("../vulkan-driver/vk-supplement.xml"),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let code = generate::generate_bindings(&parse(REGISTRY), &[supplement]).unwrap();
+        assert!(code.contains("pub struct VkPhysicalDeviceToolPropertiesEXT {"));
+        assert!(code.contains("pub type PFN_vkCmdGenerateMipmapsKAZAN = Option<"));
+    }
+
+    /// checks the table against a separate walk of `vk.xml`
+    #[test]
+    fn vk_xml_core_commands() -> Result<(), Error> {
+        let vk_xml_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../external/Vulkan-Headers/registry")
+            .join(VK_XML_FILE_NAME);
+        let versions = ["VK_VERSION_1_0", "VK_VERSION_1_1"];
+        let mut input = Input::new(&vk_xml_path);
+        for &version in &versions {
+            input = input.add_core_version(version);
+        }
+        let output = input.generate()?;
+        let registry = Element::parse(File::open(&vk_xml_path)?)?;
+        let mut expected = HashSet::new();
+        for feature in registry
+            .elements_named("feature")
+            .filter(|v| versions.contains(&v.get_attribute("name").unwrap()))
+        {
+            for command in feature
+                .elements_named("require")
+                .flat_map(|v| v.elements_named("command"))
+            {
+                expected.insert(command.get_attribute("name").unwrap().to_string());
+            }
+        }
+        let mut generated = HashSet::new();
+        for command in output.get_core_commands() {
+            assert!(generated.insert(command.name.clone()), "{}", command.name);
+        }
+        assert_eq!(generated, expected);
+        let get_scope = |name: &str| {
+            output
+                .get_core_commands()
+                .iter()
+                .find(|command| command.name == name)
+                .unwrap()
+                .scope
+        };
+        assert_eq!(get_scope("vkCreateInstance"), CommandScope::Global);
+        assert_eq!(
+            get_scope("vkEnumerateInstanceVersion"),
+            CommandScope::Global
+        );
+        assert_eq!(
+            get_scope("vkEnumeratePhysicalDevices"),
+            CommandScope::Instance
+        );
+        assert_eq!(
+            get_scope("vkGetPhysicalDeviceFeatures2"),
+            CommandScope::Instance
+        );
+        assert_eq!(get_scope("vkGetDeviceQueue"), CommandScope::Device);
+        assert_eq!(get_scope("vkQueueSubmit"), CommandScope::Device);
+        assert_eq!(get_scope("vkCmdDraw"), CommandScope::Device);
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: LGPL-2.1-or-later
+// Copyright 2018 Jacob Lifshay
+
+//! a minimal DOM for the registry. unlike `xmltree`, it keeps the text between child elements,
+//! which the registry uses for C declarations like `const <type>void</type>* <name>pNext</name>`
+
+use std::collections::HashMap;
+use std::io::Read;
+use xml::reader::{EventReader, XmlEvent};
+use Error;
+
+#[derive(Clone, Debug)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Element {
+    pub name: String,
+    pub attributes: HashMap<String, String>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    pub fn parse<R: Read>(input: R) -> Result<Element, Error> {
+        let mut stack: Vec<Element> = Vec::new();
+        for event in EventReader::new(input) {
+            match event? {
+                XmlEvent::StartElement {
+                    name, attributes, ..
+                } => stack.push(Element {
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|v| (v.name.local_name, v.value))
+                        .collect(),
+                    children: Vec::new(),
+                }),
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(Node::Element(element)),
+                        None => return Ok(element),
+                    }
+                }
+                XmlEvent::Characters(text) | XmlEvent::Whitespace(text) | XmlEvent::CData(text) => {
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(Node::Text(text));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err(Error::InvalidRegistry("missing root element".into()))
+    }
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(|v| &**v)
+    }
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|child| match child {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+    pub fn elements_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.elements().filter(move |element| element.name == name)
+    }
+    pub fn get_child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|element| element.name == name)
+    }
+    /// the text of this element and all its descendants
+    pub fn get_text(&self) -> String {
+        let mut retval = String::new();
+        for child in &self.children {
+            match child {
+                Node::Element(element) => retval += &element.get_text(),
+                Node::Text(text) => retval += text,
+            }
+        }
+        retval
+    }
+    /// the name of a `<type>`, `<enum>` or `<command>`, from the `name` attribute or the `<name>`
+    /// child
+    pub fn get_name(&self) -> Option<String> {
+        if let Some(name) = self.get_attribute("name") {
+            return Some(name.into());
+        }
+        self.get_child("name")
+            .or_else(|| self.get_child("proto").and_then(|v| v.get_child("name")))
+            .map(Element::get_text)
+    }
+}